@@ -1,4 +1,6 @@
 use crate::Page;
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
+use crate::nonce_mode::NonceMode;
 use crate::page::PageTrait;
 use aes::cipher::generic_array::typenum::U12;
 use aes_gcm::{
@@ -9,21 +11,30 @@ use aes_gcm::{
 // Support for encrypting blocks.
 // Each block is encrypted with a randomly generated 96 bit nonce
 // and the key provided which is 16 bytes.
-// A nonce is generated each time a block is encrypted.
+// A nonce is generated each time a block is encrypted, or derived from
+// the block, see NonceMode.
 // The nonce is stored in the last 12 bytes of the block. An additional
 // 16 bytes are used for the AES-128-GCM authentication tag, so the
 // page size is block size - 28.
 // There is no checksum stored, AES128-GCM has a built in
 // cryptographic checksum functionality.
+#[derive(Debug)]
 pub struct Aes128GcmSanity {}
 
 impl Aes128GcmSanity {
-    pub fn encrypt_page(page: &mut Page, input_key: &Vec<u8>) {
+    pub fn encrypt_page(page: &mut Page, input_key: &[u8]) {
+        Aes128GcmSanity::encrypt_page_with_nonce_mode(page, input_key, NonceMode::Random);
+    }
+
+    pub fn encrypt_page_with_nonce_mode(page: &mut Page, input_key: &[u8], nonce_mode: NonceMode) {
         assert!(input_key.len() == 16, "Key is incorrect size");
         let block_size = page.get_pg_ctr_bytes().len();
-        let key: &Key<Aes128Gcm> = input_key.as_slice().into();
+        let key: &Key<Aes128Gcm> = input_key.into();
         let cipher = Aes128Gcm::new(key);
-        let nonce = Aes128Gcm::generate_nonce(&mut OsRng); // 96-bits; unique per run.
+        let nonce = match nonce_mode {
+            NonceMode::Random => Aes128Gcm::generate_nonce(&mut OsRng), // 96-bits; unique per run.
+            NonceMode::Derived => Nonce::<U12>::from(NonceMode::derive_nonce(page, input_key)),
+        };
         // The encrypted size matches the unencrypted size.
         let encrypted_page_bytes = cipher
             .encrypt(&nonce, page.get_page_bytes())
@@ -33,17 +44,51 @@ impl Aes128GcmSanity {
         page.get_pg_ctr_bytes_mut()[block_size - 12..block_size].copy_from_slice(&nonce);
     }
 
-    pub fn decrypt_page(page: &mut Page, input_key: &Vec<u8>) {
+    pub fn decrypt_page(page: &mut Page, input_key: &[u8]) {
+        assert!(
+            Aes128GcmSanity::try_decrypt_page(page, input_key),
+            "Failed to decrypt page"
+        );
+    }
+
+    // As decrypt_page but returns false, leaving the page as it is, if
+    // the block does not decrypt with the key.
+    pub fn try_decrypt_page(page: &mut Page, input_key: &[u8]) -> bool {
         assert!(input_key.len() == 16, "Key is incorrect size");
         let block_size = page.get_pg_ctr_bytes().len();
-        let key: &Key<Aes128Gcm> = input_key.as_slice().into();
+        let key: &Key<Aes128Gcm> = input_key.into();
         let cipher = Aes128Gcm::new(key);
         let nonce: &Nonce<U12> = (&page.get_pg_ctr_bytes()[block_size - 12..block_size]).into();
         let plaintext = cipher.decrypt(nonce, &page.get_pg_ctr_bytes()[0..block_size - 12]);
-        let mut plaintext = plaintext.expect("Failed to decrypt page");
+        let Ok(mut plaintext) = plaintext else {
+            return false;
+        };
         // Pad the plaintext to the block size if necessary
         plaintext.resize(page.get_pg_ctr_bytes().len(), 0);
         // Copy the unencrypted bytes back into the page.
         page.replace_bytes(plaintext);
+        true
+    }
+}
+
+impl BlockSanityProvider for Aes128GcmSanity {
+    fn get_id(&self) -> u8 {
+        BlockSanity::Aes128Gcm.into()
+    }
+
+    fn get_bytes_used(&self) -> usize {
+        BlockSanity::get_bytes_used(BlockSanity::Aes128Gcm)
+    }
+
+    fn is_encrypted(&self) -> bool {
+        true
+    }
+
+    fn set_block_sanity(&self, page: &mut Page, key: &[u8], nonce_mode: NonceMode) {
+        Aes128GcmSanity::encrypt_page_with_nonce_mode(page, key, nonce_mode);
+    }
+
+    fn check_block_sanity(&self, page: &mut Page, key: &[u8]) -> bool {
+        Aes128GcmSanity::try_decrypt_page(page, key)
     }
 }
@@ -0,0 +1,7 @@
+// One change in a Db::write_batch. The changes are made in order so a
+// later op on the same key wins.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
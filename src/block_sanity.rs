@@ -1,15 +1,62 @@
-use crate::{Aes128GcmSanity, Page, XxHashSanity};
+#[cfg(feature = "encryption")]
+use crate::Aes128GcmSanity;
+#[cfg(feature = "chacha20")]
+use crate::ChaCha20Poly1305Sanity;
+use crate::digby_error::DigbyError;
+use crate::nonce_mode::NonceMode;
+use crate::page_no::PageNo;
+use crate::{Crc32cSanity, Page, XxHashSanity};
+use std::sync::Arc;
+
+// What a block sanity does, set when a block is written and checked
+// when it is read. The built in checksums and ciphers implement it and
+// an application can add its own, for example an HMAC of the page, by
+// giving DbOptions::sanity_provider one with an id from
+// BlockSanity::FIRST_CUSTOM_ID. The id is kept in the root page so the
+// DB can only be opened again with the same provider.
+//
+// The page is the first block size - get_bytes_used bytes of the
+// block, the rest is for the sanity. key is the key given to the DB,
+// empty if there is none, a built in cipher cuts it down or pads it to
+// its key size.
+pub trait BlockSanityProvider: Send + Sync + std::fmt::Debug {
+    // The id kept in the root page, see BlockSanity.
+    fn get_id(&self) -> u8;
+
+    // The bytes at the end of each block used by the sanity.
+    fn get_bytes_used(&self) -> usize;
+
+    // An encrypted block is not readable until checked, so cannot be
+    // read without checking it, see Db::set_verify_checksums.
+    fn is_encrypted(&self) -> bool {
+        false
+    }
+
+    // Set the sanity of a block before it is written, nonce_mode is for
+    // ciphers that need a nonce.
+    fn set_block_sanity(&self, page: &mut Page, key: &[u8], nonce_mode: NonceMode);
+
+    // Check a block read from disk, decrypting it if it is encrypted.
+    fn check_block_sanity(&self, page: &mut Page, key: &[u8]) -> bool;
+}
 
 // Used to check a block read from disk is not
 // corrupt. This is done either by recording
 // a checksum of the page within the block,
 // or encrypting the page in the block.
-// Two approaches are supported at present, xxhash_32
-// as a checksum or AES-128-GCM encryption of the block.
+// Four approaches are supported at present, xxhash_32 or CRC32C
+// as a checksum or AES-128-GCM or ChaCha20-Poly1305 encryption of the
+// block. AES-128-GCM needs the "encryption" feature and
+// ChaCha20-Poly1305 the "chacha20" feature, without it the type can
+// still be read but using it panics. Custom is a BlockSanityProvider
+// from outside the crate, its id is from FIRST_CUSTOM_ID up.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum BlockSanity {
-    XxH32Checksum = 0,
-    Aes128Gcm = 1,
+    XxH32Checksum,
+    Aes128Gcm,
+    ChaCha20Poly1305,
+    Crc32c,
+    Custom(u8),
 }
 
 impl TryFrom<u8> for BlockSanity {
@@ -19,6 +66,9 @@ impl TryFrom<u8> for BlockSanity {
         match value {
             0 => Ok(BlockSanity::XxH32Checksum),
             1 => Ok(BlockSanity::Aes128Gcm),
+            2 => Ok(BlockSanity::ChaCha20Poly1305),
+            3 => Ok(BlockSanity::Crc32c),
+            BlockSanity::FIRST_CUSTOM_ID..=u8::MAX => Ok(BlockSanity::Custom(value)),
             _ => Err(()),
         }
     }
@@ -29,49 +79,169 @@ impl From<BlockSanity> for u8 {
         match value {
             BlockSanity::XxH32Checksum => 0,
             BlockSanity::Aes128Gcm => 1,
+            BlockSanity::ChaCha20Poly1305 => 2,
+            BlockSanity::Crc32c => 3,
+            BlockSanity::Custom(id) => id,
         }
     }
 }
 
 impl BlockSanity {
+    pub const ENCRYPTION_NOT_COMPILED_IN: &str =
+        "AES-128-GCM encryption is not compiled in, build with the encryption feature.";
+    pub const CHACHA20_NOT_COMPILED_IN: &str =
+        "ChaCha20-Poly1305 encryption is not compiled in, build with the chacha20 feature.";
+    pub const CUSTOM_NEEDS_PROVIDER: &str =
+        "A custom block sanity needs its provider, see DbOptions::sanity_provider.";
+
+    // Ids below this are kept for the block sanities of the crate, ids
+    // from it up are for BlockSanityProviders from outside.
+    pub const FIRST_CUSTOM_ID: u8 = 128;
+
+    // The block sanity a DB uses by default, AES-128-GCM if it has a key.
+    pub const fn for_key(has_key: bool) -> BlockSanity {
+        if has_key {
+            BlockSanity::Aes128Gcm
+        } else {
+            BlockSanity::XxH32Checksum
+        }
+    }
+
+    // Can this build check and set this type of block sanity.
+    pub const fn is_supported(&self) -> bool {
+        match self {
+            BlockSanity::XxH32Checksum | BlockSanity::Crc32c => true,
+            BlockSanity::Aes128Gcm => cfg!(feature = "encryption"),
+            BlockSanity::ChaCha20Poly1305 => cfg!(feature = "chacha20"),
+            BlockSanity::Custom(_) => false,
+        }
+    }
+
+    // Why is_supported is false.
+    pub const fn get_not_compiled_in_message(&self) -> &'static str {
+        match self {
+            BlockSanity::ChaCha20Poly1305 => BlockSanity::CHACHA20_NOT_COMPILED_IN,
+            BlockSanity::Custom(_) => BlockSanity::CUSTOM_NEEDS_PROVIDER,
+            _ => BlockSanity::ENCRYPTION_NOT_COMPILED_IN,
+        }
+    }
+
+    // Only known for the built in block sanities, a custom one says with
+    // BlockSanityProvider::is_encrypted.
+    pub const fn is_encrypted(&self) -> bool {
+        matches!(self, BlockSanity::Aes128Gcm | BlockSanity::ChaCha20Poly1305)
+    }
+
+    // 0 for Custom, the provider gives its size.
     pub const fn get_bytes_used(block_sanity_type: BlockSanity) -> usize {
         match block_sanity_type {
             BlockSanity::XxH32Checksum => 4,
             BlockSanity::Aes128Gcm => 28,
+            BlockSanity::ChaCha20Poly1305 => 28,
+            BlockSanity::Crc32c => 4,
+            BlockSanity::Custom(_) => 0,
         }
     }
 
-    pub fn check_block_sanity(&self, page: &mut Page, key: &Vec<u8>) {
+    // The size of the encryption key, a key given to the DB is cut down
+    // or padded with zeros to this size. A custom block sanity is given
+    // the key as it is.
+    pub const fn get_key_size(&self) -> usize {
         match self {
-            BlockSanity::XxH32Checksum => {
-                XxHashSanity::verify_checksum(page);
-            }
-            BlockSanity::Aes128Gcm => {
-                Aes128GcmSanity::decrypt_page(page, key);
-            }
+            BlockSanity::XxH32Checksum | BlockSanity::Crc32c | BlockSanity::Custom(_) => 0,
+            BlockSanity::Aes128Gcm => 16,
+            BlockSanity::ChaCha20Poly1305 => 32,
         }
     }
 
-    pub fn set_block_sanity(&self, page: &mut Page, key: &Vec<u8>) {
+    // The key as the block sanity uses it, see get_key_size.
+    pub fn fit_key(&self, key: &[u8]) -> Vec<u8> {
+        if let BlockSanity::Custom(_) = self {
+            return key.to_vec();
+        }
+        let mut enc_key = key.to_vec();
+        enc_key.resize(self.get_key_size(), 0);
+        enc_key
+    }
+
+    // The provider for a block sanity of the crate. Panics if it is not
+    // compiled in or is Custom, see is_supported.
+    pub fn get_provider(&self) -> Arc<dyn BlockSanityProvider> {
         match self {
-            BlockSanity::XxH32Checksum => {
-                XxHashSanity::set_checksum(page);
-            }
-            BlockSanity::Aes128Gcm => {
-                Aes128GcmSanity::encrypt_page(page, key);
-            }
+            BlockSanity::XxH32Checksum => Arc::new(XxHashSanity {}),
+            BlockSanity::Crc32c => Arc::new(Crc32cSanity {}),
+            #[cfg(feature = "encryption")]
+            BlockSanity::Aes128Gcm => Arc::new(Aes128GcmSanity {}),
+            #[cfg(feature = "chacha20")]
+            BlockSanity::ChaCha20Poly1305 => Arc::new(ChaCha20Poly1305Sanity {}),
+            _ => panic!("{}", self.get_not_compiled_in_message()),
+        }
+    }
+
+    // Check a block read from page_no, the page is decrypted if the
+    // block is encrypted.
+    pub fn check_block_sanity(
+        &self,
+        page: &mut Page,
+        key: &[u8],
+        page_no: PageNo,
+    ) -> Result<(), DigbyError> {
+        BlockSanity::check_block_sanity_with(self.get_provider().as_ref(), page, key, page_no)
+    }
+
+    // As check_block_sanity with the provider, a block that fails is a
+    // ChecksumMismatch or for an encrypted block an EncryptionMismatch.
+    pub fn check_block_sanity_with(
+        provider: &dyn BlockSanityProvider,
+        page: &mut Page,
+        key: &[u8],
+        page_no: PageNo,
+    ) -> Result<(), DigbyError> {
+        if provider.check_block_sanity(page, key) {
+            return Ok(());
         }
+        if provider.is_encrypted() {
+            return Err(DigbyError::EncryptionMismatch(page_no));
+        }
+        Err(DigbyError::ChecksumMismatch(page_no))
+    }
+
+    // As check_block_sanity but only says if the block passes the check.
+    pub fn is_block_sane(&self, page: &mut Page, key: &[u8]) -> bool {
+        self.get_provider().check_block_sanity(page, key)
+    }
+
+    pub fn set_block_sanity(&self, page: &mut Page, key: &[u8]) {
+        self.set_block_sanity_with_nonce_mode(page, key, NonceMode::Random);
+    }
+
+    // As set_block_sanity but an encrypted block uses a nonce chosen by
+    // nonce_mode, a checksum ignores it.
+    pub fn set_block_sanity_with_nonce_mode(
+        &self,
+        page: &mut Page,
+        key: &[u8],
+        nonce_mode: NonceMode,
+    ) {
+        self.get_provider().set_block_sanity(page, key, nonce_mode);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::page::PageTrait;
 
     #[test]
     fn test_block_sanity_bytes_used() {
         assert_eq!(BlockSanity::get_bytes_used(BlockSanity::XxH32Checksum), 4);
+        assert_eq!(BlockSanity::get_bytes_used(BlockSanity::Crc32c), 4);
         assert_eq!(BlockSanity::get_bytes_used(BlockSanity::Aes128Gcm), 28);
+        assert_eq!(
+            BlockSanity::get_bytes_used(BlockSanity::ChaCha20Poly1305),
+            28
+        );
+        assert_eq!(BlockSanity::ChaCha20Poly1305.get_key_size(), 32);
     }
 
     #[test]
@@ -81,6 +251,79 @@ mod tests {
             BlockSanity::XxH32Checksum
         );
         assert_eq!(BlockSanity::try_from(1).unwrap(), BlockSanity::Aes128Gcm);
-        assert!(BlockSanity::try_from(2).is_err());
+        assert_eq!(
+            BlockSanity::try_from(2).unwrap(),
+            BlockSanity::ChaCha20Poly1305
+        );
+        assert_eq!(u8::from(BlockSanity::ChaCha20Poly1305), 2);
+        assert_eq!(BlockSanity::try_from(3).unwrap(), BlockSanity::Crc32c);
+        assert_eq!(u8::from(BlockSanity::Crc32c), 3);
+        assert!(BlockSanity::try_from(4).is_err());
+        assert!(BlockSanity::try_from(BlockSanity::FIRST_CUSTOM_ID - 1).is_err());
+        assert_eq!(
+            BlockSanity::try_from(200).unwrap(),
+            BlockSanity::Custom(200)
+        );
+        assert_eq!(u8::from(BlockSanity::Custom(200)), 200);
+        assert!(!BlockSanity::Custom(200).is_supported());
+    }
+
+    #[test]
+    fn test_block_sanity_is_supported() {
+        assert!(BlockSanity::XxH32Checksum.is_supported());
+        assert!(BlockSanity::Crc32c.is_supported());
+        assert_eq!(
+            BlockSanity::Aes128Gcm.is_supported(),
+            cfg!(feature = "encryption")
+        );
+        assert_eq!(
+            BlockSanity::ChaCha20Poly1305.is_supported(),
+            cfg!(feature = "chacha20")
+        );
+    }
+
+    #[test]
+    fn test_block_sanity_is_block_sane() {
+        let sanity_types = [
+            BlockSanity::XxH32Checksum,
+            BlockSanity::Aes128Gcm,
+            BlockSanity::ChaCha20Poly1305,
+            BlockSanity::Crc32c,
+        ];
+        for sanity_type in sanity_types.into_iter().filter(BlockSanity::is_supported) {
+            let key = vec![7u8; sanity_type.get_key_size()];
+            let page_size = 4096 - BlockSanity::get_bytes_used(sanity_type);
+            let mut page = Page::new(4096, page_size);
+            page.set_version(34);
+            sanity_type.set_block_sanity(&mut page, &key);
+            let mut copy = Page::new(4096, page_size);
+            copy.get_pg_ctr_bytes_mut()
+                .copy_from_slice(page.get_pg_ctr_bytes());
+            assert!(sanity_type.is_block_sane(&mut copy, &key));
+            assert_eq!(copy.get_version(), 34);
+
+            // The same bytes read as a smaller block are not sane.
+            let mut small = Page::new(1024, 1024 - BlockSanity::get_bytes_used(sanity_type));
+            small
+                .get_pg_ctr_bytes_mut()
+                .copy_from_slice(&page.get_pg_ctr_bytes()[0..1024]);
+            assert!(!sanity_type.is_block_sane(&mut small, &key));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "encryption"))]
+    #[should_panic(expected = "AES-128-GCM encryption is not compiled in")]
+    fn test_block_sanity_encryption_not_compiled_in() {
+        let mut page = Page::new(4096, 4068);
+        BlockSanity::Aes128Gcm.set_block_sanity(&mut page, &[0u8; 16]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "chacha20"))]
+    #[should_panic(expected = "ChaCha20-Poly1305 encryption is not compiled in")]
+    fn test_block_sanity_chacha20_not_compiled_in() {
+        let mut page = Page::new(4096, 4068);
+        BlockSanity::ChaCha20Poly1305.set_block_sanity(&mut page, &[0u8; 32]);
     }
 }
@@ -0,0 +1,114 @@
+use crate::Page;
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
+use crate::nonce_mode::NonceMode;
+use crate::page::PageTrait;
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+
+// Support for encrypting blocks with ChaCha20-Poly1305, an alternative
+// to AES-128-GCM that is faster and constant time on CPUs without AES
+// instructions. The layout of the block is the same as for
+// Aes128GcmSanity, a 96 bit nonce, random or derived as set by
+// NonceMode, in the last 12 bytes of the block and a 16 byte Poly1305 tag before it, so the page size is
+// block size - 28. The key is 32 bytes.
+#[derive(Debug)]
+pub struct ChaCha20Poly1305Sanity {}
+
+impl ChaCha20Poly1305Sanity {
+    pub fn encrypt_page(page: &mut Page, input_key: &[u8]) {
+        ChaCha20Poly1305Sanity::encrypt_page_with_nonce_mode(page, input_key, NonceMode::Random);
+    }
+
+    pub fn encrypt_page_with_nonce_mode(page: &mut Page, input_key: &[u8], nonce_mode: NonceMode) {
+        assert!(input_key.len() == 32, "Key is incorrect size");
+        let block_size = page.get_pg_ctr_bytes().len();
+        let key: &Key = input_key.into();
+        let cipher = ChaCha20Poly1305::new(key);
+        let nonce = match nonce_mode {
+            NonceMode::Random => ChaCha20Poly1305::generate_nonce(&mut OsRng),
+            NonceMode::Derived => Nonce::from(NonceMode::derive_nonce(page, input_key)),
+        };
+        let encrypted_page_bytes = cipher
+            .encrypt(&nonce, page.get_page_bytes())
+            .expect("Failed to encrypt page");
+        page.get_pg_ctr_bytes_mut()[0..block_size - 12].copy_from_slice(&encrypted_page_bytes);
+        page.get_pg_ctr_bytes_mut()[block_size - 12..block_size].copy_from_slice(&nonce);
+    }
+
+    pub fn decrypt_page(page: &mut Page, input_key: &[u8]) {
+        assert!(
+            ChaCha20Poly1305Sanity::try_decrypt_page(page, input_key),
+            "Failed to decrypt page"
+        );
+    }
+
+    // As decrypt_page but returns false, leaving the page as it is, if
+    // the block does not decrypt with the key.
+    pub fn try_decrypt_page(page: &mut Page, input_key: &[u8]) -> bool {
+        assert!(input_key.len() == 32, "Key is incorrect size");
+        let block_size = page.get_pg_ctr_bytes().len();
+        let key: &Key = input_key.into();
+        let cipher = ChaCha20Poly1305::new(key);
+        let nonce: &Nonce = (&page.get_pg_ctr_bytes()[block_size - 12..block_size]).into();
+        let plaintext = cipher.decrypt(nonce, &page.get_pg_ctr_bytes()[0..block_size - 12]);
+        let Ok(mut plaintext) = plaintext else {
+            return false;
+        };
+        plaintext.resize(page.get_pg_ctr_bytes().len(), 0);
+        page.replace_bytes(plaintext);
+        true
+    }
+}
+
+impl BlockSanityProvider for ChaCha20Poly1305Sanity {
+    fn get_id(&self) -> u8 {
+        BlockSanity::ChaCha20Poly1305.into()
+    }
+
+    fn get_bytes_used(&self) -> usize {
+        BlockSanity::get_bytes_used(BlockSanity::ChaCha20Poly1305)
+    }
+
+    fn is_encrypted(&self) -> bool {
+        true
+    }
+
+    fn set_block_sanity(&self, page: &mut Page, key: &[u8], nonce_mode: NonceMode) {
+        ChaCha20Poly1305Sanity::encrypt_page_with_nonce_mode(page, key, nonce_mode);
+    }
+
+    fn check_block_sanity(&self, page: &mut Page, key: &[u8]) -> bool {
+        ChaCha20Poly1305Sanity::try_decrypt_page(page, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chacha20_poly1305_encrypt_decrypt() {
+        let key = [3u8; 32];
+        let mut page = Page::new(4096, 4096 - 28);
+        page.set_version(12);
+        page.get_page_bytes_mut()[100..104].copy_from_slice(&[1, 2, 3, 4]);
+        ChaCha20Poly1305Sanity::encrypt_page(&mut page, &key);
+        assert_ne!(&page.get_page_bytes()[100..104], &[1, 2, 3, 4]);
+
+        // The wrong key, or a changed byte, fails and leaves the page.
+        let encrypted = page.get_pg_ctr_bytes().to_vec();
+        assert!(!ChaCha20Poly1305Sanity::try_decrypt_page(
+            &mut page, &[4u8; 32]
+        ));
+        page.get_pg_ctr_bytes_mut()[200] ^= 1;
+        assert!(!ChaCha20Poly1305Sanity::try_decrypt_page(&mut page, &key));
+        assert_eq!(&page.get_pg_ctr_bytes()[0..100], &encrypted[0..100]);
+        page.get_pg_ctr_bytes_mut()[200] ^= 1;
+
+        ChaCha20Poly1305Sanity::decrypt_page(&mut page, &key);
+        assert_eq!(page.get_version(), 12);
+        assert_eq!(&page.get_page_bytes()[100..104], &[1, 2, 3, 4]);
+    }
+}
@@ -8,7 +8,6 @@ use crate::page::Page;
 use crate::page::PageTrait;
 use crate::page::PageType;
 use crate::page_cache::PageCache;
-use crate::tuple::Overflow;
 use crate::tuple::TupleTrait;
 
 pub struct ClearHandler {
@@ -36,7 +35,7 @@ impl ClearHandler {
         // If the root of the page is a leaf page, ie
         // only page in the tree then special case it.
         if first.get_type() == PageType::LeafPage {
-            let root_leaf_page = LeafPage::from_page(first);
+            let root_leaf_page = LeafPage::from_page(first, page_cache.get_key_order());
             return ClearHandler::clear_root_leaf_page(
                 root_leaf_page,
                 free_page_tracker,
@@ -45,7 +44,7 @@ impl ClearHandler {
             );
         }
 
-        let root_dir_page = DirPage::from_page(first);
+        let root_dir_page = DirPage::from_page(first, page_cache.get_key_order());
         ClearHandler::clear_tree_dir_pages(root_dir_page, free_page_tracker, page_cache);
         ClearHandler::create_new_root_page(free_page_tracker, page_cache, new_version)
     }
@@ -65,7 +64,7 @@ impl ClearHandler {
             let page = page_cache.get_page(dir_entry);
             if page.get_type() == PageType::LeafPage {
                 ClearHandler::clear_leaf_page(
-                    LeafPage::from_page(page),
+                    LeafPage::from_page(page, page_cache.get_key_order()),
                     free_page_tracker,
                     page_cache,
                 );
@@ -73,7 +72,7 @@ impl ClearHandler {
             }
             // Recursion. May not be best approach here.
             ClearHandler::clear_tree_dir_pages(
-                DirPage::from_page(page),
+                DirPage::from_page(page, page_cache.get_key_order()),
                 free_page_tracker,
                 page_cache,
             );
@@ -111,9 +110,9 @@ impl ClearHandler {
     ) {
         let tuples = page.get_all_tuples();
         for tuple in tuples {
-            if tuple.get_overflow() != Overflow::None {
+            if tuple.get_overflow().has_overflow_pages() {
                 OverflowPageHandler::delete_overflow_tuple_pages(
-                    Some(tuple),
+                    Some(&tuple),
                     page_cache,
                     free_page_tracker,
                 );
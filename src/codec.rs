@@ -0,0 +1,130 @@
+// A codec turns a Rust type into the bytes stored in the DB and back,
+// used by TypedDb.
+//
+// The tree orders keys by comparing their bytes, so for range queries
+// to work the key codec must produce bytes that sort in the same order
+// as the keys. The big-endian integer codecs do this, little-endian or
+// variable length encodings (such as bincode) do not - they are fine for
+// values and for keys only used with get/put/delete.
+pub trait Codec<T> {
+    fn encode(&self, value: &T) -> Vec<u8>;
+    fn decode(&self, bytes: &[u8]) -> T;
+}
+
+// u64 as big-endian bytes, sorts in numeric order.
+pub struct BigEndianU64Codec;
+
+impl Codec<u64> for BigEndianU64Codec {
+    fn encode(&self, value: &u64) -> Vec<u8> {
+        value.to_be_bytes().to_vec()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> u64 {
+        u64::from_be_bytes(bytes.try_into().expect("Bad length for u64 key."))
+    }
+}
+
+// i64 as big-endian bytes with the sign bit flipped so negative
+// numbers sort before positive numbers.
+pub struct BigEndianI64Codec;
+
+impl Codec<i64> for BigEndianI64Codec {
+    fn encode(&self, value: &i64) -> Vec<u8> {
+        ((*value as u64) ^ (1 << 63)).to_be_bytes().to_vec()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> i64 {
+        let value = u64::from_be_bytes(bytes.try_into().expect("Bad length for i64 key."));
+        (value ^ (1 << 63)) as i64
+    }
+}
+
+// Strings as their UTF-8 bytes, sorts by byte order.
+pub struct StringCodec;
+
+impl Codec<String> for StringCodec {
+    fn encode(&self, value: &String) -> Vec<u8> {
+        value.as_bytes().to_vec()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        String::from_utf8(bytes.to_vec()).expect("Stored string is not UTF-8.")
+    }
+}
+
+// Raw bytes, stored as is.
+pub struct BytesCodec;
+
+impl Codec<Vec<u8>> for BytesCodec {
+    fn encode(&self, value: &Vec<u8>) -> Vec<u8> {
+        value.clone()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Vec<u8> {
+        bytes.to_vec()
+    }
+}
+
+// Any serde type using bincode. The bytes do not sort in the same order
+// as the values so do not use it for keys that are used in a range.
+#[cfg(feature = "serde")]
+pub struct BincodeCodec;
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + serde::de::DeserializeOwned> Codec<T> for BincodeCodec {
+    fn encode(&self, value: &T) -> Vec<u8> {
+        bincode::serialize(value).expect("Failed to serialize with bincode.")
+    }
+
+    fn decode(&self, bytes: &[u8]) -> T {
+        bincode::deserialize(bytes).expect("Failed to deserialize with bincode.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_big_endian_u64_codec_order() {
+        let values = [0u64, 1, 255, 256, 65536, u64::MAX];
+        for pair in values.windows(2) {
+            assert!(BigEndianU64Codec.encode(&pair[0]) < BigEndianU64Codec.encode(&pair[1]));
+        }
+        for value in values {
+            assert_eq!(
+                BigEndianU64Codec.decode(&BigEndianU64Codec.encode(&value)),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_big_endian_i64_codec_order() {
+        let values = [i64::MIN, -256, -1, 0, 1, 256, i64::MAX];
+        for pair in values.windows(2) {
+            assert!(BigEndianI64Codec.encode(&pair[0]) < BigEndianI64Codec.encode(&pair[1]));
+        }
+        for value in values {
+            assert_eq!(
+                BigEndianI64Codec.decode(&BigEndianI64Codec.encode(&value)),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_codec() {
+        let value = "hello".to_string();
+        assert_eq!(StringCodec.decode(&StringCodec.encode(&value)), value);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_bincode_codec() {
+        let value = (1u32, "hello".to_string(), vec![1.5f64, 2.5]);
+        let bytes = BincodeCodec.encode(&value);
+        let decoded: (u32, String, Vec<f64>) = BincodeCodec.decode(&bytes);
+        assert_eq!(decoded, value);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::PageNo;
+use crate::db_config::DbConfig;
+use crate::dir_page::DirPage;
+use crate::free_page_tracker::FreePageTracker;
+use crate::page::PageType;
+use crate::page_cache::PageCache;
+use crate::tree_builder::TreeBuilder;
+use crate::tree_scan_handler::TreeScanHandler;
+use crate::tuple::Tuple;
+
+pub struct CompactHandler {}
+
+// Rebuild a tree into new pages. After lots of deletes, or random
+// inserts that split pages in half, the leaf and dir pages of a tree
+// can be mostly empty. Compacting reads the tuples out in key order
+// and packs them into as few leaf pages as will hold them, then builds
+// the dir pages above from the bottom up - a bulk load rather than
+// adding the tuples one at a time which would split each page in half
+// again.
+//
+// The tuples are copied as they are stored in the tree, an overflow
+// tuple is still a reference to the same overflow pages so those are
+// not rewritten. The old leaf and dir pages are returned as free pages.
+//
+// The new pages have no fences so no prefix compression, the same as a
+// root leaf page. The pages are full so the next add to a page will
+// split it.
+impl CompactHandler {
+    // Returns the page number of the root of the new tree.
+    pub fn compact_tree(
+        root_page_no: PageNo,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+        new_version: u64,
+        db_config: &DbConfig,
+    ) -> PageNo {
+        let tuples = TreeScanHandler::get_tuples_in_range(&[], None, root_page_no, page_cache);
+        CompactHandler::free_tree_pages(root_page_no, free_page_tracker, page_cache);
+        CompactHandler::build_tree(
+            tuples,
+            free_page_tracker,
+            page_cache,
+            new_version,
+            db_config,
+        )
+    }
+
+    // Build a packed tree of the tuples, which must be in key order, see
+    // TreeBuilder. No tuples gives an empty leaf page. Returns the page
+    // number of the root of the new tree.
+    pub fn build_tree(
+        tuples: Vec<Tuple>,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+        new_version: u64,
+        db_config: &DbConfig,
+    ) -> PageNo {
+        let mut tree_builder = TreeBuilder::new(new_version, db_config);
+        for tuple in tuples {
+            tree_builder.add_tuple(&tuple, free_page_tracker, page_cache);
+        }
+        tree_builder.finish(free_page_tracker, page_cache)
+    }
+
+    // Return the leaf and dir pages of a tree as free pages, but not
+    // the overflow pages as the new tree still uses them.
+    pub fn free_tree_pages(
+        root_page_no: PageNo,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+    ) {
+        let mut stack = vec![root_page_no];
+        while let Some(page_no) = stack.pop() {
+            let page = page_cache.get_page(page_no);
+            if page.get_type() == PageType::DirPage {
+                stack.extend(
+                    DirPage::from_page(page, page_cache.get_key_order()).get_all_child_pages(),
+                );
+            }
+            free_page_tracker.return_free_page_no(page_no);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use crate::PageNo;
+use crate::db_config::DbConfig;
+use crate::db_master_page::DbMasterPage;
+use crate::dir_page::DirPage;
+use crate::free_dir_page::FreeDirPage;
+use crate::leaf_page::LeafPage;
+use crate::overflow_page::OverflowPage;
+use crate::page::PageTrait;
+use crate::page::PageType;
+use crate::page_cache::PageCache;
+use crate::table_dir_entry::TableDirEntry;
+use crate::tuple::{Tuple, TupleTrait};
+use std::collections::{HashMap, HashSet};
+
+// Shrink the file without a second copy of it. Pages near the end of
+// the file are moved into free blocks nearer the front, then the file
+// is cut down to the last block in use.
+//
+// Moving a page changes its page number so whatever points to it has
+// to be rewritten too: the dir page above it, the leaf page holding an
+// overflow tuple, the overflow page before it in a chain or the table
+// directory entry for a table root. Nothing is written in place, the
+// rewritten pages also go to free blocks and the old blocks are only
+// freed once the master page is written, the same as any commit, so a
+// crash leaves the DB at either the old or the new layout.
+//
+// The blocks in use are found by walking the trees from the master
+// page, every other block is free. This picks up blocks leaked by a
+// failed commit as well as those in the free page directory. Blocks
+// freed by the pass, including the old free page directory, cannot be
+// reused in the pass, so the free page directory is rebuilt from
+// scratch with all of them.
+//
+// A pass picks the lowest boundary it can: every page at or past the
+// boundary moves below it, along with the pages that point to a moved
+// page - the dir pages above it, the overflow pages before it in a
+// chain - and the new free page directory. The boundary is the lowest
+// where there are enough free blocks below it for all of those, so the
+// file can then be cut at the boundary. The blocks freed by a pass are
+// used by the next, the caller makes passes until no boundary below
+// the end of the file works.
+//
+// Only single block pages are handled, which is all the DB creates.
+pub struct CompactInPlaceHandler {
+    // Blocks in use once the pass commits.
+    live: HashSet<u64>,
+    // Blocks free before the pass, highest first so the lowest is
+    // popped.
+    free_slots: Vec<u64>,
+    // Pages at or past this block are moved.
+    boundary: u64,
+    new_version: u64,
+}
+
+impl CompactInPlaceHandler {
+    pub fn new(master_page: &DbMasterPage, new_version: u64, page_cache: &mut PageCache) -> Self {
+        let block_count = page_cache.get_total_page_count();
+        let parents = CompactInPlaceHandler::get_tree_pages(master_page, page_cache);
+        let mut live: HashSet<u64> = parents.keys().copied().collect();
+        // The root page and the two master pages.
+        live.extend([0, 1, 2]);
+        // The current free page directory is freed by the pass but it is
+        // in use until then.
+        let mut free_dir_pages: HashSet<u64> = HashSet::new();
+        let mut free_dir_page_no = master_page.get_free_page_dir_page_no(0);
+        while free_dir_page_no.get_blk_offset() != 0 {
+            free_dir_pages.insert(free_dir_page_no.get_blk_offset());
+            let free_dir_page = FreeDirPage::from_page(page_cache.get_page(free_dir_page_no));
+            free_dir_page_no = free_dir_page.get_nxt_free_dir_pg();
+        }
+        let free_slots: Vec<u64> = (0..block_count)
+            .rev()
+            .filter(|blk_offset| !live.contains(blk_offset) && !free_dir_pages.contains(blk_offset))
+            .collect();
+
+        // Find the lowest boundary that works. The end of the file always
+        // works, nothing moves.
+        let capacity = FreeDirPage::get_capacity(page_cache.get_page_config()) as u64;
+        let fits = |boundary: u64| {
+            let free_dir_page_count = boundary.div_ceil(capacity).max(1);
+            let free_below = free_slots.iter().filter(|slot| **slot < boundary).count() as u64;
+            let moves = CompactInPlaceHandler::count_moves(&parents, boundary) as u64;
+            moves + free_dir_page_count <= free_below
+        };
+        let mut low = live.len() as u64;
+        let mut boundary = block_count;
+        while low < boundary {
+            let mid = low + (boundary - low) / 2;
+            if fits(mid) {
+                boundary = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        CompactInPlaceHandler {
+            live,
+            free_slots,
+            boundary,
+            new_version,
+        }
+    }
+
+    // True if the pass can make the file smaller, if not there is no
+    // point making it.
+    pub fn will_shrink(&self, page_cache: &PageCache) -> bool {
+        self.boundary < page_cache.get_total_page_count()
+    }
+
+    // The number of pages that move with the boundary, the pages past
+    // it and every page that points to one of those.
+    fn count_moves(parents: &HashMap<u64, Option<u64>>, boundary: u64) -> usize {
+        let mut moved: HashSet<u64> = HashSet::new();
+        for blk_offset in parents.keys().filter(|blk_offset| **blk_offset >= boundary) {
+            let mut page = Some(*blk_offset);
+            while let Some(blk_offset) = page {
+                if !moved.insert(blk_offset) {
+                    break;
+                }
+                page = parents[&blk_offset];
+            }
+        }
+        moved.len()
+    }
+
+    // Every block used by the global tree, the table directory tree, the
+    // table trees and their overflow chains, with the block of the page
+    // that points to it. The tree roots are pointed to by the master
+    // page so have no parent. The blocks of the free page directory are
+    // not included, a pass always writes a new one.
+    fn get_tree_pages(
+        master_page: &DbMasterPage,
+        page_cache: &mut PageCache,
+    ) -> HashMap<u64, Option<u64>> {
+        let mut pages: HashMap<u64, Option<u64>> = HashMap::new();
+        let mut stack = vec![
+            (master_page.get_global_tree_root_page_no(), false, None),
+            (master_page.get_table_dir_page_no(), true, None),
+        ];
+        while let Some((page_no, is_table_dir, parent)) = stack.pop() {
+            CompactInPlaceHandler::add_page(&mut pages, page_no, parent);
+            let blk_offset = Some(page_no.get_blk_offset());
+            let page = page_cache.get_page(page_no);
+            if page.get_type() == PageType::DirPage {
+                let child_pages =
+                    DirPage::from_page(page, page_cache.get_key_order()).get_all_child_pages();
+                stack.extend(
+                    child_pages
+                        .into_iter()
+                        .map(|child| (child, is_table_dir, blk_offset)),
+                );
+                continue;
+            }
+            for tuple in LeafPage::from_page(page, page_cache.get_key_order()).get_all_tuples() {
+                if is_table_dir {
+                    let table_dir_entry = TableDirEntry::from_bytes(tuple.get_value());
+                    stack.push((table_dir_entry.get_root_page_no(), false, blk_offset));
+                } else if tuple.get_overflow().has_overflow_pages() {
+                    let mut overflow_page_no = CompactInPlaceHandler::get_page_ref(&tuple);
+                    let mut parent = blk_offset;
+                    while overflow_page_no.get_blk_offset() != 0 {
+                        CompactInPlaceHandler::add_page(&mut pages, overflow_page_no, parent);
+                        parent = Some(overflow_page_no.get_blk_offset());
+                        let overflow_page =
+                            OverflowPage::from_page(page_cache.get_page(overflow_page_no));
+                        overflow_page_no = PageNo::from_u64(overflow_page.get_next_page());
+                    }
+                }
+            }
+        }
+        pages
+    }
+
+    fn add_page(pages: &mut HashMap<u64, Option<u64>>, page_no: PageNo, parent: Option<u64>) {
+        assert_eq!(
+            page_no.get_blk_cnt(),
+            1,
+            "In place compaction only handles single block pages."
+        );
+        assert!(
+            pages.insert(page_no.get_blk_offset(), parent).is_none(),
+            "Page {:?} is used twice.",
+            page_no
+        );
+    }
+
+    // The page number at the start of a tuple value, the overflow
+    // pages of an overflow tuple or the root of a table tree.
+    fn get_page_ref(tuple: &Tuple) -> PageNo {
+        PageNo::from_bytes(&tuple.get_value()[0..8])
+    }
+
+    // The tuple with the page number at the start of its value changed,
+    // the value is the same size so it is updated in place in the leaf.
+    fn set_page_ref(tuple: &Tuple, page_no: PageNo) -> Tuple {
+        let mut value = tuple.get_value().to_vec();
+        value[0..8].copy_from_slice(&page_no.get_bytes());
+        Tuple::new_with_overflow(
+            tuple.get_key(),
+            &value,
+            tuple.get_version(),
+            tuple.get_overflow(),
+        )
+    }
+
+    // Move the pages of a tree that need to move, returns the page
+    // number of the root of the tree which is the same if nothing in
+    // the tree moved.
+    pub fn move_tree(
+        &mut self,
+        page_no: PageNo,
+        is_table_dir: bool,
+        page_cache: &mut PageCache,
+    ) -> PageNo {
+        let page = page_cache.get_page(page_no);
+        if page.get_type() == PageType::DirPage {
+            let mut dir_page = DirPage::from_page(page, page_cache.get_key_order());
+            let mut changed = false;
+            for child_page_no in dir_page.get_all_child_pages() {
+                let new_child_page_no = self.move_tree(child_page_no, is_table_dir, page_cache);
+                if new_child_page_no != child_page_no {
+                    dir_page.replace_child_page(child_page_no, new_child_page_no);
+                    changed = true;
+                }
+            }
+            return self.move_page(&mut dir_page, changed, page_cache);
+        }
+
+        let mut leaf_page = LeafPage::from_page(page, page_cache.get_key_order());
+        let mut changed = false;
+        for tuple in leaf_page.get_all_tuples() {
+            let (old_page_no, new_page_no) = if is_table_dir {
+                let root_page_no = TableDirEntry::from_bytes(tuple.get_value()).get_root_page_no();
+                (
+                    root_page_no,
+                    self.move_tree(root_page_no, false, page_cache),
+                )
+            } else if tuple.get_overflow().has_overflow_pages() {
+                let head_page_no = CompactInPlaceHandler::get_page_ref(&tuple);
+                (
+                    head_page_no,
+                    self.move_overflow_chain(head_page_no, page_cache),
+                )
+            } else {
+                continue;
+            };
+            if new_page_no != old_page_no {
+                let tuple = CompactInPlaceHandler::set_page_ref(&tuple, new_page_no);
+                let (ok, _) = leaf_page.add_tuple(&tuple);
+                assert!(ok, "BUG: Tuple of the same size did not fit.");
+                changed = true;
+            }
+        }
+        self.move_page(&mut leaf_page, changed, page_cache)
+    }
+
+    // Overflow chains can be long so walk them rather than recurse. A
+    // page moves if it is past the boundary or the page after it moved.
+    fn move_overflow_chain(&mut self, head_page_no: PageNo, page_cache: &mut PageCache) -> PageNo {
+        let mut chain: Vec<PageNo> = Vec::new();
+        let mut page_no = head_page_no;
+        while page_no.get_blk_offset() != 0 {
+            chain.push(page_no);
+            let overflow_page = OverflowPage::from_page(page_cache.get_page(page_no));
+            page_no = PageNo::from_u64(overflow_page.get_next_page());
+        }
+
+        let mut next_page_no = PageNo::from_u64(0);
+        let mut next_changed = false;
+        while let Some(page_no) = chain.pop() {
+            let mut overflow_page = OverflowPage::from_page(page_cache.get_page(page_no));
+            if next_changed {
+                overflow_page.set_next_page(next_page_no.to_u64());
+            }
+            next_page_no = self.move_page(&mut overflow_page, next_changed, page_cache);
+            next_changed = next_page_no != page_no;
+        }
+        next_page_no
+    }
+
+    // Write the page to a free block if it is past the boundary or has
+    // changed, returns where the page is now.
+    fn move_page<T: PageTrait>(
+        &mut self,
+        page: &mut T,
+        changed: bool,
+        page_cache: &mut PageCache,
+    ) -> PageNo {
+        let page_no = page.get_page_number();
+        if !changed && page_no.get_blk_offset() < self.boundary {
+            return page_no;
+        }
+        let new_page_no = self.get_free_slot();
+        page.set_page_number(new_page_no);
+        page.set_version(self.new_version);
+        page_cache.put_page(page.get_page());
+        self.live.remove(&page_no.get_blk_offset());
+        new_page_no
+    }
+
+    // The lowest block that was free before the pass, the boundary was
+    // picked so there are enough below it.
+    fn get_free_slot(&mut self) -> PageNo {
+        let blk_offset = self
+            .free_slots
+            .pop()
+            .expect("BUG: No free block for a moved page.");
+        assert!(
+            blk_offset < self.boundary,
+            "BUG: No free block below the boundary."
+        );
+        self.live.insert(blk_offset);
+        PageNo::from_u64(blk_offset)
+    }
+
+    // Once the trees are moved write a new free page directory holding
+    // every block up to the last one in use that is not in use. Returns
+    // the head of the directory and the number of blocks the file needs.
+    pub fn write_free_dir(
+        &mut self,
+        page_cache: &mut PageCache,
+        db_config: &DbConfig,
+    ) -> (PageNo, u64) {
+        let capacity = FreeDirPage::get_capacity(db_config);
+        let mut free_dir_page_nos = vec![self.get_free_slot()];
+        let (block_count, free_pages) = loop {
+            let block_count = self.live.iter().max().unwrap() + 1;
+            let free_pages: Vec<PageNo> = (0..block_count)
+                .filter(|blk_offset| !self.live.contains(blk_offset))
+                .map(PageNo::from_u64)
+                .collect();
+            if free_pages.len() <= free_dir_page_nos.len() * capacity {
+                break (block_count, free_pages);
+            }
+            free_dir_page_nos.push(self.get_free_slot());
+        };
+
+        // The head page gets the lowest free pages and the pages are
+        // added highest first, so the next commits take the lowest free
+        // blocks first.
+        let mut chunks = free_pages.chunks(capacity);
+        for (index, free_dir_page_no) in free_dir_page_nos.iter().enumerate() {
+            let mut free_dir_page =
+                FreeDirPage::create_new(db_config, *free_dir_page_no, self.new_version);
+            if let Some(chunk) = chunks.next() {
+                let chunk: Vec<PageNo> = chunk.iter().rev().copied().collect();
+                free_dir_page.add_free_pages(&chunk);
+            }
+            if index > 0 {
+                free_dir_page.set_prev_free_dir_pg(&free_dir_page_nos[index - 1]);
+            }
+            if index + 1 < free_dir_page_nos.len() {
+                free_dir_page.set_nxt_free_dir_pg(&free_dir_page_nos[index + 1]);
+            }
+            page_cache.put_page(free_dir_page.get_page());
+        }
+        (free_dir_page_nos[0], block_count)
+    }
+}
@@ -0,0 +1,9 @@
+// Which trees Db::compact rebuilds. The global tree is the one used by
+// put/get/delete, a table is a tree found through the table directory.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum CompactTarget {
+    Global,
+    Table(Vec<u8>),
+    // The global tree and every table.
+    All,
+}
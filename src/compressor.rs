@@ -1,8 +1,14 @@
 // Used to compress data overflow tuples.
 // The type of compression
 // used is determined when the DB is created.
-// Currently None and lz4 are supported.
+// Currently None, lz4, zstd and snappy are supported. Each codec is
+// behind a feature of the same name, lz4 is the only one on by
+// default. The type of a codec that is not built in can still be
+// decoded so a DB using it gives a clear error.
 
+use std::fmt;
+
+#[derive(Clone, Copy)]
 pub struct Compressor {
     pub compressor_type: CompressorType,
 }
@@ -11,17 +17,47 @@ pub struct Compressor {
 pub enum CompressorType {
     None = 0,
     LZ4 = 1,
+    Zstd = 2,
+    Snappy = 3,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CompressorTypeError {
+    // Not a compressor type.
+    Unknown(u8),
+    // A compressor type this build does not have the codec for.
+    NotCompiledIn(CompressorType),
+}
+
+impl fmt::Display for CompressorTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressorTypeError::Unknown(value) => write!(f, "Unknown compression type {}", value),
+            CompressorTypeError::NotCompiledIn(compressor_type) => write!(
+                f,
+                "Compression codec {:?} not compiled in, build with the {} feature.",
+                compressor_type,
+                compressor_type.feature_name()
+            ),
+        }
+    }
 }
 
 impl TryFrom<u8> for CompressorType {
-    type Error = ();
+    type Error = CompressorTypeError;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(CompressorType::None),
-            1 => Ok(CompressorType::LZ4),
-            _ => Err(()),
+        let compressor_type = match value {
+            0 => CompressorType::None,
+            1 => CompressorType::LZ4,
+            2 => CompressorType::Zstd,
+            3 => CompressorType::Snappy,
+            _ => return Err(CompressorTypeError::Unknown(value)),
+        };
+        if !compressor_type.is_supported() {
+            return Err(CompressorTypeError::NotCompiledIn(compressor_type));
         }
+        Ok(compressor_type)
     }
 }
 
@@ -30,26 +66,73 @@ impl From<CompressorType> for u8 {
         match value {
             CompressorType::None => 0,
             CompressorType::LZ4 => 1,
+            CompressorType::Zstd => 2,
+            CompressorType::Snappy => 3,
+        }
+    }
+}
+
+impl CompressorType {
+    // Is the codec built in.
+    pub const fn is_supported(&self) -> bool {
+        match self {
+            CompressorType::None => true,
+            CompressorType::LZ4 => cfg!(feature = "lz4"),
+            CompressorType::Zstd => cfg!(feature = "zstd"),
+            CompressorType::Snappy => cfg!(feature = "snappy"),
+        }
+    }
+
+    // The cargo feature that builds in the codec.
+    pub const fn feature_name(&self) -> &'static str {
+        match self {
+            CompressorType::None => "default",
+            CompressorType::LZ4 => "lz4",
+            CompressorType::Zstd => "zstd",
+            CompressorType::Snappy => "snappy",
         }
     }
 }
 
 impl Compressor {
     pub fn new(compressor_type: CompressorType) -> Self {
+        assert!(
+            compressor_type.is_supported(),
+            "{}",
+            CompressorTypeError::NotCompiledIn(compressor_type)
+        );
         Compressor { compressor_type }
     }
 
     pub fn compress(&self, data: &[u8]) -> Vec<u8> {
         match self.compressor_type {
             CompressorType::None => data.to_vec(),
+            #[cfg(feature = "lz4")]
             CompressorType::LZ4 => lz4_flex::compress_prepend_size(data),
+            #[cfg(feature = "zstd")]
+            CompressorType::Zstd => zstd::encode_all(data, 0).expect("Failed to compress"),
+            #[cfg(feature = "snappy")]
+            CompressorType::Snappy => snap::raw::Encoder::new()
+                .compress_vec(data)
+                .expect("Failed to compress"),
+            #[allow(unreachable_patterns)]
+            compressor_type => panic!("{}", CompressorTypeError::NotCompiledIn(compressor_type)),
         }
     }
 
     pub fn decompress(&self, data: &[u8]) -> Vec<u8> {
         match self.compressor_type {
             CompressorType::None => data.to_vec(),
+            #[cfg(feature = "lz4")]
             CompressorType::LZ4 => lz4_flex::decompress_size_prepended(data).unwrap(),
+            #[cfg(feature = "zstd")]
+            CompressorType::Zstd => zstd::decode_all(data).expect("Failed to decompress"),
+            #[cfg(feature = "snappy")]
+            CompressorType::Snappy => snap::raw::Decoder::new()
+                .decompress_vec(data)
+                .expect("Failed to decompress"),
+            #[allow(unreachable_patterns)]
+            compressor_type => panic!("{}", CompressorTypeError::NotCompiledIn(compressor_type)),
         }
     }
 }
@@ -61,14 +144,41 @@ mod tests {
     #[test]
     fn test_compressor_type_try_from() {
         assert_eq!(CompressorType::try_from(0).unwrap(), CompressorType::None);
-        assert_eq!(CompressorType::try_from(1).unwrap(), CompressorType::LZ4);
-        assert!(CompressorType::try_from(2).is_err());
+        for compressor_type in [
+            CompressorType::LZ4,
+            CompressorType::Zstd,
+            CompressorType::Snappy,
+        ] {
+            let result = CompressorType::try_from(u8::from(compressor_type));
+            if compressor_type.is_supported() {
+                assert_eq!(result, Ok(compressor_type));
+            } else {
+                assert_eq!(
+                    result,
+                    Err(CompressorTypeError::NotCompiledIn(compressor_type))
+                );
+            }
+        }
+        assert_eq!(
+            CompressorType::try_from(4),
+            Err(CompressorTypeError::Unknown(4))
+        );
     }
 
     #[test]
     fn test_compressor_type_from() {
         assert_eq!(u8::from(CompressorType::None), 0);
         assert_eq!(u8::from(CompressorType::LZ4), 1);
+        assert_eq!(u8::from(CompressorType::Zstd), 2);
+        assert_eq!(u8::from(CompressorType::Snappy), 3);
+    }
+
+    #[test]
+    fn test_compressor_type_error_message() {
+        assert_eq!(
+            CompressorTypeError::NotCompiledIn(CompressorType::Zstd).to_string(),
+            "Compression codec Zstd not compiled in, build with the zstd feature."
+        );
     }
 
     #[test]
@@ -83,10 +193,10 @@ mod tests {
         assert_eq!(decompressed, data);
     }
 
-    #[test]
-    fn test_compressor_lz4() {
-        let compressor = Compressor::new(CompressorType::LZ4);
-        // Create some highly compressible data
+    // Compress some highly compressible data and back.
+    #[cfg(any(feature = "lz4", feature = "zstd", feature = "snappy"))]
+    fn check_round_trip(compressor_type: CompressorType) {
+        let compressor = Compressor::new(compressor_type);
         let data = vec![b'a'; 1000];
 
         let compressed = compressor.compress(&data);
@@ -95,5 +205,34 @@ mod tests {
 
         let decompressed = compressor.decompress(&compressed);
         assert_eq!(decompressed, data);
+
+        // Empty data comes back empty.
+        let compressed = compressor.compress(&[]);
+        assert_eq!(compressor.decompress(&compressed), Vec::<u8>::new());
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_compressor_lz4() {
+        check_round_trip(CompressorType::LZ4);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn test_compressor_zstd() {
+        check_round_trip(CompressorType::Zstd);
+    }
+
+    #[test]
+    #[cfg(feature = "snappy")]
+    fn test_compressor_snappy() {
+        check_round_trip(CompressorType::Snappy);
+    }
+
+    #[test]
+    #[cfg(not(feature = "zstd"))]
+    #[should_panic(expected = "Compression codec Zstd not compiled in")]
+    fn test_compressor_not_compiled_in() {
+        Compressor::new(CompressorType::Zstd);
     }
 }
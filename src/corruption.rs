@@ -0,0 +1,157 @@
+use crate::page_no::PageNo;
+use std::fmt;
+
+// Problems found when checking the structure of the DB file, for
+// example by Db::verify. Each carries the page number where the
+// problem was found.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Corruption {
+    // A page number that points past the end of the file.
+    PageOutOfRange(PageNo),
+    // A page that is not the type expected, or has an unknown type.
+    WrongPageType(PageNo),
+    // An overflow page with a used size larger than the page.
+    BadUsedSize(PageNo),
+    // An overflow page that points back into its own chain.
+    OverflowCycle(PageNo),
+    // An overflow chain, given by its head page, that holds fewer or more
+    // bytes than the lengths of the tuple at its start say.
+    OverflowLength(PageNo),
+    // A page with a version after the current master page.
+    VersionAfterMaster(PageNo),
+    // A page that fails its checksum or cannot be decrypted.
+    BadBlock(PageNo),
+    // A leaf or dir page with keys that are not in order.
+    UnsortedKeys(PageNo),
+    // A page with keys outside the range the dir page above routes to
+    // it.
+    KeyOutOfRange(PageNo),
+    // A page in the free page directory that is also in use.
+    FreePageInUse(PageNo),
+    // A page in the free page directory more than once.
+    FreePageTwice(PageNo),
+    // A master page with a count of free pages that does not match its
+    // free page directory.
+    FreePageCount(PageNo),
+    // The file is not a whole number of blocks, this block is cut short.
+    TruncatedBlock(PageNo),
+    // Creating the DB did not finish, this page is not yet the root page.
+    Unfinished(PageNo),
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Corruption::PageOutOfRange(page_no) => {
+                write!(f, "page {:?} is outside the file", page_no)
+            }
+            Corruption::WrongPageType(page_no) => {
+                write!(f, "page {:?} has the wrong page type", page_no)
+            }
+            Corruption::BadUsedSize(page_no) => {
+                write!(f, "overflow page {:?} has a bad used size", page_no)
+            }
+            Corruption::OverflowCycle(page_no) => {
+                write!(f, "overflow chain has a cycle at page {:?}", page_no)
+            }
+            Corruption::OverflowLength(page_no) => write!(
+                f,
+                "overflow chain at page {:?} does not match the tuple's length",
+                page_no
+            ),
+            Corruption::VersionAfterMaster(page_no) => {
+                write!(f, "page {:?} has a version after the master page", page_no)
+            }
+            Corruption::BadBlock(page_no) => {
+                write!(f, "page {:?} fails its checksum or decryption", page_no)
+            }
+            Corruption::UnsortedKeys(page_no) => {
+                write!(f, "page {:?} has keys out of order", page_no)
+            }
+            Corruption::KeyOutOfRange(page_no) => {
+                write!(f, "page {:?} has keys outside its range", page_no)
+            }
+            Corruption::FreePageInUse(page_no) => {
+                write!(f, "free page {:?} is in use", page_no)
+            }
+            Corruption::FreePageTwice(page_no) => {
+                write!(f, "page {:?} is free more than once", page_no)
+            }
+            Corruption::FreePageCount(page_no) => write!(
+                f,
+                "master page {:?} has the wrong count of free pages",
+                page_no
+            ),
+            Corruption::TruncatedBlock(page_no) => {
+                write!(f, "page {:?} is cut short, the file is truncated", page_no)
+            }
+            Corruption::Unfinished(page_no) => write!(
+                f,
+                "page {:?} is not a root page, creating the DB did not finish",
+                page_no
+            ),
+        }
+    }
+}
+
+impl Corruption {
+    // The page the problem was found on.
+    pub fn get_page_no(&self) -> PageNo {
+        match self {
+            Corruption::PageOutOfRange(page_no)
+            | Corruption::WrongPageType(page_no)
+            | Corruption::BadUsedSize(page_no)
+            | Corruption::OverflowCycle(page_no)
+            | Corruption::OverflowLength(page_no)
+            | Corruption::VersionAfterMaster(page_no)
+            | Corruption::BadBlock(page_no)
+            | Corruption::UnsortedKeys(page_no)
+            | Corruption::KeyOutOfRange(page_no)
+            | Corruption::FreePageInUse(page_no)
+            | Corruption::FreePageTwice(page_no)
+            | Corruption::FreePageCount(page_no)
+            | Corruption::TruncatedBlock(page_no)
+            | Corruption::Unfinished(page_no) => *page_no,
+        }
+    }
+}
+
+impl std::error::Error for Corruption {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corruption_display() {
+        let page_no = PageNo::from_u64(5);
+        let corruption = Corruption::FreePageInUse(page_no);
+        assert_eq!(corruption.get_page_no(), page_no);
+        assert_eq!(
+            corruption.to_string(),
+            format!("free page {:?} is in use", page_no)
+        );
+        assert_eq!(
+            Corruption::OverflowLength(page_no).to_string(),
+            format!(
+                "overflow chain at page {:?} does not match the tuple's length",
+                page_no
+            )
+        );
+        assert_eq!(
+            Corruption::UnsortedKeys(page_no).to_string(),
+            format!("page {:?} has keys out of order", page_no)
+        );
+        assert_eq!(
+            Corruption::FreePageCount(page_no).to_string(),
+            format!(
+                "master page {:?} has the wrong count of free pages",
+                page_no
+            )
+        );
+        assert_eq!(
+            Corruption::Unfinished(PageNo::from_u64(0)).to_string(),
+            "page PageNo(0) is not a root page, creating the DB did not finish"
+        );
+    }
+}
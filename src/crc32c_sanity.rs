@@ -0,0 +1,81 @@
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
+use crate::digby_error::DigbyError;
+use crate::nonce_mode::NonceMode;
+use crate::page::Page;
+use crate::page::PageTrait;
+use byteorder::LittleEndian;
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+// A CRC32C checksum of the page, as XxHashSanity but for tools that
+// already check CRC32C. It covers the same page bytes and is stored in
+// the same place, the last 4 bytes of the block.
+#[derive(Debug)]
+pub struct Crc32cSanity {}
+
+impl Crc32cSanity {
+    pub fn set_checksum(page: &mut Page) {
+        let checksum = crc32c::crc32c(&page.get_page_bytes()[0..]);
+        let offset = page.get_pg_ctr_bytes().len() as u64 - 4;
+        let mut cursor = Cursor::new(page.get_pg_ctr_bytes_mut());
+        cursor.set_position(offset);
+        cursor
+            .write_u32::<LittleEndian>(checksum)
+            .expect("Failed to write checksum");
+    }
+
+    pub fn verify_checksum(page: &Page) -> Result<(), DigbyError> {
+        if !Crc32cSanity::is_checksum_valid(page) {
+            return Err(DigbyError::ChecksumMismatch(page.get_page_number()));
+        }
+        Ok(())
+    }
+
+    pub fn is_checksum_valid(page: &Page) -> bool {
+        let calculated_checksum = crc32c::crc32c(&page.get_page_bytes()[0..]);
+        let offset = page.get_pg_ctr_bytes().len() as u64 - 4;
+        let mut cursor = std::io::Cursor::new(page.get_pg_ctr_bytes());
+        cursor.set_position(offset);
+        let stored_checksum = cursor.read_u32::<LittleEndian>().unwrap();
+        stored_checksum == calculated_checksum
+    }
+}
+
+impl BlockSanityProvider for Crc32cSanity {
+    fn get_id(&self) -> u8 {
+        BlockSanity::Crc32c.into()
+    }
+
+    fn get_bytes_used(&self) -> usize {
+        BlockSanity::get_bytes_used(BlockSanity::Crc32c)
+    }
+
+    fn set_block_sanity(&self, page: &mut Page, _key: &[u8], _nonce_mode: NonceMode) {
+        Crc32cSanity::set_checksum(page);
+    }
+
+    fn check_block_sanity(&self, page: &mut Page, _key: &[u8]) -> bool {
+        Crc32cSanity::is_checksum_valid(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_no::PageNo;
+
+    #[test]
+    fn test_crc32c_checksum() {
+        let mut page = Page::new(4096, 4092);
+        page.set_page_number(PageNo::from_u64(42));
+        Crc32cSanity::set_checksum(&mut page);
+        assert!(Crc32cSanity::verify_checksum(&page).is_ok());
+        // The stored checksum is the plain CRC32C of the page bytes.
+        let stored = u32::from_le_bytes(page.get_pg_ctr_bytes()[4092..].try_into().unwrap());
+        assert_eq!(stored, crc32c::crc32c(page.get_page_bytes()));
+        // Modify the page and verify that checksum verification fails
+        page.set_version(34); // Corrupt the page
+        let error = Crc32cSanity::verify_checksum(&page).unwrap_err();
+        assert!(matches!(error, DigbyError::ChecksumMismatch(page_no) if page_no.to_u64() == 42));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::DigbyError;
+use base64::prelude::{BASE64_STANDARD, Engine};
+use std::io::BufRead;
+
+// How the key and value columns of a CSV file read by Db::import_csv
+// are turned into the bytes stored. Utf8 stores the bytes of the field
+// as they are, Base64 (standard alphabet with padding) and Hex decode
+// them first, so binary keys and values can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvEncoding {
+    Utf8,
+    Base64,
+    Hex,
+}
+
+impl CsvEncoding {
+    // None if the field is not valid for the encoding.
+    pub fn decode(&self, field: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            CsvEncoding::Utf8 => Some(field.to_vec()),
+            CsvEncoding::Base64 => BASE64_STANDARD.decode(field).ok(),
+            CsvEncoding::Hex => {
+                if !field.len().is_multiple_of(2) {
+                    return None;
+                }
+                field
+                    .chunks(2)
+                    .map(|pair| {
+                        let high = (pair[0] as char).to_digit(16)?;
+                        let low = (pair[1] as char).to_digit(16)?;
+                        Some((high * 16 + low) as u8)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+// Reads the rows of a CSV file, RFC 4180 with commas between the
+// fields. A field in double quotes can hold commas, newlines and
+// doubled double quotes. Lines end with \n or \r\n and blank lines are
+// skipped. The fields are bytes, there is no check they are UTF-8.
+pub struct CsvReader<R: BufRead> {
+    reader: R,
+    line: u64,
+}
+
+// The fields of a row with the line it starts on.
+pub type CsvRow = (u64, Vec<Vec<u8>>);
+
+enum FieldState {
+    Start,
+    Unquoted,
+    Quoted,
+    // After the double quote that closes a quoted field.
+    Closed,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R) -> Self {
+        CsvReader { reader, line: 0 }
+    }
+
+    // The next row, its line counting from 1, or None at the end of the
+    // file. A quoted field that is not closed, or has
+    // more after the closing quote, is an Import error for the line the
+    // row starts on.
+    pub fn read_row(&mut self) -> Result<Option<CsvRow>, DigbyError> {
+        let mut row: Vec<Vec<u8>> = Vec::new();
+        let mut field: Vec<u8> = Vec::new();
+        let mut state = FieldState::Start;
+        let mut start_line = self.line + 1;
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if self.reader.read_until(b'\n', &mut buf)? == 0 {
+                return match state {
+                    FieldState::Quoted => Err(DigbyError::Import { line: start_line }),
+                    // The last row ended with a newline.
+                    _ => Ok(None),
+                };
+            }
+            self.line += 1;
+            if matches!(state, FieldState::Start)
+                && row.is_empty()
+                && (buf == b"\n" || buf == b"\r\n")
+            {
+                start_line = self.line + 1;
+                continue;
+            }
+            for &byte in &buf {
+                state = match (state, byte) {
+                    (FieldState::Quoted, b'"') => FieldState::Closed,
+                    (FieldState::Quoted, _) => {
+                        field.push(byte);
+                        FieldState::Quoted
+                    }
+                    (FieldState::Closed, b'"') => {
+                        field.push(b'"');
+                        FieldState::Quoted
+                    }
+                    (FieldState::Start, b'"') => FieldState::Quoted,
+                    (_, b',') => {
+                        row.push(std::mem::take(&mut field));
+                        FieldState::Start
+                    }
+                    (state, b'\n') => {
+                        if matches!(state, FieldState::Unquoted) && field.last() == Some(&b'\r') {
+                            field.pop();
+                        }
+                        row.push(std::mem::take(&mut field));
+                        return Ok(Some((start_line, row)));
+                    }
+                    (FieldState::Closed, b'\r') => FieldState::Closed,
+                    (FieldState::Closed, _) => {
+                        return Err(DigbyError::Import { line: start_line });
+                    }
+                    (_, _) => {
+                        field.push(byte);
+                        FieldState::Unquoted
+                    }
+                };
+            }
+            // The last line has no newline.
+            if !buf.ends_with(b"\n") && !matches!(state, FieldState::Quoted) {
+                row.push(field);
+                return Ok(Some((start_line, row)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_rows(csv: &[u8]) -> Result<Vec<CsvRow>, DigbyError> {
+        let mut reader = CsvReader::new(csv);
+        let mut rows = Vec::new();
+        while let Some(row) = reader.read_row()? {
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    fn fields(fields: &[&str]) -> Vec<Vec<u8>> {
+        fields
+            .iter()
+            .map(|field| field.as_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_reader_rows() {
+        let rows =
+            read_rows(b"a,b\r\n\n\"c,1\",\"say \"\"hi\"\"\"\n\"multi\n\nline\",,x\r\n\"\",last")
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (1, fields(&["a", "b"])),
+                (3, fields(&["c,1", "say \"hi\""])),
+                (4, fields(&["multi\n\nline", "", "x"])),
+                (7, fields(&["", "last"])),
+            ]
+        );
+        assert!(read_rows(b"").unwrap().is_empty());
+        assert_eq!(read_rows(b"a\n").unwrap(), vec![(1, fields(&["a"]))]);
+    }
+
+    #[test]
+    fn test_csv_reader_malformed() {
+        assert!(matches!(
+            read_rows(b"a,b\n\"open,b\nc,d\n"),
+            Err(DigbyError::Import { line: 2 })
+        ));
+        assert!(matches!(
+            read_rows(b"a,b\nc,d\n\"closed\"x,b\n"),
+            Err(DigbyError::Import { line: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_csv_encoding_decode() {
+        assert_eq!(CsvEncoding::Utf8.decode(b"key"), Some(b"key".to_vec()));
+        assert_eq!(CsvEncoding::Base64.decode(b"a2V5"), Some(b"key".to_vec()));
+        assert_eq!(CsvEncoding::Base64.decode(b"a2V"), None);
+        assert_eq!(CsvEncoding::Hex.decode(b"00fFa0"), Some(vec![0, 255, 160]));
+        assert_eq!(CsvEncoding::Hex.decode(b"0"), None);
+        assert_eq!(CsvEncoding::Hex.decode(b"0g"), None);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::Db;
+use crate::page_no::PageNo;
+use crate::tree_scan_handler::TreeScanHandler;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::ops::Bound;
+
+// An iterator over the key/values of the global tree in key order, see
+// Db::cursor, Db::cursor_range and Db::cursor_prefix. `for (key, value)
+// in &mut db` walks the whole tree with one.
+//
+// The cursor borrows the Db mutably for as long as it is held, as every
+// read goes through the page cache, so no commit can happen while it
+// is in use and it reads the tree of the commit that was current when
+// it was made. Rather than read the whole range up front as Db::range
+// does the tree is read BATCH_SIZE entries at a time, so a cursor that
+// is dropped after a few entries, or used with take, only reads the
+// pages it needs. To read a commit while making others use a Snapshot.
+//
+// The cursor can be moved with seek, key and value give the entry next
+// will return without moving it on. Positions are found by descending
+// the tree from its root, so paging on from a key kept from an earlier
+// cursor costs one descent rather than reading from the start.
+//
+// Oversized keys are ordered on the part of the key stored in the tree,
+// as for Db::range.
+pub struct Cursor<'a> {
+    db: &'a mut Db,
+    tree_page_no: PageNo,
+    // The start of the cursor's range, a seek does not go before it.
+    start: Vec<u8>,
+    // Where the next batch starts, None once the range has been read.
+    next_start: Option<Bound<Vec<u8>>>,
+    end: Option<Vec<u8>>,
+    // Only keys starting with the prefix, an oversized key can only be
+    // matched on the part in the tree until it is read.
+    prefix: Option<Vec<u8>>,
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a> Cursor<'a> {
+    // The entries read from the tree in one go.
+    pub const BATCH_SIZE: usize = 256;
+
+    pub fn new(
+        db: &'a mut Db,
+        tree_page_no: PageNo,
+        start: &[u8],
+        end: Option<&[u8]>,
+        prefix: Option<&[u8]>,
+    ) -> Self {
+        Cursor {
+            db,
+            tree_page_no,
+            start: start.to_vec(),
+            next_start: Some(TreeScanHandler::start_bound(start).map(<[u8]>::to_vec)),
+            end: end.map(<[u8]>::to_vec),
+            prefix: prefix.map(<[u8]>::to_vec),
+            entries: VecDeque::new(),
+        }
+    }
+
+    // Move the cursor to the first key >= key in its range, before or
+    // after where it is now. The entries already read are dropped and
+    // the next read descends the tree again from its root.
+    pub fn seek(&mut self, key: &[u8]) {
+        self.entries.clear();
+        let key_order = self.db.key_order();
+        let after_start =
+            self.start.is_empty() || key_order.compare(key, &self.start) != Ordering::Less;
+        let key = if after_start { key } else { &self.start };
+        self.next_start = Some(TreeScanHandler::start_bound(key).map(<[u8]>::to_vec));
+    }
+
+    // The key next would return, without moving the cursor on. None at
+    // the end of the range.
+    pub fn key(&mut self) -> Option<&[u8]> {
+        self.current().map(|(key, _)| key.as_slice())
+    }
+
+    // The value next would return, without moving the cursor on. None at
+    // the end of the range.
+    pub fn value(&mut self) -> Option<&[u8]> {
+        self.current().map(|(_, value)| value.as_slice())
+    }
+
+    // Read on until the front entry is one to return, if there are any
+    // left in the range.
+    fn current(&mut self) -> Option<&(Vec<u8>, Vec<u8>)> {
+        loop {
+            while let Some((key, _)) = self.entries.front() {
+                if self
+                    .prefix
+                    .as_ref()
+                    .is_none_or(|prefix| key.starts_with(prefix))
+                {
+                    return self.entries.front();
+                }
+                self.entries.pop_front();
+            }
+            self.next_start.as_ref()?;
+            self.read_batch();
+        }
+    }
+
+    fn read_batch(&mut self) {
+        let Some(start) = self.next_start.take() else {
+            return;
+        };
+        let (entries, next_start) = self.db.range_from_tree_limit(
+            start.as_ref().map(Vec::as_slice),
+            self.end.as_deref(),
+            self.tree_page_no,
+            Cursor::BATCH_SIZE,
+        );
+        self.next_start = next_start.map(Bound::Excluded);
+        self.entries.extend(entries);
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.current()?;
+        self.entries.pop_front()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Db {
+    type Item = (Vec<u8>, Vec<u8>);
+    type IntoIter = Cursor<'a>;
+
+    fn into_iter(self) -> Cursor<'a> {
+        self.cursor()
+    }
+}
@@ -1,21 +1,56 @@
-use crate::block_sanity::BlockSanity;
-use crate::compressor::CompressorType;
+use crate::batch_op::BatchOp;
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
+use crate::compact_handler::CompactHandler;
+use crate::compact_in_place_handler::CompactInPlaceHandler;
+use crate::compact_target::CompactTarget;
+use crate::compressor::{CompressorType, CompressorTypeError};
+use crate::corruption::Corruption;
+use crate::csv_reader::{CsvEncoding, CsvReader};
+use crate::cursor::Cursor;
 use crate::db_config::DbConfig;
 use crate::db_master_page::DbMasterPage;
 use crate::db_root_page::DbRootPage;
+use crate::digby_error::DigbyError;
+use crate::dump_handler::{DumpHandler, DumpRecord};
 use crate::file_layer::FileLayer;
 use crate::free_page_tracker::FreePageTracker;
+use crate::key_order::KeyOrder;
+use crate::nonce_mode::NonceMode;
+use crate::observer::Observer;
 use crate::overflow_tuple::OverflowTuple;
+use crate::page::Page;
 use crate::page::PageTrait;
+use crate::page::PageType;
 use crate::page_cache::PageCache;
 use crate::page_container_layer::PageContainerLayer;
+use crate::page_dump::PageDump;
 use crate::page_no::PageNo;
+use crate::read_tx::ReadTx;
+use crate::reader::{Reader, ReaderShared, ReaderSource};
+#[cfg(feature = "serde")]
+use crate::serde_format::{Bincode, SerdeFormat};
+use crate::snapshot::Snapshot;
+use crate::storage::Storage;
+use crate::sync_mode::SyncMode;
+use crate::table_dir_entry::TableDirEntry;
+use crate::table_info::TableInfo;
+use crate::table_root_cache::TableRootCache;
+use crate::tree_builder::TreeBuilder;
+use crate::tree_level::TreeLevel;
 use crate::tuple::{Overflow, Tuple, TupleTrait};
 use crate::tx_ctx::TxCtx;
+use crate::verify_handler::VerifyHandler;
+use crate::verify_report::VerifyReport;
+use crate::version_holder::VersionHolder;
 use crate::{
-    ClearHandler, Compressor, FreeDirPage, LeafPage, OverflowPageHandler, StoreTupleProcessor,
-    TreeDeleteHandler, TupleProcessor,
+    ClearHandler, Compressor, DirPage, FreeDirPage, LeafPage, OverflowPage, OverflowPageHandler,
+    StoreTupleProcessor, TreeDeleteHandler, TreeScanHandler, TupleProcessor,
 };
+use base64::prelude::{BASE64_STANDARD, Engine};
+use std::cmp::Ordering;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Bound;
+use std::sync::Arc;
 
 // Layers in the Db are:
 //   file layer - manipulate the file holding the db nodes.
@@ -24,10 +59,24 @@ use crate::{
 //                pages.
 //
 // Compressor to use when compressing large tuples.
+// A tree of the committed DB as (table name, the table's own compressor,
+// root page), the global tree has no name and uses the DB's compressor.
+type CommittedTree = (Option<Vec<u8>>, Option<CompressorType>, PageNo);
+
+type KeyValue = (Vec<u8>, Vec<u8>);
+
 pub struct Db {
     page_cache: PageCache,
     compressor: Compressor,
     db_config: DbConfig,
+    table_root_cache: TableRootCache,
+    // Commits are refused, see Db::open_read_only.
+    read_only: bool,
+    // The version of the master page the cached pages of a read only Db
+    // belong to.
+    read_only_version: u64,
+    // Shared with the Readers of the Db, see Db::reader.
+    reader_shared: Arc<ReaderShared>,
 }
 
 // TODO - initial db layout.
@@ -71,6 +120,25 @@ impl Db {
     // TODO - should support multiple block sizes at once to allow very
     // large pages for large tuples.
     pub const BLOCK_SIZE: usize = 4096;
+    // The range of block sizes a DB can have, any size in it not only a
+    // power of two.
+    pub const MIN_BLOCK_SIZE: usize = 128;
+    pub const MAX_BLOCK_SIZE: usize = 65536;
+    // The smallest page, the block less its block sanity, it must hold
+    // the largest fixed layout which is the master page.
+    pub const MIN_PAGE_SIZE: usize = DbMasterPage::SIZE;
+    // The pages a new DB file is made with, and the fixed pages among
+    // them - the root page, the two master pages, the free page directory
+    // and the roots of the table directory and global tree.
+    pub const INITIAL_PAGES: u64 = 10;
+    pub const FIXED_PAGES: u64 = 6;
+    // The free pages added to the file at a time, see
+    // set_free_page_growth.
+    pub const FREE_PAGE_GROWTH: u64 = 8;
+    // The largest key and value a put takes, a larger one is returned as
+    // KeyTooLarge or ValueTooLarge, see TupleProcessor::MAX_KEY_SIZE.
+    pub const MAX_KEY_SIZE: usize = TupleProcessor::MAX_KEY_SIZE;
+    pub const MAX_VALUE_SIZE: usize = TupleProcessor::MAX_VALUE_SIZE;
 
     // Create a DB object.
     //   path - the path to the file to use. If the file does not exist then create it for
@@ -82,6 +150,16 @@ impl Db {
         Db::new_with_page_size(path, key, compressor_type, Db::BLOCK_SIZE)
     }
 
+    // As "new" but failing to open the DB is returned as an error rather
+    // than a panic.
+    pub fn try_new(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+    ) -> Result<Self, DigbyError> {
+        Db::try_new_with_page_size(path, key, compressor_type, Db::BLOCK_SIZE)
+    }
+
     // As "new" but allows a different block_size to be used.
     pub fn new_with_page_size(
         path: &str,
@@ -89,69 +167,512 @@ impl Db {
         compressor_type: CompressorType,
         block_size: usize,
     ) -> Self {
+        Db::try_new_with_page_size(path, key, compressor_type, block_size)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new_with_page_size(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+    ) -> Result<Self, DigbyError> {
+        let block_sanity = BlockSanity::for_key(key.is_some());
+        Db::try_new_with_cipher(path, key, compressor_type, block_size, block_sanity)
+    }
+
+    // As "try_new_with_page_size" but an encrypted DB uses the cipher
+    // given by block_sanity rather than AES-128-GCM, for example
+    // ChaCha20-Poly1305 which is faster without AES hardware support. A
+    // DB without a key can use a CRC32C checksum rather than xxhash_32.
+    // An existing DB must be opened with the block sanity it was created
+    // with.
+    pub fn try_new_with_cipher(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+        block_sanity: BlockSanity,
+    ) -> Result<Self, DigbyError> {
+        let db_file = Db::open_locked_file(path)?;
+        let db = Db::try_new_with_block_sanity(
+            Box::new(db_file),
+            key.clone(),
+            compressor_type,
+            block_size,
+            0,
+            block_sanity,
+        )?;
+        db.reader_shared.set_source(ReaderSource {
+            path: path.to_string(),
+            key: key.map(|key| block_sanity.fit_key(&key)),
+            compressor_type,
+            block_size,
+            sanity_provider: block_sanity.get_provider(),
+            key_order: db.db_config.key_order,
+        });
+        Ok(db)
+    }
+
+    // As open_file but with an exclusive lock on the file, so a second
+    // Db cannot write the file while it is open. The lock is advisory,
+    // flock on unix, and is released when the file is closed as the Db
+    // is dropped. Returns Locked if another Db, in this process or
+    // another, has the file open for writing or reading.
+    pub fn open_locked_file(path: &str) -> Result<std::fs::File, DigbyError> {
+        Db::lock_file(Db::open_file(path)?)
+    }
+
+    // Take the exclusive lock on an open file, see open_locked_file.
+    fn lock_file(db_file: std::fs::File) -> Result<std::fs::File, DigbyError> {
+        match db_file.try_lock() {
+            Ok(()) => Ok(db_file),
+            Err(std::fs::TryLockError::WouldBlock) => Err(DigbyError::Locked),
+            Err(std::fs::TryLockError::Error(error)) => Err(error.into()),
+        }
+    }
+
+    // Open the DB file for reading and writing, creating it if it does
+    // not exist.
+    pub fn open_file(path: &str) -> std::io::Result<std::fs::File> {
         use std::fs::OpenOptions;
         use std::path::Path;
 
-        let mut is_new = false;
-
-        // Might make sense to lock the file.
-        // If file exists open to append, new database,
-        // else treat as an existing database
-        let db_file: std::fs::File;
+        // Not locked, see open_locked_file.
+        // If file exists open it, an empty file is treated as a new
+        // database, else create the file for a new database.
         if Path::new(path).exists() {
-            db_file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(path)
-                .expect("Failed to open existing DB file");
-            if std::fs::metadata(path).unwrap().len() == 0 {
-                // If file is empty treat as new database.
-                is_new = true;
-            }
+            OpenOptions::new().read(true).write(true).open(path)
         } else {
             // File does not exist, create.
-            db_file = OpenOptions::new()
+            OpenOptions::new()
                 .write(true)
                 .read(true)
                 .create(true)
                 .truncate(true) // Not necessary as file does not exist but clippy wants it.
                 .open(path)
-                .expect("Failed to open or create DB file");
+        }
+    }
+
+    // Open an existing DB without write access, for a process that must
+    // never change the file. The file is opened read only so the OS
+    // stops any write, a new DB is not created and commits, and
+    // anything else that writes, fail with a PermissionDenied error. The
+    // file is not synced when the Db is dropped. Reads see the commit
+    // that was current when they are made, as for any other Db.
+    pub fn open_read_only(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+    ) -> Self {
+        Db::try_open_read_only(path, key, compressor_type)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_open_read_only(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+    ) -> Result<Self, DigbyError> {
+        Db::try_open_read_only_with_page_size(path, key, compressor_type, Db::BLOCK_SIZE)
+    }
+
+    pub fn try_open_read_only_with_page_size(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+    ) -> Result<Self, DigbyError> {
+        let block_sanity = BlockSanity::for_key(key.is_some());
+        Db::try_open_read_only_with_cipher(path, key, compressor_type, block_size, block_sanity)
+    }
+
+    pub fn try_open_read_only_with_cipher(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+        block_sanity: BlockSanity,
+    ) -> Result<Self, DigbyError> {
+        let db_file = Db::open_read_only_file(path)?;
+        Db::try_open_read_only_with_storage(
+            Box::new(db_file),
+            path,
+            key,
+            compressor_type,
+            block_size,
+            block_sanity,
+        )
+    }
+
+    // Open the DB file read only with a shared lock, see
+    // open_read_only.
+    pub fn open_read_only_file(path: &str) -> Result<std::fs::File, DigbyError> {
+        let db_file = std::fs::File::open(path)?;
+        // A shared lock stops a writer opening the file while it is
+        // read. If a writer already has it the reads go ahead without
+        // the lock, copy on write means each read sees a whole commit.
+        if let Err(std::fs::TryLockError::Error(error)) = db_file.try_lock_shared() {
+            return Err(error.into());
+        }
+        // An empty file would be made into a new DB.
+        if db_file.metadata()?.len() == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Cannot create a DB read only",
+            )
+            .into());
+        }
+        Ok(db_file)
+    }
+
+    // As try_open_read_only_with_cipher with the file from
+    // open_read_only_file already opened into storage, path is where
+    // Readers open the file again.
+    pub fn try_open_read_only_with_storage(
+        storage: Box<dyn Storage>,
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+        block_sanity: BlockSanity,
+    ) -> Result<Self, DigbyError> {
+        // The cipher may not be in this build.
+        assert!(
+            block_sanity.is_supported(),
+            "{}",
+            block_sanity.get_not_compiled_in_message()
+        );
+        Db::try_open_read_only_with_sanity_provider(
+            storage,
+            path,
+            key.map(|key| block_sanity.fit_key(&key)),
+            compressor_type,
+            block_size,
+            block_sanity.get_provider(),
+        )
+    }
+
+    // As try_open_read_only_with_storage with the block sanity from a
+    // provider, see try_new_with_sanity_provider.
+    pub fn try_open_read_only_with_sanity_provider(
+        mut storage: Box<dyn Storage>,
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+        sanity_provider: Arc<dyn BlockSanityProvider>,
+    ) -> Result<Self, DigbyError> {
+        // A writer would create the DB again, a reader cannot.
+        if Db::check_page_size(block_size, sanity_provider.get_bytes_used()).is_ok()
+            && Db::is_partly_initialized(
+                storage.as_mut(),
+                block_size,
+                sanity_provider.as_ref(),
+                key.as_deref(),
+            )?
+        {
+            return Err(Corruption::Unfinished(PageNo::new(0, 0)).into());
+        }
+        let db_config = DbConfig::builder()
+            .block_size(block_size)
+            .compressor_type(compressor_type)
+            .build();
+        let mut db = Db::try_new_with_sanity_provider(
+            storage,
+            key.clone(),
+            db_config,
+            sanity_provider.clone(),
+        )?;
+        db.read_only = true;
+        db.reader_shared.set_source(ReaderSource {
+            path: path.to_string(),
+            key,
+            compressor_type,
+            block_size,
+            sanity_provider,
+            key_order: db.db_config.key_order,
+        });
+        Ok(db)
+    }
+
+    pub fn open_auto(path: &str, key: Option<Vec<u8>>) -> Self {
+        Db::try_open_auto(path, key).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Open an existing DB knowing only its key, the block size, the
+    // checksum or cipher and the compressor are found from the file, for
+    // tools that open any DB. A file that does not exist, or is empty, is
+    // created with the default block size and no compression.
+    //
+    // A DB compressed with a codec this build does not have is a
+    // CompressorMismatch error. Panics if the file is not a DB or the key
+    // is wrong, see detect_block_size.
+    pub fn try_open_auto(path: &str, key: Option<Vec<u8>>) -> Result<Self, DigbyError> {
+        let root_page = match std::fs::File::open(path) {
+            Ok(mut db_file) => Db::detect_root_page(&mut db_file, key.as_deref()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        let (block_size, block_sanity, compressor_type) = match root_page {
+            Some((block_size, block_sanity, root_page)) => (
+                block_size,
+                block_sanity,
+                CompressorType::try_from(root_page.get_compression_type())
+                    .map_err(|error| DigbyError::CompressorMismatch(error.to_string()))?,
+            ),
+            None => (
+                Db::BLOCK_SIZE,
+                BlockSanity::for_key(key.is_some()),
+                CompressorType::None,
+            ),
+        };
+        Db::try_new_with_cipher(path, key, compressor_type, block_size, block_sanity)
+    }
+
+    // Find the block size of the DB in the storage, None if the storage
+    // is empty. The block size is needed to read the root page, so each
+    // block size the storage is a whole number of blocks of is tried in
+    // turn, powers of two from MIN_BLOCK_SIZE to MAX_BLOCK_SIZE first
+    // then the rest - the first block of that size must pass the block
+    // sanity check, checksum or decryption, and be a root page with that
+    // block size stored. The sanity check covers the whole block so the
+    // bytes at the start of a larger block do not pass as a smaller block.
+    //
+    // Panics if no block size fits, the storage is not a DB or the key is
+    // wrong.
+    pub fn detect_block_size(storage: &mut dyn Storage, key: Option<&[u8]>) -> Option<usize> {
+        Db::detect_format(storage, key).map(|(block_size, _)| block_size)
+    }
+
+    // As detect_block_size but also finds the block sanity of the DB.
+    // Without a key each checksum is tried at each block size, with one
+    // each cipher in this build.
+    pub fn detect_format(
+        storage: &mut dyn Storage,
+        key: Option<&[u8]>,
+    ) -> Option<(usize, BlockSanity)> {
+        Db::detect_root_page(storage, key)
+            .map(|(block_size, block_sanity, _)| (block_size, block_sanity))
+    }
+
+    // As detect_format, with the root page that was found.
+    fn detect_root_page(
+        storage: &mut dyn Storage,
+        key: Option<&[u8]>,
+    ) -> Option<(usize, BlockSanity, DbRootPage)> {
+        let storage_size = storage
+            .len()
+            .expect("Failed to get the size of the DB storage");
+        if storage_size == 0 {
+            return None;
+        }
+        let sanity_types: Vec<BlockSanity> = if key.is_some() {
+            [BlockSanity::Aes128Gcm, BlockSanity::ChaCha20Poly1305]
+                .into_iter()
+                .filter(BlockSanity::is_supported)
+                .collect()
+        } else {
+            vec![BlockSanity::XxH32Checksum, BlockSanity::Crc32c]
+        };
+        let block_sizes = (Db::MIN_BLOCK_SIZE..=Db::MAX_BLOCK_SIZE)
+            .filter(|block_size| block_size.is_power_of_two())
+            .chain(
+                (Db::MIN_BLOCK_SIZE..=Db::MAX_BLOCK_SIZE)
+                    .filter(|block_size| !block_size.is_power_of_two()),
+            );
+        for block_size in block_sizes {
+            if storage_size >= block_size as u64 && storage_size.is_multiple_of(block_size as u64) {
+                for &sanity_type in &sanity_types {
+                    let mut page = Page::new(
+                        block_size,
+                        block_size - BlockSanity::get_bytes_used(sanity_type),
+                    );
+                    storage
+                        .read_exact_at(page.get_pg_ctr_bytes_mut(), 0)
+                        .expect("Failed to read the DB storage");
+                    let mut enc_key = key.unwrap_or(&[]).to_vec();
+                    enc_key.resize(sanity_type.get_key_size(), 0);
+                    if sanity_type.is_block_sane(&mut page, &enc_key)
+                        && DbRootPage::is_db_root_page(&page)
+                    {
+                        let root_page = DbRootPage::from_page(page);
+                        // 0 in a file from before the block size was stored.
+                        let stored_block_size = root_page.get_block_size() as usize;
+                        if stored_block_size == 0 || stored_block_size == block_size {
+                            return Some((block_size, sanity_type, root_page));
+                        }
+                    }
+                }
+            }
+        }
+        panic!("Failed to find the block size of the DB, it is not a DB or the key is wrong");
+    }
+
+    // As "new_with_page_size" but the blocks are kept in the given
+    // storage rather than a file. If the storage is empty a new
+    // database is created in it. Any Storage can be used, a File,
+    // MemoryStorage to run without the disk or one for a block device.
+    pub fn new_with_storage(
+        storage: Box<dyn Storage>,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+    ) -> Self {
+        Db::try_new_with_storage(storage, key, compressor_type, block_size)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new_with_storage(
+        storage: Box<dyn Storage>,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+    ) -> Result<Self, DigbyError> {
+        Db::try_new_with_overflow_threshold(storage, key, compressor_type, block_size, 0)
+    }
+
+    // As "try_new_with_storage" but a new database stores values of
+    // overflow_threshold bytes or more in overflow pages, 0 for the
+    // default for the page size. The threshold is kept in the root page,
+    // an existing database uses the one it was created with and
+    // overflow_threshold is ignored.
+    pub fn try_new_with_overflow_threshold(
+        storage: Box<dyn Storage>,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+        overflow_threshold: usize,
+    ) -> Result<Self, DigbyError> {
+        let block_sanity = BlockSanity::for_key(key.is_some());
+        Db::try_new_with_block_sanity(
+            storage,
+            key,
+            compressor_type,
+            block_size,
+            overflow_threshold,
+            block_sanity,
+        )
+    }
+
+    // As "try_new_with_overflow_threshold" but with the block sanity
+    // given rather than following the key. An encrypting block sanity
+    // needs a key, a checksum ignores it.
+    pub fn try_new_with_block_sanity(
+        storage: Box<dyn Storage>,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+        block_size: usize,
+        overflow_threshold: usize,
+        block_sanity: BlockSanity,
+    ) -> Result<Self, DigbyError> {
+        let db_config = DbConfig::builder()
+            .block_size(block_size)
+            .compressor_type(compressor_type)
+            .block_sanity(block_sanity)
+            .overflow_threshold(overflow_threshold)
+            .build();
+        Db::try_new_with_config(storage, key, db_config)
+    }
+
+    // As "try_new_with_block_sanity" but the options are taken from a
+    // DbConfig, so options such as the nonce mode are in place before a
+    // new DB's first pages are written. The page size is worked out
+    // from the block size and block sanity.
+    pub fn try_new_with_config(
+        storage: Box<dyn Storage>,
+        key: Option<Vec<u8>>,
+        db_config: DbConfig,
+    ) -> Result<Self, DigbyError> {
+        let block_sanity = db_config.block_sanity;
+        // The cipher may not be in this build.
+        assert!(
+            block_sanity.is_supported(),
+            "{}",
+            block_sanity.get_not_compiled_in_message()
+        );
+        Db::check_block_size(db_config.block_size, block_sanity)?;
+        Db::try_new_with_sanity_provider(
+            storage,
+            key.map(|key| block_sanity.fit_key(&key)),
+            db_config,
+            block_sanity.get_provider(),
+        )
+    }
+
+    // As "try_new_with_config" but the blocks are checked by the given
+    // provider, which can be one from outside the crate, rather than
+    // the block sanity in the DbConfig. The key is given to the provider
+    // as it is. The provider's id is kept in the root page of a new DB
+    // and an existing DB must have been created with the same id, see
+    // BlockSanityProvider.
+    pub fn try_new_with_sanity_provider(
+        mut storage: Box<dyn Storage>,
+        key: Option<Vec<u8>>,
+        db_config: DbConfig,
+        sanity_provider: Arc<dyn BlockSanityProvider>,
+    ) -> Result<Self, DigbyError> {
+        let Ok(block_sanity) = BlockSanity::try_from(sanity_provider.get_id()) else {
+            panic!(
+                "Block sanity id {} is not a block sanity of the crate or from {}",
+                sanity_provider.get_id(),
+                BlockSanity::FIRST_CUSTOM_ID
+            );
+        };
+        let block_size = db_config.block_size;
+        let compressor_type = db_config.compressor_type;
+        let overflow_threshold = db_config.overflow_threshold;
+        let sanity_bytes_used = sanity_provider.get_bytes_used();
+        Db::check_page_size(block_size, sanity_bytes_used)?;
+        assert!(
+            key.is_some() || !sanity_provider.is_encrypted(),
+            "{:?} needs a key",
+            block_sanity
+        );
+        // If the storage is empty treat as new database. If creating it
+        // was cut short, before the root page was written, start again.
+        let mut is_new = storage.is_empty()?;
+        if !is_new
+            && Db::is_partly_initialized(
+                storage.as_mut(),
+                block_size,
+                sanity_provider.as_ref(),
+                key.as_deref(),
+            )?
+        {
+            storage.set_len(0)?;
             is_new = true;
         }
+        // Opened with another block size the root page would fail its
+        // checksum, or the file would not be a whole number of blocks,
+        // look for the block size it was created with first.
+        if !is_new
+            && let Some(stored_block_size) =
+                Db::stored_block_size(storage.as_mut(), sanity_provider.as_ref())?
+            && stored_block_size != block_size
+        {
+            return Err(DigbyError::BlockSizeMismatch(stored_block_size, block_size));
+        }
 
-        // Set up the file layer with the open file.
-        let file_layer: FileLayer = FileLayer::new(db_file, block_size);
+        // Set up the file layer with the storage.
+        let file_layer: FileLayer = FileLayer::new_with_storage(storage, block_size)?;
         // Create block layer - this will depend on if encrytion is being
         // used or just checksums. There is no encryption and checksum as
-        // the Aes128Gcm has built in checksum support.
-        // TODO -  checksum hardcoded to xxHash32 and encryption to
-        // AES-128-GCM.
+        // the AEAD ciphers have built in checksum support.
         // File layer is passed to block layer.
-        let block_layer: PageContainerLayer;
-        let sanity_type: BlockSanity;
-        let page_config: DbConfig;
-        if let Some(k) = key {
-            sanity_type = BlockSanity::Aes128Gcm;
-            let sanity_bytes_used = BlockSanity::get_bytes_used(BlockSanity::Aes128Gcm);
-            page_config = DbConfig::builder()
-                .block_size(block_size)
-                .page_size(block_size - sanity_bytes_used)
-                .compressor_type(compressor_type)
-                .block_sanity(sanity_type)
-                .build();
-            block_layer = PageContainerLayer::new_with_key(file_layer, page_config, k);
-        } else {
-            sanity_type = BlockSanity::XxH32Checksum;
-            let sanity_bytes_used = BlockSanity::get_bytes_used(BlockSanity::XxH32Checksum);
-            page_config = DbConfig::builder()
-                .block_size(block_size)
-                .page_size(block_size - sanity_bytes_used)
-                .compressor_type(compressor_type)
-                .block_sanity(sanity_type)
-                .build();
-            block_layer = PageContainerLayer::new(file_layer, page_config);
-        }
+        let sanity_type = block_sanity;
+        let page_config = DbConfig {
+            page_size: block_size - sanity_bytes_used,
+            block_sanity_size: sanity_bytes_used,
+            block_sanity,
+            ..db_config
+        };
+        let block_layer = PageContainerLayer::new_with_provider(
+            file_layer,
+            page_config,
+            key.unwrap_or_default(),
+            sanity_provider,
+        );
         // Create page cache with the block layer.
         let page_cache: PageCache = PageCache::new(block_layer);
 
@@ -159,34 +680,303 @@ impl Db {
             page_cache,
             compressor: Compressor::new(compressor_type),
             db_config: page_config,
+            table_root_cache: TableRootCache::new(),
+            read_only: false,
+            read_only_version: 0,
+            reader_shared: Arc::new(ReaderShared::new()),
         };
 
         if is_new {
+            let max_overflow_threshold = TupleProcessor::max_overflow_threshold(&db.db_config);
+            if overflow_threshold > max_overflow_threshold {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Overflow threshold {} is larger than {}",
+                        overflow_threshold, max_overflow_threshold
+                    ),
+                )
+                .into());
+            }
+            if db.db_config.initial_pages < Db::FIXED_PAGES {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Initial pages {} is fewer than the {} fixed pages",
+                        db.db_config.initial_pages,
+                        Db::FIXED_PAGES
+                    ),
+                )
+                .into());
+            }
             // Need to populate the new database with some metadata pages
             // including the sanity_type (encryption or checksum).
-            db.init_db_file(sanity_type)
-                .expect("Failed to initialize DB file");
+            db.init_db_file(sanity_type).map_err(|error| {
+                std::io::Error::new(
+                    error.kind(),
+                    format!("Failed to initialize DB file: {}", error),
+                )
+            })?;
         } else {
             // The DB already exists, check it is sane.
-            db.check_db_integrity().expect("DB integrity check failed");
+            db.check_db_integrity()?;
+        }
+        let master_page = db.try_get_master_page()?;
+        db.publish_commit(&master_page);
+        Ok(db)
+    }
+
+    // Whether the storage holds a DB that init_db_file did not finish.
+    // The root page is written last so until it is the first block is
+    // still the free page generate_free_pages made. Once written the
+    // first block is always the root page, a root page that fails its
+    // checksum is not taken as a DB to create again.
+    fn is_partly_initialized(
+        storage: &mut dyn Storage,
+        block_size: usize,
+        sanity_provider: &dyn BlockSanityProvider,
+        key: Option<&[u8]>,
+    ) -> Result<bool, DigbyError> {
+        if storage.len()? < block_size as u64 {
+            return Ok(false);
+        }
+        let mut page = Page::new(block_size, block_size - sanity_provider.get_bytes_used());
+        storage.read_exact_at(page.get_pg_ctr_bytes_mut(), 0)?;
+        Ok(
+            sanity_provider.check_block_sanity(&mut page, key.unwrap_or(&[]))
+                && page.try_get_type() == Some(PageType::Free)
+                && page.get_page_number() == PageNo::new(0, 0),
+        )
+    }
+
+    // The block size kept in the root page of a checksummed DB, None if
+    // it is encrypted, from before the block size was stored or the
+    // block of that size is not a sane root page. The page is at the
+    // start of a checksummed block so the size can be read before the
+    // block is checked.
+    fn stored_block_size(
+        storage: &mut dyn Storage,
+        sanity_provider: &dyn BlockSanityProvider,
+    ) -> Result<Option<usize>, DigbyError> {
+        if sanity_provider.is_encrypted() || storage.len()? < Db::MIN_BLOCK_SIZE as u64 {
+            return Ok(None);
+        }
+        let mut size_bytes = [0u8; 4];
+        storage.read_exact_at(&mut size_bytes, DbRootPage::BLOCK_SIZE_OFFSET)?;
+        let block_size = u32::from_le_bytes(size_bytes) as usize;
+        if !(Db::MIN_BLOCK_SIZE..=Db::MAX_BLOCK_SIZE).contains(&block_size)
+            || storage.len()? < block_size as u64
+        {
+            return Ok(None);
+        }
+        if block_size < sanity_provider.get_bytes_used() {
+            return Ok(None);
+        }
+        let mut page = Page::new(block_size, block_size - sanity_provider.get_bytes_used());
+        storage.read_exact_at(page.get_pg_ctr_bytes_mut(), 0)?;
+        if sanity_provider.check_block_sanity(&mut page, &[]) && DbRootPage::is_db_root_page(&page)
+        {
+            return Ok(Some(block_size));
+        }
+        Ok(None)
+    }
+
+    // A block size must be from MIN_BLOCK_SIZE to MAX_BLOCK_SIZE and
+    // leave MIN_PAGE_SIZE for the page after the block sanity, an
+    // encrypted DB needs a larger block than a checksummed one.
+    pub fn check_block_size(
+        block_size: usize,
+        block_sanity: BlockSanity,
+    ) -> Result<(), DigbyError> {
+        Db::check_page_size(block_size, BlockSanity::get_bytes_used(block_sanity))
+    }
+
+    // As check_block_size for a block sanity using sanity_bytes_used
+    // bytes of each block.
+    fn check_page_size(block_size: usize, sanity_bytes_used: usize) -> Result<(), DigbyError> {
+        if !(Db::MIN_BLOCK_SIZE..=Db::MAX_BLOCK_SIZE).contains(&block_size)
+            || block_size < sanity_bytes_used + Db::MIN_PAGE_SIZE
+        {
+            return Err(DigbyError::InvalidBlockSize(block_size));
         }
-        db
+        Ok(())
     }
 
-    pub fn delete(&mut self, key: &[u8]) -> bool {
+    pub fn delete(&mut self, key: impl AsRef<[u8]>) -> bool {
+        let key = key.as_ref();
         let mut tx_ctx = self.new_transaction();
         let deleted = self.delete_txn(key, &mut tx_ctx);
-        self.commit(&mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
         deleted
     }
 
+    // As delete but errors are returned rather than panicking, the pages
+    // are checked as they are for try_put.
+    pub fn try_delete(&mut self, key: impl AsRef<[u8]>) -> Result<bool, DigbyError> {
+        let key = key.as_ref();
+        let mut tx_ctx = self.try_new_transaction()?;
+        self.try_get_from_tree(key, tx_ctx.global_root_page_no)?;
+        let deleted = self.delete_txn(key, &mut tx_ctx);
+        self.commit(&mut tx_ctx)?;
+        Ok(deleted)
+    }
+
+    // Make all the puts and deletes in a single commit, a crash or a
+    // write error leaves either all of them or none of them in the DB.
+    // Cheaper than a commit per op as the master page is written and
+    // the file synced once for the batch.
+    pub fn write_batch(&mut self, ops: Vec<BatchOp>) -> std::io::Result<()> {
+        let mut tx_ctx = self.new_transaction();
+        for op in ops {
+            match op {
+                BatchOp::Put(key, value) => self.put_txn(&key, &value, &mut tx_ctx),
+                BatchOp::Delete(key) => {
+                    self.delete_txn(&key, &mut tx_ctx);
+                }
+            }
+        }
+        self.commit(&mut tx_ctx)
+    }
+
+    pub fn bulk_load(&mut self, sorted: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) {
+        self.try_bulk_load(sorted)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    // Load the keys and values into an empty DB in a single commit, for
+    // an initial import. They must be in key order with no key given
+    // twice, this is only checked by a debug assert - out of order keys
+    // give a tree that cannot find them. The leaf pages are packed full
+    // in key order and the dir pages built over them, see TreeBuilder,
+    // rather than each key being stored from the root down, and the file
+    // is synced once at the end, so it is much quicker than a put for
+    // each key. Values are compressed as they are for put. An oversized
+    // key is stored under its short key, which is out of order, so those
+    // are put into the tree once it is built.
+    //
+    // Returns NotEmpty if the global tree has any keys, the tables are
+    // not looked at. On an error nothing is committed.
+    pub fn try_bulk_load(
+        &mut self,
+        sorted: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<(), DigbyError> {
+        if self.read_only {
+            return Err(Db::read_only_error().into());
+        }
+        let mut tx_ctx = self.try_new_transaction()?;
+        if !self.global_tree_is_empty(&tx_ctx)? {
+            return Err(DigbyError::NotEmpty);
+        }
+        if let Err(error) = self.bulk_load_txn(sorted, &mut tx_ctx) {
+            // Drop the pages written for the load, as commit does when
+            // it fails.
+            self.page_cache.revert();
+            self.page_cache.take_write_error();
+            return Err(error);
+        }
+        Ok(self.commit(&mut tx_ctx)?)
+    }
+
+    fn global_tree_is_empty(&mut self, tx_ctx: &TxCtx) -> Result<bool, DigbyError> {
+        let root_page = self
+            .page_cache
+            .try_get_page_ref(tx_ctx.global_root_page_no)?;
+        Ok(
+            root_page.get_type() == PageType::LeafPage
+                && LeafPage::get_entries_size(root_page) == 0,
+        )
+    }
+
+    fn bulk_load_txn(
+        &mut self,
+        sorted: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+        tx_ctx: &mut TxCtx,
+    ) -> Result<(), DigbyError> {
+        let mut tree_builder = TreeBuilder::new(tx_ctx.new_version, &self.db_config);
+        let mut oversized: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        let mut last_key: Option<Vec<u8>> = None;
+        for (key, value) in sorted {
+            debug_assert!(
+                last_key.as_ref().is_none_or(|last_key| {
+                    self.db_config.key_order.compare(last_key, &key) == Ordering::Less
+                }),
+                "Bulk load keys must be in order with no duplicates."
+            );
+            TupleProcessor::check_sizes(key.len(), value.len())?;
+            if TupleProcessor::is_oversized_key(&key, &self.db_config) {
+                oversized.push((key.clone(), value));
+            } else {
+                let tuple = TupleProcessor::generate_tuple(
+                    &key,
+                    &value,
+                    &mut self.page_cache,
+                    &mut tx_ctx.free_page_tracker,
+                    tx_ctx.new_version,
+                    &self.compressor,
+                    &self.db_config,
+                );
+                tree_builder.add_tuple(&tuple, &mut tx_ctx.free_page_tracker, &mut self.page_cache);
+            }
+            last_key = Some(key);
+        }
+        // The empty root leaf page is replaced by the new tree.
+        tx_ctx
+            .free_page_tracker
+            .return_free_page_no(tx_ctx.global_root_page_no);
+        tx_ctx.global_root_page_no =
+            tree_builder.finish(&mut tx_ctx.free_page_tracker, &mut self.page_cache);
+        for (key, value) in oversized {
+            self.put_with_compression_txn(&key, &value, true, tx_ctx)?;
+        }
+        Ok(())
+    }
+
+    // Delete a key from the DB, returning the value it had or None if
+    // the key was not in the DB.
+    pub fn delete_returning(&mut self, key: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+        let key = key.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        let old_value = self.delete_returning_txn(key, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+        old_value
+    }
+
+    // As delete_returning but errors are returned rather than panicking.
+    // The tree is only descended by the delete, unlike try_put the pages
+    // are not checked first, a bad page on the way down panics.
+    pub fn try_delete_returning(
+        &mut self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        let key = key.as_ref();
+        TupleProcessor::check_key_size(key.len())?;
+        let mut tx_ctx = self.try_new_transaction()?;
+        let old_value = self.delete_returning_txn(key, &mut tx_ctx);
+        self.commit(&mut tx_ctx)?;
+        Ok(old_value)
+    }
+
+    // The value comes from the tuple the delete removed, so the tree is
+    // only descended once.
+    pub fn delete_returning_txn(&mut self, key: &[u8], tx_ctx: &mut TxCtx) -> Option<Vec<u8>> {
+        let deleted_tuple = self.delete_tuple_txn(key, tx_ctx)?;
+        Some(self.resolve_tuple(&deleted_tuple, self.compressor).1)
+    }
+
     // Delete a key from the DB, returns a bool to indicate if the key was deleted.
     // If false the key did not exist.
     fn delete_txn(&mut self, key: &[u8], tx_ctx: &mut TxCtx) -> bool {
+        self.delete_tuple_txn(key, tx_ctx).is_some()
+    }
+
+    // Delete a key from the DB, returning the tuple that was removed or
+    // None if the key did not exist.
+    fn delete_tuple_txn(&mut self, key: &[u8], tx_ctx: &mut TxCtx) -> Option<Tuple> {
+        TupleProcessor::check_key_size(key.len()).unwrap_or_else(|error| panic!("{}", error));
         // If the key is very large then a short version with a SHA256
         // hash will to stored as a reference in the DB tree. Need
         // to create a key that will be used for the operations.
-        let key_to_use: Vec<u8> = if TupleProcessor::is_oversized_key(key) {
+        let key_to_use: Vec<u8> = if TupleProcessor::is_oversized_key(key, &self.db_config) {
             TupleProcessor::generate_short_key(key)
         } else {
             key.to_owned()
@@ -197,7 +987,7 @@ impl Db {
         // Get the actual root page.
         let root_page = self.page_cache.get_page(tree_root_page_no);
         // Now pass to the TreeDeleteHandler to do the delete.
-        let (new_tree_root_page_no, deleted) = TreeDeleteHandler::delete_key(
+        let (new_tree_root_page_no, deleted_tuple) = TreeDeleteHandler::delete_key(
             &key_to_use,
             root_page,
             &mut self.page_cache,
@@ -205,12 +995,10 @@ impl Db {
             tx_ctx.new_version,
             &self.db_config,
         );
-        if !deleted {
-            // If nothing deleted then pages do not need to be rewritten.
-            return false;
-        }
+        // If nothing deleted then pages do not need to be rewritten.
+        let deleted_tuple = deleted_tuple?;
         tx_ctx.global_root_page_no = new_tree_root_page_no;
-        deleted
+        Some(deleted_tuple)
     }
 
     // Dirty read - get a value in a transaction context.
@@ -219,74 +1007,685 @@ impl Db {
     }
 
     // Get the value associated with key in the DB. If the key
-    // is not in the DB then None will be returned.
-    pub fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+    // is not in the DB then None will be returned, a key stored with
+    // an empty value returns Some of an empty Vec.
+    pub fn get(&mut self, key: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+        let key = key.as_ref();
         let master_page = self.get_master_page();
         let tree_page_no = master_page.get_global_tree_root_page_no();
         self.get_from_tree(key, tree_page_no)
     }
 
+    // As get but a page that cannot be read, or fails its checksum or
+    // decryption, is returned as an error.
+    pub fn try_get(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>, DigbyError> {
+        let key = key.as_ref();
+        let master_page = self.try_get_master_page()?;
+        self.try_get_from_tree(key, master_page.get_global_tree_root_page_no())
+    }
+
+    // Get the values for a set of keys, returned in the same order as
+    // the keys, None for a key that is not there. The master page is read
+    // once and the keys are looked up together in key order, so the
+    // pages they share on the way down the tree are read once rather
+    // than once per key as calling get for each would.
+    pub fn multi_get(&mut self, keys: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        let master_page = self.get_master_page();
+        let tree_page_no = master_page.get_global_tree_root_page_no();
+        self.try_multi_get_from_tree(keys, tree_page_no)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // As multi_get for the tree with root tree_page_no, a page that cannot
+    // be read is returned as an error.
+    pub fn try_multi_get_from_tree(
+        &mut self,
+        keys: &[Vec<u8>],
+        tree_page_no: PageNo,
+    ) -> Result<Vec<Option<Vec<u8>>>, DigbyError> {
+        // The keys as stored in the tree, the short key for an oversized
+        // key, sorted with the index of the key they came from.
+        let mut tree_keys: Vec<(Vec<u8>, usize)> = Vec::with_capacity(keys.len());
+        for (index, key) in keys.iter().enumerate() {
+            TupleProcessor::check_key_size(key.len())?;
+            let tree_key = if TupleProcessor::is_oversized_key(key, &self.db_config) {
+                TupleProcessor::generate_short_key(key)
+            } else {
+                key.clone()
+            };
+            tree_keys.push((tree_key, index));
+        }
+        let key_order = self.db_config.key_order;
+        tree_keys.sort_by(|(a, a_index), (b, b_index)| {
+            key_order.compare(a, b).then(a_index.cmp(b_index))
+        });
+        let (sorted_keys, indexes): (Vec<Vec<u8>>, Vec<usize>) = tree_keys.into_iter().unzip();
+        let tuples =
+            StoreTupleProcessor::try_get_tuples(&sorted_keys, tree_page_no, &mut self.page_cache)?;
+
+        let mut values = vec![None; keys.len()];
+        for (tuple, index) in tuples.into_iter().zip(indexes) {
+            let Some(tuple) = tuple else {
+                continue;
+            };
+            // An oversized key always has an overflow tuple, it holds the
+            // full key to check against.
+            values[index] = if tuple.get_overflow().has_overflow_pages() {
+                self.get_overflow_tuple_value(&keys[index], &tuple, u64::MAX, self.compressor)?
+            } else {
+                Some(Db::get_tuple_value(&tuple, self.compressor))
+            };
+        }
+        Ok(values)
+    }
+
     // Given the tree root page number get the value associated with
     // the key in the DB if there is one.
     //
+    // None only comes from the key not being found in the tree. Once
+    // the tuple is found the value is returned as stored, which can be
+    // empty - inline, compressed or in overflow pages.
+    //
     // The tree_page_no can be the root of the global tree or
     // the root page of a table tree.
-    fn get_from_tree(&mut self, key: &[u8], tree_page_no: PageNo) -> Option<Vec<u8>> {
+    pub fn get_from_tree(&mut self, key: &[u8], tree_page_no: PageNo) -> Option<Vec<u8>> {
+        self.try_get_from_tree(key, tree_page_no)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get_from_tree(
+        &mut self,
+        key: &[u8],
+        tree_page_no: PageNo,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        self.try_get_from_tree_at(key, tree_page_no, u64::MAX)
+    }
+
+    // As try_get_from_tree for a tree as it was at max_version, a page
+    // written after it is returned as SnapshotExpired.
+    pub fn try_get_from_tree_at(
+        &mut self,
+        key: &[u8],
+        tree_page_no: PageNo,
+        max_version: u64,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        self.try_get_from_tree_with(key, tree_page_no, max_version, self.compressor)
+    }
+
+    // As try_get_from_tree_at for a tree whose values are compressed with
+    // compressor, a table with its own compressor.
+    fn try_get_from_tree_with(
+        &mut self,
+        key: &[u8],
+        tree_page_no: PageNo,
+        max_version: u64,
+        compressor: Compressor,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        TupleProcessor::check_key_size(key.len())?;
         // If the key is very large then a shorted version is stored in the tree
         // using the SHA256 of the key.
-        if !TupleProcessor::is_oversized_key(key) {
+        if !TupleProcessor::is_oversized_key(key, &self.db_config) {
             // Not oversized so look up key.
-            {
-                let tuple =
-                    StoreTupleProcessor::get_tuple(key, tree_page_no, &mut self.page_cache)?;
-                // Found tuple, but it may be an overflow tuple (ie it has
-                // a small key but a large value). Need to get overflow tuple
-                // from the overflow pages.
-                if tuple.get_overflow() != Overflow::None {
-                    return self.get_overflow_tuple_value(key, &tuple);
-                }
-                return Some(self.get_tuple_value(&tuple));
+            let Some(tuple) = StoreTupleProcessor::try_get_tuple_at(
+                key,
+                tree_page_no,
+                max_version,
+                &mut self.page_cache,
+            )?
+            else {
+                return Ok(None);
+            };
+            // Found tuple, but it may be an overflow tuple (ie it has
+            // a small key but a large value). Need to get overflow tuple
+            // from the overflow pages.
+            if tuple.get_overflow().has_overflow_pages() {
+                return self.get_overflow_tuple_value(key, &tuple, max_version, compressor);
             }
+            return Ok(Some(Db::get_tuple_value(&tuple, compressor)));
         }
 
         // Oversized key - get short version
         let short_key = TupleProcessor::generate_short_key(key);
         // This tuple will have a page number as the value, the page will be an overflow page
-        // that forms a linked list of pages that will hold the tuple.
-        let tuple = StoreTupleProcessor::get_tuple(&short_key, tree_page_no, &mut self.page_cache);
-        // Do not have this key.
-        tuple.as_ref()?;
+        // that forms a linked list of pages that will hold the tuple. If there is no tuple
+        // we do not have this key.
+        let Some(tuple) = StoreTupleProcessor::try_get_tuple_at(
+            &short_key,
+            tree_page_no,
+            max_version,
+            &mut self.page_cache,
+        )?
+        else {
+            return Ok(None);
+        };
         // The tuple exists, we have a reference to the overflow tuple in the overflow pages
         // in the tuple so look it up with get_overflow_tuple_value
-        self.get_overflow_tuple_value(key, &tuple.unwrap())
+        self.get_overflow_tuple_value(key, &tuple, max_version, compressor)
     }
 
-    // A tuple has been found but its an overflow tuple and holds
-    // a reference to where the real tuple is, this function
-    // resolves the overflow tuple to get the real tuple.
-    fn get_overflow_tuple_value(&mut self, key: &[u8], tuple: &Tuple) -> Option<Vec<u8>> {
-        assert!(tuple.get_overflow() != Overflow::None);
-        // Tuple exists, the value will be a page number for the overflow page.
-        let overflow_page_no = u64::from_le_bytes(tuple.get_value()[0..8].try_into().unwrap());
-        let overflow_tuple: OverflowTuple =
-            OverflowPageHandler::get_overflow_tuple(overflow_page_no, &mut self.page_cache);
-        // Confirm the key is the same - would require a SHA256 clash to fail
-        assert_eq!(
-            key,
-            self.get_tuple_key(&overflow_tuple),
-            "BUG: Supplied key does not match key in returned OverflowTuple"
+    // Get the key/values with start <= key < end in key order. If end
+    // is None then get everything from start.
+    //
+    // Keys longer than TupleProcessor::max_inline_key_size are stored in
+    // the tree as the first 223 bytes and a SHA256 of the key so the
+    // ordering of these keys will not match the full key ordering after
+    // 223 bytes.
+    pub fn range(
+        &mut self,
+        start: impl AsRef<[u8]>,
+        end: Option<impl AsRef<[u8]>>,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let master_page = self.get_master_page();
+        let tree_page_no = master_page.get_global_tree_root_page_no();
+        self.range_from_tree(
+            start.as_ref(),
+            end.as_ref().map(|end| end.as_ref()),
+            tree_page_no,
+        )
+    }
+
+    // As range but the key/values come largest key first, for views of
+    // the most recent first when keys increase over time. Values are
+    // decompressed and overflow tuples resolved as they are for range.
+    pub fn range_rev(
+        &mut self,
+        start: impl AsRef<[u8]>,
+        end: Option<impl AsRef<[u8]>>,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let master_page = self.get_master_page();
+        let tuples = TreeScanHandler::get_tuples_in_range_rev(
+            start.as_ref(),
+            end.as_ref().map(|end| end.as_ref()),
+            master_page.get_global_tree_root_page_no(),
+            &mut self.page_cache,
         );
-        Some(self.get_tuple_value(&overflow_tuple))
+        tuples
+            .iter()
+            .map(|tuple| self.resolve_tuple(tuple, self.compressor))
+            .collect()
     }
 
-    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+    // Get the key/values whose keys start with prefix in key order, a
+    // range from prefix up to the first key past all keys starting with
+    // it. An empty or all 0xff prefix scans to the end. With a comparator
+    // the keys with a prefix need not be together so the whole tree is
+    // scanned.
+    pub fn scan_prefix(
+        &mut self,
+        prefix: impl AsRef<[u8]>,
+    ) -> std::vec::IntoIter<(Vec<u8>, Vec<u8>)> {
+        let prefix = prefix.as_ref();
+        // As for delete_prefix_txn only the first SHORT_KEY_PREFIX_SIZE
+        // bytes of an oversized key can be matched in the tree, scan on
+        // that much and check the full key.
+        let (scan_start, scan_end) = self.prefix_range(prefix);
+        self.range(&scan_start, scan_end.as_deref())
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    // A Cursor over the whole global tree in key order, the tree is read
+    // as the cursor is iterated. See Cursor for how long it can be held.
+    pub fn cursor(&mut self) -> Cursor<'_> {
+        self.cursor_range([], None::<&[u8]>)
+    }
+
+    // A Cursor over the keys with start <= key < end, to the end of the
+    // tree if end is None.
+    pub fn cursor_range(
+        &mut self,
+        start: impl AsRef<[u8]>,
+        end: Option<impl AsRef<[u8]>>,
+    ) -> Cursor<'_> {
+        let tree_page_no = self.get_master_page().get_global_tree_root_page_no();
+        Cursor::new(
+            self,
+            tree_page_no,
+            start.as_ref(),
+            end.as_ref().map(|end| end.as_ref()),
+            None,
+        )
+    }
+
+    // A Cursor over the keys that start with prefix, as scan_prefix.
+    pub fn cursor_prefix(&mut self, prefix: impl AsRef<[u8]>) -> Cursor<'_> {
+        let prefix = prefix.as_ref();
+        let tree_page_no = self.get_master_page().get_global_tree_root_page_no();
+        let (scan_start, scan_end) = self.prefix_range(prefix);
+        Cursor::new(
+            self,
+            tree_page_no,
+            &scan_start,
+            scan_end.as_deref(),
+            Some(prefix),
+        )
+    }
+
+    // Dirty read - range in a transaction context.
+    pub fn range_txn(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        tx_ctx: &TxCtx,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.range_from_tree(start, end, tx_ctx.global_root_page_no)
+    }
+
+    // Run f with reads served from the DB as it is now. The master page
+    // is read once and every read in f uses its tree roots, so a batch
+    // of related reads sees a single commit. Writes need &mut self so
+    // cannot happen during f anyway, this is the shape reads take once
+    // they can run alongside a writer.
+    pub fn read_transaction<R>(&mut self, f: impl FnOnce(&mut ReadTx) -> R) -> R {
+        let master_page = self.get_master_page();
+        let mut read_tx = ReadTx::new(self, &master_page);
+        f(&mut read_tx)
+    }
+
+    // A handle for reading the global tree from other threads, see
+    // Reader. Reads are served from the last commit made through this
+    // Db, or for a read only Db the last commit it has seen. Panics if
+    // the Db was not opened from a path, the Reader opens the file
+    // again.
+    pub fn reader(&self) -> Reader {
+        self.try_reader()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_reader(&self) -> Result<Reader, DigbyError> {
+        if !self.reader_shared.has_source() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "A reader needs a DB opened from a path",
+            )
+            .into());
+        }
+        Ok(Reader::new(self.reader_shared.clone()))
+    }
+
+    // Read key from the global tree of a commit published to a Reader,
+    // rather than the current master page. The cached pages are dropped
+    // when the commit changes.
+    pub fn try_get_at_commit(
+        &mut self,
+        key: &[u8],
+        commit: &Snapshot,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        self.set_read_only_version(commit.get_version())?;
+        commit.try_get(self, key)
+    }
+
+    // The version of the current commit, from the current master page.
+    // It goes up by at least one with each commit, so a version that has
+    // not changed means nothing has been committed since. A transaction
+    // that is dropped rather than committed does not change it,
+    // sync_masters, compact_in_place, compact_file and
+    // compact_into_new_file move it on without changing the data. A read only Db sees the commits of the process
+    // writing the file.
+    pub fn version(&mut self) -> u64 {
+        self.try_version()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_version(&mut self) -> Result<u64, DigbyError> {
+        Ok(self.try_get_master_page()?.get_version())
+    }
+
+    // Capture the global tree as it is now, later commits do not change
+    // what the snapshot reads. See Snapshot for how long it stays valid.
+    pub fn snapshot(&mut self) -> Snapshot {
+        let master_page = self.get_master_page();
+        Snapshot::new(
+            master_page.get_version(),
+            master_page.get_global_tree_root_page_no(),
+        )
+    }
+
+    pub fn range_from_tree(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        tree_page_no: PageNo,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.range_from_tree_with(start, end, tree_page_no, self.compressor)
+    }
+
+    // As range_from_tree for a tree whose values are compressed with
+    // compressor.
+    fn range_from_tree_with(
+        &mut self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        tree_page_no: PageNo,
+        compressor: Compressor,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let tuples =
+            TreeScanHandler::get_tuples_in_range(start, end, tree_page_no, &mut self.page_cache);
+        tuples
+            .iter()
+            .map(|tuple| self.resolve_tuple(tuple, compressor))
+            .collect()
+    }
+
+    // As range_from_tree but stops after about limit key/values, see
+    // TreeScanHandler::get_tuples_in_range_limit. Also returns the key
+    // the next call carries on after, None if the range has been read.
+    pub fn range_from_tree_limit(
+        &mut self,
+        start: Bound<&[u8]>,
+        end: Option<&[u8]>,
+        tree_page_no: PageNo,
+        limit: usize,
+    ) -> (Vec<KeyValue>, Option<Vec<u8>>) {
+        let tuples = TreeScanHandler::get_tuples_in_range_limit(
+            start,
+            end,
+            tree_page_no,
+            limit,
+            &mut self.page_cache,
+        );
+        // The last key found, as stored in the tree which for an
+        // oversized key is its short key.
+        let next_start = match tuples.last() {
+            Some(tuple) if tuples.len() >= limit => Some(tuple.get_key().to_vec()),
+            _ => None,
+        };
+        let compressor = self.compressor;
+        let entries = tuples
+            .iter()
+            .map(|tuple| self.resolve_tuple(tuple, compressor))
+            .collect();
+        (entries, next_start)
+    }
+
+    // Get the full key and value for a tuple in the tree, the tuple
+    // could refer to an overflow tuple or be compressed.
+    fn resolve_tuple(&mut self, tuple: &Tuple, compressor: Compressor) -> (Vec<u8>, Vec<u8>) {
+        if tuple.get_overflow().has_overflow_pages() {
+            let overflow_page_no = u64::from_le_bytes(tuple.get_value()[0..8].try_into().unwrap());
+            let overflow_tuple =
+                OverflowPageHandler::get_overflow_tuple(overflow_page_no, &mut self.page_cache);
+            return (
+                Db::get_tuple_key(&overflow_tuple, compressor),
+                Db::get_tuple_value(&overflow_tuple, compressor),
+            );
+        }
+        (
+            tuple.get_key().to_vec(),
+            Db::get_tuple_value(tuple, compressor),
+        )
+    }
+
+    // A tuple has been found but its an overflow tuple and holds
+    // a reference to where the real tuple is, this function
+    // resolves the overflow tuple to get the real tuple.
+    //
+    // If the key in the overflow tuple is not the key asked for then
+    // an oversized key has the same short key as one already stored, a
+    // SHA256 clash. The key asked for is not in the DB so None.
+    fn get_overflow_tuple_value(
+        &mut self,
+        key: &[u8],
+        tuple: &Tuple,
+        max_version: u64,
+        compressor: Compressor,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        let overflow_tuple = self.try_get_overflow_tuple(tuple, max_version)?;
+        if key != Db::get_tuple_key(&overflow_tuple, compressor) {
+            return Ok(None);
+        }
+        Ok(Some(Db::get_tuple_value(&overflow_tuple, compressor)))
+    }
+
+    fn try_get_overflow_tuple(
+        &mut self,
+        tuple: &Tuple,
+        max_version: u64,
+    ) -> Result<OverflowTuple, DigbyError> {
+        assert!(tuple.get_overflow().has_overflow_pages());
+        // Tuple exists, the value will be a page number for the overflow page.
+        let overflow_page_no = u64::from_le_bytes(tuple.get_value()[0..8].try_into().unwrap());
+        OverflowPageHandler::try_get_overflow_tuple_at(
+            overflow_page_no,
+            max_version,
+            &mut self.page_cache,
+        )
+    }
+
+    // An oversized key is stored under its short key, if another key
+    // with the same short key is already stored then storing this one
+    // would replace it. Return HashCollision rather than lose the other
+    // key. Keys that are not oversized are stored as they are so there
+    // is nothing to check.
+    pub fn try_check_key_collision(
+        &mut self,
+        key: &[u8],
+        tree_page_no: PageNo,
+    ) -> Result<(), DigbyError> {
+        self.try_check_key_collision_with(key, tree_page_no, self.compressor)
+    }
+
+    // As try_check_key_collision for a tree whose keys are compressed
+    // with compressor.
+    fn try_check_key_collision_with(
+        &mut self,
+        key: &[u8],
+        tree_page_no: PageNo,
+        compressor: Compressor,
+    ) -> Result<(), DigbyError> {
+        if !TupleProcessor::is_oversized_key(key, &self.db_config) {
+            return Ok(());
+        }
+        let short_key = TupleProcessor::generate_short_key(key);
+        let Some(tuple) = StoreTupleProcessor::try_get_tuple_at(
+            &short_key,
+            tree_page_no,
+            u64::MAX,
+            &mut self.page_cache,
+        )?
+        else {
+            return Ok(());
+        };
+        let overflow_tuple = self.try_get_overflow_tuple(&tuple, u64::MAX)?;
+        if key != Db::get_tuple_key(&overflow_tuple, compressor) {
+            return Err(DigbyError::HashCollision);
+        }
+        Ok(())
+    }
+
+    pub fn put(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        let key = key.as_ref();
+        let value = value.as_ref();
         let mut tx_ctx = self.new_transaction();
         self.put_txn(key, value, &mut tx_ctx);
-        self.commit(&mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+    }
+
+    // As put but errors are returned rather than panicking. The pages
+    // down to the key are read and checked first so a corrupt page
+    // there stops the put before it changes anything, pages the put
+    // reads after that, such as the free page directory, are not.
+    pub fn try_put(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<(), DigbyError> {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.try_new_transaction()?;
+        self.try_get_from_tree(key, tx_ctx.global_root_page_no)?;
+        self.try_put_txn(key, value, &mut tx_ctx)?;
+        Ok(self.commit(&mut tx_ctx)?)
     }
 
     // Store a key and value in the db.
     pub fn put_txn(&mut self, key: &[u8], value: &[u8], tx_ctx: &mut TxCtx) {
+        self.try_put_txn(key, value, tx_ctx)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    // As put_txn but an oversized key that clashes with one already
+    // stored is returned as HashCollision, nothing is changed.
+    pub fn try_put_txn(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) -> Result<(), DigbyError> {
+        self.put_with_compression_txn(key, value, true, tx_ctx)
+    }
+
+    // Store a key and value, returning the value it replaced or None if
+    // the key was not in the DB.
+    pub fn put_returning(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Option<Vec<u8>> {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        let old_value = self.put_returning_txn(key, value, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+        old_value
+    }
+
+    // As put_returning but errors are returned rather than panicking.
+    // The tree is only descended by the put, unlike try_put the pages
+    // are not checked first, a bad page on the way down panics.
+    pub fn try_put_returning(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.try_new_transaction()?;
+        let old_value = self.try_put_returning_txn(key, value, &mut tx_ctx)?;
+        self.commit(&mut tx_ctx)?;
+        Ok(old_value)
+    }
+
+    pub fn put_returning_txn(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) -> Option<Vec<u8>> {
+        self.try_put_returning_txn(key, value, tx_ctx)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // The value comes from the tuple the put replaced, so the tree is
+    // only descended once.
+    pub fn try_put_returning_txn(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) -> Result<Option<Vec<u8>>, DigbyError> {
+        let replaced_tuple = self.store_with_compression_txn(key, value, true, true, tx_ctx)?;
+        Ok(replaced_tuple.map(|tuple| self.resolve_tuple(&tuple, self.compressor).1))
+    }
+
+    // Store a key and value only if the key is not in the DB, returns
+    // true if it was stored. The check and the put are in the same
+    // transaction so nothing can store the key in between.
+    pub fn put_if_absent(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> bool {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        let inserted = self.put_if_absent_txn(key, value, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+        inserted
+    }
+
+    // As put_if_absent but errors are returned rather than panicking,
+    // the pages are checked as they are for try_put.
+    pub fn try_put_if_absent(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<bool, DigbyError> {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.try_new_transaction()?;
+        self.try_get_from_tree(key, tx_ctx.global_root_page_no)?;
+        let inserted = self.try_put_if_absent_txn(key, value, &mut tx_ctx)?;
+        self.commit(&mut tx_ctx)?;
+        Ok(inserted)
+    }
+
+    pub fn put_if_absent_txn(&mut self, key: &[u8], value: &[u8], tx_ctx: &mut TxCtx) -> bool {
+        self.try_put_if_absent_txn(key, value, tx_ctx)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // The key is looked for in the leaf page the tuple would be added
+    // to, so the tree is only descended once. If it is there nothing is
+    // written.
+    pub fn try_put_if_absent_txn(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) -> Result<bool, DigbyError> {
+        let existing_tuple = self.store_with_compression_txn(key, value, true, false, tx_ctx)?;
+        Ok(existing_tuple.is_none())
+    }
+
+    // Store a key and value without attempting compression, whatever the
+    // compressor for the DB is. Useful for values that are already
+    // compressed (images, gzipped blobs) where LZ4 wastes CPU and may
+    // make them bigger. The tuple records it is not compressed so get
+    // works as normal.
+    pub fn put_raw(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        self.put_raw_txn(key, value, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+    }
+
+    pub fn put_raw_txn(&mut self, key: &[u8], value: &[u8], tx_ctx: &mut TxCtx) {
+        self.put_with_compression_txn(key, value, false, tx_ctx)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    fn put_with_compression_txn(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        compress: bool,
+        tx_ctx: &mut TxCtx,
+    ) -> Result<(), DigbyError> {
+        self.store_with_compression_txn(key, value, compress, true, tx_ctx)?;
+        Ok(())
+    }
+
+    // As put_with_compression_txn, returning the tuple that was in the
+    // tree for the key or None if the key was not there. If replace is
+    // false a key already in the tree is left as it is.
+    fn store_with_compression_txn(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        compress: bool,
+        replace: bool,
+        tx_ctx: &mut TxCtx,
+    ) -> Result<Option<Tuple>, DigbyError> {
+        // Check before anything is written, generate_tuple stores an
+        // oversized key in overflow pages.
+        TupleProcessor::check_sizes(key.len(), value.len())?;
+        self.try_check_key_collision(key, tx_ctx.global_root_page_no)?;
+        let no_compressor = Compressor::new(CompressorType::None);
+        let compressor = if compress {
+            &self.compressor
+        } else {
+            &no_compressor
+        };
         // Create the tuple we want to add. This could be an overflow
         // tuple - if it is an overflow tuple this method will
         // store the key/value in the overflow pages and the tuple
@@ -297,7 +1696,7 @@ impl Db {
             &mut self.page_cache,
             &mut tx_ctx.free_page_tracker,
             tx_ctx.new_version,
-            &self.compressor,
+            compressor,
             &self.db_config,
         );
 
@@ -306,22 +1705,24 @@ impl Db {
         // Now get the root page of the tree.
         let page = self.page_cache.get_page(tree_root_page_no);
         // Store the tuple, this will return the page number of the
-        // new root of the page.
-        let new_tree_root_page_no = StoreTupleProcessor::store_tuple(
+        // new root of the page and any tuple already there.
+        let (new_tree_root_page_no, existing_tuple) = StoreTupleProcessor::store_tuple_returning(
             tuple,
             page,
+            replace,
             &mut tx_ctx.free_page_tracker,
             &mut self.page_cache,
             tx_ctx.new_version,
             &self.db_config,
         );
         tx_ctx.global_root_page_no = PageNo::from_u64(new_tree_root_page_no);
+        Ok(existing_tuple)
     }
 
     pub fn clear(&mut self) {
         let mut tx_ctx = self.new_transaction();
         self.clear_txn(&mut tx_ctx);
-        self.commit(&mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
     }
 
     // Remove all entries in the root tree.
@@ -343,91 +1744,802 @@ impl Db {
         );
     }
 
-    pub fn new_transaction(&mut self) -> TxCtx {
+    // Shrink the file by moving pages near the end into free blocks
+    // nearer the front and cutting the file down, see
+    // CompactInPlaceHandler. Unlike compact the trees are not packed,
+    // the pages are moved as they are, and no second copy of the file
+    // is needed. Each pass is a commit so a crash leaves the DB as it
+    // was after the last pass, the file may be longer than it needs to
+    // be and the next call picks up the blocks past the end. Passes
+    // are made until the file stops getting smaller, then the master
+    // pages are synced as the backup master still refers to the
+    // pages before they moved.
+    //
+    // There must be no transaction in progress. Write errors are
+    // handled as they are for a commit.
+    pub fn compact_in_place(&mut self) -> std::io::Result<()> {
+        if self.read_only {
+            return Err(Db::read_only_error());
+        }
+        while self.compact_in_place_pass()? {}
+        self.sync_masters()
+    }
+
+    // Give the blocks of the pages on the free list back to the file
+    // system by punching holes in the file where they are, a lighter
+    // alternative to compact or compact_in_place for a DB that has
+    // deleted most of its data. The file keeps its length and a trimmed
+    // page reads as zeros until a commit reuses it. Returns the number of
+    // pages trimmed.
+    //
+    // It is best effort, see Storage::punch_hole. Only Linux, on a file
+    // system that supports FALLOC_FL_PUNCH_HOLE, frees the disk space,
+    // elsewhere the pages are left as they are. The master pages are
+    // synced first as the backup master still refers to the pages the
+    // last commit freed. A Snapshot from before the last commit that
+    // reads a trimmed page gets BadBlock rather than SnapshotExpired.
+    //
+    // There must be no transaction in progress.
+    pub fn trim(&mut self) -> std::io::Result<u64> {
+        self.sync_masters()?;
         let master_page = self.get_master_page();
+        let free_page_nos = self.get_free_page_nos(master_page.get_free_page_dir_page_no(0));
+        for page_no in &free_page_nos {
+            self.page_cache.punch_hole(*page_no)?;
+        }
+        Ok(free_page_nos.len() as u64)
+    }
+
+    // Returns false if the pass could not shrink the file, nothing is
+    // written then.
+    fn compact_in_place_pass(&mut self) -> std::io::Result<bool> {
+        let mut master_page = self.get_master_page();
         let old_version = master_page.get_version();
-        let new_version = old_version + 1;
-        // Find the free page directory that has the free page numbers.
-        let free_page_dir_page_no = master_page.get_free_page_dir_page_no(0);
-        let free_page_tracker = FreePageTracker::new(
-            self.page_cache.get_page(free_page_dir_page_no),
+        let new_version = Db::next_version(&master_page).map_err(std::io::Error::other)?;
+        let mut handler =
+            CompactInPlaceHandler::new(&master_page, new_version, &mut self.page_cache);
+        if !handler.will_shrink(&self.page_cache) {
+            return Ok(false);
+        }
+        let global_root_page_no = handler.move_tree(
+            master_page.get_global_tree_root_page_no(),
+            false,
+            &mut self.page_cache,
+        );
+        let table_dir_page_no = handler.move_tree(
+            master_page.get_table_dir_page_no(),
+            true,
+            &mut self.page_cache,
+        );
+        let (free_dir_page_no, block_count) =
+            handler.write_free_dir(&mut self.page_cache, &self.db_config);
+        let table_dir_changed = table_dir_page_no != master_page.get_table_dir_page_no();
+        let free_page_count = self.count_free_pages(free_dir_page_no);
+        let result = self.write_master_page(
+            &mut master_page,
             new_version,
-            *self.page_cache.get_page_config(),
+            global_root_page_no,
+            table_dir_page_no,
+            free_dir_page_no,
+            free_page_count,
         );
-        TxCtx::new(master_page, new_version, free_page_tracker)
+        if result.is_err() {
+            self.page_cache.revert();
+            self.page_cache.take_write_error();
+            return result.map(|_| false);
+        }
+        self.table_root_cache
+            .commit(old_version, new_version, table_dir_changed);
+
+        // The blocks past the last one in use are not in the free page
+        // directory, cut them off.
+        if block_count < self.page_cache.get_total_page_count() {
+            self.page_cache.truncate(block_count);
+            self.sync_commit();
+        }
+        match self.page_cache.take_write_error() {
+            Some(error) => Err(error),
+            None => Ok(true),
+        }
     }
 
-    // Create a new table in the DB. A table is another b+ tree in the
-    // DB, the root page to the table tree can be found in another tree,
-    // the table directory tree.
-    pub fn create_table(&mut self, name: &[u8]) {
+    pub fn compact(&mut self, target: CompactTarget) {
         let mut tx_ctx = self.new_transaction();
-        self.create_table_txn(name, &mut tx_ctx);
-        self.commit(&mut tx_ctx);
+        self.compact_txn(target, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
     }
 
-    pub fn create_table_txn(&mut self, name: &[u8], tx_ctx: &mut TxCtx) {
-        // Assert on the things that cannot be handled yet.
-        assert!(
-            name.len() < u8::MAX as usize,
-            "Cannot handle table name larger than u8::MAX."
-        );
+    // Rebuild the target trees into packed pages, see CompactHandler.
+    // Only the target trees are rewritten, compacting the global tree
+    // leaves the table trees alone and compacting a table leaves the
+    // global tree and the other tables alone. A table that does not
+    // exist is ignored.
+    pub fn compact_txn(&mut self, target: CompactTarget, tx_ctx: &mut TxCtx) {
+        let table_names: Vec<Vec<u8>> = match &target {
+            CompactTarget::Global => Vec::new(),
+            CompactTarget::Table(name) => vec![name.clone()],
+            CompactTarget::All => TreeScanHandler::get_tuples_in_range(
+                &[],
+                None,
+                tx_ctx.tree_dir_root_page_no,
+                &mut self.page_cache,
+            )
+            .iter()
+            .map(|tuple| tuple.get_key().to_vec())
+            .collect(),
+        };
+        if matches!(target, CompactTarget::Global | CompactTarget::All) {
+            tx_ctx.global_root_page_no = CompactHandler::compact_tree(
+                tx_ctx.global_root_page_no,
+                &mut tx_ctx.free_page_tracker,
+                &mut self.page_cache,
+                tx_ctx.new_version,
+                &self.db_config,
+            );
+        }
+        for name in table_names {
+            let Some(table_root_page_no) = self.get_table_tree_root_txn(&name, tx_ctx) else {
+                continue;
+            };
+            let new_table_root_page_no = CompactHandler::compact_tree(
+                table_root_page_no,
+                &mut tx_ctx.free_page_tracker,
+                &mut self.page_cache,
+                tx_ctx.new_version,
+                &self.db_config,
+            );
+            self.set_table_tree_root_txn(&name, new_table_root_page_no, tx_ctx);
+        }
+    }
 
-        // TODO Test if the table exists before creating.
+    // Rewrite the DB file at path into a new file holding only the live
+    // data, and swap it in. The global tree and the table trees are
+    // copied in key order into packed pages, as compact does, with the
+    // overflow pages they use. Free pages are not copied so the new
+    // file is about the size of the data in the DB, where
+    // compact_in_place leaves the pages as they are. See
+    // compact_into_new_file, which this opens the file for.
+    //
+    // The file must not be open by a Db, it is locked and Locked is
+    // returned. Values in the global tree are stored again with
+    // compressor_type, even ones stored with put_raw, a table keeps the
+    // compressor it has. The file is opened in byte order, the
+    // comparator of a DB opened with DbOptions::comparator is not in the
+    // file, so compact such a DB with compact or compact_in_place.
+    pub fn compact_file(
+        path: &str,
+        key: Option<Vec<u8>>,
+        compressor_type: CompressorType,
+    ) -> Result<(), DigbyError> {
+        let (block_size, block_sanity) =
+            Db::detect_format(&mut std::fs::File::open(path)?, key.as_deref())
+                .unwrap_or((Db::BLOCK_SIZE, BlockSanity::for_key(key.is_some())));
+        let mut db = Db::try_new_with_cipher(path, key, compressor_type, block_size, block_sanity)?;
+        db.compact_into_new_file()
+    }
 
-        // Need to create a root page for the new table tree, the first page
-        // in the tree will be a leaf page.
-        let new_table_root_page_no = tx_ctx.free_page_tracker.get_free_page(&mut self.page_cache);
-        let mut new_table_root_page = LeafPage::create_new(
-            self.page_cache.get_page_config(),
-            new_table_root_page_no,
-            tx_ctx.new_version,
-        );
-        // Store the new root page back into the file.
-        self.page_cache.put_page(new_table_root_page.get_page());
+    // As compact_file for the file this Db has open, the Db carries on
+    // with the new file. The name compact is taken by the compaction
+    // within the file, which cannot make the file smaller.
+    //
+    // The new file is written next to the old one as path.compact,
+    // synced, then renamed over path so a crash leaves one file or the
+    // other. It is locked before anything is written to it and the Db
+    // keeps its lock on the old file until the rename is synced, so
+    // another Db cannot open either part way through. The new file has
+    // this Db's options, key and compressor. The Db then reads and
+    // writes the new file, its settings such as the sync mode and the
+    // observer are kept, the cached pages are dropped, and Readers open
+    // the new file for their next read.
+    //
+    // Returns an InvalidInput error if the Db was not opened from a
+    // path, and a PermissionDenied error if it is read only. A TxCtx
+    // from before the call must not be committed after it. The trees are
+    // built in the order they are read, which is the DB's key order.
+    pub fn compact_into_new_file(&mut self) -> Result<(), DigbyError> {
+        if self.read_only {
+            return Err(Db::read_only_error().into());
+        }
+        let Some(source) = self.reader_shared.get_source() else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Compacting into a new file needs a DB opened from a path",
+            )
+            .into());
+        };
+        // A DB out of versions cannot be copied, fail before making the
+        // new file.
+        Db::next_version(&self.try_get_master_page()?)?;
+        let compact_path = format!("{}.compact", source.path);
+        let compact_file = Db::lock_file(
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&compact_path)?,
+        )?;
+        compact_file.set_len(0)?;
+        // The lock is held by both handles, it goes when the last is closed.
+        let new_file = compact_file.try_clone()?;
+        let compact_config = DbConfig {
+            application_id: self.application_id(),
+            user_version: self.user_version(),
+            ..self.db_config
+        };
+        let mut compact_db = Db::try_new_with_sanity_provider(
+            Box::new(compact_file),
+            source.key,
+            compact_config,
+            source.sanity_provider,
+        )?;
+        self.copy_trees_to(&mut compact_db)?;
+        compact_db.flush()?;
+        drop(compact_db);
+        std::fs::rename(&compact_path, &source.path)?;
+        // Sync the directory so the rename is durable.
+        let dir = match std::path::Path::new(&source.path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        std::fs::File::open(dir)?.sync_all()?;
+        // Closing the old file releases its lock.
+        self.page_cache.replace_storage(Box::new(new_file))?;
+        self.table_root_cache = TableRootCache::new();
+        let master_page = self.try_get_master_page()?;
+        self.reader_shared
+            .reopen(Db::get_commit_snapshot(&master_page));
+        Ok(())
+    }
 
-        // Create the tuple that will be the reference to the new table
-        // that will be stored in the table directory tree.
-        // The tuple will have a key of the table name and value of the
-        // root page number for the table's tree.
-        let tuple = TupleProcessor::generate_tuple(
-            name,
-            &new_table_root_page_no.get_bytes(),
-            &mut self.page_cache,
-            &mut tx_ctx.free_page_tracker,
-            tx_ctx.new_version,
-            &self.compressor,
-            &self.db_config,
-        );
+    // Write every entry of the committed DB to writer, the global tree
+    // then each table in name order, see DumpHandler for the format. The
+    // dump has the keys and values but nothing of the pages, so
+    // Db::restore can load it into a DB with another block size,
+    // compressor or key. Values are read from the pages as they are
+    // written, the DB does not have to fit in memory.
+    //
+    // Errors writing to writer are returned, a page that cannot be read
+    // panics as it does for iter_all.
+    pub fn dump(&mut self, writer: impl Write) -> Result<(), DigbyError> {
+        let mut writer = BufWriter::new(writer);
+        let trees = self.try_get_trees()?;
+        DumpHandler::write_header(&mut writer)?;
+        let mut entry_count = 0;
+        for (name, compressor_type, root_page_no) in trees {
+            // The dump only records whether a table compresses, it is
+            // restored with the compressor of the DB it goes into.
+            let compress = compressor_type != Some(CompressorType::None);
+            DumpHandler::write_table(&mut writer, name.as_deref(), compress)?;
+            let compressor = self.table_compressor(compressor_type);
+            for tuple in
+                TreeScanHandler::get_tuples_in_range(&[], None, root_page_no, &mut self.page_cache)
+            {
+                let (key, value) = self.resolve_tuple(&tuple, compressor);
+                DumpHandler::write_entry(&mut writer, &key, &value)?;
+                entry_count += 1;
+            }
+        }
+        DumpHandler::write_end(&mut writer, entry_count)?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        // Get the root page of the table directory tree.
-        let table_tree_root_page_no = tx_ctx.tree_dir_root_page_no;
-        let page = self.page_cache.get_page(table_tree_root_page_no);
-        // Store the reference to the new table in the table
-        // directory tree.
-        tx_ctx.tree_dir_root_page_no = PageNo::from_u64(StoreTupleProcessor::store_tuple(
-            tuple,
-            page,
-            &mut tx_ctx.free_page_tracker,
-            &mut self.page_cache,
-            tx_ctx.new_version,
-            &self.db_config,
-        ));
+    // Write every entry of the committed DB to writer as newline
+    // delimited JSON, one object a line, to look at a DB with jq and the
+    // like:
+    //
+    //   {"table":null,"key":"a2V5","value":"dmFsdWU="}
+    //
+    // The table name, key and value are base64 with padding, the global
+    // tree has a null table and comes first, then each table in name
+    // order. Values are read as get reads them, decompressed and from
+    // their overflow pages.
+    pub fn export_ndjson(&mut self, writer: impl Write) -> Result<(), DigbyError> {
+        let mut writer = BufWriter::new(writer);
+        for (name, compressor_type, root_page_no) in self.try_get_trees()? {
+            let table = match name {
+                Some(name) => format!("\"{}\"", BASE64_STANDARD.encode(name)),
+                None => "null".to_string(),
+            };
+            let compressor = self.table_compressor(compressor_type);
+            for tuple in
+                TreeScanHandler::get_tuples_in_range(&[], None, root_page_no, &mut self.page_cache)
+            {
+                let (key, value) = self.resolve_tuple(&tuple, compressor);
+                writeln!(
+                    writer,
+                    "{{\"table\":{},\"key\":\"{}\",\"value\":\"{}\"}}",
+                    table,
+                    BASE64_STANDARD.encode(key),
+                    BASE64_STANDARD.encode(value)
+                )?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
     }
 
-    pub fn commit(&mut self, tx_ctx: &mut TxCtx) {
-        self.finalise_db_changes(
-            &mut tx_ctx.master_page,
-            tx_ctx.new_version,
-            tx_ctx.global_root_page_no,
-            tx_ctx.tree_dir_root_page_no,
-            &mut tx_ctx.free_page_tracker,
-        );
+    // The global tree first then the tables in name order.
+    fn try_get_trees(&mut self) -> Result<Vec<CommittedTree>, DigbyError> {
+        let master_page = self.try_get_master_page()?;
+        let mut trees = vec![(None, None, master_page.get_global_tree_root_page_no())];
+        for tuple in TreeScanHandler::get_tuples_in_range(
+            &[],
+            None,
+            master_page.get_table_dir_page_no(),
+            &mut self.page_cache,
+        ) {
+            let entry = TableDirEntry::from_bytes(tuple.get_value());
+            trees.push((
+                Some(tuple.get_key().to_vec()),
+                entry.get_compressor_type(),
+                entry.get_root_page_no(),
+            ));
+        }
+        Ok(trees)
     }
 
-    // After completing updates to the tree need to finalise the changes
-    // to the database.
-    // This means:
+    // Entries restored or imported in each commit.
+    const WRITE_BATCH: u64 = 1000;
+
+    // Put the entries of a dump from Db::dump into dest, creating the
+    // tables in it. A table created with CompressorType::None is created
+    // with it again, the others use dest's compressor. Entries replace
+    // any dest already has with the same key. Returns the number of
+    // entries restored.
+    //
+    // The entries are committed in batches of WRITE_BATCH so a large
+    // dump does not build up one huge transaction. A dump that is cut
+    // short or damaged is an error, the batches before it have been
+    // committed, so restore into an empty DB to start again.
+    pub fn restore(reader: impl Read, dest: &mut Db) -> Result<u64, DigbyError> {
+        let mut reader = BufReader::new(reader);
+        DumpHandler::read_header(&mut reader)?;
+        let mut tx_ctx = dest.try_new_transaction()?;
+        // None until the first table record, then Some(None) for the
+        // global tree.
+        let mut table: Option<Option<Vec<u8>>> = None;
+        let mut entry_count = 0;
+        loop {
+            match DumpHandler::read_record(&mut reader)? {
+                DumpRecord::Table { name, compress } => {
+                    if let Some(name) = &name {
+                        if compress {
+                            dest.create_table_txn(name, &mut tx_ctx);
+                        } else {
+                            dest.create_table_with_compressor_txn(
+                                name,
+                                CompressorType::None,
+                                &mut tx_ctx,
+                            )?;
+                        }
+                    }
+                    table = Some(name);
+                }
+                DumpRecord::Entry(key, value) => {
+                    match &table {
+                        Some(None) => dest.try_put_txn(&key, &value, &mut tx_ctx)?,
+                        Some(Some(name)) => {
+                            dest.try_put_table_entry_txn(name, &key, &value, &mut tx_ctx)?
+                        }
+                        None => return Err(DumpHandler::invalid("entry before a table").into()),
+                    }
+                    entry_count += 1;
+                    if entry_count % Db::WRITE_BATCH == 0 {
+                        dest.commit(&mut tx_ctx)?;
+                        tx_ctx = dest.try_new_transaction()?;
+                    }
+                }
+                DumpRecord::End(dump_entry_count) => {
+                    if dump_entry_count != entry_count {
+                        return Err(DumpHandler::invalid("dump entry count does not match").into());
+                    }
+                    break;
+                }
+            }
+        }
+        dest.commit(&mut tx_ctx)?;
+        Ok(entry_count)
+    }
+
+    // Put a key and value into the global tree for each row of a CSV
+    // file, see CsvReader, the fields in key_col and value_col, counting
+    // from 0, as they are. Returns the number of rows imported.
+    pub fn import_csv(
+        &mut self,
+        reader: impl Read,
+        key_col: usize,
+        value_col: usize,
+    ) -> Result<u64, DigbyError> {
+        self.import_csv_with(reader, key_col, value_col, CsvEncoding::Utf8)
+    }
+
+    // As import_csv with the key and value fields decoded first, see
+    // CsvEncoding.
+    //
+    // Into an empty global tree the rows go through bulk load for as long
+    // as their keys are in order with no duplicates, in a single commit,
+    // so a sorted file loads much faster. The rest of the rows, or all of
+    // them if the tree has keys, are put and committed in batches of
+    // WRITE_BATCH, a later row replaces the value of an earlier one with
+    // the same key. A malformed row, one without the columns or one that
+    // does not decode is an Import error with its line. The rows in the
+    // commits before it are kept, so import into an empty DB to start
+    // again.
+    pub fn import_csv_with(
+        &mut self,
+        reader: impl Read,
+        key_col: usize,
+        value_col: usize,
+        encoding: CsvEncoding,
+    ) -> Result<u64, DigbyError> {
+        if self.read_only {
+            return Err(Db::read_only_error().into());
+        }
+        let mut csv_reader = CsvReader::new(BufReader::new(reader));
+        let mut next_entry = || -> Result<Option<KeyValue>, DigbyError> {
+            let Some((line, row)) = csv_reader.read_row()? else {
+                return Ok(None);
+            };
+            let field = |col: usize| {
+                row.get(col)
+                    .and_then(|field| encoding.decode(field))
+                    .ok_or(DigbyError::Import { line })
+            };
+            Ok(Some((field(key_col)?, field(value_col)?)))
+        };
+        let mut row_count = 0;
+        let mut tx_ctx = self.try_new_transaction()?;
+        // The first row out of order stops the bulk load, it is put with
+        // the rows after it.
+        let mut unsorted_entry = None;
+        if self.global_tree_is_empty(&tx_ctx)? {
+            let mut read_error = None;
+            let mut last_key: Option<Vec<u8>> = None;
+            let key_order = self.db_config.key_order;
+            let sorted = std::iter::from_fn(|| match next_entry() {
+                Ok(Some((key, value))) => {
+                    if last_key
+                        .as_ref()
+                        .is_some_and(|last_key| key_order.compare(last_key, &key) != Ordering::Less)
+                    {
+                        unsorted_entry = Some((key, value));
+                        return None;
+                    }
+                    last_key = Some(key.clone());
+                    row_count += 1;
+                    Some((key, value))
+                }
+                Ok(None) => None,
+                Err(error) => {
+                    read_error = Some(error);
+                    None
+                }
+            });
+            let result = self.bulk_load_txn(sorted, &mut tx_ctx);
+            if let Err(error) = result.and(read_error.map_or(Ok(()), Err)) {
+                // Drop the pages written for the load, as commit does when
+                // it fails.
+                self.page_cache.revert();
+                self.page_cache.take_write_error();
+                return Err(error);
+            }
+            self.commit(&mut tx_ctx)?;
+            if unsorted_entry.is_none() {
+                return Ok(row_count);
+            }
+            tx_ctx = self.try_new_transaction()?;
+        }
+        while let Some((key, value)) = match unsorted_entry.take() {
+            Some(entry) => Some(entry),
+            None => next_entry()?,
+        } {
+            self.try_put_txn(&key, &value, &mut tx_ctx)?;
+            row_count += 1;
+            if row_count % Db::WRITE_BATCH == 0 {
+                self.commit(&mut tx_ctx)?;
+                tx_ctx = self.try_new_transaction()?;
+            }
+        }
+        self.commit(&mut tx_ctx)?;
+        Ok(row_count)
+    }
+
+    // Copy the trees of the current commit into dest as a single
+    // commit, replacing the trees there. See compact_file.
+    fn copy_trees_to(&mut self, dest: &mut Db) -> Result<(), DigbyError> {
+        let master_page = self.try_get_master_page()?;
+        // The copy commits at the version after this DB's, so the
+        // version of a compacted file carries on from where it was.
+        let mut tx_ctx = dest.try_new_transaction_from(Db::next_version(&master_page)?)?;
+        CompactHandler::free_tree_pages(
+            tx_ctx.global_root_page_no,
+            &mut tx_ctx.free_page_tracker,
+            &mut dest.page_cache,
+        );
+        tx_ctx.global_root_page_no = self.copy_tree_to(
+            master_page.get_global_tree_root_page_no(),
+            self.compressor,
+            dest.compressor,
+            dest,
+            &mut tx_ctx,
+        );
+
+        // The table directory holds the root page of each table, they
+        // are all new pages in dest.
+        let mut dir_tuples: Vec<Tuple> = Vec::new();
+        for tuple in TreeScanHandler::get_tuples_in_range(
+            &[],
+            None,
+            master_page.get_table_dir_page_no(),
+            &mut self.page_cache,
+        ) {
+            let entry = TableDirEntry::from_bytes(tuple.get_value());
+            // A table keeps its own compressor, one using the DB's
+            // compressor uses dest's.
+            let compressor_type = entry.get_compressor_type();
+            let root_page_no = self.copy_tree_to(
+                entry.get_root_page_no(),
+                self.table_compressor(compressor_type),
+                dest.table_compressor(compressor_type),
+                dest,
+                &mut tx_ctx,
+            );
+            let mut entry = TableDirEntry::new(root_page_no, tx_ctx.new_version);
+            entry.set_compressor_type(compressor_type);
+            dir_tuples.push(Tuple::new(
+                tuple.get_key(),
+                &entry.to_bytes(),
+                tx_ctx.new_version,
+            ));
+        }
+        CompactHandler::free_tree_pages(
+            tx_ctx.tree_dir_root_page_no,
+            &mut tx_ctx.free_page_tracker,
+            &mut dest.page_cache,
+        );
+        tx_ctx.tree_dir_root_page_no = CompactHandler::build_tree(
+            dir_tuples,
+            &mut tx_ctx.free_page_tracker,
+            &mut dest.page_cache,
+            tx_ctx.new_version,
+            &dest.db_config,
+        );
+        Ok(dest.commit(&mut tx_ctx)?)
+    }
+
+    // Copy a tree into packed pages in dest, the keys and values are
+    // read with source_compressor and stored again in dest with
+    // dest_compressor, so overflow pages are copied too. Returns the
+    // root page of the tree in dest.
+    fn copy_tree_to(
+        &mut self,
+        root_page_no: PageNo,
+        source_compressor: Compressor,
+        dest_compressor: Compressor,
+        dest: &mut Db,
+        tx_ctx: &mut TxCtx,
+    ) -> PageNo {
+        let mut tuples: Vec<Tuple> = Vec::new();
+        for tuple in
+            TreeScanHandler::get_tuples_in_range(&[], None, root_page_no, &mut self.page_cache)
+        {
+            let (key, value) = self.resolve_tuple(&tuple, source_compressor);
+            tuples.push(TupleProcessor::generate_tuple(
+                &key,
+                &value,
+                &mut dest.page_cache,
+                &mut tx_ctx.free_page_tracker,
+                tx_ctx.new_version,
+                &dest_compressor,
+                &dest.db_config,
+            ));
+        }
+        CompactHandler::build_tree(
+            tuples,
+            &mut tx_ctx.free_page_tracker,
+            &mut dest.page_cache,
+            tx_ctx.new_version,
+            &dest.db_config,
+        )
+    }
+
+    pub fn new_transaction(&mut self) -> TxCtx {
+        self.try_new_transaction()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new_transaction(&mut self) -> Result<TxCtx, DigbyError> {
+        self.try_new_transaction_from(0)
+    }
+
+    // A transaction whose commit has a version of at least min_version,
+    // so copy_trees_to can carry the version of the DB it copies.
+    fn try_new_transaction_from(&mut self, min_version: u64) -> Result<TxCtx, DigbyError> {
+        let master_page = self.try_get_master_page()?;
+        let new_version = Db::next_version(&master_page)?.max(min_version);
+        // Find the free page directory that has the free page numbers.
+        let free_page_dir_page_no = master_page.get_free_page_dir_page_no(0);
+        let free_page_tracker = FreePageTracker::new(
+            self.page_cache.try_get_page(free_page_dir_page_no)?,
+            new_version,
+            *self.page_cache.get_page_config(),
+        );
+        Ok(TxCtx::new(master_page, new_version, free_page_tracker))
+    }
+
+    // The version for the next write of the master page. Versions are 56
+    // bits, past VersionHolder::MAX_VERSION the master page would go back
+    // to version 0 and lose to the older master, so it is an error
+    // instead.
+    fn next_version(master_page: &DbMasterPage) -> Result<u64, DigbyError> {
+        let old_version = master_page.get_version();
+        if old_version >= VersionHolder::MAX_VERSION {
+            return Err(DigbyError::VersionOverflow);
+        }
+        Ok(old_version + 1)
+    }
+
+    // Create a new table in the DB. A table is another b+ tree in the
+    // DB, the root page to the table tree can be found in another tree,
+    // the table directory tree.
+    //
+    // Returns false, and leaves the table as it is, if there is already
+    // a table with the name.
+    pub fn create_table(&mut self, name: impl AsRef<[u8]>) -> bool {
+        let name = name.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        if !self.create_table_txn(name, &mut tx_ctx) {
+            return false;
+        }
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+        true
+    }
+
+    pub fn create_table_txn(&mut self, name: &[u8], tx_ctx: &mut TxCtx) -> bool {
+        self.create_table_entry_txn(name, None, tx_ctx)
+    }
+
+    // As create_table but the values put in the table are compressed
+    // with compressor_type rather than the DB's compressor, for example
+    // zstd for a table of JSON and CompressorType::None for one of
+    // values that are already compressed. The compressor is kept in the
+    // table's entry in the table directory and the table's values are
+    // read and written with it. A compressor that is not built in is a
+    // CompressorMismatch.
+    pub fn create_table_with_compressor(
+        &mut self,
+        name: impl AsRef<[u8]>,
+        compressor_type: CompressorType,
+    ) -> bool {
+        self.try_create_table_with_compressor(name, compressor_type)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_create_table_with_compressor(
+        &mut self,
+        name: impl AsRef<[u8]>,
+        compressor_type: CompressorType,
+    ) -> Result<bool, DigbyError> {
+        let name = name.as_ref();
+        let mut tx_ctx = self.try_new_transaction()?;
+        if !self.create_table_with_compressor_txn(name, compressor_type, &mut tx_ctx)? {
+            return Ok(false);
+        }
+        self.commit(&mut tx_ctx)?;
+        Ok(true)
+    }
+
+    pub fn create_table_with_compressor_txn(
+        &mut self,
+        name: &[u8],
+        compressor_type: CompressorType,
+        tx_ctx: &mut TxCtx,
+    ) -> Result<bool, DigbyError> {
+        if !compressor_type.is_supported() {
+            return Err(DigbyError::CompressorMismatch(
+                CompressorTypeError::NotCompiledIn(compressor_type).to_string(),
+            ));
+        }
+        Ok(self.create_table_entry_txn(name, Some(compressor_type), tx_ctx))
+    }
+
+    // The compressor used for values put in a table, None if there is
+    // no table with the name.
+    pub fn table_compressor_type(&mut self, name: impl AsRef<[u8]>) -> Option<CompressorType> {
+        let tx_ctx = self.new_transaction();
+        self.get_table_dir_entry_txn(name.as_ref(), &tx_ctx)
+            .map(|entry| {
+                self.table_compressor(entry.get_compressor_type())
+                    .compressor_type
+            })
+    }
+
+    fn create_table_entry_txn(
+        &mut self,
+        name: &[u8],
+        compressor_type: Option<CompressorType>,
+        tx_ctx: &mut TxCtx,
+    ) -> bool {
+        // Assert on the things that cannot be handled yet.
+        assert!(
+            name.len() < u8::MAX as usize,
+            "Cannot handle table name larger than u8::MAX."
+        );
+
+        // Creating it again would orphan the pages of the table.
+        if self.table_exists_txn(name, tx_ctx) {
+            return false;
+        }
+
+        // Need to create a root page for the new table tree, the first page
+        // in the tree will be a leaf page.
+        let new_table_root_page_no = tx_ctx.free_page_tracker.get_free_page(&mut self.page_cache);
+        let mut new_table_root_page = LeafPage::create_new(
+            self.page_cache.get_page_config(),
+            new_table_root_page_no,
+            tx_ctx.new_version,
+        );
+        // Store the new root page back into the file.
+        self.page_cache.put_page(new_table_root_page.get_page());
+
+        // Store the reference to the new table in the table
+        // directory tree.
+        let mut table_dir_entry = TableDirEntry::new(new_table_root_page_no, tx_ctx.new_version);
+        table_dir_entry.set_compressor_type(compressor_type);
+        self.set_table_dir_entry_txn(name, &table_dir_entry, tx_ctx);
+        true
+    }
+
+    // Commit the changes made in the transaction. If writing to the file
+    // failed at any point in the transaction, or fails during the commit,
+    // the error is returned and the Db goes back to the last commit in
+    // the file - the cached pages are dropped and the free pages taken by
+    // the transaction are forgotten, the transaction cannot be used
+    // again. Pages appended to the file for the transaction are leaked.
+    //
+    // If the error came from syncing the new master page the commit may
+    // or may not be in the file, the Db reads the master pages back to
+    // find out.
+    //
+    // A write error from a transaction that was dropped without
+    // committing fails the next commit.
+    pub fn commit(&mut self, tx_ctx: &mut TxCtx) -> std::io::Result<()> {
+        if self.read_only {
+            // The pages written by the transaction did not get to the
+            // file either, drop them from the cache.
+            self.page_cache.revert();
+            self.page_cache.take_write_error();
+            return Err(Db::read_only_error());
+        }
+        // Take what is needed to update the table root cache before the
+        // master page is updated.
+        let old_version = tx_ctx.master_page.get_version();
+        let table_dir_changed =
+            tx_ctx.tree_dir_root_page_no != tx_ctx.master_page.get_table_dir_page_no();
+        let result = self.finalise_db_changes(
+            &mut tx_ctx.master_page,
+            tx_ctx.new_version,
+            tx_ctx.global_root_page_no,
+            tx_ctx.tree_dir_root_page_no,
+            &mut tx_ctx.free_page_tracker,
+        );
+        if result.is_err() {
+            self.page_cache.revert();
+            // Reverting can fail to read the file size, the first error
+            // is the one to report.
+            self.page_cache.take_write_error();
+            return result;
+        }
+        self.table_root_cache
+            .commit(old_version, tx_ctx.new_version, table_dir_changed);
+        Ok(())
+    }
+
+    // After completing updates to the tree need to finalise the changes
+    // to the database.
+    // This means:
     //    Writing the free page directory back to out.
     //    Updating the master page with the new tree roots and new
     //    free page directory.
@@ -442,7 +2554,13 @@ impl Db {
         new_root_page_no: PageNo,
         new_table_tree_root_no: PageNo,
         free_page_tracker: &mut FreePageTracker,
-    ) {
+    ) -> std::io::Result<()> {
+        // The count of free pages before the commit, a file from before
+        // the count was kept has its free page directory counted once.
+        let free_page_count = match master_page.get_free_page_count() {
+            Some(free_page_count) => free_page_count,
+            None => self.count_free_pages(master_page.get_free_page_dir_page_no(0)),
+        };
         // Write out the free pages.
         // Write the new free page directory back through the page cache.
         let mut free_dir_pages = free_page_tracker.get_free_dir_pages(&mut self.page_cache);
@@ -451,13 +2569,37 @@ impl Db {
         while let Some(mut free_dir_page) = free_dir_pages.pop() {
             self.page_cache.put_page(free_dir_page.get_page());
         }
+        let free_page_count = free_page_count
+            .checked_add_signed(free_page_tracker.get_free_page_delta())
+            .expect("Free page count below zero.");
+        self.write_master_page(
+            master_page,
+            new_version,
+            new_root_page_no,
+            new_table_tree_root_no,
+            first_free_dir_page,
+            free_page_count,
+        )
+    }
 
+    // The last step of a commit, once all the other pages for the commit
+    // have been put in the page cache.
+    fn write_master_page(
+        &mut self,
+        master_page: &mut DbMasterPage,
+        new_version: u64,
+        new_root_page_no: PageNo,
+        new_table_tree_root_no: PageNo,
+        first_free_dir_page: PageNo,
+        free_page_count: u64,
+    ) -> std::io::Result<()> {
         // Now need to update the master - update the following:
         //   - The global tree root page.
         //   - The table directory tree.
-        //   - The free page directory.
+        //   - The free page directory and the number of pages in it.
         //   - The new version.
         master_page.set_free_page_dir_page_no(0, first_free_dir_page);
+        master_page.set_free_page_count(free_page_count);
         master_page.set_global_tree_root_page_no(new_root_page_no);
         master_page.set_table_dir_page_no(new_table_tree_root_no);
         master_page.set_version(new_version);
@@ -467,133 +2609,783 @@ impl Db {
         master_page.flip_page_number();
 
         // Sync all pages except the master, which has not been written yet.
-        self.page_cache.sync_data();
-        // Put the master page.
-        self.page_cache.put_page(master_page.get_page());
+        self.sync_commit();
+        // Do not write the master if any page in the commit did not get
+        // to the file.
+        if let Some(error) = self.page_cache.take_write_error() {
+            return Err(error);
+        }
+        // Putting the page can encrypt it in place, take the commit for
+        // the readers first.
+        let commit = Db::get_commit_snapshot(master_page);
+        // Put the master page, readers opening the file wait for it.
+        {
+            let _commit = self.reader_shared.lock_commit();
+            self.page_cache.put_page(master_page.get_page());
+        }
         // Now sync the master
-        self.page_cache.sync_data();
+        self.sync_commit();
+        if let Some(error) = self.page_cache.take_write_error() {
+            return Err(error);
+        }
+        self.reader_shared.publish(commit);
+        Ok(())
     }
 
-    pub fn get_table_tree_root(&mut self, name: &[u8]) -> Option<PageNo> {
-        let tx_ctx = self.new_transaction();
-        self.get_table_tree_root_txn(name, &tx_ctx)
+    fn get_commit_snapshot(master_page: &DbMasterPage) -> Snapshot {
+        Snapshot::new(
+            master_page.get_version(),
+            master_page.get_global_tree_root_page_no(),
+        )
     }
 
-    // Get the root page number for a table tree if it exists.
-    pub fn get_table_tree_root_txn(&mut self, name: &[u8], tx_ctx: &TxCtx) -> Option<PageNo> {
-        assert!(
-            name.len() < u8::MAX as usize,
-            "Cannot handle keys larger than u8::MAX."
-        );
-        let table_dir_page_no = tx_ctx.tree_dir_root_page_no;
+    // Make the commit of master_page the one Readers read from.
+    fn publish_commit(&self, master_page: &DbMasterPage) {
+        self.reader_shared
+            .publish(Db::get_commit_snapshot(master_page));
+    }
 
-        if let Some(tuple) =
-            StoreTupleProcessor::get_tuple(name, table_dir_page_no, &mut self.page_cache)
+    // Sync the file with sync_all whatever the sync mode, so with
+    // SyncMode::None the commits so far are durable once it returns. A
+    // read only Db has nothing to flush.
+    //
+    // An error writing pages since the last commit or flush is returned
+    // here rather than failing the next commit.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.page_cache.sync_all();
+        match self.page_cache.take_write_error() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    // Copy the DB to a new file at dest_path, a file already there is
+    // replaced. The Db stays open and can carry on being used once the
+    // copy returns. Commits are copy on write and the master page is
+    // written last, so the blocks in the file are a consistent DB as of
+    // the current master page. They are copied as they are, the backup
+    // opens with the same key, block size and compressor as this DB.
+    // Pages written by a transaction in progress are copied too, but
+    // nothing in the backup refers to them.
+    //
+    // The root page is copied last, as it is written last when a DB is
+    // created, so a backup that is cut short does not open.
+    //
+    // A read only Db copies the file as it is, if another process
+    // commits part way through the copy the backup can have pages from
+    // a later commit than its master page.
+    pub fn backup(&mut self, dest_path: &str) -> Result<(), DigbyError> {
+        // A read only Db picks up the latest commit, and the blocks it
+        // added to the file.
+        self.try_get_master_page()?;
+        let mut dest = std::fs::File::create(dest_path)?;
+        let block_count = self.page_cache.get_total_page_count();
+        self.page_cache.copy_blocks(&mut dest, 1, block_count - 1)?;
+        dest.sync_data()?;
+        self.page_cache.copy_blocks(&mut dest, 0, 1)?;
+        dest.sync_all()?;
+        Ok(())
+    }
+
+    // Sync the file during a commit as set by the sync mode.
+    fn sync_commit(&mut self) {
+        match self.db_config.sync_mode {
+            SyncMode::None => {}
+            SyncMode::Data => self.page_cache.sync_data(),
+            SyncMode::Full => self.page_cache.sync_all(),
+        }
+    }
+
+    // Bring the two master pages back into agreement. A commit only
+    // writes the stale master so the other one is left describing the
+    // commit before, and after a failed commit it can be further
+    // behind. This checks the trees of the current master, then writes
+    // a copy of it into the stale slot with the next version. The copy
+    // becomes the current master and the old current master, which
+    // has the same roots and free page directory, is the backup with
+    // the older version. Either page can then be used to open the DB.
+    //
+    // It does not change the crash semantics. The write goes to the
+    // stale slot just as the master write of a commit does, if it is
+    // torn or lost the current master still describes the same DB. No
+    // other pages are written. The next commit overwrites the backup
+    // as usual, the pair is then one commit apart again.
+    //
+    // Corruption found checking the trees is returned as an InvalidData
+    // error and nothing is written. A write error is handled as it is
+    // for a commit.
+    pub fn sync_masters(&mut self) -> std::io::Result<()> {
+        if self.read_only {
+            return Err(Db::read_only_error());
+        }
+        self.verify().map_err(|corruption| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, corruption)
+        })?;
+        let mut master_page = self.get_master_page();
+        let old_version = master_page.get_version();
+        master_page.set_version(Db::next_version(&master_page).map_err(std::io::Error::other)?);
+        master_page.flip_page_number();
+        let commit = Db::get_commit_snapshot(&master_page);
         {
-            assert!(tuple.get_overflow() == Overflow::None);
-            assert_eq!(tuple.get_value().len(), 8);
-            let page_no = PageNo::from_bytes(tuple.get_value());
-            Some(page_no)
-        } else {
-            None
+            let _commit = self.reader_shared.lock_commit();
+            self.page_cache.put_page(master_page.get_page());
+        }
+        self.sync_commit();
+        if let Some(error) = self.page_cache.take_write_error() {
+            self.page_cache.revert();
+            self.page_cache.take_write_error();
+            return Err(error);
         }
+        self.reader_shared.publish(commit);
+        self.table_root_cache
+            .commit(old_version, old_version + 1, false);
+        Ok(())
     }
 
-    pub fn put_table_entry(&mut self, table_name: &[u8], key: &[u8], value: &[u8]) {
-        let mut tx_ctx = self.new_transaction();
-        self.put_table_entry_txn(table_name, key, value, &mut tx_ctx);
-        self.commit(&mut tx_ctx);
+    // Get the root page number for a table tree in the committed DB. The
+    // roots are cached so reading a table does not have to walk the
+    // table directory tree each time.
+    pub fn get_table_tree_root(&mut self, name: impl AsRef<[u8]>) -> Option<PageNo> {
+        let master_page = self.get_master_page();
+        self.get_table_tree_root_at(
+            name.as_ref(),
+            master_page.get_version(),
+            master_page.get_table_dir_page_no(),
+        )
     }
 
-    // Put a key value into a table. If the table does not exist then create it.
-    pub fn put_table_entry_txn(
+    // Get the root page number for a table tree as it was at a version,
+    // table_dir_page_no must be the table directory root of that
+    // version.
+    pub fn get_table_tree_root_at(
         &mut self,
-        table_name: &[u8],
-        key: &[u8],
-        value: &[u8],
-        tx_ctx: &mut TxCtx,
-    ) {
-        assert!(
-            table_name.len() < u8::MAX as usize,
-            "Cannot handle table name larger than u8::MAX."
-        );
+        name: &[u8],
+        version: u64,
+        table_dir_page_no: PageNo,
+    ) -> Option<PageNo> {
+        self.get_table_tree_at(name, version, table_dir_page_no)
+            .map(|(root_page_no, _)| root_page_no)
+    }
 
-        let mut table_root_page_no_wrapped = self.get_table_tree_root_txn(table_name, tx_ctx);
-        if table_root_page_no_wrapped.is_none() {
-            // Note this is a transaction on its own, ie the master
-            // page is overrwritten. Could do all this in a single
-            // transaction.
-            self.create_table_txn(table_name, tx_ctx);
-            table_root_page_no_wrapped = self.get_table_tree_root_txn(table_name, tx_ctx);
-        }
-        let table_root_page = table_root_page_no_wrapped.unwrap();
+    // As get_table_tree_root_at along with the compressor for the values
+    // in the table, see create_table_with_compressor.
+    pub fn get_table_tree_at(
+        &mut self,
+        name: &[u8],
+        version: u64,
+        table_dir_page_no: PageNo,
+    ) -> Option<(PageNo, Compressor)> {
+        let (root_page_no, compressor_type) = match self.table_root_cache.get(name, version) {
+            Some(cached) => cached,
+            None => {
+                let entry = self.get_table_dir_entry(name, table_dir_page_no)?;
+                let (root_page_no, compressor_type) =
+                    (entry.get_root_page_no(), entry.get_compressor_type());
+                self.table_root_cache
+                    .insert(name, root_page_no, compressor_type, version);
+                (root_page_no, compressor_type)
+            }
+        };
+        Some((root_page_no, self.table_compressor(compressor_type)))
+    }
 
-        // Create the tuple we want to add.
-        // If key/value are large then this could be an overflow tuple
-        // with the ley/value stored in overflow pages by this method.
-        let tuple = TupleProcessor::generate_tuple(
-            key,
-            value,
-            &mut self.page_cache,
-            &mut tx_ctx.free_page_tracker,
-            tx_ctx.new_version,
-            &self.compressor,
-            &self.db_config,
-        );
+    // As get_table_tree_at for the committed DB.
+    fn get_table_tree(&mut self, name: &[u8]) -> Option<(PageNo, Compressor)> {
+        let master_page = self.get_master_page();
+        self.get_table_tree_at(
+            name,
+            master_page.get_version(),
+            master_page.get_table_dir_page_no(),
+        )
+    }
 
-        // Store the tuple in the table's tree, this will return
-        // the new root page number for the table tree.
-        let table_root_page = self.page_cache.get_page(table_root_page);
-        let new_table_root_page_no = StoreTupleProcessor::store_tuple(
-            tuple,
-            table_root_page,
-            &mut tx_ctx.free_page_tracker,
+    // Get the name, root page and size of every table in the DB, in
+    // table name order. Each table tree is walked to count the entries
+    // and pages so this is not cheap for large tables.
+    pub fn table_infos(&mut self) -> Vec<TableInfo> {
+        let tx_ctx = self.new_transaction();
+        let table_dir_page_no = tx_ctx.tree_dir_root_page_no;
+        let tables = TreeScanHandler::get_tuples_in_range(
+            &[],
+            None,
+            table_dir_page_no,
             &mut self.page_cache,
-            tx_ctx.new_version,
-            &self.db_config,
         );
+        let mut table_infos = Vec::with_capacity(tables.len());
+        for tuple in tables {
+            let root_page_no = TableDirEntry::from_bytes(tuple.get_value()).get_root_page_no();
+            let (entry_count, page_count) = self.count_tree(root_page_no);
+            table_infos.push(TableInfo {
+                name: tuple.get_key().to_vec(),
+                root_page_no,
+                entry_count,
+                page_count,
+            });
+        }
+        table_infos
+    }
 
-        // Need to update the table directory tree with the new root
-        // for the table tree, create a tuple for the new tree reference.
-        let table_tuple = TupleProcessor::generate_tuple(
-            table_name,
-            &new_table_root_page_no.to_le_bytes(),
+    // The names of every table in the committed DB, in table name order.
+    // Unlike table_infos the table trees are not read.
+    pub fn list_tables(&mut self) -> Vec<Vec<u8>> {
+        let master_page = self.get_master_page();
+        TreeScanHandler::get_tuples_in_range(
+            &[],
+            None,
+            master_page.get_table_dir_page_no(),
+            &mut self.page_cache,
+        )
+        .iter()
+        .map(|tuple| tuple.get_key().to_vec())
+        .collect()
+    }
+
+    // Every entry in the committed DB as (table_name, key, value). The
+    // global tree comes first with an empty table name, then each table
+    // in table name order, each in key order. A tree is only read when
+    // the iterator gets to it.
+    pub fn iter_all(&mut self) -> impl Iterator<Item = (Vec<u8>, Vec<u8>, Vec<u8>)> + '_ {
+        let master_page = self.get_master_page();
+        let tables = TreeScanHandler::get_tuples_in_range(
+            &[],
+            None,
+            master_page.get_table_dir_page_no(),
+            &mut self.page_cache,
+        );
+        let mut trees = vec![(
+            Vec::new(),
+            master_page.get_global_tree_root_page_no(),
+            self.compressor,
+        )];
+        for tuple in tables {
+            let entry = TableDirEntry::from_bytes(tuple.get_value());
+            trees.push((
+                tuple.get_key().to_vec(),
+                entry.get_root_page_no(),
+                self.table_compressor(entry.get_compressor_type()),
+            ));
+        }
+        trees
+            .into_iter()
+            .flat_map(move |(name, root_page_no, compressor)| {
+                self.range_from_tree_with(&[], None, root_page_no, compressor)
+                    .into_iter()
+                    .map(move |(key, value)| (name.clone(), key, value))
+            })
+    }
+
+    // Every entry in a committed table in key order, values are
+    // decompressed and overflow tuples resolved as the iterator gets to
+    // them. A table that does not exist has no entries.
+    pub fn table_iter(
+        &mut self,
+        table_name: impl AsRef<[u8]>,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+        let (tuples, compressor) = match self.get_table_tree(table_name.as_ref()) {
+            Some((root_page_no, compressor)) => (
+                TreeScanHandler::get_tuples_in_range(&[], None, root_page_no, &mut self.page_cache),
+                compressor,
+            ),
+            None => (Vec::new(), self.compressor),
+        };
+        tuples
+            .into_iter()
+            .map(move |tuple| self.resolve_tuple(&tuple, compressor))
+    }
+
+    // The number of keys in the committed global tree. The leaf pages
+    // are read for their entry counts, the values are not.
+    pub fn len(&mut self) -> u64 {
+        let master_page = self.get_master_page();
+        self.count_tree(master_page.get_global_tree_root_page_no())
+            .0
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
+
+    // The number of keys in a committed table, 0 if there is no such
+    // table.
+    pub fn table_len(&mut self, table_name: impl AsRef<[u8]>) -> u64 {
+        match self.get_table_tree_root(table_name.as_ref()) {
+            Some(root_page_no) => self.count_tree(root_page_no).0,
+            None => 0,
+        }
+    }
+
+    // Count the entries and the leaf/dir pages in a tree.
+    fn count_tree(&mut self, root_page_no: PageNo) -> (u64, u64) {
+        let mut entry_count = 0;
+        let mut page_count = 0;
+        let mut stack = vec![root_page_no];
+        while let Some(page_no) = stack.pop() {
+            let page = self.page_cache.get_page(page_no);
+            page_count += 1;
+            match page.get_type() {
+                PageType::DirPage => {
+                    stack.extend(
+                        DirPage::from_page(page, self.db_config.key_order).get_all_child_pages(),
+                    );
+                }
+                PageType::LeafPage => {
+                    entry_count += LeafPage::from_page(page, self.db_config.key_order)
+                        .get_no_page_entries() as u64;
+                }
+                _ => panic!("Unexpected page type in table tree."),
+            }
+        }
+        (entry_count, page_count)
+    }
+
+    // The smallest key in the committed global tree, None if it is
+    // empty. It is the first key range returns for the whole tree, so a
+    // cursor can start from it. Deletes drop empty leaf pages from the
+    // tree so only the pages down the left edge are read.
+    pub fn first_key(&mut self) -> Option<Vec<u8>> {
+        self.edge_key(true)
+    }
+
+    // As first_key for the largest key, the pages down the right edge of
+    // the tree are read.
+    pub fn last_key(&mut self) -> Option<Vec<u8>> {
+        self.edge_key(false)
+    }
+
+    fn edge_key(&mut self, first: bool) -> Option<Vec<u8>> {
+        let master_page = self.get_master_page();
+        let mut page_no = master_page.get_global_tree_root_page_no();
+        loop {
+            let page = self.page_cache.get_page(page_no);
+            if page.get_type() == PageType::DirPage {
+                let dir_page = DirPage::from_page(page, self.db_config.key_order);
+                page_no = if first {
+                    dir_page.get_page_to_left()
+                } else {
+                    dir_page.get_last_child_page()
+                };
+                continue;
+            }
+            let leaf_page = LeafPage::from_page(page, self.db_config.key_order);
+            let tuple = if first {
+                leaf_page.get_first_tuple()
+            } else {
+                leaf_page.get_last_tuple()
+            }?;
+            // An oversized key is stored under its short key, the key
+            // itself is in the overflow pages.
+            if matches!(
+                tuple.get_overflow(),
+                Overflow::KeyOverflow | Overflow::KeyValueOverflow
+            ) {
+                return Some(self.resolve_tuple(&tuple, self.compressor).0);
+            }
+            return Some(tuple.get_key().to_vec());
+        }
+    }
+
+    // The number of levels in the committed global tree, 1 when the
+    // root is a leaf page. Every leaf is at the same depth so only the
+    // path down the left of the tree is read.
+    pub fn tree_depth(&mut self) -> u32 {
+        let master_page = self.get_master_page();
+        let mut page_no = master_page.get_global_tree_root_page_no();
+        let mut depth = 1;
+        loop {
+            let page = self.page_cache.get_page_ref(page_no);
+            if page.get_type() != PageType::DirPage {
+                return depth;
+            }
+            page_no = DirPage::get_page_to_left_page(page);
+            depth += 1;
+        }
+    }
+
+    // The pages of each level of the committed global tree, from the
+    // root down, with how many entries the pages have. Shows how full
+    // the pages are and the fanout of the dir pages, to see why
+    // lookups are slow. Every leaf and dir page of the tree is read.
+    pub fn tree_histogram(&mut self) -> Vec<TreeLevel> {
+        let master_page = self.get_master_page();
+        let mut levels: Vec<TreeLevel> = Vec::new();
+        let mut page_nos = vec![master_page.get_global_tree_root_page_no()];
+        while !page_nos.is_empty() {
+            let mut tree_level = TreeLevel::default();
+            let mut child_page_nos: Vec<PageNo> = Vec::new();
+            for page_no in page_nos {
+                let page = self.page_cache.get_page(page_no);
+                match page.get_type() {
+                    PageType::DirPage => {
+                        let child_pages = DirPage::from_page(page, self.db_config.key_order)
+                            .get_all_child_pages();
+                        tree_level.add_page(child_pages.len() as u64);
+                        child_page_nos.extend(child_pages);
+                    }
+                    PageType::LeafPage => {
+                        tree_level.add_page(
+                            LeafPage::from_page(page, self.db_config.key_order)
+                                .get_no_page_entries() as u64,
+                        );
+                    }
+                    _ => panic!("Unexpected page type in the global tree."),
+                }
+            }
+            levels.push(tree_level);
+            page_nos = child_page_nos;
+        }
+        levels
+    }
+
+    // The number of pages on the free list of the committed DB, pages
+    // that were freed by a commit and can be reused by the next. Each
+    // commit keeps the count in the master page, the free page directory
+    // is only walked for a DB that has not been committed to since it
+    // was created or since a version that did not keep the count.
+    pub fn free_page_count(&mut self) -> u64 {
+        let master_page = self.get_master_page();
+        match master_page.get_free_page_count() {
+            Some(free_page_count) => free_page_count,
+            None => self.count_free_pages(master_page.get_free_page_dir_page_no(0)),
+        }
+    }
+
+    // The pages in the free page directory from free_dir_page_no.
+    fn get_free_page_nos(&mut self, free_dir_page_no: PageNo) -> Vec<PageNo> {
+        let mut free_page_nos = Vec::new();
+        let mut free_dir_page_no = free_dir_page_no;
+        while free_dir_page_no.get_blk_offset() != 0 {
+            let free_dir_page = FreeDirPage::from_page(self.page_cache.get_page(free_dir_page_no));
+            free_page_nos.extend(free_dir_page.get_all_free_pages());
+            free_dir_page_no = free_dir_page.get_nxt_free_dir_pg();
+        }
+        free_page_nos
+    }
+
+    // Walk the free page directory from free_dir_page_no adding up the
+    // pages in it.
+    fn count_free_pages(&mut self, free_dir_page_no: PageNo) -> u64 {
+        let mut free_page_count = 0;
+        let mut free_dir_page_no = free_dir_page_no;
+        while free_dir_page_no.get_blk_offset() != 0 {
+            let free_dir_page = FreeDirPage::from_page(self.page_cache.get_page(free_dir_page_no));
+            free_page_count += free_dir_page.get_entries() as u64;
+            free_dir_page_no = free_dir_page.get_nxt_free_dir_pg();
+        }
+        free_page_count
+    }
+
+    // The number of pages kept in memory, the least recently used page
+    // is dropped when it is full. Defaults to PageCache::DEFAULT_CACHE_SIZE.
+    pub fn set_page_cache_size(&mut self, cache_size: usize) {
+        self.page_cache.set_cache_size(cache_size);
+    }
+
+    // The number of free page buffers kept for reuse so reading and
+    // writing pages does not go to the allocator each time, see PagePool.
+    // Set to 0 to turn off, defaults to PagePool::DEFAULT_MAX_BUFFERS.
+    pub fn set_page_pool_size(&mut self, max_buffers: usize) {
+        self.page_cache.set_page_pool_size(max_buffers);
+    }
+
+    // Read a run of read_ahead_blks blocks from the file on a page cache
+    // miss, useful for scans. Set to 0 to turn off, which is the default.
+    pub fn set_read_ahead(&mut self, read_ahead_blks: u64) {
+        self.page_cache.set_read_ahead(read_ahead_blks);
+    }
+
+    // Skip checking the page checksum on reads, for trusted storage.
+    // Pages are still written with a checksum so the file can be read
+    // later with checking on. Has no effect on an encrypted DB, failing
+    // to decrypt is how a bad block is found and cannot be skipped.
+    //
+    // With checking off a torn write or a flipped bit on disk is not
+    // noticed, the page is used as read. A bad value is returned as if
+    // it were stored, and a bad tree or free page directory page can
+    // panic, send a read to the wrong page or be written back into the
+    // file by the next commit where checking would have stopped it.
+    // Only turn it off where the file can be thrown away, and use verify
+    // or reopen with checking on to find out if the file is sound.
+    pub fn set_verify_checksums(&mut self, verify_checksums: bool) {
+        self.page_cache.set_verify_checksums(verify_checksums);
+        self.db_config.verify_checksums = verify_checksums;
+    }
+
+    // Set how the nonce of each encrypted block is chosen, see NonceMode.
+    // Defaults to NonceMode::Random, Derived makes the same writes give
+    // the same file. Has no effect on a DB that is not encrypted.
+    pub fn set_nonce_mode(&mut self, nonce_mode: NonceMode) {
+        self.page_cache.set_nonce_mode(nonce_mode);
+        self.db_config.nonce_mode = nonce_mode;
+    }
+
+    // The number of free pages added to the end of the file when a
+    // commit has used all the free pages, and the file synced once for
+    // them. Larger grows the file in fewer steps when loading a lot of
+    // data, capped at what one free page directory page holds. Defaults
+    // to FREE_PAGE_GROWTH.
+    pub fn set_free_page_growth(&mut self, free_page_growth: u64) {
+        assert!(free_page_growth > 0, "Free page growth must be at least 1");
+        self.page_cache.set_free_page_growth(free_page_growth);
+        self.db_config.free_page_growth = free_page_growth;
+    }
+
+    // The order keys are kept in, see KeyOrder.
+    pub fn key_order(&self) -> KeyOrder {
+        self.db_config.key_order
+    }
+
+    // Set the order keys are kept in, see KeyOrder. It is not kept in the
+    // file so must be set straight after opening the DB, to the order it
+    // has always had. Changing the order of a DB with keys in it
+    // corrupts the ordering, see DbOptions::comparator.
+    pub fn set_key_order(&mut self, key_order: KeyOrder) {
+        self.page_cache.set_key_order(key_order);
+        self.db_config.key_order = key_order;
+        self.reader_shared.set_key_order(key_order);
+    }
+
+    // Set how commits sync the file, see SyncMode for the trade-offs.
+    // Defaults to SyncMode::Data, set it straight after opening the DB.
+    pub fn set_sync_mode(&mut self, sync_mode: SyncMode) {
+        self.db_config.sync_mode = sync_mode;
+    }
+
+    // The block size the DB was created with, it is kept in the root page
+    // and the DB can only be opened with it, see Db::open_auto.
+    pub fn block_size(&self) -> usize {
+        self.db_config.block_size
+    }
+
+    // Values this size or larger are stored in overflow pages.
+    pub fn get_overflow_threshold(&self) -> usize {
+        TupleProcessor::overflow_threshold(&self.db_config)
+    }
+
+    // The application id and user version the DB was created with, 0 if
+    // they were not set. See DbOptions::application_id.
+    pub fn application_id(&self) -> u32 {
+        self.db_config.application_id
+    }
+
+    pub fn user_version(&self) -> u32 {
+        self.db_config.user_version
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read_only_error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Db is read only")
+    }
+
+    // Install an observer that gets called on disk IO.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.page_cache.set_observer(observer);
+    }
+
+    // Get the root page number for a table tree if it exists.
+    pub fn get_table_tree_root_txn(
+        &mut self,
+        name: impl AsRef<[u8]>,
+        tx_ctx: &TxCtx,
+    ) -> Option<PageNo> {
+        self.get_table_dir_entry_txn(name.as_ref(), tx_ctx)
+            .map(|table_dir_entry| table_dir_entry.get_root_page_no())
+    }
+
+    // Get the version of the last commit that changed a table, can be
+    // used to tell if a table has changed without reading it. Tables
+    // last changed before versions were kept return 0.
+    pub fn table_version(&mut self, name: impl AsRef<[u8]>) -> Option<u64> {
+        let tx_ctx = self.new_transaction();
+        self.table_version_txn(name.as_ref(), &tx_ctx)
+    }
+
+    pub fn table_version_txn(&mut self, name: &[u8], tx_ctx: &TxCtx) -> Option<u64> {
+        self.get_table_dir_entry_txn(name, tx_ctx)
+            .map(|table_dir_entry| table_dir_entry.get_version())
+    }
+
+    // Does the table exist. A table created with create_table, or by a
+    // put to it, exists until delete_table is called even if it has no
+    // entries, clear_table and deleting its entries leave it in place.
+    pub fn table_exists(&mut self, name: impl AsRef<[u8]>) -> bool {
+        self.get_table_tree_root(name.as_ref()).is_some()
+    }
+
+    pub fn table_exists_txn(&mut self, name: &[u8], tx_ctx: &TxCtx) -> bool {
+        self.get_table_dir_entry_txn(name, tx_ctx).is_some()
+    }
+
+    fn get_table_dir_entry_txn(&mut self, name: &[u8], tx_ctx: &TxCtx) -> Option<TableDirEntry> {
+        self.get_table_dir_entry(name, tx_ctx.tree_dir_root_page_no)
+    }
+
+    // The compressor for the values of a table, its own if it was
+    // created with one, otherwise the DB's. Takes the compressor type
+    // from the table's TableDirEntry.
+    fn table_compressor(&self, compressor_type: Option<CompressorType>) -> Compressor {
+        match compressor_type {
+            Some(compressor_type) => Compressor::new(compressor_type),
+            None => self.compressor,
+        }
+    }
+
+    fn get_table_dir_entry(
+        &mut self,
+        name: &[u8],
+        table_dir_page_no: PageNo,
+    ) -> Option<TableDirEntry> {
+        assert!(
+            name.len() < u8::MAX as usize,
+            "Cannot handle keys larger than u8::MAX."
+        );
+        if let Some(tuple) =
+            StoreTupleProcessor::get_tuple(name, table_dir_page_no, &mut self.page_cache)
+        {
+            assert!(tuple.get_overflow() == Overflow::None);
+            Some(TableDirEntry::from_bytes(tuple.get_value()))
+        } else {
+            None
+        }
+    }
+
+    pub fn put_table_entry(
+        &mut self,
+        table_name: impl AsRef<[u8]>,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) {
+        let table_name = table_name.as_ref();
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        self.put_table_entry_txn(table_name, key, value, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+    }
+
+    // As put_table_entry but an oversized key that clashes with one
+    // already in the table is returned as HashCollision.
+    pub fn try_put_table_entry(
+        &mut self,
+        table_name: impl AsRef<[u8]>,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+    ) -> Result<(), DigbyError> {
+        let table_name = table_name.as_ref();
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let mut tx_ctx = self.try_new_transaction()?;
+        self.try_put_table_entry_txn(table_name, key, value, &mut tx_ctx)?;
+        Ok(self.commit(&mut tx_ctx)?)
+    }
+
+    // Put a key value into a table. If the table does not exist then create it.
+    pub fn put_table_entry_txn(
+        &mut self,
+        table_name: &[u8],
+        key: &[u8],
+        value: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) {
+        self.try_put_table_entry_txn(table_name, key, value, tx_ctx)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    pub fn try_put_table_entry_txn(
+        &mut self,
+        table_name: &[u8],
+        key: &[u8],
+        value: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) -> Result<(), DigbyError> {
+        assert!(
+            table_name.len() < u8::MAX as usize,
+            "Cannot handle table name larger than u8::MAX."
+        );
+        TupleProcessor::check_sizes(key.len(), value.len())?;
+
+        let mut table_dir_entry_wrapped = self.get_table_dir_entry_txn(table_name, tx_ctx);
+        if table_dir_entry_wrapped.is_none() {
+            // Note this is a transaction on its own, ie the master
+            // page is overrwritten. Could do all this in a single
+            // transaction.
+            self.create_table_txn(table_name, tx_ctx);
+            table_dir_entry_wrapped = self.get_table_dir_entry_txn(table_name, tx_ctx);
+        }
+        let table_dir_entry = table_dir_entry_wrapped.unwrap();
+        let table_root_page = table_dir_entry.get_root_page_no();
+        // The table may have been created with its own compressor.
+        let compressor = self.table_compressor(table_dir_entry.get_compressor_type());
+        self.try_check_key_collision_with(key, table_root_page, compressor)?;
+
+        // Create the tuple we want to add.
+        // If key/value are large then this could be an overflow tuple
+        // with the ley/value stored in overflow pages by this method.
+        let tuple = TupleProcessor::generate_tuple(
+            key,
+            value,
             &mut self.page_cache,
             &mut tx_ctx.free_page_tracker,
             tx_ctx.new_version,
-            &self.compressor,
+            &compressor,
             &self.db_config,
         );
 
-        // Now store the new table reference into the table directory tree.
-        let table_dir_root_page_no = tx_ctx.tree_dir_root_page_no;
-        let table_dir_root_page = self.page_cache.get_page(table_dir_root_page_no);
-        let new_table_dir_root_page_no = StoreTupleProcessor::store_tuple(
-            table_tuple,
-            table_dir_root_page,
+        // Store the tuple in the table's tree, this will return
+        // the new root page number for the table tree.
+        let table_root_page = self.page_cache.get_page(table_root_page);
+        let new_table_root_page_no = StoreTupleProcessor::store_tuple(
+            tuple,
+            table_root_page,
             &mut tx_ctx.free_page_tracker,
             &mut self.page_cache,
             tx_ctx.new_version,
             &self.db_config,
         );
-        tx_ctx.tree_dir_root_page_no = PageNo::from_u64(new_table_dir_root_page_no);
+
+        // Need to update the table directory tree with the new root
+        // for the table tree.
+        self.set_table_tree_root_txn(table_name, PageNo::from_u64(new_table_root_page_no), tx_ctx);
+        Ok(())
     }
 
-    // Remove all the entries in a table.
-    pub fn clear_table(&mut self, table_name: &[u8]) {
+    // Remove all the entries in a table, the table stays in the table
+    // directory with an empty tree. The pages of the table tree and the
+    // overflow pages of its large keys and values go back on the free
+    // list.
+    pub fn clear_table(&mut self, table_name: impl AsRef<[u8]>) {
         self.clear_table_with_delete(table_name, false);
     }
 
     // Clear a table then remove it from the table directory
     // tree.
-    pub fn delete_table(&mut self, table_name: &[u8]) {
+    pub fn delete_table(&mut self, table_name: impl AsRef<[u8]>) {
         self.clear_table_with_delete(table_name, true);
     }
 
-    pub fn clear_table_with_delete(&mut self, table_name: &[u8], delete: bool) {
+    // As delete_table, returns false if there was no table to drop. The
+    // pages of the table tree and the overflow pages of its large keys
+    // and values go back on the free list.
+    pub fn drop_table(&mut self, table_name: impl AsRef<[u8]>) -> bool {
+        let table_name = table_name.as_ref();
+        let mut tx_ctx = self.new_transaction();
+        if !self.table_exists_txn(table_name, &tx_ctx) {
+            return false;
+        }
+        self.clear_table_with_delete_txn(table_name, true, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
+        true
+    }
+
+    pub fn clear_table_with_delete(&mut self, table_name: impl AsRef<[u8]>, delete: bool) {
         let mut tx_ctx = self.new_transaction();
-        self.clear_table_with_delete_txn(table_name, delete, &mut tx_ctx);
-        self.commit(&mut tx_ctx);
+        self.clear_table_with_delete_txn(table_name.as_ref(), delete, &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
     }
 
     // Clear the contents of a table. If delete is true then the table will be deleted, if false
@@ -627,16 +3419,14 @@ impl Db {
             &self.db_config,
         );
 
-        // Now need to update the table directory tree.
-        let table_dir_root_page_no = tx_ctx.tree_dir_root_page_no;
-        let table_dir_root_page = self.page_cache.get_page(table_dir_root_page_no);
-
         // If the table is to be deleted, then delete the table key/name
         // from the table directory tree.
-        let new_table_dir_root_page_no: PageNo = if delete {
+        if delete {
             tx_ctx
                 .free_page_tracker
                 .return_free_page_no(new_table_root_page_no);
+            let table_dir_root_page_no = tx_ctx.tree_dir_root_page_no;
+            let table_dir_root_page = self.page_cache.get_page(table_dir_root_page_no);
             let (new_page, _is_deleted) = TreeDeleteHandler::delete_key(
                 table_name,
                 table_dir_root_page,
@@ -646,44 +3436,38 @@ impl Db {
                 &self.db_config,
             );
             // Page number of the new root of the table directory tree.
-            new_page
+            tx_ctx.tree_dir_root_page_no = new_page;
         } else {
             // Not deleting the table, need to update its
             // reference to the new table tree root page.
-            //
-            // Create new table reference.
-            let table_tuple = TupleProcessor::generate_tuple(
-                table_name,
-                &new_table_root_page_no.get_bytes(),
-                &mut self.page_cache,
-                &mut tx_ctx.free_page_tracker,
-                tx_ctx.new_version,
-                &self.compressor,
-                &self.db_config,
-            );
-            // Store table reference and provide the
-            // new table directory tree root page.
-            PageNo::from_u64(StoreTupleProcessor::store_tuple(
-                table_tuple,
-                table_dir_root_page,
-                &mut tx_ctx.free_page_tracker,
-                &mut self.page_cache,
-                tx_ctx.new_version,
-                &self.db_config,
-            ))
-        };
-        tx_ctx.tree_dir_root_page_no = new_table_dir_root_page_no;
+            self.set_table_tree_root_txn(table_name, new_table_root_page_no, tx_ctx);
+        }
     }
 
     // Get a value from a table tree.
-    pub fn get_table_entry(&mut self, table_name: &[u8], key: &[u8]) -> Option<Vec<u8>> {
-        // Name size check handled in get_table_tree_root
-        let table_root_page_no_wrapped = self.get_table_tree_root(table_name);
-        // If the table does not exist could throw an error.
-        table_root_page_no_wrapped?;
+    pub fn get_table_entry(
+        &mut self,
+        table_name: impl AsRef<[u8]>,
+        key: impl AsRef<[u8]>,
+    ) -> Option<Vec<u8>> {
+        let table_name = table_name.as_ref();
+        let key = key.as_ref();
+        // Name size check handled in get_table_dir_entry. If the table
+        // does not exist could throw an error.
+        let (table_root_page_no, compressor) = self.get_table_tree(table_name)?;
+        self.get_from_table_tree(key, table_root_page_no, compressor)
+    }
 
-        let table_root_page_no = table_root_page_no_wrapped.unwrap();
-        self.get_from_tree(key, table_root_page_no)
+    // Get a value from a table tree whose values are compressed with
+    // compressor.
+    pub fn get_from_table_tree(
+        &mut self,
+        key: &[u8],
+        table_root_page_no: PageNo,
+        compressor: Compressor,
+    ) -> Option<Vec<u8>> {
+        self.try_get_from_tree_with(key, table_root_page_no, u64::MAX, compressor)
+            .unwrap_or_else(|error| panic!("{}", error))
     }
 
     pub fn get_table_entry_txn(
@@ -692,19 +3476,23 @@ impl Db {
         key: &[u8],
         tx_ctx: &TxCtx,
     ) -> Option<Vec<u8>> {
-        // Name size check handled in get_table_tree_root
-        let table_root_page_no_wrapped = self.get_table_tree_root_txn(table_name, tx_ctx);
-        // If the table does not exist could throw an error.
-        table_root_page_no_wrapped?;
-
-        let table_root_page_no = table_root_page_no_wrapped.unwrap();
-        self.get_from_tree(key, table_root_page_no)
+        // Name size check handled in get_table_dir_entry. If the table
+        // does not exist could throw an error.
+        let table_dir_entry = self.get_table_dir_entry_txn(table_name, tx_ctx)?;
+        let compressor = self.table_compressor(table_dir_entry.get_compressor_type());
+        self.get_from_table_tree(key, table_dir_entry.get_root_page_no(), compressor)
     }
 
-    pub fn delete_table_entry(&mut self, table_name: &[u8], key: &[u8]) -> bool {
+    pub fn delete_table_entry(
+        &mut self,
+        table_name: impl AsRef<[u8]>,
+        key: impl AsRef<[u8]>,
+    ) -> bool {
+        let table_name = table_name.as_ref();
+        let key = key.as_ref();
         let mut tx_ctx = self.new_transaction();
         let deleted = self.delete_table_entry_txn(table_name, key, &mut tx_ctx);
-        self.commit(&mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
         deleted
     }
 
@@ -719,7 +3507,7 @@ impl Db {
         // Name size check handled in get_table_tree_root
         let table_root_page_no_wrapped = self.get_table_tree_root_txn(table_name, tx_ctx);
         if table_root_page_no_wrapped.is_none() {
-            // table does not exist - should maybe throw error
+            // No table, nothing to delete. The table is not created.
             return false;
         }
         let table_root_page_no = table_root_page_no_wrapped.unwrap();
@@ -727,7 +3515,7 @@ impl Db {
         // If its an oversized key then need to generate a short one key for it.
         // The short key is the first 223 bytes of the key followed by the
         // SHA256 of the whole key.
-        let key_to_use: Vec<u8> = if TupleProcessor::is_oversized_key(key) {
+        let key_to_use: Vec<u8> = if TupleProcessor::is_oversized_key(key, &self.db_config) {
             TupleProcessor::generate_short_key(key)
         } else {
             key.to_owned()
@@ -736,7 +3524,7 @@ impl Db {
         // Delete the key from the table tree and get back the new root page
         // number of the table tree.
         let root_page = self.page_cache.get_page(table_root_page_no);
-        let (new_tree_free_page_no, deleted) = TreeDeleteHandler::delete_key(
+        let (new_tree_free_page_no, deleted_tuple) = TreeDeleteHandler::delete_key(
             &key_to_use,
             root_page,
             &mut self.page_cache,
@@ -744,72 +3532,274 @@ impl Db {
             tx_ctx.new_version,
             &self.db_config,
         );
-        if !deleted {
+        if deleted_tuple.is_none() {
             // No changes to DB needed
             return false;
         }
 
-        // The table tree has been updated, need to update the
-        // table directory tree with the new root page for the
-        // table tree.
-        let table_tuple = TupleProcessor::generate_tuple(
-            table_name,
-            &new_tree_free_page_no.get_bytes(),
-            &mut self.page_cache,
-            &mut tx_ctx.free_page_tracker,
-            tx_ctx.new_version,
-            &self.compressor,
-            &self.db_config,
-        );
+        self.set_table_tree_root_txn(table_name, new_tree_free_page_no, tx_ctx);
+        true
+    }
 
-        // Get the table directory tree.
-        let table_dir_root_page_no = tx_ctx.tree_dir_root_page_no;
-        let table_dir_root_page = self.page_cache.get_page(table_dir_root_page_no);
-        // Update the reference for the table tree.
-        let new_table_dir_root_page_no = StoreTupleProcessor::store_tuple(
-            table_tuple,
-            table_dir_root_page,
-            &mut tx_ctx.free_page_tracker,
-            &mut self.page_cache,
-            tx_ctx.new_version,
-            &self.db_config,
-        );
-        tx_ctx.tree_dir_root_page_no = PageNo::from_u64(new_table_dir_root_page_no);
+    pub fn delete_prefix(&mut self, table_name: impl AsRef<[u8]>, prefix: impl AsRef<[u8]>) -> u64 {
+        let mut tx_ctx = self.new_transaction();
+        let deleted = self.delete_prefix_txn(table_name.as_ref(), prefix.as_ref(), &mut tx_ctx);
+        self.commit(&mut tx_ctx).expect("Failed to commit");
         deleted
     }
-}
 
-// Functions to either create or to initialise the database.
-impl Db {
-    fn check_db_integrity(&mut self) -> std::io::Result<()> {
-        let root_page = DbRootPage::from_page(self.page_cache.get_page(PageNo::new(0, 0)));
-        // There is no sanity check for sanity type, if the db was created with
-        // encryption and then opened without a key then we will not be able to open
-        // the root_page as the checksum will not match.
-        // This could be avoided if the root page was not encrypted.
-        let stored_compressor_type = CompressorType::try_from(root_page.get_compression_type())
-            .expect("Unknown compression");
-        if stored_compressor_type != self.compressor.compressor_type {
-            panic!(
-                "Db compression mis-match, stored type is {:?}, requested type {:?}",
-                root_page.get_compression_type(),
-                self.compressor.compressor_type
-            );
-        }
-        // Get the two master pages.
-        let master_page1 = DbMasterPage::from_page(self.page_cache.get_page(PageNo::new(0, 1)));
-        let master_page2 = DbMasterPage::from_page(self.page_cache.get_page(PageNo::new(0, 2)));
-        // Determine which is the current master.
-        let current_master = if master_page1.get_version() > master_page2.get_version() {
-            master_page1
-        } else {
-            master_page2
-        };
+    // Delete every key in a table that starts with prefix, returns the
+    // number of keys deleted. If the table does not exist nothing is
+    // deleted.
+    pub fn delete_prefix_txn(
+        &mut self,
+        table_name: &[u8],
+        prefix: &[u8],
+        tx_ctx: &mut TxCtx,
+    ) -> u64 {
+        let Some(table_dir_entry) = self.get_table_dir_entry_txn(table_name, tx_ctx) else {
+            return 0;
+        };
+        let mut table_root_page_no = table_dir_entry.get_root_page_no();
+        let compressor = self.table_compressor(table_dir_entry.get_compressor_type());
+
+        // Oversized keys are stored as a short key, only the first
+        // SHORT_KEY_PREFIX_SIZE bytes can be matched in the tree. For
+        // a longer prefix scan on that much and check the full key.
+        let (scan_start, scan_end) = self.prefix_range(prefix);
+        let tuples = TreeScanHandler::get_tuples_in_range(
+            &scan_start,
+            scan_end.as_deref(),
+            table_root_page_no,
+            &mut self.page_cache,
+        );
+
+        let mut deleted = 0;
+        for tuple in tuples {
+            let full_key = match tuple.get_overflow() {
+                Overflow::KeyOverflow | Overflow::KeyValueOverflow => {
+                    self.resolve_tuple(&tuple, compressor).0
+                }
+                _ => tuple.get_key().to_vec(),
+            };
+            if !full_key.starts_with(prefix) {
+                continue;
+            }
+            // Delete using the key as stored in the tree.
+            let root_page = self.page_cache.get_page(table_root_page_no);
+            let (new_root_page_no, was_deleted) = TreeDeleteHandler::delete_key(
+                tuple.get_key(),
+                root_page,
+                &mut self.page_cache,
+                &mut tx_ctx.free_page_tracker,
+                tx_ctx.new_version,
+                &self.db_config,
+            );
+            assert!(
+                was_deleted.is_some(),
+                "Key found in scan should be deleted."
+            );
+            table_root_page_no = new_root_page_no;
+            deleted += 1;
+        }
+
+        // Only update the table directory tree once.
+        if deleted > 0 {
+            self.set_table_tree_root_txn(table_name, table_root_page_no, tx_ctx);
+        }
+        deleted
+    }
+
+    // The range of the tree to scan for the keys starting with prefix,
+    // on the part of the prefix an oversized key has in the tree. The
+    // keys are checked against the full prefix after. In byte order the
+    // keys with a prefix are together, with a comparator they can be
+    // anywhere so it is the whole tree.
+    fn prefix_range(&self, prefix: &[u8]) -> (Vec<u8>, Option<Vec<u8>>) {
+        if !self.db_config.key_order.is_bytes() {
+            return (Vec::new(), None);
+        }
+        let scan_prefix = &prefix[..prefix.len().min(TupleProcessor::SHORT_KEY_PREFIX_SIZE)];
+        (scan_prefix.to_vec(), Db::prefix_end(scan_prefix))
+    }
+
+    // The smallest key greater than every key starting with prefix, None
+    // if there is no such key as the prefix is empty or all 0xff.
+    fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(end);
+            }
+        }
+        None
+    }
+
+    // Point the table directory tree at a new root page for a table tree,
+    // this also sets the table version to the version being committed.
+    // The table keeps its compressor.
+    fn set_table_tree_root_txn(
+        &mut self,
+        table_name: &[u8],
+        table_root_page_no: PageNo,
+        tx_ctx: &mut TxCtx,
+    ) {
+        let compressor_type = self
+            .get_table_dir_entry_txn(table_name, tx_ctx)
+            .and_then(|table_dir_entry| table_dir_entry.get_compressor_type());
+        let mut table_dir_entry = TableDirEntry::new(table_root_page_no, tx_ctx.new_version);
+        table_dir_entry.set_compressor_type(compressor_type);
+        self.set_table_dir_entry_txn(table_name, &table_dir_entry, tx_ctx);
+    }
+
+    fn set_table_dir_entry_txn(
+        &mut self,
+        table_name: &[u8],
+        table_dir_entry: &TableDirEntry,
+        tx_ctx: &mut TxCtx,
+    ) {
+        let table_tuple = TupleProcessor::generate_tuple(
+            table_name,
+            &table_dir_entry.to_bytes(),
+            &mut self.page_cache,
+            &mut tx_ctx.free_page_tracker,
+            tx_ctx.new_version,
+            &self.compressor,
+            &self.db_config,
+        );
+
+        // Get the table directory tree.
+        let table_dir_root_page_no = tx_ctx.tree_dir_root_page_no;
+        let table_dir_root_page = self.page_cache.get_page(table_dir_root_page_no);
+        // Update the reference for the table tree.
+        let new_table_dir_root_page_no = StoreTupleProcessor::store_tuple(
+            table_tuple,
+            table_dir_root_page,
+            &mut tx_ctx.free_page_tracker,
+            &mut self.page_cache,
+            tx_ctx.new_version,
+            &self.db_config,
+        );
+        tx_ctx.tree_dir_root_page_no = PageNo::from_u64(new_table_dir_root_page_no);
+    }
+}
+
+// Functions to store serde types in the global tree, serialized with
+// bincode or another SerdeFormat and stored with put and get.
+#[cfg(feature = "serde")]
+impl Db {
+    pub fn put_typed<T: serde::Serialize>(&mut self, key: impl AsRef<[u8]>, value: &T) {
+        self.try_put_typed(key, value)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_put_typed<T: serde::Serialize>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<(), DigbyError> {
+        self.try_put_typed_with(key, value, &Bincode)
+    }
+
+    pub fn try_put_typed_with<T: serde::Serialize>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+        format: &impl SerdeFormat,
+    ) -> Result<(), DigbyError> {
+        let bytes = format.serialize(value)?;
+        self.try_put(key, &bytes)
+    }
+
+    // A value stored as a different type, or not by put_typed, is an
+    // error rather than None.
+    pub fn get_typed<T: serde::de::DeserializeOwned>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+    ) -> Option<T> {
+        self.try_get_typed(key)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get_typed<T: serde::de::DeserializeOwned>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Option<T>, DigbyError> {
+        self.try_get_typed_with(key, &Bincode)
+    }
+
+    pub fn try_get_typed_with<T: serde::de::DeserializeOwned>(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        format: &impl SerdeFormat,
+    ) -> Result<Option<T>, DigbyError> {
+        self.try_get(key)?
+            .map(|bytes| format.deserialize(&bytes))
+            .transpose()
+    }
+}
+
+// Functions to either create or to initialise the database.
+impl Db {
+    fn check_db_integrity(&mut self) -> Result<(), DigbyError> {
+        let root_page_no = PageNo::new(0, 0);
+        let root_page = self.page_cache.try_get_page(root_page_no)?;
+        if !DbRootPage::is_db_root_page(&root_page) {
+            return Err(Corruption::WrongPageType(root_page_no).into());
+        }
+        let root_page = DbRootPage::from_page(root_page);
+        if !root_page.is_current_format() {
+            return Err(DigbyError::FormatMismatch(
+                root_page.get_db_major_version(),
+                root_page.get_db_minor_version(),
+            ));
+        }
+        // If the db was created with encryption and then opened without a
+        // key, or with another cipher, then we will not be able to open
+        // the root_page as the checksum will not match. If the block passes
+        // the check anyway the stored type must still match.
+        // This could be avoided if the root page was not encrypted.
+        if root_page.get_sanity_type() != self.db_config.block_sanity {
+            return Err(DigbyError::SanityMismatch(
+                root_page.get_sanity_type(),
+                self.db_config.block_sanity,
+            ));
+        }
+        // A DB compressed with a codec this build does not have cannot be
+        // opened.
+        let stored_compressor_type = CompressorType::try_from(root_page.get_compression_type())
+            .map_err(|error| DigbyError::CompressorMismatch(error.to_string()))?;
+        if stored_compressor_type != self.compressor.compressor_type {
+            return Err(DigbyError::CompressorMismatch(format!(
+                "Db compression mis-match, stored type is {:?}, requested type {:?}",
+                root_page.get_compression_type(),
+                self.compressor.compressor_type
+            )));
+        }
+        // Files from before the threshold was stored have 0, the default.
+        self.db_config.overflow_threshold = root_page.get_overflow_threshold() as usize;
+        // Files from before the block size was stored have 0. A different
+        // block size usually fails the block sanity check of the root
+        // page first.
+        let stored_block_size = root_page.get_block_size() as usize;
+        if stored_block_size != 0 && stored_block_size != self.db_config.block_size {
+            return Err(DigbyError::BlockSizeMismatch(
+                stored_block_size,
+                self.db_config.block_size,
+            ));
+        }
+        self.db_config.application_id = root_page.get_application_id();
+        self.db_config.user_version = root_page.get_user_version();
+        // Get the current master page.
+        let current_master = self.try_get_master_page()?;
         let current_version = current_master.get_version();
         // Check the free_dir_page is sane.
         let free_dir_page_no = current_master.get_free_page_dir_page_no(0);
-        let free_dir_page = FreeDirPage::from_page(self.page_cache.get_page(free_dir_page_no));
-        assert!(free_dir_page.get_version() <= current_version);
+        let free_dir_page = FreeDirPage::from_page(self.page_cache.try_get_page(free_dir_page_no)?);
+        if free_dir_page.get_version() > current_version {
+            return Err(Corruption::VersionAfterMaster(free_dir_page_no).into());
+        }
 
         Ok(())
     }
@@ -823,8 +3813,9 @@ impl Db {
         // Provides a list of free pages that can be modified or added
         // to the free page directory if not used in the init process -
         // the init process will generate some unused pages.
-        let mut free_pages: Vec<PageNo> = self.page_cache.generate_free_pages(10, 0);
-        assert!(free_pages.len() == 10);
+        let initial_pages = self.db_config.initial_pages;
+        let mut free_pages: Vec<PageNo> = self.page_cache.generate_free_pages(initial_pages, 0);
+        assert!(free_pages.len() as u64 == initial_pages);
 
         // Write the global tree root page at page number 5.
         // The first page in a tree is a leaf page.
@@ -868,14 +3859,36 @@ impl Db {
         self.page_cache.put_page(master_page2.get_page());
 
         // Now write the free page directory at page 3.
-        let mut free_dir_page =
-            FreeDirPage::create_new(self.page_cache.get_page_config(), PageNo::new(0, 3), 0);
+        let mut free_dir_pages = vec![FreeDirPage::create_new(
+            self.page_cache.get_page_config(),
+            PageNo::new(0, 3),
+            0,
+        )];
         // The free_dir_page is no longer free, and also the root db page won't be free after
         // we write it in the next step.
         free_pages.retain(|&x| x.get_blk_offset() != 0);
         free_pages.retain(|&x| x.get_blk_offset() != 3);
-        free_dir_page.add_free_pages(&free_pages);
-        self.page_cache.put_page(free_dir_page.get_page());
+        assert!(free_pages.len() as u64 == initial_pages - Db::FIXED_PAGES);
+        // More free pages than page 3 holds go in more free page
+        // directory pages, taken from the free pages and linked after it.
+        let capacity = FreeDirPage::get_capacity(self.page_cache.get_page_config());
+        while free_pages.len() > capacity * free_dir_pages.len() {
+            let free_dir_page_no = free_pages.pop().unwrap();
+            let mut next_free_dir_page =
+                FreeDirPage::create_new(self.page_cache.get_page_config(), free_dir_page_no, 0);
+            let last = free_dir_pages.last_mut().unwrap();
+            last.set_nxt_free_dir_pg(&free_dir_page_no);
+            next_free_dir_page.set_prev_free_dir_pg(&last.get_page_number());
+            free_dir_pages.push(next_free_dir_page);
+        }
+        for (free_dir_page, free_pages) in
+            free_dir_pages.iter_mut().zip(free_pages.chunks(capacity))
+        {
+            free_dir_page.add_free_pages(&free_pages.to_vec());
+        }
+        for mut free_dir_page in free_dir_pages {
+            self.page_cache.put_page(free_dir_page.get_page());
+        }
 
         // Flush all pages so far, don't sync the db metadata page yet.
         self.page_cache.sync_data();
@@ -887,12 +3900,18 @@ impl Db {
             DbRootPage::create_new(self.page_cache.get_page_config());
         db_root_page.set_sanity_type(sanity_type);
         db_root_page.set_compression_type(self.compressor.compressor_type.into());
+        db_root_page
+            .set_overflow_threshold(TupleProcessor::overflow_threshold(&self.db_config) as u32);
+        db_root_page.set_block_size(self.db_config.block_size as u32);
+        db_root_page.set_application_id(self.db_config.application_id);
+        db_root_page.set_user_version(self.db_config.user_version);
         self.page_cache.put_page(db_root_page.get_page());
 
-        assert!(free_pages.len() == 4, "There should be 4 free pages");
-
         self.page_cache.sync_data();
-        Ok(())
+        match self.page_cache.take_write_error() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
     }
 }
 
@@ -905,22 +3924,95 @@ impl Db {
     //   Update the master page after making tree changes.
     //   Overwrite the non-current master page with the new version.
     fn get_master_page(&mut self) -> DbMasterPage {
-        let master_page1 = DbMasterPage::from_page(self.page_cache.get_page(PageNo::from_u64(1)));
-        let master_page2 = DbMasterPage::from_page(self.page_cache.get_page(PageNo::from_u64(2)));
+        self.try_get_master_page()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
 
-        if master_page1.get_version() > master_page2.get_version() {
-            master_page1
-        } else {
-            master_page2
+    fn try_get_master_page(&mut self) -> Result<DbMasterPage, DigbyError> {
+        if self.read_only {
+            self.refresh_read_only()?;
+        }
+        let master_page = self.try_read_master_pages()?;
+        // Another process commits to the file a read only Db has open,
+        // its Readers pick up each commit it sees.
+        if self.read_only {
+            self.publish_commit(&master_page);
+        }
+        Ok(master_page)
+    }
+
+    // Another process can commit to the file a read only Db has open, so
+    // the master pages are read from the file each time. If there has
+    // been a commit the cached pages may have been reused by it, drop
+    // them and pick up any growth of the file.
+    //
+    // A read that starts before a commit finishes on the pages of the
+    // old commit, which are only reused by the commit after. A read that
+    // runs across two commits can see reused pages.
+    fn refresh_read_only(&mut self) -> Result<(), DigbyError> {
+        self.page_cache.remove_page(PageNo::from_u64(1));
+        self.page_cache.remove_page(PageNo::from_u64(2));
+        let version = self.try_read_master_pages()?.get_version();
+        self.set_read_only_version(version)
+    }
+
+    // The current master page, the one of the two with the higher
+    // version. A crash part way through writing a master page can leave
+    // it torn, failing its checksum or decryption, and then the other
+    // one is used. The commit the torn page was for never finished. It
+    // may have reused pages freed by the commit before it, but those
+    // pages are not part of that commit's own tree, only the pages it
+    // replaced. The tree of the other master page is intact, so the DB
+    // opens at the commit before. The next commit writes over the torn page, until
+    // then it fails its check and is read from the file each time. If
+    // both fail, as they do with the wrong key, the error is returned.
+    fn try_read_master_pages(&mut self) -> Result<DbMasterPage, DigbyError> {
+        let master_page1 = self.page_cache.try_get_page(PageNo::from_u64(1));
+        let master_page2 = self.page_cache.try_get_page(PageNo::from_u64(2));
+        match (master_page1, master_page2) {
+            (Ok(master_page1), Ok(master_page2)) => {
+                if master_page1.get_version() > master_page2.get_version() {
+                    Ok(DbMasterPage::from_page(master_page1))
+                } else {
+                    Ok(DbMasterPage::from_page(master_page2))
+                }
+            }
+            (Ok(master_page), Err(error)) | (Err(error), Ok(master_page))
+                if Db::is_torn_block(&error) =>
+            {
+                Ok(DbMasterPage::from_page(master_page))
+            }
+            (Err(error), _) | (_, Err(error)) => Err(error),
+        }
+    }
+
+    // An error from a block that was not written whole.
+    fn is_torn_block(error: &DigbyError) -> bool {
+        matches!(
+            error,
+            DigbyError::ChecksumMismatch(_) | DigbyError::EncryptionMismatch(_)
+        )
+    }
+
+    // Drop the cached pages if they belong to a different commit than
+    // version, their blocks may have been reused since.
+    fn set_read_only_version(&mut self, version: u64) -> Result<(), DigbyError> {
+        if version != self.read_only_version {
+            self.page_cache.revert();
+            if let Some(error) = self.page_cache.take_write_error() {
+                return Err(error.into());
+            }
+            self.read_only_version = version;
         }
+        Ok(())
     }
 
     // A tuple may be compressed, uncompress if necessary.
     // Should this be with the tuple code?
-    fn get_tuple_value<T: TupleTrait>(&self, tuple: &T) -> Vec<u8> {
+    fn get_tuple_value<T: TupleTrait>(tuple: &T, compressor: Compressor) -> Vec<u8> {
         let overflow = tuple.get_overflow();
         if overflow == Overflow::ValueCompressed || overflow == Overflow::KeyValueCompressed {
-            return self.compressor.decompress(tuple.get_value());
+            return compressor.decompress(tuple.get_value());
         }
         // Return a copy of the tuple. This is now a copy of a copy!?
         tuple.get_value().to_vec()
@@ -929,21 +4021,90 @@ impl Db {
     // Get the key - it could be compressed in which case it needs to be
     // uncompressed.
     // Should this be with the tuple code?
-    fn get_tuple_key<T: TupleTrait>(&self, tuple: &T) -> Vec<u8> {
+    fn get_tuple_key<T: TupleTrait>(tuple: &T, compressor: Compressor) -> Vec<u8> {
         let overflow = tuple.get_overflow();
         if overflow == Overflow::KeyValueCompressed {
-            return self.compressor.decompress(tuple.get_key());
+            return compressor.decompress(tuple.get_key());
         }
         tuple.get_key().to_vec()
     }
 }
 
+// Functions to check the DB.
+impl Db {
+    // Walk the global tree and all the table trees checking the pages
+    // are sane, returns the first problem found. See verify_report.
+    pub fn verify(&mut self) -> Result<(), Corruption> {
+        match self.verify_report().problems.into_iter().next() {
+            Some(corruption) => Err(corruption),
+            None => Ok(()),
+        }
+    }
+
+    // Check every page of the current commit and report all the
+    // problems found rather than stopping at the first, see
+    // VerifyHandler. The pages are read from the file again to check
+    // their checksums, so the page cache is emptied of them.
+    pub fn verify_report(&mut self) -> VerifyReport {
+        let master_page = self.get_master_page();
+        VerifyHandler::verify(&master_page, &mut self.page_cache)
+    }
+
+    // Decode a single page for debugging. The page is read through
+    // the page cache so it must pass the checksum/decryption.
+    pub fn dump_page(&mut self, page_no: PageNo) -> PageDump {
+        assert!(
+            page_no.get_blk_offset() + page_no.get_blk_cnt()
+                <= self.page_cache.get_total_page_count(),
+            "Page is outside the file."
+        );
+        let page = self.page_cache.get_page(page_no);
+        let mut dump = PageDump {
+            page_no: page.get_page_number(),
+            page_type: page.try_get_type(),
+            version: page.get_version(),
+            entries: None,
+            free_space: None,
+            keys: Vec::new(),
+            next_page: None,
+            used_size: None,
+        };
+        match dump.page_type {
+            Some(PageType::LeafPage) => {
+                let leaf_page = LeafPage::from_page(page, self.db_config.key_order);
+                dump.entries = Some(leaf_page.get_no_page_entries());
+                dump.free_space = Some(leaf_page.get_free_space());
+                dump.keys = leaf_page.get_all_keys();
+            }
+            Some(PageType::DirPage) => {
+                let dir_page = DirPage::from_page(page, self.db_config.key_order);
+                dump.entries = Some(dir_page.get_entries_size());
+                dump.free_space = Some(dir_page.get_free_space());
+                dump.keys = dir_page.get_all_keys();
+            }
+            Some(PageType::Overflow) => {
+                let overflow_page = OverflowPage::from_page(page);
+                dump.next_page = Some(overflow_page.get_next_page());
+                dump.used_size = Some(overflow_page.get_used_size());
+            }
+            Some(PageType::FreeDir) => {
+                dump.entries = Some(FreeDirPage::from_page(page).get_entries());
+            }
+            _ => {}
+        }
+        dump
+    }
+}
+
 impl Drop for Db {
     // This is a bit weird - the file is not closed as
     // this is done via the file object drop.
     // Not sure this is necessary.
     fn drop(&mut self) {
-        self.page_cache.sync_all();
+        // Nothing to sync if read only, and the file may not be writable.
+        if !self.read_only {
+            self.page_cache.sync_all();
+        }
     }
 }
 
@@ -954,57 +4115,1712 @@ mod tests {
     use std::fs;
     use tempfile::NamedTempFile;
 
-    #[test]
-    fn test_db_creation() {
+    #[test]
+    fn test_db_creation() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+        }
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            let _head_page1 = DbMasterPage::from_page(db.page_cache.get_page(PageNo::from_u64(1)));
+            let head_page2 = DbMasterPage::from_page(db.page_cache.get_page(PageNo::from_u64(2)));
+            let free_page_dir_page_no = head_page2.get_free_page_dir_page_no(0);
+            let free_page_dir_page =
+                FreeDirPage::from_page(db.page_cache.get_page(free_page_dir_page_no));
+            assert!(free_page_dir_page.get_entries() == 4);
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_store_value() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let key = b"the_key".to_vec();
+        let value = b"the_value".to_vec();
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            assert!(!db.delete(&key));
+            db.put(&key, &value);
+        }
+        // The new scope essentially closes the DB - when Files run out of scope then
+        // they are close, Rust bizairely does not allow error handling on close!
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            let returned_value = db.get(&key).unwrap();
+            assert!(returned_value == value);
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_db_put_raw() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let key = b"compressed".to_vec();
+        let raw_key = b"raw".to_vec();
+        // Too big for a leaf page but very compressible, LZ4 will shrink
+        // it so it fits in the leaf.
+        let value = vec![0u8; 5000];
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::LZ4,
+            );
+            db.put(&key, &value);
+            db.put_raw(&raw_key, &value);
+
+            let root = db.get_master_page().get_global_tree_root_page_no();
+            let tuple = StoreTupleProcessor::get_tuple(&key, root, &mut db.page_cache).unwrap();
+            assert_eq!(tuple.get_overflow(), Overflow::ValueCompressed);
+            let tuple = StoreTupleProcessor::get_tuple(&raw_key, root, &mut db.page_cache).unwrap();
+            assert_eq!(tuple.get_overflow(), Overflow::ValueOverflow);
+        }
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::LZ4,
+            );
+            assert_eq!(db.get(&key).unwrap(), value);
+            assert_eq!(db.get(&raw_key).unwrap(), value);
+            assert!(db.delete(&key));
+            assert!(db.delete(&raw_key));
+            assert!(db.get(&key).is_none());
+            assert!(db.get(&raw_key).is_none());
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    fn check_put_delete_returning(compressor_type: CompressorType) {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            compressor_type,
+            1024,
+        );
+        let oversized_key = vec![3u8; 1000];
+        // Inline, compressible, in overflow pages and empty.
+        let values = [
+            b"small".to_vec(),
+            vec![0u8; 3000],
+            (0..5000u32).map(|i| (i * 7) as u8).collect(),
+            vec![],
+        ];
+        for key in [b"key".to_vec(), oversized_key] {
+            assert_eq!(db.put_returning(&key, &values[0]), None);
+            for pair in values.windows(2) {
+                assert_eq!(db.put_returning(&key, &pair[1]), Some(pair[0].clone()));
+            }
+            assert_eq!(db.put_returning(&key, &values[1]), Some(vec![]));
+            assert_eq!(db.delete_returning(&key), Some(values[1].clone()));
+            assert_eq!(db.delete_returning(&key), None);
+            assert_eq!(db.get(&key), None);
+            db.put(&key, &values[2]);
+            assert_eq!(db.delete_returning(&key), Some(values[2].clone()));
+        }
+        assert_eq!(db.delete_returning(b"missing"), None);
+        assert_eq!(db.verify(), Ok(()));
+
+        // With dir pages above the leaves, through the try variants.
+        for i in 0..500u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        for value in &values {
+            db.put(b"key", value);
+            assert_eq!(
+                db.try_put_returning(b"key", b"next").unwrap(),
+                Some(value.clone())
+            );
+        }
+        assert_eq!(
+            db.try_delete_returning(b"key").unwrap(),
+            Some(b"next".to_vec())
+        );
+        assert_eq!(db.try_delete_returning(b"key").unwrap(), None);
+        assert_eq!(db.try_put_returning(b"key", b"new").unwrap(), None);
+        let too_large = vec![1u8; TupleProcessor::MAX_KEY_SIZE + 1];
+        assert!(matches!(
+            db.try_delete_returning(&too_large),
+            Err(DigbyError::KeyTooLarge(_))
+        ));
+        assert!(matches!(
+            db.try_put_returning(&too_large, b"value"),
+            Err(DigbyError::KeyTooLarge(_))
+        ));
+        assert_eq!(db.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_db_put_delete_returning() {
+        check_put_delete_returning(CompressorType::None);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_db_put_delete_returning_lz4() {
+        check_put_delete_returning(CompressorType::LZ4);
+    }
+
+    #[test]
+    fn test_db_verify() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                256,
+            );
+            for i in 0..200u32 {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+            db.put(b"large", vec![7u8; 5000]);
+            db.put_table_entry(b"table", b"key", vec![8u8; 5000]);
+            assert_eq!(db.verify(), Ok(()));
+
+            // Corrupt the head of the overflow chain for the large value.
+            let root = db.get_master_page().get_global_tree_root_page_no();
+            let tuple = StoreTupleProcessor::get_tuple(b"large", root, &mut db.page_cache).unwrap();
+            let head_page_no = PageNo::from_bytes(tuple.get_value());
+            let mut page = db.page_cache.get_page(head_page_no);
+            page.set_type(PageType::Free);
+            db.page_cache.put_page(&mut page);
+            assert_eq!(db.verify(), Err(Corruption::WrongPageType(head_page_no)));
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    // An overflow chain cut short is found by verify and by reads,
+    // rather than the value being returned short.
+    #[test]
+    fn test_db_verify_overflow_length() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            256,
+        );
+        db.put(b"large", vec![7u8; 5000]);
+        assert_eq!(db.verify(), Ok(()));
+
+        let root = db.get_master_page().get_global_tree_root_page_no();
+        let tuple = StoreTupleProcessor::get_tuple(b"large", root, &mut db.page_cache).unwrap();
+        let head_page_no = PageNo::from_bytes(tuple.get_value());
+        let mut head_page = OverflowPage::from_page(db.page_cache.get_page(head_page_no));
+        let second_page_no = PageNo::from_u64(head_page.get_next_page());
+        let second_page = OverflowPage::from_page(db.page_cache.get_page(second_page_no));
+        head_page.set_next_page(second_page.get_next_page());
+        db.page_cache.put_page(head_page.get_page());
+
+        assert_eq!(db.verify(), Err(Corruption::OverflowLength(head_page_no)));
+        assert!(matches!(
+            db.try_get(b"large"),
+            Err(DigbyError::Corruption(Corruption::OverflowLength(page_no))) if page_no == head_page_no
+        ));
+    }
+
+    // Small pages so the free page directory runs over many pages.
+    #[test]
+    fn test_db_free_page_count_kept() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, 256);
+        // Nothing committed, the free page directory is walked.
+        assert_eq!(db.get_master_page().get_free_page_count(), None);
+        assert_eq!(db.free_page_count(), 4);
+        for i in 0..500u32 {
+            db.put(i.to_be_bytes(), [1u8; 20]);
+        }
+        db.put(b"large", vec![7u8; 50000]);
+        assert!(db.delete(b"large"));
+        for i in 0..250u32 {
+            assert!(db.delete(i.to_be_bytes()));
+        }
+        let master_page = db.get_master_page();
+        let free_page_count = db.count_free_pages(master_page.get_free_page_dir_page_no(0));
+        assert!(free_page_count > 200);
+        assert_eq!(master_page.get_free_page_count(), Some(free_page_count));
+        assert_eq!(db.free_page_count(), free_page_count);
+        assert!(db.verify_report().is_ok());
+
+        db.compact_in_place().expect("Failed to compact");
+        let master_page = db.get_master_page();
+        let free_page_count = db.count_free_pages(master_page.get_free_page_dir_page_no(0));
+        assert_eq!(master_page.get_free_page_count(), Some(free_page_count));
+
+        // A count that has drifted from the free page directory.
+        let mut master_page = db.get_master_page();
+        master_page.set_free_page_count(free_page_count + 1);
+        db.page_cache.put_page(master_page.get_page());
+        assert_eq!(
+            db.verify(),
+            Err(Corruption::FreePageCount(master_page.get_page_number()))
+        );
+    }
+
+    // Find the leaf page in the global tree that holds key.
+    fn find_leaf_page_no(db: &mut Db, key: &[u8]) -> PageNo {
+        let mut page_no = db.get_master_page().get_global_tree_root_page_no();
+        loop {
+            let page = db.page_cache.get_page_ref(page_no);
+            if page.get_type() == PageType::LeafPage {
+                return page_no;
+            }
+            page_no = DirPage::get_next_page(page, key, db.db_config.key_order);
+        }
+    }
+
+    #[test]
+    fn test_db_verify_report() {
+        use std::os::unix::fs::FileExt;
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, 256);
+        for i in 0..200u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        db.put(b"large", vec![7u8; 5000]);
+        db.put_table_entry(b"table", b"key", vec![8u8; 5000]);
+        for i in 0..200u32 {
+            db.put(i.to_be_bytes(), i.to_be_bytes());
+        }
+        let report = db.verify_report();
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert!(report.pages_checked > 40);
+        assert!(report.free_pages > 0);
+        assert_eq!(report.free_pages, db.free_page_count());
+
+        // Swap the first two slots of a leaf, each slot is 6 bytes after
+        // the 30 byte header.
+        let unsorted_page_no = find_leaf_page_no(&mut db, &100u32.to_be_bytes());
+        let mut page = db.page_cache.get_page(unsorted_page_no);
+        page.get_page_bytes_mut()[30..42].rotate_left(6);
+        db.page_cache.put_page(&mut page);
+
+        // Overwrite the last leaf with the keys of the first.
+        let first_page_no = find_leaf_page_no(&mut db, &0u32.to_be_bytes());
+        let out_of_range_page_no = find_leaf_page_no(&mut db, &199u32.to_be_bytes());
+        let first_page = LeafPage::from_page(
+            db.page_cache.get_page(first_page_no),
+            db.db_config.key_order,
+        );
+        let mut leaf_page = LeafPage::create_new(
+            db.page_cache.get_page_config(),
+            out_of_range_page_no,
+            first_page.get_version(),
+        );
+        for tuple in first_page.get_all_tuples() {
+            leaf_page.add_tuple(&tuple);
+        }
+        db.page_cache.put_page(leaf_page.get_page());
+
+        // Put a page in use and a page already free in the free page
+        // directory.
+        let free_dir_page_no = db.get_master_page().get_free_page_dir_page_no(0);
+        let mut free_dir_page = FreeDirPage::from_page(db.page_cache.get_page(free_dir_page_no));
+        let twice_page_no = free_dir_page.get_all_free_pages()[0];
+        for page_no in [first_page_no, twice_page_no] {
+            if free_dir_page.is_full() {
+                free_dir_page.get_free_page();
+            }
+            free_dir_page.add_free_page(page_no);
+        }
+        db.page_cache.put_page(free_dir_page.get_page());
+
+        // Break the checksum of another leaf in the file.
+        let bad_page_no = find_leaf_page_no(&mut db, &50u32.to_be_bytes());
+        {
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .open(path)
+                .expect("Failed to open DB file");
+            file.write_all_at(&[0xff; 8], bad_page_no.get_blk_offset() * 256 + 100)
+                .expect("Failed to write DB file");
+        }
+
+        let report = db.verify_report();
+        assert!(!report.is_ok());
+        // The pages added to the free page directory are not in the
+        // master page's count.
+        let master_page_no = db.get_master_page().get_page_number();
+        for corruption in [
+            Corruption::UnsortedKeys(unsorted_page_no),
+            Corruption::KeyOutOfRange(out_of_range_page_no),
+            Corruption::FreePageInUse(first_page_no),
+            Corruption::FreePageTwice(twice_page_no),
+            Corruption::BadBlock(bad_page_no),
+            Corruption::FreePageCount(master_page_no),
+        ] {
+            assert!(
+                report.problems.contains(&corruption),
+                "{:?}",
+                report.problems
+            );
+        }
+        assert_eq!(report.problems.len(), 6, "{:?}", report.problems);
+        assert_eq!(report.get_bad_page_nos().len(), 6);
+        assert_eq!(
+            db.verify(),
+            Err(report.problems.into_iter().next().unwrap())
+        );
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_no_verify_checksums() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            for i in 0..100u32 {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+        }
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            db.set_verify_checksums(false);
+            for i in 0..100u32 {
+                assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+            }
+            // Writes still set the checksum.
+            db.put(b"after", b"value");
+        }
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            assert_eq!(db.get(b"after").unwrap(), b"value");
+            assert_eq!(db.verify(), Ok(()));
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_dump_page() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            db.put(b"key1", b"value1");
+            db.put(b"key2", b"value2");
+            db.put(b"large", vec![3u8; 5000]);
+
+            let root = db.get_master_page().get_global_tree_root_page_no();
+            let dump = db.dump_page(root);
+            assert_eq!(dump.page_no, root);
+            assert_eq!(dump.page_type, Some(PageType::LeafPage));
+            assert_eq!(dump.version, 4);
+            assert_eq!(dump.entries, Some(3));
+            assert!(dump.free_space.unwrap() > 0);
+            assert_eq!(
+                dump.keys,
+                vec![b"key1".to_vec(), b"key2".to_vec(), b"large".to_vec()]
+            );
+
+            let tuple = StoreTupleProcessor::get_tuple(b"large", root, &mut db.page_cache).unwrap();
+            let dump = db.dump_page(PageNo::from_bytes(tuple.get_value()));
+            assert_eq!(dump.page_type, Some(PageType::Overflow));
+            assert_ne!(dump.next_page, Some(0));
+            assert!(dump.used_size.unwrap() > 0);
+            assert!(dump.keys.is_empty());
+
+            let dump = db.dump_page(PageNo::from_u64(0));
+            assert_eq!(dump.page_type, Some(PageType::DbRoot));
+            assert!(dump.entries.is_none());
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_range() {
+        use std::collections::BTreeMap;
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                256,
+            );
+            let mut expected: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+            let mut keys: Vec<u32> = (0..500).collect();
+            keys.shuffle(&mut rng());
+            for i in keys {
+                let key = format!("key{:05}", i * 3).into_bytes();
+                let value = i.to_le_bytes().to_vec();
+                db.put(&key, &value);
+                expected.insert(key, value);
+            }
+            // Remove some so there are gaps and deleted pages.
+            for i in 100..200u32 {
+                let key = format!("key{:05}", i * 3).into_bytes();
+                assert!(db.delete(&key));
+                expected.remove(&key);
+            }
+            db.put(b"large", vec![9u8; 3000]);
+            expected.insert(b"large".to_vec(), vec![9u8; 3000]);
+
+            let ranges: [(&[u8], Option<&[u8]>); 6] = [
+                (b"", None),
+                (b"key00100", Some(b"key00200")),
+                (b"key00301", Some(b"key00700")),
+                (b"key00500", None),
+                (b"key01400", Some(b"key01400")),
+                (b"key", Some(b"lz")),
+            ];
+            for (start, end) in ranges {
+                let got = db.range(start, end);
+                let want: Vec<(Vec<u8>, Vec<u8>)> = expected
+                    .iter()
+                    .filter(|(key, _)| {
+                        key.as_slice() >= start && end.is_none_or(|end| key.as_slice() < end)
+                    })
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                assert_eq!(got, want);
+            }
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    struct SyncCounter {
+        syncs: std::sync::Arc<std::sync::atomic::AtomicU64>,
+        full_syncs: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    }
+
+    impl Observer for SyncCounter {
+        fn on_sync(&mut self, full: bool) {
+            self.syncs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if full {
+                self.full_syncs
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+    }
+
+    // Number of sync_data and sync_all calls for a single put with the
+    // sync mode.
+    fn syncs_per_commit(sync_mode: SyncMode) -> (u64, u64) {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let syncs = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let full_syncs = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            db.set_sync_mode(sync_mode);
+            db.set_observer(Box::new(SyncCounter {
+                syncs: syncs.clone(),
+                full_syncs: full_syncs.clone(),
+            }));
+            db.put(b"key", b"value");
+            assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+        }
+        // Take off the sync_all done on drop.
+        let syncs = syncs.load(std::sync::atomic::Ordering::SeqCst);
+        let full_syncs = full_syncs.load(std::sync::atomic::Ordering::SeqCst) - 1;
+        (syncs - 1 - full_syncs, full_syncs)
+    }
+
+    #[test]
+    fn test_db_write_batch() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let syncs = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        db.put(b"old", b"value");
+        db.put(b"replaced", b"before");
+        // Leave free pages for the batch so the file is not extended
+        // much, which syncs too.
+        db.put(b"blob", vec![1u8; 2000 * Db::BLOCK_SIZE]);
+        assert!(db.delete(b"blob"));
+        db.set_observer(Box::new(SyncCounter {
+            syncs: syncs.clone(),
+            full_syncs: std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0)),
+        }));
+        let version = db.get_master_page().get_version();
+        let mut ops: Vec<BatchOp> = (0..500u32)
+            .map(|i| BatchOp::Put(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+            .collect();
+        ops.push(BatchOp::Delete(b"old".to_vec()));
+        ops.push(BatchOp::Put(b"replaced".to_vec(), b"after".to_vec()));
+        ops.push(BatchOp::Put(b"large".to_vec(), vec![5u8; 20000]));
+        // Later ops on a key see the earlier ones.
+        ops.push(BatchOp::Delete(0u32.to_be_bytes().to_vec()));
+        ops.push(BatchOp::Put(b"missing".to_vec(), b"x".to_vec()));
+        ops.push(BatchOp::Delete(b"missing".to_vec()));
+        ops.push(BatchOp::Delete(b"never".to_vec()));
+        db.write_batch(ops).expect("Failed to write batch");
+
+        // One commit, syncing twice for the commit and maybe when the
+        // file grows rather than twice for every op.
+        assert_eq!(db.get_master_page().get_version(), version + 1);
+        assert!(syncs.load(std::sync::atomic::Ordering::SeqCst) < 5);
+        assert_eq!(db.get(0u32.to_be_bytes()), None);
+        for i in 1..500u32 {
+            assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+        }
+        assert_eq!(db.get(b"old"), None);
+        assert_eq!(db.get(b"replaced"), Some(b"after".to_vec()));
+        assert_eq!(db.get(b"large"), Some(vec![5u8; 20000]));
+        assert_eq!(db.get(b"missing"), None);
+        assert_eq!(db.verify(), Ok(()));
+
+        // An empty batch is an empty commit.
+        db.write_batch(vec![]).expect("Failed to write batch");
+        assert_eq!(db.get_master_page().get_version(), version + 2);
+        assert_eq!(db.get(b"replaced"), Some(b"after".to_vec()));
+    }
+
+    #[test]
+    fn test_db_sync_mode() {
+        assert_eq!(syncs_per_commit(SyncMode::None), (0, 0));
+        assert_eq!(syncs_per_commit(SyncMode::Data), (2, 0));
+        assert_eq!(syncs_per_commit(SyncMode::Full), (0, 2));
+    }
+
+    #[test]
+    fn test_db_flush() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let syncs = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+        {
+            let mut db = Db::new(path, None, CompressorType::None);
+            db.set_sync_mode(SyncMode::None);
+            db.set_observer(Box::new(SyncCounter {
+                syncs: syncs.clone(),
+                full_syncs: std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            }));
+            for i in 0..10u32 {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+            assert_eq!(syncs.load(std::sync::atomic::Ordering::SeqCst), 0);
+            db.flush().expect("Failed to flush");
+            assert_eq!(syncs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        }
+        let mut db = Db::open_read_only(path, None, CompressorType::None);
+        db.flush().expect("Failed to flush");
+        for i in 0..10u32 {
+            assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_db_scan_prefix() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        assert_eq!(db.scan_prefix(b"user:").count(), 0);
+        for key in [
+            b"user:1".to_vec(),
+            b"user:123:name".to_vec(),
+            b"user:123:email".to_vec(),
+            b"user:124:name".to_vec(),
+            b"user;".to_vec(),
+            b"usea".to_vec(),
+            vec![0xff, 0xff],
+            vec![0xff, 0xff, 0x01],
+        ] {
+            db.put(&key, b"value");
+        }
+        let keys = |db: &mut Db, prefix: &[u8]| -> Vec<Vec<u8>> {
+            db.scan_prefix(prefix).map(|(key, _)| key).collect()
+        };
+        assert_eq!(
+            keys(&mut db, b"user:123:"),
+            vec![b"user:123:email".to_vec(), b"user:123:name".to_vec()]
+        );
+        assert_eq!(keys(&mut db, b"user:").len(), 4);
+        assert_eq!(keys(&mut db, b"user:2"), Vec::<Vec<u8>>::new());
+        assert_eq!(
+            keys(&mut db, &[0xff, 0xff]),
+            vec![vec![0xff, 0xff], vec![0xff, 0xff, 0x01]]
+        );
+        assert_eq!(keys(&mut db, b"").len(), 8);
+
+        // Oversized keys match on the full key, not the part in the tree.
+        let mut long_a = vec![b'x'; 3000];
+        let mut long_b = long_a.clone();
+        long_a.push(b'a');
+        long_b.push(b'b');
+        db.put(&long_a, b"a");
+        db.put(&long_b, b"b");
+        assert_eq!(keys(&mut db, &vec![b'x'; 3000]).len(), 2);
+        let matched: Vec<_> = db.scan_prefix(&long_b).collect();
+        assert_eq!(matched, vec![(long_b, b"b".to_vec())]);
+    }
+
+    #[test]
+    fn test_db_as_ref_keys() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        db.put("key", "value");
+        db.put([1u8, 2], vec![3u8]);
+        db.put(String::from("owned"), b"bytes");
+        assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+        assert_eq!(db.get("key"), db.get(b"key"));
+        assert_eq!(db.get([1u8, 2]), Some(vec![3]));
+        assert_eq!(db.try_get("owned").unwrap(), Some(b"bytes".to_vec()));
+        assert!(db.delete("key"));
+        assert!(!db.try_delete("key").unwrap());
+        assert!(db.put_if_absent("key", "first"));
+        assert!(!db.try_put_if_absent("key", [2u8]).unwrap());
+        assert_eq!(db.put_returning("key", "second"), Some(b"first".to_vec()));
+        assert_eq!(
+            db.try_put_returning(b"key", "third").unwrap(),
+            Some(b"second".to_vec())
+        );
+        assert_eq!(db.delete_returning("key"), Some(b"third".to_vec()));
+        assert_eq!(db.try_delete_returning(String::from("key")).unwrap(), None);
+        db.put_raw("raw", "value");
+        assert_eq!(db.get("raw"), Some(b"value".to_vec()));
+        assert_eq!(db.cursor_prefix("ra").count(), 1);
+
+        assert!(db.create_table("table"));
+        db.put_table_entry("table", "key", "value");
+        assert_eq!(db.get_table_entry(b"table", "key"), Some(b"value".to_vec()));
+        assert!(db.delete_table_entry("table", b"key"));
+        assert!(db.table_exists("table"));
+        assert_eq!(db.table_len("table"), 0);
+        assert!(db.table_version("table").is_some());
+        db.put_table_entry("table", "prefix1", "value");
+        assert_eq!(db.delete_prefix("table", "prefix"), 1);
+        db.clear_table("table");
+        db.delete_table("table");
+        assert!(!db.drop_table("table"));
+    }
+
+    #[test]
+    fn test_db_prefix_end() {
+        assert_eq!(Db::prefix_end(b"abc"), Some(b"abd".to_vec()));
+        assert_eq!(Db::prefix_end(&[1, 0xff, 0xff]), Some(vec![2]));
+        assert_eq!(Db::prefix_end(&[0xff, 0xff]), None);
+        assert_eq!(Db::prefix_end(b""), None);
+    }
+
+    #[test]
+    fn test_db_table_version() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            assert_eq!(db.table_version(b"table"), None);
+            db.create_table(b"table");
+            let created = db.table_version(b"table").unwrap();
+            assert!(created > 0);
+
+            // Changes to other tables or the global tree do not change it.
+            db.put_table_entry(b"other", b"key", b"value");
+            db.put(b"key", b"value");
+            assert_eq!(db.delete_prefix(b"table", b"key"), 0);
+            assert_eq!(db.table_version(b"table"), Some(created));
+
+            db.put_table_entry(b"table", b"key", b"value");
+            let put = db.table_version(b"table").unwrap();
+            assert!(put > created);
+            assert!(db.delete_table_entry(b"table", b"key"));
+            let deleted = db.table_version(b"table").unwrap();
+            assert!(deleted > put);
+            db.clear_table(b"table");
+            assert!(db.table_version(b"table").unwrap() > deleted);
+            db.delete_table(b"table");
+            assert_eq!(db.table_version(b"table"), None);
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_table_version_legacy_entry() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            db.put_table_entry(b"table", b"key", b"value");
+            let root_page_no = db.get_table_tree_root(b"table").unwrap();
+
+            // Rewrite the table entry as an old style 8 byte root page number.
+            let mut tx_ctx = db.new_transaction();
+            let tuple = TupleProcessor::generate_tuple(
+                b"table",
+                &root_page_no.get_bytes(),
+                &mut db.page_cache,
+                &mut tx_ctx.free_page_tracker,
+                tx_ctx.new_version,
+                &db.compressor,
+                &db.db_config,
+            );
+            let page = db.page_cache.get_page(tx_ctx.tree_dir_root_page_no);
+            tx_ctx.tree_dir_root_page_no = PageNo::from_u64(StoreTupleProcessor::store_tuple(
+                tuple,
+                page,
+                &mut tx_ctx.free_page_tracker,
+                &mut db.page_cache,
+                tx_ctx.new_version,
+                &db.db_config,
+            ));
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+        }
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            assert_eq!(db.table_version(b"table"), Some(0));
+            assert_eq!(
+                db.get_table_entry(b"table", b"key"),
+                Some(b"value".to_vec())
+            );
+            assert_eq!(db.table_infos()[0].entry_count, 1);
+            assert_eq!(db.verify(), Ok(()));
+            // The next change writes the entry with a version.
+            db.put_table_entry(b"table", b"key2", b"value2");
+            assert!(db.table_version(b"table").unwrap() > 0);
+            assert_eq!(
+                db.get_table_entry(b"table", b"key"),
+                Some(b"value".to_vec())
+            );
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_table_root_cache() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                256,
+            );
+            db.put_table_entry(b"table", 0u32.to_be_bytes(), b"value");
+            assert_eq!(
+                db.get_table_entry(b"table", 0u32.to_be_bytes()),
+                Some(b"value".to_vec())
+            );
+            let version = db.get_master_page().get_version();
+            let (first_root, _) = db.table_root_cache.get(b"table", version).unwrap();
+
+            // A commit that does not touch the table directory keeps the root.
+            db.put(b"key", b"value");
+            let version = db.get_master_page().get_version();
+            assert_eq!(
+                db.table_root_cache.get(b"table", version),
+                Some((first_root, None))
+            );
+
+            // Split the table tree, the cached root must not be used.
+            for i in 1..100u32 {
+                db.put_table_entry(b"table", i.to_be_bytes(), i.to_le_bytes());
+            }
+            let version = db.get_master_page().get_version();
+            assert!(db.table_root_cache.get(b"table", version).is_none());
+            for i in 1..100u32 {
+                assert_eq!(
+                    db.get_table_entry(b"table", i.to_be_bytes()),
+                    Some(i.to_le_bytes().to_vec())
+                );
+            }
+            let (root, _) = db.table_root_cache.get(b"table", version).unwrap();
+            assert_ne!(root, first_root);
+            assert_eq!(
+                db.dump_page(root).page_type,
+                Some(PageType::DirPage),
+                "Table tree should have split"
+            );
+            let tx_ctx = db.new_transaction();
+            assert_eq!(db.get_table_tree_root_txn(b"table", &tx_ctx), Some(root));
+
+            // Deleting the table drops it from the cache.
+            db.delete_table(b"table");
+            assert_eq!(db.get_table_entry(b"table", 1u32.to_be_bytes()), None);
+            assert!(db.table_root_cache.is_empty());
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_table_infos() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                256,
+            );
+            assert!(db.table_infos().is_empty());
+            db.create_table(b"empty");
+            for i in 0..200u32 {
+                db.put_table_entry(b"big", i.to_be_bytes(), b"value");
+            }
+            db.put_table_entry(b"small", b"key", b"value");
+            db.put(b"not_a_table", b"value");
+
+            let table_infos = db.table_infos();
+            let names: Vec<&[u8]> = table_infos.iter().map(|t| t.name.as_slice()).collect();
+            assert_eq!(names, vec![&b"big"[..], b"empty", b"small"]);
+            for table_info in &table_infos {
+                assert_eq!(
+                    Some(table_info.root_page_no),
+                    db.get_table_tree_root(&table_info.name)
+                );
+            }
+            assert_eq!(table_infos[0].entry_count, 200);
+            assert!(table_infos[0].page_count > 1);
+            assert_eq!(table_infos[1].entry_count, 0);
+            assert_eq!(table_infos[1].page_count, 1);
+            assert_eq!(table_infos[2].entry_count, 1);
+            assert_eq!(table_infos[2].page_count, 1);
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_list_tables() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                256,
+            );
+            assert!(db.list_tables().is_empty());
+            db.put(b"not_a_table", b"value");
+            // Enough tables to split the table dir more than once.
+            let mut names: Vec<Vec<u8>> = (0..300u32)
+                .map(|i| format!("table{}", i).into_bytes())
+                .collect();
+            let mut tx_ctx = db.new_transaction();
+            for name in &names {
+                db.create_table_txn(name, &mut tx_ctx);
+            }
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+            let mut depth = 1;
+            let table_dir_page_no = db.get_master_page().get_table_dir_page_no();
+            let mut page = db.page_cache.get_page(table_dir_page_no);
+            while page.get_type() == PageType::DirPage {
+                let child_page_no =
+                    DirPage::from_page(page, db.db_config.key_order).get_all_child_pages()[0];
+                page = db.page_cache.get_page(child_page_no);
+                depth += 1;
+            }
+            assert!(depth >= 3, "{}", depth);
+
+            names.sort();
+            assert_eq!(db.list_tables(), names);
+            db.delete_table(b"table7");
+            names.retain(|name| name != b"table7");
+            assert_eq!(db.list_tables(), names);
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_iter_all() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                1024,
+            );
+            assert_eq!(db.iter_all().count(), 0);
+            db.put_table_entry(b"small", b"key", b"value");
+            db.create_table(b"empty");
+            for i in (0..100u32).rev() {
+                db.put_table_entry(b"big", i.to_be_bytes(), i.to_le_bytes());
+            }
+            db.put(b"global2", b"value2");
+            db.put(b"global1", b"value1");
+            db.put_table_entry(b"small", vec![7u8; 1000], vec![8u8; 5000]);
+        }
+        {
+            let mut db = Db::new_with_page_size(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+                1024,
+            );
+            let mut expected = vec![
+                (vec![], b"global1".to_vec(), b"value1".to_vec()),
+                (vec![], b"global2".to_vec(), b"value2".to_vec()),
+            ];
+            for i in 0..100u32 {
+                expected.push((
+                    b"big".to_vec(),
+                    i.to_be_bytes().to_vec(),
+                    i.to_le_bytes().to_vec(),
+                ));
+            }
+            expected.push((b"small".to_vec(), vec![7u8; 1000], vec![8u8; 5000]));
+            expected.push((b"small".to_vec(), b"key".to_vec(), b"value".to_vec()));
+            assert_eq!(db.iter_all().collect::<Vec<_>>(), expected);
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_put_if_absent() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        assert!(db.put_if_absent(b"key", b"first"));
+        assert!(!db.put_if_absent(b"key", b"second"));
+        assert_eq!(db.get(b"key"), Some(b"first".to_vec()));
+
+        // An empty value is present.
+        assert!(db.put_if_absent(b"empty", b""));
+        assert!(!db.put_if_absent(b"empty", b"value"));
+        assert_eq!(db.get(b"empty"), Some(Vec::new()));
+
+        // Oversized keys are compared in full.
+        let long_key = vec![1u8; 2000];
+        let mut other_long_key = long_key.clone();
+        other_long_key[1999] = 2;
+        assert!(db.put_if_absent(&long_key, vec![3u8; 5000]));
+        assert!(!db.put_if_absent(&long_key, b"second"));
+        assert!(db.put_if_absent(&other_long_key, b"other"));
+        assert_eq!(db.get(&long_key), Some(vec![3u8; 5000]));
+        assert_eq!(db.get(&other_long_key), Some(b"other".to_vec()));
+
+        // A key put earlier in the transaction is present, and a deleted
+        // key is absent.
+        let mut tx_ctx = db.new_transaction();
+        assert!(db.put_if_absent_txn(b"txn", b"first", &mut tx_ctx));
+        assert!(!db.put_if_absent_txn(b"txn", b"second", &mut tx_ctx));
+        assert!(db.delete_txn(b"key", &mut tx_ctx));
+        assert!(db.put_if_absent_txn(b"key", b"again", &mut tx_ctx));
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+        assert_eq!(db.get(b"txn"), Some(b"first".to_vec()));
+        assert_eq!(db.get(b"key"), Some(b"again".to_vec()));
+
+        // With dir pages above the leaves, through try_put_if_absent. A
+        // large value that is not stored leaves no overflow pages behind.
+        for i in 0..500u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        assert!(
+            !db.try_put_if_absent(7u32.to_be_bytes(), [9u8; 5000])
+                .unwrap()
+        );
+        assert!(db.try_put_if_absent(b"new", b"value").unwrap());
+        assert!(!db.try_put_if_absent(b"new", b"again").unwrap());
+        assert_eq!(
+            db.get(7u32.to_be_bytes()),
+            Some(7u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(db.get(b"new"), Some(b"value".to_vec()));
+        assert_eq!(db.verify(), Ok(()));
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_first_and_last_key() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        assert_eq!(db.first_key(), None);
+        assert_eq!(db.last_key(), None);
+        db.put(b"only", b"value");
+        assert_eq!(db.first_key(), Some(b"only".to_vec()));
+        assert_eq!(db.last_key(), Some(b"only".to_vec()));
+
+        let mut keys: Vec<u32> = (0..3000).collect();
+        keys.shuffle(&mut rng());
+        for i in &keys {
+            db.put((i * 2).to_be_bytes(), [1u8; 20]);
+        }
+        db.delete(b"only");
+        assert!(db.tree_depth() > 2);
+        assert_eq!(db.first_key(), Some(0u32.to_be_bytes().to_vec()));
+        assert_eq!(db.last_key(), Some(5998u32.to_be_bytes().to_vec()));
+
+        // Deleting the ends empties the leaf pages at the edges.
+        for i in (0..500u32).chain(2500..3000) {
+            assert!(db.delete((i * 2).to_be_bytes()));
+        }
+        assert_eq!(db.first_key(), Some(1000u32.to_be_bytes().to_vec()));
+        assert_eq!(db.last_key(), Some(4998u32.to_be_bytes().to_vec()));
+        let range = db.range(b"", None::<&[u8]>);
+        assert_eq!(db.first_key(), Some(range[0].0.clone()));
+        assert_eq!(db.last_key(), Some(range.last().unwrap().0.clone()));
+
+        // An oversized key is returned whole.
+        let long_key = vec![0xffu8; 2000];
+        db.put(&long_key, b"value");
+        assert_eq!(db.last_key(), Some(long_key));
+
+        // Uncommitted changes are not seen.
+        let mut tx_ctx = db.new_transaction();
+        db.put_txn(b"", b"value", &mut tx_ctx);
+        assert_eq!(db.first_key(), Some(1000u32.to_be_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_db_range_rev() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::LZ4,
+            1024,
+        );
+        assert!(db.range_rev(b"", None::<&[u8]>).is_empty());
+
+        let mut keys: Vec<u32> = (0..3000).collect();
+        keys.shuffle(&mut rng());
+        for i in &keys {
+            // Compressible values, some large enough for overflow pages.
+            let value = if i % 100 == 0 {
+                vec![*i as u8; 5000]
+            } else {
+                vec![*i as u8; 40]
+            };
+            db.put((i * 2).to_be_bytes(), &value);
+        }
+        let long_key = vec![0xffu8; 2000];
+        db.put(&long_key, b"value");
+        assert!(db.tree_depth() > 2);
+
+        for (start, end) in [
+            (Vec::new(), None),
+            (
+                100u32.to_be_bytes().to_vec(),
+                Some(4001u32.to_be_bytes().to_vec()),
+            ),
+            (
+                101u32.to_be_bytes().to_vec(),
+                Some(101u32.to_be_bytes().to_vec()),
+            ),
+            (5990u32.to_be_bytes().to_vec(), None),
+        ] {
+            let mut expected = db.range(&start, end.as_deref());
+            expected.reverse();
+            assert_eq!(db.range_rev(&start, end.as_deref()), expected);
+        }
+        let all = db.range_rev(b"", None::<&[u8]>);
+        assert_eq!(all.len(), 3001);
+        assert_eq!(all[0], (long_key, b"value".to_vec()));
+        assert_eq!(all[1].0, 5998u32.to_be_bytes().to_vec());
+        assert_eq!(all.last().unwrap().1, vec![0u8; 5000]);
+    }
+
+    #[test]
+    fn test_db_tree_depth_and_histogram() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        assert_eq!(db.tree_depth(), 1);
+        let levels = db.tree_histogram();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].page_count, 1);
+        assert_eq!(levels[0].entry_count, 0);
+
+        for i in 0..5000u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        let depth = db.tree_depth();
+        assert!(depth >= 3);
+        let levels = db.tree_histogram();
+        assert_eq!(levels.len(), depth as usize);
+        assert_eq!(levels[0].page_count, 1);
+        // The fanout of each level is the pages in the level below.
+        for i in 1..levels.len() {
+            assert_eq!(levels[i - 1].entry_count, levels[i].page_count);
+        }
+        let leaves = levels.last().unwrap();
+        assert_eq!(leaves.entry_count, 5000);
+        let root_page_no = db.get_master_page().get_global_tree_root_page_no();
+        assert_eq!(
+            levels.iter().map(|level| level.page_count).sum::<u64>(),
+            db.count_tree(root_page_no).1
+        );
+        assert!(leaves.get_min_entries() > 0);
+
+        // Read only, the commit is not changed.
+        let version = db.snapshot().get_version();
+        db.tree_histogram();
+        assert_eq!(db.snapshot().get_version(), version);
+    }
+
+    #[test]
+    fn test_db_version() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let mut db = Db::new(path, None, CompressorType::None);
+        let version = db.version();
+        assert_eq!(version, db.snapshot().get_version());
+        assert_eq!(db.version(), version);
+        assert_eq!(db.get(b"key"), None);
+        assert_eq!(db.version(), version);
+
+        db.put(b"key", b"value");
+        assert_eq!(db.version(), version + 1);
+        let mut tx_ctx = db.new_transaction();
+        db.put_txn(b"key", b"other", &mut tx_ctx);
+        drop(tx_ctx);
+        assert_eq!(db.version(), version + 1);
+        db.sync_masters().expect("Failed to sync masters");
+        assert!(db.version() > version + 1);
+
+        // A read only Db follows the writer's commits.
+        let mut reader = Db::open_read_only(path, None, CompressorType::None);
+        assert_eq!(reader.version(), db.version());
+        db.put(b"key", b"new");
+        assert_eq!(reader.version(), db.version());
+    }
+
+    #[test]
+    fn test_db_len() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        assert_eq!(db.len(), 0);
+        assert!(db.is_empty());
+        for i in 0..500u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        db.put(b"large", vec![1u8; 5000]);
+        db.put(vec![2u8; 2000], b"long key");
+        db.put_table_entry(b"table", b"key", b"value");
+        assert_eq!(db.len(), 502);
+        assert!(!db.is_empty());
+        assert_eq!(db.table_len(b"table"), 1);
+        assert_eq!(db.table_len(b"missing"), 0);
+
+        // Updates do not change the count, deletes do.
+        db.put(0u32.to_be_bytes(), b"updated");
+        for i in 0..100u32 {
+            assert!(db.delete(i.to_be_bytes()));
+        }
+        assert_eq!(db.len(), 402);
+
+        // Only committed keys are counted.
+        let mut tx_ctx = db.new_transaction();
+        db.put_txn(b"uncommitted", b"value", &mut tx_ctx);
+        assert_eq!(db.len(), 402);
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+        assert_eq!(db.len(), 403);
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    fn check_table_iter(compressor_type: CompressorType) {
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
         {
-            Db::new(
+            let mut db = Db::new_with_page_size(
                 temp_file.path().to_str().unwrap(),
                 None,
-                CompressorType::None,
+                compressor_type,
+                1024,
             );
+            assert_eq!(db.table_iter(b"user1").count(), 0);
+            for i in (0..300u32).rev() {
+                db.put_table_entry(b"user1", i.to_be_bytes(), i.to_le_bytes());
+            }
+            db.put_table_entry(b"user1", vec![7u8; 1000], vec![8u8; 5000]);
+            db.put_table_entry(b"user2", b"other", b"value");
+            db.put(b"global", b"value");
+            db.create_table(b"empty");
         }
         {
-            let mut db = Db::new(
+            let mut db = Db::new_with_page_size(
                 temp_file.path().to_str().unwrap(),
                 None,
-                CompressorType::None,
+                compressor_type,
+                1024,
             );
-            let _head_page1 = DbMasterPage::from_page(db.page_cache.get_page(PageNo::from_u64(1)));
-            let head_page2 = DbMasterPage::from_page(db.page_cache.get_page(PageNo::from_u64(2)));
-            let free_page_dir_page_no = head_page2.get_free_page_dir_page_no(0);
-            let free_page_dir_page =
-                FreeDirPage::from_page(db.page_cache.get_page(free_page_dir_page_no));
-            assert!(free_page_dir_page.get_entries() == 4);
+            let mut expected: Vec<(Vec<u8>, Vec<u8>)> = (0..300u32)
+                .map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+                .collect();
+            expected.push((vec![7u8; 1000], vec![8u8; 5000]));
+            assert_eq!(db.table_iter(b"user1").collect::<Vec<_>>(), expected);
+            assert_eq!(
+                db.table_iter(b"user2").collect::<Vec<_>>(),
+                vec![(b"other".to_vec(), b"value".to_vec())]
+            );
+            assert_eq!(db.table_iter(b"empty").count(), 0);
+            assert_eq!(db.table_iter(b"missing").count(), 0);
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
     }
 
     #[test]
-    fn test_db_store_value() {
+    fn test_db_table_iter() {
+        check_table_iter(CompressorType::None);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn test_db_table_iter_lz4() {
+        check_table_iter(CompressorType::LZ4);
+    }
+
+    // The page numbers and bytes of every leaf and dir page in a tree.
+    #[test]
+    #[cfg(not(feature = "encryption"))]
+    #[should_panic(expected = "AES-128-GCM encryption is not compiled in")]
+    fn test_db_key_without_encryption() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        Db::new(
+            temp_file.path().to_str().unwrap(),
+            Some(b"the_encryption_key".to_vec()),
+            CompressorType::None,
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "snappy"))]
+    #[should_panic(expected = "Compression codec Snappy not compiled in")]
+    fn test_db_codec_not_compiled_in() {
         let temp_file = NamedTempFile::new().expect("Failed to create temp file");
-        let key = b"the_key".to_vec();
-        let value = b"the_value".to_vec();
         {
             let mut db = Db::new(
                 temp_file.path().to_str().unwrap(),
                 None,
                 CompressorType::None,
             );
-            assert!(!db.delete(&key));
-            db.put(key.as_ref(), value.as_ref());
+            // Make it look like the DB was created by a build with snappy.
+            let mut root_page = DbRootPage::from_page(db.page_cache.get_page(PageNo::new(0, 0)));
+            root_page.set_compression_type(CompressorType::Snappy.into());
+            db.page_cache.put_page(root_page.get_page());
         }
-        // The new scope essentially closes the DB - when Files run out of scope then
-        // they are close, Rust bizairely does not allow error handling on close!
+        Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+    }
+
+    #[test]
+    fn test_db_delete_collapses_root() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
         {
-            let mut db = Db::new(
+            let mut db = Db::new_with_page_size(
                 temp_file.path().to_str().unwrap(),
                 None,
                 CompressorType::None,
+                1024,
             );
-            let returned_value = db.get(key.as_ref()).unwrap();
-            assert!(returned_value == value);
+            for i in 0..200u32 {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+            let root = db.get_master_page().get_global_tree_root_page_no();
+            assert_eq!(db.page_cache.get_page(root).get_type(), PageType::DirPage);
+
+            for i in 1..200u32 {
+                assert!(db.delete(i.to_be_bytes()));
+            }
+            let root = db.get_master_page().get_global_tree_root_page_no();
+            assert_eq!(db.page_cache.get_page(root).get_type(), PageType::LeafPage);
+            assert_eq!(db.get(0u32.to_be_bytes()).unwrap(), 0u32.to_le_bytes());
+            assert_eq!(db.verify(), Ok(()));
+
+            // The tree grows again from the collapsed root.
+            for i in (1..200u32).rev() {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+            for i in 0..200u32 {
+                assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+            }
+            assert_eq!(db.verify(), Ok(()));
+        }
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    // Levels in the global tree, a tree with only a leaf page is 1.
+    fn tree_depth(db: &mut Db) -> u32 {
+        let mut depth = 1;
+        let root_page_no = db.get_master_page().get_global_tree_root_page_no();
+        let mut page = db.page_cache.get_page(root_page_no);
+        while page.get_type() == PageType::DirPage {
+            let child_page_no =
+                DirPage::from_page(page, db.db_config.key_order).get_all_child_pages()[0];
+            page = db.page_cache.get_page(child_page_no);
+            depth += 1;
+        }
+        depth
+    }
+
+    // Keys that share little with their neighbours so the dir pages
+    // cannot shorten them much.
+    fn spread_key(i: u32) -> Vec<u8> {
+        let mut key = i.wrapping_mul(2654435761).to_be_bytes().to_vec();
+        key.extend_from_slice(&[(i % 251) as u8; 60]);
+        key
+    }
+
+    // Put keys until the global tree is depth levels, returns the
+    // number of keys put.
+    fn grow_tree(db: &mut Db, depth: u32) -> u32 {
+        let mut count = 0u32;
+        while tree_depth(db) < depth {
+            let mut tx_ctx = db.new_transaction();
+            for i in count..count + 1000 {
+                db.put_txn(&spread_key(i), &i.to_le_bytes(), &mut tx_ctx);
+            }
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+            count += 1000;
+        }
+        count
+    }
+
+    #[test]
+    fn test_db_get_multi_level_tree() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            4096,
+        );
+        // A root dir page, a level of dir pages below it and the leaves.
+        let count = grow_tree(&mut db, 3);
+        for i in 0..count {
+            assert_eq!(db.get(spread_key(i)), Some(i.to_le_bytes().to_vec()));
+        }
+        assert_eq!(db.get(spread_key(count)), None);
+        assert_eq!(db.range(b"", None::<&[u8]>).len(), count as usize);
+        assert_eq!(db.verify(), Ok(()));
+    }
+
+    // Deleting every key removes the emptied leaves from their dir
+    // pages, the emptied dir pages from theirs, and leaves a single
+    // empty leaf as the root.
+    #[test]
+    fn test_db_delete_all_multi_level_tree() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            4096,
+        );
+        let count = grow_tree(&mut db, 3);
+        // Delete in key order from both ends so leaves at the edges and
+        // in the middle of dir pages are removed.
+        let mut keys: Vec<(Vec<u8>, u32)> = (0..count).map(|i| (spread_key(i), i)).collect();
+        keys.sort();
+        let (low, high) = keys.split_at(keys.len() / 2);
+        let order: Vec<&(Vec<u8>, u32)> = high.iter().rev().chain(low.iter()).collect();
+        for (index, (key, _)) in order.iter().enumerate() {
+            assert!(db.delete(key));
+            if index % 1000 == 0 {
+                assert_eq!(db.verify(), Ok(()));
+                for (key, i) in &order[index + 1..] {
+                    assert_eq!(db.get(key), Some(i.to_le_bytes().to_vec()));
+                }
+            }
+        }
+
+        let root = db.get_master_page().get_global_tree_root_page_no();
+        let root_page = db.page_cache.get_page(root);
+        assert_eq!(root_page.get_type(), PageType::LeafPage);
+        assert!(LeafPage::from_page(root_page, db.db_config.key_order).is_empty());
+        assert_eq!(db.verify(), Ok(()));
+        assert!(db.range(b"", None::<&[u8]>).is_empty());
+        for (key, _) in &keys {
+            assert_eq!(db.get(key), None);
+        }
+        assert!(!db.delete(&keys[0].0));
+
+        // The tree grows again from the empty root.
+        let count = grow_tree(&mut db, 3);
+        for i in 0..count {
+            assert_eq!(db.get(spread_key(i)), Some(i.to_le_bytes().to_vec()));
+        }
+        assert_eq!(db.verify(), Ok(()));
+    }
+
+    fn tree_pages(db: &mut Db, root_page_no: PageNo) -> Vec<(PageNo, Vec<u8>)> {
+        let mut pages = Vec::new();
+        let mut stack = vec![root_page_no];
+        while let Some(page_no) = stack.pop() {
+            let page = db.page_cache.get_page(page_no);
+            pages.push((page_no, page.get_page_bytes().to_vec()));
+            if page.get_type() == PageType::DirPage {
+                stack
+                    .extend(DirPage::from_page(page, db.db_config.key_order).get_all_child_pages());
+            }
+        }
+        pages
+    }
+
+    // Fill a tree in random order then delete most of it so the pages
+    // are left mostly empty.
+    fn fragment(db: &mut Db, table: Option<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut ids: Vec<u32> = (0..2000).collect();
+        ids.shuffle(&mut rng());
+        let mut tx_ctx = db.new_transaction();
+        for id in &ids {
+            let key = format!("key{:05}", id).into_bytes();
+            // Some values large enough to need overflow pages.
+            let value = if id % 100 == 0 {
+                vec![*id as u8; 3000]
+            } else {
+                id.to_le_bytes().to_vec()
+            };
+            match table {
+                Some(table) => db.put_table_entry_txn(table, &key, &value, &mut tx_ctx),
+                None => db.put_txn(&key, &value, &mut tx_ctx),
+            }
+        }
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+        let mut tx_ctx = db.new_transaction();
+        let mut expected = Vec::new();
+        for id in 0..2000u32 {
+            let key = format!("key{:05}", id).into_bytes();
+            if id % 10 == 0 {
+                let value = if id % 100 == 0 {
+                    vec![id as u8; 3000]
+                } else {
+                    id.to_le_bytes().to_vec()
+                };
+                expected.push((key, value));
+                continue;
+            }
+            match table {
+                Some(table) => db.delete_table_entry_txn(table, &key, &mut tx_ctx),
+                None => db.delete_txn(&key, &mut tx_ctx),
+            };
+        }
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+        expected
+    }
+
+    #[test]
+    fn test_db_sync_masters() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let get_masters = |db: &mut Db| {
+            (
+                DbMasterPage::from_page(db.page_cache.get_page(PageNo::from_u64(1))),
+                DbMasterPage::from_page(db.page_cache.get_page(PageNo::from_u64(2))),
+            )
+        };
+        {
+            let mut db = Db::new_with_page_size(path, None, CompressorType::None, 256);
+            for i in 0..50u32 {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+            db.put_table_entry(b"table", b"key", b"value");
+            // Only one master is written each commit so they differ.
+            let (master_page1, master_page2) = get_masters(&mut db);
+            assert_ne!(
+                master_page1.get_table_dir_page_no(),
+                master_page2.get_table_dir_page_no()
+            );
+            assert_ne!(
+                master_page1.get_free_page_dir_page_no(0),
+                master_page2.get_free_page_dir_page_no(0)
+            );
+            let current = db.get_master_page();
+
+            db.sync_masters().expect("Failed to sync masters");
+            let (master_page1, master_page2) = get_masters(&mut db);
+            assert_eq!(
+                master_page1.get_global_tree_root_page_no(),
+                master_page2.get_global_tree_root_page_no()
+            );
+            assert_eq!(
+                master_page1.get_table_dir_page_no(),
+                master_page2.get_table_dir_page_no()
+            );
+            assert_eq!(
+                master_page1.get_free_page_dir_page_no(0),
+                master_page2.get_free_page_dir_page_no(0)
+            );
+            assert_eq!(
+                master_page1
+                    .get_version()
+                    .abs_diff(master_page2.get_version()),
+                1
+            );
+            // The copy is the current master, the roots are unchanged.
+            let synced = db.get_master_page();
+            assert_ne!(synced.get_page_number(), current.get_page_number());
+            assert_eq!(synced.get_version(), current.get_version() + 1);
+            assert_eq!(
+                synced.get_global_tree_root_page_no(),
+                current.get_global_tree_root_page_no()
+            );
+            assert_eq!(
+                db.get_table_entry(b"table", b"key"),
+                Some(b"value".to_vec())
+            );
+
+            // The next commit overwrites the backup.
+            db.put(b"after", b"sync");
+            let (master_page1, master_page2) = get_masters(&mut db);
+            assert_ne!(
+                master_page1.get_global_tree_root_page_no(),
+                master_page2.get_global_tree_root_page_no()
+            );
+            assert_eq!(db.get_master_page().get_version(), synced.get_version() + 1);
+        }
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, 256);
+        assert_eq!(db.verify(), Ok(()));
+        for i in 0..50u32 {
+            assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+        }
+        assert_eq!(db.get(b"after"), Some(b"sync".to_vec()));
+        assert_eq!(
+            db.get_table_entry(b"table", b"key"),
+            Some(b"value".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_db_compact_global() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let expected = {
+            let mut db = Db::new_with_page_size(path, None, CompressorType::None, 1024);
+            let expected = fragment(&mut db, None);
+            fragment(&mut db, Some(b"table"));
+            let global_root = db.get_master_page().get_global_tree_root_page_no();
+            let table_root = db.get_table_tree_root(b"table").unwrap();
+            let (_, global_pages) = db.count_tree(global_root);
+            let table_pages = tree_pages(&mut db, table_root);
+
+            db.compact(CompactTarget::Global);
+            assert_eq!(db.verify(), Ok(()));
+            let global_root = db.get_master_page().get_global_tree_root_page_no();
+            let (entries, pages) = db.count_tree(global_root);
+            assert_eq!(entries, expected.len() as u64);
+            assert!(pages < global_pages / 2);
+            // The table tree has not been touched.
+            assert_eq!(db.get_table_tree_root(b"table"), Some(table_root));
+            assert_eq!(tree_pages(&mut db, table_root), table_pages);
+            expected
+        };
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, 1024);
+        assert_eq!(db.range(b"", None::<&[u8]>), expected);
+        for (key, value) in &expected {
+            assert_eq!(db.get(key).as_ref(), Some(value));
+        }
+        // The compacted tree can still be changed.
+        for id in 0..2000u32 {
+            db.put(format!("new{:05}", id).as_bytes(), b"value");
+        }
+        for (key, _) in &expected {
+            assert!(db.delete(key));
+        }
+        assert_eq!(db.verify(), Ok(()));
+        assert_eq!(db.range(b"", None::<&[u8]>).len(), 2000);
+        fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    #[test]
+    fn test_db_compact_table() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, 1024);
+        fragment(&mut db, None);
+        let expected = fragment(&mut db, Some(b"table"));
+        let other = fragment(&mut db, Some(b"other"));
+        let global_root = db.get_master_page().get_global_tree_root_page_no();
+        let global_pages = tree_pages(&mut db, global_root);
+        let other_root = db.get_table_tree_root(b"other").unwrap();
+        let other_pages = tree_pages(&mut db, other_root);
+        let table_root = db.get_table_tree_root(b"table").unwrap();
+        let (_, table_pages) = db.count_tree(table_root);
+
+        db.compact(CompactTarget::Table(b"table".to_vec()));
+        db.compact(CompactTarget::Table(b"missing".to_vec()));
+        assert_eq!(db.verify(), Ok(()));
+        let table_root = db.get_table_tree_root(b"table").unwrap();
+        let (entries, pages) = db.count_tree(table_root);
+        assert_eq!(entries, expected.len() as u64);
+        assert!(pages < table_pages / 2);
+        for (key, value) in &expected {
+            assert_eq!(db.get_table_entry(b"table", key).as_ref(), Some(value));
+        }
+        assert_eq!(
+            db.get_master_page().get_global_tree_root_page_no(),
+            global_root
+        );
+        assert_eq!(tree_pages(&mut db, global_root), global_pages);
+        assert_eq!(tree_pages(&mut db, other_root), other_pages);
+
+        db.compact(CompactTarget::All);
+        assert_eq!(db.verify(), Ok(()));
+        assert_ne!(db.get_table_tree_root(b"other").unwrap(), other_root);
+        for (key, value) in &other {
+            assert_eq!(db.get_table_entry(b"other", key).as_ref(), Some(value));
         }
+        let table_infos = db.table_infos();
+        assert_eq!(table_infos[0].entry_count, other.len() as u64);
+        assert_eq!(table_infos[1].entry_count, expected.len() as u64);
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
     }
 
@@ -1021,8 +5837,8 @@ mod tests {
                 None,
                 CompressorType::None,
             );
-            db.put(key.as_ref(), value.as_ref());
-            db.put(another_key.as_ref(), another_value.as_ref());
+            db.put(&key, &value);
+            db.put(&another_key, &another_value);
         }
         // The new scope essentially closes the DB - when Files run out of scope then
         // they are close, Rust bizairely does not allow error handling on close!
@@ -1032,9 +5848,9 @@ mod tests {
                 None,
                 CompressorType::None,
             );
-            let returned_value = db.get(key.as_ref()).unwrap();
+            let returned_value = db.get(&key).unwrap();
             assert!(returned_value == value);
-            let returned_value = db.get(another_key.as_ref()).unwrap();
+            let returned_value = db.get(&another_key).unwrap();
             assert!(returned_value == another_value);
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1051,7 +5867,7 @@ mod tests {
                 None,
                 CompressorType::None,
             );
-            db.put(key.as_ref(), value.as_ref());
+            db.put(&key, &value);
         }
         // The new scope essentially closes the DB - when Files run out of scope then
         // they are close, Rust bizairely does not allow error handling on close!
@@ -1061,7 +5877,7 @@ mod tests {
                 None,
                 CompressorType::None,
             );
-            let returned_value = db.get(key.as_ref()).unwrap();
+            let returned_value = db.get(&key).unwrap();
             assert!(returned_value == value);
         }
         {
@@ -1070,7 +5886,7 @@ mod tests {
                 None,
                 CompressorType::None,
             );
-            let deleted = db.delete(key.as_ref());
+            let deleted = db.delete(&key);
             assert!(deleted);
         }
         {
@@ -1079,7 +5895,7 @@ mod tests {
                 None,
                 CompressorType::None,
             );
-            let returned_value = db.get(key.as_ref());
+            let returned_value = db.get(&key);
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1098,7 +5914,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..=size {
-                db.put(&i.to_be_bytes(), &i.to_be_bytes());
+                db.put(i.to_be_bytes(), i.to_be_bytes());
             }
         }
         // The new scope essentially closes the DB - when Files run out of scope then
@@ -1111,7 +5927,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..=size {
-                let returned_value = db.get(&i.to_be_bytes()).unwrap();
+                let returned_value = db.get(i.to_be_bytes()).unwrap();
                 assert_eq!(u64::from_be_bytes(returned_value.try_into().unwrap()), i);
             }
         }
@@ -1123,13 +5939,13 @@ mod tests {
                 block_size,
             );
             for i in (0..(size + 1)).rev() {
-                let returned_value = db.get(&i.to_be_bytes()).unwrap();
+                let returned_value = db.get(i.to_be_bytes()).unwrap();
                 assert_eq!(u64::from_be_bytes(returned_value.try_into().unwrap()), i);
-                let deleted = db.delete(&i.to_be_bytes());
+                let deleted = db.delete(i.to_be_bytes());
                 if !deleted {
                     assert!(deleted);
                 }
-                let returned_value = db.get(&i.to_be_bytes());
+                let returned_value = db.get(i.to_be_bytes());
                 assert!(returned_value.is_none());
             }
         }
@@ -1141,7 +5957,7 @@ mod tests {
                 block_size,
             );
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_be_bytes());
+            let returned_value = db.get(i.to_be_bytes());
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1160,7 +5976,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..=size {
-                db.put(&i.to_le_bytes(), &i.to_le_bytes());
+                db.put(i.to_le_bytes(), i.to_le_bytes());
             }
         }
         // The new scope essentially closes the DB - when Files run out of scope then
@@ -1173,7 +5989,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..=size {
-                let returned_value = db.get(&i.to_le_bytes()).unwrap();
+                let returned_value = db.get(i.to_le_bytes()).unwrap();
                 assert_eq!(u64::from_le_bytes(returned_value.try_into().unwrap()), i);
             }
         }
@@ -1185,13 +6001,13 @@ mod tests {
                 block_size,
             );
             for i in (0..(size + 1)).rev() {
-                let returned_value = db.get(&i.to_le_bytes()).unwrap();
+                let returned_value = db.get(i.to_le_bytes()).unwrap();
                 assert_eq!(u64::from_le_bytes(returned_value.try_into().unwrap()), i);
-                let deleted = db.delete(&i.to_le_bytes());
+                let deleted = db.delete(i.to_le_bytes());
                 if !deleted {
                     assert!(deleted);
                 }
-                let returned_value = db.get(&i.to_le_bytes());
+                let returned_value = db.get(i.to_le_bytes());
                 assert!(returned_value.is_none());
             }
         }
@@ -1203,7 +6019,7 @@ mod tests {
                 block_size,
             );
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_le_bytes());
+            let returned_value = db.get(i.to_le_bytes());
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1220,15 +6036,15 @@ mod tests {
                 CompressorType::None,
                 256,
             );
-            let deleted = db.delete(&0u64.to_be_bytes());
+            let deleted = db.delete(0u64.to_be_bytes());
             assert!(!deleted);
             let mut numbers: Vec<u64> = (0..=size).collect();
             let mut rng = rng();
             numbers.shuffle(&mut rng);
             for i in numbers {
-                db.put(&i.to_be_bytes(), &i.to_be_bytes());
+                db.put(i.to_be_bytes(), i.to_be_bytes());
             }
-            let deleted = db.delete(&6400u64.to_be_bytes());
+            let deleted = db.delete(6400u64.to_be_bytes());
             assert!(!deleted);
         }
         // The new scope essentially closes the DB - when Files run out of scope then
@@ -1241,7 +6057,7 @@ mod tests {
                 256,
             );
             for i in 0u64..=size {
-                let returned_value = db.get(&i.to_be_bytes()).unwrap();
+                let returned_value = db.get(i.to_be_bytes()).unwrap();
                 assert_eq!(u64::from_be_bytes(returned_value.try_into().unwrap()), i);
             }
         }
@@ -1256,11 +6072,11 @@ mod tests {
             let mut rng = rng();
             numbers.shuffle(&mut rng);
             for i in numbers {
-                let returned_value = db.get(&i.to_be_bytes()).unwrap();
+                let returned_value = db.get(i.to_be_bytes()).unwrap();
                 assert_eq!(u64::from_be_bytes(returned_value.try_into().unwrap()), i);
-                let deleted = db.delete(&i.to_be_bytes());
+                let deleted = db.delete(i.to_be_bytes());
                 assert!(deleted);
-                let returned_value = db.get(&i.to_be_bytes());
+                let returned_value = db.get(i.to_be_bytes());
                 assert!(returned_value.is_none());
             }
         }
@@ -1272,7 +6088,7 @@ mod tests {
                 256,
             );
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_be_bytes());
+            let returned_value = db.get(i.to_be_bytes());
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1294,7 +6110,7 @@ mod tests {
             let mut rng = rng();
             numbers.shuffle(&mut rng);
             for i in numbers {
-                db.put(&i.to_le_bytes(), &i.to_le_bytes());
+                db.put(i.to_le_bytes(), i.to_le_bytes());
             }
         }
         // The new scope essentially closes the DB - when Files run out of scope then
@@ -1307,7 +6123,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..=size {
-                let returned_value = db.get(&i.to_le_bytes()).unwrap();
+                let returned_value = db.get(i.to_le_bytes()).unwrap();
                 assert_eq!(u64::from_le_bytes(returned_value.try_into().unwrap()), i);
             }
         }
@@ -1322,11 +6138,11 @@ mod tests {
             let mut rng = rng();
             numbers.shuffle(&mut rng);
             for i in numbers {
-                let returned_value = db.get(&i.to_le_bytes()).unwrap();
+                let returned_value = db.get(i.to_le_bytes()).unwrap();
                 assert_eq!(u64::from_le_bytes(returned_value.try_into().unwrap()), i);
-                let deleted = db.delete(&i.to_le_bytes());
+                let deleted = db.delete(i.to_le_bytes());
                 assert!(deleted);
-                let returned_value = db.get(&i.to_le_bytes());
+                let returned_value = db.get(i.to_le_bytes());
                 assert!(returned_value.is_none());
             }
         }
@@ -1338,7 +6154,7 @@ mod tests {
                 block_size,
             );
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_le_bytes());
+            let returned_value = db.get(i.to_le_bytes());
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1419,7 +6235,7 @@ mod tests {
             let mut rng = rng();
             numbers.shuffle(&mut rng);
             for i in numbers {
-                db.put(&i.to_be_bytes(), &i.to_be_bytes());
+                db.put(i.to_be_bytes(), i.to_be_bytes());
             }
         }
         // The new scope essentially closes the DB - when Files run out of scope then
@@ -1433,7 +6249,7 @@ mod tests {
             );
             db.clear();
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_be_bytes());
+            let returned_value = db.get(i.to_be_bytes());
             assert!(returned_value.is_none());
         }
         {
@@ -1447,13 +6263,60 @@ mod tests {
             let mut rng = rng();
             numbers.shuffle(&mut rng);
             for i in numbers {
-                let returned_value = db.get(&i.to_be_bytes());
+                let returned_value = db.get(i.to_be_bytes());
                 assert!(returned_value.is_none());
             }
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
     }
 
+    // The pages of the cleared tree go on the free list and are used
+    // again when the DB is filled again. The file still grows a little
+    // as pages freed by the fill's own copy on write are not reused in
+    // the same commit, but nothing like the size of the first fill.
+    #[test]
+    fn test_db_clear_frees_pages() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        let fill = |db: &mut Db| {
+            let mut tx_ctx = db.new_transaction();
+            for i in 0..5000u64 {
+                let value = if i % 500 == 0 {
+                    vec![1u8; 5000]
+                } else {
+                    i.to_le_bytes().to_vec()
+                };
+                db.put_txn(&i.to_be_bytes(), &value, &mut tx_ctx);
+            }
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+        };
+        fill(&mut db);
+        let file_len = temp_file.as_file().metadata().unwrap().len();
+        let free_before = db.free_page_count();
+
+        db.clear();
+        assert!(db.is_empty());
+        for i in (0..5000u64).step_by(13) {
+            assert_eq!(db.get(i.to_be_bytes()), None);
+        }
+        db.verify().expect("DB should verify");
+        let report = db.verify_report();
+        // The pages of the tree and the overflow pages of the large
+        // values are all free.
+        assert!(db.free_page_count() > free_before + 40);
+        assert!(report.free_pages > free_before + 40);
+
+        fill(&mut db);
+        assert!(temp_file.as_file().metadata().unwrap().len() < file_len + file_len / 10);
+        assert_eq!(db.len(), 5000);
+        db.verify().expect("DB should verify");
+    }
+
     #[test]
     fn test_match() {
         use std::cmp::Ordering;
@@ -1482,9 +6345,9 @@ mod tests {
                 block_size,
             );
             for i in 0u64..size {
-                db.put(&i.to_be_bytes(), &i.to_be_bytes());
+                db.put(i.to_be_bytes(), i.to_be_bytes());
                 for j in 0u64..i {
-                    let returned_value = db.get(&j.to_be_bytes());
+                    let returned_value = db.get(j.to_be_bytes());
                     assert!(returned_value.is_some());
                 }
             }
@@ -1499,7 +6362,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..size {
-                let returned_value = db.get(&i.to_be_bytes());
+                let returned_value = db.get(i.to_be_bytes());
                 if returned_value.is_none() {
                     assert!(returned_value.is_some());
                 }
@@ -1517,13 +6380,13 @@ mod tests {
                 block_size,
             );
             for i in 0u64..size {
-                let returned_value = db.get(&i.to_be_bytes()).unwrap();
+                let returned_value = db.get(i.to_be_bytes()).unwrap();
                 assert_eq!(u64::from_be_bytes(returned_value.try_into().unwrap()), i);
-                let deleted = db.delete(&i.to_be_bytes());
+                let deleted = db.delete(i.to_be_bytes());
                 if !deleted {
                     assert!(deleted);
                 }
-                let returned_value = db.get(&i.to_be_bytes());
+                let returned_value = db.get(i.to_be_bytes());
                 assert!(returned_value.is_none());
             }
         }
@@ -1535,7 +6398,7 @@ mod tests {
                 block_size,
             );
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_be_bytes());
+            let returned_value = db.get(i.to_be_bytes());
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -1554,9 +6417,9 @@ mod tests {
                 block_size,
             );
             for i in 0u64..size {
-                db.put(&i.to_le_bytes(), &i.to_le_bytes());
+                db.put(i.to_le_bytes(), i.to_le_bytes());
                 for j in 0u64..i {
-                    let returned_value = db.get(&j.to_le_bytes());
+                    let returned_value = db.get(j.to_le_bytes());
                     if returned_value.is_none() {
                         assert!(returned_value.is_some());
                     }
@@ -1573,7 +6436,7 @@ mod tests {
                 block_size,
             );
             for i in 0u64..size {
-                let returned_value = db.get(&i.to_le_bytes());
+                let returned_value = db.get(i.to_le_bytes());
                 if returned_value.is_none() {
                     assert!(returned_value.is_some());
                 }
@@ -1591,13 +6454,13 @@ mod tests {
                 block_size,
             );
             for i in 0u64..size {
-                let returned_value = db.get(&i.to_le_bytes()).unwrap();
+                let returned_value = db.get(i.to_le_bytes()).unwrap();
                 assert_eq!(u64::from_le_bytes(returned_value.try_into().unwrap()), i);
-                let deleted = db.delete(&i.to_le_bytes());
+                let deleted = db.delete(i.to_le_bytes());
                 if !deleted {
                     assert!(deleted);
                 }
-                let returned_value = db.get(&i.to_le_bytes());
+                let returned_value = db.get(i.to_le_bytes());
                 assert!(returned_value.is_none());
             }
         }
@@ -1609,9 +6472,393 @@ mod tests {
                 block_size,
             );
             let i: u64 = 0;
-            let returned_value = db.get(&i.to_le_bytes());
+            let returned_value = db.get(i.to_le_bytes());
             assert!(returned_value.is_none());
         }
         fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
     }
+
+    #[test]
+    #[should_panic(expected = "Db format mis-match, stored version is 0.1")]
+    fn test_db_old_format_version() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        {
+            let mut db = Db::new(
+                temp_file.path().to_str().unwrap(),
+                None,
+                CompressorType::None,
+            );
+            // Write the root page back with the version from before key
+            // lengths were u16.
+            let mut page = db.page_cache.get_page(PageNo::new(0, 0));
+            page.get_page_bytes_mut()[22..24].copy_from_slice(&1u16.to_le_bytes());
+            db.page_cache.put_page(&mut page);
+        }
+        Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+    }
+
+    #[test]
+    fn test_db_try_new_errors() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        {
+            let mut db = Db::try_new(path, None, CompressorType::None).expect("Failed to open");
+            db.try_put(b"key", b"value").expect("Failed to put");
+        }
+        if BlockSanity::Aes128Gcm.is_supported() {
+            let error = Db::try_new(path, Some(vec![1u8; 16]), CompressorType::None).err();
+            assert!(matches!(error, Some(DigbyError::EncryptionMismatch(_))));
+        }
+        #[cfg(feature = "lz4")]
+        assert!(matches!(
+            Db::try_new(path, None, CompressorType::LZ4).err(),
+            Some(DigbyError::CompressorMismatch(_))
+        ));
+
+        // The block sanity stored in the root page must match.
+        let sanity_file = NamedTempFile::new().expect("Failed to create temp file");
+        let sanity_path = sanity_file.path().to_str().unwrap();
+        {
+            let mut db = Db::new(sanity_path, None, CompressorType::None);
+            let mut page = db.page_cache.get_page(PageNo::new(0, 0));
+            page.get_page_bytes_mut()[24] = u8::from(BlockSanity::ChaCha20Poly1305);
+            db.page_cache.put_page(&mut page);
+        }
+        assert!(matches!(
+            Db::try_new(sanity_path, None, CompressorType::None).err(),
+            Some(DigbyError::SanityMismatch(
+                BlockSanity::ChaCha20Poly1305,
+                BlockSanity::XxH32Checksum
+            ))
+        ));
+
+        // The format check is the same as for new.
+        {
+            let mut db = Db::new(path, None, CompressorType::None);
+            let mut page = db.page_cache.get_page(PageNo::new(0, 0));
+            page.get_page_bytes_mut()[22..24].copy_from_slice(&1u16.to_le_bytes());
+            db.page_cache.put_page(&mut page);
+        }
+        assert!(matches!(
+            Db::try_new(path, None, CompressorType::None).err(),
+            Some(DigbyError::FormatMismatch(0, 1))
+        ));
+
+        // Not a DB.
+        fs::write(path, vec![5u8; 4 * Db::BLOCK_SIZE]).expect("Failed to write file");
+        assert!(matches!(
+            Db::try_new(path, None, CompressorType::None).err(),
+            Some(DigbyError::ChecksumMismatch(page_no)) if page_no.to_u64() == 0
+        ));
+        let missing = temp_file.path().join("missing");
+        assert!(matches!(
+            Db::try_new(missing.to_str().unwrap(), None, CompressorType::None).err(),
+            Some(DigbyError::Io(_))
+        ));
+    }
+
+    // A file cut short by an interrupted write or a partial copy is
+    // reported as corruption, not a panic, so it can be set aside.
+    #[test]
+    fn test_db_try_new_truncated() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        {
+            let mut db = Db::new(path, None, CompressorType::None);
+            for i in 0..500u32 {
+                db.put(i.to_be_bytes(), [i as u8; 100]);
+            }
+        }
+        let len = fs::metadata(path).unwrap().len();
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .expect("Failed to open file");
+        file.set_len(len - 100).expect("Failed to truncate");
+        let block_count = len / Db::BLOCK_SIZE as u64;
+        assert!(matches!(
+            Db::try_new(path, None, CompressorType::None).err(),
+            Some(DigbyError::Corruption(Corruption::TruncatedBlock(page_no)))
+                if page_no.to_u64() == block_count - 1
+        ));
+
+        // Cut on a block boundary the pages past the end are missing.
+        file.set_len(4 * Db::BLOCK_SIZE as u64)
+            .expect("Failed to truncate");
+        let result = Db::try_new(path, None, CompressorType::None)
+            .and_then(|mut db| db.try_get(499u32.to_be_bytes()));
+        assert!(matches!(
+            result,
+            Err(DigbyError::Corruption(Corruption::PageOutOfRange(_)))
+        ));
+    }
+
+    // A corrupt leaf page fails the reads and writes that go through it,
+    // the rest of the DB can still be used.
+    #[test]
+    fn test_db_try_corrupt_page() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let key = 100u32.to_be_bytes();
+        let leaf_page_no = {
+            let mut db = Db::new_with_page_size(path, None, CompressorType::None, 1024);
+            for i in 0..200u32 {
+                db.put(i.to_be_bytes(), i.to_le_bytes());
+            }
+            let mut page_no = db.get_master_page().get_global_tree_root_page_no();
+            loop {
+                let page = db.page_cache.get_page_ref(page_no);
+                if page.get_type() == PageType::LeafPage {
+                    break page_no;
+                }
+                page_no = DirPage::get_next_page(page, &key, db.db_config.key_order);
+            }
+        };
+        {
+            use std::os::unix::fs::FileExt;
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .open(path)
+                .expect("Failed to open DB file");
+            file.write_all_at(&[0xff; 8], leaf_page_no.get_blk_offset() * 1024 + 100)
+                .expect("Failed to write DB file");
+        }
+
+        let mut db = Db::try_new_with_page_size(path, None, CompressorType::None, 1024)
+            .expect("Failed to open");
+        let is_corrupt_leaf = |result: Result<(), DigbyError>| matches!(result, Err(DigbyError::ChecksumMismatch(page_no)) if page_no == leaf_page_no);
+        assert!(is_corrupt_leaf(db.try_get(key).map(|_| ())));
+        assert!(is_corrupt_leaf(db.try_put(key, b"new")));
+        assert!(is_corrupt_leaf(db.try_delete(key).map(|_| ())));
+        assert_eq!(
+            db.try_get(0u32.to_be_bytes()).unwrap(),
+            Some(0u32.to_le_bytes().to_vec())
+        );
+        db.try_put(b"other", b"value").expect("Failed to put");
+        assert_eq!(db.try_get(b"other").unwrap(), Some(b"value".to_vec()));
+        assert!(db.try_delete(b"other").unwrap());
+        assert!(!db.try_delete(b"other").unwrap());
+    }
+
+    // A torn current master page is passed over for the other one, the DB
+    // opens at the commit before and the next commit writes over it.
+    #[test]
+    fn test_db_torn_master_page() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let (old_version, master_page_no) = {
+            let mut db = Db::new(path, None, CompressorType::None);
+            db.put(b"key", b"old");
+            let old_version = db.snapshot().get_version();
+            db.put(b"key", b"new");
+            (old_version, db.get_master_page().get_page_number())
+        };
+        {
+            use std::os::unix::fs::FileExt;
+            let file = fs::OpenOptions::new()
+                .write(true)
+                .open(path)
+                .expect("Failed to open DB file");
+            file.write_all_at(
+                &[0xff; 100],
+                master_page_no.get_blk_offset() * Db::BLOCK_SIZE as u64 + 2000,
+            )
+            .expect("Failed to write DB file");
+        }
+
+        let mut db = Db::try_new(path, None, CompressorType::None).expect("Failed to open");
+        assert_eq!(db.snapshot().get_version(), old_version);
+        assert_eq!(db.get(b"key"), Some(b"old".to_vec()));
+        db.put(b"key", b"newer");
+        assert_eq!(db.get_master_page().get_page_number(), master_page_no);
+        drop(db);
+        let mut db = Db::try_new(path, None, CompressorType::None).expect("Failed to open");
+        assert_eq!(db.get(b"key"), Some(b"newer".to_vec()));
+        db.verify().expect("DB should verify");
+    }
+
+    #[test]
+    fn test_db_try_version_overflow() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        db.put(b"key", b"value");
+        let mut master_page = db.get_master_page();
+        master_page.set_version(VersionHolder::MAX_VERSION);
+        db.page_cache.put_page(master_page.get_page());
+        assert!(matches!(
+            db.try_put(b"key", b"new"),
+            Err(DigbyError::VersionOverflow)
+        ));
+        assert!(matches!(
+            db.try_delete(b"key"),
+            Err(DigbyError::VersionOverflow)
+        ));
+        assert_eq!(db.try_get(b"key").unwrap(), Some(b"value".to_vec()));
+        // Nothing else writes the master page past the last version.
+        assert!(db.sync_masters().is_err());
+        assert!(db.compact_in_place().is_err());
+        assert_eq!(db.version(), VersionHolder::MAX_VERSION);
+        assert_eq!(db.try_get(b"key").unwrap(), Some(b"value".to_vec()));
+        drop(db);
+
+        // Compacting the file does not start the versions again.
+        let path = temp_file.path().to_str().unwrap();
+        assert!(matches!(
+            Db::compact_file(path, None, CompressorType::None),
+            Err(DigbyError::VersionOverflow)
+        ));
+        assert!(!std::path::Path::new(&format!("{}.compact", path)).exists());
+        let mut db = Db::new(path, None, CompressorType::None);
+        assert_eq!(db.version(), VersionHolder::MAX_VERSION);
+
+        // Restoring a dump into a new DB does.
+        let mut dump = Vec::new();
+        db.dump(&mut dump).expect("Failed to dump");
+        let new_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut new_db = Db::new(
+            new_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        Db::restore(dump.as_slice(), &mut new_db).expect("Failed to restore");
+        assert!(new_db.version() < 10);
+        assert_eq!(new_db.get(b"key"), Some(b"value".to_vec()));
+        new_db.put(b"key", b"new");
+        assert_eq!(new_db.get(b"key"), Some(b"new".to_vec()));
+        new_db.verify().expect("DB should verify");
+    }
+
+    // A SHA256 clash cannot be made so store a tuple for one oversized
+    // key under the short key of another. The other key then finds a
+    // different key in the overflow pages, as it would with a clash.
+    // Returns the new root of the tree.
+    fn store_clashing_short_key(
+        db: &mut Db,
+        key: &[u8],
+        other_key: &[u8],
+        tree_page_no: PageNo,
+        tx_ctx: &mut TxCtx,
+    ) -> PageNo {
+        // The clash has its own overflow chain, so it is not freed when
+        // key is updated.
+        let tuple = TupleProcessor::generate_tuple(
+            key,
+            b"clash",
+            &mut db.page_cache,
+            &mut tx_ctx.free_page_tracker,
+            tx_ctx.new_version,
+            &db.compressor,
+            &db.db_config,
+        );
+        let clash = Tuple::new_with_overflow(
+            &TupleProcessor::generate_short_key(other_key),
+            tuple.get_value(),
+            tx_ctx.new_version,
+            tuple.get_overflow(),
+        );
+        let page = db.page_cache.get_page(tree_page_no);
+        PageNo::from_u64(StoreTupleProcessor::store_tuple(
+            clash,
+            page,
+            &mut tx_ctx.free_page_tracker,
+            &mut db.page_cache,
+            tx_ctx.new_version,
+            &db.db_config,
+        ))
+    }
+
+    #[test]
+    fn test_db_hash_collision() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        let key = vec![1u8; 3000];
+        let mut other_key = key.clone();
+        other_key[2999] = 2;
+        db.put(&key, b"value");
+        let mut tx_ctx = db.new_transaction();
+        tx_ctx.global_root_page_no = store_clashing_short_key(
+            &mut db,
+            &key,
+            &other_key,
+            tx_ctx.global_root_page_no,
+            &mut tx_ctx,
+        );
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+
+        // The other key is not stored, putting it would replace key.
+        assert_eq!(db.get(&other_key), None);
+        assert!(matches!(
+            db.try_put(&other_key, b"other"),
+            Err(DigbyError::HashCollision)
+        ));
+        assert_eq!(db.get(&key), Some(b"value".to_vec()));
+        assert_eq!(db.get(&other_key), None);
+        // The key itself can still be updated.
+        db.try_put(&key, b"new").expect("Failed to put");
+        assert_eq!(db.get(&key), Some(b"new".to_vec()));
+        assert_eq!(db.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_db_hash_collision_table() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        let key = vec![1u8; 3000];
+        let mut other_key = key.clone();
+        other_key[2999] = 2;
+        db.put_table_entry(b"table", &key, b"value");
+        let root = db.get_table_tree_root(b"table").unwrap();
+        let mut tx_ctx = db.new_transaction();
+        let new_root = store_clashing_short_key(&mut db, &key, &other_key, root, &mut tx_ctx);
+        db.set_table_tree_root_txn(b"table", new_root, &mut tx_ctx);
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+
+        assert_eq!(db.get_table_entry(b"table", &other_key), None);
+        assert!(matches!(
+            db.try_put_table_entry(b"table", &other_key, b"other"),
+            Err(DigbyError::HashCollision)
+        ));
+        assert_eq!(db.get_table_entry(b"table", &key), Some(b"value".to_vec()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key collides with a different key already stored")]
+    fn test_db_hash_collision_put_panics() {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        let key = vec![1u8; 3000];
+        let mut other_key = key.clone();
+        other_key[2999] = 2;
+        db.put(&key, b"value");
+        let mut tx_ctx = db.new_transaction();
+        tx_ctx.global_root_page_no = store_clashing_short_key(
+            &mut db,
+            &key,
+            &other_key,
+            tx_ctx.global_root_page_no,
+            &mut tx_ctx,
+        );
+        db.commit(&mut tx_ctx).expect("Failed to commit");
+        db.put(&other_key, b"other");
+    }
 }
@@ -1,5 +1,8 @@
 use crate::block_sanity::BlockSanity;
 use crate::compressor::CompressorType;
+use crate::key_order::KeyOrder;
+use crate::nonce_mode::NonceMode;
+use crate::sync_mode::SyncMode;
 
 #[derive(Copy, Clone, Debug)]
 pub struct DbConfig {
@@ -10,6 +13,30 @@ pub struct DbConfig {
     pub block_sanity: BlockSanity,
     pub leaf_page_blk_exp: u8,
     pub dir_page_blk_exp: u8,
+    // If false the checksum is not checked when reading a page, only
+    // for trusted storage. Checksums are always written, and encryption
+    // is always checked as the page cannot be decrypted without it.
+    pub verify_checksums: bool,
+    // How commits sync the file, see SyncMode.
+    pub sync_mode: SyncMode,
+    // Values this size or larger go to overflow pages, 0 for the
+    // default for the page size. See TupleProcessor::overflow_threshold.
+    pub overflow_threshold: usize,
+    // How encrypted blocks get their nonce, see NonceMode.
+    pub nonce_mode: NonceMode,
+    // Set by the application when the DB is created and kept in the root
+    // page, 0 if not set. See DbOptions::application_id.
+    pub application_id: u32,
+    pub user_version: u32,
+    // The pages a new DB file is created with, the fixed pages and the
+    // rest as free pages. See DbOptions::initial_pages.
+    pub initial_pages: u64,
+    // The free pages added to the end of the file when a commit has used
+    // them all. See Db::set_free_page_growth.
+    pub free_page_growth: u64,
+    // The order of the keys in the pages, it must be the same each time
+    // the DB is opened. See KeyOrder.
+    pub key_order: KeyOrder,
 }
 
 impl DbConfig {
@@ -35,6 +62,15 @@ pub struct DbConfigBuilder {
     block_sanity: BlockSanity,
     leaf_page_blk_exp: u8,
     dir_page_blk_exp: u8,
+    verify_checksums: bool,
+    sync_mode: SyncMode,
+    overflow_threshold: usize,
+    nonce_mode: NonceMode,
+    application_id: u32,
+    user_version: u32,
+    initial_pages: u64,
+    free_page_growth: u64,
+    key_order: KeyOrder,
 }
 
 impl Default for DbConfigBuilder {
@@ -53,6 +89,15 @@ impl DbConfigBuilder {
             block_sanity: BlockSanity::XxH32Checksum,
             leaf_page_blk_exp: 0,
             dir_page_blk_exp: 0,
+            verify_checksums: true,
+            sync_mode: SyncMode::Data,
+            overflow_threshold: 0,
+            nonce_mode: NonceMode::Random,
+            application_id: 0,
+            user_version: 0,
+            initial_pages: 10,
+            free_page_growth: 8,
+            key_order: KeyOrder::Bytes,
         }
     }
 
@@ -92,6 +137,51 @@ impl DbConfigBuilder {
         self
     }
 
+    pub const fn verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    pub const fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    pub const fn overflow_threshold(mut self, overflow_threshold: usize) -> Self {
+        self.overflow_threshold = overflow_threshold;
+        self
+    }
+
+    pub const fn nonce_mode(mut self, nonce_mode: NonceMode) -> Self {
+        self.nonce_mode = nonce_mode;
+        self
+    }
+
+    pub const fn application_id(mut self, application_id: u32) -> Self {
+        self.application_id = application_id;
+        self
+    }
+
+    pub const fn user_version(mut self, user_version: u32) -> Self {
+        self.user_version = user_version;
+        self
+    }
+
+    pub const fn initial_pages(mut self, initial_pages: u64) -> Self {
+        self.initial_pages = initial_pages;
+        self
+    }
+
+    pub const fn free_page_growth(mut self, free_page_growth: u64) -> Self {
+        self.free_page_growth = free_page_growth;
+        self
+    }
+
+    pub const fn key_order(mut self, key_order: KeyOrder) -> Self {
+        self.key_order = key_order;
+        self
+    }
+
     pub const fn build(self) -> DbConfig {
         DbConfig {
             block_size: self.block_size,
@@ -101,6 +191,15 @@ impl DbConfigBuilder {
             block_sanity: self.block_sanity,
             leaf_page_blk_exp: self.leaf_page_blk_exp,
             dir_page_blk_exp: self.dir_page_blk_exp,
+            verify_checksums: self.verify_checksums,
+            sync_mode: self.sync_mode,
+            overflow_threshold: self.overflow_threshold,
+            nonce_mode: self.nonce_mode,
+            application_id: self.application_id,
+            user_version: self.user_version,
+            initial_pages: self.initial_pages,
+            free_page_growth: self.free_page_growth,
+            key_order: self.key_order,
         }
     }
 }
@@ -126,5 +225,38 @@ mod tests {
         assert!(matches!(config.compressor_type, CompressorType::LZ4));
         assert_eq!(config.leaf_page_blk_exp, 1);
         assert_eq!(config.dir_page_blk_exp, 2);
+        assert!(config.verify_checksums);
+
+        let config = DbConfig::builder().verify_checksums(false).build();
+        assert!(!config.verify_checksums);
+
+        assert_eq!(DbConfig::builder().build().sync_mode, SyncMode::Data);
+        let config = DbConfig::builder().sync_mode(SyncMode::Full).build();
+        assert_eq!(config.sync_mode, SyncMode::Full);
+
+        assert_eq!(DbConfig::builder().build().overflow_threshold, 0);
+        assert_eq!(DbConfig::builder().build().nonce_mode, NonceMode::Random);
+        let config = DbConfig::builder().nonce_mode(NonceMode::Derived).build();
+        assert_eq!(config.nonce_mode, NonceMode::Derived);
+        let config = DbConfig::builder().overflow_threshold(3000).build();
+        assert_eq!(config.overflow_threshold, 3000);
+        let config = DbConfig::builder()
+            .application_id(0x6469_6762)
+            .user_version(7)
+            .build();
+        assert_eq!(config.application_id, 0x6469_6762);
+        assert_eq!(config.user_version, 7);
+        assert_eq!(DbConfig::builder().build().application_id, 0);
+        assert_eq!(DbConfig::builder().build().initial_pages, 10);
+        let config = DbConfig::builder().initial_pages(1000).build();
+        assert_eq!(config.initial_pages, 1000);
+        assert_eq!(DbConfig::builder().build().free_page_growth, 8);
+        let config = DbConfig::builder().free_page_growth(256).build();
+        assert_eq!(config.free_page_growth, 256);
+        assert!(DbConfig::builder().build().key_order.is_bytes());
+        let config = DbConfig::builder()
+            .key_order(KeyOrder::Comparator(|a, b| b.cmp(a)))
+            .build();
+        assert!(!config.key_order.is_bytes());
     }
 }
@@ -9,6 +9,7 @@ use crate::page_no::PageNo;
 // | FreePageDir1 (8 bytes) | FreePageDir2 (8 bytes) | FreePageDir3 (8 bytes) |
 // | FreePageDir4 (8 bytes) | FreePageDir5 (8 bytes) | FreePageDir6 (8 bytes) |
 // | FreePageDir7 (8 bytes) | FreePageDir8 (8 bytes) | FreePageDir9 (8 bytes) |
+// | FreePageCount (8 bytes) |
 // Could have more FreePageDir in future.
 pub struct DbMasterPage {
     page: Page,
@@ -72,6 +73,10 @@ impl DbMasterPage {
         DbMasterPage { page }
     }
 
+    // The bytes used by the layout above, a page must be at least this
+    // large. See Db::MIN_PAGE_SIZE.
+    pub const SIZE: usize = 120;
+
     const GLOBAL_TREE_OFFSET: usize = 16;
     pub fn get_global_tree_root_page_no(&self) -> PageNo {
         self.get_pg_no_offset(DbMasterPage::GLOBAL_TREE_OFFSET)
@@ -102,6 +107,23 @@ impl DbMasterPage {
         self.set_pg_no_offset(DbMasterPage::TABLE_DIR_PAGE, page_no);
     }
 
+    // The number of pages in the free page directory, kept plus one so
+    // a master page written before the count was kept reads as None.
+    const FREE_PAGE_COUNT_OFFSET: usize = 112;
+    pub fn get_free_page_count(&self) -> Option<u64> {
+        let offset = DbMasterPage::FREE_PAGE_COUNT_OFFSET;
+        let bytes: [u8; 8] = self.page.get_page_bytes()[offset..offset + 8]
+            .try_into()
+            .unwrap();
+        u64::from_le_bytes(bytes).checked_sub(1)
+    }
+
+    pub fn set_free_page_count(&mut self, free_page_count: u64) {
+        let offset = DbMasterPage::FREE_PAGE_COUNT_OFFSET;
+        self.page.get_page_bytes_mut()[offset..offset + 8]
+            .copy_from_slice(&(free_page_count + 1).to_le_bytes());
+    }
+
     fn set_pg_no_offset(&mut self, offset: usize, value: PageNo) {
         self.page.get_page_bytes_mut()[offset..offset + 8].copy_from_slice(&value.get_bytes());
     }
@@ -147,6 +169,34 @@ mod tests {
         assert!(67 == master_page.get_free_page_dir_page_no(0).get_blk_offset());
         assert!(87 == master_page.get_global_tree_root_page_no().get_blk_offset());
         assert!(34 == master_page.get_table_dir_page_no().get_blk_offset());
+        assert_eq!(master_page.get_free_page_count(), None);
+        master_page.set_free_page_count(0);
+        assert_eq!(master_page.get_free_page_count(), Some(0));
+        master_page.set_free_page_count(1234);
+        assert_eq!(master_page.get_free_page_count(), Some(1234));
+        assert!(67 == master_page.get_free_page_dir_page_no(0).get_blk_offset());
+        assert!(0 == master_page.get_free_page_dir_page_no(9).get_blk_offset());
+    }
+
+    #[test]
+    fn test_large_page_numbers() {
+        let mut master_page = DbMasterPage::create_new(&DB_CONFIG, PageNo::from_u64(1), 1);
+        let large_offset = u64::from(u32::MAX) * 16;
+        master_page.set_free_page_dir_page_no(0, PageNo::new(0, large_offset));
+        master_page.set_global_tree_root_page_no(PageNo::new(0, large_offset + 1));
+        master_page.set_table_dir_page_no(PageNo::new(0, large_offset + 2));
+        assert_eq!(
+            master_page.get_free_page_dir_page_no(0).get_blk_offset(),
+            large_offset
+        );
+        assert_eq!(
+            master_page.get_global_tree_root_page_no().get_blk_offset(),
+            large_offset + 1
+        );
+        assert_eq!(
+            master_page.get_table_dir_page_no().get_blk_offset(),
+            large_offset + 2
+        );
     }
 
     #[test]
@@ -0,0 +1,305 @@
+use crate::Db;
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
+use crate::compressor::CompressorType;
+use crate::db_config::DbConfig;
+use crate::digby_error::DigbyError;
+use crate::key_order::{KeyComparator, KeyOrder};
+#[cfg(feature = "mmap")]
+use crate::mmap_storage::MmapStorage;
+use crate::nonce_mode::NonceMode;
+use crate::storage::Storage;
+use crate::sync_mode::SyncMode;
+use std::sync::Arc;
+
+// Options for opening a Db, an alternative to the Db::new family that
+// does not need every option passed. The defaults are those of Db::new
+// with no key and no compression:
+//
+//   let db = DbOptions::new().path("my.db").compressor(CompressorType::LZ4).open();
+//
+// The path is needed, the rest are optional. A read only Db is opened
+// with Db::open_read_only.
+#[derive(Clone, Debug)]
+pub struct DbOptions {
+    path: Option<String>,
+    encryption_key: Option<Vec<u8>>,
+    block_sanity: Option<BlockSanity>,
+    sanity_provider: Option<Arc<dyn BlockSanityProvider>>,
+    compressor_type: CompressorType,
+    block_size: usize,
+    read_only: bool,
+    sync_mode: SyncMode,
+    overflow_threshold: usize,
+    nonce_mode: NonceMode,
+    application_id: u32,
+    user_version: u32,
+    require_application_id: bool,
+    initial_pages: u64,
+    free_page_growth: u64,
+    verify_checksums: bool,
+    key_order: KeyOrder,
+    #[cfg(feature = "mmap")]
+    mmap: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DbOptions {
+    pub fn new() -> Self {
+        DbOptions {
+            path: None,
+            encryption_key: None,
+            block_sanity: None,
+            sanity_provider: None,
+            compressor_type: CompressorType::None,
+            block_size: Db::BLOCK_SIZE,
+            read_only: false,
+            sync_mode: SyncMode::Data,
+            overflow_threshold: 0,
+            nonce_mode: NonceMode::Random,
+            application_id: 0,
+            user_version: 0,
+            require_application_id: false,
+            initial_pages: Db::INITIAL_PAGES,
+            free_page_growth: Db::FREE_PAGE_GROWTH,
+            verify_checksums: true,
+            key_order: KeyOrder::Bytes,
+            #[cfg(feature = "mmap")]
+            mmap: false,
+        }
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    // Encrypt the DB with AES-128-GCM using the key rather than
+    // checksumming the blocks.
+    pub fn encryption_key(mut self, key: &[u8]) -> Self {
+        self.encryption_key = Some(key.to_vec());
+        self
+    }
+
+    // The cipher used with encryption_key, AES-128-GCM if not set, or
+    // without a key the checksum, xxhash_32 if not set. The DB must be
+    // opened with the block sanity it was created with.
+    pub fn block_sanity(mut self, block_sanity: BlockSanity) -> Self {
+        self.block_sanity = Some(block_sanity);
+        self
+    }
+
+    // Check blocks with a BlockSanityProvider of the application's own,
+    // for example an HMAC, rather than a block sanity of the crate. Its
+    // id must be from BlockSanity::FIRST_CUSTOM_ID and is kept in the
+    // root page, the DB must be opened with a provider with that id that
+    // checks blocks the same way. The encryption key, if there is one,
+    // is given to the provider. Replaces block_sanity. Db::open_auto
+    // and Db::compact_file only know the crate's block sanities.
+    pub fn sanity_provider(mut self, sanity_provider: Arc<dyn BlockSanityProvider>) -> Self {
+        assert!(
+            sanity_provider.get_id() >= BlockSanity::FIRST_CUSTOM_ID,
+            "A custom block sanity id must be from {}",
+            BlockSanity::FIRST_CUSTOM_ID
+        );
+        self.sanity_provider = Some(sanity_provider);
+        self
+    }
+
+    pub fn compressor(mut self, compressor_type: CompressorType) -> Self {
+        self.compressor_type = compressor_type;
+        self
+    }
+
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    // How encrypted blocks get their nonce, see NonceMode.
+    pub fn nonce_mode(mut self, nonce_mode: NonceMode) -> Self {
+        self.nonce_mode = nonce_mode;
+        self
+    }
+
+    // Values this size or larger are stored in overflow pages. Only used
+    // when the DB is created, it is kept in the file. Defaults to a
+    // quarter of a leaf page, see TupleProcessor::overflow_threshold.
+    pub fn overflow_threshold(mut self, overflow_threshold: usize) -> Self {
+        self.overflow_threshold = overflow_threshold;
+        self
+    }
+
+    // An id for the application that owns the DB, a magic number of its
+    // own, and the version of its schema. Only used when the DB is
+    // created, they are kept in the file, see Db::application_id and
+    // Db::user_version.
+    pub fn application_id(mut self, application_id: u32) -> Self {
+        self.application_id = application_id;
+        self
+    }
+
+    pub fn user_version(mut self, user_version: u32) -> Self {
+        self.user_version = user_version;
+        self
+    }
+
+    // Refuse to open a DB that was created with a different application
+    // id to the one given to application_id, an ApplicationIdMismatch
+    // error. A new DB gets the id so always opens.
+    pub fn require_application_id(mut self, require_application_id: bool) -> Self {
+        self.require_application_id = require_application_id;
+        self
+    }
+
+    // The pages a new DB file is made with, at least Db::FIXED_PAGES for
+    // the root, master, free page directory and tree root pages, the rest
+    // are free pages for the first writes. More saves growing the file a
+    // few pages at a time when a large DB is loaded, fewer keeps a small
+    // DB small. Only used when the DB is created, defaults to
+    // Db::INITIAL_PAGES.
+    pub fn initial_pages(mut self, initial_pages: u64) -> Self {
+        self.initial_pages = initial_pages;
+        self
+    }
+
+    // The free pages added to the file at a time, see
+    // Db::set_free_page_growth.
+    pub fn free_page_growth(mut self, free_page_growth: u64) -> Self {
+        self.free_page_growth = free_page_growth;
+        self
+    }
+
+    // Check the checksum of each page read, on by default. Off is for
+    // trusted local scratch DBs only, see Db::set_verify_checksums. The
+    // root and master pages are still checked as the DB is opened, and
+    // an encrypted DB is always checked as it is decrypted.
+    pub fn verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    // Keep the keys in the order of the comparator rather than byte
+    // order, see KeyOrder. The comparator is not kept in the file, the DB
+    // must be opened with the same comparator every time. Opening it
+    // with a different comparator, or without one, corrupts the
+    // ordering. Db::compact_file only knows byte order.
+    pub fn comparator(mut self, comparator: KeyComparator) -> Self {
+        self.key_order = KeyOrder::Comparator(comparator);
+        self
+    }
+
+    // Read the file through a memory map rather than a read for each
+    // block, see MmapStorage for what to know before using it.
+    #[cfg(feature = "mmap")]
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    pub fn open(self) -> Db {
+        self.try_open().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_open(self) -> Result<Db, DigbyError> {
+        let path = self.path.clone().expect("DbOptions needs a path.");
+        let block_sanity = self
+            .block_sanity
+            .unwrap_or(BlockSanity::for_key(self.encryption_key.is_some()));
+        let mut db = match (self.read_only, self.sanity_provider.clone()) {
+            (true, None) => {
+                let db_file = Db::open_read_only_file(&path)?;
+                Db::try_open_read_only_with_storage(
+                    self.storage(db_file)?,
+                    &path,
+                    self.encryption_key.clone(),
+                    self.compressor_type,
+                    self.block_size,
+                    block_sanity,
+                )?
+            }
+            (true, Some(sanity_provider)) => {
+                let db_file = Db::open_read_only_file(&path)?;
+                Db::try_open_read_only_with_sanity_provider(
+                    self.storage(db_file)?,
+                    &path,
+                    self.encryption_key.clone(),
+                    self.compressor_type,
+                    self.block_size,
+                    sanity_provider,
+                )?
+            }
+            (false, None) => {
+                let db_file = Db::open_locked_file(&path)?;
+                Db::try_new_with_config(
+                    self.storage(db_file)?,
+                    self.encryption_key.clone(),
+                    self.db_config(block_sanity),
+                )?
+            }
+            (false, Some(sanity_provider)) => {
+                let db_file = Db::open_locked_file(&path)?;
+                Db::try_new_with_sanity_provider(
+                    self.storage(db_file)?,
+                    self.encryption_key.clone(),
+                    self.db_config(block_sanity),
+                    sanity_provider,
+                )?
+            }
+        };
+        if self.require_application_id && db.application_id() != self.application_id {
+            return Err(DigbyError::ApplicationIdMismatch(
+                db.application_id(),
+                self.application_id,
+            ));
+        }
+        db.set_sync_mode(self.sync_mode);
+        db.set_free_page_growth(self.free_page_growth);
+        db.set_verify_checksums(self.verify_checksums);
+        db.set_key_order(self.key_order);
+        Ok(db)
+    }
+
+    // The config for a writer, a sanity provider replaces the block
+    // sanity in it.
+    fn db_config(&self, block_sanity: BlockSanity) -> DbConfig {
+        DbConfig::builder()
+            .block_size(self.block_size)
+            .compressor_type(self.compressor_type)
+            .block_sanity(block_sanity)
+            .overflow_threshold(self.overflow_threshold)
+            .nonce_mode(self.nonce_mode)
+            .application_id(self.application_id)
+            .user_version(self.user_version)
+            .initial_pages(self.initial_pages)
+            .key_order(self.key_order)
+            .build()
+    }
+
+    #[cfg(feature = "mmap")]
+    fn storage(&self, db_file: std::fs::File) -> Result<Box<dyn Storage>, DigbyError> {
+        if self.mmap {
+            return Ok(Box::new(MmapStorage::new(db_file)?));
+        }
+        Ok(Box::new(db_file))
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn storage(&self, db_file: std::fs::File) -> Result<Box<dyn Storage>, DigbyError> {
+        Ok(Box::new(db_file))
+    }
+}
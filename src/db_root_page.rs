@@ -9,7 +9,14 @@ use std::io::Cursor;
 
 // | Page No (8 bytes) | Version/Type (8 bytes) |
 // | Magic Number(u32) | DbVersionMajor (u16) | DbVersionMinor (u16) |
-// | Sanity (u8) | Compression (u8) |
+// | Sanity (u8) | Compression (u8) | Overflow Threshold (u32) |
+// | Application Id (u32) | User Version (u32) | Block Size (u32) |
+//
+// The overflow threshold is 0 in files created before it was stored,
+// they use the default for the page size. The application id and user
+// version are the application's own, like SQLite's pragmas of the same
+// names, 0 in files created before they were stored. The block size is
+// 0 in files created before it was stored.
 pub struct DbRootPage {
     page: Page,
 }
@@ -48,7 +55,8 @@ impl PageTrait for DbRootPage {
 impl DbRootPage {
     const MAGIC_NUMBER: u32 = 26061973;
     const VERSION_MAJOR: u16 = 0;
-    const VERSION_MINOR: u16 = 1;
+    // Version 0.2 stores the key lengths in leaf and dir pages as u16.
+    const VERSION_MINOR: u16 = 2;
 
     pub fn create_new(page_config: &DbConfig) -> Self {
         let mut db_root_page = DbRootPage {
@@ -77,6 +85,19 @@ impl DbRootPage {
         head_page
     }
 
+    // True if the page looks like a root page, used to check a guess
+    // at the block size of a DB file.
+    pub fn is_db_root_page(page: &Page) -> bool {
+        if page.try_get_type() != Some(PageType::DbRoot)
+            || page.get_page_number().get_blk_offset() != 0
+        {
+            return false;
+        }
+        let mut cursor = Cursor::new(page.get_page_bytes());
+        cursor.set_position(16);
+        cursor.read_u32::<LittleEndian>().unwrap() == Self::MAGIC_NUMBER
+    }
+
     pub fn get_magic_number(&self) -> u32 {
         let mut cursor = Cursor::new(self.page.get_page_bytes());
         cursor.set_position(16);
@@ -119,6 +140,13 @@ impl DbRootPage {
             .expect("Failed to write minor version number");
     }
 
+    // True if the DB file uses the same page format as this code. There
+    // is no upgrade from an older format.
+    pub fn is_current_format(&self) -> bool {
+        self.get_db_major_version() == Self::VERSION_MAJOR
+            && self.get_db_minor_version() == Self::VERSION_MINOR
+    }
+
     pub fn get_sanity_type(&self) -> BlockSanity {
         let mut cursor = Cursor::new(self.page.get_page_bytes());
         cursor.set_position(24);
@@ -146,6 +174,66 @@ impl DbRootPage {
             .write_u8(compression_type)
             .expect("Failed to write compression type");
     }
+
+    pub fn get_overflow_threshold(&self) -> u32 {
+        let mut cursor = Cursor::new(self.page.get_page_bytes());
+        cursor.set_position(26);
+        cursor.read_u32::<LittleEndian>().unwrap()
+    }
+
+    pub fn set_overflow_threshold(&mut self, overflow_threshold: u32) {
+        let mut cursor = Cursor::new(&mut self.page.get_page_bytes_mut()[..]);
+        cursor.set_position(26);
+        cursor
+            .write_u32::<LittleEndian>(overflow_threshold)
+            .expect("Failed to write overflow threshold");
+    }
+
+    pub fn get_application_id(&self) -> u32 {
+        let mut cursor = Cursor::new(self.page.get_page_bytes());
+        cursor.set_position(30);
+        cursor.read_u32::<LittleEndian>().unwrap()
+    }
+
+    pub fn set_application_id(&mut self, application_id: u32) {
+        let mut cursor = Cursor::new(&mut self.page.get_page_bytes_mut()[..]);
+        cursor.set_position(30);
+        cursor
+            .write_u32::<LittleEndian>(application_id)
+            .expect("Failed to write application id");
+    }
+
+    pub fn get_user_version(&self) -> u32 {
+        let mut cursor = Cursor::new(self.page.get_page_bytes());
+        cursor.set_position(34);
+        cursor.read_u32::<LittleEndian>().unwrap()
+    }
+
+    pub fn set_user_version(&mut self, user_version: u32) {
+        let mut cursor = Cursor::new(&mut self.page.get_page_bytes_mut()[..]);
+        cursor.set_position(34);
+        cursor
+            .write_u32::<LittleEndian>(user_version)
+            .expect("Failed to write user version");
+    }
+
+    // Where the block size is in the block, Db reads it before it knows
+    // the block size.
+    pub const BLOCK_SIZE_OFFSET: u64 = 38;
+
+    pub fn get_block_size(&self) -> u32 {
+        let mut cursor = Cursor::new(self.page.get_page_bytes());
+        cursor.set_position(Self::BLOCK_SIZE_OFFSET);
+        cursor.read_u32::<LittleEndian>().unwrap()
+    }
+
+    pub fn set_block_size(&mut self, block_size: u32) {
+        let mut cursor = Cursor::new(&mut self.page.get_page_bytes_mut()[..]);
+        cursor.set_position(Self::BLOCK_SIZE_OFFSET);
+        cursor
+            .write_u32::<LittleEndian>(block_size)
+            .expect("Failed to write block size");
+    }
 }
 
 #[cfg(test)]
@@ -168,7 +256,17 @@ mod tests {
         assert_eq!(root_page.get_magic_number(), DbRootPage::MAGIC_NUMBER);
         assert_eq!(root_page.get_db_major_version(), DbRootPage::VERSION_MAJOR);
         assert_eq!(root_page.get_db_minor_version(), DbRootPage::VERSION_MINOR);
+        assert!(root_page.is_current_format());
+        assert_eq!(root_page.get_overflow_threshold(), 0);
+        assert_eq!(root_page.get_application_id(), 0);
+        assert_eq!(root_page.get_user_version(), 0);
+        assert_eq!(root_page.get_block_size(), 0);
         assert_eq!(root_page.page.get_type(), PageType::DbRoot);
+        assert!(DbRootPage::is_db_root_page(&root_page.page));
+        assert!(!DbRootPage::is_db_root_page(&Page::create_new(
+            &page_config,
+            1
+        )));
     }
 
     #[test]
@@ -229,6 +327,8 @@ mod tests {
 
         let root_page = DbRootPage::from_page(page);
         assert_eq!(root_page.get_magic_number(), DbRootPage::MAGIC_NUMBER);
+        // No version written so an older format.
+        assert!(!root_page.is_current_format());
     }
 
     #[test]
@@ -279,6 +379,20 @@ mod tests {
         root_page.set_compression_type(1);
         assert_eq!(root_page.get_compression_type(), 1);
 
+        root_page.set_overflow_threshold(3000);
+        assert_eq!(root_page.get_overflow_threshold(), 3000);
+        assert_eq!(root_page.get_compression_type(), 1);
+
+        root_page.set_application_id(u32::MAX);
+        root_page.set_user_version(12);
+        assert_eq!(root_page.get_application_id(), u32::MAX);
+        assert_eq!(root_page.get_user_version(), 12);
+        assert_eq!(root_page.get_overflow_threshold(), 3000);
+
+        root_page.set_block_size(65536);
+        assert_eq!(root_page.get_block_size(), 65536);
+        assert_eq!(root_page.get_user_version(), 12);
+
         root_page.set_version(100);
         assert_eq!(root_page.get_version(), 100);
     }
@@ -0,0 +1,220 @@
+use crate::block_sanity::BlockSanity;
+use crate::corruption::Corruption;
+use crate::page_no::PageNo;
+use std::fmt;
+
+// Errors from the Result returning Db calls, try_new, try_get and so
+// on. The calls without try_ panic with the error's message.
+#[derive(Debug)]
+pub enum DigbyError {
+    // Opening, reading, writing or syncing the file failed.
+    Io(std::io::Error),
+    // The structure of the DB is wrong, see Corruption.
+    Corruption(Corruption),
+    // A block read does not match its checksum.
+    ChecksumMismatch(PageNo),
+    // A block read could not be decrypted, the key is wrong or the
+    // block is corrupt.
+    EncryptionMismatch(PageNo),
+    // The DB was created with a different compressor, or one this
+    // build does not have.
+    CompressorMismatch(String),
+    // The DB was created with a different block sanity, stored then
+    // requested. A different cipher usually fails to decrypt first.
+    SanityMismatch(BlockSanity, BlockSanity),
+    // The DB was created by an incompatible version of digby.
+    FormatMismatch(u16, u16),
+    // The DB has used every version number, Db::dump it and Db::restore
+    // it into a new DB.
+    VersionOverflow,
+    // A page a Snapshot reads has been reused by a later commit.
+    SnapshotExpired(PageNo),
+    // An oversized key has the same short key, prefix and SHA256, as a
+    // different key already stored.
+    HashCollision,
+    // Another Db, in this or another process, has the file open for
+    // writing.
+    Locked,
+    // The key or value is larger than the maximum, which is given.
+    KeyTooLarge(usize),
+    ValueTooLarge(usize),
+    // bulk_load needs the global tree to be empty.
+    NotEmpty,
+    // A value could not be serialized, or the bytes stored could not be
+    // deserialized as the type asked for, see Db::try_get_typed.
+    Serialization(String),
+    // A row of a CSV file given to Db::import_csv is malformed, or does
+    // not have the key and value columns, or they do not decode. The
+    // line is the one the row starts on, counting from 1.
+    Import { line: u64 },
+    // The DB was created by another application, the stored application
+    // id then the one expected. See DbOptions::require_application_id.
+    ApplicationIdMismatch(u32, u32),
+    // The block size is outside Db::MIN_BLOCK_SIZE to Db::MAX_BLOCK_SIZE,
+    // or too small for Db::MIN_PAGE_SIZE once the block sanity is taken.
+    InvalidBlockSize(usize),
+    // The DB was created with a different block size, stored then
+    // requested.
+    BlockSizeMismatch(usize, usize),
+}
+
+impl fmt::Display for DigbyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DigbyError::Io(error) => write!(f, "{}", error),
+            DigbyError::Corruption(corruption) => write!(f, "DB is corrupt, {}", corruption),
+            DigbyError::ChecksumMismatch(page_no) => write!(
+                f,
+                "Calculated checksum does not match stored checksum for page {}",
+                page_no.to_u64()
+            ),
+            DigbyError::EncryptionMismatch(page_no) => {
+                write!(f, "Failed to decrypt page {}", page_no.to_u64())
+            }
+            DigbyError::CompressorMismatch(message) => write!(f, "{}", message),
+            DigbyError::SanityMismatch(stored, requested) => write!(
+                f,
+                "Db block sanity mis-match, stored type is {:?}, requested type {:?}",
+                stored, requested
+            ),
+            DigbyError::FormatMismatch(major, minor) => write!(
+                f,
+                "Db format mis-match, stored version is {}.{}",
+                major, minor
+            ),
+            DigbyError::VersionOverflow => write!(f, "Db has run out of version numbers"),
+            DigbyError::SnapshotExpired(page_no) => write!(
+                f,
+                "Snapshot has expired, page {} has been reused",
+                page_no.to_u64()
+            ),
+            DigbyError::HashCollision => {
+                write!(f, "Key collides with a different key already stored")
+            }
+            DigbyError::Locked => write!(f, "Db file is locked by another Db"),
+            DigbyError::KeyTooLarge(max) => {
+                write!(f, "Key is larger than the maximum of {} bytes", max)
+            }
+            DigbyError::ValueTooLarge(max) => {
+                write!(f, "Value is larger than the maximum of {} bytes", max)
+            }
+            DigbyError::NotEmpty => write!(f, "Db is not empty"),
+            DigbyError::Serialization(message) => write!(f, "Serialization failed, {}", message),
+            DigbyError::Import { line } => write!(f, "Malformed CSV row at line {}", line),
+            DigbyError::InvalidBlockSize(block_size) => {
+                write!(f, "Block size {} is not supported", block_size)
+            }
+            DigbyError::BlockSizeMismatch(stored, requested) => write!(
+                f,
+                "Db block size mis-match, stored size is {}, requested size {}",
+                stored, requested
+            ),
+            DigbyError::ApplicationIdMismatch(stored, expected) => write!(
+                f,
+                "Db application id mis-match, stored id is {:#x}, expected {:#x}",
+                stored, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DigbyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DigbyError::Io(error) => Some(error),
+            DigbyError::Corruption(corruption) => Some(corruption),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for DigbyError {
+    fn from(error: std::io::Error) -> Self {
+        DigbyError::Io(error)
+    }
+}
+
+impl From<Corruption> for DigbyError {
+    fn from(corruption: Corruption) -> Self {
+        DigbyError::Corruption(corruption)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_digby_error_display() {
+        let page_no = PageNo::from_u64(7);
+        assert_eq!(
+            DigbyError::ChecksumMismatch(page_no).to_string(),
+            "Calculated checksum does not match stored checksum for page 7"
+        );
+        assert_eq!(
+            DigbyError::EncryptionMismatch(page_no).to_string(),
+            "Failed to decrypt page 7"
+        );
+        assert_eq!(
+            DigbyError::SanityMismatch(BlockSanity::Aes128Gcm, BlockSanity::ChaCha20Poly1305)
+                .to_string(),
+            "Db block sanity mis-match, stored type is Aes128Gcm, requested type ChaCha20Poly1305"
+        );
+        assert_eq!(
+            DigbyError::KeyTooLarge(100).to_string(),
+            "Key is larger than the maximum of 100 bytes"
+        );
+        assert_eq!(
+            DigbyError::ValueTooLarge(100).to_string(),
+            "Value is larger than the maximum of 100 bytes"
+        );
+        assert_eq!(
+            DigbyError::FormatMismatch(0, 1).to_string(),
+            "Db format mis-match, stored version is 0.1"
+        );
+        let error = DigbyError::from(Corruption::PageOutOfRange(page_no));
+        assert!(matches!(
+            error,
+            DigbyError::Corruption(Corruption::PageOutOfRange(_))
+        ));
+        assert!(error.source().is_some());
+        let error = DigbyError::from(std::io::Error::other("No space left"));
+        assert_eq!(error.to_string(), "No space left");
+        assert!(error.source().is_some());
+        assert!(DigbyError::VersionOverflow.source().is_none());
+        assert_eq!(
+            DigbyError::SnapshotExpired(page_no).to_string(),
+            "Snapshot has expired, page 7 has been reused"
+        );
+        assert_eq!(
+            DigbyError::HashCollision.to_string(),
+            "Key collides with a different key already stored"
+        );
+        assert_eq!(DigbyError::NotEmpty.to_string(), "Db is not empty");
+        assert_eq!(
+            DigbyError::Serialization("unexpected end of file".to_string()).to_string(),
+            "Serialization failed, unexpected end of file"
+        );
+        assert_eq!(
+            DigbyError::Import { line: 12 }.to_string(),
+            "Malformed CSV row at line 12"
+        );
+        assert_eq!(
+            DigbyError::ApplicationIdMismatch(0, 0x6469_6762).to_string(),
+            "Db application id mis-match, stored id is 0x0, expected 0x64696762"
+        );
+        assert_eq!(
+            DigbyError::InvalidBlockSize(64).to_string(),
+            "Block size 64 is not supported"
+        );
+        assert_eq!(
+            DigbyError::BlockSizeMismatch(4096, 1024).to_string(),
+            "Db block size mis-match, stored size is 4096, requested size 1024"
+        );
+        assert_eq!(
+            DigbyError::Locked.to_string(),
+            "Db file is locked by another Db"
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::key_order::KeyOrder;
 use crate::page::PageTrait;
 use crate::page::PageType;
 use crate::page_no::PageNo;
@@ -8,6 +9,7 @@ use std::cmp::Ordering;
 
 pub struct DirPage {
     page: Page,
+    key_order: KeyOrder,
 }
 
 impl PageTrait for DirPage {
@@ -39,14 +41,14 @@ impl PageTrait for DirPage {
 // The value in a DirPage is the page number of the child page so size is known.
 pub struct DirSlot {
     offset: u16,
-    key_len: u8,
+    key_len: u16,
 }
 
 // Header
 //
 // | Page No (8 bytes) | VersionHolder(8 bytes) | Entries(u16) | Free_Space(u16) |
-// | prefix_length (u8) | left_fence_key_offset (u16) | left_fence_key_size (u8)
-// | right_fence_key_offset (u16) | right_fence_key_size (u8) |
+// | prefix_length (u16) | left_fence_key_offset (u16) | left_fence_key_size (u16)
+// | right_fence_key_offset (u16) | right_fence_key_size (u16) |
 // | page_to_the_left (u64) |
 // | slot | slot | slot | ...
 // | free space
@@ -61,6 +63,10 @@ pub struct DirSlot {
 // the actual keys and their associated page numbers that grow up and finally
 // the fence keys for the dir_page if it has any.
 //
+// As in leaf_page the slots are kept sorted in ascending order of key, a key is
+// added by shifting the slots after it along, so finding the child page for a
+// key is a binary search of the slots and both page types are O(log n) to search.
+//
 // If the page has n keys then it holds n+1 references to pages. The left (or smallest) most
 // page it references is stored in "page_to_the_left". If the client has a key that is smaller
 // than the lowest key in the page then goes to this page, the "page_to_the_left".
@@ -87,6 +93,9 @@ pub struct DirSlot {
 // for the for the new page to the right can be "aec". Anything less than "aec" will go to
 // the page to the left and we do not need to store the "d" in the diectory node.
 //
+// Keys are compared in the KeyOrder of the DB. With a comparator the keys are not in byte
+// order so there is no head compression and the child keys are stored whole.
+//
 // For the page cache we want to be able to return immutable shared references to pages to
 // clients who are read only, ie just using get. While for clients who want to add or delete
 // tuples we need to provide a mutable version of the page, we provide a copy.
@@ -100,9 +109,9 @@ pub struct DirSlot {
 // get_no_entries_in_page which takes &Page. get_no_entries_in_page should be used by
 // read only clients providing a reference is a base Page.
 impl DirPage {
-    const HEADER_SIZE: usize = 35; // 8 + 8 + 2 + 2 + 1 + 2 + 1 + 2 + 1 + 8
+    const HEADER_SIZE: usize = 38; // 8 + 8 + 2 + 2 + 2 + 2 + 2 + 2 + 2 + 8
     const VALUE_SIZE: usize = 8; // u64 page number of child page
-    const SLOT_SIZE: usize = 3; // 2 (offset) + 1 (key_len)
+    const SLOT_SIZE: usize = 4; // 2 (offset) + 2 (key_len)
 
     pub fn create_new(page_config: &DbConfig, page_number: PageNo, version: u64) -> Self {
         if page_number.get_blk_offset() != 0 {
@@ -114,7 +123,10 @@ impl DirPage {
         let mut page = Page::create_new(page_config, page_config.get_dir_page_blk_cnt());
         page.set_type(PageType::DirPage);
         page.set_page_number(page_number);
-        let mut dir_page = DirPage { page };
+        let mut dir_page = DirPage {
+            page,
+            key_order: page_config.key_order,
+        };
         dir_page.set_free_space(dir_page.page.get_pg_size() as u16 - DirPage::HEADER_SIZE as u16);
         dir_page.set_version(version);
         dir_page.set_page_to_left(PageNo::from_u64(0));
@@ -125,11 +137,11 @@ impl DirPage {
         dir_page
     }
 
-    pub fn from_page(page: Page) -> Self {
+    pub fn from_page(page: Page, key_order: KeyOrder) -> Self {
         if page.get_type() != PageType::DirPage {
             panic!("Page type is not DirPage");
         }
-        DirPage { page }
+        DirPage { page, key_order }
     }
 
     // Used when rebuilding page when resetting fences.
@@ -156,7 +168,7 @@ impl DirPage {
         self.page.get_page_bytes_mut()[16..18].copy_from_slice(&bytes);
     }
 
-    fn get_free_space(&self) -> u16 {
+    pub fn get_free_space(&self) -> u16 {
         let bytes = &self.page.get_page_bytes()[18..20];
         u16::from_le_bytes(bytes.try_into().unwrap())
     }
@@ -166,24 +178,43 @@ impl DirPage {
         self.page.get_page_bytes_mut()[18..20].copy_from_slice(&bytes);
     }
 
-    fn get_prefix_length_page(page: &Page) -> u8 {
-        page.get_page_bytes()[20]
+    // Take size bytes from the free space. Panics rather than letting the
+    // u16 wrap if there is not enough space, a wrapped free space would
+    // corrupt the page.
+    fn reduce_free_space(&mut self, size: usize) {
+        let free_space = u16::try_from(size)
+            .ok()
+            .and_then(|size| self.get_free_space().checked_sub(size))
+            .expect("Not enough free space in page.");
+        self.set_free_space(free_space);
+    }
+
+    // Give size bytes back to the free space.
+    fn increase_free_space(&mut self, size: usize) {
+        let free_space = u16::try_from(self.get_free_space() as usize + size)
+            .expect("Free space larger than page.");
+        self.set_free_space(free_space);
+    }
+
+    fn get_prefix_length_page(page: &Page) -> u16 {
+        let bytes = &page.get_page_bytes()[20..22];
+        u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
-    fn get_prefix_length(&self) -> u8 {
+    fn get_prefix_length(&self) -> u16 {
         Self::get_prefix_length_page(&self.page)
     }
 
     pub fn get_page_to_left_page(page: &Page) -> PageNo {
-        PageNo::from_bytes(&page.get_page_bytes()[27..35])
+        PageNo::from_bytes(&page.get_page_bytes()[30..38])
     }
 
     pub fn get_page_to_left(&self) -> PageNo {
         Self::get_page_to_left_page(&self.page)
     }
 
-    fn set_page_to_left(&mut self, page_no: PageNo) {
-        self.page.get_page_bytes_mut()[27..35].copy_from_slice(&page_no.get_bytes());
+    pub fn set_page_to_left(&mut self, page_no: PageNo) {
+        self.page.get_page_bytes_mut()[30..38].copy_from_slice(&page_no.get_bytes());
     }
 
     pub fn get_dir_left_key(&self) -> Option<Vec<u8>> {
@@ -202,17 +233,18 @@ impl DirPage {
             self.get_entries_size() == 0,
             "Cannot set left fence key on a page that already has entries."
         );
-        let free_space = self.get_free_space() as usize;
-        let offset = self.calculate_entries_offset() - key.len();
+        let entries_offset = self.calculate_entries_offset();
+        self.reduce_free_space(key.len());
+        let offset = entries_offset - key.len();
         self.page.get_page_bytes_mut()[offset..offset + key.len()].copy_from_slice(key);
         let offset_bytes = (offset as u16).to_le_bytes();
-        self.page.get_page_bytes_mut()[21..23].copy_from_slice(&offset_bytes);
-        self.page.get_page_bytes_mut()[23] = key.len() as u8;
-        self.set_free_space(free_space as u16 - key.len() as u16);
+        self.page.get_page_bytes_mut()[22..24].copy_from_slice(&offset_bytes);
+        let size_bytes = (key.len() as u16).to_le_bytes();
+        self.page.get_page_bytes_mut()[24..26].copy_from_slice(&size_bytes);
     }
 
     pub fn has_left_fence_page(page: &Page) -> bool {
-        page.get_page_bytes()[23] != 0
+        Self::get_left_fence_key_size_page(page) != 0
     }
 
     pub fn has_left_fence(&self) -> bool {
@@ -220,16 +252,16 @@ impl DirPage {
     }
 
     fn clear_left_fence_key(&mut self) {
-        self.page.get_page_bytes_mut()[23] = 0;
-        self.page.get_page_bytes_mut()[21..23].copy_from_slice(&[0, 0]);
+        self.page.get_page_bytes_mut()[22..26].copy_from_slice(&[0; 4]);
     }
 
-    fn get_left_fence_key_size_page(page: &Page) -> u8 {
-        page.get_page_bytes()[23]
+    fn get_left_fence_key_size_page(page: &Page) -> u16 {
+        let bytes = &page.get_page_bytes()[24..26];
+        u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
     fn get_left_fence_key_offset_page(page: &Page) -> u16 {
-        let bytes = &page.get_page_bytes()[21..23];
+        let bytes = &page.get_page_bytes()[22..24];
         u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
@@ -248,17 +280,18 @@ impl DirPage {
             self.get_entries_size() == 0,
             "Cannot set right fence key on a page that already has entries."
         );
-        let free_space = self.get_free_space() as usize;
-        let offset = self.calculate_entries_offset() - key.len();
+        let entries_offset = self.calculate_entries_offset();
+        self.reduce_free_space(key.len());
+        let offset = entries_offset - key.len();
         self.page.get_page_bytes_mut()[offset..offset + key.len()].copy_from_slice(key);
         let offset_bytes = (offset as u16).to_le_bytes();
-        self.page.get_page_bytes_mut()[24..26].copy_from_slice(&offset_bytes);
-        self.page.get_page_bytes_mut()[26] = key.len() as u8;
-        self.set_free_space(free_space as u16 - key.len() as u16);
+        self.page.get_page_bytes_mut()[26..28].copy_from_slice(&offset_bytes);
+        let size_bytes = (key.len() as u16).to_le_bytes();
+        self.page.get_page_bytes_mut()[28..30].copy_from_slice(&size_bytes);
     }
 
     pub fn has_right_fence_page(page: &Page) -> bool {
-        page.get_page_bytes()[26] != 0
+        Self::get_right_fence_key_size_page(page) != 0
     }
 
     pub fn has_right_fence(&self) -> bool {
@@ -266,20 +299,20 @@ impl DirPage {
     }
 
     fn clear_right_fence_key(&mut self) {
-        self.page.get_page_bytes_mut()[26] = 0;
-        self.page.get_page_bytes_mut()[24..26].copy_from_slice(&[0, 0]);
+        self.page.get_page_bytes_mut()[26..30].copy_from_slice(&[0; 4]);
     }
 
     fn get_right_fence_key_offset_page(page: &Page) -> u16 {
-        let bytes = &page.get_page_bytes()[24..26];
+        let bytes = &page.get_page_bytes()[26..28];
         u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
-    fn get_right_fence_key_size_page(page: &Page) -> u8 {
-        page.get_page_bytes()[26]
+    fn get_right_fence_key_size_page(page: &Page) -> u16 {
+        let bytes = &page.get_page_bytes()[28..30];
+        u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
-    fn get_right_fence_key_size(&self) -> u8 {
+    fn get_right_fence_key_size(&self) -> u16 {
         Self::get_right_fence_key_size_page(&self.page)
     }
 
@@ -293,7 +326,7 @@ impl DirPage {
         Self::get_right_fence_key_page(&self.page)
     }
 
-    pub fn set_prefix_length(&mut self, prefix_length: u8) {
+    pub fn set_prefix_length(&mut self, prefix_length: u16) {
         assert!(
             self.get_entries_size() == 0,
             "Cannot set prefix length on a page that already has entries."
@@ -302,7 +335,7 @@ impl DirPage {
             prefix_length <= self.get_right_fence_key_size(),
             "Prefix length cannot be larger than the right fence key size."
         );
-        self.page.get_page_bytes_mut()[20] = prefix_length;
+        self.page.get_page_bytes_mut()[20..22].copy_from_slice(&prefix_length.to_le_bytes());
     }
 
     fn get_slot_at_index_page(page: &Page, index: usize) -> DirSlot {
@@ -310,7 +343,8 @@ impl DirPage {
         let slot_offset = DirPage::HEADER_SIZE + index * DirPage::SLOT_SIZE;
         let offset_bytes = &page.get_page_bytes()[slot_offset..slot_offset + 2];
         let offset = u16::from_le_bytes(offset_bytes.try_into().unwrap());
-        let key_len = page.get_page_bytes()[slot_offset + 2];
+        let key_len_bytes = &page.get_page_bytes()[slot_offset + 2..slot_offset + 4];
+        let key_len = u16::from_le_bytes(key_len_bytes.try_into().unwrap());
         DirSlot { offset, key_len }
     }
 
@@ -322,11 +356,13 @@ impl DirPage {
         let slot_offset = DirPage::HEADER_SIZE + index * DirPage::SLOT_SIZE;
         let offset_bytes = slot.offset.to_le_bytes();
         self.page.get_page_bytes_mut()[slot_offset..slot_offset + 2].copy_from_slice(&offset_bytes);
-        self.page.get_page_bytes_mut()[slot_offset + 2] = slot.key_len;
+        let key_len_bytes = slot.key_len.to_le_bytes();
+        self.page.get_page_bytes_mut()[slot_offset + 2..slot_offset + 4]
+            .copy_from_slice(&key_len_bytes);
     }
 
     fn get_value_at_slot_page<'a>(page: &'a Page, slot: &DirSlot) -> &'a [u8] {
-        let val_offset = (slot.offset + slot.key_len as u16) as usize;
+        let val_offset = (slot.offset + slot.key_len) as usize;
         &page.get_page_bytes()[val_offset..val_offset + DirPage::VALUE_SIZE]
     }
 
@@ -355,7 +391,11 @@ impl DirPage {
         Self::get_key_prefix_page(&self.page)
     }
 
-    fn get_index_for_key_page(page: &Page, key_suffix: &[u8]) -> (bool, usize) {
+    fn get_index_for_key_page(
+        page: &Page,
+        key_suffix: &[u8],
+        key_order: KeyOrder,
+    ) -> (bool, usize) {
         let entries = Self::get_no_entries_in_page(page) as usize;
 
         // binary search for the key suffix in the slots
@@ -367,7 +407,7 @@ impl DirPage {
             let slot = Self::get_slot_at_index_page(page, mid);
             let key_at_slot = Self::get_key_at_slot_page(page, &slot);
 
-            match key_suffix.cmp(key_at_slot) {
+            match key_order.compare(key_suffix, key_at_slot) {
                 Ordering::Less => high = mid, // Needle is smaller, look in the left half
                 Ordering::Equal => return (true, mid),
                 Ordering::Greater => low = mid + 1, // Needle is larger, look in the right half
@@ -377,8 +417,12 @@ impl DirPage {
         (false, low)
     }
 
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.key_order.compare(a, b)
+    }
+
     fn get_index_for_key(&self, key_suffix: &[u8]) -> (bool, usize) {
-        Self::get_index_for_key_page(&self.page, key_suffix)
+        Self::get_index_for_key_page(&self.page, key_suffix, self.key_order)
     }
 
     fn shift_slots_right_from(&mut self, from_index: usize) {
@@ -407,6 +451,25 @@ impl DirPage {
     //   not be an exact match)
     // - if a child page has split then we need to update on entry and add a new entry.
     // This function is to update an existing key with a new page number.
+    // A child page has moved without changing, swap the old page number
+    // for the new one.
+    pub fn replace_child_page(&mut self, old_page_no: PageNo, new_page_no: PageNo) {
+        if self.get_page_to_left() == old_page_no {
+            self.set_page_to_left(new_page_no);
+            return;
+        }
+        for index in 0..self.get_entries_size() as usize {
+            if self.get_page_no_at_index(index) == old_page_no {
+                let slot = self.get_slot_at_index(index);
+                let val_offset = (slot.offset + slot.key_len) as usize;
+                self.page.get_page_bytes_mut()[val_offset..val_offset + DirPage::VALUE_SIZE]
+                    .copy_from_slice(&new_page_no.get_bytes());
+                return;
+            }
+        }
+        panic!("BUG: Child page {:?} not in dir page.", old_page_no);
+    }
+
     fn update_child_page_no(&mut self, key: &[u8], page_no: u64) {
         let entries = self.get_entries_size() as usize;
         // Page empty - we can just add the page number as the left most page and return.
@@ -418,14 +481,16 @@ impl DirPage {
         // Sanity check - we are updating an entry in this dir page
         // as we have just updated a child page. This means we have just
         // found the old child page reference when looking for the leaf page
-        if self.has_left_fence() && key < self.get_left_fence_key() {
+        if self.has_left_fence() && self.compare(key, self.get_left_fence_key()) == Ordering::Less {
             self.set_page_to_left(PageNo::from_u64(page_no));
             return;
         }
 
-        if self.has_right_fence() && key > self.get_right_fence_key() {
+        if self.has_right_fence()
+            && self.compare(key, self.get_right_fence_key()) == Ordering::Greater
+        {
             let slot_to_update = self.get_slot_at_index(entries - 1);
-            let val_offset = (slot_to_update.offset + slot_to_update.key_len as u16) as usize;
+            let val_offset = (slot_to_update.offset + slot_to_update.key_len) as usize;
             let val_bytes = page_no.to_le_bytes();
             self.page.get_page_bytes_mut()[val_offset..val_offset + DirPage::VALUE_SIZE]
                 .copy_from_slice(&val_bytes);
@@ -446,7 +511,7 @@ impl DirPage {
 
         // Get first key and check if the key belongs to the left most page.
         let slot = self.get_slot_at_index(0);
-        if key_suffix < self.get_key_at_slot(&slot) {
+        if self.compare(key_suffix, self.get_key_at_slot(&slot)) == Ordering::Less {
             // The key belongs to the left most page. We just need to update the page number for the left most page.
             self.set_page_to_left(PageNo::from_u64(page_no));
             return;
@@ -456,7 +521,7 @@ impl DirPage {
         let (found, index) = self.get_index_for_key(key_suffix);
         let index_to_update = if found { index } else { index - 1 };
         let slot_to_update = self.get_slot_at_index(index_to_update);
-        let val_offset = (slot_to_update.offset + slot_to_update.key_len as u16) as usize;
+        let val_offset = (slot_to_update.offset + slot_to_update.key_len) as usize;
         let val_bytes = page_no.to_le_bytes();
         self.page.get_page_bytes_mut()[val_offset..val_offset + DirPage::VALUE_SIZE]
             .copy_from_slice(&val_bytes);
@@ -477,11 +542,7 @@ impl DirPage {
         let left_fence = self.get_left_fence_key().to_vec();
         let prefix_length: usize = if old_prefix_length > 0 {
             // Only set compression if it was already set.
-            left_fence
-                .iter()
-                .zip(new_right_fence)
-                .take_while(|(a, b)| a == b)
-                .count()
+            self.key_order.prefix_length(&left_fence, new_right_fence)
         } else {
             0
         };
@@ -490,7 +551,7 @@ impl DirPage {
         self.reset(self.get_pg_size());
         self.set_left_fence_key(left_fence.as_ref());
         self.set_right_fence_key(new_right_fence);
-        self.set_prefix_length(prefix_length as u8);
+        self.set_prefix_length(prefix_length as u16);
         for tuple in entries {
             let ok = self.add_child_page(tuple.0.as_ref(), tuple.1);
             if !ok {
@@ -512,11 +573,7 @@ impl DirPage {
         let right_fence = self.get_right_fence_key().to_vec();
         let prefix_length: usize = if old_prefix_length > 0 {
             // Only set compression if it was already set.
-            new_left_fence
-                .iter()
-                .zip(right_fence.as_slice())
-                .take_while(|(a, b)| a == b)
-                .count()
+            self.key_order.prefix_length(new_left_fence, &right_fence)
         } else {
             0
         };
@@ -525,7 +582,7 @@ impl DirPage {
         self.reset(self.get_pg_size());
         self.set_left_fence_key(new_left_fence);
         self.set_right_fence_key(right_fence.as_ref());
-        self.set_prefix_length(prefix_length as u8);
+        self.set_prefix_length(prefix_length as u16);
         for tuple in entries {
             let ok = self.add_child_page(tuple.0.as_ref(), tuple.1);
             if !ok {
@@ -540,8 +597,8 @@ impl DirPage {
 
     // Called when a child page has split and we need to add a
     // new entry for the new page in the dir_page.
-    fn add_child_page(&mut self, key: &[u8], page_no: u64) -> bool {
-        if self.has_left_fence() && key < self.get_left_fence_key() {
+    pub fn add_child_page(&mut self, key: &[u8], page_no: u64) -> bool {
+        if self.has_left_fence() && self.compare(key, self.get_left_fence_key()) == Ordering::Less {
             if !self.reset_with_new_left_fence(key) {
                 // Reset failed as cannot rebuild same page with new compression as not enough space.
                 // Trigger a split first.
@@ -551,7 +608,9 @@ impl DirPage {
             return self.add_child_page(key, page_no);
         }
 
-        if self.has_right_fence() && key > self.get_right_fence_key() {
+        if self.has_right_fence()
+            && self.compare(key, self.get_right_fence_key()) == Ordering::Greater
+        {
             if !self.reset_with_new_right_fence(key) {
                 // Reset failed as cannot rebuild same page with new compression as not enough space.
                 // Trigger a split first.
@@ -621,8 +680,10 @@ impl DirPage {
         // Sanity check
         let new_entry_size = key.len() + value.len();
         let new_entry_total_size = new_entry_size + DirPage::SLOT_SIZE;
-        let free_space = self.get_free_space() as usize;
-        assert!(new_entry_total_size <= free_space);
+        assert!(
+            new_entry_total_size <= self.get_free_space() as usize,
+            "Not enough free space in page."
+        );
 
         // Find offset where the key/value entry can be added.
         let entries = self.get_entries_size() as usize;
@@ -639,14 +700,14 @@ impl DirPage {
         // Create a slot and add it.
         let slot = DirSlot {
             offset: new_entry_offset as u16,
-            key_len: key.len() as u8,
+            key_len: key.len() as u16,
         };
         self.shift_slots_right_from(index);
         self.set_slot_at_index(index, slot);
 
         // Update entries and free space.
         self.set_entries_size((entries + 1) as u16);
-        self.set_free_space(free_space as u16 - new_entry_total_size as u16);
+        self.reduce_free_space(new_entry_total_size);
     }
 
     fn get_page_no_at_index_page(page: &Page, index: usize) -> PageNo {
@@ -658,6 +719,16 @@ impl DirPage {
         Self::get_page_no_at_index_page(&self.page, index)
     }
 
+    // The child page with the largest keys, the page of the last entry
+    // or the page to the left if there are no entries.
+    pub fn get_last_child_page(&self) -> PageNo {
+        let entries = self.get_entries_size() as usize;
+        if entries == 0 {
+            return self.get_page_to_left();
+        }
+        self.get_page_no_at_index(entries - 1)
+    }
+
     pub fn get_key_suffix_and_value_at_index(&self, index: usize) -> (&[u8], &[u8]) {
         let slot = self.get_slot_at_index(index);
         let key = self.get_key_at_slot(&slot);
@@ -771,19 +842,16 @@ impl DirPage {
         let right_lowest_key = self.get_key_at_index(mid + 1);
         let right_fence_right_key = self.get_key_at_index(entries - 1);
         assert!(
-            right_fence_right_key > right_lowest_key,
+            self.compare(&right_fence_right_key, &right_lowest_key) == Ordering::Greater,
             "BUG: Right page right fence key is not greater than right page left fence key."
         );
-        let right_prefix_length = right_lowest_key
-            .as_slice()
-            .iter()
-            .zip(right_fence_right_key.as_slice())
-            .take_while(|(a, b)| a == b)
-            .count();
+        let right_prefix_length = self
+            .key_order
+            .prefix_length(&right_lowest_key, &right_fence_right_key);
         right_page.set_page_to_left(self.get_page_no_at_index(mid));
         right_page.set_left_fence_key(right_lowest_key.as_ref());
         right_page.set_right_fence_key(right_fence_right_key.as_ref());
-        right_page.set_prefix_length(right_prefix_length as u8);
+        right_page.set_prefix_length(right_prefix_length as u16);
         for i in (mid + 1)..entries {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             // Use the prefix length to only store the key suffix.
@@ -815,18 +883,16 @@ impl DirPage {
         let mid_key = self.get_key_suffix_at_index(mid);
         let left_page_right_fence_key = self.get_key_suffix_at_index(mid - 1);
         assert!(
-            left_page_right_fence_key > low_key,
+            self.compare(left_page_right_fence_key, low_key) == Ordering::Greater,
             "BUG: Left page right fence key is not greater than left page left fence key."
         );
         left_page.set_page_to_left(self.get_page_to_left());
         left_page.set_left_fence_key(low_key);
         left_page.set_right_fence_key(left_page_right_fence_key);
-        let left_prefix_length = low_key
-            .iter()
-            .zip(left_page_right_fence_key)
-            .take_while(|(a, b)| a == b)
-            .count();
-        left_page.set_prefix_length(left_prefix_length as u8);
+        let left_prefix_length = self
+            .key_order
+            .prefix_length(low_key, left_page_right_fence_key);
+        left_page.set_prefix_length(left_prefix_length as u16);
         for i in 0..mid {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             // Use the prefix length to only store the key suffix.
@@ -865,17 +931,15 @@ impl DirPage {
         left_page.set_left_fence_key(&low_key);
         left_page.set_right_fence_key(&left_page_right_fence_key);
         assert!(
-            left_page_right_fence_key > low_key,
+            self.compare(&left_page_right_fence_key, &low_key) == Ordering::Greater,
             "BUG:Left page right fence key is not greater than left page left fence key."
         );
-        let left_prefix_length = low_key
-            .iter()
-            .zip(left_page_right_fence_key.as_slice())
-            .take_while(|(a, b)| a == b)
-            .count();
+        let left_prefix_length = self
+            .key_order
+            .prefix_length(&low_key, &left_page_right_fence_key);
         // The offset of the suffix in the key is the prefix length of the page.
         let left_prefix_offset = left_prefix_length - self.get_prefix_length() as usize;
-        left_page.set_prefix_length(left_prefix_length as u8);
+        left_page.set_prefix_length(left_prefix_length as u16);
         for i in 0..mid {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             // This should avoid moving bytes around - we will be appending slots.
@@ -884,20 +948,18 @@ impl DirPage {
 
         let right_page_low_key = self.get_key_at_index(mid + 1);
         let right_page_high_key = self.get_key_at_index(entries - 1);
-        let right_prefix_length = right_page_low_key
-            .iter()
-            .zip(right_page_high_key.as_slice())
-            .take_while(|(a, b)| a == b)
-            .count();
+        let right_prefix_length = self
+            .key_order
+            .prefix_length(&right_page_low_key, &right_page_high_key);
         let right_suffix_offset = right_prefix_length - self.get_prefix_length() as usize;
         right_page.set_page_to_left(self.get_page_no_at_index(mid));
         right_page.set_left_fence_key(right_page_low_key.as_slice());
         right_page.set_right_fence_key(&right_page_high_key);
         assert!(
-            right_page_low_key < right_page_high_key,
+            self.compare(&right_page_low_key, &right_page_high_key) == Ordering::Less,
             "BUG: Right page left fence key is not less than right page right fence key."
         );
-        right_page.set_prefix_length(right_prefix_length as u8);
+        right_page.set_prefix_length(right_prefix_length as u16);
         for i in (mid + 1)..entries {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             right_page.add_key_value_at_index(i - (mid + 1), &key[right_suffix_offset..], value);
@@ -966,7 +1028,7 @@ impl DirPage {
             // No Head, just shift the tail to the left.
             // If the entry to remove is the last entry, we can just update the free
             // space and entries without shifting.
-            self.set_free_space((free_space + entry_size + DirPage::SLOT_SIZE) as u16);
+            self.increase_free_space(entry_size + DirPage::SLOT_SIZE);
             self.set_entries_size((new_entry_count) as u16);
             return;
         }
@@ -996,25 +1058,29 @@ impl DirPage {
 
         // Update entries and free space.
         self.set_entries_size(new_entry_count as u16);
-        self.set_free_space((free_space + entry_size + DirPage::SLOT_SIZE) as u16);
+        self.increase_free_space(entry_size + DirPage::SLOT_SIZE);
     }
 
     pub fn get_next(&self, key: &[u8]) -> PageNo {
-        Self::get_next_page(&self.page, key)
+        Self::get_next_page(&self.page, key, self.key_order)
     }
 
-    pub fn get_next_page(page: &Page, key: &[u8]) -> PageNo {
+    pub fn get_next_page(page: &Page, key: &[u8], key_order: KeyOrder) -> PageNo {
         // There is only the page to the left.
         let entries = Self::get_no_entries_in_page(page);
         if entries == 0 {
             return Self::get_page_to_left_page(page);
         }
 
-        if Self::has_left_fence_page(page) && key < Self::get_left_fence_key_page(page) {
+        if Self::has_left_fence_page(page)
+            && key_order.compare(key, Self::get_left_fence_key_page(page)) == Ordering::Less
+        {
             return Self::get_page_to_left_page(page);
         }
 
-        if Self::has_right_fence_page(page) && key > Self::get_right_fence_key_page(page) {
+        if Self::has_right_fence_page(page)
+            && key_order.compare(key, Self::get_right_fence_key_page(page)) == Ordering::Greater
+        {
             return Self::get_page_no_at_index_page(page, entries as usize - 1);
         }
 
@@ -1035,17 +1101,17 @@ impl DirPage {
 
         let slot = Self::get_slot_at_index_page(page, 0);
         let first_key = Self::get_key_at_slot_page(page, &slot);
-        if key_suffix < first_key {
+        if key_order.compare(key_suffix, first_key) == Ordering::Less {
             return Self::get_page_to_left_page(page);
         }
 
         let last_entry = Self::get_slot_at_index_page(page, entries as usize - 1);
         let last_key = Self::get_key_at_slot_page(page, &last_entry);
-        if key_suffix > last_key {
+        if key_order.compare(key_suffix, last_key) == Ordering::Greater {
             return Self::get_page_no_at_index_page(page, entries as usize - 1);
         }
 
-        let (found, index) = Self::get_index_for_key_page(page, key_suffix);
+        let (found, index) = Self::get_index_for_key_page(page, key_suffix, key_order);
         if found {
             Self::get_page_no_at_index_page(page, index)
         } else {
@@ -1063,7 +1129,9 @@ impl DirPage {
             return;
         }
 
-        if self.has_right_fence() && key > self.get_right_fence_key() {
+        if self.has_right_fence()
+            && self.compare(key, self.get_right_fence_key()) == Ordering::Greater
+        {
             let index = entries - 1;
             assert_eq!(
                 PageNo::from_u64(page_no),
@@ -1089,7 +1157,7 @@ impl DirPage {
         let key_suffix = &key[prefix_length..];
         let slot = self.get_slot_at_index(0);
         let first_key = self.get_key_at_slot(&slot);
-        if key_suffix < first_key {
+        if self.compare(key_suffix, first_key) == Ordering::Less {
             assert_eq!(PageNo::from_u64(page_no), self.get_page_no_at_index(0));
             self.remove_key_value_at_index(0);
             return;
@@ -1125,6 +1193,27 @@ impl DirPage {
         }
     }
 
+    // Full keys in the page, ie with any prefix added back.
+    pub fn get_all_keys(&self) -> Vec<Vec<u8>> {
+        (0..self.get_entries_size() as usize)
+            .map(|i| self.get_key_at_index(i))
+            .collect()
+    }
+
+    // The child pages in key order with the smallest key that is routed
+    // to each child page, the page to the left has no smallest key.
+    pub fn get_child_pages_with_keys(&self) -> Vec<(Option<Vec<u8>>, PageNo)> {
+        let mut child_pages = Vec::new();
+        let pg_to_left = self.get_page_to_left();
+        if pg_to_left.get_blk_offset() > 0 {
+            child_pages.push((None, pg_to_left));
+        }
+        for i in 0..self.get_entries_size() as usize {
+            child_pages.push((Some(self.get_key_at_index(i)), self.get_page_no_at_index(i)));
+        }
+        child_pages
+    }
+
     pub fn get_all_child_pages(&self) -> Vec<PageNo> {
         let mut child_pages = Vec::new();
         let pg_to_left = self.get_page_to_left();
@@ -1169,6 +1258,19 @@ mod tests {
         assert_eq!(dir_page.get_dir_left_key(), None);
     }
 
+    #[test]
+    #[should_panic(expected = "Not enough free space in page.")]
+    fn test_add_entry_larger_than_free_space() {
+        let page_config = DbConfig::builder()
+            .block_size(256)
+            .block_sanity_size(0)
+            .build();
+        let mut dir_page = DirPage::create_new(&page_config, PageNo::new(0, 1), 0);
+        for i in 0..20u8 {
+            dir_page.add_key_value_at_index(i as usize, &[i; 20], &[0u8; DirPage::VALUE_SIZE]);
+        }
+    }
+
     #[test]
     #[should_panic(expected = "Page type is not DirPage")]
     fn test_invalid_page() {
@@ -1178,7 +1280,7 @@ mod tests {
             .build();
         let mut leaf_page = Page::new(page_config.block_size, page_config.page_size);
         leaf_page.set_type(PageType::LeafPage);
-        let _dir_page = DirPage::from_page(leaf_page);
+        let _dir_page = DirPage::from_page(leaf_page, page_config.key_order);
     }
 
     #[test]
@@ -1361,7 +1463,7 @@ mod tests {
     fn test_reset_left_fence_full() {
         let page_config = DbConfig::builder()
             .block_size(160)
-            .block_sanity_size(160 - 112)
+            .block_sanity_size(160 - 118)
             .build();
         let mut dir_page = DirPage::create_new(&page_config, PageNo::new(0, 1), 0);
         let key1 = b"aaaaaaaaaaaaaaaaaaaa";
@@ -1386,7 +1488,7 @@ mod tests {
     fn test_add_left_fence_full() {
         let page_config = DbConfig::builder()
             .block_size(160)
-            .block_sanity_size(160 - 112)
+            .block_sanity_size(160 - 118)
             .build();
         let mut dir_page = DirPage::create_new(&page_config, PageNo::new(0, 1), 0);
         let key1 = b"aaaaaaaaaaaaaaaaaaaa";
@@ -1411,7 +1513,7 @@ mod tests {
     fn test_reset_right_fence_full() {
         let page_config = DbConfig::builder()
             .block_size(160)
-            .block_sanity_size(160 - 112)
+            .block_sanity_size(160 - 118)
             .build();
         let mut dir_page = DirPage::create_new(&page_config, PageNo::new(0, 1), 0);
         let key1 = b"aaaaaaaaaaaaaaaaaaaa";
@@ -1436,7 +1538,7 @@ mod tests {
     fn test_add_right_fence_full() {
         let page_config = DbConfig::builder()
             .block_size(160)
-            .block_sanity_size(160 - 112)
+            .block_sanity_size(160 - 118)
             .build();
         let mut dir_page = DirPage::create_new(&page_config, PageNo::new(0, 1), 0);
         let key1 = b"aaaaaaaaaaaaaaaaaaaa";
@@ -1501,6 +1603,13 @@ mod tests {
 
         dir_page.remove_key_page(b"key9", 8);
         assert_eq!(dir_page.get_next(b"key8"), PageNo::from_u64(7));
+
+        dir_page.replace_child_page(PageNo::from_u64(2), PageNo::from_u64(12));
+        dir_page.replace_child_page(PageNo::from_u64(7), PageNo::from_u64(17));
+        assert_eq!(dir_page.get_page_to_left(), PageNo::from_u64(12));
+        assert_eq!(dir_page.get_next(b"key6"), PageNo::from_u64(12));
+        assert_eq!(dir_page.get_next(b"key7"), PageNo::from_u64(17));
+        assert_eq!(dir_page.get_next(b"key9"), PageNo::from_u64(17));
     }
 
     #[test]
@@ -1526,4 +1635,31 @@ mod tests {
             assert_eq!(right_page.get_next(&key), PageNo::from_u64(i as u64));
         }
     }
+
+    #[test]
+    fn test_long_keys_and_prefix() {
+        // Key lengths and the prefix length are u16 so keys over 255
+        // bytes can be stored.
+        let page_config = DbConfig::builder().block_size(4096).build();
+        let mut dir_page = DirPage::create_new(&page_config, PageNo::new(0, 1), 0);
+        let mut key1 = vec![b'k'; 400];
+        key1.push(1);
+        let mut key2 = vec![b'k'; 400];
+        key2.push(2);
+        let mut key3 = vec![b'k'; 400];
+        key3.push(3);
+        dir_page.set_left_fence_key(&key1);
+        dir_page.set_right_fence_key(&key3);
+        dir_page.set_prefix_length(400);
+        dir_page.set_page_to_left(PageNo::from_u64(1));
+        dir_page.add_child_page(&key1, 2);
+        dir_page.add_child_page(&key2, 3);
+        assert_eq!(dir_page.get_prefix_length(), 400);
+        assert_eq!(dir_page.get_left_fence_key(), key1.as_slice());
+        assert_eq!(dir_page.get_right_fence_key(), key3.as_slice());
+        assert_eq!(dir_page.get_all_keys(), vec![key1.clone(), key2.clone()]);
+        assert_eq!(dir_page.get_next(&key1), PageNo::from_u64(2));
+        assert_eq!(dir_page.get_next(&key3), PageNo::from_u64(3));
+        assert_eq!(dir_page.get_page_to_left(), PageNo::from_u64(1));
+    }
 }
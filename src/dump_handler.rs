@@ -0,0 +1,197 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+pub struct DumpHandler {}
+
+// A record read back from a dump.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DumpRecord {
+    // The entries that follow are in this table, None for the global
+    // tree. compress is false for a table created with
+    // CompressorType::None.
+    Table {
+        name: Option<Vec<u8>>,
+        compress: bool,
+    },
+    Entry(Vec<u8>, Vec<u8>),
+    // The end of the dump, with the number of entries in it.
+    End(u64),
+}
+
+// The stream written by Db::dump and read by Db::restore. It holds the
+// keys and values only, nothing about the pages, so a dump can be
+// restored into a DB with another block size, compressor or key. All
+// numbers are little endian.
+//
+// | "digbydmp" | format version (u16) |
+// then records, each starting with a type byte:
+// | 'T' | name len (u8) | name | compress (u8) |  entries that follow are in this table
+// | 'E' | key len (u64) | key | value len (u64) | value |
+// | 'Z' | entry count (u64) |  the end of the dump
+//
+// Table names are shorter than 255 bytes so a name len of 255 is kept
+// for the global tree, it has no name. The entry count in the end record
+// catches a dump that has been cut short.
+impl DumpHandler {
+    const MAGIC: &'static [u8; 8] = b"digbydmp";
+    const FORMAT_VERSION: u16 = 1;
+    const GLOBAL_TREE: u8 = u8::MAX;
+    const TABLE: u8 = b'T';
+    const ENTRY: u8 = b'E';
+    const END: u8 = b'Z';
+
+    pub fn write_header(writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(DumpHandler::MAGIC)?;
+        writer.write_u16::<LittleEndian>(DumpHandler::FORMAT_VERSION)
+    }
+
+    pub fn write_table(
+        writer: &mut impl Write,
+        name: Option<&[u8]>,
+        compress: bool,
+    ) -> std::io::Result<()> {
+        writer.write_u8(DumpHandler::TABLE)?;
+        match name {
+            Some(name) => {
+                assert!(
+                    name.len() < DumpHandler::GLOBAL_TREE as usize,
+                    "Table name must be less than 255 bytes"
+                );
+                writer.write_u8(name.len() as u8)?;
+                writer.write_all(name)?;
+            }
+            None => writer.write_u8(DumpHandler::GLOBAL_TREE)?,
+        }
+        writer.write_u8(compress as u8)
+    }
+
+    pub fn write_entry(writer: &mut impl Write, key: &[u8], value: &[u8]) -> std::io::Result<()> {
+        writer.write_u8(DumpHandler::ENTRY)?;
+        writer.write_u64::<LittleEndian>(key.len() as u64)?;
+        writer.write_all(key)?;
+        writer.write_u64::<LittleEndian>(value.len() as u64)?;
+        writer.write_all(value)
+    }
+
+    pub fn write_end(writer: &mut impl Write, entry_count: u64) -> std::io::Result<()> {
+        writer.write_u8(DumpHandler::END)?;
+        writer.write_u64::<LittleEndian>(entry_count)
+    }
+
+    // A stream that is not a dump, or from a later format, is an
+    // InvalidData error.
+    pub fn read_header(reader: &mut impl Read) -> std::io::Result<()> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != DumpHandler::MAGIC {
+            return Err(DumpHandler::invalid("not a digby dump"));
+        }
+        let format_version = reader.read_u16::<LittleEndian>()?;
+        if format_version != DumpHandler::FORMAT_VERSION {
+            return Err(DumpHandler::invalid("unknown dump format version"));
+        }
+        Ok(())
+    }
+
+    // A stream that ends part way through a record is an UnexpectedEof
+    // error, an unknown record type is InvalidData.
+    pub fn read_record(reader: &mut impl Read) -> std::io::Result<DumpRecord> {
+        match reader.read_u8()? {
+            DumpHandler::TABLE => {
+                let name = match reader.read_u8()? {
+                    DumpHandler::GLOBAL_TREE => None,
+                    name_len => Some(DumpHandler::read_bytes(reader, name_len as u64)?),
+                };
+                let compress = reader.read_u8()? != 0;
+                Ok(DumpRecord::Table { name, compress })
+            }
+            DumpHandler::ENTRY => {
+                let key_len = reader.read_u64::<LittleEndian>()?;
+                let key = DumpHandler::read_bytes(reader, key_len)?;
+                let value_len = reader.read_u64::<LittleEndian>()?;
+                let value = DumpHandler::read_bytes(reader, value_len)?;
+                Ok(DumpRecord::Entry(key, value))
+            }
+            DumpHandler::END => Ok(DumpRecord::End(reader.read_u64::<LittleEndian>()?)),
+            _ => Err(DumpHandler::invalid("unknown dump record type")),
+        }
+    }
+
+    // The buffer grows as the bytes arrive, a bad length in a damaged
+    // dump runs out of stream rather than memory.
+    fn read_bytes(reader: &mut impl Read, len: u64) -> std::io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        reader.take(len).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < len {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+
+    pub fn invalid(message: &str) -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_records() {
+        let mut dump: Vec<u8> = Vec::new();
+        DumpHandler::write_header(&mut dump).unwrap();
+        DumpHandler::write_table(&mut dump, None, true).unwrap();
+        DumpHandler::write_entry(&mut dump, b"key", b"value").unwrap();
+        DumpHandler::write_table(&mut dump, Some(b"table"), false).unwrap();
+        DumpHandler::write_entry(&mut dump, b"", &[7u8; 1000]).unwrap();
+        DumpHandler::write_end(&mut dump, 2).unwrap();
+
+        let mut reader = dump.as_slice();
+        DumpHandler::read_header(&mut reader).unwrap();
+        assert_eq!(
+            DumpHandler::read_record(&mut reader).unwrap(),
+            DumpRecord::Table {
+                name: None,
+                compress: true
+            }
+        );
+        assert_eq!(
+            DumpHandler::read_record(&mut reader).unwrap(),
+            DumpRecord::Entry(b"key".to_vec(), b"value".to_vec())
+        );
+        assert_eq!(
+            DumpHandler::read_record(&mut reader).unwrap(),
+            DumpRecord::Table {
+                name: Some(b"table".to_vec()),
+                compress: false
+            }
+        );
+        assert_eq!(
+            DumpHandler::read_record(&mut reader).unwrap(),
+            DumpRecord::Entry(Vec::new(), vec![7u8; 1000])
+        );
+        assert_eq!(
+            DumpHandler::read_record(&mut reader).unwrap(),
+            DumpRecord::End(2)
+        );
+        assert!(reader.is_empty());
+
+        // Cut short in the middle of the long value.
+        let mut reader = &dump[..dump.len() - 100];
+        DumpHandler::read_header(&mut reader).unwrap();
+        for _ in 0..3 {
+            DumpHandler::read_record(&mut reader).unwrap();
+        }
+        assert_eq!(
+            DumpHandler::read_record(&mut reader).unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+
+        let mut reader: &[u8] = b"digbydmq\x01\x00";
+        assert_eq!(
+            DumpHandler::read_header(&mut reader).unwrap_err().kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -1,91 +1,342 @@
+use crate::corruption::Corruption;
+use crate::digby_error::DigbyError;
+use crate::observer::Observer;
 use crate::page::Page;
 use crate::page_no::PageNo;
+use crate::storage::Storage;
 
+// The file layer reads and writes blocks to the file. Optionally it
+// can read ahead, when a read misses the read buffer a run of
+// contiguous blocks is read into the buffer so reads of the following
+// blocks are served from memory rather than a seek and read each.
+// This helps sequential scans where sibling pages are generally
+// close together in the file. Writes that overlap the buffer update
+// it so it never holds stale blocks.
+//
+// Write and sync errors are returned rather than panicking so a
+// commit that fails part way through, say on a full disk, can be
+// backed out. A file that is not a whole number of blocks, or a read
+// past the end of the file, is returned as Corruption so a truncated
+// file can be reported rather than crash the caller.
 pub struct FileLayer {
-    file: std::fs::File,
+    file: Box<dyn Storage>,
     block_size: usize,
     block_count: u64,
+    read_ahead_blks: u64,
+    read_buffer: Vec<u8>,
+    read_buffer_blk_offset: u64,
+    read_buffer_blk_cnt: u64,
+    observer: Option<Box<dyn Observer>>,
 }
 
 impl FileLayer {
-    pub fn new(file: std::fs::File, block_size: usize) -> Self {
-        let metadata = file.metadata().expect("Failed to get metadata for file.");
-        let file_size = metadata.len();
-        assert!(
-            file_size.is_multiple_of(block_size as u64),
-            "File size is not a multiple of block size."
-        );
+    // Blocks read and written in one go by copy_blocks.
+    const COPY_BLKS: u64 = 64;
+
+    pub fn new(file: std::fs::File, block_size: usize) -> Result<Self, DigbyError> {
+        FileLayer::new_with_storage(Box::new(file), block_size)
+    }
+
+    pub fn new_with_storage(file: Box<dyn Storage>, block_size: usize) -> Result<Self, DigbyError> {
+        let file_size = file.len()?;
         let block_count: u64 = file_size / block_size as u64;
-        FileLayer {
+        // The last block is cut short, an interrupted write or a partial
+        // copy of the file.
+        if !file_size.is_multiple_of(block_size as u64) {
+            return Err(Corruption::TruncatedBlock(PageNo::from_u64(block_count)).into());
+        }
+        Ok(FileLayer {
             file,
             block_size,
             block_count,
-        }
+            read_ahead_blks: 0,
+            read_buffer: Vec::new(),
+            read_buffer_blk_offset: 0,
+            read_buffer_blk_cnt: 0,
+            observer: None,
+        })
+    }
+
+    // Set the number of blocks to read in one go when a read misses
+    // the read buffer, 0 turns read-ahead off.
+    pub fn set_read_ahead(&mut self, read_ahead_blks: u64) {
+        self.read_ahead_blks = read_ahead_blks;
+        self.read_buffer = Vec::new();
+        self.read_buffer_blk_cnt = 0;
+    }
+
+    // Throw away the blocks read ahead, they are read again from the
+    // file when next needed.
+    pub fn clear_read_buffer(&mut self) {
+        self.read_buffer_blk_cnt = 0;
+    }
+
+    pub fn get_read_ahead(&self) -> u64 {
+        self.read_ahead_blks
+    }
+
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observer = Some(observer);
     }
 
     pub fn get_block_count(&self) -> u64 {
         self.block_count
     }
 
-    pub fn append_new_page(&mut self, page: &Page, page_no: &PageNo) {
-        use std::io::{Seek, SeekFrom, Write};
-
+    // The block count goes up even if the append fails, the blocks
+    // have been handed out. Call reset_block_count to go back to the
+    // blocks that are really in the file.
+    pub fn append_new_page(&mut self, page: &Page, page_no: &PageNo) -> std::io::Result<()> {
         let pg_blk_count = page_no.get_blk_cnt();
         let pg_blk_offset = page_no.get_blk_offset();
         assert!(
             pg_blk_offset == self.block_count,
             "page_number should match page_count"
         );
+        self.block_count += pg_blk_count;
         let offset = pg_blk_offset * self.block_size as u64;
+        self.file.write_all_at(page.get_pg_ctr_bytes(), offset)?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_disk_write(pg_blk_offset, pg_blk_count);
+        }
+        Ok(())
+    }
+
+    // As append_new_page for a run of pages, the first at page_no and
+    // each following the last, written to the end of the file in one
+    // write. The page numbers are not read from the pages, an encrypted
+    // page cannot be read until it is decrypted.
+    pub fn append_new_pages(&mut self, pages: &[Page], page_no: &PageNo) -> std::io::Result<()> {
+        if pages.is_empty() {
+            return Ok(());
+        }
+        let pg_blk_offset = page_no.get_blk_offset();
+        assert!(
+            pg_blk_offset == self.block_count,
+            "page_number should match page_count"
+        );
+        let mut bytes =
+            Vec::with_capacity(pages.iter().map(|page| page.get_pg_ctr_bytes().len()).sum());
+        for page in pages {
+            assert!(
+                page.get_pg_ctr_bytes()
+                    .len()
+                    .is_multiple_of(self.block_size),
+                "page should be whole blocks"
+            );
+            bytes.extend_from_slice(page.get_pg_ctr_bytes());
+        }
+        let blk_count = (bytes.len() / self.block_size) as u64;
+        self.block_count += blk_count;
         self.file
-            .seek(SeekFrom::Start(offset))
-            .expect("Failed to seek for append_new_page");
-        self.file
-            .write_all(page.get_pg_ctr_bytes())
-            .expect("Failed to write for append_new_page");
-        self.block_count += pg_blk_count;
+            .write_all_at(&bytes, pg_blk_offset * self.block_size as u64)?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_disk_write(pg_blk_offset, blk_count);
+        }
+        Ok(())
     }
 
-    pub fn write_page_to_disk(&mut self, page: &Page, page_no: &PageNo) -> std::io::Result<()> {
-        use std::io::{Seek, SeekFrom, Write};
+    // Set the block count from the size of the file, dropping any
+    // blocks from appends that did not make it to the file. The read
+    // buffer is dropped too as it may hold blocks that failed to write.
+    pub fn reset_block_count(&mut self) -> std::io::Result<()> {
+        self.block_count = self.file.len()? / self.block_size as u64;
+        self.read_buffer_blk_cnt = 0;
+        Ok(())
+    }
 
+    // Swap in another file, for one that has been renamed over the
+    // file this layer had. The block count is taken from the new file
+    // and the read buffer is dropped.
+    pub fn replace_storage(&mut self, file: Box<dyn Storage>) -> std::io::Result<()> {
+        self.block_count = file.len()? / self.block_size as u64;
+        self.file = file;
+        self.read_buffer_blk_cnt = 0;
+        Ok(())
+    }
+
+    // Cut the file down to block_count blocks, the blocks past the end
+    // must no longer be in use.
+    pub fn truncate(&mut self, block_count: u64) -> std::io::Result<()> {
+        assert!(
+            block_count <= self.block_count,
+            "Truncating past the end of the file."
+        );
+        self.file.set_len(block_count * self.block_size as u64)?;
+        self.block_count = block_count;
+        self.read_buffer_blk_cnt = 0;
+        Ok(())
+    }
+
+    pub fn write_page_to_disk(&mut self, page: &Page, page_no: &PageNo) -> std::io::Result<()> {
         let pg_blk_offset = page_no.get_blk_offset();
         let offset = pg_blk_offset * self.block_size as u64;
-        self.file
-            .seek(SeekFrom::Start(offset))
-            .expect("Failed to seek for write_page_to_disk");
-        self.file
-            .write_all(page.get_pg_ctr_bytes())
-            .expect("Failed to write for write_page_to_disk");
+        self.file.write_all_at(page.get_pg_ctr_bytes(), offset)?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_disk_write(pg_blk_offset, page_no.get_blk_cnt());
+        }
+        self.update_read_buffer(page.get_pg_ctr_bytes(), pg_blk_offset);
         Ok(())
     }
 
+    // A page that runs past the end of the file is PageOutOfRange.
     pub fn read_page_from_disk(
         &mut self,
         page: &mut Page,
         page_no: &PageNo,
-    ) -> std::io::Result<()> {
-        use std::io::{Read, Seek, SeekFrom};
-
+    ) -> Result<(), DigbyError> {
         let pg_blk_offset = page_no.get_blk_offset();
-        assert!(pg_blk_offset < self.block_count);
+        let pg_blk_count = page_no.get_blk_cnt();
+        if pg_blk_offset + pg_blk_count > self.block_count {
+            return Err(Corruption::PageOutOfRange(*page_no).into());
+        }
+
+        // Blocks in the buffer are used even with read-ahead off, they
+        // may have been prefetched.
+        let in_read_buffer = self.in_read_buffer(pg_blk_offset, pg_blk_count);
+        if in_read_buffer || self.read_ahead_blks > 0 {
+            if !in_read_buffer {
+                self.fill_read_buffer(pg_blk_offset, pg_blk_count)?;
+            }
+            let start = ((pg_blk_offset - self.read_buffer_blk_offset) as usize) * self.block_size;
+            let len = page.get_pg_ctr_bytes().len();
+            page.get_pg_ctr_bytes_mut()
+                .copy_from_slice(&self.read_buffer[start..start + len]);
+            return Ok(());
+        }
 
         let offset = pg_blk_offset * self.block_size as u64;
         self.file
-            .seek(SeekFrom::Start(offset))
-            .expect("Failed to seek for read");
+            .read_exact_at(page.get_pg_ctr_bytes_mut(), offset)?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_disk_read(pg_blk_offset, pg_blk_count);
+        }
+        Ok(())
+    }
+
+    fn in_read_buffer(&self, blk_offset: u64, blk_cnt: u64) -> bool {
+        blk_offset >= self.read_buffer_blk_offset
+            && blk_offset + blk_cnt <= self.read_buffer_blk_offset + self.read_buffer_blk_cnt
+    }
+
+    // Read a run of blocks starting at blk_offset into the read buffer,
+    // always reads at least the blocks for the page being asked for but
+    // does not read past the end of the file.
+    fn fill_read_buffer(&mut self, blk_offset: u64, blk_cnt: u64) -> std::io::Result<()> {
+        let blks_to_read = self
+            .read_ahead_blks
+            .max(blk_cnt)
+            .min(self.block_count - blk_offset);
+        self.read_into_buffer(blk_offset, blks_to_read)
+    }
+
+    // Read blk_cnt blocks from blk_offset into the read buffer in one
+    // go, so reads of them that follow are served from memory. Used
+    // when the caller knows which blocks it is about to read, such as
+    // the pages of an overflow chain. Blocks past the end of the file
+    // are left out, and nothing is read if the first block is already
+    // in the buffer.
+    pub fn prefetch(&mut self, blk_offset: u64, blk_cnt: u64) -> std::io::Result<()> {
+        let blks_to_read = blk_cnt.min(self.block_count.saturating_sub(blk_offset));
+        if blks_to_read == 0 || self.in_read_buffer(blk_offset, 1) {
+            return Ok(());
+        }
+        self.read_into_buffer(blk_offset, blks_to_read)
+    }
+
+    fn read_into_buffer(&mut self, blk_offset: u64, blks_to_read: u64) -> std::io::Result<()> {
+        self.read_buffer
+            .resize(blks_to_read as usize * self.block_size, 0);
         self.file
-            .read_exact(page.get_pg_ctr_bytes_mut())
-            .expect("Failed to read");
+            .read_exact_at(&mut self.read_buffer, blk_offset * self.block_size as u64)?;
+        self.read_buffer_blk_offset = blk_offset;
+        self.read_buffer_blk_cnt = blks_to_read;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_disk_read(blk_offset, blks_to_read);
+        }
+        Ok(())
+    }
+
+    // Copy any part of a written page that lies in the read buffer
+    // into the buffer.
+    fn update_read_buffer(&mut self, bytes: &[u8], blk_offset: u64) {
+        let write_start = blk_offset * self.block_size as u64;
+        let write_end = write_start + bytes.len() as u64;
+        let buf_start = self.read_buffer_blk_offset * self.block_size as u64;
+        let buf_end = buf_start + self.read_buffer_blk_cnt * self.block_size as u64;
+        let start = write_start.max(buf_start);
+        let end = write_end.min(buf_end);
+        if start >= end {
+            return;
+        }
+        self.read_buffer[(start - buf_start) as usize..(end - buf_start) as usize]
+            .copy_from_slice(&bytes[(start - write_start) as usize..(end - write_start) as usize]);
+    }
+
+    // Copy blk_cnt blocks from blk_offset to the same place in dest,
+    // as they are in the file. Blocks are not checked or decrypted.
+    pub fn copy_blocks(
+        &mut self,
+        dest: &mut dyn Storage,
+        blk_offset: u64,
+        blk_cnt: u64,
+    ) -> std::io::Result<()> {
+        assert!(
+            blk_offset + blk_cnt <= self.block_count,
+            "Copying blocks past the end of the file."
+        );
+        let mut buffer = Vec::new();
+        let mut copied = 0;
+        while copied < blk_cnt {
+            let blks_to_copy = (blk_cnt - copied).min(FileLayer::COPY_BLKS);
+            buffer.resize(blks_to_copy as usize * self.block_size, 0);
+            let offset = (blk_offset + copied) * self.block_size as u64;
+            self.file.read_exact_at(&mut buffer, offset)?;
+            if let Some(observer) = self.observer.as_mut() {
+                observer.on_disk_read(blk_offset + copied, blks_to_copy);
+            }
+            dest.write_all_at(&buffer, offset)?;
+            copied += blks_to_copy;
+        }
+        Ok(())
+    }
+
+    // Give the blocks of a free page back to the file system, see
+    // Storage::punch_hole. The page reads as zeros until it is written
+    // again, so it is dropped from the read buffer.
+    pub fn punch_hole(&mut self, page_no: &PageNo) -> std::io::Result<()> {
+        let pg_blk_offset = page_no.get_blk_offset();
+        let pg_blk_count = page_no.get_blk_cnt();
+        assert!(
+            pg_blk_offset + pg_blk_count <= self.block_count,
+            "Punching a hole past the end of the file."
+        );
+        self.file.punch_hole(
+            pg_blk_offset * self.block_size as u64,
+            pg_blk_count * self.block_size as u64,
+        )?;
+        if pg_blk_offset < self.read_buffer_blk_offset + self.read_buffer_blk_cnt
+            && self.read_buffer_blk_offset < pg_blk_offset + pg_blk_count
+        {
+            self.read_buffer_blk_cnt = 0;
+        }
         Ok(())
     }
 
-    pub fn sync_all(&self) {
-        self.file.sync_all().expect("Failed to sync")
+    pub fn sync_all(&mut self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_sync(true);
+        }
+        Ok(())
     }
 
-    pub fn sync_data(&self) {
-        self.file.sync_data().expect("Failed to sync data")
+    pub fn sync_data(&mut self) -> std::io::Result<()> {
+        self.file.sync_data()?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_sync(false);
+        }
+        Ok(())
     }
 }
 
@@ -94,6 +345,8 @@ mod tests {
     use std::os::unix::fs::FileExt;
 
     use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
     const BLOCK_SIZE: usize = 4096;
     use rand::Rng;
     use rand::distr::Alphanumeric;
@@ -102,9 +355,12 @@ mod tests {
     #[test]
     fn test_file_layer_write_and_read() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let mut file_layer = FileLayer::new(temp_file, BLOCK_SIZE);
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
         let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4); // Create a new page
-        file_layer.append_new_page(&page, &PageNo::from_u64(0));
+        file_layer
+            .append_new_page(&page, &PageNo::from_u64(0))
+            .expect("Failed to append page");
         let test_data: String = rand::rng()
             .sample_iter(&Alphanumeric)
             .take(BLOCK_SIZE)
@@ -128,13 +384,137 @@ mod tests {
         assert_eq!(page.get_pg_ctr_bytes(), read_page.get_pg_ctr_bytes());
     }
 
+    struct WriteCounter {
+        writes: Arc<AtomicU64>,
+    }
+
+    impl Observer for WriteCounter {
+        fn on_disk_write(&mut self, _blk_offset: u64, _blk_cnt: u64) {
+            self.writes.fetch_add(1, AtomicOrdering::SeqCst);
+        }
+    }
+
+    // A run of pages is written in one go.
+    #[test]
+    fn test_file_layer_append_new_pages() {
+        let writes = Arc::new(AtomicU64::new(0));
+        let mut file_layer =
+            FileLayer::new(tempfile().expect("Failed to create temp file"), BLOCK_SIZE)
+                .expect("Failed to create file layer");
+        file_layer.set_observer(Box::new(WriteCounter {
+            writes: writes.clone(),
+        }));
+        file_layer
+            .append_new_pages(&[], &PageNo::from_u64(0))
+            .expect("Failed to append pages");
+        let pages: Vec<Page> = (0..5u8)
+            .map(|index| {
+                let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+                page.get_pg_ctr_bytes_mut().fill(index);
+                page
+            })
+            .collect();
+        file_layer
+            .append_new_pages(&pages, &PageNo::from_u64(0))
+            .expect("Failed to append pages");
+        assert_eq!(file_layer.get_block_count(), 5);
+        assert_eq!(writes.load(AtomicOrdering::SeqCst), 1);
+        for (index, page) in pages.iter().enumerate() {
+            let mut read_page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+            file_layer
+                .read_page_from_disk(&mut read_page, &PageNo::from_u64(index as u64))
+                .expect("Failed to read page");
+            assert_eq!(read_page.get_pg_ctr_bytes(), page.get_pg_ctr_bytes());
+        }
+    }
+
+    #[test]
+    fn test_file_layer_read_past_end() {
+        // A read past the end of the file is an error rather than a panic.
+        let temp_file = tempfile().expect("Failed to create temp file");
+        temp_file
+            .set_len(2 * BLOCK_SIZE as u64)
+            .expect("Failed to set length");
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
+        let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+        assert!(
+            file_layer
+                .read_page_from_disk(&mut page, &PageNo::from_u64(1))
+                .is_ok()
+        );
+        assert!(matches!(
+            file_layer.read_page_from_disk(&mut page, &PageNo::from_u64(2)),
+            Err(DigbyError::Corruption(Corruption::PageOutOfRange(_)))
+        ));
+        file_layer.set_read_ahead(4);
+        assert!(matches!(
+            file_layer.read_page_from_disk(&mut page, &PageNo::from_u64(3)),
+            Err(DigbyError::Corruption(Corruption::PageOutOfRange(_)))
+        ));
+    }
+
+    #[test]
+    fn test_file_layer_truncate() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
+        let page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+        for page_no in 0..4 {
+            file_layer
+                .append_new_page(&page, &PageNo::from_u64(page_no))
+                .expect("Failed to append page");
+        }
+        file_layer.truncate(2).expect("Failed to truncate");
+        assert_eq!(file_layer.get_block_count(), 2);
+        assert_eq!(file_layer.file.len().unwrap(), 2 * BLOCK_SIZE as u64);
+        // Pages are appended after the new end.
+        file_layer
+            .append_new_page(&page, &PageNo::from_u64(2))
+            .expect("Failed to append page");
+        assert_eq!(file_layer.get_block_count(), 3);
+    }
+
+    #[test]
+    fn test_file_layer_copy_blocks() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
+        // More blocks than are copied in one go.
+        let blk_cnt = FileLayer::COPY_BLKS + 6;
+        let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+        for page_no in 0..blk_cnt {
+            page.get_pg_ctr_bytes_mut().fill(page_no as u8);
+            file_layer
+                .append_new_page(&page, &PageNo::from_u64(page_no))
+                .expect("Failed to append page");
+        }
+        let mut dest = tempfile().expect("Failed to create temp file");
+        file_layer
+            .copy_blocks(&mut dest, 1, blk_cnt - 1)
+            .expect("Failed to copy blocks");
+        assert_eq!(Storage::len(&dest).unwrap(), blk_cnt * BLOCK_SIZE as u64);
+        file_layer
+            .copy_blocks(&mut dest, 0, 1)
+            .expect("Failed to copy blocks");
+        let mut dest_layer = FileLayer::new(dest, BLOCK_SIZE).expect("Failed to create file layer");
+        assert_eq!(dest_layer.get_block_count(), blk_cnt);
+        for page_no in 0..blk_cnt {
+            dest_layer
+                .read_page_from_disk(&mut page, &PageNo::from_u64(page_no))
+                .expect("Failed to read page");
+            assert!(page.get_pg_ctr_bytes().iter().all(|&b| b == page_no as u8));
+        }
+    }
+
     #[test]
     #[should_panic(expected = "page_number should match page_count")]
     fn test_file_layer_write_bad_page_no() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let mut file_layer = FileLayer::new(temp_file, BLOCK_SIZE);
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
         let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4); // Create a new page
-        file_layer.append_new_page(&page, &PageNo::from_u64(24));
+        let _ = file_layer.append_new_page(&page, &PageNo::from_u64(24));
         let test_data: String = rand::rng()
             .sample_iter(&Alphanumeric)
             .take(BLOCK_SIZE)
@@ -144,16 +524,139 @@ mod tests {
             .copy_from_slice(test_data.as_bytes()); // Fill the page with test data
 
         // Write the page to disk
-        file_layer.append_new_page(&page, &PageNo::from_u64(0));
+        let _ = file_layer.append_new_page(&page, &PageNo::from_u64(0));
+    }
+
+    struct ReadCounter {
+        reads: Arc<AtomicU64>,
+    }
+
+    impl Observer for ReadCounter {
+        fn on_disk_read(&mut self, _blk_offset: u64, _blk_cnt: u64) {
+            self.reads.fetch_add(1, AtomicOrdering::SeqCst);
+        }
+    }
+
+    // Write blk_cnt blocks each filled with its block number and then
+    // read them all back in order, returns the number of disk reads.
+    fn scan_blocks(read_ahead: u64, blk_cnt: u64) -> u64 {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
+        let reads = Arc::new(AtomicU64::new(0));
+        file_layer.set_observer(Box::new(ReadCounter {
+            reads: reads.clone(),
+        }));
+        file_layer.set_read_ahead(read_ahead);
+        for i in 0..blk_cnt {
+            let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+            page.get_pg_ctr_bytes_mut().fill(i as u8);
+            file_layer
+                .append_new_page(&page, &PageNo::from_u64(i))
+                .expect("Failed to append page");
+        }
+        for i in 0..blk_cnt {
+            let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+            file_layer
+                .read_page_from_disk(&mut page, &PageNo::from_u64(i))
+                .expect("Failed to read page");
+            assert!(page.get_pg_ctr_bytes().iter().all(|b| *b == i as u8));
+        }
+        reads.load(AtomicOrdering::SeqCst)
+    }
+
+    #[test]
+    fn test_file_layer_read_ahead_scan() {
+        let no_read_ahead = scan_blocks(0, 64);
+        let read_ahead = scan_blocks(16, 64);
+        assert_eq!(no_read_ahead, 64);
+        assert_eq!(read_ahead, 4);
+    }
+
+    #[test]
+    fn test_file_layer_read_ahead_past_end_of_file() {
+        // 10 blocks with a read ahead of 16, reads stop at the end of the file.
+        assert_eq!(scan_blocks(16, 10), 1);
+    }
+
+    #[test]
+    fn test_file_layer_prefetch() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
+        let reads = Arc::new(AtomicU64::new(0));
+        file_layer.set_observer(Box::new(ReadCounter {
+            reads: reads.clone(),
+        }));
+        for i in 0..16 {
+            let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+            page.get_pg_ctr_bytes_mut().fill(i as u8);
+            file_layer
+                .append_new_page(&page, &PageNo::from_u64(i))
+                .expect("Failed to append page");
+        }
+        // Read-ahead is off, the prefetched blocks are still used. The
+        // prefetch stops at the end of the file.
+        file_layer.prefetch(4, 100).expect("Failed to prefetch");
+        file_layer.prefetch(6, 4).expect("Failed to prefetch");
+        for i in 4..16 {
+            let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+            file_layer
+                .read_page_from_disk(&mut page, &PageNo::from_u64(i))
+                .expect("Failed to read page");
+            assert!(page.get_pg_ctr_bytes().iter().all(|b| *b == i as u8));
+        }
+        assert_eq!(reads.load(AtomicOrdering::SeqCst), 1);
+        // Blocks outside the prefetch are read on their own.
+        let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+        file_layer
+            .read_page_from_disk(&mut page, &PageNo::from_u64(0))
+            .expect("Failed to read page");
+        assert_eq!(reads.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_file_layer_read_ahead_sees_writes() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let mut file_layer =
+            FileLayer::new(temp_file, BLOCK_SIZE).expect("Failed to create file layer");
+        file_layer.set_read_ahead(8);
+        let mut page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+        for i in 0..8 {
+            file_layer
+                .append_new_page(&page, &PageNo::from_u64(i))
+                .expect("Failed to append page");
+        }
+        // Fill the read buffer.
+        file_layer
+            .read_page_from_disk(&mut page, &PageNo::from_u64(0))
+            .expect("Failed to read page");
+        // Overwrite a block in the buffer, the read must see it.
+        page.get_pg_ctr_bytes_mut().fill(7);
+        file_layer
+            .write_page_to_disk(&page, &PageNo::from_u64(3))
+            .expect("Failed to write page");
+        let mut read_page = Page::new(BLOCK_SIZE, BLOCK_SIZE - 4);
+        file_layer
+            .read_page_from_disk(&mut read_page, &PageNo::from_u64(3))
+            .expect("Failed to read page");
+        assert!(read_page.get_pg_ctr_bytes().iter().all(|b| *b == 7));
     }
 
     #[test]
-    #[should_panic(expected = "File size is not a multiple of block size.")]
     fn test_file_layer_non_block_size_file() {
         let temp_file = tempfile().expect("Failed to create temp file");
         temp_file
             .write_all_at(b"Hello, Rust!", 0)
             .expect("Failed to write to temp file");
-        let _file_layer = FileLayer::new(temp_file, BLOCK_SIZE);
+        let error = FileLayer::new(temp_file, BLOCK_SIZE).err();
+        assert!(matches!(
+            error,
+            Some(DigbyError::Corruption(Corruption::TruncatedBlock(page_no))) if page_no.to_u64() == 0
+        ));
+        assert_eq!(
+            error.unwrap().to_string(),
+            "DB is corrupt, page PageNo(0) is cut short, the file is truncated"
+        );
     }
 }
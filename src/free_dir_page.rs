@@ -126,6 +126,11 @@ impl FreeDirPage {
         self.page.get_page_bytes_mut()[24..24 + 8].copy_from_slice(&prev_free_dir_pg.get_bytes());
     }
 
+    // The number of free page numbers a page can hold.
+    pub fn get_capacity(page_config: &DbConfig) -> usize {
+        (page_config.page_size - FreeDirPage::HEADER_SIZE) / 8
+    }
+
     fn is_full_for(&self, number_of_pages: usize) -> bool {
         let capacity = self.page.get_page_bytes().len() - FreeDirPage::HEADER_SIZE;
         (capacity - (8 * self.get_entries() as usize)) < 8 * number_of_pages
@@ -147,6 +152,16 @@ impl FreeDirPage {
         PageNo::from_bytes(&self.page.get_page_bytes_mut()[offset..offset + 8])
     }
 
+    // The free page numbers in the page, oldest first.
+    pub fn get_all_free_pages(&self) -> Vec<PageNo> {
+        (0..self.get_entries() as usize)
+            .map(|index| {
+                let offset = FreeDirPage::HEADER_SIZE + (8 * index);
+                PageNo::from_bytes(&self.page.get_page_bytes()[offset..offset + 8])
+            })
+            .collect()
+    }
+
     pub fn add_free_page(&mut self, free_page_number: PageNo) {
         assert!(!self.is_full());
         let entries = self.get_entries();
@@ -204,10 +219,26 @@ mod tests {
         }
         assert!(free_page_dir.is_full());
         assert_eq!(count, 507);
+        assert_eq!(FreeDirPage::get_capacity(&DB_CONFIG), 507);
         assert_eq!(507, free_page_dir.get_free_page().get_blk_offset());
         assert!(!free_page_dir.is_full());
     }
 
+    #[test]
+    fn test_large_page_numbers() {
+        let large_page_no = PageNo::new(0, u64::from(u32::MAX) + 1);
+        let mut free_page_dir = FreeDirPage::create_new(&DB_CONFIG, PageNo::new(0, 34), 657);
+        free_page_dir.add_free_page(large_page_no);
+        free_page_dir.add_free_pages(&vec![PageNo::new(3, 1 << 40), PageNo::new(0, 5)]);
+        assert_eq!(
+            free_page_dir.get_all_free_pages(),
+            vec![large_page_no, PageNo::new(3, 1 << 40), PageNo::new(0, 5)]
+        );
+        assert_eq!(free_page_dir.get_free_page(), PageNo::new(0, 5));
+        assert_eq!(free_page_dir.get_free_page(), PageNo::new(3, 1 << 40));
+        assert_eq!(free_page_dir.get_free_page(), large_page_no);
+    }
+
     #[test]
     fn test_invalid_type() {
         let mut free_page_dir = FreeDirPage::create_new(&DB_CONFIG, PageNo::new(0, 34), 657);
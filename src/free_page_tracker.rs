@@ -21,6 +21,9 @@ use crate::page_no::PageNo;
 pub struct FreePageTracker {
     free_dir_page_list: Vec<FreeDirPage>,
     returned_pages: Vec<PageNo>,
+    // Pages added to the free page directory less those taken from it,
+    // so the commit can keep the count in the master page up to date.
+    free_page_delta: i64,
     new_version: u64,
     page_config: DbConfig,
 }
@@ -42,6 +45,7 @@ impl FreePageTracker {
         FreePageTracker {
             free_dir_page_list: vec![free_dir_page],
             returned_pages: Vec::new(), // Page numbers that have been returned.
+            free_page_delta: 0,
             new_version,
             page_config,
         }
@@ -58,6 +62,7 @@ impl FreePageTracker {
 
         // If the last has free pages then use one of them.
         if last.has_free_pages() {
+            self.free_page_delta -= 1;
             return last.get_free_page();
         }
 
@@ -80,16 +85,26 @@ impl FreePageTracker {
 
         // The current free_dir_page has no free pages, it has no links
         // to other free_dir_pages - so have the page_cache generate
-        // new free pages.
-        let mut new_free_pages: Vec<PageNo> = page_cache.generate_free_pages(8, 0);
+        // new free pages, as many as fit in the free_dir_page.
+        let free_page_growth = (self.page_config.free_page_growth as usize)
+            .min(FreeDirPage::get_capacity(&self.page_config) + 1);
+        let mut new_free_pages: Vec<PageNo> =
+            page_cache.generate_free_pages(free_page_growth as u64, 0);
         // Reverse the free pages or we add at end of file first.
         new_free_pages.reverse();
         // Grab a free page number to return to the commit before adding to free_dir_page
         let new_free_page = new_free_pages.pop().unwrap();
         last.add_free_pages(&new_free_pages);
+        self.free_page_delta += new_free_pages.len() as i64;
         new_free_page
     }
 
+    // The change in the number of pages in the free page directory,
+    // complete once get_free_dir_pages has been called.
+    pub fn get_free_page_delta(&self) -> i64 {
+        self.free_page_delta
+    }
+
     pub fn get_return_pages(&self) -> Vec<PageNo> {
         self.returned_pages.clone()
     }
@@ -120,9 +135,11 @@ impl FreePageTracker {
         // Add all the returned page numbers to the free_dir_page last.
         while let Some(page_no) = self.returned_pages.pop() {
             if last.is_full() {
+                self.returned_pages.push(page_no);
                 break;
             }
             last.add_free_page(page_no);
+            self.free_page_delta += 1;
         }
 
         // If there are still free page numbers to be added then need to create
@@ -137,9 +154,11 @@ impl FreePageTracker {
             last.set_prev_free_dir_pg(&next_free_dir_page.get_page_number());
             while let Some(page_no) = self.returned_pages.pop() {
                 if next_free_dir_page.is_full() {
+                    self.returned_pages.push(page_no);
                     break;
                 }
                 next_free_dir_page.add_free_page(page_no);
+                self.free_page_delta += 1;
             }
             self.free_dir_page_list.push(next_free_dir_page);
             last = self.free_dir_page_list.last_mut().unwrap();
@@ -178,7 +197,8 @@ mod tests {
             .expect("Failed to open or create DB file");
 
         let version = 0;
-        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, PAGE_CONFIG.block_size);
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, PAGE_CONFIG.block_size)
+            .expect("Failed to create file layer");
         let block_layer: crate::PageContainerLayer =
             crate::PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let mut page_cache: PageCache = PageCache::new(block_layer);
@@ -226,4 +246,38 @@ mod tests {
         assert_eq!(free_page_tracker.get_return_pages().len(), 4);
         std::fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
     }
+
+    #[test]
+    fn test_free_page_growth() {
+        let storage = Box::new(crate::MemoryStorage::new());
+        let file_layer = crate::FileLayer::new_with_storage(storage, PAGE_CONFIG.block_size)
+            .expect("Failed to create file layer");
+        let block_layer = crate::PageContainerLayer::new(file_layer, PAGE_CONFIG);
+        let mut page_cache: PageCache = PageCache::new(block_layer);
+        let free_dir_page_no = *page_cache.generate_free_pages(1, 0).first().unwrap();
+        let mut free_dir_page =
+            FreeDirPage::create_new(page_cache.get_page_config(), free_dir_page_no, 0);
+        page_cache.put_page(free_dir_page.get_page());
+
+        page_cache.set_free_page_growth(100);
+        let mut free_page_tracker = FreePageTracker::new(
+            page_cache.get_page(free_dir_page_no),
+            1,
+            *page_cache.get_page_config(),
+        );
+        free_page_tracker.get_free_page(&mut page_cache);
+        assert_eq!(page_cache.get_total_page_count(), 101);
+        assert_eq!(free_page_tracker.get_free_page_delta(), 99);
+
+        // No more than the free page directory page holds.
+        page_cache.set_free_page_growth(100_000);
+        let mut free_page_tracker = FreePageTracker::new(
+            page_cache.get_page(free_dir_page_no),
+            1,
+            *page_cache.get_page_config(),
+        );
+        free_page_tracker.get_free_page(&mut page_cache);
+        let capacity = FreeDirPage::get_capacity(&PAGE_CONFIG) as u64;
+        assert_eq!(page_cache.get_total_page_count(), 101 + capacity + 1);
+    }
 }
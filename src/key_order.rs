@@ -0,0 +1,86 @@
+use crate::leaf_page::LeafPage;
+use std::cmp::Ordering;
+
+// Compares two keys, see KeyOrder::Comparator.
+pub type KeyComparator = fn(&[u8], &[u8]) -> Ordering;
+
+// The order keys are kept in by the pages of every tree, the global
+// tree, the tables and the table directory, so table names too.
+//
+//   Bytes      - byte by byte, a shorter key before the longer keys it
+//                is a prefix of. The default.
+//   Comparator - the order of a comparator of the application's own,
+//                for keys that do not sort as bytes such as signed
+//                numbers or strings in a collation.
+//
+// The order is not kept in the file. A DB must be opened with the same
+// order every time, changing it or opening with another comparator
+// corrupts the ordering - lookups miss keys that are there, puts store
+// keys a second time and verify reports the pages as unsorted. The
+// comparator must be a total order over all byte strings, give the
+// same answer for the same keys every time and only return Equal for
+// keys that are the same bytes.
+//
+// Keys in byte order between two fence keys share the fences' common
+// prefix, the pages store it once, and a dir page only needs as much of
+// a key as tells it from the key before, see tail_compress_key. Neither
+// holds for a comparator so its pages store whole keys and hold fewer.
+// Oversized keys are compared using their short key, see TupleProcessor.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum KeyOrder {
+    #[default]
+    Bytes,
+    Comparator(KeyComparator),
+}
+
+impl KeyOrder {
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        match self {
+            KeyOrder::Bytes => a.cmp(b),
+            KeyOrder::Comparator(comparator) => comparator(a, b),
+        }
+    }
+
+    pub fn is_bytes(&self) -> bool {
+        matches!(self, KeyOrder::Bytes)
+    }
+
+    // The length of the prefix every key from low to high starts with,
+    // the prefix a page with these fences can leave out of its keys.
+    pub fn prefix_length(&self, low: &[u8], high: &[u8]) -> usize {
+        match self {
+            KeyOrder::Bytes => low.iter().zip(high).take_while(|(a, b)| a == b).count(),
+            KeyOrder::Comparator(_) => 0,
+        }
+    }
+
+    // A key for a dir page that is greater than last_key and no greater
+    // than next_key, to split the keys up to last_key from those from
+    // next_key.
+    pub fn separator(&self, last_key: &[u8], next_key: &[u8]) -> Vec<u8> {
+        match self {
+            KeyOrder::Bytes => LeafPage::tail_compress_key(last_key, next_key),
+            KeyOrder::Comparator(_) => next_key.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_order() {
+        let bytes = KeyOrder::Bytes;
+        assert_eq!(bytes.compare(b"a", b"ab"), Ordering::Less);
+        assert_eq!(bytes.prefix_length(b"abc", b"abd"), 2);
+        assert_eq!(bytes.separator(b"abc", b"abdef"), b"abd".to_vec());
+
+        let reverse = KeyOrder::Comparator(|a, b| b.cmp(a));
+        assert_eq!(reverse.compare(b"a", b"ab"), Ordering::Greater);
+        assert_eq!(reverse.compare(b"a", b"a"), Ordering::Equal);
+        assert_eq!(reverse.prefix_length(b"abc", b"abd"), 0);
+        assert_eq!(reverse.separator(b"abd", b"abcde"), b"abcde".to_vec());
+        assert!(!reverse.is_bytes());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::VersionHolder;
+use crate::key_order::KeyOrder;
 use crate::page::PageTrait;
 use crate::page::PageType;
 use crate::page_no::PageNo;
@@ -11,6 +12,7 @@ use std::cmp::Ordering;
 
 pub struct LeafPage {
     page: Page,
+    key_order: KeyOrder,
 }
 
 impl PageTrait for LeafPage {
@@ -41,13 +43,13 @@ impl PageTrait for LeafPage {
 
 pub struct LeafSlot {
     offset: u16,
-    key_len: u8,
+    key_len: u16,
     val_len: u16,
 }
 
 // Header
 // | Page No (8 bytes) | VersionHolder(8 bytes) | Entries(u16) | Free_Space(u16) |
-// | prefix_length (u8) | left_fence_key_offset (u16) | left_fence_key_size (u8) | right_fence_key_offset (u16) | right_fence_key_size (u8) |
+// | prefix_length (u16) | left_fence_key_offset (u16) | left_fence_key_size (u16) | right_fence_key_offset (u16) | right_fence_key_size (u16) |
 // | slot | slot | free space ...
 // | key | value | key | value | right_fence_key | left_fence_key |
 //
@@ -108,10 +110,32 @@ pub struct LeafSlot {
 // for the for the new page to the right can be "aec". Anything less than "aec" will go to
 // the page to the left.
 //
+// Keys are compared in the KeyOrder of the DB. Both compressions need keys in byte order, with
+// a comparator the prefix length is always zero and the directory entry is the whole smallest key
+// of the page to the right, see KeyOrder.
+//
 
 impl LeafPage {
-    const HEADER_SIZE: usize = 27; // 8 + 8 + 2 + 2 + 1 + 2 +1 + 2 + 1
-    const SLOT_SIZE: usize = 5; // 2 (offset) + 1 (key_len) + 2 (val_len)
+    const HEADER_SIZE: usize = 30; // 8 + 8 + 2 + 2 + 2 + 2 + 2 + 2 + 2
+    const SLOT_SIZE: usize = 6; // 2 (offset) + 2 (key_len) + 2 (val_len)
+
+    // The largest value that fits with a key of key_len in an empty leaf
+    // page with no fences, which is what a tuple too big to share ends
+    // up in once the page it is added to has been split. The value is
+    // stored after the tuple's version, the two together have a u16
+    // length.
+    pub fn max_value_size(page_config: &DbConfig, key_len: usize) -> usize {
+        let page_size = page_config.block_size * page_config.get_leaf_page_blk_cnt() as usize
+            - page_config.block_sanity_size;
+        let space = page_size
+            .saturating_sub(LeafPage::HEADER_SIZE + LeafPage::SLOT_SIZE + key_len)
+            .min(u16::MAX as usize);
+        space.saturating_sub(VersionHolder::SIZE)
+    }
+
+    pub fn can_fit(page_config: &DbConfig, key_len: usize, value_len: usize) -> bool {
+        value_len <= LeafPage::max_value_size(page_config, key_len)
+    }
 
     pub fn create_new(page_config: &DbConfig, page_number: PageNo, version: u64) -> Self {
         if page_number.get_blk_offset() != 0 {
@@ -124,7 +148,10 @@ impl LeafPage {
         page.set_type(PageType::LeafPage);
         page.set_page_number(page_number);
         page.set_version(version);
-        let mut leaf_page = LeafPage { page };
+        let mut leaf_page = LeafPage {
+            page,
+            key_order: page_config.key_order,
+        };
         leaf_page
             .set_free_space(leaf_page.page.get_pg_size() as u16 - LeafPage::HEADER_SIZE as u16);
         leaf_page.set_entries_size(0);
@@ -134,11 +161,11 @@ impl LeafPage {
         leaf_page
     }
 
-    pub fn from_page(page: Page) -> Self {
+    pub fn from_page(page: Page, key_order: KeyOrder) -> Self {
         if page.get_type() != PageType::LeafPage {
             panic!("Page type is not Leaf");
         }
-        LeafPage { page }
+        LeafPage { page, key_order }
     }
 
     fn reset(&mut self, page_size: usize) {
@@ -167,7 +194,7 @@ impl LeafPage {
         self.page.get_page_bytes_mut()[16..18].copy_from_slice(&bytes);
     }
 
-    fn get_free_space(&self) -> u16 {
+    pub fn get_free_space(&self) -> u16 {
         let bytes = &self.page.get_page_bytes()[18..20];
         u16::from_le_bytes(bytes.try_into().unwrap())
     }
@@ -177,8 +204,27 @@ impl LeafPage {
         self.page.get_page_bytes_mut()[18..20].copy_from_slice(&bytes);
     }
 
-    fn get_prefix_length(page: &Page) -> u8 {
-        page.get_page_bytes()[20]
+    // Take size bytes from the free space. Panics rather than letting the
+    // u16 wrap if there is not enough space, a wrapped free space would
+    // corrupt the page.
+    fn reduce_free_space(&mut self, size: usize) {
+        let free_space = u16::try_from(size)
+            .ok()
+            .and_then(|size| self.get_free_space().checked_sub(size))
+            .expect("Not enough free space in page.");
+        self.set_free_space(free_space);
+    }
+
+    // Give size bytes back to the free space.
+    fn increase_free_space(&mut self, size: usize) {
+        let free_space = u16::try_from(self.get_free_space() as usize + size)
+            .expect("Free space larger than page.");
+        self.set_free_space(free_space);
+    }
+
+    fn get_prefix_length(page: &Page) -> u16 {
+        let bytes = &page.get_page_bytes()[20..22];
+        u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
     fn reset_with_new_right_fence(&mut self, new_right_fence: &[u8]) -> bool {
@@ -189,11 +235,7 @@ impl LeafPage {
 
         let prefix_length: usize = if old_prefix_length > 0 {
             // Only set compression if it was already set.
-            left_fence
-                .iter()
-                .zip(new_right_fence)
-                .take_while(|(a, b)| a == b)
-                .count()
+            self.key_order.prefix_length(&left_fence, new_right_fence)
         } else {
             0
         };
@@ -202,7 +244,7 @@ impl LeafPage {
         self.reset(self.get_pg_size());
         self.set_left_fence_key(left_fence.as_ref());
         self.set_right_fence_key(new_right_fence);
-        self.set_prefix_length(prefix_length as u8);
+        self.set_prefix_length(prefix_length as u16);
         for tuple in entties {
             let (ok, _) = self.add_tuple(&tuple);
             if !ok {
@@ -223,11 +265,7 @@ impl LeafPage {
 
         let prefix_length: usize = if old_prefix_length > 0 {
             // Only set compression if it was already set.
-            new_left_fence
-                .iter()
-                .zip(&right_fence)
-                .take_while(|(a, b)| a == b)
-                .count()
+            self.key_order.prefix_length(new_left_fence, &right_fence)
         } else {
             0
         };
@@ -236,7 +274,7 @@ impl LeafPage {
         self.reset(self.get_pg_size());
         self.set_left_fence_key(new_left_fence);
         self.set_right_fence_key(right_fence.as_ref());
-        self.set_prefix_length(prefix_length as u8);
+        self.set_prefix_length(prefix_length as u16);
         for tuple in entries {
             let (ok, _) = self.add_tuple(&tuple);
             if !ok {
@@ -254,31 +292,32 @@ impl LeafPage {
             Self::get_entries_size(&self.page) == 0,
             "Cannot set left fence key on a page that already has entries."
         );
-        let free_space = self.get_free_space() as usize;
-        let offset = self.calculate_entries_offset() - key.len();
+        let entries_offset = self.calculate_entries_offset();
+        self.reduce_free_space(key.len());
+        let offset = entries_offset - key.len();
         self.page.get_page_bytes_mut()[offset..offset + key.len()].copy_from_slice(key);
         let offset_bytes = (offset as u16).to_le_bytes();
-        self.page.get_page_bytes_mut()[21..23].copy_from_slice(&offset_bytes);
-        self.page.get_page_bytes_mut()[23] = key.len() as u8;
-        self.set_free_space(free_space as u16 - key.len() as u16);
+        self.page.get_page_bytes_mut()[22..24].copy_from_slice(&offset_bytes);
+        let size_bytes = (key.len() as u16).to_le_bytes();
+        self.page.get_page_bytes_mut()[24..26].copy_from_slice(&size_bytes);
     }
 
     fn has_left_fence(page: &Page) -> bool {
-        page.get_page_bytes()[23] != 0
+        Self::get_left_fence_key_size(page) != 0
     }
 
-    fn get_left_fence_key_size(page: &Page) -> u8 {
-        page.get_page_bytes()[23]
+    fn get_left_fence_key_size(page: &Page) -> u16 {
+        let bytes = &page.get_page_bytes()[24..26];
+        u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
     fn get_left_fence_key_offset(page: &Page) -> u16 {
-        let bytes = &page.get_page_bytes()[21..23];
+        let bytes = &page.get_page_bytes()[22..24];
         u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
     fn clear_left_fence_key(&mut self) {
-        self.page.get_page_bytes_mut()[21..23].copy_from_slice(&[0, 0]);
-        self.page.get_page_bytes_mut()[23] = 0;
+        self.page.get_page_bytes_mut()[22..26].copy_from_slice(&[0; 4]);
     }
 
     fn get_left_fence_key(page: &Page) -> &[u8] {
@@ -302,31 +341,32 @@ impl LeafPage {
             Self::get_entries_size(&self.page) == 0,
             "Cannot set right fence key on a page that already has entries."
         );
-        let free_space = self.get_free_space() as usize;
-        let offset = self.calculate_entries_offset() - key.len();
+        let entries_offset = self.calculate_entries_offset();
+        self.reduce_free_space(key.len());
+        let offset = entries_offset - key.len();
         self.page.get_page_bytes_mut()[offset..offset + key.len()].copy_from_slice(key);
         let offset_bytes = (offset as u16).to_le_bytes();
-        self.page.get_page_bytes_mut()[24..26].copy_from_slice(&offset_bytes);
-        self.page.get_page_bytes_mut()[26] = key.len() as u8;
-        self.set_free_space(free_space as u16 - key.len() as u16);
+        self.page.get_page_bytes_mut()[26..28].copy_from_slice(&offset_bytes);
+        let size_bytes = (key.len() as u16).to_le_bytes();
+        self.page.get_page_bytes_mut()[28..30].copy_from_slice(&size_bytes);
     }
 
     fn has_right_fence(page: &Page) -> bool {
-        page.get_page_bytes()[26] != 0
+        Self::get_right_fence_key_size(page) != 0
     }
 
     fn get_right_fence_key_offset(page: &Page) -> u16 {
-        let bytes = &page.get_page_bytes()[24..26];
+        let bytes = &page.get_page_bytes()[26..28];
         u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
     fn clear_right_fence_key(&mut self) {
-        self.page.get_page_bytes_mut()[24..26].copy_from_slice(&[0, 0]);
-        self.page.get_page_bytes_mut()[26] = 0;
+        self.page.get_page_bytes_mut()[26..30].copy_from_slice(&[0; 4]);
     }
 
-    fn get_right_fence_key_size(page: &Page) -> u8 {
-        page.get_page_bytes()[26]
+    fn get_right_fence_key_size(page: &Page) -> u16 {
+        let bytes = &page.get_page_bytes()[28..30];
+        u16::from_le_bytes(bytes.try_into().unwrap())
     }
 
     fn get_right_fence_key(page: &Page) -> &[u8] {
@@ -335,7 +375,7 @@ impl LeafPage {
         &page.get_page_bytes()[offset..offset + size]
     }
 
-    fn set_prefix_length(&mut self, prefix_length: u8) {
+    fn set_prefix_length(&mut self, prefix_length: u16) {
         assert!(
             Self::get_entries_size(&self.page) == 0,
             "Cannot set prefix length on a page that already has entries."
@@ -344,7 +384,7 @@ impl LeafPage {
             prefix_length <= Self::get_right_fence_key_size(&self.page),
             "Prefix length cannot be larger than the right fence key size."
         );
-        self.page.get_page_bytes_mut()[20] = prefix_length;
+        self.page.get_page_bytes_mut()[20..22].copy_from_slice(&prefix_length.to_le_bytes());
     }
 
     fn get_slot_at_index(page: &Page, index: usize) -> LeafSlot {
@@ -352,8 +392,9 @@ impl LeafPage {
         let slot_offset = LeafPage::HEADER_SIZE + index * LeafPage::SLOT_SIZE;
         let offset_bytes = &page.get_page_bytes()[slot_offset..slot_offset + 2];
         let offset = u16::from_le_bytes(offset_bytes.try_into().unwrap());
-        let key_len = page.get_page_bytes()[slot_offset + 2];
-        let val_len_bytes = &page.get_page_bytes()[slot_offset + 3..slot_offset + 5];
+        let key_len_bytes = &page.get_page_bytes()[slot_offset + 2..slot_offset + 4];
+        let key_len = u16::from_le_bytes(key_len_bytes.try_into().unwrap());
+        let val_len_bytes = &page.get_page_bytes()[slot_offset + 4..slot_offset + 6];
         let val_len = u16::from_le_bytes(val_len_bytes.try_into().unwrap());
         LeafSlot {
             offset,
@@ -366,14 +407,16 @@ impl LeafPage {
         let slot_offset = LeafPage::HEADER_SIZE + index * LeafPage::SLOT_SIZE;
         let offset_bytes = slot.offset.to_le_bytes();
         self.page.get_page_bytes_mut()[slot_offset..slot_offset + 2].copy_from_slice(&offset_bytes);
-        self.page.get_page_bytes_mut()[slot_offset + 2] = slot.key_len;
+        let key_len_bytes = slot.key_len.to_le_bytes();
+        self.page.get_page_bytes_mut()[slot_offset + 2..slot_offset + 4]
+            .copy_from_slice(&key_len_bytes);
         let val_len_bytes = slot.val_len.to_le_bytes();
-        self.page.get_page_bytes_mut()[slot_offset + 3..slot_offset + 5]
+        self.page.get_page_bytes_mut()[slot_offset + 4..slot_offset + 6]
             .copy_from_slice(&val_len_bytes);
     }
 
     fn get_value_at_slot<'a>(page: &'a Page, slot: &LeafSlot) -> &'a [u8] {
-        let val_offset = (slot.offset + slot.key_len as u16) as usize;
+        let val_offset = (slot.offset + slot.key_len) as usize;
         &page.get_page_bytes()[val_offset..val_offset + slot.val_len as usize]
     }
 
@@ -390,7 +433,7 @@ impl LeafPage {
         &Self::get_left_fence_key(page)[0..prefix_length]
     }
 
-    fn get_index_for_key(page: &Page, key_suffix: &[u8]) -> (bool, usize) {
+    fn get_index_for_key(page: &Page, key_suffix: &[u8], key_order: KeyOrder) -> (bool, usize) {
         let entries = Self::get_entries_size(page) as usize;
 
         // binary search for the key suffix in the slots
@@ -402,7 +445,7 @@ impl LeafPage {
             let slot = Self::get_slot_at_index(page, mid);
             let key_at_slot = Self::get_key_at_slot(page, &slot);
 
-            match key_suffix.cmp(key_at_slot) {
+            match key_order.compare(key_suffix, key_at_slot) {
                 Ordering::Less => high = mid, // Needle is smaller, look in the left half
                 Ordering::Equal => return (true, mid),
                 Ordering::Greater => low = mid + 1, // Needle is larger, look in the right half
@@ -447,7 +490,12 @@ impl LeafPage {
         // originally belonged to that page is added again and is now routed to this page
         // so need to account for this.
         // Always rebuild if we have a right fence and key is larger than right fence.
-        if Self::has_right_fence(&self.page) && tuple_key > Self::get_right_fence_key(&self.page) {
+        if Self::has_right_fence(&self.page)
+            && self
+                .key_order
+                .compare(tuple_key, Self::get_right_fence_key(&self.page))
+                == Ordering::Greater
+        {
             if !self.reset_with_new_right_fence(tuple_key) {
                 // Reset failed as cannot rebuild same page with new compression as not enough space.
                 // Trigger a split first. Note as the key is bigger than the right fence we know we
@@ -461,7 +509,12 @@ impl LeafPage {
         // This is needed as we are using tail compression in the dir pages.
         // The dir page holds a truncated version of the left most key only,
         // to it can send tuples here that are less than the left most key.
-        if Self::has_left_fence(&self.page) && tuple_key < Self::get_left_fence_key(&self.page) {
+        if Self::has_left_fence(&self.page)
+            && self
+                .key_order
+                .compare(tuple_key, Self::get_left_fence_key(&self.page))
+                == Ordering::Less
+        {
             if !self.reset_with_new_left_fence(tuple_key) {
                 // Reset failed as cannot rebuild same page with new compression as not enough space.
                 // Trigger a split first. Note as the key is bigger than the right fence we know we
@@ -483,7 +536,7 @@ impl LeafPage {
             );
         }
         let key_suffix = &tuple_key[prefix_length..];
-        let (found, index) = Self::get_index_for_key(&self.page, key_suffix);
+        let (found, index) = Self::get_index_for_key(&self.page, key_suffix, self.key_order);
 
         let mut existing_tuple: Option<Tuple> = None;
         if found {
@@ -493,7 +546,7 @@ impl LeafPage {
                 // If the new value has the same length as the old value, we can just overwrite the value in place
                 // without needing to shift entries around.
                 // TODO - if size is less we could also just overwrite and leave some unused space.
-                let val_offset = (slot.offset + slot.key_len as u16) as usize;
+                let val_offset = (slot.offset + slot.key_len) as usize;
                 self.page.get_page_bytes_mut()[val_offset..val_offset + slot.val_len as usize]
                     .copy_from_slice(tuple.get_version_value());
                 return (true, existing_tuple);
@@ -528,8 +581,10 @@ impl LeafPage {
         // Sanity check
         let new_entry_size = key.len() + value.len();
         let new_entry_total_size = new_entry_size + LeafPage::SLOT_SIZE;
-        let free_space = self.get_free_space() as usize;
-        assert!(new_entry_total_size <= free_space);
+        assert!(
+            new_entry_total_size <= self.get_free_space() as usize,
+            "Not enough free space in page."
+        );
 
         // Find offset where the key/value entry can be added.
         let entries = Self::get_entries_size(&self.page) as usize;
@@ -546,7 +601,7 @@ impl LeafPage {
         // Create a slot and add it.
         let slot = LeafSlot {
             offset: new_entry_offset as u16,
-            key_len: key.len() as u8,
+            key_len: key.len() as u16,
             val_len: value.len() as u16,
         };
         self.shift_slots_right_from(index);
@@ -554,19 +609,23 @@ impl LeafPage {
 
         // Update entries and free space.
         self.set_entries_size((entries + 1) as u16);
-        self.set_free_space(free_space as u16 - new_entry_total_size as u16);
+        self.reduce_free_space(new_entry_total_size);
     }
 
-    pub fn get_tuple_from_page(page: &Page, key: &[u8]) -> Option<Tuple> {
-        if Self::has_right_fence(page) && key > Self::get_right_fence_key(page) {
+    pub fn get_tuple_from_page(page: &Page, key: &[u8], key_order: KeyOrder) -> Option<Tuple> {
+        if Self::has_right_fence(page)
+            && key_order.compare(key, Self::get_right_fence_key(page)) == Ordering::Greater
+        {
             return None;
         }
-        if Self::has_left_fence(page) && key < Self::get_left_fence_key(page) {
+        if Self::has_left_fence(page)
+            && key_order.compare(key, Self::get_left_fence_key(page)) == Ordering::Less
+        {
             return None;
         }
 
         let prefix_length = Self::get_prefix_length(page) as usize;
-        let (found, index) = Self::get_index_for_key(page, &key[prefix_length..]);
+        let (found, index) = Self::get_index_for_key(page, &key[prefix_length..], key_order);
         if !found {
             return None;
         }
@@ -574,7 +633,7 @@ impl LeafPage {
     }
 
     pub fn get_tuple(&self, key: &[u8]) -> Option<Tuple> {
-        Self::get_tuple_from_page(&self.page, key)
+        Self::get_tuple_from_page(&self.page, key, self.key_order)
     }
 
     fn get_tuple_at_index(page: &Page, index: usize) -> Tuple {
@@ -616,6 +675,30 @@ impl LeafPage {
         full_key
     }
 
+    // Full keys in the page, ie with any prefix added back.
+    pub fn get_all_keys(&self) -> Vec<Vec<u8>> {
+        (0..Self::get_entries_size(&self.page) as usize)
+            .map(|i| self.get_key_at_index(i))
+            .collect()
+    }
+
+    // The tuple with the smallest key, None if the page is empty.
+    pub fn get_first_tuple(&self) -> Option<Tuple> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(Self::get_tuple_at_index(&self.page, 0))
+    }
+
+    // The tuple with the largest key, None if the page is empty.
+    pub fn get_last_tuple(&self) -> Option<Tuple> {
+        let entries = Self::get_entries_size(&self.page) as usize;
+        if entries == 0 {
+            return None;
+        }
+        Some(Self::get_tuple_at_index(&self.page, entries - 1))
+    }
+
     pub fn get_all_tuples(&self) -> Vec<Tuple> {
         let entries = Self::get_entries_size(&self.page);
         let mut tuples = Vec::new();
@@ -661,7 +744,7 @@ impl LeafPage {
             left_page.add_key_value_at_index(i, key, value);
         }
 
-        let split_key = LeafPage::tail_compress_key(left_page_right_fence_key, mid_key);
+        let split_key = self.key_order.separator(left_page_right_fence_key, mid_key);
 
         right_page.set_left_fence_key(mid_key);
         for i in mid..entries {
@@ -704,17 +787,13 @@ impl LeafPage {
             left_page.add_key_value_at_index(i, key, value);
         }
 
-        let split_key = LeafPage::tail_compress_key(left_page_right_fence_key, mid_key);
+        let split_key = self.key_order.separator(left_page_right_fence_key, mid_key);
 
         let right_fence_key = Self::get_right_fence_key(&self.page);
         right_page.set_right_fence_key(right_fence_key);
         right_page.set_left_fence_key(mid_key);
-        let right_prefix_length = mid_key
-            .iter()
-            .zip(right_fence_key)
-            .take_while(|(a, b)| a == b)
-            .count();
-        right_page.set_prefix_length(right_prefix_length as u8);
+        let right_prefix_length = self.key_order.prefix_length(mid_key, right_fence_key);
+        right_page.set_prefix_length(right_prefix_length as u16);
         for i in mid..entries {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             right_page.add_key_value_at_index(i - mid, &key[right_prefix_length..], value);
@@ -747,19 +826,17 @@ impl LeafPage {
         let left_page_left_fence_key = Self::get_left_fence_key(&self.page);
         left_page.set_left_fence_key(left_page_left_fence_key);
         left_page.set_right_fence_key(left_page_right_fence_key);
-        let left_prefix_length = left_page_left_fence_key
-            .iter()
-            .zip(left_page_right_fence_key)
-            .take_while(|(a, b)| a == b)
-            .count();
-        left_page.set_prefix_length(left_prefix_length as u8);
+        let left_prefix_length = self
+            .key_order
+            .prefix_length(left_page_left_fence_key, left_page_right_fence_key);
+        left_page.set_prefix_length(left_prefix_length as u16);
         for i in 0..mid {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             // This should avoid moving bytes around - we will be appending slots.
             left_page.add_key_value_at_index(i, &key[left_prefix_length..], value);
         }
 
-        let split_key = LeafPage::tail_compress_key(left_page_right_fence_key, mid_key);
+        let split_key = self.key_order.separator(left_page_right_fence_key, mid_key);
 
         // Create page to the right.
         right_page.set_left_fence_key(mid_key);
@@ -794,30 +871,26 @@ impl LeafPage {
         let left_page_left_fence_key = Self::get_left_fence_key(&self.page);
         left_page.set_left_fence_key(left_page_left_fence_key);
         left_page.set_right_fence_key(last_key.as_slice());
-        let left_prefix_length = left_page_left_fence_key
-            .iter()
-            .zip(last_key.as_slice())
-            .take_while(|(a, b)| a == b)
-            .count();
+        let left_prefix_length = self
+            .key_order
+            .prefix_length(left_page_left_fence_key, &last_key);
         let left_suffix_offset = left_prefix_length - Self::get_prefix_length(&self.page) as usize;
-        left_page.set_prefix_length(left_prefix_length as u8);
+        left_page.set_prefix_length(left_prefix_length as u16);
         for i in 0..mid {
             let (key, value) = self.get_key_suffix_and_value_at_index(i);
             // This should avoid moving bytes around - we will be appending slots.
             left_page.add_key_value_at_index(i, &key[left_suffix_offset..], value);
         }
 
-        let split_key = LeafPage::tail_compress_key(&last_key, &mid_key);
+        let split_key = self.key_order.separator(&last_key, &mid_key);
 
         right_page.set_left_fence_key(&mid_key);
         let right_page_right_fence_key = Self::get_right_fence_key(&self.page);
         right_page.set_right_fence_key(right_page_right_fence_key);
-        let right_prefix_length = mid_key
-            .iter()
-            .zip(right_page_right_fence_key)
-            .take_while(|(a, b)| a == b)
-            .count();
-        right_page.set_prefix_length(right_prefix_length as u8);
+        let right_prefix_length = self
+            .key_order
+            .prefix_length(&mid_key, right_page_right_fence_key);
+        right_page.set_prefix_length(right_prefix_length as u16);
         let right_suffix_offset =
             right_prefix_length - Self::get_prefix_length(&self.page) as usize;
         for i in mid..entries {
@@ -914,15 +987,26 @@ impl LeafPage {
      *
      */
     pub fn delete_key(&mut self, key: &[u8]) -> Option<Tuple> {
-        if Self::has_right_fence(&self.page) && key > Self::get_right_fence_key(&self.page) {
+        if Self::has_right_fence(&self.page)
+            && self
+                .key_order
+                .compare(key, Self::get_right_fence_key(&self.page))
+                == Ordering::Greater
+        {
             return None;
         }
-        if Self::has_left_fence(&self.page) && key < Self::get_left_fence_key(&self.page) {
+        if Self::has_left_fence(&self.page)
+            && self
+                .key_order
+                .compare(key, Self::get_left_fence_key(&self.page))
+                == Ordering::Less
+        {
             return None;
         }
 
         let prefix_length = Self::get_prefix_length(&self.page) as usize;
-        let (found, index) = Self::get_index_for_key(&self.page, &key[prefix_length..]);
+        let (found, index) =
+            Self::get_index_for_key(&self.page, &key[prefix_length..], self.key_order);
         if !found {
             return None;
         }
@@ -959,7 +1043,7 @@ impl LeafPage {
         if entry_offset == entries_offset {
             // No Head, just shift the tail to the left.
             // If the entry to remove is the last entry, we can just update the free space and entries without shifting.
-            self.set_free_space((free_space + entry_size + LeafPage::SLOT_SIZE) as u16);
+            self.increase_free_space(entry_size + LeafPage::SLOT_SIZE);
             self.set_entries_size((new_entry_count) as u16);
             return;
         }
@@ -988,7 +1072,7 @@ impl LeafPage {
 
         // Update entries and free space.
         self.set_entries_size(new_entry_count as u16);
-        self.set_free_space((free_space + entry_size + LeafPage::SLOT_SIZE) as u16);
+        self.increase_free_space(entry_size + LeafPage::SLOT_SIZE);
     }
 }
 
@@ -1027,7 +1111,29 @@ mod tests {
             .build();
         let mut dir_page = Page::new(page_config.block_size, page_config.page_size);
         dir_page.set_type(PageType::DirPage);
-        let _leaf_page = LeafPage::from_page(dir_page);
+        let _leaf_page = LeafPage::from_page(dir_page, page_config.key_order);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough free space in page.")]
+    fn test_add_entry_larger_than_free_space() {
+        let page_config = DbConfig::builder()
+            .block_size(256)
+            .block_sanity_size(0)
+            .build();
+        let mut leaf_page = LeafPage::create_new(&page_config, PageNo::new(0, 1), 0);
+        leaf_page.add_key_value_at_index(0, b"key", &[0u8; 300]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough free space in page.")]
+    fn test_fence_key_larger_than_free_space() {
+        let page_config = DbConfig::builder()
+            .block_size(128)
+            .block_sanity_size(0)
+            .build();
+        let mut leaf_page = LeafPage::create_new(&page_config, PageNo::new(0, 1), 0);
+        leaf_page.set_left_fence_key(&[1u8; 200]);
     }
 
     #[test]
@@ -1221,8 +1327,8 @@ mod tests {
     fn test_page_reset_left_fence_overflow() {
         let page_config = DbConfig::builder()
             .block_size(4096)
-            .page_size(129)
-            .block_sanity_size(4096 - 129)
+            .page_size(134)
+            .block_sanity_size(4096 - 134)
             .compressor_type(crate::compressor::CompressorType::None)
             .leaf_page_blk_exp(0)
             .dir_page_blk_exp(0)
@@ -1247,8 +1353,8 @@ mod tests {
     fn test_add_page_reset_left_fence_overflow() {
         let page_config = DbConfig::builder()
             .block_size(4096)
-            .page_size(129)
-            .block_sanity_size(4096 - 129)
+            .page_size(134)
+            .block_sanity_size(4096 - 134)
             .compressor_type(crate::compressor::CompressorType::None)
             .leaf_page_blk_exp(0)
             .dir_page_blk_exp(0)
@@ -1274,8 +1380,8 @@ mod tests {
     fn test_page_reset_right_fence_overflow() {
         let page_config = DbConfig::builder()
             .block_size(4096)
-            .page_size(129)
-            .block_sanity_size(4096 - 129)
+            .page_size(134)
+            .block_sanity_size(4096 - 134)
             .compressor_type(crate::compressor::CompressorType::None)
             .leaf_page_blk_exp(0)
             .dir_page_blk_exp(0)
@@ -1721,8 +1827,8 @@ mod tests {
     fn test_reset_to_small_to_reset() {
         let page_config = DbConfig::builder()
             .block_size(4096)
-            .page_size(125)
-            .block_sanity_size(4096 - 125)
+            .page_size(132)
+            .block_sanity_size(4096 - 132)
             .compressor_type(crate::compressor::CompressorType::None)
             .leaf_page_blk_exp(0)
             .dir_page_blk_exp(0)
@@ -1763,8 +1869,8 @@ mod tests {
     fn test_reset_to_small_after_reset_to_add_tuple() {
         let page_config = DbConfig::builder()
             .block_size(4096)
-            .page_size(129)
-            .block_sanity_size(4096 - 129)
+            .page_size(136)
+            .block_sanity_size(4096 - 136)
             .compressor_type(crate::compressor::CompressorType::None)
             .leaf_page_blk_exp(0)
             .dir_page_blk_exp(0)
@@ -1800,4 +1906,50 @@ mod tests {
         assert_eq!(LeafPage::get_left_fence_key(leaf_page.get_page()), &key1);
         assert_eq!(LeafPage::get_right_fence_key(leaf_page.get_page()), &key5);
     }
+
+    #[test]
+    fn test_long_keys_and_prefix() {
+        // Key lengths and the prefix length are u16 so keys over 255
+        // bytes can be stored.
+        let page_config = DbConfig::builder()
+            .block_size(4096)
+            .page_size(4092)
+            .block_sanity_size(4)
+            .compressor_type(crate::compressor::CompressorType::None)
+            .leaf_page_blk_exp(0)
+            .dir_page_blk_exp(0)
+            .build();
+
+        let mut keys = Vec::new();
+        for i in 1..5u8 {
+            let mut key = vec![b'k'; 300];
+            key.push(i);
+            keys.push(key);
+        }
+        let mut leaf_page = LeafPage::create_new(&page_config, PageNo::new(0, 1), 0);
+        leaf_page.set_left_fence_key(&keys[0]);
+        leaf_page.set_right_fence_key(&keys[3]);
+        leaf_page.set_prefix_length(300);
+        for key in &keys {
+            assert!(leaf_page.add_tuple(&Tuple::new(key, b"value", 123)).0);
+        }
+        assert_eq!(LeafPage::get_prefix_length(leaf_page.get_page()), 300);
+        assert_eq!(LeafPage::get_left_fence_key(leaf_page.get_page()), &keys[0]);
+        assert_eq!(
+            LeafPage::get_right_fence_key(leaf_page.get_page()),
+            &keys[3]
+        );
+        assert_eq!(leaf_page.get_all_keys(), keys);
+        for key in &keys {
+            let tuple = leaf_page.get_tuple(key).unwrap();
+            assert_eq!(tuple.get_key(), key.as_slice());
+            assert_eq!(tuple.get_value(), b"value");
+        }
+
+        // A key with no prefix that is longer than 255 bytes.
+        let mut leaf_page = LeafPage::create_new(&page_config, PageNo::new(0, 1), 0);
+        let key = vec![b'x'; 1000];
+        assert!(leaf_page.add_tuple(&Tuple::new(&key, b"value", 123)).0);
+        assert_eq!(leaf_page.get_tuple(&key).unwrap().get_key(), key.as_slice());
+    }
 }
@@ -4,6 +4,7 @@ use crate::leaf_page::LeafPage;
 use crate::page::PageTrait;
 use crate::page_cache::PageCache;
 use crate::tuple::{Tuple, TupleTrait};
+use std::cmp::Ordering;
 
 pub struct LeafPageHandler {}
 
@@ -50,6 +51,16 @@ impl LeafPageHandler {
         // LeafPageHandler::add_to_leaf_page to be called recursively if
         // pages need to be split.
         let deleted_tuple = LeafPageHandler::add_to_leaf_page(db_config, tuple, &mut pages);
+        // The pages can come back out of order after a second split. Put
+        // them in key order, this also puts the page replacing the original
+        // page (left key of None) first which the parent dir page relies on.
+        let key_order = db_config.key_order;
+        pages.sort_by(
+            |(_, left_key_a), (_, left_key_b)| match (left_key_a, left_key_b) {
+                (Some(left_key_a), Some(left_key_b)) => key_order.compare(left_key_a, left_key_b),
+                _ => left_key_a.is_some().cmp(&left_key_b.is_some()),
+            },
+        );
         UpdateResult {
             tree_leaf_pages: pages,
             deleted_tuple,
@@ -111,9 +122,17 @@ impl LeafPageHandler {
         if right_page.is_empty() {
             let (ok, _) = right_page.add_tuple(&tuple);
             assert!(ok);
-            // Push the two pages back on the stack to be written back.
-            leaf_page_stack.push((left_page, copy_page_left_key));
-            leaf_page_stack.push((right_page, Some(tuple.get_key().to_vec())));
+            // Push the two pages back on the stack to be written back. If the
+            // tuple is before the entry in the left page then the page with
+            // the tuple takes the place of the original page.
+            let entry_key = left_page.get_left_key().unwrap();
+            if db_config.key_order.compare(tuple.get_key(), &entry_key) == Ordering::Less {
+                leaf_page_stack.push((right_page, copy_page_left_key));
+                leaf_page_stack.push((left_page, Some(entry_key)));
+            } else {
+                leaf_page_stack.push((left_page, copy_page_left_key));
+                leaf_page_stack.push((right_page, Some(tuple.get_key().to_vec())));
+            }
             return existing_tuple;
         }
 
@@ -122,7 +141,7 @@ impl LeafPageHandler {
         let left_key = optional_left_key.unwrap();
 
         // Tuple is to the left of the slit key so try and add to the left page.
-        if tuple.get_key() < left_key.as_slice() {
+        if db_config.key_order.compare(tuple.get_key(), &left_key) == Ordering::Less {
             let (ok, _) = left_page.add_tuple(&tuple);
             if ok {
                 // Order does not matter as pages won't be split.
@@ -235,9 +254,9 @@ mod tests {
 
         assert!(pages.tree_leaf_pages.len() == 2);
         let tree_leaf_page1 = pages.tree_leaf_pages.pop().unwrap().0;
-        assert_eq!(tree_leaf_page1.get_no_page_entries(), 98);
+        assert_eq!(tree_leaf_page1.get_no_page_entries(), 93);
         let tree_leaf_page2 = pages.tree_leaf_pages.pop().unwrap().0;
-        assert_eq!(tree_leaf_page2.get_no_page_entries(), 96);
+        assert_eq!(tree_leaf_page2.get_no_page_entries(), 92);
     }
 
     // In this test we overwrite the same key twice.
@@ -421,4 +440,65 @@ mod tests {
         }
         assert_eq!(tree_leaf_page_count, 3);
     }
+
+    #[test]
+    fn test_split_single_entry_page_smaller_key() {
+        let page_config = DbConfig::builder()
+            .block_size(2048)
+            .block_sanity_size(0)
+            .compressor_type(crate::compressor::CompressorType::None)
+            .build();
+        let mut leaf_page = LeafPage::create_new(&page_config, PageNo::new(0, 0), 0);
+        let (ok, _) = leaf_page.add_tuple(&Tuple::new(b"b", &[0u8; 1200], 1));
+        assert!(ok);
+        // Does not fit with "b", and as the only entry the page is split
+        // with "a" going into the new page.
+        let pages =
+            LeafPageHandler::add_tuple(&page_config, leaf_page, Tuple::new(b"a", &[0u8; 1200], 2));
+        assert_eq!(pages.tree_leaf_pages.len(), 2);
+        // The page with "a" replaces the original page.
+        let (first, first_left_key) = &pages.tree_leaf_pages[0];
+        assert_eq!(first_left_key, &None);
+        assert_eq!(first.get_left_key(), Some(b"a".to_vec()));
+        let (second, second_left_key) = &pages.tree_leaf_pages[1];
+        assert_eq!(second_left_key, &Some(b"b".to_vec()));
+        assert_eq!(second.get_left_key(), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_three_way_split_pages_in_key_order() {
+        let page_config = DbConfig::builder()
+            .block_size(4096)
+            .page_size(4092)
+            .block_sanity_size(4)
+            .compressor_type(crate::compressor::CompressorType::None)
+            .build();
+        let mut leaf_page = LeafPage::create_new(&page_config, PageNo::new(0, 0), 0);
+        for (key, size) in [(b"b", 1100), (b"d", 2048)] {
+            let (ok, _) = leaf_page.add_tuple(&Tuple::new(key, &vec![0u8; size], 1));
+            assert!(ok);
+        }
+        // "c" goes to the left of "d" but does not fit with "b" so the
+        // left page is split again.
+        let pages =
+            LeafPageHandler::add_tuple(&page_config, leaf_page, Tuple::new(b"c", &[0u8; 3000], 2));
+        let left_keys: Vec<Option<Vec<u8>>> = pages
+            .tree_leaf_pages
+            .iter()
+            .map(|(_, left_key)| left_key.clone())
+            .collect();
+        assert_eq!(
+            left_keys,
+            vec![None, Some(b"c".to_vec()), Some(b"d".to_vec())]
+        );
+        let first_keys: Vec<Vec<u8>> = pages
+            .tree_leaf_pages
+            .iter()
+            .map(|(page, _)| page.get_left_key().unwrap())
+            .collect();
+        assert_eq!(
+            first_keys,
+            vec![b"b".to_vec(), b"c".to_vec(), b"d".to_vec()]
+        );
+    }
 }
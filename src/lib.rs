@@ -41,12 +41,20 @@ pub mod tuple_processor;
 pub use tuple_processor::TupleProcessor;
 pub mod xxhash_sanity;
 pub use xxhash_sanity::XxHashSanity;
+pub mod crc32c_sanity;
+pub use crc32c_sanity::Crc32cSanity;
+#[cfg(feature = "encryption")]
 pub mod aes_128_gcm_sanity;
+#[cfg(feature = "encryption")]
 pub use aes_128_gcm_sanity::Aes128GcmSanity;
+#[cfg(feature = "chacha20")]
+pub mod chacha20_poly1305_sanity;
+#[cfg(feature = "chacha20")]
+pub use chacha20_poly1305_sanity::ChaCha20Poly1305Sanity;
 pub mod compressor;
 pub use compressor::Compressor;
 pub mod block_sanity;
-pub use block_sanity::BlockSanity;
+pub use block_sanity::{BlockSanity, BlockSanityProvider};
 pub mod tree_delete_handler;
 pub use tree_delete_handler::TreeDeleteHandler;
 pub mod clear_handler;
@@ -59,3 +67,73 @@ pub mod tx_ctx;
 pub use tx_ctx::TxCtx;
 pub mod page_no;
 pub use page_no::PageNo;
+pub mod observer;
+pub use observer::Observer;
+pub mod corruption;
+pub use corruption::Corruption;
+pub mod page_dump;
+pub use page_dump::PageDump;
+pub mod tree_scan_handler;
+pub use tree_scan_handler::TreeScanHandler;
+pub mod codec;
+pub use codec::Codec;
+pub mod typed_db;
+pub use typed_db::TypedDb;
+pub mod table_info;
+pub use table_info::TableInfo;
+pub mod tree_level;
+pub use tree_level::TreeLevel;
+pub mod sync_mode;
+pub use sync_mode::SyncMode;
+pub mod table_dir_entry;
+pub use table_dir_entry::TableDirEntry;
+pub mod table_root_cache;
+pub use table_root_cache::TableRootCache;
+pub mod compact_handler;
+pub use compact_handler::CompactHandler;
+pub mod tree_builder;
+pub use tree_builder::TreeBuilder;
+pub mod compact_target;
+pub use compact_target::CompactTarget;
+pub mod storage;
+pub use storage::Storage;
+pub mod memory_storage;
+pub use memory_storage::MemoryStorage;
+#[cfg(feature = "mmap")]
+pub mod mmap_storage;
+#[cfg(feature = "mmap")]
+pub use mmap_storage::MmapStorage;
+#[cfg(feature = "serde")]
+pub mod serde_format;
+#[cfg(feature = "serde")]
+pub use serde_format::{Bincode, SerdeFormat};
+pub mod page_pool;
+pub use page_pool::PagePool;
+pub mod compact_in_place_handler;
+pub use compact_in_place_handler::CompactInPlaceHandler;
+pub mod read_tx;
+pub use read_tx::ReadTx;
+pub mod batch_op;
+pub use batch_op::BatchOp;
+pub mod digby_error;
+pub use digby_error::DigbyError;
+pub mod db_options;
+pub use db_options::DbOptions;
+pub mod snapshot;
+pub use snapshot::Snapshot;
+pub mod verify_report;
+pub use verify_report::VerifyReport;
+pub mod verify_handler;
+pub use verify_handler::VerifyHandler;
+pub mod reader;
+pub use reader::Reader;
+pub mod nonce_mode;
+pub use nonce_mode::NonceMode;
+pub mod dump_handler;
+pub use dump_handler::{DumpHandler, DumpRecord};
+pub mod csv_reader;
+pub use csv_reader::{CsvEncoding, CsvReader, CsvRow};
+pub mod cursor;
+pub use cursor::Cursor;
+pub mod key_order;
+pub use key_order::{KeyComparator, KeyOrder};
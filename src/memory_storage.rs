@@ -0,0 +1,116 @@
+use crate::storage::Storage;
+use std::sync::{Arc, Mutex};
+
+// Storage that keeps the blocks in memory rather than in a file, so a
+// whole Db can run without touching the disk, for tests:
+//
+//   let storage = MemoryStorage::new();
+//   let db = Db::new_with_storage(Box::new(storage.clone()), None, CompressorType::None, 4096);
+//
+// Clones share the same bytes, a clone kept by the caller can be given
+// to a new Db once the first is dropped, as reopening the file would,
+// or its bytes copied out with to_vec. Syncs do nothing, nothing
+// survives the process.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+
+    // Start from the bytes of a DB file, read in or from another
+    // MemoryStorage.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        MemoryStorage {
+            bytes: Arc::new(Mutex::new(bytes)),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let bytes = self.bytes.lock().unwrap();
+        let start = offset as usize;
+        let Some(src) = bytes.get(start..start + buf.len()) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        };
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    // Writing past the end fills the gap with zeros, as a file does.
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        let mut bytes = self.bytes.lock().unwrap();
+        let start = offset as usize;
+        if bytes.len() < start + buf.len() {
+            bytes.resize(start + buf.len(), 0);
+        }
+        bytes[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn len(&self) -> std::io::Result<u64> {
+        Ok(self.bytes.lock().unwrap().len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.bytes.lock().unwrap().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    // Nothing to give back, the bytes are zeroed as a file's would be.
+    fn punch_hole(&mut self, offset: u64, len: u64) -> std::io::Result<()> {
+        let mut bytes = self.bytes.lock().unwrap();
+        let end = ((offset + len) as usize).min(bytes.len());
+        let start = (offset as usize).min(end);
+        bytes[start..end].fill(0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_storage_write_read() {
+        let mut storage = MemoryStorage::new();
+        assert!(storage.is_empty().unwrap());
+        storage.write_all_at(b"world", 5).unwrap();
+        storage.write_all_at(b"hello", 0).unwrap();
+        assert_eq!(storage.len().unwrap(), 10);
+        let mut buf = [0u8; 10];
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"helloworld");
+        assert!(storage.read_exact_at(&mut buf, 5).is_err());
+
+        // Clones share the bytes.
+        let mut clone = storage.clone();
+        clone.set_len(5).unwrap();
+        assert_eq!(storage.len().unwrap(), 5);
+        assert_eq!(storage.to_vec(), b"hello");
+        storage.write_all_at(b"!", 7).unwrap();
+        assert_eq!(
+            MemoryStorage::from_bytes(clone.to_vec()).to_vec(),
+            b"hello\0\0!"
+        );
+    }
+}
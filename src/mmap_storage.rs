@@ -0,0 +1,136 @@
+use crate::storage::Storage;
+use memmap2::Mmap;
+
+// Storage for the DB file that reads blocks from a memory map of the
+// file rather than with a seek and read for each, for a DB whose
+// working set fits in memory. A read is a copy out of the map. Opened
+// with DbOptions::mmap.
+//
+// Writes and syncs go to the file as they do without the map, so a
+// commit syncs its pages then its master page as before and the crash
+// consistency of the DB is the same. The map is shared with the file,
+// the OS keeps it in step with the writes. It is not grown as blocks are
+// appended to the file, the first read past the end of the map maps the
+// file again at its new length. Cutting the file down drops the map
+// first.
+//
+// Things to know before using it:
+//   - A read of the map that fails, an I/O error or a block that is no
+//     longer in the file, is a SIGBUS that kills the process rather than
+//     an error returned. Another process cutting the file down, as
+//     Db::compact_in_place does, while a read only Db has it mapped is
+//     such a read.
+//   - The file is mapped whole, on a 32 bit target the DB must fit in
+//     the address space.
+//   - The pages of the map count towards the memory of the process but
+//     are the OS's page cache, they are dropped under memory pressure
+//     and read again from the file when next used.
+pub struct MmapStorage {
+    file: std::fs::File,
+    // None while the file is empty, an empty file cannot be mapped.
+    map: Option<Mmap>,
+}
+
+impl MmapStorage {
+    pub fn new(file: std::fs::File) -> std::io::Result<Self> {
+        let mut storage = MmapStorage { file, map: None };
+        storage.remap()?;
+        Ok(storage)
+    }
+
+    fn remap(&mut self) -> std::io::Result<()> {
+        self.map = None;
+        if self.file.metadata()?.len() > 0 {
+            // The map is only read within the length of the file when it
+            // was mapped, and the file is only cut down by set_len which
+            // drops the map first. Another process cutting the file down
+            // is the caveat above.
+            self.map = Some(unsafe { Mmap::map(&self.file)? });
+        }
+        Ok(())
+    }
+
+    fn mapped_len(&self) -> u64 {
+        self.map.as_ref().map_or(0, |map| map.len() as u64)
+    }
+}
+
+impl Storage for MmapStorage {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let end = offset + buf.len() as u64;
+        if end > self.mapped_len() {
+            self.remap()?;
+        }
+        let Some(map) = self.map.as_ref().filter(|map| end <= map.len() as u64) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            ));
+        };
+        buf.copy_from_slice(&map[offset as usize..end as usize]);
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        Storage::write_all_at(&mut self.file, buf, offset)
+    }
+
+    fn len(&self) -> std::io::Result<u64> {
+        Storage::len(&self.file)
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        self.map = None;
+        std::fs::File::set_len(&self.file, len)?;
+        self.remap()
+    }
+
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        self.file.sync_data()
+    }
+
+    // The map reads the hole as zeros, it is still in the file.
+    fn punch_hole(&mut self, offset: u64, len: u64) -> std::io::Result<()> {
+        Storage::punch_hole(&mut self.file, offset, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_mmap_storage_write_read() {
+        let mut storage = MmapStorage::new(tempfile().expect("Failed to create temp file"))
+            .expect("Failed to map file");
+        assert!(storage.is_empty().unwrap());
+        let mut buf = [0u8; 10];
+        assert!(storage.read_exact_at(&mut buf, 0).is_err());
+
+        // Writes past the end of the map are read once it is mapped
+        // again, writes within it are read straight away.
+        storage.write_all_at(b"world", 5).unwrap();
+        storage.write_all_at(b"hello", 0).unwrap();
+        assert_eq!(storage.len().unwrap(), 10);
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"helloworld");
+        storage.write_all_at(b"HELLO", 0).unwrap();
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"HELLOworld");
+        assert!(storage.read_exact_at(&mut buf, 5).is_err());
+
+        storage.set_len(5).unwrap();
+        assert_eq!(storage.len().unwrap(), 5);
+        assert!(storage.read_exact_at(&mut buf, 0).is_err());
+        let mut buf = [0u8; 5];
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"HELLO");
+        storage.set_len(0).unwrap();
+        assert!(storage.read_exact_at(&mut buf, 0).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::page::{Page, PageTrait};
+use sha2::{Digest, Sha256};
+
+// How the 96 bit nonce is chosen when a block is encrypted, AES-128-GCM
+// or ChaCha20-Poly1305. The nonce is stored in the block so either mode
+// decrypts the same way, the mode can change while the DB is open.
+//
+//   Random  - a new random nonce from OsRng for each write, the default.
+//   Derived - the nonce is derived from the block being written, so the
+//             same writes give the same file. Backups and replicas can
+//             be compared byte for byte and the RNG is not needed.
+//
+// A nonce must never be used twice with the same key for different
+// plain text, for GCM that gives away the authentication key. The
+// derived nonce is the first 96 bits of SHA256 over the key, page
+// number, version and the whole page being written. The page number and
+// version alone are not enough - a page changed twice in a transaction
+// is written twice with the same page number and version, and a
+// transaction that is rolled back leaves its version to be used again
+// by the next one with other contents. With the page bytes included two
+// writes only get the same nonce if they write the same plain text to
+// the same page, which gives the same cipher text and shows nothing but
+// that the page was written again, or if SHA256 collides in 96 bits.
+// That has the same odds as two random 96 bit nonces colliding.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub enum NonceMode {
+    #[default]
+    Random,
+    Derived,
+}
+
+impl NonceMode {
+    // The nonce Derived uses to encrypt the page with the key.
+    pub fn derive_nonce(page: &Page, key: &[u8]) -> [u8; 12] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"digby nonce");
+        hasher.update(key);
+        hasher.update(page.get_page_number().to_u64().to_le_bytes());
+        hasher.update(page.get_version().to_le_bytes());
+        hasher.update(page.get_page_bytes());
+        let hash = hasher.finalize();
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&hash[0..12]);
+        nonce
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page_no::PageNo;
+
+    #[test]
+    fn test_nonce_mode_derive_nonce() {
+        assert_eq!(NonceMode::default(), NonceMode::Random);
+        let key = [1u8; 16];
+        let mut page = Page::new(4096, 4068);
+        page.set_page_number(PageNo::from_u64(5));
+        page.set_version(3);
+        let nonce = NonceMode::derive_nonce(&page, &key);
+        assert_eq!(NonceMode::derive_nonce(&page, &key), nonce);
+        assert_ne!(NonceMode::derive_nonce(&page, &[2u8; 16]), nonce);
+
+        // Each of page number, version and contents changes the nonce.
+        page.set_page_number(PageNo::from_u64(6));
+        assert_ne!(NonceMode::derive_nonce(&page, &key), nonce);
+        page.set_page_number(PageNo::from_u64(5));
+        page.set_version(4);
+        assert_ne!(NonceMode::derive_nonce(&page, &key), nonce);
+        page.set_version(3);
+        page.get_page_bytes_mut()[100] = 1;
+        assert_ne!(NonceMode::derive_nonce(&page, &key), nonce);
+        page.get_page_bytes_mut()[100] = 0;
+        assert_eq!(NonceMode::derive_nonce(&page, &key), nonce);
+    }
+}
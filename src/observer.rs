@@ -0,0 +1,19 @@
+// Hook for watching the IO the DB does against the file. The file layer
+// calls the observer each time it goes to the disk, so it can be used
+// for stats or in tests to check how many reads/writes/syncs an
+// operation took. All the methods default to doing nothing so an
+// observer only needs to implement what it is interested in.
+//
+// Reads served from the read-ahead buffer do not hit the disk and are
+// not reported.
+pub trait Observer: Send {
+    // A read from disk of blk_cnt blocks starting at blk_offset.
+    fn on_disk_read(&mut self, _blk_offset: u64, _blk_cnt: u64) {}
+
+    // A write to disk of blk_cnt blocks starting at blk_offset.
+    fn on_disk_write(&mut self, _blk_offset: u64, _blk_cnt: u64) {}
+
+    // A sync of the file, full is true for a sync_all and false for a
+    // sync_data.
+    fn on_sync(&mut self, _full: bool) {}
+}
@@ -53,6 +53,11 @@ impl OverflowPage {
         overflow_page
     }
 
+    // The tuple bytes an overflow page of one block can hold.
+    pub fn get_capacity(page_config: &DbConfig) -> usize {
+        page_config.block_size - page_config.block_sanity_size - OverflowPage::HEADER_SIZE
+    }
+
     pub fn from_page(page: Page) -> Self {
         if page.get_type() != crate::page::PageType::Overflow {
             panic!("Invalid page type for OverflowPage");
@@ -93,6 +98,11 @@ impl OverflowPage {
         self.get_pg_size() - (self.get_used_size() as usize + OverflowPage::HEADER_SIZE)
     }
 
+    // The used size must fit in the page after the header.
+    pub fn is_used_size_valid(&self) -> bool {
+        self.get_used_size() as usize + OverflowPage::HEADER_SIZE <= self.get_pg_size()
+    }
+
     pub fn add_bytes(&mut self, bytes: &[u8], size: usize) {
         self.get_page().get_page_bytes_mut()
             [OverflowPage::HEADER_SIZE..OverflowPage::HEADER_SIZE + size]
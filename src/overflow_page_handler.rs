@@ -3,73 +3,186 @@ use crate::OverflowPage;
 use crate::OverflowTuple;
 use crate::PageCache;
 use crate::PageNo;
+use crate::corruption::Corruption;
+use crate::digby_error::DigbyError;
 use crate::page::PageTrait;
-use crate::tuple::Overflow;
+use crate::page::PageType;
 use crate::tuple::Tuple;
 use crate::tuple::TupleTrait;
+use std::collections::HashSet;
 
 pub struct OverflowPageHandler {}
 
 impl OverflowPageHandler {
+    // The most blocks read in one go when prefetching an overflow chain,
+    // 1MB with 4K blocks.
+    pub const MAX_PREFETCH_BLKS: usize = 256;
+
     pub fn store_overflow_tuple(
         tuple: OverflowTuple,
         page_cache: &mut PageCache,
         free_page_tracker: &mut FreePageTracker,
         version: u64,
     ) -> PageNo {
-        // We write the buffer backwards as we want to create a linked list
-        // of pages. The last page we write will be the head of the list
-        // and contain the start of the OverflowTuple.
+        // The pages for the chain are taken up front and sorted, so a
+        // chain on new pages at the end of the file runs forwards through
+        // it and can be read back in a few large reads rather than a read
+        // a page, see try_get_overflow_tuple_at. The head holds the start
+        // of the OverflowTuple.
         let buffer = tuple.get_serialized();
-        let mut end = tuple.get_byte_size();
+        let page_capacity = OverflowPage::get_capacity(page_cache.get_page_config());
+        let mut page_nos: Vec<PageNo> = (0..buffer.len().div_ceil(page_capacity))
+            .map(|_| free_page_tracker.get_free_page(page_cache))
+            .collect();
+        page_nos.sort_by_key(|page_no| page_no.get_blk_offset());
 
-        let mut previous = PageNo::from_u64(0);
-        let mut next_page: PageNo;
-        loop {
-            next_page = free_page_tracker.get_free_page(page_cache);
+        for (index, bytes) in buffer.chunks(page_capacity).enumerate() {
             let mut page =
-                OverflowPage::create_new(page_cache.get_page_config(), next_page, version);
-            page.set_next_page(previous.to_u64());
-
-            let free_space = page.get_free_space();
-            let bytes_to_write: usize = if end < free_space { end } else { free_space };
-            page.add_bytes(&buffer[end - bytes_to_write..end], bytes_to_write);
+                OverflowPage::create_new(page_cache.get_page_config(), page_nos[index], version);
+            let next_page = page_nos
+                .get(index + 1)
+                .map_or(0, |page_no| page_no.to_u64());
+            page.set_next_page(next_page);
+            page.add_bytes(bytes, bytes.len());
             page_cache.put_page(page.get_page());
-            end -= bytes_to_write;
-            if end == 0 {
-                break;
-            }
-            previous = next_page;
         }
 
-        next_page
+        page_nos[0]
     }
 
     pub fn get_overflow_tuple(overflow_page_no: u64, page_cache: &mut PageCache) -> OverflowTuple {
+        OverflowPageHandler::try_get_overflow_tuple(overflow_page_no, page_cache)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // As get_overflow_tuple but a page in the chain that cannot be read
+    // is returned as an error.
+    pub fn try_get_overflow_tuple(
+        overflow_page_no: u64,
+        page_cache: &mut PageCache,
+    ) -> Result<OverflowTuple, DigbyError> {
+        OverflowPageHandler::try_get_overflow_tuple_at(overflow_page_no, u64::MAX, page_cache)
+    }
+
+    // As try_get_overflow_tuple for a chain written at or before
+    // max_version, see Snapshot.
+    //
+    // The lengths at the start of the tuple give its size, once they
+    // are read the buffer is sized for the whole tuple. A chain that
+    // ends before the tuple does, or carries on after it, is returned as
+    // Corruption::OverflowLength rather than a tuple that is cut short.
+    //
+    // While the chain runs forwards through the file the pages still to
+    // come are prefetched, up to MAX_PREFETCH_BLKS at a time, so a large
+    // tuple takes a few large reads rather than a read a page.
+    pub fn try_get_overflow_tuple_at(
+        overflow_page_no: u64,
+        max_version: u64,
+        page_cache: &mut PageCache,
+    ) -> Result<OverflowTuple, DigbyError> {
         let mut buffer: Vec<u8> = Vec::new();
+        let mut tuple_size: Option<usize> = None;
 
+        let page_capacity = OverflowPage::get_capacity(page_cache.get_page_config());
         let mut page_no = overflow_page_no;
         loop {
-            let page = OverflowPage::from_page(page_cache.get_page(PageNo::from_u64(page_no)));
+            let current_page_no = PageNo::from_u64(page_no);
+            let page = OverflowPage::from_page(page_cache.try_get_page(current_page_no)?);
+            if page.get_version() > max_version {
+                return Err(DigbyError::SnapshotExpired(PageNo::from_u64(page_no)));
+            }
             buffer.append(&mut page.get_tuple_bytes());
             page_no = page.get_next_page();
+            if tuple_size.is_none() && buffer.len() >= OverflowTuple::LENGTHS_SIZE {
+                tuple_size = OverflowTuple::get_serialized_size(&buffer);
+                let Some(size) = tuple_size else {
+                    break;
+                };
+                // Only a hint, a chain with bad lengths fails below.
+                let _ = buffer.try_reserve_exact(size.saturating_sub(buffer.len()));
+            }
+            if page_no == 0 || tuple_size.is_some_and(|size| buffer.len() >= size) {
+                break;
+            }
+            let next_page_no = PageNo::from_u64(page_no);
+            if let Some(size) = tuple_size
+                && next_page_no.get_blk_offset() == current_page_no.get_blk_offset() + 1
+            {
+                let blk_cnt = (size - buffer.len())
+                    .div_ceil(page_capacity)
+                    .min(OverflowPageHandler::MAX_PREFETCH_BLKS);
+                page_cache.prefetch(next_page_no, blk_cnt as u64)?;
+            }
+        }
+        if page_no != 0 || tuple_size != Some(buffer.len()) {
+            return Err(Corruption::OverflowLength(PageNo::from_u64(overflow_page_no)).into());
+        }
+        Ok(OverflowTuple::from_bytes(buffer))
+    }
+
+    // The first bytes of a chain, up to the tuple's lengths, and the
+    // number of bytes in the chain so far. A chain can be checked against
+    // the size of its tuple without building the tuple.
+    pub fn add_chain_bytes(page: &OverflowPage, lengths: &mut Vec<u8>, chain_size: &mut usize) {
+        if lengths.len() < OverflowTuple::LENGTHS_SIZE {
+            let bytes = page.get_tuple_bytes();
+            let needed = OverflowTuple::LENGTHS_SIZE - lengths.len();
+            lengths.extend_from_slice(&bytes[..needed.min(bytes.len())]);
+        }
+        *chain_size += page.get_used_size() as usize;
+    }
+
+    // Walk the chain of overflow pages from head_page_no checking each
+    // page, without building the tuple - the tuple could be very large.
+    // The chain must hold as many bytes as the tuple's lengths say.
+    // Returns the number of pages in the chain.
+    pub fn validate_chain(
+        head_page_no: u64,
+        page_cache: &mut PageCache,
+    ) -> Result<usize, Corruption> {
+        let mut lengths: Vec<u8> = Vec::with_capacity(OverflowTuple::LENGTHS_SIZE);
+        let mut chain_size = 0;
+        let mut seen: HashSet<u64> = HashSet::new();
+        let mut page_no = head_page_no;
+        loop {
+            let overflow_page_no = PageNo::from_u64(page_no);
+            if overflow_page_no.get_blk_offset() + overflow_page_no.get_blk_cnt()
+                > page_cache.get_total_page_count()
+            {
+                return Err(Corruption::PageOutOfRange(overflow_page_no));
+            }
+            if !seen.insert(page_no) {
+                return Err(Corruption::OverflowCycle(overflow_page_no));
+            }
+            let page = page_cache.get_page(overflow_page_no);
+            if page.try_get_type() != Some(PageType::Overflow) {
+                return Err(Corruption::WrongPageType(overflow_page_no));
+            }
+            let page = OverflowPage::from_page(page);
+            if !page.is_used_size_valid() {
+                return Err(Corruption::BadUsedSize(overflow_page_no));
+            }
+            OverflowPageHandler::add_chain_bytes(&page, &mut lengths, &mut chain_size);
+            page_no = page.get_next_page();
             if page_no == 0 {
                 break;
             }
         }
-        OverflowTuple::from_bytes(buffer)
+        if OverflowTuple::get_serialized_size(&lengths) != Some(chain_size) {
+            return Err(Corruption::OverflowLength(PageNo::from_u64(head_page_no)));
+        }
+        Ok(seen.len())
     }
 
     pub fn delete_overflow_tuple_pages(
-        tuple_option: Option<Tuple>,
+        tuple_option: Option<&Tuple>,
         page_cache: &mut PageCache,
         free_page_tracker: &mut FreePageTracker,
     ) -> u32 {
-        if tuple_option.is_none() {
+        let Some(tuple) = tuple_option else {
             return 0;
-        }
-        let tuple = tuple_option.unwrap();
-        if tuple.get_overflow() == Overflow::None {
+        };
+        if !tuple.get_overflow().has_overflow_pages() {
             return 0;
         }
         // A tuple has been deleted that points to a overflow page.
@@ -103,6 +216,7 @@ impl OverflowPageHandler {
 mod tests {
     use super::*;
     use crate::db_config::DbConfig;
+    use crate::tuple::Overflow;
 
     const PAGE_CONFIG: DbConfig = DbConfig::builder()
         .block_size(4096)
@@ -129,7 +243,8 @@ mod tests {
         let new_version: u64 = 90;
 
         // Set up the page_cache
-        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, PAGE_CONFIG.block_size);
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, PAGE_CONFIG.block_size)
+            .expect("Failed to create file layer");
         let block_layer: crate::PageContainerLayer =
             crate::PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let mut page_cache: crate::PageCache = crate::PageCache::new(block_layer);
@@ -172,7 +287,7 @@ mod tests {
         );
         assert_eq!(count, 0);
         let count = OverflowPageHandler::delete_overflow_tuple_pages(
-            Some(tuple_no_overflow.clone()),
+            Some(&tuple_no_overflow),
             &mut page_cache,
             &mut free_page_tracker,
         );
@@ -182,7 +297,7 @@ mod tests {
         let overflow_tuple_val =
             Tuple::new_with_overflow(&key[0..10], &page_no_bytes, 1, Overflow::KeyValueOverflow);
         let count = OverflowPageHandler::delete_overflow_tuple_pages(
-            Some(overflow_tuple_val.clone()),
+            Some(&overflow_tuple_val),
             &mut page_cache,
             &mut free_page_tracker,
         );
@@ -196,4 +311,157 @@ mod tests {
 
         std::fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
     }
+
+    #[test]
+    fn validate_overflow_chain() {
+        let temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        let db_file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_file)
+            .expect("Failed to open or create DB file");
+
+        let version: u64 = 89;
+        let new_version: u64 = 90;
+
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, PAGE_CONFIG.block_size)
+            .expect("Failed to create file layer");
+        let block_layer: crate::PageContainerLayer =
+            crate::PageContainerLayer::new(file_layer, PAGE_CONFIG);
+        let mut page_cache: crate::PageCache = crate::PageCache::new(block_layer);
+
+        let free_dir_page_no = *page_cache.generate_free_pages(1, 0).first().unwrap();
+        let mut free_dir_page =
+            crate::FreeDirPage::create_new(page_cache.get_page_config(), free_dir_page_no, version);
+        page_cache.put_page(free_dir_page.get_page());
+        let mut free_page_tracker = FreePageTracker::new(
+            page_cache.get_page(free_dir_page_no),
+            new_version,
+            *page_cache.get_page_config(),
+        );
+
+        let key: Vec<u8> = vec![111u8; 100];
+        let value: Vec<u8> = vec![56u8; 18192];
+        let tuple = OverflowTuple::new(&key, &value, new_version, Overflow::None);
+        let head_page_no = OverflowPageHandler::store_overflow_tuple(
+            tuple,
+            &mut page_cache,
+            &mut free_page_tracker,
+            new_version,
+        );
+
+        // 18292 bytes of tuple over pages that hold 4066 bytes each.
+        let chain_len = OverflowPageHandler::validate_chain(head_page_no.to_u64(), &mut page_cache)
+            .expect("Chain should be valid");
+        assert_eq!(chain_len, 5);
+
+        // Point the second page in the chain back at itself.
+        let head_page = OverflowPage::from_page(page_cache.get_page(head_page_no));
+        let second_page_no = PageNo::from_u64(head_page.get_next_page());
+        let mut second_page = OverflowPage::from_page(page_cache.get_page(second_page_no));
+        second_page.set_next_page(second_page_no.to_u64());
+        page_cache.put_page(second_page.get_page());
+
+        assert_eq!(
+            OverflowPageHandler::validate_chain(head_page_no.to_u64(), &mut page_cache),
+            Err(Corruption::OverflowCycle(second_page_no))
+        );
+        // Reading the tuple stops once it has the bytes the lengths ask
+        // for, the chain carries on past them.
+        assert!(matches!(
+            OverflowPageHandler::try_get_overflow_tuple(head_page_no.to_u64(), &mut page_cache),
+            Err(DigbyError::Corruption(Corruption::OverflowLength(page_no))) if page_no == head_page_no
+        ));
+
+        // Cut the chain short after the second page.
+        second_page.set_next_page(0);
+        page_cache.put_page(second_page.get_page());
+        assert_eq!(
+            OverflowPageHandler::validate_chain(head_page_no.to_u64(), &mut page_cache),
+            Err(Corruption::OverflowLength(head_page_no))
+        );
+        assert!(matches!(
+            OverflowPageHandler::try_get_overflow_tuple(head_page_no.to_u64(), &mut page_cache),
+            Err(DigbyError::Corruption(Corruption::OverflowLength(page_no))) if page_no == head_page_no
+        ));
+
+        // A page that is not an overflow page in the chain.
+        assert_eq!(
+            OverflowPageHandler::validate_chain(free_dir_page_no.to_u64(), &mut page_cache),
+            Err(Corruption::WrongPageType(free_dir_page_no))
+        );
+
+        std::fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+    }
+
+    // Store an overflow tuple with a serialized size that is a given
+    // number of bytes, check it reads back and return the chain length.
+    fn store_tuple_of_size(tuple_size: usize) -> usize {
+        let temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        let db_file = std::fs::OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_file)
+            .expect("Failed to open or create DB file");
+
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, PAGE_CONFIG.block_size)
+            .expect("Failed to create file layer");
+        let block_layer: crate::PageContainerLayer =
+            crate::PageContainerLayer::new(file_layer, PAGE_CONFIG);
+        let mut page_cache: crate::PageCache = crate::PageCache::new(block_layer);
+
+        let free_dir_page_no = *page_cache.generate_free_pages(1, 0).first().unwrap();
+        let mut free_dir_page =
+            crate::FreeDirPage::create_new(page_cache.get_page_config(), free_dir_page_no, 1);
+        page_cache.put_page(free_dir_page.get_page());
+        let mut free_page_tracker = FreePageTracker::new(
+            page_cache.get_page(free_dir_page_no),
+            2,
+            *page_cache.get_page_config(),
+        );
+
+        // Serialized tuple is 8 + 8 bytes of lengths, the key, 8 bytes version and the value.
+        let key: Vec<u8> = vec![1u8; 10];
+        let value: Vec<u8> = (0..tuple_size - 34).map(|i| i as u8).collect();
+        let tuple = OverflowTuple::new(&key, &value, 2, Overflow::None);
+        assert_eq!(tuple.get_byte_size(), tuple_size);
+        let head_page_no = OverflowPageHandler::store_overflow_tuple(
+            tuple,
+            &mut page_cache,
+            &mut free_page_tracker,
+            2,
+        );
+        let reloaded_tuple =
+            OverflowPageHandler::get_overflow_tuple(head_page_no.to_u64(), &mut page_cache);
+        assert_eq!(reloaded_tuple.get_key(), key);
+        assert_eq!(reloaded_tuple.get_value(), value);
+
+        // No page in the chain should be empty.
+        let mut page_no = head_page_no.to_u64();
+        while page_no != 0 {
+            let page = OverflowPage::from_page(page_cache.get_page(PageNo::from_u64(page_no)));
+            assert!(page.get_used_size() > 0);
+            page_no = page.get_next_page();
+        }
+
+        std::fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+        OverflowPageHandler::validate_chain(head_page_no.to_u64(), &mut page_cache)
+            .expect("Chain should be valid")
+    }
+
+    #[test]
+    fn store_overflow_tuple_page_boundaries() {
+        let capacity =
+            OverflowPage::create_new(&PAGE_CONFIG, PageNo::from_u64(1), 1).get_free_space();
+        assert_eq!(store_tuple_of_size(capacity - 1), 1);
+        assert_eq!(store_tuple_of_size(capacity), 1);
+        assert_eq!(store_tuple_of_size(capacity + 1), 2);
+        assert_eq!(store_tuple_of_size(capacity * 2), 2);
+        assert_eq!(store_tuple_of_size(capacity * 2 + 1), 3);
+        assert_eq!(store_tuple_of_size(capacity * 5), 5);
+    }
 }
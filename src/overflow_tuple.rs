@@ -1,78 +1,19 @@
-use crate::tuple::Overflow;
-use crate::tuple::TupleTrait;
-use crate::version_holder::VersionHolder;
+use crate::tuple::{Overflow, SerializedTuple};
 
-// There must be some clever way to do this rather than copying code.
-// The only difference is that the overflow tuple is serialised with u64 (8 bytes)
-// for the key and value length rather than the u8/u16 used in Tuple.
-#[derive(Clone)]
-pub struct OverflowTuple {
-    // key_len - 8 bytes
-    // value_len - 8 bytes
-    // key - key_len bytes
-    // version - 8 bytes
-    // value - value_len bytes
-    serialized: Vec<u8>,
-}
-
-impl TupleTrait for OverflowTuple {
-    fn get_key(&self) -> &[u8] {
-        let key_len: usize = u64::from_le_bytes(self.serialized[0..8].try_into().unwrap()) as usize;
-        &self.serialized[16..16 + key_len]
-    }
-
-    fn get_value(&self) -> &[u8] {
-        let key_len: usize = u64::from_le_bytes(self.serialized[0..8].try_into().unwrap()) as usize;
-        &self.serialized[24 + key_len..]
-    }
-
-    fn get_version(&self) -> u64 {
-        let key_len: usize = u64::from_le_bytes(self.serialized[0..8].try_into().unwrap()) as usize;
-        VersionHolder::from_bytes(&self.serialized[16 + key_len..16 + key_len + 8]).get_version()
-    }
-
-    fn get_version_value(&self) -> &[u8] {
-        let key_len: usize = u64::from_le_bytes(self.serialized[0..8].try_into().unwrap()) as usize;
-        &self.serialized[16 + key_len..]
-    }
-
-    fn get_serialized(&self) -> &[u8] {
-        &self.serialized
-    }
-
-    fn get_byte_size(&self) -> usize {
-        self.serialized.len()
-    }
-
-    fn get_overflow(&self) -> Overflow {
-        let key_len: usize = u64::from_le_bytes(self.serialized[0..8].try_into().unwrap()) as usize;
-        Overflow::try_from(
-            VersionHolder::from_bytes(&self.serialized[16 + key_len..16 + key_len + 8]).get_flags(),
-        )
-        .unwrap()
-    }
-}
+// A tuple stored in overflow pages, laid out as a Tuple but with 8 byte
+// key and value lengths as the key and value can be larger than a page.
+pub type OverflowTuple = SerializedTuple<8>;
 
 impl OverflowTuple {
     pub fn new(key: &[u8], value: &[u8], version: u64, overflow: Overflow) -> Self {
-        let mut serialized = Vec::with_capacity(8 + key.len() + 8 + value.len() + 8);
-        serialized.extend_from_slice(&(key.len() as u64).to_le_bytes());
-        serialized.extend_from_slice(&(value.len() as u64).to_le_bytes());
-        serialized.extend_from_slice(key);
-        let version_holder = VersionHolder::new(overflow as u8, version);
-        serialized.extend_from_slice(&version_holder.get_bytes()[0..8]);
-        serialized.extend_from_slice(value);
-        OverflowTuple { serialized }
-    }
-
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        OverflowTuple { serialized: bytes }
+        OverflowTuple::serialize(key, value, version, overflow)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tuple::TupleTrait;
 
     #[test]
     fn test_tuple() {
@@ -84,10 +25,10 @@ mod tests {
         assert_eq!(tuple.get_key(), &key);
         assert_eq!(tuple.get_value(), &value);
         assert_eq!(tuple.get_version(), version);
-        assert_eq!(tuple.get_serialized(), &tuple.serialized);
+        assert_eq!(tuple.get_overflow(), Overflow::None);
         assert_eq!(
             tuple.get_version_value(),
-            &tuple.serialized[16 + key.len()..]
+            &tuple.get_serialized()[16 + key.len()..]
         );
     }
 
@@ -104,4 +45,20 @@ mod tests {
         assert_eq!(deserialized.get_value(), &value);
         assert_eq!(deserialized.get_version(), version);
     }
+
+    #[test]
+    fn test_tuple_serialized_size() {
+        let tuple = OverflowTuple::new(b"key", &[1u8; 5000], 1, Overflow::None);
+        let serialized = tuple.get_serialized();
+        assert_eq!(
+            OverflowTuple::get_serialized_size(&serialized[0..16]),
+            Some(serialized.len())
+        );
+        assert_eq!(
+            OverflowTuple::get_serialized_size(serialized),
+            Some(serialized.len())
+        );
+        assert_eq!(OverflowTuple::get_serialized_size(&serialized[0..15]), None);
+        assert_eq!(OverflowTuple::get_serialized_size(&[0xff; 16]), None);
+    }
 }
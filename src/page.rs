@@ -1,5 +1,6 @@
 use crate::db_config::DbConfig;
 use crate::page_no::PageNo;
+use crate::page_pool::PagePool;
 use crate::version_holder::VersionHolder;
 use std::convert::TryFrom;
 
@@ -54,6 +55,8 @@ pub trait PageTrait {
 pub struct Page {
     pg_ctr_bytes: Vec<u8>,
     page_size: usize,
+    // If the buffer came from a pool it goes back when the page is dropped.
+    pool: Option<PagePool>,
 }
 
 impl PageTrait for Page {
@@ -90,6 +93,7 @@ impl Page {
         Page {
             pg_ctr_bytes: vec![0u8; buffer_size],
             page_size: buffer_size - page_meta.block_sanity_size,
+            pool: None,
         }
     }
 
@@ -97,6 +101,16 @@ impl Page {
         Page {
             pg_ctr_bytes: vec![0u8; block_size],
             page_size,
+            pool: None,
+        }
+    }
+
+    // A page using a buffer from the pool, see PagePool.
+    pub fn from_pool(buffer: Vec<u8>, page_size: usize, pool: PagePool) -> Self {
+        Page {
+            pg_ctr_bytes: buffer,
+            page_size,
+            pool: Some(pool),
         }
     }
 
@@ -117,6 +131,12 @@ impl Page {
             .unwrap()
     }
 
+    // As get_type but does not panic if the type is unknown, for use
+    // when checking a page that may be corrupt.
+    pub fn try_get_type(&self) -> Option<PageType> {
+        PageType::try_from(VersionHolder::from_bytes(&self.pg_ctr_bytes[8..8 + 8]).get_flags()).ok()
+    }
+
     pub fn set_type(&mut self, page_type: PageType) {
         let mut version_holder = VersionHolder::from_bytes(&self.pg_ctr_bytes[8..8 + 8]);
         version_holder.set_flags(page_type as u8);
@@ -128,6 +148,14 @@ impl Page {
     }
 }
 
+impl Drop for Page {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.return_buffer(std::mem::take(&mut self.pg_ctr_bytes));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
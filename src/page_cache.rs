@@ -1,25 +1,46 @@
+use crate::corruption::Corruption;
 use crate::db_config::DbConfig;
+use crate::digby_error::DigbyError;
+use crate::key_order::KeyOrder;
+use crate::nonce_mode::NonceMode;
+use crate::observer::Observer;
 use crate::page::Page;
 use crate::page::PageTrait;
 use crate::page_container_layer::PageContainerLayer;
 use crate::page_no::PageNo;
+use crate::page_pool::PagePool;
+use crate::storage::Storage;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::collections::VecDeque;
 
+// Pages read or written are kept in memory, up to cache_size_limit of
+// them. When the cache is full the least recently used page is dropped.
+// Each page in page_map has the tick it was last used at, lru holds the
+// same ticks in order so the oldest is the first entry.
+//
+// Writes go through to the block layer as well as updating the cache,
+// so a dropped page can always be read back from the file.
 pub struct PageCache {
     block_layer: PageContainerLayer,
-    page_map: HashMap<PageNo, Page>,
-    deque: VecDeque<PageNo>,
+    page_map: HashMap<PageNo, (Page, u64)>,
+    lru: BTreeMap<u64, PageNo>,
+    tick: u64,
     cache_size_limit: usize,
+    // Buffers for the pages the cache copies in and out.
+    page_pool: PagePool,
 }
 
 impl PageCache {
+    pub const DEFAULT_CACHE_SIZE: usize = 1024;
+
     pub fn new(block_layer: PageContainerLayer) -> Self {
         PageCache {
             block_layer,
             page_map: HashMap::new(),
-            deque: VecDeque::new(),
-            cache_size_limit: 1024usize,
+            lru: BTreeMap::new(),
+            tick: 0,
+            cache_size_limit: PageCache::DEFAULT_CACHE_SIZE,
+            page_pool: PagePool::new(PagePool::DEFAULT_MAX_BUFFERS),
         }
     }
 
@@ -40,48 +61,99 @@ impl PageCache {
     // immutable refernce to a page that is shared, and a version
     // that returns a copy of the page.
     pub fn get_page(&mut self, page_number: PageNo) -> Page {
-        match self.page_map.get(&page_number) {
-            Some(page) => {
-                let mut page_copy =
-                    Page::create_new(self.get_page_config(), page_number.get_blk_cnt());
-                page_copy
-                    .get_pg_ctr_bytes_mut()
-                    .copy_from_slice(page.get_pg_ctr_bytes());
-                page_copy
-            }
-            None => {
-                let page = self.block_layer.read_page(page_number);
-                let mut page_for_cache =
-                    Page::create_new(self.get_page_config(), page_number.get_blk_cnt());
-                page_for_cache
-                    .get_pg_ctr_bytes_mut()
-                    .copy_from_slice(page.get_pg_ctr_bytes());
-                self.add_page_to_cache(page_number, page_for_cache);
-                page
-            }
-        }
+        self.try_get_page(page_number)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // As get_page but a page that cannot be read, is outside the file or
+    // fails the block sanity check is returned as an error.
+    pub fn try_get_page(&mut self, page_number: PageNo) -> Result<Page, DigbyError> {
+        self.try_get_page_ref(page_number)?;
+        Ok(self.copy_page(&self.page_map.get(&page_number).unwrap().0))
     }
 
     pub fn get_page_ref(&mut self, page_number: PageNo) -> &Page {
+        self.try_get_page_ref(page_number)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get_page_ref(&mut self, page_number: PageNo) -> Result<&Page, DigbyError> {
         if self.page_map.contains_key(&page_number) {
-            return self.page_map.get(&page_number).unwrap();
+            self.touch(page_number);
+        } else {
+            if page_number.get_blk_offset() + page_number.get_blk_cnt()
+                > self.get_total_page_count()
+            {
+                return Err(Corruption::PageOutOfRange(page_number).into());
+            }
+            let mut new_page = self.page_pool.get_page(
+                self.block_layer.get_page_config(),
+                page_number.get_blk_cnt(),
+            );
+            self.block_layer
+                .try_read_page_into(&mut new_page, page_number)?;
+            self.add_page_to_cache(page_number, new_page);
         }
+        Ok(&self.page_map.get(&page_number).unwrap().0)
+    }
+
+    // A copy of the page in a buffer from the pool.
+    fn copy_page(&self, page: &Page) -> Page {
+        let mut page_copy = self.page_pool.get_page(
+            self.block_layer.get_page_config(),
+            page.get_page_number().get_blk_cnt(),
+        );
+        page_copy
+            .get_pg_ctr_bytes_mut()
+            .copy_from_slice(page.get_pg_ctr_bytes());
+        page_copy
+    }
+
+    // The number of free page buffers to keep for reuse, 0 turns off
+    // pooling.
+    pub fn set_page_pool_size(&mut self, max_buffers: usize) {
+        self.page_pool.set_max_buffers(max_buffers);
+    }
+
+    // The number of pages to keep in memory, at least 1. Pages over the
+    // limit are dropped, least recently used first.
+    pub fn set_cache_size(&mut self, cache_size_limit: usize) {
+        assert!(cache_size_limit > 0, "Page cache size must be at least 1.");
+        self.cache_size_limit = cache_size_limit;
+        self.evict(cache_size_limit);
+    }
 
-        let new_page = self.block_layer.read_page(page_number);
-        self.add_page_to_cache(page_number, new_page);
-        self.page_map.get(&page_number).unwrap()
+    pub fn get_cached_page_count(&self) -> usize {
+        self.page_map.len()
+    }
+
+    // Mark a cached page as the most recently used.
+    fn touch(&mut self, page_no: PageNo) {
+        self.tick += 1;
+        let entry = self.page_map.get_mut(&page_no).unwrap();
+        self.lru.remove(&entry.1);
+        entry.1 = self.tick;
+        self.lru.insert(self.tick, page_no);
+    }
+
+    // Drop the least recently used pages until there are no more than
+    // limit.
+    fn evict(&mut self, limit: usize) {
+        while self.page_map.len() > limit {
+            let (_, page_no) = self.lru.pop_first().unwrap();
+            self.page_map.remove(&page_no);
+        }
     }
 
     fn add_page_to_cache(&mut self, page_no: PageNo, page: Page) {
-        if self.page_map.insert(page_no, page).is_none() {
-            // Added new page. Add to the dequeue and if it overflows
-            // delete an entry.
-            self.deque.push_back(page_no);
-            if self.deque.len() > self.cache_size_limit {
-                let page_to_delete = self.deque.pop_front().unwrap();
-                self.page_map.remove(&page_to_delete);
-            }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.page_map.insert(page_no, (page, self.tick)) {
+            self.lru.remove(&last_used);
         }
+        self.lru.insert(self.tick, page_no);
+        // The page just added is the most recently used, it is not the
+        // one dropped.
+        self.evict(self.cache_size_limit);
     }
 
     pub fn put_page(&mut self, page: &mut Page) {
@@ -89,11 +161,7 @@ impl PageCache {
         // Take a copy of the page before the block_layer processes it,
         // the block layer might encrypt it.
         // TODO - block_layer.write_page should return the page to us to avoid need to copy.
-        let mut page_for_cache =
-            Page::create_new(self.get_page_config(), page.get_page_number().get_blk_cnt());
-        page_for_cache
-            .get_pg_ctr_bytes_mut()
-            .copy_from_slice(page.get_pg_ctr_bytes());
+        let page_for_cache = self.copy_page(page);
         self.block_layer.write_page(page, page_no);
         self.add_page_to_cache(page_no, page_for_cache);
     }
@@ -102,6 +170,111 @@ impl PageCache {
         self.block_layer.get_total_page_count()
     }
 
+    // Number of blocks to read from disk in one go on a cache miss,
+    // the extra blocks are held in a buffer in the file layer.
+    pub fn set_read_ahead(&mut self, read_ahead_blks: u64) {
+        self.block_layer.set_read_ahead(read_ahead_blks);
+    }
+
+    // Read blk_cnt blocks from page_no on from disk in one go, ready for
+    // the reads of them that follow. Nothing is read if page_no is
+    // already in the cache.
+    pub fn prefetch(&mut self, page_no: PageNo, blk_cnt: u64) -> Result<(), DigbyError> {
+        if self.page_map.contains_key(&page_no) {
+            return Ok(());
+        }
+        self.block_layer.prefetch(page_no.get_blk_offset(), blk_cnt)
+    }
+
+    pub fn set_verify_checksums(&mut self, verify_checksums: bool) {
+        self.block_layer.set_verify_checksums(verify_checksums);
+    }
+
+    pub fn set_nonce_mode(&mut self, nonce_mode: NonceMode) {
+        self.block_layer.set_nonce_mode(nonce_mode);
+    }
+
+    pub fn set_free_page_growth(&mut self, free_page_growth: u64) {
+        self.block_layer.set_free_page_growth(free_page_growth);
+    }
+
+    pub fn set_key_order(&mut self, key_order: KeyOrder) {
+        self.block_layer.set_key_order(key_order);
+    }
+
+    pub fn get_key_order(&self) -> KeyOrder {
+        self.get_page_config().key_order
+    }
+
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.block_layer.set_observer(observer);
+    }
+
+    // Take the first error from a write or sync to the file since the
+    // last call, see PageContainerLayer.
+    pub fn take_write_error(&mut self) -> Option<std::io::Error> {
+        self.block_layer.take_write_error()
+    }
+
+    // Throw away the cached pages and any blocks appended to the file
+    // layer that did not get to the file. Used to get back to the last
+    // commit after a commit fails, the cache can hold pages from the
+    // failed commit including the new master page.
+    pub fn revert(&mut self) {
+        self.page_map.clear();
+        self.lru.clear();
+        self.block_layer.reset_block_count();
+    }
+
+    // Drop a page from the cache so the next get reads it from the file,
+    // the blocks read ahead are dropped too as they could hold it.
+    pub fn remove_page(&mut self, page_no: PageNo) {
+        if let Some((_, last_used)) = self.page_map.remove(&page_no) {
+            self.lru.remove(&last_used);
+        }
+        self.block_layer.clear_read_buffer();
+    }
+
+    // Punch a hole in the file where a free page is, see
+    // FileLayer::punch_hole. The page is dropped from the cache.
+    pub fn punch_hole(&mut self, page_no: PageNo) -> std::io::Result<()> {
+        if let Some((_, last_used)) = self.page_map.remove(&page_no) {
+            self.lru.remove(&last_used);
+        }
+        self.block_layer.punch_hole(page_no)
+    }
+
+    // Read and write another file from now on, see
+    // FileLayer::replace_storage. The cached pages were read from the
+    // old file and are dropped.
+    pub fn replace_storage(&mut self, file: Box<dyn Storage>) -> std::io::Result<()> {
+        self.page_map.clear();
+        self.lru.clear();
+        self.block_layer.replace_storage(file)
+    }
+
+    // Cut the file down to block_count blocks, dropping any cached
+    // pages past the new end.
+    pub fn truncate(&mut self, block_count: u64) {
+        self.page_map
+            .retain(|page_no, _| page_no.get_blk_offset() < block_count);
+        self.lru
+            .retain(|_, page_no| page_no.get_blk_offset() < block_count);
+        self.block_layer.truncate(block_count);
+    }
+
+    // Copy blocks from the file to dest as they are, see
+    // FileLayer::copy_blocks. Writes go through so the file has every
+    // page the cache has.
+    pub fn copy_blocks(
+        &mut self,
+        dest: &mut dyn Storage,
+        blk_offset: u64,
+        blk_cnt: u64,
+    ) -> std::io::Result<()> {
+        self.block_layer.copy_blocks(dest, blk_offset, blk_cnt)
+    }
+
     pub fn sync_data(&mut self) {
         self.block_layer.sync_data()
     }
@@ -133,7 +306,8 @@ mod tests {
     #[test]
     fn test_page_cache_read_write() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         let block_layer = PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let mut page_cache = PageCache::new(block_layer);
         let page_number = 0;
@@ -150,4 +324,53 @@ mod tests {
         assert_eq!(read_page.get_page_number().to_u64(), page_number);
         assert_eq!(read_page.get_page_bytes(), page.get_page_bytes());
     }
+
+    #[test]
+    fn test_page_cache_lru() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
+        let block_layer = PageContainerLayer::new(file_layer, PAGE_CONFIG);
+        let mut page_cache = PageCache::new(block_layer);
+        page_cache.generate_free_pages(10, 0);
+        for page_number in 0..10 {
+            let mut page = Page::create_new(page_cache.get_page_config(), 1);
+            page.set_page_number(PageNo::from_u64(page_number));
+            page.set_type(page::PageType::Free);
+            page_cache.put_page(&mut page);
+        }
+        assert_eq!(page_cache.get_cached_page_count(), 10);
+        page_cache.set_cache_size(3);
+        let cached = |page_cache: &PageCache| {
+            let mut cached: Vec<u64> = page_cache.page_map.keys().map(|p| p.to_u64()).collect();
+            cached.sort();
+            cached
+        };
+        assert_eq!(cached(&page_cache), vec![7, 8, 9]);
+
+        // A get makes a page the most recently used.
+        page_cache.get_page_ref(PageNo::from_u64(7));
+        page_cache.get_page(PageNo::from_u64(2));
+        assert_eq!(cached(&page_cache), vec![2, 7, 9]);
+        let mut page = page_cache.get_page(PageNo::from_u64(9));
+        page_cache.get_page(PageNo::from_u64(4));
+        assert_eq!(cached(&page_cache), vec![2, 4, 9]);
+
+        // So does a put, and the cached copy is replaced.
+        page.set_type(page::PageType::LeafPage);
+        page_cache.put_page(&mut page);
+        page_cache.get_page(PageNo::from_u64(5));
+        page_cache.get_page(PageNo::from_u64(6));
+        assert_eq!(cached(&page_cache), vec![5, 6, 9]);
+        assert_eq!(
+            page_cache.get_page(PageNo::from_u64(9)).get_type(),
+            page::PageType::LeafPage
+        );
+        assert_eq!(page_cache.lru.len(), 3);
+
+        page_cache.remove_page(PageNo::from_u64(6));
+        page_cache.truncate(9);
+        assert_eq!(cached(&page_cache), vec![5]);
+        assert_eq!(page_cache.lru.len(), 1);
+    }
 }
@@ -1,9 +1,15 @@
-use crate::block_sanity::BlockSanity;
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
 use crate::db_config::DbConfig;
+use crate::digby_error::DigbyError;
 use crate::file_layer::FileLayer;
+use crate::key_order::KeyOrder;
+use crate::nonce_mode::NonceMode;
+use crate::observer::Observer;
 use crate::page::Page;
 use crate::page::PageTrait;
 use crate::page_no::PageNo;
+use crate::storage::Storage;
+use std::sync::Arc;
 
 // The DB is divided into pages, for example leaf
 // pages (which hold key/values) or directory pages
@@ -28,8 +34,8 @@ use crate::page_no::PageNo;
 //
 // The amount of bytes used for checkum/encryption
 // depends on the BlockSanity used. 4 bytes for a
-// xxhash_32 hash of the page bytes, 28 bytes for
-// AES-128-GCM encryption of the page.
+// xxhash_32 or CRC32C hash of the page bytes, 28 bytes for
+// AES-128-GCM or ChaCha20-Poly1305 encryption of the page.
 //
 // The file block size is determined at DB creation time,
 // on Linux 4096 bytes can be sent to disk atomically -
@@ -39,38 +45,77 @@ use crate::page_no::PageNo;
 // The page container layer is also respnsible for generating
 // free pages.
 //
+// Errors writing or syncing the file do not panic, the first one is
+// held until take_write_error is called. The caller carries on and
+// checks for an error before committing, see Db::commit.
+//
 
 pub struct PageContainerLayer {
     file_layer: FileLayer,
     page_config: DbConfig,
-    block_sanity: BlockSanity,
+    block_sanity: Arc<dyn BlockSanityProvider>,
     key: Vec<u8>, // The encryption key if encryption is being used.
+    write_error: Option<std::io::Error>,
 }
 
 impl PageContainerLayer {
+    // The most new pages generate_free_pages writes in one go.
+    const APPEND_PAGES: usize = 256;
+
     pub fn new(file_layer: FileLayer, page_config: DbConfig) -> Self {
         PageContainerLayer {
             file_layer,
             page_config,
-            block_sanity: BlockSanity::XxH32Checksum,
+            block_sanity: BlockSanity::XxH32Checksum.get_provider(),
             key: Vec::new(),
+            write_error: None,
         }
     }
 
     pub fn new_with_key(file_layer: FileLayer, page_config: DbConfig, key: Vec<u8>) -> Self {
-        let mut enc_key = vec![0u8; 16];
-        // Note we only use the first 16 bytes of the key for AES-128-GCM
-        if key.len() >= 16 {
-            enc_key.copy_from_slice(&key[0..16]);
-        } else {
-            // If the key is less than 16 bytes, pad with zeros
-            enc_key[0..key.len()].copy_from_slice(&key[..]);
-        }
+        PageContainerLayer::new_with_cipher(file_layer, page_config, key, BlockSanity::Aes128Gcm)
+    }
+
+    // As new_with_key but the blocks are encrypted with the given
+    // cipher rather than AES-128-GCM. A checksum block sanity does not
+    // use the key.
+    pub fn new_with_cipher(
+        file_layer: FileLayer,
+        page_config: DbConfig,
+        key: Vec<u8>,
+        block_sanity: BlockSanity,
+    ) -> Self {
+        // Note we only use the first key_size bytes of the key, 16 for
+        // AES-128-GCM and 32 for ChaCha20-Poly1305, see fit_key.
+        let enc_key = block_sanity.fit_key(&key);
+        PageContainerLayer::new_with_provider(
+            file_layer,
+            page_config,
+            enc_key,
+            block_sanity.get_provider(),
+        )
+    }
+
+    // As new_with_cipher with the block sanity from a provider, which
+    // is given the key as it is. The page size in page_config must leave
+    // room for the provider's bytes.
+    pub fn new_with_provider(
+        file_layer: FileLayer,
+        page_config: DbConfig,
+        key: Vec<u8>,
+        block_sanity: Arc<dyn BlockSanityProvider>,
+    ) -> Self {
+        assert_eq!(
+            page_config.block_sanity_size,
+            block_sanity.get_bytes_used(),
+            "Page config does not match the block sanity"
+        );
         PageContainerLayer {
             file_layer,
-            block_sanity: BlockSanity::Aes128Gcm,
+            block_sanity,
             page_config,
-            key: enc_key,
+            key,
+            write_error: None,
         }
     }
 
@@ -80,17 +125,48 @@ impl PageContainerLayer {
 
     pub fn read_page(&mut self, page_no: PageNo) -> Page {
         let mut page = Page::create_new(&self.page_config, page_no.get_blk_cnt());
-        self.file_layer
-            .read_page_from_disk(&mut page, &page_no)
-            .expect("Failed to read page");
-        self.check_sanity(&mut page);
+        self.read_page_into(&mut page, page_no);
         page
     }
 
+    // As read_page but into a page the caller provides, which must be
+    // the size of the page being read.
+    pub fn read_page_into(&mut self, page: &mut Page, page_no: PageNo) {
+        self.try_read_page_into(page, page_no)
+            .unwrap_or_else(|error| panic!("{}", error));
+    }
+
+    // As read_page_into but a failed read or a block that fails the
+    // sanity check is returned as an error.
+    pub fn try_read_page_into(
+        &mut self,
+        page: &mut Page,
+        page_no: PageNo,
+    ) -> Result<(), DigbyError> {
+        self.file_layer.read_page_from_disk(page, &page_no)?;
+        self.check_sanity(page, page_no)
+    }
+
     pub fn get_total_page_count(&self) -> u64 {
         self.file_layer.get_block_count()
     }
 
+    pub fn set_read_ahead(&mut self, read_ahead_blks: u64) {
+        self.file_layer.set_read_ahead(read_ahead_blks);
+    }
+
+    pub fn clear_read_buffer(&mut self) {
+        self.file_layer.clear_read_buffer();
+    }
+
+    pub fn prefetch(&mut self, blk_offset: u64, blk_cnt: u64) -> Result<(), DigbyError> {
+        Ok(self.file_layer.prefetch(blk_offset, blk_cnt)?)
+    }
+
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.file_layer.set_observer(observer);
+    }
+
     pub fn write_page(&mut self, page: &mut Page, page_no: PageNo) {
         assert!(
             page_no.get_blk_offset() < self.file_layer.get_block_count(),
@@ -98,9 +174,55 @@ impl PageContainerLayer {
         );
 
         self.set_sanity(page);
-        self.file_layer
-            .write_page_to_disk(page, &page_no)
-            .expect("Failed to write page");
+        let result = self.file_layer.write_page_to_disk(page, &page_no);
+        self.record_write_error(result);
+    }
+
+    fn record_write_error(&mut self, result: std::io::Result<()>) {
+        if let Err(error) = result
+            && self.write_error.is_none()
+        {
+            self.write_error = Some(error);
+        }
+    }
+
+    // Take the first error from a write or sync since the last call.
+    pub fn take_write_error(&mut self) -> Option<std::io::Error> {
+        self.write_error.take()
+    }
+
+    // Go back to the blocks that are in the file after a failed commit,
+    // pages appended for the commit that did not get to the file are
+    // dropped.
+    pub fn reset_block_count(&mut self) {
+        let result = self.file_layer.reset_block_count();
+        self.record_write_error(result);
+    }
+
+    pub fn replace_storage(&mut self, file: Box<dyn Storage>) -> std::io::Result<()> {
+        self.file_layer.replace_storage(file)
+    }
+
+    pub fn truncate(&mut self, block_count: u64) {
+        let result = self.file_layer.truncate(block_count);
+        self.record_write_error(result);
+    }
+
+    // Errors are returned rather than recorded, nothing is written to
+    // this file.
+    pub fn copy_blocks(
+        &mut self,
+        dest: &mut dyn Storage,
+        blk_offset: u64,
+        blk_cnt: u64,
+    ) -> std::io::Result<()> {
+        self.file_layer.copy_blocks(dest, blk_offset, blk_cnt)
+    }
+
+    // Errors are returned rather than recorded, it is not part of a
+    // commit.
+    pub fn punch_hole(&mut self, page_no: PageNo) -> std::io::Result<()> {
+        self.file_layer.punch_hole(&page_no)
     }
 
     // There has been a request for more free pages during a commit - there are
@@ -114,40 +236,80 @@ impl PageContainerLayer {
         // Set page sanity.
         // Append new page.
         // Get new file block offset - repeat
+        // The pages are appended in runs of up to APPEND_PAGES, a write
+        // for each run and a sync at the end.
         let mut created_page_nos: Vec<PageNo> = Vec::new();
+        let page_ctr_size = self.page_config.block_size * (1 << block_cnt_exp);
+        let mut block_offset = self.file_layer.get_block_count();
+        let mut pages: Vec<Page> = Vec::new();
+        let mut run_page_no = PageNo::new(block_cnt_exp, block_offset);
         for _ in 0..no_new_pages {
-            let block_offset = self.file_layer.get_block_count();
-            let page_ctr_size = self.page_config.block_size * (1 << block_cnt_exp);
             let mut page = Page::new(
                 page_ctr_size,
                 page_ctr_size - self.page_config.block_sanity_size,
             );
             let new_page_no = PageNo::new(block_cnt_exp, block_offset);
+            block_offset += new_page_no.get_blk_cnt();
             page.set_page_number(new_page_no);
             page.set_type(crate::page::PageType::Free);
             self.set_sanity(&mut page);
             created_page_nos.push(new_page_no);
-            self.file_layer.append_new_page(&page, &new_page_no);
+            pages.push(page);
+            if pages.len() == PageContainerLayer::APPEND_PAGES {
+                let result = self.file_layer.append_new_pages(&pages, &run_page_no);
+                self.record_write_error(result);
+                pages.clear();
+                run_page_no = PageNo::new(block_cnt_exp, block_offset);
+            }
         }
+        let result = self.file_layer.append_new_pages(&pages, &run_page_no);
+        self.record_write_error(result);
         // Sync the file and file metadata.
-        self.file_layer.sync_all();
+        let result = self.file_layer.sync_all();
+        self.record_write_error(result);
         created_page_nos
     }
 
     fn set_sanity(&self, page: &mut Page) {
-        self.block_sanity.set_block_sanity(page, &self.key);
+        self.block_sanity
+            .set_block_sanity(page, &self.key, self.page_config.nonce_mode);
+    }
+
+    fn check_sanity(&self, page: &mut Page, page_no: PageNo) -> Result<(), DigbyError> {
+        if !self.block_sanity.is_encrypted() && !self.page_config.verify_checksums {
+            return Ok(());
+        }
+        BlockSanity::check_block_sanity_with(self.block_sanity.as_ref(), page, &self.key, page_no)
     }
 
-    fn check_sanity(&self, page: &mut Page) {
-        self.block_sanity.check_block_sanity(page, &self.key);
+    // Turn checksum checking on reads on or off, has no effect if
+    // encryption is being used.
+    pub fn set_verify_checksums(&mut self, verify_checksums: bool) {
+        self.page_config.verify_checksums = verify_checksums;
+    }
+
+    // Choose how the nonce of an encrypted block is made, has no effect
+    // if checksums are being used.
+    pub fn set_nonce_mode(&mut self, nonce_mode: NonceMode) {
+        self.page_config.nonce_mode = nonce_mode;
+    }
+
+    pub fn set_free_page_growth(&mut self, free_page_growth: u64) {
+        self.page_config.free_page_growth = free_page_growth;
+    }
+
+    pub fn set_key_order(&mut self, key_order: KeyOrder) {
+        self.page_config.key_order = key_order;
     }
 
     pub fn sync_data(&mut self) {
-        self.file_layer.sync_data();
+        let result = self.file_layer.sync_data();
+        self.record_write_error(result);
     }
 
     pub fn sync_all(&mut self) {
-        self.file_layer.sync_all();
+        let result = self.file_layer.sync_all();
+        self.record_write_error(result);
     }
 }
 
@@ -172,7 +334,8 @@ mod tests {
     #[test]
     fn test_block_layer_put_get() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         let mut block_layer = PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let page_number = 0;
         block_layer.generate_free_pages(10, 0);
@@ -186,9 +349,11 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "encryption")]
     fn test_block_layer_put_get_encrypted() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         // Use oversized key to test that only the first 16 bytes are used for AES-128-GCM
         let key = [0u8; 32].to_vec(); // Key for AES-128-GCM
         let mut block_layer = PageContainerLayer::new_with_key(
@@ -215,9 +380,11 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "encryption")]
     fn test_block_layer_put_get_encrypted_small_key() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         // Use undersized key to test that only the first 16 bytes are used for AES-128-GCM
         let key = [0u8; 8].to_vec(); // Key for AES-128-GCM
         let mut block_layer = PageContainerLayer::new_with_key(
@@ -243,11 +410,81 @@ mod tests {
         assert_eq!(&retrieved_page.get_page_bytes()[40..44], &[1, 2, 3, 4]);
     }
 
+    #[test]
+    #[cfg(feature = "chacha20")]
+    fn test_block_layer_put_get_chacha20() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
+        // Undersized key, padded to the 32 bytes ChaCha20-Poly1305 uses.
+        let key = [3u8; 16].to_vec();
+        let mut block_layer = PageContainerLayer::new_with_cipher(
+            file_layer,
+            DbConfig::builder()
+                .block_size(4096)
+                .page_size(4096 - BlockSanity::get_bytes_used(BlockSanity::ChaCha20Poly1305))
+                .block_sanity(BlockSanity::ChaCha20Poly1305)
+                .compressor_type(crate::compressor::CompressorType::None)
+                .leaf_page_blk_exp(0)
+                .dir_page_blk_exp(0)
+                .build(),
+            key,
+            BlockSanity::ChaCha20Poly1305,
+        );
+        let page_number = 0;
+        block_layer.generate_free_pages(10, 0);
+        let mut page = Page::create_new(block_layer.get_page_config(), 1);
+        page.set_page_number(PageNo::from_u64(page_number));
+        page.set_type(PageType::Free);
+        page.get_page_bytes_mut()[40..44].copy_from_slice(&[1, 2, 3, 4]); // Sample data
+        block_layer.write_page(&mut page, PageNo::from_u64(page_number));
+        let retrieved_page = block_layer.read_page(PageNo::from_u64(page_number));
+        assert_eq!(&retrieved_page.get_page_bytes()[40..44], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_block_layer_no_verify_checksums() {
+        let temp_file = tempfile().expect("Failed to create temp file");
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
+        let mut block_layer = PageContainerLayer::new(
+            file_layer,
+            DbConfig::builder()
+                .compressor_type(crate::compressor::CompressorType::None)
+                .verify_checksums(false)
+                .build(),
+        );
+        block_layer.generate_free_pages(1, 0);
+        let mut page = Page::create_new(block_layer.get_page_config(), 1);
+        page.set_page_number(PageNo::from_u64(0));
+        page.set_type(PageType::Free);
+        block_layer.write_page(&mut page, PageNo::from_u64(0));
+        // Damage the checksum, the read does not check it.
+        let mut bad_page = Page::create_new(block_layer.get_page_config(), 1);
+        bad_page.set_page_number(PageNo::from_u64(0));
+        bad_page.set_type(PageType::Free);
+        bad_page.get_pg_ctr_bytes_mut()[4092..4096].copy_from_slice(&[1, 2, 3, 4]);
+        block_layer
+            .file_layer
+            .write_page_to_disk(&bad_page, &PageNo::from_u64(0))
+            .expect("Failed to write page");
+        let retrieved_page = block_layer.read_page(PageNo::from_u64(0));
+        assert_eq!(retrieved_page.get_type(), PageType::Free);
+
+        // Turn checking back on and the damage is found.
+        block_layer.set_verify_checksums(true);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            block_layer.read_page(PageNo::from_u64(0));
+        }));
+        assert!(result.is_err());
+    }
+
     #[test]
     #[should_panic(expected = "Writing page outside the file.")]
     fn test_block_out_side_page_range() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         let mut block_layer = PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let mut page = Page::create_new(block_layer.get_page_config(), 1);
         page.set_page_number(PageNo::from_u64(4));
@@ -259,7 +496,8 @@ mod tests {
     #[test]
     fn test_create_new_pages() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         let mut block_layer = PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let mut free_pages = block_layer.generate_free_pages(1, 0);
         assert!(free_pages.len() == 1);
@@ -272,7 +510,8 @@ mod tests {
     #[test]
     fn test_create_root_page() {
         let temp_file = tempfile().expect("Failed to create temp file");
-        let file_layer = FileLayer::new(temp_file, PAGE_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(temp_file, PAGE_CONFIG.block_size).expect("Failed to create file layer");
         let mut block_layer = PageContainerLayer::new(file_layer, PAGE_CONFIG);
         let mut page = DbRootPage::create_new(block_layer.get_page_config());
         block_layer.generate_free_pages(1, 0);
@@ -0,0 +1,19 @@
+use crate::page::PageType;
+use crate::page_no::PageNo;
+
+// A decoded view of a single page for debugging, see Db::dump_page.
+// Only the fields that make sense for the page type are set, for
+// example keys are only set for leaf and dir pages and next_page and
+// used_size only for overflow pages.
+#[derive(Debug)]
+pub struct PageDump {
+    pub page_no: PageNo,
+    // None if the type byte in the page is not a known page type.
+    pub page_type: Option<PageType>,
+    pub version: u64,
+    pub entries: Option<u16>,
+    pub free_space: Option<u16>,
+    pub keys: Vec<Vec<u8>>,
+    pub next_page: Option<u64>,
+    pub used_size: Option<u16>,
+}
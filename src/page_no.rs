@@ -18,7 +18,9 @@ pub struct PageNo(pub u64);
 // The file_blk_offset is the offset into the file where the
 // page container starts - the offset is in blocks.
 //
-// Note there is no hard coded block size.
+// Note there is no hard coded block size. With 56 bits for the
+// offset a DB of 4096 byte blocks can be up to 2^68 bytes, the page
+// number is 8 bytes wherever it is stored.
 impl PageNo {
     const TOP_BYTE_MASK: u64 = 0xFF00_0000_0000_0000;
     const BOTTOM_56_MASK: u64 = 0x00FF_FFFF_FFFF_FFFF;
@@ -88,4 +90,20 @@ mod tests {
         assert_eq!(page_no_2.get_pg_blk_size(4096), 4096 * 2);
         assert_eq!(page_no_2.get_blk_offset(), 57);
     }
+
+    #[test]
+    fn test_page_no_large_offset() {
+        let offset = u64::from(u32::MAX) + 10;
+        let page_no = PageNo::new(0, offset);
+        assert_eq!(page_no.get_blk_offset(), offset);
+        assert_eq!(PageNo::from_bytes(&page_no.get_bytes()), page_no);
+
+        let max_offset = (1u64 << 56) - 1;
+        let mut page_no = PageNo::new(8, max_offset);
+        assert_eq!(page_no.get_blk_offset(), max_offset);
+        assert_eq!(page_no.get_blk_cnt_exp(), 8);
+        page_no.set_blk_offset(offset);
+        assert_eq!(page_no.get_blk_offset(), offset);
+        assert_eq!(page_no.get_blk_cnt_exp(), 8);
+    }
 }
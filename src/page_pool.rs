@@ -0,0 +1,129 @@
+use crate::db_config::DbConfig;
+use crate::page::Page;
+use std::sync::{Arc, Mutex};
+
+// A free list of page buffers. Pages are copied in and out of the page
+// cache so a put or get allocates a buffer or two for every page it
+// touches. Pages taken from the pool hand their buffer back when they
+// are dropped, the next page taken from the pool reuses it rather than
+// going to the allocator.
+//
+// Only single block pages are pooled, the buffers are all exactly the
+// block size. Larger pages are allocated as usual. The pool keeps at
+// most max_buffers free buffers, any more are freed.
+//
+// The contents of a buffer from the pool are whatever the last page
+// left in it, so only use it for a page that is going to be completely
+// overwritten, such as a copy or a read from disk.
+#[derive(Clone)]
+pub struct PagePool {
+    inner: Arc<Mutex<PagePoolInner>>,
+}
+
+struct PagePoolInner {
+    buffers: Vec<Vec<u8>>,
+    max_buffers: usize,
+}
+
+impl PagePool {
+    pub const DEFAULT_MAX_BUFFERS: usize = 64;
+
+    pub fn new(max_buffers: usize) -> Self {
+        PagePool {
+            inner: Arc::new(Mutex::new(PagePoolInner {
+                buffers: Vec::new(),
+                max_buffers,
+            })),
+        }
+    }
+
+    // Get a page of blk_cnt blocks, its bytes are not cleared.
+    pub fn get_page(&self, page_config: &DbConfig, blk_cnt: u64) -> Page {
+        if blk_cnt != 1 {
+            return Page::create_new(page_config, blk_cnt);
+        }
+        let buffer = self.inner.lock().unwrap().buffers.pop();
+        match buffer {
+            Some(buffer) => Page::from_pool(
+                buffer,
+                page_config.block_size - page_config.block_sanity_size,
+                self.clone(),
+            ),
+            None => Page::from_pool(
+                vec![0u8; page_config.block_size],
+                page_config.block_size - page_config.block_sanity_size,
+                self.clone(),
+            ),
+        }
+    }
+
+    // Called when a page from the pool is dropped.
+    pub fn return_buffer(&self, buffer: Vec<u8>) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.buffers.len() < inner.max_buffers {
+            inner.buffers.push(buffer);
+        }
+    }
+
+    // Set the number of free buffers to keep, 0 turns the pool off.
+    pub fn set_max_buffers(&self, max_buffers: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.max_buffers = max_buffers;
+        inner.buffers.truncate(max_buffers);
+    }
+
+    pub fn get_free_buffer_count(&self) -> usize {
+        self.inner.lock().unwrap().buffers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::PageTrait;
+    use crate::page_no::PageNo;
+
+    const PAGE_CONFIG: DbConfig = DbConfig::builder()
+        .block_size(4096)
+        .page_size(4092)
+        .block_sanity_size(4)
+        .build();
+
+    #[test]
+    fn test_page_pool_reuses_buffers() {
+        let pool = PagePool::new(2);
+        let mut page = pool.get_page(&PAGE_CONFIG, 1);
+        assert_eq!(page.get_pg_ctr_bytes().len(), 4096);
+        assert_eq!(page.get_page_bytes().len(), 4092);
+        page.set_page_number(PageNo::from_u64(42));
+        let ptr = page.get_pg_ctr_bytes().as_ptr();
+        drop(page);
+        assert_eq!(pool.get_free_buffer_count(), 1);
+
+        // The buffer comes back as is.
+        let page = pool.get_page(&PAGE_CONFIG, 1);
+        assert_eq!(page.get_pg_ctr_bytes().as_ptr(), ptr);
+        assert_eq!(page.get_page_number().to_u64(), 42);
+        assert_eq!(pool.get_free_buffer_count(), 0);
+        drop(page);
+
+        // Keeps no more than max_buffers.
+        let pages: Vec<Page> = (0..4).map(|_| pool.get_page(&PAGE_CONFIG, 1)).collect();
+        drop(pages);
+        assert_eq!(pool.get_free_buffer_count(), 2);
+        pool.set_max_buffers(0);
+        assert_eq!(pool.get_free_buffer_count(), 0);
+        drop(pool.get_page(&PAGE_CONFIG, 1));
+        assert_eq!(pool.get_free_buffer_count(), 0);
+    }
+
+    #[test]
+    fn test_page_pool_multi_block_pages() {
+        let pool = PagePool::new(2);
+        let page = pool.get_page(&PAGE_CONFIG, 4);
+        assert_eq!(page.get_pg_ctr_bytes().len(), 4 * 4096);
+        assert_eq!(page.get_page_bytes().len(), 4 * 4096 - 4);
+        drop(page);
+        assert_eq!(pool.get_free_buffer_count(), 0);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::Db;
+use crate::compressor::Compressor;
+use crate::db_master_page::DbMasterPage;
+use crate::page::PageTrait;
+use crate::page_no::PageNo;
+
+// Reads from the DB as it was at one commit, see Db::read_transaction.
+// The tree roots are taken from the master page when the read
+// transaction starts and every read uses them, so a later commit
+// cannot change what is seen part way through.
+pub struct ReadTx<'a> {
+    db: &'a mut Db,
+    version: u64,
+    global_root_page_no: PageNo,
+    table_dir_page_no: PageNo,
+}
+
+impl<'a> ReadTx<'a> {
+    pub fn new(db: &'a mut Db, master_page: &DbMasterPage) -> Self {
+        ReadTx {
+            db,
+            version: master_page.get_version(),
+            global_root_page_no: master_page.get_global_tree_root_page_no(),
+            table_dir_page_no: master_page.get_table_dir_page_no(),
+        }
+    }
+
+    // The version of the commit being read.
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.db.get_from_tree(key, self.global_root_page_no)
+    }
+
+    pub fn range(&mut self, start: &[u8], end: Option<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.db
+            .range_from_tree(start, end, self.global_root_page_no)
+    }
+
+    pub fn get_table_entry(&mut self, table_name: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+        let (table_root_page_no, compressor) = self.get_table_tree(table_name)?;
+        self.db
+            .get_from_table_tree(key, table_root_page_no, compressor)
+    }
+
+    pub fn table_exists(&mut self, table_name: &[u8]) -> bool {
+        self.get_table_tree(table_name).is_some()
+    }
+
+    fn get_table_tree(&mut self, table_name: &[u8]) -> Option<(PageNo, Compressor)> {
+        self.db
+            .get_table_tree_at(table_name, self.version, self.table_dir_page_no)
+    }
+}
@@ -0,0 +1,178 @@
+use crate::Db;
+use crate::block_sanity::BlockSanityProvider;
+use crate::compressor::CompressorType;
+use crate::digby_error::DigbyError;
+use crate::key_order::KeyOrder;
+use crate::page_no::PageNo;
+use crate::snapshot::Snapshot;
+use std::sync::{Arc, Mutex};
+
+// A handle for reading the global tree from other threads while a Db
+// carries on committing, see Db::reader. Clones share the same state so
+// a Reader can be cloned for each thread, or shared in an Arc.
+//
+// Each read is served from the last commit made through the Db. A
+// commit is copy on write and its pages are written before the master
+// page, so once the master page is written the tree of the commit is
+// in the file and is not changed. The Db publishes the version and
+// global tree root of each commit once it has its master page written
+// and a read uses the last one published, the master pages are not
+// read.
+//
+// Reads are made by read only Dbs opened on the same file, one for
+// each read going at the same time. They are kept in a pool and reused,
+// each has its own page cache. The pages of a commit are freed by a
+// later commit and can be reused by the commit after that, a read that
+// runs across two commits can find its pages reused. It fails with
+// SnapshotExpired, or a checksum error if the page was being written,
+// and the read is tried again on the latest commit. An error when there
+// has been no commit since the read started is returned.
+#[derive(Clone)]
+pub struct Reader {
+    shared: Arc<ReaderShared>,
+}
+
+// How to open the DB file again for the read only Dbs. The key is as
+// the sanity provider takes it.
+#[derive(Clone)]
+pub struct ReaderSource {
+    pub path: String,
+    pub key: Option<Vec<u8>>,
+    pub compressor_type: CompressorType,
+    pub block_size: usize,
+    pub sanity_provider: Arc<dyn BlockSanityProvider>,
+    pub key_order: KeyOrder,
+}
+
+// The state shared by a Db and its Readers.
+pub struct ReaderShared {
+    // None if the Db was not opened from a path, it cannot have readers.
+    source: Mutex<Option<ReaderSource>>,
+    // The last commit published by the Db. The Db also holds the lock
+    // while it writes a master page, and a read only Db is opened with
+    // it held, so it never reads a master page part way through being
+    // written.
+    commit: Mutex<Snapshot>,
+    pool: Mutex<Vec<Db>>,
+}
+
+impl ReaderShared {
+    pub fn new() -> Self {
+        ReaderShared {
+            source: Mutex::new(None),
+            commit: Mutex::new(Snapshot::new(0, PageNo::from_u64(0))),
+            pool: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn set_source(&self, source: ReaderSource) {
+        *self.source.lock().unwrap() = Some(source);
+    }
+
+    // The read only Dbs are opened with the key order of the Db, it is
+    // not kept in the file.
+    pub fn set_key_order(&self, key_order: KeyOrder) {
+        if let Some(source) = self.source.lock().unwrap().as_mut() {
+            source.key_order = key_order;
+        }
+    }
+
+    // How the Db's file was opened, None if it was not opened from a
+    // path.
+    pub fn get_source(&self) -> Option<ReaderSource> {
+        self.source.lock().unwrap().clone()
+    }
+
+    // The file has been replaced, drop the pooled Dbs as they have the
+    // old one open and publish the first commit of the new one. Reads
+    // already going finish on the old file.
+    pub fn reopen(&self, snapshot: Snapshot) {
+        let mut commit = self.commit.lock().unwrap();
+        self.pool.lock().unwrap().clear();
+        *commit = snapshot;
+    }
+
+    pub fn has_source(&self) -> bool {
+        self.source.lock().unwrap().is_some()
+    }
+
+    // Lock out readers opening the file while a master page is written.
+    pub fn lock_commit(&self) -> std::sync::MutexGuard<'_, Snapshot> {
+        self.commit.lock().unwrap()
+    }
+
+    pub fn publish(&self, snapshot: Snapshot) {
+        *self.commit.lock().unwrap() = snapshot;
+    }
+
+    pub fn get_commit(&self) -> Snapshot {
+        *self.commit.lock().unwrap()
+    }
+
+    fn take_db(&self) -> Result<Db, DigbyError> {
+        if let Some(db) = self.pool.lock().unwrap().pop() {
+            return Ok(db);
+        }
+        let source = self.source.lock().unwrap();
+        let source = source
+            .as_ref()
+            .expect("A reader needs a DB opened from a path");
+        let _commit = self.commit.lock().unwrap();
+        let db_file = Db::open_read_only_file(&source.path)?;
+        let mut db = Db::try_open_read_only_with_sanity_provider(
+            Box::new(db_file),
+            &source.path,
+            source.key.clone(),
+            source.compressor_type,
+            source.block_size,
+            source.sanity_provider.clone(),
+        )?;
+        db.set_key_order(source.key_order);
+        Ok(db)
+    }
+
+    fn put_db(&self, db: Db) {
+        self.pool.lock().unwrap().push(db);
+    }
+}
+
+impl Default for ReaderShared {
+    fn default() -> Self {
+        ReaderShared::new()
+    }
+}
+
+impl Reader {
+    pub fn new(shared: Arc<ReaderShared>) -> Self {
+        Reader { shared }
+    }
+
+    // The version of the commit reads are served from.
+    pub fn get_version(&self) -> u64 {
+        self.shared.get_commit().get_version()
+    }
+
+    // Panics if the read fails.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.try_get(key)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, DigbyError> {
+        let mut db = self.shared.take_db()?;
+        let mut commit = self.shared.get_commit();
+        let result = loop {
+            let result = db.try_get_at_commit(key, &commit);
+            if result.is_ok() {
+                break result;
+            }
+            let latest = self.shared.get_commit();
+            if latest.get_version() == commit.get_version() {
+                break result;
+            }
+            commit = latest;
+        };
+        self.shared.put_db(db);
+        result
+    }
+}
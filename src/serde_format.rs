@@ -0,0 +1,27 @@
+use crate::DigbyError;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+// How Db::put_typed_with and Db::get_typed_with turn a serde type into
+// the bytes stored and back. Db::put_typed and Db::get_typed use
+// Bincode, another format such as JSON can be used by implementing this
+// for it, the crate only depends on bincode.
+//
+// Unlike a Codec the errors are returned, a value written by a
+// different type or format fails to deserialize rather than panicking.
+pub trait SerdeFormat {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, DigbyError>;
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DigbyError>;
+}
+
+pub struct Bincode;
+
+impl SerdeFormat for Bincode {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, DigbyError> {
+        bincode::serialize(value).map_err(|error| DigbyError::Serialization(error.to_string()))
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, DigbyError> {
+        bincode::deserialize(bytes).map_err(|error| DigbyError::Serialization(error.to_string()))
+    }
+}
@@ -0,0 +1,45 @@
+use crate::Db;
+use crate::digby_error::DigbyError;
+use crate::page_no::PageNo;
+
+// The global tree as it was at one commit, see Db::snapshot. Unlike
+// ReadTx it does not borrow the Db, so commits can carry on while it
+// is held and the Db is passed in for each read.
+//
+// The pages of the snapshot's tree are not held off the free list. A
+// commit that replaces a page frees it and the commit after that can
+// reuse it, so the snapshot is only good while the pages it reads
+// have not been reused. Every page on the way down is checked against
+// the snapshot's version, a reused page has a later version and the
+// read fails with SnapshotExpired rather than returning what the page
+// holds now. A snapshot always survives the next commit, how long
+// after that depends on how many pages are being freed and reused.
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot {
+    version: u64,
+    global_root_page_no: PageNo,
+}
+
+impl Snapshot {
+    pub fn new(version: u64, global_root_page_no: PageNo) -> Self {
+        Snapshot {
+            version,
+            global_root_page_no,
+        }
+    }
+
+    // The version of the commit the snapshot was taken at.
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    // Panics if the snapshot has expired.
+    pub fn get(&self, db: &mut Db, key: &[u8]) -> Option<Vec<u8>> {
+        self.try_get(db, key)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_get(&self, db: &mut Db, key: &[u8]) -> Result<Option<Vec<u8>>, DigbyError> {
+        db.try_get_from_tree_at(key, self.global_root_page_no, self.version)
+    }
+}
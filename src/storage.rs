@@ -0,0 +1,121 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+// Where the file layer keeps its blocks. Normally this is the DB file,
+// but it can be swapped out, for example for MemoryStorage to run a Db
+// without the disk, or a store that fails writes to test how the DB
+// handles a full disk. Pass one to Db::new_with_storage.
+pub trait Storage: Send {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<()>;
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()>;
+
+    // Current length of the store in bytes.
+    fn len(&self) -> std::io::Result<u64>;
+
+    fn is_empty(&self) -> std::io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    // Cut the store down to, or extend it to, len bytes.
+    fn set_len(&mut self, len: u64) -> std::io::Result<()>;
+
+    fn sync_all(&mut self) -> std::io::Result<()>;
+
+    fn sync_data(&mut self) -> std::io::Result<()>;
+
+    // Give the space of len bytes at offset back to the file system,
+    // they read as zeros after and the length of the store is the same.
+    // Best effort, a store that cannot do it leaves the bytes as they
+    // are.
+    fn punch_hole(&mut self, _offset: u64, _len: u64) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Storage for std::fs::File {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buf)
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.write_all(buf)
+    }
+
+    fn len(&self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        std::fs::File::set_len(self, len)
+    }
+
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        std::fs::File::sync_all(self)
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        std::fs::File::sync_data(self)
+    }
+
+    // Only Linux, a file system that does not support punching holes
+    // keeps the blocks.
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, offset: u64, len: u64) -> std::io::Result<()> {
+        use std::os::fd::AsRawFd;
+        // The fd is open for the life of self, fallocate only changes
+        // the blocks of the file.
+        let result = unsafe {
+            libc::fallocate(
+                self.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                offset as libc::off_t,
+                len as libc::off_t,
+            )
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::EOPNOTSUPP) {
+            return Ok(());
+        }
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_file_storage_write_read() {
+        let mut file = tempfile().expect("Failed to create temp file");
+        assert!(Storage::is_empty(&file).unwrap());
+        file.write_all_at(b"world", 5).unwrap();
+        file.write_all_at(b"hello", 0).unwrap();
+        assert_eq!(Storage::len(&file).unwrap(), 10);
+        let mut buf = [0u8; 10];
+        file.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(&buf, b"helloworld");
+        assert!(file.read_exact_at(&mut buf, 5).is_err());
+        file.set_len(5).unwrap();
+        assert_eq!(Storage::len(&file).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_file_storage_punch_hole() {
+        let mut file = tempfile().expect("Failed to create temp file");
+        file.write_all_at(&[7u8; 3 * 4096], 0).unwrap();
+        file.punch_hole(4096, 4096).unwrap();
+        assert_eq!(Storage::len(&file).unwrap(), 3 * 4096);
+        let mut buf = [0u8; 3 * 4096];
+        file.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..4096].iter().all(|b| *b == 7));
+        assert!(buf[2 * 4096..].iter().all(|b| *b == 7));
+        #[cfg(target_os = "linux")]
+        assert!(buf[4096..2 * 4096].iter().all(|b| *b == 0));
+    }
+}
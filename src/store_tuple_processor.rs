@@ -1,6 +1,7 @@
 use crate::OverflowPageHandler;
 use crate::PageNo;
 use crate::db_config::DbConfig;
+use crate::digby_error::DigbyError;
 use crate::dir_page::DirPage;
 use crate::free_page_tracker::FreePageTracker;
 use crate::leaf_page::LeafPage;
@@ -10,6 +11,7 @@ use crate::page_cache::PageCache;
 use crate::tree_dir_entry::TreeDirEntry;
 use crate::tree_dir_handler::{DirPageRef, TreeDirHandler};
 use crate::tuple::{Tuple, TupleTrait};
+use std::cmp::Ordering;
 
 pub struct StoreTupleProcessor {}
 
@@ -21,20 +23,101 @@ impl StoreTupleProcessor {
     // references to the page rather than copies of the pages as when
     // used with update and delete.
     pub fn get_tuple(key: &[u8], page_no: PageNo, page_cache: &mut PageCache) -> Option<Tuple> {
+        StoreTupleProcessor::try_get_tuple(key, page_no, page_cache)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // As get_tuple but a page on the way down that cannot be read is
+    // returned as an error.
+    pub fn try_get_tuple(
+        key: &[u8],
+        page_no: PageNo,
+        page_cache: &mut PageCache,
+    ) -> Result<Option<Tuple>, DigbyError> {
+        StoreTupleProcessor::try_get_tuple_at(key, page_no, u64::MAX, page_cache)
+    }
+
+    // As try_get_tuple for a tree as it was at max_version. A page newer
+    // than max_version has been freed and reused since, see Snapshot.
+    pub fn try_get_tuple_at(
+        key: &[u8],
+        page_no: PageNo,
+        max_version: u64,
+        page_cache: &mut PageCache,
+    ) -> Result<Option<Tuple>, DigbyError> {
+        let key_order = page_cache.get_key_order();
         let mut page_number = page_no;
         loop {
-            let page = page_cache.get_page_ref(page_number);
+            let page = page_cache.try_get_page_ref(page_number)?;
+            if page.get_version() > max_version {
+                return Err(DigbyError::SnapshotExpired(page_number));
+            }
             // If the page is a tree leaf then if the key is stored
             // then it will be in this leaf page.
             if page.get_type() == PageType::LeafPage {
-                return LeafPage::get_tuple_from_page(page, key);
+                return Ok(LeafPage::get_tuple_from_page(page, key, key_order));
             }
             // If its a tree dir page then descend to the next
             // level.
-            page_number = DirPage::get_next_page(page, key);
+            page_number = DirPage::get_next_page(page, key, key_order);
         }
     }
 
+    // Get the tuples for a set of keys, which must be sorted, in the
+    // same order as the keys. Rather than descend from the root for each
+    // key the keys that go to the same child of a dir page are passed
+    // down together, so each page on the way to the leaves is read once
+    // and all the keys in a leaf are looked up in one visit.
+    pub fn try_get_tuples(
+        keys: &[Vec<u8>],
+        page_no: PageNo,
+        page_cache: &mut PageCache,
+    ) -> Result<Vec<Option<Tuple>>, DigbyError> {
+        let key_order = page_cache.get_key_order();
+        debug_assert!(keys.is_sorted_by(|a, b| key_order.compare(a, b) != Ordering::Greater));
+        let mut tuples = Vec::with_capacity(keys.len());
+        StoreTupleProcessor::get_tuples_in_page(keys, page_no, page_cache, &mut tuples)?;
+        Ok(tuples)
+    }
+
+    fn get_tuples_in_page(
+        keys: &[Vec<u8>],
+        page_no: PageNo,
+        page_cache: &mut PageCache,
+        tuples: &mut Vec<Option<Tuple>>,
+    ) -> Result<(), DigbyError> {
+        let key_order = page_cache.get_key_order();
+        let page = page_cache.try_get_page_ref(page_no)?;
+        if page.get_type() == PageType::LeafPage {
+            tuples.extend(
+                keys.iter()
+                    .map(|key| LeafPage::get_tuple_from_page(page, key, key_order)),
+            );
+            return Ok(());
+        }
+        // The keys are sorted so the keys for a child are next to each
+        // other, group them as the child page and the end of its keys.
+        let mut children: Vec<(PageNo, usize)> = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            let child_page_no = DirPage::get_next_page(page, key, key_order);
+            match children.last_mut() {
+                Some((last_page_no, end)) if *last_page_no == child_page_no => *end = index + 1,
+                _ => children.push((child_page_no, index + 1)),
+            }
+        }
+        let mut start = 0;
+        for (child_page_no, end) in children {
+            StoreTupleProcessor::get_tuples_in_page(
+                &keys[start..end],
+                child_page_no,
+                page_cache,
+                tuples,
+            )?;
+            start = end;
+        }
+        Ok(())
+    }
+
     // Given the root page of the tree store the tuple, the root page
     // could be a leaf page if the tree is empty or it could be dir
     // page.
@@ -46,13 +129,43 @@ impl StoreTupleProcessor {
         new_version: u64,
         db_config: &DbConfig,
     ) -> u64 {
+        let (root_page_no, _) = StoreTupleProcessor::store_tuple_returning(
+            tuple,
+            first,
+            true,
+            free_page_tracker,
+            page_cache,
+            new_version,
+            db_config,
+        );
+        root_page_no
+    }
+
+    // As store_tuple but the tuple already in the tree for the key is
+    // returned as well, found in the leaf page the tuple is added to so
+    // the tree is only descended once. If replace is true it is
+    // replaced and its overflow pages are returned to the free page
+    // tracker, which does not hand them out again in this commit so its
+    // value can still be read. If replace is false and the key is in the
+    // tree nothing is written, the overflow pages of tuple are freed and
+    // the root page number is unchanged.
+    pub fn store_tuple_returning(
+        tuple: Tuple,
+        first: Page,
+        replace: bool,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+        new_version: u64,
+        db_config: &DbConfig,
+    ) -> (u64, Option<Tuple>) {
         // Special case if the first page is a leaf page.
         if first.get_type() == PageType::LeafPage {
             // The root of the tree is actually a leaf page - requires special handling.
-            let tree_root_single = LeafPage::from_page(first);
+            let tree_root_single = LeafPage::from_page(first, db_config.key_order);
             return StoreTupleProcessor::store_tuple_tree_root_single(
                 tuple,
                 tree_root_single,
+                replace,
                 free_page_tracker,
                 page_cache,
                 new_version,
@@ -63,10 +176,11 @@ impl StoreTupleProcessor {
         // The root page is a tree dir page, convert to dir page
         // and descend into the tree to find the correct leaf page
         // to add the tuple too.
-        let root_dir_page = DirPage::from_page(first);
+        let root_dir_page = DirPage::from_page(first, db_config.key_order);
         StoreTupleProcessor::store_tuple_tree(
             tuple,
             root_dir_page,
+            replace,
             free_page_tracker,
             page_cache,
             new_version,
@@ -80,15 +194,17 @@ impl StoreTupleProcessor {
     // after the tuple is added. The directory pages are stored on a stack.
     //
     // Returns the page number of the root page of the tree after adding
-    // the tuple.
+    // the tuple, and the tuple that was in the tree for the key.
     fn store_tuple_tree(
         tuple: Tuple,
         root_dir_page: DirPage,
+        replace: bool,
         free_page_tracker: &mut FreePageTracker,
         page_cache: &mut PageCache,
         new_version: u64,
         db_config: &DbConfig,
-    ) -> u64 {
+    ) -> (u64, Option<Tuple>) {
+        let root_page_no = root_dir_page.get_page_number().to_u64();
         let mut dir_page = root_dir_page;
         // This is the stack for storing the tree dir as we descend into
         // the tree.
@@ -109,10 +225,21 @@ impl StoreTupleProcessor {
             let page = page_cache.get_page(next_page_no);
             // If the page is a leaf page we can start the add process
             if page.get_type() == PageType::LeafPage {
-                leaf_page = LeafPage::from_page(page);
+                leaf_page = LeafPage::from_page(page, db_config.key_order);
                 break;
             }
-            dir_page = DirPage::from_page(page);
+            dir_page = DirPage::from_page(page, db_config.key_order);
+        }
+
+        // The key is kept as it is, the overflow pages written for
+        // tuple are not needed.
+        if !replace && let Some(existing_tuple) = leaf_page.get_tuple(tuple.get_key()) {
+            OverflowPageHandler::delete_overflow_tuple_pages(
+                Some(&tuple),
+                page_cache,
+                free_page_tracker,
+            );
+            return (root_page_no, Some(existing_tuple));
         }
 
         // Now have a leaf_page and a stack of dir pages.
@@ -124,8 +251,9 @@ impl StoreTupleProcessor {
         // was overwritten. The method add_tuple will return any tuple that
         // was overwritten in the update_result, this tuple could point to an
         // overflow tuple - in that case the overflow tuple needs to be deleted.
+        let deleted_tuple = update_result.deleted_tuple;
         OverflowPageHandler::delete_overflow_tuple_pages(
-            update_result.deleted_tuple,
+            deleted_tuple.as_ref(),
             page_cache,
             free_page_tracker,
         );
@@ -182,10 +310,10 @@ impl StoreTupleProcessor {
         // If after walking the stack there is only one dir_entry
         // then the root has not split - we can just return its page number.
         if dir_entries.len() == 1 {
-            return dir_entries.first().unwrap().get_page_no();
+            return (dir_entries.first().unwrap().get_page_no(), deleted_tuple);
         }
 
-        // We have hit the top of the stack but have two dir entries, the root has split.
+        // We have hit the top of the stack but have two or more dir entries, the root has split.
         // Need to create a new root, register the entries and return the reference to the root.
         // Need a new TreeDirPage.
         let new_tree_dir_page =
@@ -200,7 +328,7 @@ impl StoreTupleProcessor {
             new_version,
         );
         assert!(dir_entries.len() == 1);
-        dir_entries.first().unwrap().get_page_no()
+        (dir_entries.first().unwrap().get_page_no(), deleted_tuple)
     }
 
     // Write out the dir pages, we are passed TreeDirPageRef. When splitting
@@ -273,17 +401,33 @@ impl StoreTupleProcessor {
     fn store_tuple_tree_root_single(
         tuple: Tuple,
         tree_root_single: LeafPage,
+        replace: bool,
         free_page_tracker: &mut FreePageTracker,
         page_cache: &mut PageCache,
         new_version: u64,
         db_config: &DbConfig,
-    ) -> u64 {
+    ) -> (u64, Option<Tuple>) {
+        // The key is kept as it is, the overflow pages written for
+        // tuple are not needed.
+        if !replace && let Some(existing_tuple) = tree_root_single.get_tuple(tuple.get_key()) {
+            OverflowPageHandler::delete_overflow_tuple_pages(
+                Some(&tuple),
+                page_cache,
+                free_page_tracker,
+            );
+            return (
+                tree_root_single.get_page_number().to_u64(),
+                Some(existing_tuple),
+            );
+        }
+
         // Add the tuple to the leaf page.
         let mut update_result = LeafPageHandler::add_tuple(db_config, tree_root_single, tuple);
 
         // Clean up any overflow pages that may now be dangling if a tuple was overwritten
+        let deleted_tuple = update_result.deleted_tuple.take();
         OverflowPageHandler::delete_overflow_tuple_pages(
-            update_result.deleted_tuple,
+            deleted_tuple.as_ref(),
             page_cache,
             free_page_tracker,
         );
@@ -303,7 +447,7 @@ impl StoreTupleProcessor {
             // Write the new root leaf page to disk
             page_cache.put_page(root_leaf_page.get_page());
             // Return the new root page_number
-            return page_number;
+            return (page_number, deleted_tuple);
         }
 
         // The root leaf page has split. Need a new DirPage that will act as the root and hold the
@@ -333,7 +477,7 @@ impl StoreTupleProcessor {
             new_version,
         );
         assert!(dir_entries.len() == 1);
-        dir_entries.first().unwrap().get_page_no()
+        (dir_entries.first().unwrap().get_page_no(), deleted_tuple)
     }
 }
 
@@ -364,7 +508,8 @@ mod tests {
             .open(&temp_file)
             .expect("Failed to open or create DB file");
 
-        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, DB_CONFIG.block_size);
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, DB_CONFIG.block_size)
+            .expect("Failed to create file layer");
         let block_layer: crate::PageContainerLayer =
             crate::PageContainerLayer::new(file_layer, DB_CONFIG);
         let mut page_cache: crate::PageCache = crate::PageCache::new(block_layer);
@@ -417,7 +562,8 @@ mod tests {
             .open(&temp_file)
             .expect("Failed to open or create DB file");
 
-        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, DB_CONFIG.block_size);
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, DB_CONFIG.block_size)
+            .expect("Failed to create file layer");
         let block_layer: crate::PageContainerLayer =
             crate::PageContainerLayer::new(file_layer, DB_CONFIG);
         let mut page_cache: crate::PageCache = crate::PageCache::new(block_layer);
@@ -464,9 +610,10 @@ mod tests {
                 break;
             }
         }
-        assert_eq!(j, 193);
+        assert_eq!(j, 184);
 
-        let root_page = DirPage::from_page(page_cache.get_page(root_tree_page_no));
+        let root_page =
+            DirPage::from_page(page_cache.get_page(root_tree_page_no), DB_CONFIG.key_order);
         // There are two leaf pages, but only 1 key stored.
         assert_eq!(root_page.get_entries_size(), 1);
         std::fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -484,7 +631,8 @@ mod tests {
             .open(&temp_file)
             .expect("Failed to open or create DB file");
 
-        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, DB_CONFIG.block_size);
+        let file_layer: crate::FileLayer = crate::FileLayer::new(db_file, DB_CONFIG.block_size)
+            .expect("Failed to create file layer");
         let block_layer: crate::PageContainerLayer =
             crate::PageContainerLayer::new(file_layer, DB_CONFIG);
         let mut page_cache: crate::PageCache = crate::PageCache::new(block_layer);
@@ -530,7 +678,8 @@ mod tests {
 
         let root_page = page_cache.get_page(root_tree_page_no);
         assert!(root_page.get_type() == PageType::DirPage);
-        let root_dir_page = DirPage::from_page(page_cache.get_page(root_tree_page_no));
+        let root_dir_page =
+            DirPage::from_page(page_cache.get_page(root_tree_page_no), DB_CONFIG.key_order);
         // There should be 42 entries.
         assert_eq!(root_dir_page.get_entries_size(), 1);
         let tuple = StoreTupleProcessor::get_tuple(
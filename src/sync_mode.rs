@@ -0,0 +1,27 @@
+// How hard a commit works to make its changes durable. A commit writes
+// the new pages, syncs, writes the master page and syncs again so a
+// crash leaves either the old or the new master pointing at complete
+// trees. The sync mode controls what those two syncs are:
+//
+//   None - no syncs at all. Fastest, and a process crash is fine as the
+//          writes are in the OS page cache, but an OS crash or power
+//          loss can lose commits. Worse the OS can write the master page
+//          before the pages it points to so the DB can be left corrupt.
+//   Data - sync_data (fdatasync) for both syncs, the default. The page
+//          contents are durable and the ordering is kept. fdatasync only
+//          flushes the file metadata needed to read the data back, on
+//          Linux that includes the file size but that is not promised
+//          everywhere so pages appended by the commit can be lost.
+//   Full - sync_all (fsync) for both syncs. Also flushes the file
+//          metadata so appended pages are durable. Slowest.
+//
+// Only commits follow the sync mode, creating a new DB file always uses
+// sync_data and dropping the Db always does a sync_all. Db::flush does a
+// sync_all at any time, with None it makes the commits so far durable
+// so a bulk load can skip the syncs and flush at the end.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum SyncMode {
+    None,
+    Data,
+    Full,
+}
@@ -0,0 +1,128 @@
+use crate::compressor::CompressorType;
+use crate::page_no::PageNo;
+
+// The value stored for a table in the table directory tree, the key is
+// the table name. Holds the root page of the table tree and the version
+// of the last commit that changed the table.
+//
+// Older DBs stored just the 8 byte root page number, these are read with
+// a version of 0 and are rewritten in the new format the next time the
+// table changes.
+//
+// A table created with its own compressor, see
+// Db::create_table_with_compressor, has a 17th byte for the compressor
+// type. Tables using the DB's compressor leave it off.
+pub struct TableDirEntry {
+    root_page_no: PageNo,
+    version: u64,
+    compressor_type: Option<CompressorType>,
+}
+
+impl TableDirEntry {
+    const LEGACY_SIZE: usize = 8;
+    const SIZE: usize = 16;
+    const COMPRESSOR_SIZE: usize = 17;
+
+    pub fn new(root_page_no: PageNo, version: u64) -> Self {
+        TableDirEntry {
+            root_page_no,
+            version,
+            compressor_type: None,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match bytes.len() {
+            TableDirEntry::LEGACY_SIZE => TableDirEntry {
+                root_page_no: PageNo::from_bytes(bytes),
+                version: 0,
+                compressor_type: None,
+            },
+            TableDirEntry::SIZE => TableDirEntry {
+                root_page_no: PageNo::from_bytes(&bytes[0..8]),
+                version: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+                compressor_type: None,
+            },
+            TableDirEntry::COMPRESSOR_SIZE => TableDirEntry {
+                root_page_no: PageNo::from_bytes(&bytes[0..8]),
+                version: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+                compressor_type: Some(
+                    CompressorType::try_from(bytes[16])
+                        .unwrap_or_else(|error| panic!("Table directory entry has {}.", error)),
+                ),
+            },
+            _ => panic!("Table directory entry has a bad size."),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TableDirEntry::COMPRESSOR_SIZE);
+        bytes.extend_from_slice(&self.root_page_no.get_bytes());
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        if let Some(compressor_type) = self.compressor_type {
+            bytes.push(compressor_type.into());
+        }
+        bytes
+    }
+
+    pub fn get_root_page_no(&self) -> PageNo {
+        self.root_page_no
+    }
+
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+
+    // The table's own compressor, None if it uses the DB's.
+    pub fn get_compressor_type(&self) -> Option<CompressorType> {
+        self.compressor_type
+    }
+
+    pub fn set_compressor_type(&mut self, compressor_type: Option<CompressorType>) {
+        self.compressor_type = compressor_type;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_dir_entry() {
+        let table_dir_entry = TableDirEntry::new(PageNo::from_u64(45), 7);
+        let bytes = table_dir_entry.to_bytes();
+        assert_eq!(bytes.len(), 16);
+        let table_dir_entry = TableDirEntry::from_bytes(&bytes);
+        assert_eq!(table_dir_entry.get_root_page_no(), PageNo::from_u64(45));
+        assert_eq!(table_dir_entry.get_version(), 7);
+    }
+
+    #[test]
+    fn test_table_dir_entry_compressor() {
+        let mut table_dir_entry = TableDirEntry::new(PageNo::from_u64(45), 7);
+        assert_eq!(table_dir_entry.get_compressor_type(), None);
+        table_dir_entry.set_compressor_type(Some(CompressorType::None));
+        let bytes = table_dir_entry.to_bytes();
+        assert_eq!(bytes.len(), 17);
+        let table_dir_entry = TableDirEntry::from_bytes(&bytes);
+        assert_eq!(table_dir_entry.get_root_page_no(), PageNo::from_u64(45));
+        assert_eq!(table_dir_entry.get_version(), 7);
+        assert_eq!(
+            table_dir_entry.get_compressor_type(),
+            Some(CompressorType::None)
+        );
+    }
+
+    #[test]
+    fn test_table_dir_entry_legacy() {
+        let table_dir_entry = TableDirEntry::from_bytes(&45u64.to_le_bytes());
+        assert_eq!(table_dir_entry.get_root_page_no(), PageNo::from_u64(45));
+        assert_eq!(table_dir_entry.get_version(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Table directory entry has a bad size.")]
+    fn test_table_dir_entry_bad_size() {
+        TableDirEntry::from_bytes(&[0u8; 12]);
+    }
+}
@@ -0,0 +1,14 @@
+use crate::page_no::PageNo;
+
+// Summary of a table in the DB, see Db::table_infos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableInfo {
+    pub name: Vec<u8>,
+    // Root page of the table's tree.
+    pub root_page_no: PageNo,
+    // Number of key/values in the table.
+    pub entry_count: u64,
+    // Number of leaf and dir pages in the table's tree, overflow
+    // pages are not counted.
+    pub page_count: u64,
+}
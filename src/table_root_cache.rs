@@ -0,0 +1,103 @@
+use crate::compressor::CompressorType;
+use crate::page_no::PageNo;
+use std::collections::HashMap;
+
+// Cache of table name to table tree root page and the table's own
+// compressor, see TableDirEntry, saves walking the table directory tree
+// on every read of a table. The cache is only for the
+// committed DB and is tagged with the version it is valid for, reads in
+// a transaction do not use it as they can see uncommitted changes.
+//
+// On commit the cache is moved on to the new version if the table
+// directory tree did not change, otherwise it is emptied.
+pub struct TableRootCache {
+    version: u64,
+    roots: HashMap<Vec<u8>, (PageNo, Option<CompressorType>)>,
+}
+
+impl Default for TableRootCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TableRootCache {
+    pub fn new() -> Self {
+        TableRootCache {
+            version: 0,
+            roots: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &[u8], version: u64) -> Option<(PageNo, Option<CompressorType>)> {
+        if self.version != version {
+            return None;
+        }
+        self.roots.get(name).copied()
+    }
+
+    pub fn insert(
+        &mut self,
+        name: &[u8],
+        root_page_no: PageNo,
+        compressor_type: Option<CompressorType>,
+        version: u64,
+    ) {
+        if self.version != version {
+            self.roots.clear();
+            self.version = version;
+        }
+        self.roots
+            .insert(name.to_vec(), (root_page_no, compressor_type));
+    }
+
+    // A commit has moved the DB from old_version to new_version. Keep the
+    // cached roots only if they were for old_version and the table
+    // directory tree has not changed.
+    pub fn commit(&mut self, old_version: u64, new_version: u64, table_dir_changed: bool) {
+        if table_dir_changed || self.version != old_version {
+            self.roots.clear();
+        }
+        self.version = new_version;
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_root_cache() {
+        let mut cache = TableRootCache::new();
+        cache.insert(b"table", PageNo::from_u64(7), None, 3);
+        assert_eq!(cache.get(b"table", 3), Some((PageNo::from_u64(7), None)));
+        assert_eq!(cache.get(b"table", 4), None);
+        assert_eq!(cache.get(b"other", 3), None);
+
+        // Commit that does not touch the table directory keeps the roots.
+        cache.commit(3, 4, false);
+        assert_eq!(cache.get(b"table", 4), Some((PageNo::from_u64(7), None)));
+        // Commit that changes the table directory drops them.
+        cache.commit(4, 5, true);
+        assert_eq!(cache.get(b"table", 5), None);
+        assert!(cache.is_empty());
+
+        // Commit from a version the cache does not know about drops them.
+        cache.insert(b"table", PageNo::from_u64(7), None, 5);
+        cache.commit(6, 7, false);
+        assert_eq!(cache.get(b"table", 7), None);
+
+        // Insert for a new version drops the old roots.
+        cache.insert(b"table", PageNo::from_u64(7), None, 7);
+        cache.insert(b"other", PageNo::from_u64(8), None, 8);
+        assert_eq!(cache.len(), 1);
+    }
+}
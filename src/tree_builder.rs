@@ -0,0 +1,160 @@
+use crate::PageNo;
+use crate::db_config::DbConfig;
+use crate::dir_page::DirPage;
+use crate::free_page_tracker::FreePageTracker;
+use crate::leaf_page::LeafPage;
+use crate::page::PageTrait;
+use crate::page_cache::PageCache;
+use crate::tuple::{Tuple, TupleTrait};
+
+// Build a packed tree from tuples given one at a time in key order. Each
+// leaf page is filled as full as it will go and written out once the
+// next tuple does not fit, so only the leaf being filled and a key and
+// page number for each leaf are held. finish builds the dir pages over
+// the leaves from the bottom up and returns the root. Used by compact
+// and bulk_load, see CompactHandler.
+//
+// The pages have no fences so no prefix compression, the same as a root
+// leaf page. The pages are full so the next add to a page will split it.
+pub struct TreeBuilder {
+    new_version: u64,
+    db_config: DbConfig,
+    leaf_page: LeafPage,
+    // The key a dir page uses to find the leaf being filled.
+    first_key: Vec<u8>,
+    last_key: Vec<u8>,
+    // The key and page number of each leaf written, the key for the
+    // first page is not used as it is the page to the left.
+    leaves: Vec<(Vec<u8>, PageNo)>,
+}
+
+impl TreeBuilder {
+    pub fn new(new_version: u64, db_config: &DbConfig) -> Self {
+        TreeBuilder {
+            new_version,
+            db_config: *db_config,
+            leaf_page: LeafPage::create_new(db_config, PageNo::from_u64(0), new_version),
+            first_key: Vec::new(),
+            last_key: Vec::new(),
+            leaves: Vec::new(),
+        }
+    }
+
+    // The tuple must have a key greater than the last one added, in the
+    // key order of the DB.
+    pub fn add_tuple(
+        &mut self,
+        tuple: &Tuple,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+    ) {
+        if !self.leaf_page.add_tuple(tuple).0 {
+            let leaf_page = std::mem::replace(
+                &mut self.leaf_page,
+                LeafPage::create_new(&self.db_config, PageNo::from_u64(0), self.new_version),
+            );
+            let first_key = std::mem::replace(
+                &mut self.first_key,
+                self.db_config
+                    .key_order
+                    .separator(&self.last_key, tuple.get_key()),
+            );
+            self.write_leaf(first_key, leaf_page, free_page_tracker, page_cache);
+            let (ok, _) = self.leaf_page.add_tuple(tuple);
+            assert!(ok, "Tuple does not fit in an empty leaf page.");
+        }
+        self.last_key = tuple.get_key().to_vec();
+    }
+
+    // Write the last leaf and build the dir pages. No tuples gives an
+    // empty leaf page. Returns the page number of the root of the tree.
+    pub fn finish(
+        mut self,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+    ) -> PageNo {
+        let first_key = std::mem::take(&mut self.first_key);
+        let leaf_page = std::mem::replace(
+            &mut self.leaf_page,
+            LeafPage::create_new(&self.db_config, PageNo::from_u64(0), self.new_version),
+        );
+        self.write_leaf(first_key, leaf_page, free_page_tracker, page_cache);
+
+        // Build dir pages over the level below until there is a single
+        // page, that is the root.
+        let mut level = self.leaves;
+        while level.len() > 1 {
+            level = TreeBuilder::build_dir_level(
+                level,
+                free_page_tracker,
+                page_cache,
+                self.new_version,
+                &self.db_config,
+            );
+        }
+        level[0].1
+    }
+
+    fn write_leaf(
+        &mut self,
+        first_key: Vec<u8>,
+        mut leaf_page: LeafPage,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+    ) {
+        let page_no = free_page_tracker.get_free_page(page_cache);
+        leaf_page.set_page_number(page_no);
+        page_cache.put_page(leaf_page.get_page());
+        self.leaves.push((first_key, page_no));
+    }
+
+    // Pack the child pages into dir pages. The first child of each dir
+    // page is its page to the left and its key becomes the key for the
+    // dir page in the level above.
+    fn build_dir_level(
+        children: Vec<(Vec<u8>, PageNo)>,
+        free_page_tracker: &mut FreePageTracker,
+        page_cache: &mut PageCache,
+        new_version: u64,
+        db_config: &DbConfig,
+    ) -> Vec<(Vec<u8>, PageNo)> {
+        // Work out which children go in each dir page.
+        let mut groups: Vec<Vec<(Vec<u8>, PageNo)>> = Vec::new();
+        let mut group: Vec<(Vec<u8>, PageNo)> = Vec::new();
+        let mut dir_page = DirPage::create_new(db_config, PageNo::from_u64(0), new_version);
+        for (key, child_page_no) in children {
+            if group.is_empty() {
+                dir_page.set_page_to_left(child_page_no);
+            } else if !dir_page.add_child_page(&key, child_page_no.to_u64()) {
+                groups.push(std::mem::take(&mut group));
+                dir_page = DirPage::create_new(db_config, PageNo::from_u64(0), new_version);
+                dir_page.set_page_to_left(child_page_no);
+            }
+            group.push((key, child_page_no));
+        }
+        groups.push(group);
+        // A dir page with only a page to the left has no keys, move the
+        // last child of the page before over to it.
+        let groups_len = groups.len();
+        if groups_len > 1 && groups[groups_len - 1].len() == 1 {
+            let child = groups[groups_len - 2].pop().unwrap();
+            groups[groups_len - 1].insert(0, child);
+        }
+
+        let mut level: Vec<(Vec<u8>, PageNo)> = Vec::with_capacity(groups.len());
+        for mut group in groups {
+            let mut dir_page = DirPage::create_new(db_config, PageNo::from_u64(0), new_version);
+            let (first_key, first_page_no) = group.remove(0);
+            dir_page.set_page_to_left(first_page_no);
+            for (key, child_page_no) in group {
+                let ok = dir_page.add_child_page(&key, child_page_no.to_u64());
+                assert!(ok, "Child page does not fit in the dir page.");
+            }
+            let page_no = free_page_tracker.get_free_page(page_cache);
+            dir_page.set_page_number(page_no);
+            page_cache.put_page(dir_page.get_page());
+            level.push((first_key, page_no));
+        }
+        level
+    }
+}
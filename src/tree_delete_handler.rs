@@ -2,15 +2,17 @@ use crate::PageNo;
 use crate::db_config::DbConfig;
 use crate::dir_page::DirPage;
 use crate::page::{PageTrait, PageType};
-use crate::tuple::{Overflow, TupleTrait};
+use crate::tuple::{Tuple, TupleTrait};
 use crate::{FreePageTracker, LeafPage, OverflowPageHandler, Page, PageCache, TreeDirEntry};
 
 pub struct TreeDeleteHandler {}
 
 impl TreeDeleteHandler {
     // Delete a key from the tree. Returns the new page number of
-    // the root of the tree along with a bool to indicate if the
-    // tuple associated with the key existed and was deleted.
+    // the root of the tree along with the tuple that was deleted, None
+    // if the key was not in the tree. The overflow pages of the tuple
+    // are returned to the free page tracker, which does not hand them
+    // out again in this commit, so its value can still be read.
     pub fn delete_key(
         key: &[u8],
         root_page: Page,
@@ -18,11 +20,11 @@ impl TreeDeleteHandler {
         free_page_tracker: &mut FreePageTracker,
         new_version: u64,
         _db_config: &DbConfig,
-    ) -> (PageNo, bool) {
+    ) -> (PageNo, Option<Tuple>) {
         // The root of the tree is a leaf node.
         if root_page.get_type() == PageType::LeafPage {
             // The root of the tree is actually a leaf page - requires special handling.
-            let mut tree_root_single = LeafPage::from_page(root_page);
+            let mut tree_root_single = LeafPage::from_page(root_page, page_cache.get_key_order());
             return TreeDeleteHandler::delete_key_from_root(
                 key,
                 &mut tree_root_single,
@@ -34,7 +36,7 @@ impl TreeDeleteHandler {
 
         // The root page is a directory page - need to descend into the
         // tree to delete.
-        let root_dir_page = DirPage::from_page(root_page);
+        let root_dir_page = DirPage::from_page(root_page, page_cache.get_key_order());
         TreeDeleteHandler::delete_key_from_tree(
             key,
             root_dir_page,
@@ -50,7 +52,7 @@ impl TreeDeleteHandler {
         page_cache: &mut PageCache,
         free_page_tracker: &mut FreePageTracker,
         new_version: u64,
-    ) -> (PageNo, bool) {
+    ) -> (PageNo, Option<Tuple>) {
         let root_page_no = root_dir_page.get_page_number();
         let mut dir_page = root_dir_page;
         // This is the stack for storing the tree dir as we descend into
@@ -65,23 +67,21 @@ impl TreeDeleteHandler {
             dir_pages.push(dir_page);
             let page = page_cache.get_page(next_page);
             if page.get_type() == PageType::LeafPage {
-                leaf_page = LeafPage::from_page(page);
+                leaf_page = LeafPage::from_page(page, page_cache.get_key_order());
                 break;
             }
-            dir_page = DirPage::from_page(page);
+            dir_page = DirPage::from_page(page, page_cache.get_key_order());
         }
 
-        let tuple = leaf_page.delete_key(key);
-        if tuple.is_none() {
-            return (root_page_no, false);
-        }
+        let Some(tuple) = leaf_page.delete_key(key) else {
+            return (root_page_no, None);
+        };
 
         // Have we just removed an overflow page?
-        let tuple_unwrapped = tuple.unwrap();
-        if tuple_unwrapped.get_overflow() != Overflow::None {
+        if tuple.get_overflow().has_overflow_pages() {
             // Overflow page - need to delete overflows.
             OverflowPageHandler::delete_overflow_tuple_pages(
-                Some(tuple_unwrapped),
+                Some(&tuple),
                 page_cache,
                 free_page_tracker,
             );
@@ -110,7 +110,34 @@ impl TreeDeleteHandler {
             new_leaf_page_no.to_u64(),
             old_leaf_page_no.to_u64(),
         );
-        (new_root_page_no, true)
+        let new_root_page_no =
+            TreeDeleteHandler::collapse_root(new_root_page_no, page_cache, free_page_tracker);
+        (new_root_page_no, Some(tuple))
+    }
+
+    // After deletes the root dir page can be left with only the page to
+    // the left, the tree has one more level than it needs. Make the single
+    // child the new root, repeating until the root has more than one child
+    // or is a leaf page. The child keeps its fences, adding a key outside
+    // the fences resets them as it does for any other page.
+    fn collapse_root(
+        root_page_no: PageNo,
+        page_cache: &mut PageCache,
+        free_page_tracker: &mut FreePageTracker,
+    ) -> PageNo {
+        let mut root_page_no = root_page_no;
+        loop {
+            let page = page_cache.get_page(root_page_no);
+            if page.get_type() == PageType::LeafPage {
+                return root_page_no;
+            }
+            let dir_page = DirPage::from_page(page, page_cache.get_key_order());
+            if dir_page.get_entries_size() != 0 {
+                return root_page_no;
+            }
+            free_page_tracker.return_free_page_no(root_page_no);
+            root_page_no = dir_page.get_page_to_left();
+        }
     }
 
     fn fix_stack(
@@ -254,7 +281,7 @@ impl TreeDeleteHandler {
         page_cache: &mut PageCache,
         free_page_tracker: &mut FreePageTracker,
         new_version: u64,
-    ) -> (PageNo, bool) {
+    ) -> (PageNo, Option<Tuple>) {
         let root_page_no = root_page.get_page_number();
 
         // A leaf tree cannot overflow on delete, this may seem
@@ -262,20 +289,17 @@ impl TreeDeleteHandler {
         // entry in the page is deleted it could trigger rebuilding
         // the compression. However for leaf pages we keep fences
         // on delete.
-        let tuple = root_page.delete_key(key);
-        if tuple.is_none() {
+        let Some(tuple) = root_page.delete_key(key) else {
             // Nothing deleted, no changes to the tree.
-            return (root_page_no, false);
-        }
+            return (root_page_no, None);
+        };
 
-        // A tuple was deleted and we can unwrap it.
-        // The tuple in the tree could point to an oveflow
-        // tuple so need to delete it.
-        let tuple_unwrapped = tuple.unwrap();
-        if tuple_unwrapped.get_overflow() != Overflow::None {
+        // A tuple was deleted. The tuple in the tree could point
+        // to an oveflow tuple so need to delete it.
+        if tuple.get_overflow().has_overflow_pages() {
             // Overflow page - need to delete overflows.
             OverflowPageHandler::delete_overflow_tuple_pages(
-                Some(tuple_unwrapped),
+                Some(&tuple),
                 page_cache,
                 free_page_tracker,
             );
@@ -293,6 +317,6 @@ impl TreeDeleteHandler {
         root_page.set_version(new_version);
         page_cache.put_page(root_page.get_page());
 
-        (new_root_page_no, true)
+        (new_root_page_no, Some(tuple))
     }
 }
@@ -6,10 +6,10 @@ pub struct TreeDirEntry {
 
 impl TreeDirEntry {
     pub fn new(key: Vec<u8>, page_no: u64) -> Self {
-        assert!(key.len() <= u8::MAX as usize);
+        assert!(key.len() <= u16::MAX as usize);
         let mut serialized = Vec::new();
         serialized.extend_from_slice(&page_no.to_le_bytes());
-        serialized.push(key.len() as u8);
+        serialized.extend_from_slice(&(key.len() as u16).to_le_bytes());
         serialized.extend_from_slice(&key);
 
         TreeDirEntry {
@@ -25,7 +25,7 @@ impl TreeDirEntry {
 
         let mut cursor = Cursor::new(&bytes[..]);
         let page_no = cursor.read_u64::<LittleEndian>().unwrap();
-        let key_len = cursor.read_u8().unwrap();
+        let key_len = cursor.read_u16::<LittleEndian>().unwrap();
         let mut key = vec![0u8; key_len as usize];
         cursor.read_exact(&mut key).unwrap();
 
@@ -65,6 +65,6 @@ mod tests {
         let tree_dir_entry2 = TreeDirEntry::from_bytes(tree_dir_entry1.get_serialized().to_vec());
         assert!(b"mmk".to_vec() == tree_dir_entry2.get_key());
         assert!(45 == tree_dir_entry2.get_page_no());
-        assert_eq!(tree_dir_entry1.get_byte_size(), 12);
+        assert_eq!(tree_dir_entry1.get_byte_size(), 13);
     }
 }
@@ -2,6 +2,7 @@ use crate::dir_page::DirPage;
 use crate::page::PageTrait;
 use crate::page_cache::PageCache;
 use crate::{FreePageTracker, TreeDirEntry, db_config};
+use std::cmp::Ordering;
 
 pub struct TreeDirHandler {}
 
@@ -11,42 +12,17 @@ pub struct DirPageRef {
 }
 
 impl TreeDirHandler {
+    // Store the entries for a leaf page, and any pages it split into,
+    // in its parent dir page. The first entry replaces the reference to
+    // the original leaf page. If the dir page is full it is split, there
+    // could be up to two new entries if the leaf page split three ways.
     pub fn handle_tree_leaf_store(
         db_config: &db_config::DbConfig,
-        mut dir_page: DirPage,
+        dir_page: DirPage,
         entries: Vec<TreeDirEntry>,
     ) -> Vec<DirPageRef> {
         assert!(!entries.is_empty(), "entries was empty");
-        let mut tree_dir_pages: Vec<DirPageRef> = Vec::new();
-
-        if dir_page.store_child_pages(&entries) {
-            tree_dir_pages.push(DirPageRef {
-                page: dir_page,
-                left_key: None,
-            });
-            return tree_dir_pages;
-        }
-
-        let (mut left_dir, mut right_dir, new_left_key) = dir_page.split_page(db_config, 0);
-
-        if entries.first().unwrap().get_key() < new_left_key.as_slice() {
-            // Use original page to add entries. Note if the first is less than the left key in the
-            // new page then all entries will be.
-            assert!(left_dir.store_child_pages(&entries));
-        } else {
-            // Use the original page.
-            assert!(right_dir.store_child_pages(&entries));
-        }
-        tree_dir_pages.push(DirPageRef {
-            page: left_dir,
-            left_key: None,
-        });
-        tree_dir_pages.push(DirPageRef {
-            page: right_dir,
-            left_key: Some(new_left_key),
-        });
-
-        tree_dir_pages
+        TreeDirHandler::store_entries(db_config, dir_page, &entries, None)
     }
 
     pub fn map_dir_pages(
@@ -66,41 +42,64 @@ impl TreeDirHandler {
         }
     }
 
+    // As handle_tree_leaf_store but the entries are for a dir page that
+    // has been updated, and maybe split, lower down in the tree.
     pub fn handle_tree_dir_store(
         db_config: &db_config::DbConfig,
-        mut parent_dir_page: DirPage,
+        parent_dir_page: DirPage,
         entries: Vec<TreeDirEntry>,
     ) -> Vec<DirPageRef> {
         assert!(!entries.is_empty(), "dir entries was empty");
-        let mut tree_dir_pages: Vec<DirPageRef> = Vec::new();
+        TreeDirHandler::store_entries(db_config, parent_dir_page, &entries, None)
+    }
 
-        if parent_dir_page.store_child_pages(&entries) {
-            tree_dir_pages.push(DirPageRef {
-                page: parent_dir_page,
-                left_key: None,
-            });
-            return tree_dir_pages;
+    // Store the entries in the dir page, splitting it if they do not fit.
+    // All the entries go to the same side of a split as they are the
+    // pages that replaced a single child page. If that side is still too
+    // full then it is split again, so a batch can leave more than two
+    // dir pages. The dir pages are returned in key order.
+    fn store_entries(
+        db_config: &db_config::DbConfig,
+        mut dir_page: DirPage,
+        entries: &[TreeDirEntry],
+        left_key: Option<Vec<u8>>,
+    ) -> Vec<DirPageRef> {
+        if dir_page.store_child_pages(entries) {
+            return vec![DirPageRef {
+                page: dir_page,
+                left_key,
+            }];
         }
 
-        // Need to split the parent dir page.
-        let (mut left_dir, mut right_dir, new_left_key) = parent_dir_page.split_page(db_config, 0);
-
-        if entries.first().unwrap().get_key() < new_left_key.as_slice() {
-            // Add entries to original page.
-            assert!(left_dir.store_child_pages(&entries));
+        let (left_dir, right_dir, new_left_key) = dir_page.split_page(db_config, 0);
+
+        if db_config
+            .key_order
+            .compare(entries.first().unwrap().get_key(), &new_left_key)
+            == Ordering::Less
+        {
+            // Add entries to the left page. Note if the first is less than the
+            // left key in the new page then all entries will be.
+            let mut tree_dir_pages =
+                TreeDirHandler::store_entries(db_config, left_dir, entries, left_key);
+            tree_dir_pages.push(DirPageRef {
+                page: right_dir,
+                left_key: Some(new_left_key),
+            });
+            tree_dir_pages
         } else {
-            assert!(right_dir.store_child_pages(&entries));
+            let mut tree_dir_pages = vec![DirPageRef {
+                page: left_dir,
+                left_key,
+            }];
+            tree_dir_pages.extend(TreeDirHandler::store_entries(
+                db_config,
+                right_dir,
+                entries,
+                Some(new_left_key),
+            ));
+            tree_dir_pages
         }
-        tree_dir_pages.push(DirPageRef {
-            page: left_dir,
-            left_key: None,
-        });
-        tree_dir_pages.push(DirPageRef {
-            page: right_dir,
-            left_key: Some(new_left_key),
-        });
-
-        tree_dir_pages
     }
 }
 
@@ -172,4 +171,67 @@ mod tests {
         tree_dir_page = new_pages.pop().unwrap().page;
         assert_eq!(tree_dir_page.get_page_to_left(), PageNo::from_u64(79));
     }
+
+    #[test]
+    fn test_batch_splits_dir_page_twice() {
+        let page_config = DbConfig::builder()
+            .block_size(512)
+            .block_sanity_size(0)
+            .build();
+        // Fill a dir page with ten children with 30 byte keys.
+        let mut dir_page = DirPage::create_new(&page_config, PageNo::from_u64(0), 0);
+        let mut children: Vec<(Vec<u8>, u64)> = vec![(vec![b'a'; 30], 100)];
+        assert!(dir_page.store_child_pages(&[TreeDirEntry::new(vec![b'a'; 30], 100)]));
+        for i in 1..10u8 {
+            let (last_key, last_page_no) = children.last().unwrap().clone();
+            let key = vec![b'a' + i; 30];
+            assert!(dir_page.store_child_pages(&[
+                TreeDirEntry::new(last_key, last_page_no),
+                TreeDirEntry::new(key.clone(), 100 + i as u64),
+            ]));
+            children.push((key, 100 + i as u64));
+        }
+
+        // The last child splits three ways with large keys. The new keys
+        // do not fit in the right half after a split so it splits again.
+        let (last_key, _) = children.pop().unwrap();
+        let new_key1 = [last_key.clone(), vec![b'a'; 120]].concat();
+        let new_key2 = [last_key.clone(), vec![b'b'; 120]].concat();
+        let entries = vec![
+            TreeDirEntry::new(last_key.clone(), 200),
+            TreeDirEntry::new(new_key1.clone(), 201),
+            TreeDirEntry::new(new_key2.clone(), 202),
+        ];
+        children.push((last_key, 200));
+        children.push((new_key1, 201));
+        children.push((new_key2, 202));
+        let dir_pages = TreeDirHandler::handle_tree_leaf_store(&page_config, dir_page, entries);
+        assert_eq!(dir_pages.len(), 3);
+
+        // Each child is found from the dir page with the largest left key
+        // not greater than the child key.
+        let left_keys: Vec<Option<Vec<u8>>> = dir_pages
+            .iter()
+            .enumerate()
+            .map(|(i, dir_page_ref)| {
+                if i == 0 {
+                    None
+                } else {
+                    dir_page_ref.left_key.clone()
+                }
+            })
+            .collect();
+        assert!(left_keys.iter().skip(1).all(|left_key| left_key.is_some()));
+        assert!(left_keys.is_sorted());
+        for (key, page_no) in children {
+            let index = left_keys
+                .iter()
+                .rposition(|left_key| left_key.as_ref().is_none_or(|left_key| *left_key <= key))
+                .unwrap();
+            assert_eq!(
+                dir_pages[index].page.get_next(&key),
+                PageNo::from_u64(page_no)
+            );
+        }
+    }
 }
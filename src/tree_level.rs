@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+// The pages at one level of a tree, see Db::tree_histogram. The entries
+// of a dir page are its child pages, its fanout, the entries of a leaf
+// page are its key/values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeLevel {
+    pub page_count: u64,
+    pub entry_count: u64,
+    // The number of pages with each number of entries.
+    pub histogram: BTreeMap<u64, u64>,
+}
+
+impl TreeLevel {
+    pub fn add_page(&mut self, entries: u64) {
+        self.page_count += 1;
+        self.entry_count += entries;
+        *self.histogram.entry(entries).or_insert(0) += 1;
+    }
+
+    pub fn get_min_entries(&self) -> u64 {
+        self.histogram.keys().next().copied().unwrap_or(0)
+    }
+
+    pub fn get_max_entries(&self) -> u64 {
+        self.histogram.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub fn get_average_entries(&self) -> f64 {
+        if self.page_count == 0 {
+            return 0.0;
+        }
+        self.entry_count as f64 / self.page_count as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_level() {
+        let mut tree_level = TreeLevel::default();
+        assert_eq!(tree_level.get_min_entries(), 0);
+        assert_eq!(tree_level.get_average_entries(), 0.0);
+        for entries in [10, 4, 10, 0] {
+            tree_level.add_page(entries);
+        }
+        assert_eq!(tree_level.page_count, 4);
+        assert_eq!(tree_level.entry_count, 24);
+        assert_eq!(tree_level.histogram.get(&10), Some(&2));
+        assert_eq!(tree_level.get_min_entries(), 0);
+        assert_eq!(tree_level.get_max_entries(), 10);
+        assert_eq!(tree_level.get_average_entries(), 6.0);
+    }
+}
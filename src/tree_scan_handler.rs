@@ -0,0 +1,165 @@
+use crate::PageNo;
+use crate::dir_page::DirPage;
+use crate::leaf_page::LeafPage;
+use crate::page::PageType;
+use crate::page_cache::PageCache;
+use crate::tuple::{Tuple, TupleTrait};
+use std::cmp::Ordering;
+use std::ops::Bound;
+
+pub struct TreeScanHandler {}
+
+// Walk a tree in key order returning the tuples with keys in a range.
+// Only the pages that can hold keys in the range are read - a dir page
+// routes a key to the child with the largest key less than or equal to
+// the key, so a child can only hold keys from its key up to the key
+// of the next child.
+//
+// The tuples are as stored in the tree, so the caller needs to resolve
+// overflow and compressed tuples. Oversized keys are stored as a short
+// key (see TupleProcessor) so they are compared using the short key.
+//
+// Keys are compared in the key order of the DB. An empty start is from
+// the first key of the tree, in byte order the empty key is the
+// smallest anyway but a comparator can put it anywhere.
+impl TreeScanHandler {
+    // Get the tuples with start <= key < end, or all the keys from start
+    // if there is no end.
+    pub fn get_tuples_in_range(
+        start: &[u8],
+        end: Option<&[u8]>,
+        root_page_no: PageNo,
+        page_cache: &mut PageCache,
+    ) -> Vec<Tuple> {
+        TreeScanHandler::get_tuples_in_range_limit(
+            TreeScanHandler::start_bound(start),
+            end,
+            root_page_no,
+            usize::MAX,
+            page_cache,
+        )
+    }
+
+    // The bound for a start key, an empty start is unbounded.
+    pub fn start_bound(start: &[u8]) -> Bound<&[u8]> {
+        if start.is_empty() {
+            return Bound::Unbounded;
+        }
+        Bound::Included(start)
+    }
+
+    // As get_tuples_in_range but the walk stops once limit tuples have
+    // been found, the rest of the leaf page they were found in are
+    // returned too so there can be more than limit. Fewer than limit
+    // means the range has been read to its end. The start is a bound so
+    // the next call can carry on after the last key found.
+    pub fn get_tuples_in_range_limit(
+        start: Bound<&[u8]>,
+        end: Option<&[u8]>,
+        root_page_no: PageNo,
+        limit: usize,
+        page_cache: &mut PageCache,
+    ) -> Vec<Tuple> {
+        let mut tuples = Vec::new();
+        TreeScanHandler::scan_page(
+            start,
+            end,
+            root_page_no,
+            false,
+            limit,
+            page_cache,
+            &mut tuples,
+        );
+        tuples
+    }
+
+    // As get_tuples_in_range but the tuples are largest key first. The
+    // tree is walked right to left, the children of a dir page in
+    // reverse and the tuples of a leaf page in reverse, so the leaf
+    // pages do not need links to the page on their left.
+    pub fn get_tuples_in_range_rev(
+        start: &[u8],
+        end: Option<&[u8]>,
+        root_page_no: PageNo,
+        page_cache: &mut PageCache,
+    ) -> Vec<Tuple> {
+        let mut tuples = Vec::new();
+        TreeScanHandler::scan_page(
+            TreeScanHandler::start_bound(start),
+            end,
+            root_page_no,
+            true,
+            usize::MAX,
+            page_cache,
+            &mut tuples,
+        );
+        tuples
+    }
+
+    fn scan_page(
+        start: Bound<&[u8]>,
+        end: Option<&[u8]>,
+        page_no: PageNo,
+        reverse: bool,
+        limit: usize,
+        page_cache: &mut PageCache,
+        tuples: &mut Vec<Tuple>,
+    ) {
+        let key_order = page_cache.get_key_order();
+        let page = page_cache.get_page(page_no);
+        if page.get_type() == PageType::LeafPage {
+            let leaf_page = LeafPage::from_page(page, key_order);
+            let in_range = leaf_page.get_all_tuples().into_iter().filter(|tuple| {
+                let key = tuple.get_key();
+                let after_start = match start {
+                    Bound::Included(start) => key_order.compare(key, start) != Ordering::Less,
+                    Bound::Excluded(start) => key_order.compare(key, start) == Ordering::Greater,
+                    Bound::Unbounded => true,
+                };
+                after_start && end.is_none_or(|end| key_order.compare(key, end) == Ordering::Less)
+            });
+            if reverse {
+                tuples.extend(in_range.rev());
+            } else {
+                tuples.extend(in_range);
+            }
+            return;
+        }
+
+        let child_pages = DirPage::from_page(page, key_order).get_child_pages_with_keys();
+        let mut child_page_nos: Vec<PageNo> = Vec::with_capacity(child_pages.len());
+        for (index, (child_key, child_page_no)) in child_pages.iter().enumerate() {
+            // Keys in this child are at least child_key.
+            if let (Some(child_key), Some(end)) = (child_key, end)
+                && key_order.compare(child_key, end) != Ordering::Less
+            {
+                break;
+            }
+            // Keys in this child are less than the key of the next child.
+            if let Some((Some(next_key), _)) = child_pages.get(index + 1)
+                && let Bound::Included(start) | Bound::Excluded(start) = start
+                && key_order.compare(next_key, start) != Ordering::Greater
+            {
+                continue;
+            }
+            child_page_nos.push(*child_page_no);
+        }
+        if reverse {
+            child_page_nos.reverse();
+        }
+        for child_page_no in child_page_nos {
+            if tuples.len() >= limit {
+                break;
+            }
+            TreeScanHandler::scan_page(
+                start,
+                end,
+                child_page_no,
+                reverse,
+                limit,
+                page_cache,
+                tuples,
+            );
+        }
+    }
+}
@@ -24,9 +24,11 @@ use crate::version_holder::VersionHolder;
 // 4. Key and value overflow - store SHA256 of key and page number
 //    of overflow page as value.
 //
-// If the key overflows we store the first 223 bytes of the key followed
-// by the 32 bytes of the SHA256 of the key to allow key length
-// to be stored in u8. This mean lexical sorting works up to 223 bytes.
+// Keys up to TupleProcessor::max_inline_key_size are stored in the
+// data page as they are, the key length is a u16. If the key is
+// larger than that we store the first 223 bytes of the key followed
+// by the 32 bytes of the SHA256 of the key. This mean lexical sorting
+// of those keys only works up to 223 bytes.
 //
 // When we come to store a tuple we know which Overflow type it is.
 // When we want to look up a tuple given the key we know whether
@@ -47,6 +49,18 @@ pub enum Overflow {
     KeyValueCompressed = 5,
 }
 
+impl Overflow {
+    // True if the tuple in the tree holds a page number for the overflow
+    // pages rather than the value. ValueCompressed tuples hold the
+    // compressed value in the tree so have no overflow pages.
+    pub fn has_overflow_pages(&self) -> bool {
+        matches!(
+            self,
+            Overflow::ValueOverflow | Overflow::KeyOverflow | Overflow::KeyValueOverflow
+        )
+    }
+}
+
 impl TryFrom<u8> for Overflow {
     type Error = ();
 
@@ -73,35 +87,40 @@ pub trait TupleTrait {
     fn get_overflow(&self) -> Overflow;
 }
 
+// A tuple serialized as
+//   key_len - LEN_SIZE bytes
+//   value_len - LEN_SIZE bytes
+//   key of size key_len
+//   version - 8 bytes, 7 bytes for the version 1 byte for overflow type
+//   value of size value_len
+// Tuples in leaf pages use 2 byte lengths (Tuple), tuples in overflow
+// pages 8 byte lengths (OverflowTuple). The layout is otherwise the same
+// so both are read through the one TupleTrait implementation.
 #[derive(Clone)]
-pub struct Tuple {
-    // key_len - 1 byte
-    // value_len - 2 bytes
-    // key of size key_len
-    // version - 8 bytes, 7 bytes for the version 1 byte for overflow type
-    // value of size value_len
+pub struct SerializedTuple<const LEN_SIZE: usize> {
     serialized: Vec<u8>,
 }
 
-impl TupleTrait for Tuple {
+pub type Tuple = SerializedTuple<2>;
+
+impl<const LEN_SIZE: usize> TupleTrait for SerializedTuple<LEN_SIZE> {
     fn get_key(&self) -> &[u8] {
-        let key_len = self.serialized[0] as usize;
-        &self.serialized[3..3 + key_len]
+        let key_len = self.key_len();
+        &self.serialized[Self::LENGTHS_SIZE..Self::LENGTHS_SIZE + key_len]
     }
 
     fn get_value(&self) -> &[u8] {
-        let key_len = self.serialized[0] as usize;
-        &self.serialized[3 + key_len + 8..]
+        let key_len = self.key_len();
+        &self.serialized[Self::LENGTHS_SIZE + key_len + 8..]
     }
 
     fn get_version_value(&self) -> &[u8] {
-        let key_len = self.serialized[0] as usize;
-        &self.serialized[3 + key_len..]
+        let key_len = self.key_len();
+        &self.serialized[Self::LENGTHS_SIZE + key_len..]
     }
 
     fn get_version(&self) -> u64 {
-        let key_len = self.serialized[0] as usize;
-        VersionHolder::from_bytes(&self.serialized[3 + key_len..3 + key_len + 8]).get_version()
+        self.get_version_holder().get_version()
     }
 
     fn get_serialized(&self) -> &[u8] {
@@ -113,59 +132,81 @@ impl TupleTrait for Tuple {
     }
 
     fn get_overflow(&self) -> Overflow {
-        let key_len = self.serialized[0] as usize;
-        Overflow::try_from(
-            VersionHolder::from_bytes(&self.serialized[3 + key_len..3 + key_len + 8]).get_flags(),
-        )
-        .unwrap()
+        Overflow::try_from(self.get_version_holder().get_flags()).unwrap()
     }
 }
 
-impl Tuple {
-    pub fn new(key: &[u8], value: &[u8], version: u64) -> Self {
-        assert!(
-            key.len() <= u8::MAX as usize,
-            "Key size larger than u8 can hold."
-        );
-        assert!(
-            value.len() <= u16::MAX as usize,
-            "Value size larger than u16 can hold."
-        );
-        let mut serialized: Vec<u8> = Vec::with_capacity(1 + key.len() + 2 + value.len() + 8);
-        serialized.push(key.len() as u8);
-        serialized.extend_from_slice(&(value.len() as u16).to_le_bytes());
-        let version_holder = VersionHolder::new(0, version);
+impl<const LEN_SIZE: usize> SerializedTuple<LEN_SIZE> {
+    // The key and value lengths at the start of the serialized tuple.
+    pub const LENGTHS_SIZE: usize = 2 * LEN_SIZE;
+
+    // The caller checks the key and value lengths fit in LEN_SIZE bytes.
+    pub fn serialize(key: &[u8], value: &[u8], version: u64, overflow: Overflow) -> Self {
+        let mut serialized = Vec::with_capacity(Self::LENGTHS_SIZE + key.len() + 8 + value.len());
+        serialized.extend_from_slice(&(key.len() as u64).to_le_bytes()[..LEN_SIZE]);
+        serialized.extend_from_slice(&(value.len() as u64).to_le_bytes()[..LEN_SIZE]);
+        let version_holder = VersionHolder::new(overflow as u8, version);
         serialized.extend_from_slice(key);
         serialized.extend_from_slice(&version_holder.get_bytes()[0..8]);
         serialized.extend_from_slice(value);
-        Tuple { serialized }
+        SerializedTuple { serialized }
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        SerializedTuple { serialized: bytes }
+    }
+
+    // The size of a serialized tuple from its first LENGTHS_SIZE bytes,
+    // so the overflow chain holding it can be checked as it is read.
+    // None if there are too few bytes or the lengths are larger than a
+    // key or value can be.
+    pub fn get_serialized_size(lengths: &[u8]) -> Option<usize> {
+        if lengths.len() < Self::LENGTHS_SIZE {
+            return None;
+        }
+        let key_len = Self::read_len(lengths, 0);
+        let value_len = Self::read_len(lengths, LEN_SIZE);
+        if key_len > u32::MAX as u64 || value_len > u32::MAX as u64 {
+            return None;
+        }
+        Some(Self::LENGTHS_SIZE + key_len as usize + 8 + value_len as usize)
+    }
+
+    pub fn equals(&self, other: &Self) -> bool {
+        self.serialized == other.serialized
+    }
+
+    fn read_len(bytes: &[u8], offset: usize) -> u64 {
+        let mut len = [0u8; 8];
+        len[..LEN_SIZE].copy_from_slice(&bytes[offset..offset + LEN_SIZE]);
+        u64::from_le_bytes(len)
+    }
+
+    fn key_len(&self) -> usize {
+        Self::read_len(&self.serialized, 0) as usize
+    }
+
+    fn get_version_holder(&self) -> VersionHolder {
+        let start = Self::LENGTHS_SIZE + self.key_len();
+        VersionHolder::from_bytes(&self.serialized[start..start + 8])
+    }
+}
+
+impl Tuple {
+    pub fn new(key: &[u8], value: &[u8], version: u64) -> Self {
+        Tuple::new_with_overflow(key, value, version, Overflow::None)
     }
 
     pub fn new_with_overflow(key: &[u8], value: &[u8], version: u64, overflow: Overflow) -> Self {
         assert!(
-            key.len() <= u8::MAX as usize,
-            "Key size larger than u8 can hold."
+            key.len() <= u16::MAX as usize,
+            "Key size larger than u16 can hold."
         );
         assert!(
             value.len() <= u16::MAX as usize,
             "Value size larger than u16 can hold."
         );
-        let mut serialized = Vec::with_capacity(1 + key.len() + 2 + value.len() + 8);
-        serialized.push(key.len() as u8);
-        serialized.extend_from_slice(&(value.len() as u16).to_le_bytes());
-        let version_holder = VersionHolder::new(overflow as u8, version);
-        serialized.extend_from_slice(key);
-        serialized.extend_from_slice(&version_holder.get_bytes()[0..8]);
-        serialized.extend_from_slice(value);
-        Tuple { serialized }
-    }
-
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        Tuple { serialized: bytes }
-    }
-
-    pub fn equals(&self, other: &Tuple) -> bool {
-        self.serialized == other.serialized
+        Tuple::serialize(key, value, version, overflow)
     }
 }
 
@@ -200,7 +241,7 @@ mod tests {
         assert_eq!(deserialized.get_version(), version);
         assert_eq!(
             deserialized.get_byte_size(),
-            key.len() + value.len() + 1 + 2 + 8
+            key.len() + value.len() + 2 + 2 + 8
         );
     }
 
@@ -212,9 +253,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Key size larger than u8 can hold.")]
+    #[should_panic(expected = "Key size larger than u16 can hold.")]
     fn test_tuple_new_large_key() {
-        let key = vec![0u8; 256];
+        let key = vec![0u8; 65536];
         Tuple::new(&key, b"value", 1);
     }
 
@@ -226,9 +267,9 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Key size larger than u8 can hold.")]
+    #[should_panic(expected = "Key size larger than u16 can hold.")]
     fn test_tuple_new_with_overflow_large_key() {
-        let key = vec![0u8; 256];
+        let key = vec![0u8; 65536];
         Tuple::new_with_overflow(&key, b"value", 1, Overflow::ValueOverflow);
     }
 
@@ -239,6 +280,18 @@ mod tests {
         Tuple::new_with_overflow(b"key", &value, 1, Overflow::ValueOverflow);
     }
 
+    #[test]
+    fn test_tuple_serialized_size() {
+        let tuple = Tuple::new(b"key", &[1u8; 5000], 1);
+        let serialized = tuple.get_serialized();
+        assert_eq!(Tuple::LENGTHS_SIZE, 4);
+        assert_eq!(
+            Tuple::get_serialized_size(&serialized[0..4]),
+            Some(serialized.len())
+        );
+        assert_eq!(Tuple::get_serialized_size(&serialized[0..3]), None);
+    }
+
     #[test]
     fn test_tuple_get_overflow() {
         let tuple = Tuple::new_with_overflow(b"key", b"value", 1, Overflow::ValueOverflow);
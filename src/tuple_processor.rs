@@ -1,6 +1,8 @@
 use crate::compressor::Compressor;
 use crate::compressor::CompressorType;
 use crate::db_config::DbConfig;
+use crate::digby_error::DigbyError;
+use crate::leaf_page::LeafPage;
 use crate::{
     FreePageTracker, OverflowPageHandler, OverflowTuple, PageCache,
     tuple::{Overflow, Tuple},
@@ -9,13 +11,39 @@ use sha2::{Digest, Sha256};
 
 pub struct TupleProcessor {}
 
-// If key is over 255 bytes then it is stored as [ first 223 bytes of key | SHA256 of Key],
-// to allow the key length to be stored as u8,
+// Keys up to max_inline_key_size are stored inline in the leaf page with
+// a u16 key length. If the key is larger then it is stored as
+// [ first 223 bytes of key | SHA256 of Key], a 255 byte short key.
 // The tuple will be stored in an Overflow page with the full key.
-// If keys are larger 255 bytes then lexical sorting will break down - another option
+// For those keys lexical sorting will break down - another option
 // would be just to store the SHA256 as the comppressed key.
 impl TupleProcessor {
-    const MAX_VALUE_SIZE: usize = 1024;
+    // An oversized key is stored as this many bytes of the key followed
+    // by the SHA256 of the key.
+    pub const SHORT_KEY_PREFIX_SIZE: usize = u8::MAX as usize - 32;
+    // The largest key and value that can be stored. The u16 lengths in
+    // a Tuple only hold keys and values stored inline, which are limited
+    // by the page size, larger ones go to overflow pages which use u64
+    // lengths. The real limit is the compressors, LZ4 stores the length
+    // of what it compresses as a u32.
+    pub const MAX_KEY_SIZE: usize = u32::MAX as usize - 1;
+    pub const MAX_VALUE_SIZE: usize = u32::MAX as usize - 1;
+
+    pub fn check_key_size(key_len: usize) -> Result<(), DigbyError> {
+        if key_len > TupleProcessor::MAX_KEY_SIZE {
+            return Err(DigbyError::KeyTooLarge(TupleProcessor::MAX_KEY_SIZE));
+        }
+        Ok(())
+    }
+
+    // Check a key and value can be stored before anything is written.
+    pub fn check_sizes(key_len: usize, value_len: usize) -> Result<(), DigbyError> {
+        TupleProcessor::check_key_size(key_len)?;
+        if value_len > TupleProcessor::MAX_VALUE_SIZE {
+            return Err(DigbyError::ValueTooLarge(TupleProcessor::MAX_VALUE_SIZE));
+        }
+        Ok(())
+    }
 
     pub fn generate_tuple(
         key: &[u8],
@@ -24,53 +52,63 @@ impl TupleProcessor {
         free_page_tracker: &mut FreePageTracker,
         version: u64,
         compressor: &Compressor,
-        _db_config: &DbConfig,
+        db_config: &DbConfig,
     ) -> Tuple {
-        if !TupleProcessor::is_oversized_key(key) && value.len() < TupleProcessor::MAX_VALUE_SIZE {
+        let oversized_key = TupleProcessor::is_oversized_key(key, db_config);
+        if !oversized_key && TupleProcessor::is_inline(key, value, db_config) {
             return Tuple::new(key, value, version);
         }
-        assert!(key.len() < u32::MAX as usize, "key is too large");
-        assert!(value.len() < u32::MAX as usize, "value is too large");
+        // The Db checks the sizes first, this is a backstop.
+        TupleProcessor::check_sizes(key.len(), value.len())
+            .unwrap_or_else(|error| panic!("{}", error));
 
-        let mut compressed_value: Vec<u8> = Vec::new();
+        let mut compressed_value: Option<Vec<u8>> = None;
         if compressor.compressor_type != CompressorType::None {
-            compressed_value = compressor.compress(value);
+            let value_compressed = compressor.compress(value);
             // We can store it with the value compressed.
-            if !TupleProcessor::is_oversized_key(key)
-                && compressed_value.len() < TupleProcessor::MAX_VALUE_SIZE
-            {
+            if !oversized_key && TupleProcessor::is_inline(key, &value_compressed, db_config) {
                 return Tuple::new_with_overflow(
                     key,
-                    &compressed_value,
+                    &value_compressed,
                     version,
                     Overflow::ValueCompressed,
                 );
             }
+            // Incompressible data, such as a value that is already
+            // compressed, comes out larger and is stored as it is.
+            if value_compressed.len() < value.len() {
+                compressed_value = Some(value_compressed);
+            }
         }
 
         let overflow_type: Overflow;
-        if TupleProcessor::is_oversized_key(key) && value.len() > TupleProcessor::MAX_VALUE_SIZE {
+        if oversized_key && !TupleProcessor::is_inline(&[], value, db_config) {
             overflow_type = Overflow::KeyValueOverflow;
-        } else if key.len() > u8::MAX as usize {
+        } else if oversized_key {
             overflow_type = Overflow::KeyOverflow;
         } else {
             overflow_type = Overflow::ValueOverflow
         }
 
-        let overflow_tuple: OverflowTuple = if compressor.compressor_type != CompressorType::None {
-            let overflow_key = compressor.compress(key);
-            let overflow_value = compressed_value;
-            OverflowTuple::new(
-                &overflow_key,
-                &overflow_value,
-                version,
-                Overflow::KeyValueCompressed,
-            )
-        } else {
-            // Overflow::None here as this is the overflow tuple - it is either None
-            // or compressesed. The overflow_type is used in the tuple we store in
-            // the tree
-            OverflowTuple::new(key, value, version, Overflow::None)
+        // The overflow_type is used in the tuple we store in the tree, the
+        // overflow tuple itself is None or compressed. There is no flag for
+        // a compressed key with a raw value, so the key is only compressed
+        // along with the value and only if that makes it smaller.
+        let overflow_tuple: OverflowTuple = match compressed_value {
+            Some(overflow_value) => {
+                let overflow_key = compressor.compress(key);
+                if overflow_key.len() < key.len() {
+                    OverflowTuple::new(
+                        &overflow_key,
+                        &overflow_value,
+                        version,
+                        Overflow::KeyValueCompressed,
+                    )
+                } else {
+                    OverflowTuple::new(key, &overflow_value, version, Overflow::ValueCompressed)
+                }
+            }
+            None => OverflowTuple::new(key, value, version, Overflow::None),
         };
 
         // Store the oversize tuple in overflow pages and get back the page_no
@@ -84,7 +122,7 @@ impl TupleProcessor {
         // Need to store a reference to the tuple in the tree, so need to create
         // a tuple that is not oversized. Reuse the key, or a shorted version of
         // the tree and the overflow page number.
-        if TupleProcessor::is_oversized_key(key) {
+        if oversized_key {
             let new_key = TupleProcessor::generate_short_key(key);
             return Tuple::new_with_overflow(
                 &new_key,
@@ -97,18 +135,53 @@ impl TupleProcessor {
         Tuple::new_with_overflow(key, &overflow_page_no.get_bytes(), version, overflow_type)
     }
 
-    pub fn is_oversized_key(key: &[u8]) -> bool {
-        if key.len() > u8::MAX as usize {
-            return true;
+    // The largest key stored inline in a leaf page. The key length is a
+    // u16 but a dir page still needs room for two fence keys and a few
+    // separator keys, so limit keys to an eighth of the smaller of the
+    // leaf and dir pages. Never less than 255, keys that size were
+    // always stored inline.
+    pub fn max_inline_key_size(db_config: &DbConfig) -> usize {
+        let blk_cnt = db_config
+            .get_leaf_page_blk_cnt()
+            .min(db_config.get_dir_page_blk_cnt()) as usize;
+        let page_size = db_config.block_size * blk_cnt - db_config.block_sanity_size;
+        (page_size / 8).max(u8::MAX as usize)
+    }
+
+    // Values this size or larger are stored in overflow pages, or
+    // compressed if that brings them under it. Unless the DB was created
+    // with a lower threshold it is one more than the largest value a
+    // leaf page can hold, so a value stays inline whenever it fits, see
+    // LeafPage::can_fit. Only writes use it, a value is read back from
+    // however the tuple says it is stored.
+    pub fn overflow_threshold(db_config: &DbConfig) -> usize {
+        if db_config.overflow_threshold != 0 {
+            return db_config.overflow_threshold;
         }
-        false
+        TupleProcessor::max_overflow_threshold(db_config)
+    }
+
+    // The largest threshold a DB can be created with, one more than the
+    // largest value that fits in a leaf page with an empty key.
+    pub fn max_overflow_threshold(db_config: &DbConfig) -> usize {
+        LeafPage::max_value_size(db_config, 0) + 1
+    }
+
+    // True if the value is stored in the leaf page with the key.
+    fn is_inline(key: &[u8], value: &[u8], db_config: &DbConfig) -> bool {
+        value.len() < TupleProcessor::overflow_threshold(db_config)
+            && LeafPage::can_fit(db_config, key.len(), value.len())
+    }
+
+    pub fn is_oversized_key(key: &[u8], db_config: &DbConfig) -> bool {
+        key.len() > TupleProcessor::max_inline_key_size(db_config)
     }
 
     pub fn generate_short_key(key: &[u8]) -> Vec<u8> {
         assert!(key.len() > u8::MAX as usize);
         let key_hash = Sha256::digest(key);
         let mut new_key: Vec<u8> = Vec::with_capacity(u8::MAX as usize);
-        new_key.extend_from_slice(&key[0..u8::MAX as usize - 32]);
+        new_key.extend_from_slice(&key[0..TupleProcessor::SHORT_KEY_PREFIX_SIZE]);
         new_key.extend_from_slice(&key_hash);
         assert!(new_key.len() == u8::MAX as usize);
         new_key
@@ -123,6 +196,7 @@ mod tests {
     use crate::page::PageTrait;
     use crate::page_container_layer::PageContainerLayer;
     use crate::tuple::TupleTrait;
+    use rand::RngCore;
     use tempfile::NamedTempFile;
 
     use crate::db_config::DbConfig;
@@ -136,13 +210,59 @@ mod tests {
         .dir_page_blk_exp(0)
         .build();
 
+    #[test]
+    fn test_tuple_processor_check_sizes() {
+        assert!(TupleProcessor::check_sizes(0, 0).is_ok());
+        assert!(
+            TupleProcessor::check_sizes(
+                TupleProcessor::MAX_KEY_SIZE,
+                TupleProcessor::MAX_VALUE_SIZE
+            )
+            .is_ok()
+        );
+        assert!(matches!(
+            TupleProcessor::check_sizes(u32::MAX as usize, 0),
+            Err(DigbyError::KeyTooLarge(max)) if max == TupleProcessor::MAX_KEY_SIZE
+        ));
+        assert!(matches!(
+            TupleProcessor::check_sizes(0, u32::MAX as usize),
+            Err(DigbyError::ValueTooLarge(max)) if max == TupleProcessor::MAX_VALUE_SIZE
+        ));
+        assert!(TupleProcessor::check_key_size(u32::MAX as usize).is_err());
+    }
+
     #[test]
     fn test_tuple_processor_oversized_key() {
-        let small_key = vec![0u8; 255];
-        assert!(!TupleProcessor::is_oversized_key(&small_key));
+        assert_eq!(TupleProcessor::max_inline_key_size(&DB_CONFIG), 511);
+        let small_key = vec![0u8; 511];
+        assert!(!TupleProcessor::is_oversized_key(&small_key, &DB_CONFIG));
 
-        let large_key = vec![0u8; 256];
-        assert!(TupleProcessor::is_oversized_key(&large_key));
+        let large_key = vec![0u8; 512];
+        assert!(TupleProcessor::is_oversized_key(&large_key, &DB_CONFIG));
+    }
+
+    #[test]
+    fn test_tuple_processor_overflow_threshold() {
+        // 4092 less the header, one slot and the version.
+        assert_eq!(TupleProcessor::overflow_threshold(&DB_CONFIG), 4049);
+        assert_eq!(TupleProcessor::max_overflow_threshold(&DB_CONFIG), 4049);
+        let small = DbConfig::builder()
+            .block_size(128)
+            .page_size(124)
+            .block_sanity_size(4)
+            .build();
+        assert_eq!(TupleProcessor::overflow_threshold(&small), 81);
+        let large = DbConfig::builder()
+            .block_size(65536)
+            .page_size(65532)
+            .block_sanity_size(4)
+            .leaf_page_blk_exp(1)
+            .build();
+        // Limited by the u16 length of the version and value.
+        assert_eq!(TupleProcessor::overflow_threshold(&large), 65528);
+        assert_eq!(TupleProcessor::max_overflow_threshold(&large), 65528);
+        let set = DbConfig::builder().overflow_threshold(3000).build();
+        assert_eq!(TupleProcessor::overflow_threshold(&set), 3000);
     }
 
     #[test]
@@ -164,7 +284,8 @@ mod tests {
             .truncate(true)
             .open(temp_file.path())
             .unwrap();
-        let file_layer = FileLayer::new(file, DB_CONFIG.block_size);
+        let file_layer =
+            FileLayer::new(file, DB_CONFIG.block_size).expect("Failed to create file layer");
         let block_layer = PageContainerLayer::new(file_layer, DB_CONFIG);
         let mut page_cache = PageCache::new(block_layer);
         let version = 0;
@@ -195,8 +316,19 @@ mod tests {
         );
         assert_eq!(tuple.get_overflow(), Overflow::None);
 
-        let compressor_lz4 = Compressor::new(CompressorType::LZ4);
-        let compressible_value = vec![2u8; 2000];
+        // Without LZ4 built in the value is too big and overflows instead.
+        let (compressor_lz4, compressed_overflow) = if cfg!(feature = "lz4") {
+            (
+                Compressor::new(CompressorType::LZ4),
+                Overflow::ValueCompressed,
+            )
+        } else {
+            (
+                Compressor::new(CompressorType::None),
+                Overflow::ValueOverflow,
+            )
+        };
+        let compressible_value = vec![2u8; 5000];
         let tuple_compressed = TupleProcessor::generate_tuple(
             &small_key,
             &compressible_value,
@@ -206,9 +338,10 @@ mod tests {
             &compressor_lz4,
             &DB_CONFIG,
         );
-        assert_eq!(tuple_compressed.get_overflow(), Overflow::ValueCompressed);
+        assert_eq!(tuple_compressed.get_overflow(), compressed_overflow);
 
-        let large_value = vec![3u8; 2000];
+        // Too big for a leaf page.
+        let large_value = vec![3u8; 5000];
         let tuple_large_val = TupleProcessor::generate_tuple(
             &small_key,
             &large_value,
@@ -220,7 +353,21 @@ mod tests {
         );
         assert_eq!(tuple_large_val.get_overflow(), Overflow::ValueOverflow);
 
-        let large_key = vec![4u8; 300];
+        // A medium key is stored inline.
+        let medium_key = vec![4u8; 300];
+        let tuple_medium_key = TupleProcessor::generate_tuple(
+            &medium_key,
+            &small_value,
+            &mut page_cache,
+            &mut free_page_tracker,
+            1,
+            &compressor_none,
+            &DB_CONFIG,
+        );
+        assert_eq!(tuple_medium_key.get_overflow(), Overflow::None);
+        assert_eq!(tuple_medium_key.get_key(), medium_key.as_slice());
+
+        let large_key = vec![4u8; 600];
         let tuple_large_key = TupleProcessor::generate_tuple(
             &large_key,
             &small_value,
@@ -256,5 +403,41 @@ mod tests {
             tuple_large_both_comp.get_overflow(),
             Overflow::KeyValueOverflow
         );
+
+        // The key and value in the overflow pages are only compressed if
+        // that makes them smaller.
+        let stored_overflow = |tuple: &Tuple, page_cache: &mut PageCache| {
+            let page_no = u64::from_le_bytes(tuple.get_value()[0..8].try_into().unwrap());
+            OverflowPageHandler::get_overflow_tuple(page_no, page_cache).get_overflow()
+        };
+        let (key_value_compressed, value_compressed) = if cfg!(feature = "lz4") {
+            (Overflow::KeyValueCompressed, Overflow::ValueCompressed)
+        } else {
+            (Overflow::None, Overflow::None)
+        };
+        assert_eq!(
+            stored_overflow(&tuple_large_both_comp, &mut page_cache),
+            key_value_compressed
+        );
+        let mut random_key = vec![0u8; 600];
+        let mut random_value = vec![0u8; 5000];
+        rand::rng().fill_bytes(&mut random_key);
+        rand::rng().fill_bytes(&mut random_value);
+        for (key, value, expected) in [
+            (&small_key, &random_value, Overflow::None),
+            (&random_key, &random_value, Overflow::None),
+            (&random_key, &large_value, value_compressed),
+        ] {
+            let tuple = TupleProcessor::generate_tuple(
+                key,
+                value,
+                &mut page_cache,
+                &mut free_page_tracker,
+                1,
+                &compressor_lz4,
+                &DB_CONFIG,
+            );
+            assert_eq!(stored_overflow(&tuple, &mut page_cache), expected);
+        }
     }
 }
@@ -0,0 +1,76 @@
+use crate::Db;
+use crate::codec::Codec;
+use std::marker::PhantomData;
+
+// A thin typed layer over Db, keys and values are turned into bytes with
+// a codec (see codec.rs) so Rust types can be stored directly.
+//
+// The tree orders keys by their bytes, range only gives the expected
+// order if the key codec preserves ordering, eg BigEndianU64Codec for
+// numeric keys.
+pub struct TypedDb<K, V, KC: Codec<K>, VC: Codec<V>> {
+    db: Db,
+    key_codec: KC,
+    value_codec: VC,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, KC: Codec<K>, VC: Codec<V>> TypedDb<K, V, KC, VC> {
+    pub fn new(db: Db, key_codec: KC, value_codec: VC) -> Self {
+        TypedDb {
+            db,
+            key_codec,
+            value_codec,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        self.db
+            .get(self.key_codec.encode(key))
+            .map(|value| self.value_codec.decode(&value))
+    }
+
+    pub fn put(&mut self, key: &K, value: &V) {
+        self.db
+            .put(self.key_codec.encode(key), self.value_codec.encode(value));
+    }
+
+    pub fn delete(&mut self, key: &K) -> bool {
+        self.db.delete(self.key_codec.encode(key))
+    }
+
+    // Entries with start <= key < end, or everything from start if
+    // end is None.
+    pub fn range(&mut self, start: &K, end: Option<&K>) -> Vec<(K, V)> {
+        let start = self.key_codec.encode(start);
+        let end = end.map(|end| self.key_codec.encode(end));
+        self.db
+            .range(&start, end.as_deref())
+            .iter()
+            .map(|(key, value)| (self.key_codec.decode(key), self.value_codec.decode(value)))
+            .collect()
+    }
+
+    // Access to the untyped Db underneath.
+    pub fn db(&mut self) -> &mut Db {
+        &mut self.db
+    }
+
+    pub fn into_inner(self) -> Db {
+        self.db
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K, V> TypedDb<K, V, crate::codec::BincodeCodec, crate::codec::BincodeCodec>
+where
+    K: serde::Serialize + serde::de::DeserializeOwned,
+    V: serde::Serialize + serde::de::DeserializeOwned,
+{
+    // Keys and values both stored with bincode, fine for get/put/delete
+    // but range will not be in key order.
+    pub fn with_bincode(db: Db) -> Self {
+        TypedDb::new(db, crate::codec::BincodeCodec, crate::codec::BincodeCodec)
+    }
+}
@@ -0,0 +1,289 @@
+use crate::corruption::Corruption;
+use crate::db_master_page::DbMasterPage;
+use crate::digby_error::DigbyError;
+use crate::dir_page::DirPage;
+use crate::free_dir_page::FreeDirPage;
+use crate::key_order::KeyOrder;
+use crate::leaf_page::LeafPage;
+use crate::overflow_page::OverflowPage;
+use crate::overflow_page_handler::OverflowPageHandler;
+use crate::overflow_tuple::OverflowTuple;
+use crate::page::{Page, PageTrait, PageType};
+use crate::page_cache::PageCache;
+use crate::page_no::PageNo;
+use crate::table_dir_entry::TableDirEntry;
+use crate::tuple::TupleTrait;
+use crate::verify_report::VerifyReport;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+// Check every page of the current commit, for Db::verify_report. The
+// global tree and the table directory are walked from the master page,
+// and each table tree from the table directory. Each page is read from
+// the file again, not the page cache, so its checksum or decryption is
+// checked (unless checksum checks have been turned off), then:
+//
+//   - a page must be the type expected and have a version no later
+//     than the master page.
+//   - the keys in a leaf or dir page must be in order, the key order
+//     of the DB, and inside the
+//     range the dir page above routes to it. A dir page routes keys
+//     from a child's key up to the next child's key.
+//   - overflow chains must be in the file, have no cycles and have
+//     sane used sizes.
+//   - a page in the free page directory must not be in use, or be in
+//     the directory twice, and the count of free pages in the master
+//     page must match the directory.
+//
+// A problem is added to the report and the walk carries on, the pages
+// below a page that cannot be read are not checked.
+pub struct VerifyHandler {
+    master_version: u64,
+    key_order: KeyOrder,
+    // Blocks of the pages in use, to check the free pages against.
+    used_blks: HashSet<u64>,
+    report: VerifyReport,
+}
+
+// A page to check, the keys it can hold, and whether it is in the table
+// directory tree.
+struct PageToCheck {
+    page_no: PageNo,
+    lower: Option<Vec<u8>>,
+    upper: Option<Vec<u8>>,
+    is_table_dir: bool,
+}
+
+impl VerifyHandler {
+    pub fn verify(master_page: &DbMasterPage, page_cache: &mut PageCache) -> VerifyReport {
+        let mut handler = VerifyHandler {
+            master_version: master_page.get_version(),
+            key_order: page_cache.get_key_order(),
+            used_blks: HashSet::new(),
+            report: VerifyReport::default(),
+        };
+        // The root page and the two master pages.
+        handler.used_blks.extend(0..3);
+        handler.check_tree(
+            master_page.get_global_tree_root_page_no(),
+            false,
+            page_cache,
+        );
+        handler.check_tree(master_page.get_table_dir_page_no(), true, page_cache);
+        handler.check_free_pages(master_page.get_free_page_dir_page_no(0), page_cache);
+        if master_page
+            .get_free_page_count()
+            .is_some_and(|free_page_count| free_page_count != handler.report.free_pages)
+        {
+            handler
+                .report
+                .problems
+                .push(Corruption::FreePageCount(master_page.get_page_number()));
+        }
+        handler.report
+    }
+
+    fn check_tree(&mut self, root_page_no: PageNo, is_table_dir: bool, page_cache: &mut PageCache) {
+        let mut stack = vec![PageToCheck {
+            page_no: root_page_no,
+            lower: None,
+            upper: None,
+            is_table_dir,
+        }];
+        while let Some(to_check) = stack.pop() {
+            let page_no = to_check.page_no;
+            let Some(page) = self.read_page(page_no, page_cache) else {
+                continue;
+            };
+            match page.try_get_type() {
+                Some(PageType::DirPage) => {
+                    let children =
+                        DirPage::from_page(page, self.key_order).get_child_pages_with_keys();
+                    let keys: Vec<Vec<u8>> =
+                        children.iter().filter_map(|(key, _)| key.clone()).collect();
+                    self.check_keys(page_no, &keys, &to_check);
+                    // Push in reverse so the children are checked in
+                    // key order.
+                    for (index, (key, child_page_no)) in children.iter().enumerate().rev() {
+                        let upper = match children.get(index + 1) {
+                            Some((next_key, _)) => next_key.clone(),
+                            None => to_check.upper.clone(),
+                        };
+                        stack.push(PageToCheck {
+                            page_no: *child_page_no,
+                            lower: key.clone().or_else(|| to_check.lower.clone()),
+                            upper,
+                            is_table_dir: to_check.is_table_dir,
+                        });
+                    }
+                }
+                Some(PageType::LeafPage) => {
+                    let leaf_page = LeafPage::from_page(page, self.key_order);
+                    self.check_keys(page_no, &leaf_page.get_all_keys(), &to_check);
+                    for tuple in leaf_page.get_all_tuples() {
+                        if to_check.is_table_dir {
+                            let table_dir_entry = TableDirEntry::from_bytes(tuple.get_value());
+                            self.check_tree(table_dir_entry.get_root_page_no(), false, page_cache);
+                        } else if tuple.get_overflow().has_overflow_pages() {
+                            let head_page_no =
+                                u64::from_le_bytes(tuple.get_value()[0..8].try_into().unwrap());
+                            self.check_overflow_chain(head_page_no, page_cache);
+                        }
+                    }
+                }
+                _ => self
+                    .report
+                    .problems
+                    .push(Corruption::WrongPageType(page_no)),
+            }
+        }
+    }
+
+    // Keys must be in order and from lower up to, but not including,
+    // upper.
+    fn check_keys(&mut self, page_no: PageNo, keys: &[Vec<u8>], to_check: &PageToCheck) {
+        let key_order = self.key_order;
+        if keys
+            .windows(2)
+            .any(|pair| key_order.compare(&pair[0], &pair[1]) != Ordering::Less)
+        {
+            self.report.problems.push(Corruption::UnsortedKeys(page_no));
+            return;
+        }
+        let (Some(first), Some(last)) = (keys.first(), keys.last()) else {
+            return;
+        };
+        let below = (to_check.lower.as_ref())
+            .is_some_and(|lower| key_order.compare(first, lower) == Ordering::Less);
+        let above = (to_check.upper.as_ref())
+            .is_some_and(|upper| key_order.compare(last, upper) != Ordering::Less);
+        if below || above {
+            self.report
+                .problems
+                .push(Corruption::KeyOutOfRange(page_no));
+        }
+    }
+
+    fn check_overflow_chain(&mut self, head_page_no: u64, page_cache: &mut PageCache) {
+        let mut lengths: Vec<u8> = Vec::with_capacity(OverflowTuple::LENGTHS_SIZE);
+        let mut chain_size = 0;
+        let mut seen: HashSet<u64> = HashSet::new();
+        let mut page_no = head_page_no;
+        loop {
+            let overflow_page_no = PageNo::from_u64(page_no);
+            if !seen.insert(page_no) {
+                self.report
+                    .problems
+                    .push(Corruption::OverflowCycle(overflow_page_no));
+                return;
+            }
+            let Some(page) = self.read_page(overflow_page_no, page_cache) else {
+                return;
+            };
+            if page.try_get_type() != Some(PageType::Overflow) {
+                self.report
+                    .problems
+                    .push(Corruption::WrongPageType(overflow_page_no));
+                return;
+            }
+            let page = OverflowPage::from_page(page);
+            if !page.is_used_size_valid() {
+                self.report
+                    .problems
+                    .push(Corruption::BadUsedSize(overflow_page_no));
+                return;
+            }
+            OverflowPageHandler::add_chain_bytes(&page, &mut lengths, &mut chain_size);
+            page_no = page.get_next_page();
+            if page_no == 0 {
+                break;
+            }
+        }
+        if OverflowTuple::get_serialized_size(&lengths) != Some(chain_size) {
+            self.report
+                .problems
+                .push(Corruption::OverflowLength(PageNo::from_u64(head_page_no)));
+        }
+    }
+
+    fn check_free_pages(&mut self, free_dir_page_no: PageNo, page_cache: &mut PageCache) {
+        let mut free_page_nos: Vec<PageNo> = Vec::new();
+        let mut seen: HashSet<PageNo> = HashSet::new();
+        let mut page_no = free_dir_page_no;
+        while page_no.get_blk_offset() != 0 && seen.insert(page_no) {
+            let Some(page) = self.read_page(page_no, page_cache) else {
+                break;
+            };
+            if page.try_get_type() != Some(PageType::FreeDir) {
+                self.report
+                    .problems
+                    .push(Corruption::WrongPageType(page_no));
+                break;
+            }
+            let free_dir_page = FreeDirPage::from_page(page);
+            free_page_nos.extend(free_dir_page.get_all_free_pages());
+            page_no = free_dir_page.get_nxt_free_dir_pg();
+        }
+
+        self.report.free_pages = free_page_nos.len() as u64;
+        let mut free_blks: HashSet<u64> = HashSet::new();
+        for free_page_no in free_page_nos {
+            if free_page_no.get_blk_offset() + free_page_no.get_blk_cnt()
+                > page_cache.get_total_page_count()
+            {
+                self.report
+                    .problems
+                    .push(Corruption::PageOutOfRange(free_page_no));
+                continue;
+            }
+            let blks = VerifyHandler::get_blks(free_page_no);
+            if blks.clone().any(|blk| self.used_blks.contains(&blk)) {
+                self.report
+                    .problems
+                    .push(Corruption::FreePageInUse(free_page_no));
+            } else if !blks.clone().all(|blk| free_blks.insert(blk)) {
+                self.report
+                    .problems
+                    .push(Corruption::FreePageTwice(free_page_no));
+            }
+        }
+    }
+
+    // Read a page in use from the file, returns None and adds the
+    // problem to the report if it cannot be read or has a version after
+    // the master page.
+    fn read_page(&mut self, page_no: PageNo, page_cache: &mut PageCache) -> Option<Page> {
+        if page_no.get_blk_offset() + page_no.get_blk_cnt() > page_cache.get_total_page_count() {
+            self.report
+                .problems
+                .push(Corruption::PageOutOfRange(page_no));
+            return None;
+        }
+        self.used_blks.extend(VerifyHandler::get_blks(page_no));
+        page_cache.remove_page(page_no);
+        let page = match page_cache.try_get_page(page_no) {
+            Ok(page) => page,
+            Err(DigbyError::Corruption(corruption)) => {
+                self.report.problems.push(corruption);
+                return None;
+            }
+            Err(DigbyError::ChecksumMismatch(_)) | Err(DigbyError::EncryptionMismatch(_)) => {
+                self.report.problems.push(Corruption::BadBlock(page_no));
+                return None;
+            }
+            Err(error) => panic!("{}", error),
+        };
+        self.report.pages_checked += 1;
+        if page.get_version() > self.master_version {
+            self.report
+                .problems
+                .push(Corruption::VersionAfterMaster(page_no));
+            return None;
+        }
+        Some(page)
+    }
+
+    fn get_blks(page_no: PageNo) -> std::ops::Range<u64> {
+        page_no.get_blk_offset()..page_no.get_blk_offset() + page_no.get_blk_cnt()
+    }
+}
@@ -0,0 +1,57 @@
+use crate::corruption::Corruption;
+use crate::page_no::PageNo;
+
+// What Db::verify_report found. Every page of the current commit is
+// checked, problems has each problem found with the page it was found
+// on. A DB with no problems passed.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    // Pages read from the trees, overflow chains and free page
+    // directory, pages that could not be read are not counted.
+    pub pages_checked: u64,
+    // Page numbers in the free page directory.
+    pub free_pages: u64,
+    pub problems: Vec<Corruption>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    // The pages with a problem in the order they were found, each page
+    // is listed once.
+    pub fn get_bad_page_nos(&self) -> Vec<PageNo> {
+        let mut page_nos: Vec<PageNo> = Vec::new();
+        for corruption in &self.problems {
+            let page_no = corruption.get_page_no();
+            if !page_nos.contains(&page_no) {
+                page_nos.push(page_no);
+            }
+        }
+        page_nos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_report_bad_page_nos() {
+        let mut report = VerifyReport::default();
+        assert!(report.is_ok());
+        assert!(report.get_bad_page_nos().is_empty());
+        let page_no = PageNo::from_u64(7);
+        report.problems.push(Corruption::UnsortedKeys(page_no));
+        report
+            .problems
+            .push(Corruption::BadBlock(PageNo::from_u64(3)));
+        report.problems.push(Corruption::KeyOutOfRange(page_no));
+        assert!(!report.is_ok());
+        assert_eq!(
+            report.get_bad_page_nos(),
+            vec![page_no, PageNo::from_u64(3)]
+        );
+    }
+}
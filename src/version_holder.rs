@@ -3,6 +3,10 @@ pub struct VersionHolder(u64);
 impl VersionHolder {
     const TOP_BYTE_MASK: u64 = 0xFF00_0000_0000_0000;
     const BOTTOM_56_MASK: u64 = 0x00FF_FFFF_FFFF_FFFF;
+    // Versions are 56 bits, the top byte holds flags.
+    pub const MAX_VERSION: u64 = Self::BOTTOM_56_MASK;
+    // Bytes taken by the version and flags when stored.
+    pub const SIZE: usize = 8;
 
     pub fn new(flags: u8, version: u64) -> Self {
         Self((u64::from(flags) << 56) | (version & Self::BOTTOM_56_MASK))
@@ -1,3 +1,6 @@
+use crate::block_sanity::{BlockSanity, BlockSanityProvider};
+use crate::digby_error::DigbyError;
+use crate::nonce_mode::NonceMode;
 use crate::page::Page;
 use crate::page::PageTrait;
 use byteorder::LittleEndian;
@@ -5,6 +8,7 @@ use byteorder::{ReadBytesExt, WriteBytesExt};
 use std::io::Cursor;
 use xxhash_rust::xxh32::xxh32;
 
+#[derive(Debug)]
 pub struct XxHashSanity {}
 
 impl XxHashSanity {
@@ -18,17 +22,38 @@ impl XxHashSanity {
             .expect("Failed to write checksum");
     }
 
-    pub fn verify_checksum(page: &Page) {
+    pub fn verify_checksum(page: &Page) -> Result<(), DigbyError> {
+        if !XxHashSanity::is_checksum_valid(page) {
+            return Err(DigbyError::ChecksumMismatch(page.get_page_number()));
+        }
+        Ok(())
+    }
+
+    pub fn is_checksum_valid(page: &Page) -> bool {
         let calculated_checksum = xxh32(&page.get_page_bytes()[0..], 0);
         let offset = page.get_pg_ctr_bytes().len() as u64 - 4;
         let mut cursor = std::io::Cursor::new(page.get_pg_ctr_bytes());
         cursor.set_position(offset);
         let stored_checksum = cursor.read_u32::<LittleEndian>().unwrap();
-        assert!(
-            stored_checksum == calculated_checksum,
-            "Calculated checksum does not match stored checksum for page {}",
-            page.get_page_number().to_u64()
-        );
+        stored_checksum == calculated_checksum
+    }
+}
+
+impl BlockSanityProvider for XxHashSanity {
+    fn get_id(&self) -> u8 {
+        BlockSanity::XxH32Checksum.into()
+    }
+
+    fn get_bytes_used(&self) -> usize {
+        BlockSanity::get_bytes_used(BlockSanity::XxH32Checksum)
+    }
+
+    fn set_block_sanity(&self, page: &mut Page, _key: &[u8], _nonce_mode: NonceMode) {
+        XxHashSanity::set_checksum(page);
+    }
+
+    fn check_block_sanity(&self, page: &mut Page, _key: &[u8]) -> bool {
+        XxHashSanity::is_checksum_valid(page)
     }
 }
 
@@ -38,14 +63,18 @@ mod tests {
     use crate::page_no::PageNo;
 
     #[test]
-    #[should_panic(expected = "Calculated checksum does not match stored checksum")]
     fn test_checksum() {
         let mut page = Page::new(4096, 4092);
         page.set_page_number(PageNo::from_u64(42));
         XxHashSanity::set_checksum(&mut page);
-        XxHashSanity::verify_checksum(&page);
+        assert!(XxHashSanity::verify_checksum(&page).is_ok());
         // Modify the page and verify that checksum verification fails
         page.set_version(34); // Corrupt the page
-        XxHashSanity::verify_checksum(&page); // This should panic due to checksum mismatch
+        let error = XxHashSanity::verify_checksum(&page).unwrap_err();
+        assert!(matches!(error, DigbyError::ChecksumMismatch(page_no) if page_no.to_u64() == 42));
+        assert_eq!(
+            error.to_string(),
+            "Calculated checksum does not match stored checksum for page 42"
+        );
     }
 }
@@ -0,0 +1,158 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use std::collections::BTreeMap;
+use tempfile::NamedTempFile;
+
+const BLOCK_SIZE: usize = 1024;
+
+fn check_db(db: &mut Db, global: &BTreeMap<Vec<u8>, Vec<u8>>, table: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    assert_eq!(db.verify(), Ok(()));
+    for (key, value) in global {
+        assert_eq!(db.get(key).as_ref(), Some(value));
+    }
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = global
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    assert_eq!(db.range(b"", None::<&[u8]>), expected);
+    for (key, value) in table {
+        assert_eq!(db.get_table_entry(b"table", key).as_ref(), Some(value));
+    }
+}
+
+fn fill_db(
+    db: &mut Db,
+    global: &mut BTreeMap<Vec<u8>, Vec<u8>>,
+    table: &mut BTreeMap<Vec<u8>, Vec<u8>>,
+) {
+    for i in 0..1000u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+        global.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+    }
+    let large_key = vec![4u8; 2 * BLOCK_SIZE];
+    let large_value = vec![5u8; 10 * BLOCK_SIZE];
+    db.put(&large_key, &large_value);
+    global.insert(large_key, large_value);
+    for i in 0..200u32 {
+        db.put_table_entry(b"table", i.to_be_bytes(), [3u8; 20]);
+        table.insert(i.to_be_bytes().to_vec(), vec![3u8; 20]);
+    }
+}
+
+#[test]
+fn test_db_backup() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let backup_file = NamedTempFile::new().expect("Failed to create temp file");
+    let backup_path = backup_file.path().to_str().unwrap();
+    // Something in the way that the backup replaces.
+    std::fs::write(backup_path, vec![7u8; 3 * BLOCK_SIZE]).expect("Failed to write file");
+    let mut global: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    let mut table: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    {
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+        fill_db(&mut db, &mut global, &mut table);
+        db.backup(backup_path).expect("Failed to backup");
+
+        // The DB carries on after the backup, none of this is in it.
+        for i in 0..500u32 {
+            assert!(db.delete(i.to_be_bytes()));
+        }
+        db.put(b"after", b"backup");
+        db.drop_table(b"table");
+    }
+    assert_eq!(
+        std::fs::metadata(backup_path).unwrap().len() % BLOCK_SIZE as u64,
+        0
+    );
+    let mut backup = Db::new_with_page_size(backup_path, None, CompressorType::None, BLOCK_SIZE);
+    check_db(&mut backup, &global, &table);
+    assert_eq!(backup.get(b"after"), None);
+
+    // The backup is a DB like any other.
+    backup.put(b"after", b"restore");
+    assert_eq!(backup.get(b"after"), Some(b"restore".to_vec()));
+    assert_eq!(backup.verify(), Ok(()));
+}
+
+// Pages written by a transaction that has not committed are not in
+// the backup as far as it can tell.
+#[test]
+fn test_db_backup_transaction_in_progress() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let backup_file = NamedTempFile::new().expect("Failed to create temp file");
+    let backup_path = backup_file.path().to_str().unwrap();
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    db.put(b"key", b"value");
+    let mut tx_ctx = db.new_transaction();
+    for i in 0..500u32 {
+        db.put_txn(&i.to_be_bytes(), &[1u8; 100], &mut tx_ctx);
+    }
+    db.backup(backup_path).expect("Failed to backup");
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+    assert_eq!(db.get(0u32.to_be_bytes()), Some(vec![1u8; 100]));
+
+    let mut backup = Db::new_with_page_size(backup_path, None, CompressorType::None, BLOCK_SIZE);
+    assert_eq!(backup.verify(), Ok(()));
+    assert_eq!(
+        backup.range(b"", None::<&[u8]>),
+        vec![(b"key".to_vec(), b"value".to_vec())]
+    );
+}
+
+#[test]
+fn test_db_backup_read_only() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let backup_file = NamedTempFile::new().expect("Failed to create temp file");
+    let backup_path = backup_file.path().to_str().unwrap();
+    let mut global: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    let mut table: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"before", b"reader");
+    global.insert(b"before".to_vec(), b"reader".to_vec());
+    let mut reader = Db::open_read_only(path, None, CompressorType::None);
+    assert_eq!(reader.get(b"before"), Some(b"reader".to_vec()));
+    // The reader backs up the commits made since it last read.
+    fill_db(&mut db, &mut global, &mut table);
+    reader.backup(backup_path).expect("Failed to backup");
+
+    let mut backup = Db::new(backup_path, None, CompressorType::None);
+    check_db(&mut backup, &global, &table);
+}
+
+// The backup keeps the sanity type and compressor of the DB, it only
+// opens the way the DB does.
+#[test]
+#[cfg(all(feature = "encryption", feature = "lz4"))]
+fn test_db_backup_encrypted_compressed() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let backup_file = NamedTempFile::new().expect("Failed to create temp file");
+    let backup_path = backup_file.path().to_str().unwrap();
+    let key = vec![9u8; 16];
+    let mut global: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    let mut table: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    {
+        let mut db =
+            Db::new_with_page_size(path, Some(key.clone()), CompressorType::LZ4, BLOCK_SIZE);
+        fill_db(&mut db, &mut global, &mut table);
+        db.backup(backup_path).expect("Failed to backup");
+    }
+    assert!(
+        Db::try_new_with_page_size(backup_path, None, CompressorType::LZ4, BLOCK_SIZE).is_err()
+    );
+    assert!(
+        Db::try_new_with_page_size(
+            backup_path,
+            Some(key.clone()),
+            CompressorType::None,
+            BLOCK_SIZE
+        )
+        .is_err()
+    );
+    let mut backup =
+        Db::new_with_page_size(backup_path, Some(key), CompressorType::LZ4, BLOCK_SIZE);
+    check_db(&mut backup, &global, &table);
+}
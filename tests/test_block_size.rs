@@ -0,0 +1,128 @@
+use digby::compressor::CompressorType;
+use digby::db_config::DbConfig;
+use digby::{BlockSanity, Db, DbOptions, DigbyError, MemoryStorage};
+use tempfile::NamedTempFile;
+
+fn try_new(block_size: usize, block_sanity: BlockSanity) -> Result<Db, DigbyError> {
+    Db::try_new_with_block_sanity(
+        Box::new(MemoryStorage::new()),
+        Some(vec![1u8; 32]),
+        CompressorType::None,
+        block_size,
+        0,
+        block_sanity,
+    )
+}
+
+#[test]
+fn test_db_invalid_block_size() {
+    for block_size in [0, 64, Db::MIN_BLOCK_SIZE - 1, Db::MAX_BLOCK_SIZE + 1] {
+        assert!(matches!(
+            try_new(block_size, BlockSanity::XxH32Checksum),
+            Err(DigbyError::InvalidBlockSize(size)) if size == block_size
+        ));
+    }
+    assert!(try_new(Db::MIN_BLOCK_SIZE, BlockSanity::XxH32Checksum).is_ok());
+    assert!(try_new(Db::MAX_BLOCK_SIZE, BlockSanity::Crc32c).is_ok());
+
+    // The cipher takes 28 bytes of each block, the page left must hold a
+    // master page.
+    let encrypted_min = Db::MIN_PAGE_SIZE + 28;
+    assert!(Db::check_block_size(encrypted_min - 1, BlockSanity::Aes128Gcm).is_err());
+    assert!(Db::check_block_size(encrypted_min, BlockSanity::Aes128Gcm).is_ok());
+    #[cfg(feature = "encryption")]
+    {
+        assert!(matches!(
+            try_new(Db::MIN_BLOCK_SIZE, BlockSanity::Aes128Gcm),
+            Err(DigbyError::InvalidBlockSize(_))
+        ));
+        let mut db = try_new(encrypted_min, BlockSanity::Aes128Gcm).unwrap();
+        db.put(b"key", b"value");
+        assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    }
+}
+
+// Any block size in the range can be used, not only a power of two, and
+// open_auto finds it.
+#[test]
+fn test_db_block_size_not_power_of_two() {
+    for block_size in [1000, 3000, 4100] {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        {
+            let mut db = Db::new_with_page_size(path, None, CompressorType::None, block_size);
+            for i in 0..2000u32 {
+                db.put(i.to_be_bytes(), [1u8; 20]);
+            }
+            db.put(b"large", vec![2u8; 50000]);
+        }
+        assert_eq!(
+            std::fs::metadata(path).unwrap().len() % block_size as u64,
+            0
+        );
+        let mut db_file = std::fs::File::open(path).unwrap();
+        assert_eq!(Db::detect_block_size(&mut db_file, None), Some(block_size));
+        let mut db = Db::open_auto(path, None);
+        assert_eq!(db.verify(), Ok(()));
+        for i in 0..2000u32 {
+            assert_eq!(db.get(i.to_be_bytes()), Some(vec![1u8; 20]));
+        }
+        assert_eq!(db.get(b"large"), Some(vec![2u8; 50000]));
+    }
+}
+
+// The block size is kept in the root page and a DB opened with another
+// is refused, whether or not the checksums are checked.
+#[test]
+fn test_db_block_size_mismatch() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    Db::new(path, None, CompressorType::None).put(b"key", b"value");
+    assert!(matches!(
+        Db::try_new_with_page_size(path, None, CompressorType::None, 1024),
+        Err(DigbyError::BlockSizeMismatch(4096, 1024))
+    ));
+    let db_config = DbConfig::builder()
+        .block_size(1024)
+        .compressor_type(CompressorType::None)
+        .verify_checksums(false)
+        .build();
+    let db_file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .unwrap();
+    assert!(matches!(
+        Db::try_new_with_config(Box::new(db_file), None, db_config),
+        Err(DigbyError::BlockSizeMismatch(4096, 1024))
+    ));
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
+
+// A DB created with a small block size is refused when opened with the
+// default, open_auto opens it without being told the block size.
+#[test]
+fn test_db_block_size_reopen_default() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, 128);
+        for i in 0..100u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+    }
+    assert!(matches!(
+        DbOptions::new().path(path).try_open(),
+        Err(DigbyError::BlockSizeMismatch(128, 4096))
+    ));
+    assert!(matches!(
+        DbOptions::new().path(path).read_only(true).try_open(),
+        Err(DigbyError::BlockSizeMismatch(128, 4096))
+    ));
+    let mut db = Db::open_auto(path, None);
+    assert_eq!(db.block_size(), 128);
+    for i in 0..100u32 {
+        assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+    }
+}
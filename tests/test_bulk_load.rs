@@ -0,0 +1,120 @@
+use digby::compressor::CompressorType;
+use digby::{Db, DigbyError};
+use tempfile::NamedTempFile;
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key{:08}", i).into_bytes()
+}
+
+#[test]
+fn test_bulk_load() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let count = 20000u32;
+    let loaded_pages = {
+        let mut db = Db::new(path, None, CompressorType::None);
+        db.bulk_load((0..count).map(|i| (key(i), i.to_le_bytes().to_vec())));
+        assert_eq!(db.len(), count as u64);
+        db.verify_report().pages_checked
+    };
+
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.verify().expect("DB should verify");
+    for i in (0..count).step_by(7) {
+        assert_eq!(db.get(key(i)), Some(i.to_le_bytes().to_vec()));
+    }
+    let range = db.range(key(100), Some(&key(200)));
+    assert_eq!(range.len(), 100);
+    assert_eq!(range[0].0, key(100));
+
+    // The leaf pages are packed, putting the keys one at a time splits
+    // them in half and leaves more pages.
+    let other_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut other_db = Db::new(
+        other_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    let mut tx_ctx = other_db.new_transaction();
+    for i in (0..count).rev() {
+        other_db.put_txn(&key(i), &i.to_le_bytes(), &mut tx_ctx);
+    }
+    other_db.commit(&mut tx_ctx).expect("Failed to commit");
+    assert!(loaded_pages < other_db.verify_report().pages_checked);
+
+    // The tree takes changes as normal, the full pages split.
+    db.put(key(count), b"new");
+    db.put(key(500), b"changed");
+    assert!(db.delete(key(501)));
+    db.verify().expect("DB should verify");
+    assert_eq!(db.get(key(500)), Some(b"changed".to_vec()));
+    assert_eq!(db.get(key(501)), None);
+    assert_eq!(db.len(), count as u64);
+}
+
+// Large values go to overflow pages and oversized keys are stored under
+// their short keys, out of order, they are all found after the load.
+#[test]
+fn test_bulk_load_large_tuples() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let entry = |i: u32| {
+        let mut key = key(i);
+        if i.is_multiple_of(10) {
+            key.resize(5000, i as u8);
+        }
+        let value = if i.is_multiple_of(7) {
+            vec![i as u8; 20000]
+        } else {
+            i.to_le_bytes().to_vec()
+        };
+        (key, value)
+    };
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.bulk_load((0..500u32).map(entry));
+    db.verify().expect("DB should verify");
+    assert_eq!(db.len(), 500);
+    for (key, value) in (0..500u32).map(entry) {
+        assert_eq!(db.get(&key), Some(value));
+    }
+}
+
+#[test]
+fn test_bulk_load_errors() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.bulk_load(Vec::new());
+    assert!(db.is_empty());
+
+    // Only the global tree needs to be empty.
+    db.put_table_entry(b"table", b"key", b"value");
+    db.bulk_load(vec![(b"key".to_vec(), b"value".to_vec())]);
+    assert!(matches!(
+        db.try_bulk_load(vec![(b"other".to_vec(), b"value".to_vec())]),
+        Err(DigbyError::NotEmpty)
+    ));
+    assert_eq!(db.get(b"other"), None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    assert_eq!(
+        db.get_table_entry(b"table", b"key"),
+        Some(b"value".to_vec())
+    );
+
+    let mut read_only_db = Db::open_read_only(path, None, CompressorType::None);
+    db.clear();
+    assert!(read_only_db.try_bulk_load(Vec::new()).is_err());
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "Bulk load keys must be in order with no duplicates.")]
+fn test_bulk_load_unsorted() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.bulk_load(vec![
+        (b"b".to_vec(), b"value".to_vec()),
+        (b"a".to_vec(), b"value".to_vec()),
+    ]);
+}
@@ -0,0 +1,306 @@
+use digby::Db;
+use digby::DigbyError;
+use digby::MemoryStorage;
+use digby::compressor::CompressorType;
+use std::collections::BTreeMap;
+use tempfile::NamedTempFile;
+
+const BLOCK_SIZE: usize = 1024;
+
+type Entries = BTreeMap<Vec<u8>, Vec<u8>>;
+
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(path).unwrap().len()
+}
+
+fn check_db(db: &mut Db, global: &Entries, table: &Entries) {
+    assert_eq!(db.verify(), Ok(()));
+    for (key, value) in global {
+        assert_eq!(db.get(key).as_ref(), Some(value));
+    }
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = global
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    assert_eq!(db.range(b"", None::<&[u8]>), expected);
+    for (key, value) in table {
+        assert_eq!(db.get_table_entry(b"table", key).as_ref(), Some(value));
+    }
+    assert_eq!(db.list_tables(), vec![b"empty".to_vec(), b"table".to_vec()]);
+}
+
+// Store count keys and a table of count / 6, then delete most of them.
+// Returns the live data.
+fn fill_and_delete(
+    path: &str,
+    key: Option<Vec<u8>>,
+    compressor_type: CompressorType,
+    count: u32,
+) -> (Entries, Entries) {
+    let mut global: Entries = BTreeMap::new();
+    let mut table: Entries = BTreeMap::new();
+    let mut db = Db::new_with_page_size(path, key, compressor_type, BLOCK_SIZE);
+    db.put(b"blob", vec![1u8; count as usize / 15 * BLOCK_SIZE]);
+    for i in 0..count {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    for i in 0..count / 6 {
+        db.put_table_entry(b"table", i.to_be_bytes(), [3u8; 20]);
+    }
+    db.create_table(b"empty");
+    assert!(db.delete(b"blob"));
+    for i in 0..count {
+        if i % 10 == 0 {
+            global.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+        } else {
+            assert!(db.delete(i.to_be_bytes()));
+        }
+    }
+    for i in 0..count / 6 {
+        if i % 2 == 0 {
+            table.insert(i.to_be_bytes().to_vec(), vec![3u8; 20]);
+        } else {
+            assert!(db.delete_table_entry(b"table", i.to_be_bytes()));
+        }
+    }
+    // Overflow values and an oversized key are copied too.
+    let large_value = vec![2u8; 10 * BLOCK_SIZE];
+    db.put(b"large", &large_value);
+    global.insert(b"large".to_vec(), large_value);
+    let long_key = vec![4u8; 2 * BLOCK_SIZE];
+    db.put(&long_key, b"long");
+    global.insert(long_key.clone(), b"long".to_vec());
+    db.put_table_entry(b"table", &long_key, b"long");
+    table.insert(long_key, b"long".to_vec());
+    (global, table)
+}
+
+#[test]
+fn test_db_compact_file() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let (mut global, mut table) = fill_and_delete(path, None, CompressorType::None, 3000);
+    let size_before = file_size(path);
+    let version = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE).version();
+
+    // The same DB compacted in place, for comparison.
+    let in_place_file = NamedTempFile::new().expect("Failed to create temp file");
+    let in_place_path = in_place_file.path().to_str().unwrap();
+    std::fs::copy(path, in_place_path).expect("Failed to copy file");
+    {
+        let mut db = Db::new_with_page_size(in_place_path, None, CompressorType::None, BLOCK_SIZE);
+        db.compact_in_place().expect("Failed to compact");
+    }
+
+    Db::compact_file(path, None, CompressorType::None).expect("Failed to compact");
+    let size_after = file_size(path);
+    assert!(
+        size_after * 5 < size_before,
+        "{} {}",
+        size_after,
+        size_before
+    );
+    // The trees are packed as well.
+    assert!(
+        size_after < file_size(in_place_path),
+        "{} {}",
+        size_after,
+        file_size(in_place_path)
+    );
+    assert!(!std::path::Path::new(&format!("{}.compact", path)).exists());
+
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    check_db(&mut db, &global, &table);
+    // The version carries on from the old file.
+    assert!(db.version() > version);
+    // The DB carries on as usual.
+    for i in 3000..3500u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+        global.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+    }
+    db.put_table_entry(b"table", b"after", b"compact");
+    table.insert(b"after".to_vec(), b"compact".to_vec());
+    check_db(&mut db, &global, &table);
+    drop(db);
+
+    // Compacting again leaves much the same file.
+    let size_after = file_size(path);
+    Db::compact_file(path, None, CompressorType::None).expect("Failed to compact");
+    assert!(file_size(path) <= size_after);
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    check_db(&mut db, &global, &table);
+}
+
+#[test]
+fn test_db_compact_file_new_db() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        db.put(b"key", b"value");
+    }
+    Db::compact_file(path, None, CompressorType::None).expect("Failed to compact");
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(
+        db.range(b"", None::<&[u8]>),
+        vec![(b"key".to_vec(), b"value".to_vec())]
+    );
+    assert!(db.list_tables().is_empty());
+}
+
+#[test]
+fn test_db_compact_file_wrong_compressor() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        db.put(b"key", b"value");
+    }
+    let size = file_size(path);
+    let wrong = if cfg!(feature = "lz4") {
+        CompressorType::LZ4
+    } else {
+        CompressorType::Zstd
+    };
+    assert!(Db::compact_file(path, None, wrong).is_err());
+    assert_eq!(file_size(path), size);
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
+
+// The compacted file keeps the block size, key, compressor and overflow
+// threshold.
+#[test]
+#[cfg(all(feature = "encryption", feature = "lz4"))]
+fn test_db_compact_file_encrypted_compressed() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let key = vec![9u8; 16];
+    let (mut global, table) = fill_and_delete(path, Some(key.clone()), CompressorType::LZ4, 300);
+    {
+        let mut db =
+            Db::new_with_page_size(path, Some(key.clone()), CompressorType::LZ4, BLOCK_SIZE);
+        let compressible = vec![6u8; 5 * BLOCK_SIZE];
+        db.put(b"compressible", &compressible);
+        global.insert(b"compressible".to_vec(), compressible.clone());
+        db.put_raw(b"raw", &compressible);
+        global.insert(b"raw".to_vec(), compressible);
+    }
+    let threshold =
+        Db::new_with_page_size(path, Some(key.clone()), CompressorType::LZ4, BLOCK_SIZE)
+            .get_overflow_threshold();
+    let size_before = file_size(path);
+    Db::compact_file(path, Some(key.clone()), CompressorType::LZ4).expect("Failed to compact");
+    assert!(file_size(path) < size_before);
+    assert!(Db::try_new_with_page_size(path, None, CompressorType::LZ4, BLOCK_SIZE).is_err());
+    let mut db = Db::new_with_page_size(path, Some(key), CompressorType::LZ4, BLOCK_SIZE);
+    assert_eq!(db.get_overflow_threshold(), threshold);
+    check_db(&mut db, &global, &table);
+}
+
+// A table with its own compressor keeps it, its values are stored
+// compressed in a DB that is not.
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_compact_file_table_compressor() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let compressible = vec![6u8; 40 * BLOCK_SIZE];
+    {
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+        db.create_table_with_compressor(b"lz4", CompressorType::LZ4);
+        db.put_table_entry(b"lz4", b"compressible", &compressible);
+        db.create_table_with_compressor(b"none", CompressorType::None);
+        db.put_table_entry(b"none", b"key", b"value");
+        db.put_table_entry(b"default", b"key", b"value");
+    }
+    Db::compact_file(path, None, CompressorType::None).expect("Failed to compact");
+    assert!(
+        file_size(path) < compressible.len() as u64,
+        "{}",
+        file_size(path)
+    );
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(db.table_compressor_type(b"lz4"), Some(CompressorType::LZ4));
+    assert_eq!(
+        db.get_table_entry(b"lz4", b"compressible"),
+        Some(compressible)
+    );
+    assert_eq!(
+        db.table_compressor_type(b"none"),
+        Some(CompressorType::None)
+    );
+    assert_eq!(db.get_table_entry(b"none", b"key"), Some(b"value".to_vec()));
+    assert_eq!(
+        db.table_compressor_type(b"default"),
+        Some(CompressorType::None)
+    );
+    assert_eq!(
+        db.get_table_entry(b"default", b"key"),
+        Some(b"value".to_vec())
+    );
+}
+
+// An open Db compacts its own file and carries on with the new one.
+#[test]
+fn test_db_compact_into_new_file() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let (mut global, mut table) = fill_and_delete(path, None, CompressorType::None, 3000);
+    let size_before = file_size(path);
+
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    let reader = db.reader();
+    assert_eq!(
+        reader.get(b"large"),
+        global.get(b"large".as_slice()).cloned()
+    );
+    let version = db.version();
+    db.compact_into_new_file().expect("Failed to compact");
+    assert_eq!(db.version(), version + 1);
+    assert_eq!(reader.get_version(), version + 1);
+    assert!(file_size(path) * 5 < size_before);
+    assert!(!std::path::Path::new(&format!("{}.compact", path)).exists());
+    // The Db still holds the lock, on the new file.
+    assert!(matches!(
+        Db::try_new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE),
+        Err(DigbyError::Locked)
+    ));
+    check_db(&mut db, &global, &table);
+    assert_eq!(
+        reader.get(b"large"),
+        global.get(b"large".as_slice()).cloned()
+    );
+
+    for i in 3000..3500u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+        global.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+    }
+    db.put_table_entry(b"table", b"after", b"compact");
+    table.insert(b"after".to_vec(), b"compact".to_vec());
+    check_db(&mut db, &global, &table);
+    assert_eq!(
+        reader.get(&3400u32.to_be_bytes()),
+        Some(3400u32.to_le_bytes().to_vec())
+    );
+    drop(reader);
+    drop(db);
+
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    check_db(&mut db, &global, &table);
+}
+
+#[test]
+fn test_db_compact_into_new_file_needs_path() {
+    let mut db = Db::new_with_storage(
+        Box::new(MemoryStorage::new()),
+        None,
+        CompressorType::None,
+        BLOCK_SIZE,
+    );
+    db.put(b"key", b"value");
+    assert!(db.compact_into_new_file().is_err());
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
@@ -0,0 +1,114 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use std::collections::BTreeMap;
+use tempfile::NamedTempFile;
+
+const BLOCK_SIZE: usize = 1024;
+
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(path).unwrap().len()
+}
+
+fn check_db(db: &mut Db, global: &BTreeMap<Vec<u8>, Vec<u8>>, table: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    assert_eq!(db.verify(), Ok(()));
+    for (key, value) in global {
+        assert_eq!(db.get(key).as_ref(), Some(value));
+    }
+    for (key, value) in table {
+        assert_eq!(db.get_table_entry(b"table", key).as_ref(), Some(value));
+    }
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = global
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    assert_eq!(db.range(b"", None::<&[u8]>), expected);
+}
+
+// Free up the front of the file so the pages in use are at the end,
+// then compact. Every page left has to move, along with the dir pages,
+// overflow chains and table roots that point to it.
+#[test]
+fn test_db_compact_in_place() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut global: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    let mut table: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    {
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+        db.put(b"blob", vec![1u8; 200 * BLOCK_SIZE]);
+        for i in 0..3000u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        db.put_table_entry(b"table", b"big", vec![2u8; 10 * BLOCK_SIZE]);
+        for i in 0..500u32 {
+            db.put_table_entry(b"table", i.to_be_bytes(), [3u8; 20]);
+        }
+        // Leave a key in every tenth, and some large values at the end.
+        assert!(db.delete(b"blob"));
+        for i in 0..3000u32 {
+            if i % 10 == 0 {
+                global.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+            } else {
+                assert!(db.delete(i.to_be_bytes()));
+            }
+        }
+        for i in 0..5u32 {
+            let key = format!("large{}", i).into_bytes();
+            let value = vec![i as u8; 3 * BLOCK_SIZE];
+            db.put(&key, &value);
+            global.insert(key, value);
+        }
+        // A key too long to be stored inline.
+        let long_key = vec![4u8; 2 * BLOCK_SIZE];
+        db.put(&long_key, b"long");
+        global.insert(long_key, b"long".to_vec());
+        table.insert(b"big".to_vec(), vec![2u8; 10 * BLOCK_SIZE]);
+        for i in 0..500u32 {
+            table.insert(i.to_be_bytes().to_vec(), vec![3u8; 20]);
+        }
+        check_db(&mut db, &global, &table);
+
+        let size_before = file_size(path);
+        db.compact_in_place().expect("Failed to compact");
+        let size_after = file_size(path);
+        // The blob was a little under half the file.
+        assert!(
+            size_after * 3 < size_before * 2,
+            "{} {}",
+            size_after,
+            size_before
+        );
+        check_db(&mut db, &global, &table);
+
+        // Nothing left to move, the file stays the same size.
+        db.compact_in_place().expect("Failed to compact");
+        assert_eq!(file_size(path), size_after);
+
+        // The DB is usable after compaction, new pages come from the
+        // end of the file once the free pages are used up.
+        for i in 3000..3500u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+            global.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+        }
+        db.put_table_entry(b"table", b"after", b"compact");
+        table.insert(b"after".to_vec(), b"compact".to_vec());
+        check_db(&mut db, &global, &table);
+    }
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    check_db(&mut db, &global, &table);
+}
+
+#[test]
+fn test_db_compact_in_place_new_db() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    let size_before = file_size(path);
+    db.compact_in_place().expect("Failed to compact");
+    assert!(file_size(path) <= size_before);
+    assert_eq!(db.verify(), Ok(()));
+    db.put(b"key", b"value");
+    drop(db);
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, BLOCK_SIZE);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
@@ -0,0 +1,70 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use tempfile::NamedTempFile;
+
+// Round trip large values through each codec that is built in. Run the
+// codecs that are not on by default with, for example:
+//   cargo test --no-default-features --features zstd
+//   cargo test --features zstd,snappy
+
+#[cfg(any(feature = "lz4", feature = "zstd", feature = "snappy"))]
+fn check_round_trip(compressor_type: CompressorType) {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    // Compressible values that are too big for a leaf page.
+    let values: Vec<Vec<u8>> = (0..20u8)
+        .map(|i| vec![i; 2000 + i as usize * 500])
+        .collect();
+    {
+        let mut db = Db::new(path, None, compressor_type);
+        for (i, value) in values.iter().enumerate() {
+            db.put(format!("key{:02}", i).as_bytes(), value);
+        }
+        db.put_table_entry(b"table", b"key", &values[19]);
+    }
+    {
+        let mut db = Db::new(path, None, compressor_type);
+        assert_eq!(db.verify(), Ok(()));
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(&db.get(format!("key{:02}", i).as_bytes()).unwrap(), value);
+        }
+        assert_eq!(db.get_table_entry(b"table", b"key").unwrap(), values[19]);
+        assert!(db.delete(b"key00"));
+        assert!(db.get(b"key00").is_none());
+    }
+    // The values compress so the file holds far less than the values.
+    let file_size = std::fs::metadata(path).unwrap().len() as usize;
+    let values_size: usize = values.iter().map(|value| value.len()).sum();
+    assert!(file_size < values_size, "{} {}", file_size, values_size);
+    std::fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_round_trip_lz4() {
+    check_round_trip(CompressorType::LZ4);
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn test_db_round_trip_zstd() {
+    check_round_trip(CompressorType::Zstd);
+}
+
+#[test]
+#[cfg(feature = "snappy")]
+fn test_db_round_trip_snappy() {
+    check_round_trip(CompressorType::Snappy);
+}
+
+#[test]
+#[cfg(not(feature = "zstd"))]
+#[should_panic(expected = "Compression codec Zstd not compiled in, build with the zstd feature.")]
+fn test_db_zstd_not_compiled_in() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::Zstd,
+    );
+}
@@ -0,0 +1,206 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use digby::observer::Observer;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+
+// Crash consistency harness.
+//
+// An operation is run against the DB with an observer that records each
+// block written and each sync. Then for every point in that sequence of
+// writes a crash image is built - the file as it was before the
+// operation with only the writes up to that point applied. Blocks past
+// the end of the original file that had not been written are missing,
+// so the image is truncated there. Each image is opened and must hold
+// either the state before the operation or the state after it.
+//
+// A torn write is simulated too, for each write an image with the first
+// half of its last block written and the rest left as it was. The block
+// fails its checksum, and the image must hold the same state as the one
+// before the write. When the block is the master page being written this
+// is the DB falling back to the other master page.
+
+enum Event {
+    Write { blk_offset: u64, data: Vec<u8> },
+    Sync,
+}
+
+struct Recorder {
+    file: File,
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl Observer for Recorder {
+    // Called after the write so read the blocks back to get the bytes
+    // that went to disk.
+    fn on_disk_write(&mut self, blk_offset: u64, blk_cnt: u64) {
+        let mut data = vec![0u8; blk_cnt as usize * Db::BLOCK_SIZE];
+        self.file
+            .seek(SeekFrom::Start(blk_offset * Db::BLOCK_SIZE as u64))
+            .unwrap();
+        self.file.read_exact(&mut data).unwrap();
+        self.events
+            .lock()
+            .unwrap()
+            .push(Event::Write { blk_offset, data });
+    }
+
+    fn on_sync(&mut self, _full: bool) {
+        self.events.lock().unwrap().push(Event::Sync);
+    }
+}
+
+// What is visible in the DB, the global tree and each table.
+type DbState = (Vec<(Vec<u8>, Vec<u8>)>, Vec<(Vec<u8>, u64)>);
+
+fn db_state(db: &mut Db) -> DbState {
+    db.verify().expect("DB should verify");
+    let tables = db
+        .table_infos()
+        .into_iter()
+        .map(|table_info| (table_info.name, table_info.entry_count))
+        .collect();
+    (db.range(b"", None::<&[u8]>), tables)
+}
+
+fn open_db(path: &str) -> Db {
+    Db::new(path, None, CompressorType::None)
+}
+
+fn check_crash_consistency(setup: impl Fn(&mut Db), operation: impl Fn(&mut Db)) {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let old_state = {
+        let mut db = open_db(path);
+        setup(&mut db);
+        db_state(&mut db)
+    };
+    let old_image = std::fs::read(path).unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let new_state = {
+        let mut db = open_db(path);
+        db.set_observer(Box::new(Recorder {
+            file: File::open(path).unwrap(),
+            events: events.clone(),
+        }));
+        operation(&mut db);
+        db_state(&mut db)
+    };
+    assert_ne!(old_state, new_state, "Operation should change the DB.");
+
+    let events = events.lock().unwrap();
+    let syncs = events.iter().filter(|e| matches!(e, Event::Sync)).count();
+    assert!(syncs >= 2, "Expected a sync before and after the master.");
+
+    let crash_file = NamedTempFile::new().expect("Failed to create temp file");
+    let crash_path = crash_file.path().to_str().unwrap();
+    let mut image = old_image.clone();
+    let mut seen_new_state = false;
+    let mut state = old_state.clone();
+    // Crash before any writes, then after each write.
+    for i in 0..=events.len() {
+        if i > 0 {
+            let Event::Write { blk_offset, data } = &events[i - 1] else {
+                continue;
+            };
+            let start = *blk_offset as usize * Db::BLOCK_SIZE;
+            if image.len() < start + data.len() {
+                image.resize(start + data.len(), 0);
+            }
+            let torn = start + data.len() - Db::BLOCK_SIZE / 2;
+            let mut torn_image = image.clone();
+            torn_image[start..torn].copy_from_slice(&data[..torn - start]);
+            File::create(crash_path)
+                .unwrap()
+                .write_all(&torn_image)
+                .unwrap();
+            assert_eq!(
+                db_state(&mut open_db(crash_path)),
+                state,
+                "Torn write {} of {} is corrupt.",
+                i,
+                events.len()
+            );
+            image[start..start + data.len()].copy_from_slice(data);
+        }
+        File::create(crash_path).unwrap().write_all(&image).unwrap();
+        state = db_state(&mut open_db(crash_path));
+        if state == new_state {
+            seen_new_state = true;
+        } else {
+            assert!(
+                !seen_new_state,
+                "Went back to the old state after write {} of {}.",
+                i,
+                events.len()
+            );
+            assert_eq!(state, old_state, "Crash after write {} is corrupt.", i);
+        }
+    }
+    assert!(seen_new_state, "Final image should have the new state.");
+}
+
+fn put_keys(db: &mut Db, count: u32) {
+    for i in 0..count {
+        db.put(format!("key{:05}", i).as_bytes(), i.to_le_bytes());
+    }
+}
+
+#[test]
+fn test_crash_put_into_empty_db() {
+    check_crash_consistency(|_db| {}, |db| db.put(b"key", b"value"));
+}
+
+#[test]
+fn test_crash_put() {
+    check_crash_consistency(
+        |db| put_keys(db, 20),
+        |db| db.put(b"key00010", b"new_value"),
+    );
+}
+
+#[test]
+fn test_crash_put_splits_leaf() {
+    check_crash_consistency(
+        |db| put_keys(db, 200),
+        |db| {
+            let mut tx_ctx = db.new_transaction();
+            for i in 200..400u32 {
+                db.put_txn(format!("key{:05}", i).as_bytes(), &[1u8; 64], &mut tx_ctx);
+            }
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+        },
+    );
+}
+
+#[test]
+fn test_crash_put_overflow() {
+    check_crash_consistency(
+        |db| put_keys(db, 20),
+        |db| db.put(b"large", vec![7u8; 20000]),
+    );
+}
+
+#[test]
+fn test_crash_delete() {
+    check_crash_consistency(
+        |db| {
+            put_keys(db, 20);
+            db.put(b"large", vec![7u8; 20000]);
+        },
+        |db| {
+            db.delete(b"large");
+        },
+    );
+}
+
+#[test]
+fn test_crash_table_put() {
+    check_crash_consistency(
+        |db| db.put_table_entry(b"table", b"key", b"value"),
+        |db| db.put_table_entry(b"other_table", b"key", b"value"),
+    );
+}
@@ -0,0 +1,140 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use tempfile::NamedTempFile;
+
+fn key(i: usize) -> Vec<u8> {
+    format!("key_{:06}", i).into_bytes()
+}
+
+// More entries than a batch so the cursor has to carry on from where
+// each batch stopped, the tree has several levels of pages.
+#[test]
+fn test_cursor_matches_range() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    for i in 0..2000 {
+        db.put(key(i), format!("value_{}", i));
+    }
+    // An oversized key and one in overflow pages.
+    db.put(vec![b'k'; 3000], b"oversized");
+    db.put(b"large", vec![9u8; 20000]);
+
+    let all = db.range(b"", None::<&[u8]>);
+    assert_eq!(all.len(), 2002);
+    assert_eq!(db.cursor().collect::<Vec<_>>(), all);
+    assert_eq!((&mut db).into_iter().count(), 2002);
+
+    let mut count = 0;
+    for (key, _) in &mut db {
+        assert!(!key.is_empty());
+        count += 1;
+    }
+    assert_eq!(count, 2002);
+
+    assert_eq!(
+        db.cursor_range(key(100), Some(&key(900)))
+            .collect::<Vec<_>>(),
+        db.range(key(100), Some(&key(900)))
+    );
+    assert_eq!(
+        db.cursor_range(key(1500), None::<&[u8]>)
+            .collect::<Vec<_>>(),
+        db.range(key(1500), None::<&[u8]>)
+    );
+    assert_eq!(db.cursor_range(b"zzz", None::<&[u8]>).count(), 0);
+
+    assert_eq!(db.cursor().take(3).collect::<Vec<_>>(), all[..3].to_vec());
+}
+
+#[test]
+fn test_cursor_prefix() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    for i in 0..600 {
+        db.put(format!("a_{:04}", i), b"a");
+        db.put(format!("b_{:04}", i), b"b");
+    }
+    let mut oversized_a = vec![b'c'; 3000];
+    oversized_a.push(b'a');
+    let mut oversized_b = vec![b'c'; 3000];
+    oversized_b.push(b'b');
+    db.put(&oversized_a, b"ca");
+    db.put(&oversized_b, b"cb");
+
+    assert_eq!(
+        db.cursor_prefix(b"b_").collect::<Vec<_>>(),
+        db.scan_prefix(b"b_").collect::<Vec<_>>()
+    );
+    assert_eq!(db.cursor_prefix(b"b_").count(), 600);
+    assert_eq!(db.cursor_prefix(b"a_01").count(), 100);
+    assert_eq!(
+        db.cursor_prefix(&oversized_b).collect::<Vec<_>>(),
+        vec![(oversized_b.clone(), b"cb".to_vec())]
+    );
+    assert_eq!(db.cursor_prefix(b"d").count(), 0);
+}
+
+#[test]
+fn test_cursor_empty_db() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.cursor().next(), None);
+    // The cursor has let go of the DB.
+    db.put(b"key", b"value");
+    assert_eq!(
+        db.cursor().collect::<Vec<_>>(),
+        vec![(b"key".to_vec(), b"value".to_vec())]
+    );
+}
+
+#[test]
+fn test_cursor_seek() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    for i in (0..2000).step_by(2) {
+        db.put(key(i), format!("value_{}", i));
+    }
+
+    let mut cursor = db.cursor();
+    assert_eq!(cursor.key(), Some(key(0).as_slice()));
+    assert_eq!(cursor.value(), Some(b"value_0".as_slice()));
+    // key and value do not move the cursor on.
+    assert_eq!(cursor.next().map(|(key, _)| key), Some(key(0)));
+    assert_eq!(cursor.key(), Some(key(2).as_slice()));
+
+    // To a key that is there, one that is not, back and past the end.
+    cursor.seek(&key(1000));
+    assert_eq!(cursor.key(), Some(key(1000).as_slice()));
+    cursor.seek(&key(1501));
+    assert_eq!(cursor.value(), Some(b"value_1502".as_slice()));
+    cursor.seek(&key(10));
+    assert_eq!(cursor.next().map(|(key, _)| key), Some(key(10)));
+    assert_eq!(cursor.next().map(|(key, _)| key), Some(key(12)));
+    cursor.seek(b"zzz");
+    assert_eq!(cursor.key(), None);
+    assert_eq!(cursor.next(), None);
+
+    // Fetch 100 rows after a key kept from an earlier page.
+    let page: Vec<_> = db.cursor().take(100).collect();
+    let last_key = page.last().unwrap().0.clone();
+    let mut cursor = db.cursor();
+    cursor.seek(&last_key);
+    cursor.next();
+    let next_page: Vec<_> = cursor.take(100).collect();
+    assert_eq!(next_page, db.range(key(200), Some(&key(400))));
+
+    // A seek stays inside the range of the cursor.
+    let mut cursor = db.cursor_range(key(100), Some(&key(200)));
+    cursor.seek(b"");
+    assert_eq!(cursor.key(), Some(key(100).as_slice()));
+    cursor.seek(&key(300));
+    assert_eq!(cursor.key(), None);
+    let mut cursor = db.cursor_prefix(b"key_0015");
+    cursor.seek(b"key_001550");
+    assert_eq!(cursor.key(), Some(key(1550).as_slice()));
+    assert_eq!(cursor.count(), 25);
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "chacha20")]
+
+use digby::compressor::CompressorType;
+use digby::{BlockSanity, Db, DbOptions, DigbyError};
+use tempfile::NamedTempFile;
+
+#[test]
+fn test_db_store_value_with_chacha20() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let enc_key = b"a_thirty_two_byte_encryption_key".to_vec();
+    {
+        let mut db = Db::try_new_with_cipher(
+            path,
+            Some(enc_key.clone()),
+            CompressorType::None,
+            Db::BLOCK_SIZE,
+            BlockSanity::ChaCha20Poly1305,
+        )
+        .expect("Failed to create DB");
+        db.put(b"the_key", b"the_value");
+        db.put(b"large", vec![7u8; 20000]);
+    }
+    let mut db = Db::try_new_with_cipher(
+        path,
+        Some(enc_key),
+        CompressorType::None,
+        Db::BLOCK_SIZE,
+        BlockSanity::ChaCha20Poly1305,
+    )
+    .expect("Failed to open DB");
+    assert_eq!(db.get(b"the_key"), Some(b"the_value".to_vec()));
+    assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+    // The plain text is not in the file.
+    drop(db);
+    let bytes = std::fs::read(path).expect("Failed to read file");
+    assert!(!bytes.windows(9).any(|window| window == b"the_value"));
+}
+
+#[test]
+fn test_db_chacha20_wrong_key() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let options = DbOptions::new()
+        .path(path)
+        .encryption_key(b"the_encryption_key")
+        .block_sanity(BlockSanity::ChaCha20Poly1305);
+    options.clone().open().put(b"key", b"value");
+    assert_eq!(options.open().get(b"key"), Some(b"value".to_vec()));
+    let error = DbOptions::new()
+        .path(path)
+        .encryption_key(b"bad_encryption_key")
+        .block_sanity(BlockSanity::ChaCha20Poly1305)
+        .try_open()
+        .err();
+    assert!(matches!(error, Some(DigbyError::EncryptionMismatch(_))));
+}
+
+// A DB must be opened with the cipher it was created with, open_auto
+// finds it from the file.
+#[test]
+#[cfg(feature = "encryption")]
+fn test_db_chacha20_cipher_mismatch() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let enc_key = b"the_encryption_key".to_vec();
+    Db::try_new_with_cipher(
+        path,
+        Some(enc_key.clone()),
+        CompressorType::None,
+        1024,
+        BlockSanity::ChaCha20Poly1305,
+    )
+    .expect("Failed to create DB")
+    .put(b"key", b"value");
+    assert!(
+        Db::try_new_with_page_size(path, Some(enc_key.clone()), CompressorType::None, 1024)
+            .is_err()
+    );
+
+    let mut db_file = std::fs::File::open(path).expect("Failed to open file");
+    assert_eq!(
+        Db::detect_format(&mut db_file, Some(&enc_key)),
+        Some((1024, BlockSanity::ChaCha20Poly1305))
+    );
+    drop(db_file);
+    let mut db = Db::open_auto(path, Some(enc_key));
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    assert_eq!(db.reader().get(b"key"), Some(b"value".to_vec()));
+}
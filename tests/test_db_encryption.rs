@@ -1,3 +1,5 @@
+#![cfg(feature = "encryption")]
+
 use digby::Db;
 use digby::compressor::CompressorType;
 use std::fs;
@@ -15,7 +17,7 @@ fn test_db_store_value_with_encryption() {
             Some(enc_key.to_vec()),
             CompressorType::None,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -25,7 +27,7 @@ fn test_db_store_value_with_encryption() {
             Some(enc_key.to_vec()),
             CompressorType::None,
         );
-        let returned_value = db.get(key.as_ref()).unwrap();
+        let returned_value = db.get(&key).unwrap();
         assert!(returned_value == value);
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -44,7 +46,7 @@ fn test_db_store_value_with_encryption_wrong_key() {
             Some(enc_key.to_vec()),
             CompressorType::None,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -54,7 +56,7 @@ fn test_db_store_value_with_encryption_wrong_key() {
             Some(b"bad_encryption_key".to_vec()),
             CompressorType::None,
         );
-        let returned_value = db.get(key.as_ref()).unwrap();
+        let returned_value = db.get(&key).unwrap();
         assert!(returned_value == value);
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -73,7 +75,7 @@ fn test_db_store_value_with_encryption_no_key() {
             Some(enc_key.to_vec()),
             CompressorType::None,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -88,6 +90,7 @@ fn test_db_store_value_with_encryption_no_key() {
 }
 
 #[test]
+#[cfg(feature = "lz4")]
 fn test_db_store_large_key_value_compressible_encryption() {
     let temp_file = NamedTempFile::new().expect("Failed to create temp file");
     let key: Vec<u8> = vec![111u8; 8192];
@@ -99,7 +102,7 @@ fn test_db_store_large_key_value_compressible_encryption() {
             Some(enc_key.to_vec()),
             CompressorType::LZ4,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -109,7 +112,7 @@ fn test_db_store_large_key_value_compressible_encryption() {
             Some(enc_key.to_vec()),
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref()).unwrap();
+        let returned_value = db.get(&key).unwrap();
         assert!(returned_value == value);
         assert!(db.delete(&key));
     }
@@ -119,8 +122,47 @@ fn test_db_store_large_key_value_compressible_encryption() {
             Some(enc_key.to_vec()),
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref());
+        let returned_value = db.get(&key);
         assert!(returned_value.is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
+
+// With derived nonces the same writes give the same file, with random
+// nonces every encrypted block differs.
+#[test]
+fn test_db_encryption_derived_nonce() {
+    use digby::{DbOptions, NonceMode};
+
+    let write_db = |nonce_mode: NonceMode| {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        {
+            let mut db = DbOptions::new()
+                .path(path)
+                .encryption_key(b"the_encryption_key")
+                .nonce_mode(nonce_mode)
+                .open();
+            for i in 0..100u32 {
+                db.put(i.to_be_bytes(), [i as u8; 200]);
+            }
+            db.delete(7u32.to_be_bytes());
+            db.put(b"large", vec![3u8; 20000]);
+        }
+        let mut db = Db::new(
+            path,
+            Some(b"the_encryption_key".to_vec()),
+            CompressorType::None,
+        );
+        assert_eq!(db.get(b"large"), Some(vec![3u8; 20000]));
+        assert_eq!(db.get(7u32.to_be_bytes()), None);
+        assert_eq!(db.verify(), Ok(()));
+        drop(db);
+        fs::read(path).expect("Failed to read file")
+    };
+    let derived = write_db(NonceMode::Derived);
+    assert!(derived == write_db(NonceMode::Derived));
+    let random = write_db(NonceMode::Random);
+    assert_eq!(random.len(), derived.len());
+    assert!(random != write_db(NonceMode::Random));
+}
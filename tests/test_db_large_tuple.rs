@@ -1,9 +1,12 @@
 use digby::Db;
 use digby::compressor::CompressorType;
+use digby::observer::Observer;
 use rand::RngCore;
 use rand::prelude::SliceRandom;
 use rand::rng;
 use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tempfile::NamedTempFile;
 
 #[test]
@@ -13,6 +16,7 @@ fn test_digby_db() {
 }
 
 #[test]
+#[cfg(feature = "lz4")]
 fn test_db_store_large_key_value_incompressible() {
     let temp_file = NamedTempFile::new().expect("Failed to create temp file");
     let mut key: Vec<u8> = vec![0u8; 8192];
@@ -26,7 +30,7 @@ fn test_db_store_large_key_value_incompressible() {
             None,
             CompressorType::LZ4,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -36,7 +40,7 @@ fn test_db_store_large_key_value_incompressible() {
             None,
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref()).unwrap();
+        let returned_value = db.get(&key).unwrap();
         assert!(returned_value == value);
         assert!(db.delete(&key));
     }
@@ -46,13 +50,58 @@ fn test_db_store_large_key_value_incompressible() {
             None,
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref());
+        let returned_value = db.get(&key);
         assert!(returned_value.is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
 
+struct ReadCounter {
+    reads: Arc<AtomicU64>,
+}
+
+impl Observer for ReadCounter {
+    fn on_disk_read(&mut self, _blk_offset: u64, _blk_cnt: u64) {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// A 4MB value is a chain of over 1000 overflow pages, written to the
+// end of the file they run forwards through it and are read back in a
+// handful of large reads rather than one a page.
+#[test]
+fn test_db_large_value_batched_reads() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut key: Vec<u8> = vec![0u8; 8192];
+    let mut value: Vec<u8> = vec![0u8; 4 * 1024 * 1024];
+    let mut rng = rand::rng();
+    rng.fill_bytes(&mut key);
+    rng.fill_bytes(&mut value);
+    {
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        db.put(&key, &value);
+    }
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    let reads = Arc::new(AtomicU64::new(0));
+    db.set_observer(Box::new(ReadCounter {
+        reads: reads.clone(),
+    }));
+    assert!(db.get(&key).unwrap() == value);
+    let reads = reads.load(Ordering::SeqCst);
+    assert!(reads < 16, "{} reads", reads);
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
 #[test]
+#[cfg(feature = "lz4")]
 fn test_db_store_small_key_large_value_incompressible() {
     let temp_file = NamedTempFile::new().expect("Failed to create temp file");
     let mut key: Vec<u8> = vec![0u8; 32];
@@ -66,7 +115,7 @@ fn test_db_store_small_key_large_value_incompressible() {
             None,
             CompressorType::LZ4,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -76,7 +125,7 @@ fn test_db_store_small_key_large_value_incompressible() {
             None,
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref()).unwrap();
+        let returned_value = db.get(&key).unwrap();
         assert!(returned_value == value);
         assert!(db.delete(&key));
     }
@@ -86,13 +135,14 @@ fn test_db_store_small_key_large_value_incompressible() {
             None,
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref());
+        let returned_value = db.get(&key);
         assert!(returned_value.is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
 
 #[test]
+#[cfg(feature = "lz4")]
 fn test_db_store_large_key_value_compressible() {
     let temp_file = NamedTempFile::new().expect("Failed to create temp file");
     let key: Vec<u8> = vec![111u8; 8192];
@@ -103,7 +153,7 @@ fn test_db_store_large_key_value_compressible() {
             None,
             CompressorType::LZ4,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -113,7 +163,7 @@ fn test_db_store_large_key_value_compressible() {
             None,
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref()).unwrap();
+        let returned_value = db.get(&key).unwrap();
         assert!(returned_value == value);
         assert!(db.delete(&key));
     }
@@ -123,13 +173,14 @@ fn test_db_store_large_key_value_compressible() {
             None,
             CompressorType::LZ4,
         );
-        let returned_value = db.get(key.as_ref());
+        let returned_value = db.get(&key);
         assert!(returned_value.is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
 
 #[test]
+#[cfg(feature = "lz4")]
 #[should_panic(expected = "Db compression mis-match, stored type is 1, requested type None")]
 fn test_db_store_large_key_value_compressible_mismatch() {
     let temp_file = NamedTempFile::new().expect("Failed to create temp file");
@@ -141,7 +192,7 @@ fn test_db_store_large_key_value_compressible_mismatch() {
             None,
             CompressorType::LZ4,
         );
-        db.put(key.as_ref(), value.as_ref());
+        db.put(&key, &value);
     }
     // The new scope essentially closes the DB - when Files run out of scope then
     // they are close, Rust bizairely does not allow error handling on close!
@@ -218,3 +269,221 @@ fn test_db_clear_large_tuples() {
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
+
+// Large keys and values on small pages give a deep tree where leaf pages
+// often split three ways and the dir pages holding them split too.
+#[test]
+fn test_db_three_way_leaf_splits_in_deep_tree() {
+    use std::collections::BTreeMap;
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut expected: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    {
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            2048,
+        );
+        let mut rng = rng();
+        let mut ids: Vec<u32> = (0..400).chain(0..200).collect();
+        ids.shuffle(&mut rng);
+        for batch in ids.chunks(50) {
+            let mut tx_ctx = db.new_transaction();
+            for id in batch {
+                let key = format!("{:05}{}", id, "k".repeat(195)).into_bytes();
+                let mut value = vec![0u8; 1 + (rng.next_u32() % 1024) as usize];
+                rng.fill_bytes(&mut value);
+                db.put_txn(&key, &value, &mut tx_ctx);
+                expected.insert(key, value);
+            }
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+        }
+        assert_eq!(db.verify(), Ok(()));
+    }
+    {
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            2048,
+        );
+        for (key, value) in &expected {
+            assert_eq!(db.get(key).as_ref(), Some(value));
+        }
+        let all: Vec<(Vec<u8>, Vec<u8>)> = expected.into_iter().collect();
+        assert_eq!(db.range(b"", None::<&[u8]>), all);
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+// Keys share a prefix longer than the 223 bytes kept in a short key, so
+// the range is only in full key order if the keys are stored inline.
+fn check_inline_keys(block_size: usize, key_size: usize, count: u32) {
+    use std::collections::BTreeMap;
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut expected: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    let make_key = |id: u32| {
+        let mut key = vec![b'k'; key_size - 10];
+        key.extend_from_slice(format!("{:05}", id).as_bytes());
+        key.extend_from_slice(b"00000");
+        key
+    };
+    {
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            block_size,
+        );
+        let mut ids: Vec<u32> = (0..count).collect();
+        ids.shuffle(&mut rng());
+        for batch in ids.chunks(20) {
+            let mut tx_ctx = db.new_transaction();
+            for id in batch {
+                let key = make_key(*id);
+                let value = id.to_le_bytes().to_vec();
+                db.put_txn(&key, &value, &mut tx_ctx);
+                expected.insert(key, value);
+            }
+            db.commit(&mut tx_ctx).expect("Failed to commit");
+        }
+        assert_eq!(db.verify(), Ok(()));
+    }
+    {
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            block_size,
+        );
+        for (key, value) in &expected {
+            assert_eq!(key.len(), key_size);
+            assert_eq!(db.get(key).as_ref(), Some(value));
+        }
+        let all: Vec<(Vec<u8>, Vec<u8>)> = expected.clone().into_iter().collect();
+        assert_eq!(db.range(b"", None::<&[u8]>), all);
+
+        for id in (0..count).step_by(2) {
+            let key = make_key(id);
+            assert!(db.delete(&key));
+            expected.remove(&key);
+        }
+        assert_eq!(db.verify(), Ok(()));
+        let all: Vec<(Vec<u8>, Vec<u8>)> = expected.into_iter().collect();
+        assert_eq!(db.range(b"", None::<&[u8]>), all);
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+#[test]
+fn test_db_300_byte_keys_inline() {
+    check_inline_keys(Db::BLOCK_SIZE, 300, 500);
+}
+
+#[test]
+fn test_db_2000_byte_keys_inline() {
+    check_inline_keys(16384, 2000, 200);
+}
+
+// On the default page size a 2000 byte key is too large to store inline
+// so it is stored with a short key.
+#[test]
+fn test_db_2000_byte_keys_short_key() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    let keys: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 2000]).collect();
+    for key in &keys {
+        db.put(key, &key[..10]);
+    }
+    assert_eq!(db.verify(), Ok(()));
+    for key in &keys {
+        assert_eq!(db.get(key), Some(key[..10].to_vec()));
+    }
+    assert_eq!(db.range(b"", None::<&[u8]>).len(), keys.len());
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+// With 8192 byte blocks a 6KB value fits in a leaf page so it is kept
+// inline rather than going to an overflow page.
+#[test]
+fn test_db_6kb_values_inline() {
+    use std::collections::BTreeMap;
+    let block_size = 8192;
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut expected: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+    {
+        let mut db = Db::new_with_page_size(path, None, CompressorType::None, block_size);
+        // Grow the file first so the puts below take free pages.
+        db.put(b"blob", vec![0u8; 100 * block_size]);
+        assert!(db.delete(b"blob"));
+        // A commit frees as many pages as it takes unless there is an
+        // overflow page as well.
+        let mut value = vec![0u8; 6 * 1024];
+        rng().fill_bytes(&mut value);
+        let free_page_count = db.free_page_count();
+        db.put(b"value", &value);
+        assert_eq!(db.free_page_count(), free_page_count);
+        // The largest value that fits, the page less its header, one
+        // slot, the key and the version.
+        let mut value = vec![0u8; block_size - 4 - 30 - 6 - 5 - 8];
+        rng().fill_bytes(&mut value);
+        db.put(b"value", &value);
+        assert_eq!(db.free_page_count(), free_page_count);
+        let mut value = vec![0u8; value.len() + 1];
+        rng().fill_bytes(&mut value);
+        // One more byte and it goes to overflow pages.
+        db.put(b"value", &value);
+        assert!(db.free_page_count() < free_page_count);
+        expected.insert(b"value".to_vec(), value);
+
+        // Each value needs a leaf page to itself.
+        for i in 0..50u32 {
+            let mut value = vec![0u8; 6 * 1024];
+            rng().fill_bytes(&mut value);
+            db.put(i.to_be_bytes(), &value);
+            expected.insert(i.to_be_bytes().to_vec(), value);
+        }
+        assert_eq!(db.verify(), Ok(()));
+    }
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, block_size);
+    assert_eq!(db.verify(), Ok(()));
+    for (key, value) in &expected {
+        assert_eq!(db.get(key).as_ref(), Some(value));
+    }
+    let all: Vec<(Vec<u8>, Vec<u8>)> = expected.into_iter().collect();
+    assert_eq!(db.range(b"", None::<&[u8]>), all);
+}
+
+// The value in the overflow pages is compressed but the oversized key,
+// which does not compress, is stored as it is.
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_store_large_key_incompressible_value_compressible() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut key: Vec<u8> = vec![0u8; 8192];
+    rng().fill_bytes(&mut key);
+    let value: Vec<u8> = vec![5u8; 18192];
+    {
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::LZ4,
+        );
+        db.put(&key, &value);
+    }
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::LZ4,
+    );
+    assert_eq!(db.get(&key), Some(value.clone()));
+    assert_eq!(db.range(b"", None::<&[u8]>), vec![(key.clone(), value)]);
+    db.verify().expect("DB should verify");
+    assert!(db.delete(&key));
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
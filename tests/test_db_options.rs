@@ -0,0 +1,561 @@
+use digby::compressor::CompressorType;
+use digby::page::PageType;
+use digby::page_no::PageNo;
+use digby::{BatchOp, BlockSanity, Db, DbOptions, DigbyError, SyncMode};
+use tempfile::NamedTempFile;
+
+#[test]
+fn test_db_options_defaults() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = DbOptions::new().path(path).open();
+        assert!(!db.is_read_only());
+        db.put(b"key", b"value");
+    }
+    // The same as Db::new with no key and no compression.
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    assert_eq!(
+        std::fs::metadata(path).unwrap().len() % Db::BLOCK_SIZE as u64,
+        0
+    );
+}
+
+#[test]
+fn test_db_options() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = DbOptions::new()
+            .path(path)
+            .block_size(1024)
+            .sync_mode(SyncMode::Full)
+            .open();
+        for i in 0..200u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+    }
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, 1024);
+    for i in 0..200u32 {
+        assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+    }
+}
+
+// A CRC32C checksummed DB is stored with the CRC32C of each page in the
+// last 4 bytes of its block, and has to be opened as one.
+#[test]
+fn test_db_options_crc32c() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let options = DbOptions::new()
+        .path(path)
+        .block_sanity(BlockSanity::Crc32c);
+    options.clone().open().put(b"key", b"value");
+    let bytes = std::fs::read(path).expect("Failed to read file");
+    for block in bytes.chunks(Db::BLOCK_SIZE) {
+        let stored = u32::from_le_bytes(block[Db::BLOCK_SIZE - 4..].try_into().unwrap());
+        assert_eq!(stored, crc32c::crc32c(&block[..Db::BLOCK_SIZE - 4]));
+    }
+    let mut db = options.open();
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    assert_eq!(db.verify(), Ok(()));
+    drop(db);
+
+    // The checksums do not match as xxhash_32.
+    assert!(matches!(
+        Db::try_new(path, None, CompressorType::None).err(),
+        Some(DigbyError::ChecksumMismatch(_))
+    ));
+    let mut db = Db::open_auto(path, None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_options_compressor() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = DbOptions::new()
+            .path(path)
+            .compressor(CompressorType::LZ4)
+            .open();
+        db.put(b"key", vec![0u8; 10000]);
+    }
+    let mut db = Db::new(path, None, CompressorType::LZ4);
+    assert_eq!(db.get(b"key"), Some(vec![0u8; 10000]));
+    drop(db);
+    assert!(matches!(
+        DbOptions::new().path(path).try_open().err(),
+        Some(DigbyError::CompressorMismatch(_))
+    ));
+}
+
+#[test]
+#[cfg(feature = "encryption")]
+fn test_db_options_encryption_key() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let key = [3u8; 16];
+    {
+        let mut db = DbOptions::new().path(path).encryption_key(&key).open();
+        db.put(b"key", b"value");
+    }
+    let mut db = Db::new(path, Some(key.to_vec()), CompressorType::None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    drop(db);
+    assert!(matches!(
+        DbOptions::new()
+            .path(path)
+            .encryption_key(&[4u8; 16])
+            .try_open()
+            .err(),
+        Some(DigbyError::EncryptionMismatch(_))
+    ));
+}
+
+#[test]
+fn test_db_options_read_only() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        for i in 0..100u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+    }
+    let before = std::fs::read(path).unwrap();
+
+    let mut db = DbOptions::new().path(path).read_only(true).open();
+    assert!(db.is_read_only());
+    assert_eq!(
+        db.get(7u32.to_be_bytes()),
+        Some(7u32.to_le_bytes().to_vec())
+    );
+    assert_eq!(db.range(b"", None::<&[u8]>).len(), 100);
+    assert_eq!(db.verify(), Ok(()));
+
+    // Changes are refused and leave the DB as it was.
+    let error = db
+        .write_batch(vec![
+            BatchOp::Put(b"new".to_vec(), vec![1u8; 20000]),
+            BatchOp::Delete(7u32.to_be_bytes().to_vec()),
+        ])
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(matches!(
+        db.try_put(b"new", b"value"),
+        Err(DigbyError::Io(error)) if error.kind() == std::io::ErrorKind::PermissionDenied
+    ));
+    assert!(db.sync_masters().is_err());
+    assert!(db.compact_in_place().is_err());
+    assert_eq!(db.get(b"new"), None);
+    assert_eq!(
+        db.get(7u32.to_be_bytes()),
+        Some(7u32.to_le_bytes().to_vec())
+    );
+    assert_eq!(db.verify(), Ok(()));
+    drop(db);
+    assert_eq!(std::fs::read(path).unwrap(), before);
+}
+
+#[test]
+fn test_db_options_read_only_not_a_db() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    // An empty file would be made into a new DB.
+    assert!(matches!(
+        DbOptions::new().path(path).read_only(true).try_open().err(),
+        Some(DigbyError::Io(error)) if error.kind() == std::io::ErrorKind::InvalidInput
+    ));
+    assert_eq!(std::fs::metadata(path).unwrap().len(), 0);
+    let missing = format!("{}.missing", path);
+    assert!(matches!(
+        DbOptions::new().path(&missing).read_only(true).try_open().err(),
+        Some(DigbyError::Io(error)) if error.kind() == std::io::ErrorKind::NotFound
+    ));
+    assert!(!std::path::Path::new(&missing).exists());
+}
+
+#[test]
+#[should_panic(expected = "DbOptions needs a path.")]
+fn test_db_options_no_path() {
+    DbOptions::new().open();
+}
+
+// The application id and user version are kept in the file, a DB from
+// another application is refused when the id is required.
+#[test]
+fn test_db_options_application_id() {
+    const APPLICATION_ID: u32 = 0x6d79_6170;
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = DbOptions::new()
+            .path(path)
+            .application_id(APPLICATION_ID)
+            .user_version(3)
+            .require_application_id(true)
+            .open();
+        assert_eq!(db.application_id(), APPLICATION_ID);
+        assert_eq!(db.user_version(), 3);
+        db.put(b"key", b"value");
+    }
+    // Only used on create, the file keeps the first ones.
+    let mut db = DbOptions::new()
+        .path(path)
+        .application_id(APPLICATION_ID)
+        .user_version(4)
+        .require_application_id(true)
+        .open();
+    assert_eq!(db.user_version(), 3);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    drop(db);
+    let db = DbOptions::new().path(path).read_only(true).open();
+    assert_eq!(db.application_id(), APPLICATION_ID);
+    drop(db);
+
+    Db::compact_file(path, None, CompressorType::None).expect("Failed to compact");
+    let db = Db::open_auto(path, None);
+    assert_eq!(db.application_id(), APPLICATION_ID);
+    assert_eq!(db.user_version(), 3);
+    drop(db);
+
+    for read_only in [false, true] {
+        assert!(matches!(
+            DbOptions::new()
+                .path(path)
+                .read_only(read_only)
+                .application_id(APPLICATION_ID + 1)
+                .require_application_id(true)
+                .try_open(),
+            Err(DigbyError::ApplicationIdMismatch(APPLICATION_ID, expected))
+                if expected == APPLICATION_ID + 1
+        ));
+    }
+    // Not checked unless asked for.
+    assert!(
+        DbOptions::new()
+            .path(path)
+            .application_id(APPLICATION_ID + 1)
+            .try_open()
+            .is_ok()
+    );
+
+    // A DB created without one has 0.
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    drop(Db::new(path, None, CompressorType::None));
+    let db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.application_id(), 0);
+    assert_eq!(db.user_version(), 0);
+    drop(db);
+    assert!(matches!(
+        DbOptions::new()
+            .path(path)
+            .application_id(APPLICATION_ID)
+            .require_application_id(true)
+            .try_open(),
+        Err(DigbyError::ApplicationIdMismatch(0, APPLICATION_ID))
+    ));
+}
+
+#[test]
+fn test_db_options_overflow_threshold() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let inline_value = vec![1u8; 1999];
+    let overflow_value = vec![2u8; 2000];
+    let blocks = |path: &str| std::fs::metadata(path).unwrap().len() / Db::BLOCK_SIZE as u64;
+    {
+        let mut db = DbOptions::new().path(path).overflow_threshold(2000).open();
+        assert_eq!(db.get_overflow_threshold(), 2000);
+        // Grow the file first so the puts below take free pages.
+        db.put(b"blob", vec![0u8; 100 * Db::BLOCK_SIZE]);
+        assert!(db.delete(b"blob"));
+        let free_page_count = db.free_page_count();
+        // A commit frees as many pages as it takes unless there is an
+        // overflow page as well.
+        db.put(b"inline", &inline_value);
+        assert_eq!(db.free_page_count(), free_page_count);
+        let free_page_count = db.free_page_count();
+        db.put(b"overflow", &overflow_value);
+        assert_eq!(db.free_page_count(), free_page_count - 1);
+    }
+    let size = blocks(path);
+    // The threshold is kept in the file, a different one is ignored.
+    let mut db = DbOptions::new().path(path).overflow_threshold(100).open();
+    assert_eq!(db.get_overflow_threshold(), 2000);
+    assert_eq!(db.get(b"inline"), Some(inline_value));
+    assert_eq!(db.get(b"overflow"), Some(overflow_value));
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(blocks(path), size);
+    drop(db);
+    let db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.get_overflow_threshold(), 2000);
+
+    // Default is the largest value that fits in a leaf page.
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let db = DbOptions::new().path(path).block_size(1024).open();
+    assert_eq!(db.get_overflow_threshold(), 977);
+}
+
+#[test]
+fn test_db_options_overflow_threshold_too_large() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let error = DbOptions::new()
+        .path(path)
+        .overflow_threshold(Db::BLOCK_SIZE)
+        .try_open()
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, DigbyError::Io(ref error) if error.kind() == std::io::ErrorKind::InvalidInput)
+    );
+}
+
+// A new DB file is made with the initial pages, all but the fixed pages
+// free. With more than one free page directory page holds they go in a
+// list of them.
+#[test]
+fn test_db_options_initial_pages() {
+    for (block_size, initial_pages) in [(Db::BLOCK_SIZE, 6), (Db::BLOCK_SIZE, 2000), (128, 200)] {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let options = DbOptions::new()
+            .path(path)
+            .block_size(block_size)
+            .initial_pages(initial_pages);
+        let mut db = options.clone().open();
+        let file_size = (block_size as u64) * initial_pages;
+        assert_eq!(std::fs::metadata(path).unwrap().len(), file_size);
+        let free_page_count = db.free_page_count();
+        assert!(free_page_count <= initial_pages - Db::FIXED_PAGES);
+        // Less the extra free page directory pages.
+        assert!(free_page_count * 10 >= (initial_pages - Db::FIXED_PAGES) * 9);
+        assert_eq!(db.verify(), Ok(()));
+        db.put(b"key", b"value");
+        drop(db);
+
+        // Only used when the DB is created.
+        let mut db = options.initial_pages(100_000).open();
+        assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+        for i in 0..500u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        assert_eq!(db.verify(), Ok(()));
+        if initial_pages == 2000 {
+            assert_eq!(std::fs::metadata(path).unwrap().len(), file_size);
+        }
+    }
+}
+
+// When the free pages run out the file grows by free_page_growth pages.
+#[test]
+fn test_db_options_free_page_growth() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = DbOptions::new().path(path).free_page_growth(256).open();
+    let initial_size = std::fs::metadata(path).unwrap().len();
+    for i in 0..2000u32 {
+        db.put(i.to_be_bytes(), [1u8; 100]);
+    }
+    let blocks_added =
+        (std::fs::metadata(path).unwrap().len() - initial_size) / Db::BLOCK_SIZE as u64;
+    assert!(blocks_added >= 256);
+    assert!(blocks_added < 2 * 256 + 10);
+    assert_eq!(db.verify(), Ok(()));
+}
+
+// Fill a DB and break the last byte of each of its leaf pages, the
+// checksum of a checksummed block or the tag of an encrypted one.
+fn create_broken_db(path: &str, key: Option<&[u8]>) {
+    let mut options = DbOptions::new().path(path);
+    if let Some(key) = key {
+        options = options.encryption_key(key);
+    }
+    let mut db = options.open();
+    for i in 0..2000u32 {
+        db.put(i.to_be_bytes(), [1u8; 100]);
+    }
+    let blocks = std::fs::metadata(path).unwrap().len() / Db::BLOCK_SIZE as u64;
+    let leaf_blocks: Vec<u64> = (Db::FIXED_PAGES..blocks)
+        .filter(|block| {
+            db.dump_page(PageNo::from_u64(*block)).page_type == Some(PageType::LeafPage)
+        })
+        .collect();
+    drop(db);
+    let mut bytes = std::fs::read(path).unwrap();
+    for block in leaf_blocks {
+        bytes[(block as usize + 1) * Db::BLOCK_SIZE - 1] ^= 0xff;
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+// With checking off pages with a bad checksum are read as they are.
+#[test]
+fn test_db_options_verify_checksums() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    create_broken_db(path, None);
+    {
+        let mut db = DbOptions::new().path(path).verify_checksums(false).open();
+        for i in 0..2000u32 {
+            assert_eq!(db.try_get(i.to_be_bytes()).unwrap(), Some(vec![1u8; 100]));
+        }
+    }
+    let mut db = DbOptions::new().path(path).open();
+    assert!((0..2000u32).any(|i| db.try_get(i.to_be_bytes()).is_err()));
+}
+
+// An encrypted DB still finds bad blocks as they fail to decrypt.
+#[test]
+#[cfg(feature = "encryption")]
+fn test_db_options_verify_checksums_encrypted() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let key = b"0123456789abcdef";
+    create_broken_db(path, Some(key));
+    let mut db = DbOptions::new()
+        .path(path)
+        .encryption_key(key)
+        .verify_checksums(false)
+        .open();
+    assert!((0..2000u32).any(|i| db.try_get(i.to_be_bytes()).is_err()));
+}
+
+#[test]
+fn test_db_options_initial_pages_too_few() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let error = DbOptions::new()
+        .path(path)
+        .initial_pages(Db::FIXED_PAGES - 1)
+        .try_open()
+        .err()
+        .unwrap();
+    assert!(
+        matches!(error, DigbyError::Io(ref error) if error.kind() == std::io::ErrorKind::InvalidInput)
+    );
+}
+
+// The file is read through a memory map that is mapped again as the
+// file grows and is cut down.
+#[test]
+#[cfg(feature = "mmap")]
+fn test_db_options_mmap() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = DbOptions::new().path(path).mmap(true).open();
+        for i in 0..2000u32 {
+            db.put(i.to_be_bytes(), [i as u8; 100]);
+        }
+        db.put(b"large", [7u8; 20000]);
+        db.set_page_cache_size(1);
+        assert_eq!(db.get(1000u32.to_be_bytes()), Some(vec![232u8; 100]));
+        for i in 0..2000u32 {
+            db.delete(i.to_be_bytes());
+        }
+        db.compact_in_place().expect("Failed to compact");
+        db.verify().expect("DB should verify");
+        assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+    }
+
+    let mut db = DbOptions::new()
+        .path(path)
+        .read_only(true)
+        .mmap(true)
+        .open();
+    assert!(db.is_read_only());
+    assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+    assert_eq!(db.len(), 1);
+}
+
+// With a reverse comparator the keys are kept largest first, every read
+// in key order returns them that way.
+#[test]
+fn test_db_options_comparator() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let open = || {
+        DbOptions::new()
+            .path(path)
+            .block_size(1024)
+            .comparator(|a, b| b.cmp(a))
+            .open()
+    };
+    let descending: Vec<(Vec<u8>, Vec<u8>)> = (0..2000u32)
+        .rev()
+        .map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+        .collect();
+    {
+        let mut db = open();
+        for i in 0..2000u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+            db.put_table_entry(b"table", i.to_be_bytes(), i.to_le_bytes());
+        }
+        db.create_table(b"empty");
+        db.verify().expect("DB should verify");
+    }
+
+    let mut db = open();
+    db.verify().expect("DB should verify");
+    assert_eq!(db.range(b"", None::<&[u8]>), descending);
+    assert_eq!(db.cursor().collect::<Vec<_>>(), descending);
+    assert_eq!(db.table_iter(b"table").collect::<Vec<_>>(), descending);
+    assert_eq!(db.list_tables(), vec![b"table".to_vec(), b"empty".to_vec()]);
+    // A range goes from its start down to its end.
+    assert_eq!(
+        db.range(1500u32.to_be_bytes(), Some(&1000u32.to_be_bytes())),
+        descending[499..999]
+    );
+    let mut rev = db.range_rev(b"", None::<&[u8]>);
+    rev.reverse();
+    assert_eq!(rev, descending);
+    let mut cursor = db.cursor();
+    cursor.seek(&100u32.to_be_bytes());
+    assert_eq!(cursor.key(), Some(100u32.to_be_bytes().as_slice()));
+    assert_eq!(cursor.nth(1), Some(descending[1900].clone()));
+    drop(cursor);
+    assert_eq!(
+        db.scan_prefix([0, 0, 1]).collect::<Vec<_>>(),
+        descending[1488..1744]
+    );
+    assert_eq!(
+        db.multi_get(&[vec![0, 0, 0, 5], b"none".to_vec(), vec![0, 0, 7, 0]]),
+        vec![Some(vec![5, 0, 0, 0]), None, Some(vec![0, 7, 0, 0])]
+    );
+
+    for i in (0..2000u32).filter(|i| i % 3 != 0) {
+        assert!(db.delete(i.to_be_bytes()));
+    }
+    assert_eq!(db.delete_prefix(b"table", [0, 0, 1]), 256);
+    db.compact_in_place().expect("Failed to compact");
+    db.verify().expect("DB should verify");
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = descending
+        .into_iter()
+        .filter(|(key, _)| u32::from_be_bytes(key[..].try_into().unwrap()) % 3 == 0)
+        .collect();
+    assert_eq!(db.range(b"", None::<&[u8]>), expected);
+    assert_eq!(db.get(3u32.to_be_bytes()), Some(vec![3, 0, 0, 0]));
+    assert_eq!(db.get(4u32.to_be_bytes()), None);
+    assert_eq!(db.table_len(b"table"), 1744);
+
+    // An oversized key is ordered on its short key.
+    let long_key = vec![9u8; 3000];
+    db.put(&long_key, b"long");
+    db.verify().expect("DB should verify");
+    assert_eq!(db.get(&long_key), Some(b"long".to_vec()));
+    assert_eq!(
+        db.range(b"", None::<&[u8]>)[0],
+        (long_key.clone(), b"long".to_vec())
+    );
+    assert_eq!(
+        db.scan_prefix(&long_key).collect::<Vec<_>>(),
+        vec![(long_key, b"long".to_vec())]
+    );
+}
@@ -0,0 +1,129 @@
+use digby::compressor::CompressorType;
+use digby::{Db, DigbyError};
+use rand::RngCore;
+use tempfile::NamedTempFile;
+
+// Fill a DB with small and large values, oversized keys and tables,
+// one of them empty and one that is not compressed.
+fn fill_db(db: &mut Db) {
+    let mut rng = rand::rng();
+    for i in 0..2000u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    let mut large_value = vec![0u8; 50000];
+    rng.fill_bytes(&mut large_value);
+    db.put(b"large", &large_value);
+    db.put(vec![9u8; 3000], vec![5u8; 20000]);
+    db.put(b"empty", b"");
+    for i in 0..500u32 {
+        db.put_table_entry(b"table", i.to_be_bytes(), [3u8; 40]);
+    }
+    db.put_table_entry(b"table", b"large", vec![4u8; 30000]);
+    db.create_table(b"empty_table");
+    db.create_table_with_compressor(b"raw_table", CompressorType::None);
+    db.put_table_entry(b"raw_table", b"key", vec![6u8; 10000]);
+}
+
+fn check_restored(source: &mut Db, dest: &mut Db) {
+    assert_eq!(dest.verify(), Ok(()));
+    assert_eq!(dest.list_tables(), source.list_tables());
+    assert!(dest.iter_all().eq(source.iter_all()));
+    assert_eq!(
+        dest.table_compressor_type(b"raw_table"),
+        Some(CompressorType::None)
+    );
+}
+
+// A DB with small pages restored into one with the default block size.
+#[test]
+fn test_db_dump_restore() {
+    let source_file = NamedTempFile::new().expect("Failed to create temp file");
+    let dest_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut source = Db::new_with_page_size(
+        source_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+        1024,
+    );
+    fill_db(&mut source);
+    let mut dump: Vec<u8> = Vec::new();
+    source.dump(&mut dump).expect("Failed to dump");
+
+    let mut dest = Db::new(
+        dest_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    let entry_count = Db::restore(dump.as_slice(), &mut dest).expect("Failed to restore");
+    assert_eq!(entry_count, 2003 + 501 + 1);
+    check_restored(&mut source, &mut dest);
+}
+
+#[test]
+#[cfg(all(feature = "lz4", feature = "zstd", feature = "encryption"))]
+fn test_db_dump_restore_lz4_to_zstd() {
+    let source_file = NamedTempFile::new().expect("Failed to create temp file");
+    let dest_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut source = Db::new(
+        source_file.path().to_str().unwrap(),
+        None,
+        CompressorType::LZ4,
+    );
+    fill_db(&mut source);
+    let mut dump: Vec<u8> = Vec::new();
+    source.dump(&mut dump).expect("Failed to dump");
+
+    let mut dest = Db::new(
+        dest_file.path().to_str().unwrap(),
+        Some(vec![7u8; 16]),
+        CompressorType::Zstd,
+    );
+    Db::restore(dump.as_slice(), &mut dest).expect("Failed to restore");
+    check_restored(&mut source, &mut dest);
+    assert_eq!(
+        dest.table_compressor_type(b"table"),
+        Some(CompressorType::Zstd)
+    );
+}
+
+#[test]
+fn test_db_restore_damaged_dump() {
+    let source_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut source = Db::new(
+        source_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    for i in 0..10u32 {
+        source.put(i.to_be_bytes(), [1u8; 100]);
+    }
+    let mut dump: Vec<u8> = Vec::new();
+    source.dump(&mut dump).expect("Failed to dump");
+
+    let restore = |dump: &[u8]| {
+        let dest_file = NamedTempFile::new().expect("Failed to create temp file");
+        let mut dest = Db::new(
+            dest_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        Db::restore(dump, &mut dest)
+    };
+    assert_eq!(restore(&dump).unwrap(), 10);
+    // Cut short, and cut short at the end of a record.
+    assert!(matches!(
+        restore(&dump[..dump.len() - 50]),
+        Err(DigbyError::Io(_))
+    ));
+    assert!(matches!(
+        restore(&dump[..dump.len() - 9]),
+        Err(DigbyError::Io(_))
+    ));
+    // Not a dump.
+    assert!(matches!(restore(b"not a dump"), Err(DigbyError::Io(_))));
+    // The wrong entry count.
+    let mut bad_count = dump.clone();
+    let len = bad_count.len();
+    bad_count[len - 8] = 11;
+    assert!(matches!(restore(&bad_count), Err(DigbyError::Io(_))));
+}
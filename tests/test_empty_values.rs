@@ -0,0 +1,88 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use tempfile::NamedTempFile;
+
+// A stored empty value is Some(vec![]), only a key that is not there is
+// None. Checked before and after the DB is reopened.
+fn check_empty_values(compressor_type: CompressorType) {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    // Long enough to be stored as a short key with the tuple in overflow
+    // pages.
+    let oversized_key = vec![7u8; 3000];
+    {
+        let mut db = Db::new(path, None, compressor_type);
+        db.put(b"inline", b"");
+        db.put_raw(b"raw", b"");
+        db.put(&oversized_key, b"");
+        // Replace values that were compressed and in overflow pages
+        // with empty ones.
+        db.put(b"was_compressed", vec![1u8; 5000]);
+        db.put(b"was_compressed", b"");
+        db.put(b"was_overflow", vec![2u8; 20000]);
+        db.put(b"was_overflow", b"");
+        db.create_table(b"table");
+        db.put_table_entry(b"table", b"inline", b"");
+        db.put_table_entry(b"table", &oversized_key, b"");
+
+        assert_eq!(db.get(b"inline"), Some(vec![]));
+        assert_eq!(db.get(b"raw"), Some(vec![]));
+        assert_eq!(db.get(&oversized_key), Some(vec![]));
+        assert_eq!(db.get(b"was_compressed"), Some(vec![]));
+        assert_eq!(db.get(b"was_overflow"), Some(vec![]));
+        assert_eq!(db.get(b"missing"), None);
+        assert_eq!(db.get([7u8; 3001]), None);
+    }
+    let mut db = Db::new(path, None, compressor_type);
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(db.get(b"inline"), Some(vec![]));
+    assert_eq!(db.get(b"raw"), Some(vec![]));
+    assert_eq!(db.get(&oversized_key), Some(vec![]));
+    assert_eq!(db.get(b"was_compressed"), Some(vec![]));
+    assert_eq!(db.get(b"was_overflow"), Some(vec![]));
+    assert_eq!(db.get(b"missing"), None);
+    assert_eq!(db.get([7u8; 3001]), None);
+    assert_eq!(db.get_table_entry(b"table", b"inline"), Some(vec![]));
+    assert_eq!(db.get_table_entry(b"table", &oversized_key), Some(vec![]));
+    assert_eq!(db.get_table_entry(b"table", b"missing"), None);
+    assert_eq!(
+        db.range(b"", None::<&[u8]>),
+        vec![
+            (oversized_key.clone(), vec![]),
+            (b"inline".to_vec(), vec![]),
+            (b"raw".to_vec(), vec![]),
+            (b"was_compressed".to_vec(), vec![]),
+            (b"was_overflow".to_vec(), vec![]),
+        ]
+    );
+
+    // Deleting an empty value leaves the key absent.
+    assert!(db.delete(b"inline"));
+    assert!(db.delete(&oversized_key));
+    assert_eq!(db.get(b"inline"), None);
+    assert_eq!(db.get(&oversized_key), None);
+    assert!(!db.delete(b"inline"));
+}
+
+#[test]
+fn test_db_empty_values() {
+    check_empty_values(CompressorType::None);
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_empty_values_lz4() {
+    check_empty_values(CompressorType::LZ4);
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn test_db_empty_values_zstd() {
+    check_empty_values(CompressorType::Zstd);
+}
+
+#[test]
+#[cfg(feature = "snappy")]
+fn test_db_empty_values_snappy() {
+    check_empty_values(CompressorType::Snappy);
+}
@@ -0,0 +1,77 @@
+use base64::prelude::{BASE64_STANDARD, Engine};
+use digby::compressor::CompressorType;
+use digby::{Db, MemoryStorage};
+use tempfile::NamedTempFile;
+
+// Pull the string or null out of a line written by export_ndjson, the
+// fields are base64 so there is nothing escaped.
+fn field(line: &str, name: &str) -> Option<Vec<u8>> {
+    let start = line.find(&format!("\"{}\":", name)).unwrap() + name.len() + 3;
+    let rest = &line[start..];
+    if rest.starts_with("null") {
+        return None;
+    }
+    let rest = rest.strip_prefix('"').unwrap();
+    let end = rest.find('"').unwrap();
+    Some(BASE64_STANDARD.decode(&rest[..end]).unwrap())
+}
+
+#[test]
+fn test_db_export_ndjson() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::LZ4);
+    db.put(b"key", b"value");
+    db.put(b"large", vec![7u8; 20000]);
+    db.put(b"empty", b"");
+    db.put_table_entry(b"table", [0u8, 255, 34, 92], b"\"quoted\"\n");
+    db.create_table(b"empty_table");
+
+    let mut export = Vec::new();
+    db.export_ndjson(&mut export).expect("Failed to export");
+    let export = String::from_utf8(export).unwrap();
+    let lines: Vec<&str> = export.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], r#"{"table":null,"key":"ZW1wdHk=","value":""}"#);
+    assert_eq!(
+        lines[1],
+        r#"{"table":null,"key":"a2V5","value":"dmFsdWU="}"#
+    );
+
+    let entries: Vec<_> = lines
+        .iter()
+        .map(|line| {
+            (
+                field(line, "table"),
+                field(line, "key").unwrap(),
+                field(line, "value").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(entries[2], (None, b"large".to_vec(), vec![7u8; 20000]));
+    assert_eq!(
+        entries[3],
+        (
+            Some(b"table".to_vec()),
+            vec![0u8, 255, 34, 92],
+            b"\"quoted\"\n".to_vec()
+        )
+    );
+
+    // Nothing uncommitted and nothing in an empty DB.
+    let mut tx_ctx = db.new_transaction();
+    db.put_txn(b"uncommitted", b"value", &mut tx_ctx);
+    let mut again = Vec::new();
+    db.export_ndjson(&mut again).unwrap();
+    assert_eq!(String::from_utf8(again).unwrap(), export);
+
+    let mut empty = Db::new_with_storage(
+        Box::new(MemoryStorage::new()),
+        None,
+        CompressorType::None,
+        4096,
+    );
+    let mut export = Vec::new();
+    empty.export_ndjson(&mut export).unwrap();
+    assert!(export.is_empty());
+}
@@ -0,0 +1,59 @@
+use digby::compressor::CompressorType;
+use digby::{Db, DbOptions, DigbyError};
+use tempfile::NamedTempFile;
+
+#[test]
+fn test_db_second_writer_locked() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"key", b"value");
+    assert!(matches!(
+        Db::try_new(path, None, CompressorType::None),
+        Err(DigbyError::Locked)
+    ));
+    assert!(matches!(
+        DbOptions::new().path(path).try_open(),
+        Err(DigbyError::Locked)
+    ));
+
+    // The lock goes with the Db.
+    drop(db);
+    let mut db = Db::try_new(path, None, CompressorType::None).expect("Failed to open");
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
+
+#[test]
+#[should_panic(expected = "Db file is locked by another Db")]
+fn test_db_second_writer_panics() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let _db = Db::new(path, None, CompressorType::None);
+    Db::new(path, None, CompressorType::None);
+}
+
+#[test]
+fn test_db_read_only_lock() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    Db::new(path, None, CompressorType::None).put(b"key", b"value");
+
+    // Readers share the file, a writer cannot open it while they have
+    // it.
+    let mut read_only_db1 = Db::open_read_only(path, None, CompressorType::None);
+    let mut read_only_db2 = Db::open_read_only(path, None, CompressorType::None);
+    assert!(matches!(
+        Db::try_new(path, None, CompressorType::None),
+        Err(DigbyError::Locked)
+    ));
+    assert_eq!(read_only_db1.get(b"key"), Some(b"value".to_vec()));
+    assert_eq!(read_only_db2.get(b"key"), Some(b"value".to_vec()));
+    drop(read_only_db1);
+    drop(read_only_db2);
+
+    // A reader can open the file a writer has, and sees its commits.
+    let mut db = Db::new(path, None, CompressorType::None);
+    let mut read_only_db = Db::open_read_only(path, None, CompressorType::None);
+    db.put(b"key", b"new");
+    assert_eq!(read_only_db.get(b"key"), Some(b"new".to_vec()));
+}
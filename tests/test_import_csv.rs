@@ -0,0 +1,111 @@
+use digby::compressor::CompressorType;
+use digby::{CsvEncoding, Db, DigbyError, MemoryStorage};
+
+fn new_db() -> Db {
+    Db::new_with_storage(
+        Box::new(MemoryStorage::new()),
+        None,
+        CompressorType::LZ4,
+        4096,
+    )
+}
+
+fn csv(rows: impl Iterator<Item = u32>) -> String {
+    rows.map(|i| format!("key{:05},\"value, {}\"\n", i, i))
+        .collect()
+}
+
+fn check_rows(db: &mut Db, rows: impl Iterator<Item = u32>) {
+    assert_eq!(db.verify(), Ok(()));
+    for i in rows {
+        assert_eq!(
+            db.get(format!("key{:05}", i)),
+            Some(format!("value, {}", i).into_bytes())
+        );
+    }
+}
+
+// Sorted rows into an empty DB are bulk loaded in a single commit.
+#[test]
+fn test_db_import_csv_sorted() {
+    let mut db = new_db();
+    let version = db.version();
+    let rows = db.import_csv(csv(0..3000).as_bytes(), 0, 1).unwrap();
+    assert_eq!(rows, 3000);
+    assert_eq!(db.version(), version + 1);
+    check_rows(&mut db, 0..3000);
+    assert_eq!(db.iter_all().count(), 3000);
+}
+
+// Once a row is out of order the rest are put in batches, a later row
+// replaces an earlier one with the same key.
+#[test]
+fn test_db_import_csv_unsorted() {
+    let mut db = new_db();
+    let mut input = csv(0..1500);
+    input.push_str(&csv((1500..3000).rev()));
+    input.push_str("key00007,replaced\n");
+    assert_eq!(db.import_csv(input.as_bytes(), 0, 1).unwrap(), 3001);
+    check_rows(&mut db, (0..3000).filter(|i| *i != 7));
+    assert_eq!(db.get(b"key00007"), Some(b"replaced".to_vec()));
+    assert_eq!(db.iter_all().count(), 3000);
+
+    // The DB has keys so there is no bulk load.
+    let version = db.version();
+    assert_eq!(
+        db.import_csv(csv(3000..5500).as_bytes(), 0, 1).unwrap(),
+        2500
+    );
+    assert_eq!(db.version(), version + 3);
+    check_rows(&mut db, 3000..5500);
+}
+
+#[test]
+fn test_db_import_csv_encoded() {
+    let mut db = new_db();
+    let input = "0aff,x,a2V5\n\"00\",y,\"\"\n";
+    assert_eq!(
+        db.import_csv_with(input.as_bytes(), 2, 1, CsvEncoding::Base64)
+            .map_err(|error| error.to_string()),
+        Err("Malformed CSV row at line 1".to_string())
+    );
+    let mut db = new_db();
+    assert_eq!(
+        db.import_csv_with(input.as_bytes(), 0, 1, CsvEncoding::Hex)
+            .map_err(|error| error.to_string()),
+        Err("Malformed CSV row at line 1".to_string())
+    );
+    let input = "0aff,eA==,a2V5\n\"00\",eQ==,\"\"\n";
+    assert_eq!(
+        db.import_csv_with(input.as_bytes(), 2, 1, CsvEncoding::Base64)
+            .unwrap(),
+        2
+    );
+    assert_eq!(db.get(b"key"), Some(b"x".to_vec()));
+    assert_eq!(db.get(b""), Some(b"y".to_vec()));
+}
+
+// A malformed row in the bulk load commits nothing, one after it keeps
+// the batches committed before it.
+#[test]
+fn test_db_import_csv_malformed() {
+    let mut db = new_db();
+    let mut input = csv(0..100);
+    input.push_str("key00100\n");
+    assert!(matches!(
+        db.import_csv(input.as_bytes(), 0, 1),
+        Err(DigbyError::Import { line: 101 })
+    ));
+    assert_eq!(db.iter_all().count(), 0);
+    assert_eq!(db.verify(), Ok(()));
+
+    let mut input = csv((0..2500).rev());
+    input.push_str("key09999,\"unclosed\n");
+    assert!(matches!(
+        db.import_csv(input.as_bytes(), 0, 1),
+        Err(DigbyError::Import { line: 2501 })
+    ));
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(db.iter_all().count(), 2000);
+    check_rows(&mut db, 500..2500);
+}
@@ -0,0 +1,54 @@
+use digby::compressor::CompressorType;
+use digby::{Db, MemoryStorage};
+
+fn open_db(storage: &MemoryStorage) -> Db {
+    Db::new_with_storage(
+        Box::new(storage.clone()),
+        None,
+        CompressorType::None,
+        Db::BLOCK_SIZE,
+    )
+}
+
+// The whole Db runs on a buffer in memory, a clone of the storage opens
+// it again as reopening the file would.
+#[test]
+fn test_memory_storage_db() {
+    let storage = MemoryStorage::new();
+    {
+        let mut db = open_db(&storage);
+        for i in 0..1000u32 {
+            db.put(i.to_be_bytes(), [i as u8; 100]);
+        }
+        db.put(b"large", [7u8; 20000]);
+        db.put_table_entry(b"table", b"key", b"value");
+    }
+    assert_eq!(storage.to_vec().len() % Db::BLOCK_SIZE, 0);
+
+    let mut db = open_db(&storage);
+    db.verify().expect("DB should verify");
+    assert_eq!(db.get(500u32.to_be_bytes()), Some(vec![244u8; 100]));
+    assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+    assert_eq!(
+        db.get_table_entry(b"table", b"key"),
+        Some(b"value".to_vec())
+    );
+
+    // Compacting in place cuts the buffer down.
+    let len = storage.to_vec().len();
+    for i in 0..1000u32 {
+        db.delete(i.to_be_bytes());
+    }
+    db.compact_in_place().expect("Failed to compact");
+    assert!(storage.to_vec().len() < len);
+    assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+    db.verify().expect("DB should verify");
+    drop(db);
+
+    // The bytes are a DB file.
+    let temp_file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    std::fs::write(path, storage.to_vec()).expect("Failed to write file");
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+}
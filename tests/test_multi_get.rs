@@ -0,0 +1,103 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use digby::observer::Observer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+
+// Count the disk reads of each block.
+struct ReadCounter {
+    reads: Arc<Mutex<HashMap<u64, u64>>>,
+}
+
+impl Observer for ReadCounter {
+    fn on_disk_read(&mut self, blk_offset: u64, _blk_cnt: u64) {
+        *self.reads.lock().unwrap().entry(blk_offset).or_insert(0) += 1;
+    }
+}
+
+// The values come back in the order of the keys asked for, whatever
+// the key order, with duplicates, missing keys, oversized keys and
+// values in overflow pages.
+#[test]
+fn test_multi_get() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    let mut tx_ctx = db.new_transaction();
+    for i in 0..3000u32 {
+        db.put_txn(
+            &i.to_be_bytes(),
+            format!("value_{}", i).as_bytes(),
+            &mut tx_ctx,
+        );
+    }
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+    let oversized_key = vec![3u8; 3000];
+    db.put(&oversized_key, b"oversized");
+    db.put(b"large", vec![5u8; 20000]);
+
+    let keys = vec![
+        2999u32.to_be_bytes().to_vec(),
+        b"large".to_vec(),
+        7u32.to_be_bytes().to_vec(),
+        b"missing".to_vec(),
+        oversized_key.clone(),
+        vec![3u8; 3001],
+        7u32.to_be_bytes().to_vec(),
+        1500u32.to_be_bytes().to_vec(),
+    ];
+    let expected: Vec<Option<Vec<u8>>> = keys.iter().map(|key| db.get(key)).collect();
+    assert_eq!(db.multi_get(&keys), expected);
+    assert_eq!(expected[0], Some(b"value_2999".to_vec()));
+    assert_eq!(expected[1], Some(vec![5u8; 20000]));
+    assert_eq!(expected[3], None);
+    assert_eq!(expected[4], Some(b"oversized".to_vec()));
+    assert_eq!(expected[5], None);
+    assert_eq!(db.multi_get(&[]), Vec::<Option<Vec<u8>>>::new());
+
+    let all: Vec<Vec<u8>> = (0..3000u32)
+        .rev()
+        .map(|i| i.to_be_bytes().to_vec())
+        .collect();
+    let values = db.multi_get(&all);
+    for (i, value) in (0..3000u32).rev().zip(values) {
+        assert_eq!(value, Some(format!("value_{}", i).into_bytes()));
+    }
+}
+
+// With a cache of one page each page on the way to the leaves is still
+// read once, where a get per key would read the dir pages every time.
+#[test]
+fn test_multi_get_reads_pages_once() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    let mut tx_ctx = db.new_transaction();
+    for i in 0..3000u32 {
+        db.put_txn(&i.to_be_bytes(), &[1u8; 100], &mut tx_ctx);
+    }
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+    drop(db);
+
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.set_page_cache_size(1);
+    let reads = Arc::new(Mutex::new(HashMap::new()));
+    db.set_observer(Box::new(ReadCounter {
+        reads: reads.clone(),
+    }));
+    let keys: Vec<Vec<u8>> = (0..3000u32)
+        .rev()
+        .map(|i| i.to_be_bytes().to_vec())
+        .collect();
+    assert!(
+        db.multi_get(&keys)
+            .iter()
+            .all(|value| value.as_deref() == Some(&[1u8; 100][..]))
+    );
+    let reads = reads.lock().unwrap();
+    assert!(reads.len() > 16);
+    for (blk_offset, count) in reads.iter() {
+        assert_eq!(*count, 1, "block {}", blk_offset);
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(feature = "lz4")]
+use digby::BlockSanity;
+use digby::Db;
+use digby::compressor::CompressorType;
+use tempfile::NamedTempFile;
+
+fn check_open_auto(block_size: usize, key: Option<Vec<u8>>) {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new_with_page_size(path, key.clone(), CompressorType::None, block_size);
+        for i in 0..200u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+        }
+        db.put_table_entry(b"table", b"key", b"value");
+    }
+    let mut db_file = std::fs::File::open(path).expect("Failed to open DB file");
+    assert_eq!(
+        Db::detect_block_size(&mut db_file, key.as_deref()),
+        Some(block_size)
+    );
+
+    // The caller does not give the block size.
+    let mut db = Db::open_auto(path, key);
+    assert_eq!(db.block_size(), block_size);
+    assert_eq!(db.verify(), Ok(()));
+    for i in 0..200u32 {
+        assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+    }
+    assert_eq!(
+        db.get_table_entry(b"table", b"key"),
+        Some(b"value".to_vec())
+    );
+    db.put(b"after", b"open_auto");
+    assert_eq!(db.get(b"after"), Some(b"open_auto".to_vec()));
+}
+
+#[test]
+fn test_db_open_auto_block_sizes() {
+    for block_size in [256, 512, 1024, 2048, 4096, 8192, 16384, 65536] {
+        check_open_auto(block_size, None);
+    }
+}
+
+#[test]
+#[cfg(feature = "encryption")]
+fn test_db_open_auto_block_sizes_encrypted() {
+    for block_size in [512, 4096, 16384] {
+        check_open_auto(block_size, Some(vec![9u8; 16]));
+    }
+}
+
+#[test]
+fn test_db_open_auto_new_file() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::open_auto(path, None);
+        db.put(b"key", b"value");
+    }
+    let mut db_file = std::fs::File::open(path).expect("Failed to open DB file");
+    assert_eq!(
+        Db::detect_block_size(&mut db_file, None),
+        Some(Db::BLOCK_SIZE)
+    );
+    let mut db = Db::open_auto(path, None);
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
+
+#[test]
+#[should_panic(expected = "Failed to find the block size of the DB")]
+fn test_db_open_auto_not_a_db() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    std::fs::write(temp_file.path(), vec![5u8; 8192]).expect("Failed to write file");
+    Db::open_auto(temp_file.path().to_str().unwrap(), None);
+}
+
+// The compressor comes from the file, opening with any other fails.
+#[cfg(feature = "lz4")]
+fn check_open_auto_compressor(compressor_type: CompressorType, block_sanity: BlockSanity) {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let key = block_sanity.is_encrypted().then(|| vec![3u8; 16]);
+    Db::try_new_with_cipher(path, key.clone(), compressor_type, 1024, block_sanity)
+        .expect("Failed to create DB")
+        .put(b"large", vec![7u8; 10000]);
+    assert!(
+        Db::try_new_with_cipher(path, key.clone(), CompressorType::None, 1024, block_sanity)
+            .is_err()
+    );
+
+    let mut db = Db::try_open_auto(path, key).expect("Failed to open DB");
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(db.get(b"large"), Some(vec![7u8; 10000]));
+    db.put(b"after", vec![8u8; 10000]);
+    assert_eq!(db.get(b"after"), Some(vec![8u8; 10000]));
+}
+
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_open_auto_compressor() {
+    check_open_auto_compressor(CompressorType::LZ4, BlockSanity::Crc32c);
+    #[cfg(feature = "encryption")]
+    check_open_auto_compressor(CompressorType::LZ4, BlockSanity::Aes128Gcm);
+    #[cfg(feature = "zstd")]
+    check_open_auto_compressor(CompressorType::Zstd, BlockSanity::XxH32Checksum);
+}
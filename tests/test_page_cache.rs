@@ -0,0 +1,92 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use digby::observer::Observer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::NamedTempFile;
+
+// Count the disk reads of each block.
+struct ReadCounter {
+    reads: Arc<Mutex<HashMap<u64, u64>>>,
+}
+
+impl Observer for ReadCounter {
+    fn on_disk_read(&mut self, blk_offset: u64, _blk_cnt: u64) {
+        *self.reads.lock().unwrap().entry(blk_offset).or_insert(0) += 1;
+    }
+}
+
+fn create_db(path: &str) {
+    let mut db = Db::new(path, None, CompressorType::None);
+    let mut tx_ctx = db.new_transaction();
+    for i in 0..3000u32 {
+        db.put_txn(&i.to_be_bytes(), &[1u8; 100], &mut tx_ctx);
+    }
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+}
+
+// The master pages and dir pages are used by every get so they stay in
+// a small cache while the leaf pages of a scan pass through it.
+#[test]
+fn test_db_page_cache_keeps_recently_used() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    create_db(path);
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.set_page_cache_size(16);
+    let reads = Arc::new(Mutex::new(HashMap::new()));
+    db.set_observer(Box::new(ReadCounter {
+        reads: reads.clone(),
+    }));
+    for i in 0..3000u32 {
+        assert_eq!(db.get(i.to_be_bytes()), Some(vec![1u8; 100]));
+        assert_eq!(db.get(0u32.to_be_bytes()), Some(vec![1u8; 100]));
+    }
+    let reads = reads.lock().unwrap();
+    assert!(reads.len() > 16);
+    for (blk_offset, count) in reads.iter() {
+        assert_eq!(*count, 1, "block {}", blk_offset);
+    }
+}
+
+// Commits flip the master pages and rewrite the free page directory
+// through the cache, a cache of one page still sees the latest of each.
+#[test]
+fn test_db_page_cache_size_one() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        db.set_page_cache_size(1);
+        for i in 0..500u32 {
+            db.put(i.to_be_bytes(), i.to_le_bytes());
+            db.put_table_entry(b"table", i.to_be_bytes(), i.to_le_bytes());
+        }
+        for i in (0..500u32).step_by(3) {
+            assert!(db.delete(i.to_be_bytes()));
+        }
+        assert_eq!(db.verify(), Ok(()));
+    }
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.set_page_cache_size(1);
+    for i in 0..500u32 {
+        let expected = (i % 3 != 0).then(|| i.to_le_bytes().to_vec());
+        assert_eq!(db.get(i.to_be_bytes()), expected);
+        assert_eq!(
+            db.get_table_entry(b"table", i.to_be_bytes()),
+            Some(i.to_le_bytes().to_vec())
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "Page cache size must be at least 1.")]
+fn test_db_page_cache_size_zero() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    db.set_page_cache_size(0);
+}
@@ -0,0 +1,72 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use tempfile::NamedTempFile;
+
+// Count the allocations of page buffers made by this thread, the tests
+// run on threads of their own so do not see each other's allocations.
+struct CountingAllocator;
+
+thread_local! {
+    static PAGE_ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() == Db::BLOCK_SIZE {
+            let _ = PAGE_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if layout.size() == Db::BLOCK_SIZE {
+            let _ = PAGE_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        }
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Page buffer allocations for a loop of puts and gets.
+fn page_allocations(page_pool_size: usize) -> u64 {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    db.set_page_pool_size(page_pool_size);
+    for i in 0..100u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    let start = PAGE_ALLOCATIONS.with(|count| count.get());
+    for round in 0..10u32 {
+        for i in 0..100u32 {
+            db.put(i.to_be_bytes(), (i + round).to_le_bytes());
+            assert_eq!(db.get(i.to_be_bytes()).unwrap(), (i + round).to_le_bytes());
+        }
+    }
+    PAGE_ALLOCATIONS.with(|count| count.get()) - start
+}
+
+#[test]
+fn test_page_pool_reduces_allocations() {
+    let without_pool = page_allocations(0);
+    let with_pool = page_allocations(64);
+    // Each put and get copies pages in and out of the page cache.
+    assert!(without_pool > 5000, "{}", without_pool);
+    assert!(
+        with_pool * 10 < without_pool,
+        "{} {}",
+        with_pool,
+        without_pool
+    );
+}
@@ -0,0 +1,93 @@
+use digby::Db;
+use digby::compressor::CompressorType;
+use tempfile::NamedTempFile;
+
+fn create_db(path: &str, count: u32) {
+    let mut db = Db::new(path, None, CompressorType::None);
+    for i in 0..count {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    db.put_table_entry(b"table", b"key", b"value");
+}
+
+#[test]
+fn test_db_read_only_never_writes() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    create_db(path, 500);
+    let before = std::fs::read(path).unwrap();
+    {
+        let mut db = Db::open_read_only(path, None, CompressorType::None);
+        assert!(db.is_read_only());
+        for i in 0..500u32 {
+            assert_eq!(db.get(i.to_be_bytes()), Some(i.to_le_bytes().to_vec()));
+        }
+        assert_eq!(
+            db.get_table_entry(b"table", b"key"),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(db.range(b"", None::<&[u8]>).len(), 500);
+        assert_eq!(db.verify(), Ok(()));
+        assert!(db.try_put(b"new", b"value").is_err());
+        assert!(db.try_delete(0u32.to_be_bytes()).is_err());
+        let mut tx_ctx = db.new_transaction();
+        db.create_table_txn(b"new_table", &mut tx_ctx);
+        db.put_table_entry_txn(b"table", b"key", &vec![1u8; 20000], &mut tx_ctx);
+        let error = db.commit(&mut tx_ctx).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(!db.table_exists(b"new_table"));
+        assert_eq!(
+            db.get(0u32.to_be_bytes()),
+            Some(0u32.to_le_bytes().to_vec())
+        );
+    }
+    assert_eq!(std::fs::read(path).unwrap(), before);
+}
+
+#[test]
+#[should_panic(expected = "Db is read only")]
+fn test_db_read_only_put() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    create_db(path, 10);
+    let mut db = Db::open_read_only(path, None, CompressorType::None);
+    db.put(b"new", b"value");
+}
+
+#[test]
+fn test_db_read_only_missing_file() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = format!("{}.missing", temp_file.path().to_str().unwrap());
+    assert!(Db::try_open_read_only(&path, None, CompressorType::None).is_err());
+    assert!(!std::path::Path::new(&path).exists());
+}
+
+// A read only Db sees the commits another Db makes to the same file.
+#[test]
+fn test_db_read_only_sees_commits() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    create_db(path, 100);
+    let mut writer = Db::new(path, None, CompressorType::None);
+    let mut reader = Db::open_read_only(path, None, CompressorType::None);
+    reader.set_read_ahead(8);
+    for round in 1..20u32 {
+        for i in 0..100u32 {
+            assert_eq!(
+                reader.get(i.to_be_bytes()),
+                Some((i + round - 1).to_le_bytes().to_vec())
+            );
+        }
+        // Rewrite every key and add more so the file grows and pages
+        // the reader has cached are reused.
+        let mut tx_ctx = writer.new_transaction();
+        for i in 0..100u32 {
+            writer.put_txn(&i.to_be_bytes(), &(i + round).to_le_bytes(), &mut tx_ctx);
+        }
+        let key = format!("round{}", round).into_bytes();
+        writer.put_txn(&key, &vec![round as u8; 10000], &mut tx_ctx);
+        writer.commit(&mut tx_ctx).expect("Failed to commit");
+        assert_eq!(reader.get(&key), Some(vec![round as u8; 10000]));
+    }
+    assert_eq!(reader.verify(), Ok(()));
+}
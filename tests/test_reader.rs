@@ -0,0 +1,119 @@
+use digby::compressor::CompressorType;
+use digby::{BatchOp, Db, Reader, SyncMode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tempfile::NamedTempFile;
+
+fn is_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_reader_reads_commits() {
+    is_send_sync::<Reader>();
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"key", b"old");
+    let reader = db.reader();
+    assert_eq!(reader.get(b"key"), Some(b"old".to_vec()));
+    assert_eq!(reader.get(b"missing"), None);
+
+    // A transaction is not seen until it commits.
+    let mut tx_ctx = db.new_transaction();
+    db.put_txn(b"key", b"new", &mut tx_ctx);
+    db.put_txn(b"large", &vec![1u8; 20000], &mut tx_ctx);
+    assert_eq!(reader.get(b"key"), Some(b"old".to_vec()));
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+    assert_eq!(reader.get(b"key"), Some(b"new".to_vec()));
+    assert_eq!(reader.get(b"large"), Some(vec![1u8; 20000]));
+    assert_eq!(reader.get_version(), db.snapshot().get_version());
+
+    // Clones share the Db's commits, and carry on after it is dropped.
+    let clone = reader.clone();
+    db.delete(b"key");
+    assert_eq!(clone.get(b"key"), None);
+    drop(db);
+    assert_eq!(reader.get(b"large"), Some(vec![1u8; 20000]));
+}
+
+// Readers on other threads see every commit in order while the Db keeps
+// committing, and never a value from a page that has been reused.
+#[test]
+fn test_reader_threads() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new_with_page_size(path, None, CompressorType::None, 1024);
+    db.set_sync_mode(SyncMode::None);
+    db.put(b"counter", 0u32.to_be_bytes());
+    db.put(b"key0", [0u8; 100]);
+    let reader = db.reader();
+    let done = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            let reader = reader.clone();
+            let done = &done;
+            scope.spawn(move || {
+                let mut last = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let value = reader.get(b"counter").unwrap();
+                    let counter = u32::from_be_bytes(value.try_into().unwrap());
+                    assert!(counter >= last);
+                    last = counter;
+                    // Every key up to the counter was in the same commit
+                    // or an earlier one.
+                    for i in [0, counter / 2, counter] {
+                        let key = format!("key{}", i).into_bytes();
+                        assert_eq!(reader.get(&key), Some(vec![i as u8; 100]));
+                    }
+                }
+            });
+        }
+        for i in 1..=500u32 {
+            db.write_batch(vec![
+                BatchOp::Put(format!("key{}", i).into_bytes(), vec![i as u8; 100]),
+                BatchOp::Put(b"counter".to_vec(), i.to_be_bytes().to_vec()),
+                BatchOp::Delete(format!("key{}", i / 3).into_bytes()),
+                BatchOp::Put(
+                    format!("key{}", i / 3).into_bytes(),
+                    vec![(i / 3) as u8; 100],
+                ),
+            ])
+            .expect("Failed to write batch");
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(reader.get(b"counter"), Some(500u32.to_be_bytes().to_vec()));
+}
+
+#[test]
+fn test_reader_of_read_only_db() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"key", b"old");
+    let mut read_only_db = Db::open_read_only(path, None, CompressorType::None);
+    let reader = read_only_db.reader();
+    assert_eq!(reader.get(b"key"), Some(b"old".to_vec()));
+
+    // The reader sees a commit by another Db once the read only Db has.
+    db.put(b"key", b"new");
+    assert_eq!(read_only_db.get(b"key"), Some(b"new".to_vec()));
+    assert_eq!(reader.get(b"key"), Some(b"new".to_vec()));
+}
+
+#[test]
+fn test_reader_needs_path() {
+    let file = tempfile::tempfile().expect("Failed to create temp file");
+    let db = Db::new_with_storage(Box::new(file), None, CompressorType::None, 4096);
+    assert!(db.try_reader().is_err());
+}
+
+#[test]
+#[cfg(feature = "encryption")]
+fn test_reader_encrypted() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let key = Some(b"0123456789abcdef".to_vec());
+    let mut db = Db::new(path, key, CompressorType::None);
+    db.put(b"key", b"value");
+    let reader = db.reader();
+    assert_eq!(reader.get(b"key"), Some(b"value".to_vec()));
+}
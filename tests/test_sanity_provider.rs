@@ -0,0 +1,138 @@
+use digby::nonce_mode::NonceMode;
+use digby::page::PageTrait;
+use digby::{BlockSanity, BlockSanityProvider, Db, DbOptions, DigbyError, Page};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+// A keyed FNV-1a hash of the page in the last 8 bytes of the block,
+// standing in for an HMAC.
+#[derive(Debug)]
+struct KeyedHashSanity {
+    id: u8,
+}
+
+impl KeyedHashSanity {
+    fn hash(page: &Page, key: &[u8]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in key.iter().chain(page.get_page_bytes()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+}
+
+impl BlockSanityProvider for KeyedHashSanity {
+    fn get_id(&self) -> u8 {
+        self.id
+    }
+
+    fn get_bytes_used(&self) -> usize {
+        8
+    }
+
+    fn set_block_sanity(&self, page: &mut Page, key: &[u8], _nonce_mode: NonceMode) {
+        let hash = KeyedHashSanity::hash(page, key);
+        let block = page.get_pg_ctr_bytes_mut();
+        let offset = block.len() - 8;
+        block[offset..].copy_from_slice(&hash.to_le_bytes());
+    }
+
+    fn check_block_sanity(&self, page: &mut Page, key: &[u8]) -> bool {
+        let block = page.get_pg_ctr_bytes();
+        let stored = u64::from_le_bytes(block[block.len() - 8..].try_into().unwrap());
+        stored == KeyedHashSanity::hash(page, key)
+    }
+}
+
+fn options(path: &str, id: u8, key: &[u8]) -> DbOptions {
+    DbOptions::new()
+        .path(path)
+        .encryption_key(key)
+        .sanity_provider(Arc::new(KeyedHashSanity { id }))
+}
+
+// A DB checked by a provider from outside the crate keeps its id in the
+// root page and opens again only with the same provider and key.
+#[test]
+fn test_sanity_provider() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = options(path, 200, b"secret").open();
+        for i in 0..2000u32 {
+            db.put(i.to_be_bytes(), [i as u8; 100]);
+        }
+        db.put(b"large", vec![7u8; 20000]);
+    }
+    {
+        let mut db = options(path, 200, b"secret").open();
+        assert_eq!(db.verify(), Ok(()));
+        assert_eq!(
+            db.get(1234u32.to_be_bytes()),
+            Some(vec![1234u32 as u8; 100])
+        );
+        assert_eq!(db.get(b"large"), Some(vec![7u8; 20000]));
+    }
+    {
+        let mut db = options(path, 200, b"secret").read_only(true).open();
+        assert_eq!(db.get(7u32.to_be_bytes()), Some(vec![7u8; 100]));
+    }
+
+    assert!(matches!(
+        options(path, 200, b"wrong").try_open(),
+        Err(DigbyError::ChecksumMismatch(_))
+    ));
+    assert!(matches!(
+        options(path, 201, b"secret").try_open(),
+        Err(DigbyError::SanityMismatch(
+            BlockSanity::Custom(200),
+            BlockSanity::Custom(201)
+        ))
+    ));
+    assert!(DbOptions::new().path(path).try_open().is_err());
+}
+
+#[test]
+fn test_sanity_provider_built_in() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = DbOptions::new()
+            .path(path)
+            .block_sanity(BlockSanity::Crc32c)
+            .open();
+        db.put(b"key", b"value");
+    }
+    // A crate block sanity is a provider too.
+    let storage = Box::new(
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap(),
+    );
+    let config = digby::db_config::DbConfig::builder()
+        .compressor_type(digby::compressor::CompressorType::None)
+        .build();
+    let mut db =
+        Db::try_new_with_sanity_provider(storage, None, config, BlockSanity::Crc32c.get_provider())
+            .unwrap();
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+}
+
+#[test]
+#[should_panic(expected = "A custom block sanity id must be from 128")]
+fn test_sanity_provider_id_reserved() {
+    DbOptions::new().sanity_provider(Arc::new(KeyedHashSanity { id: 5 }));
+}
+
+#[test]
+#[should_panic(expected = "A custom block sanity needs its provider")]
+fn test_sanity_provider_custom_without_provider() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    DbOptions::new()
+        .path(temp_file.path().to_str().unwrap())
+        .block_sanity(BlockSanity::Custom(200))
+        .open();
+}
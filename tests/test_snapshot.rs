@@ -0,0 +1,69 @@
+use digby::compressor::CompressorType;
+use digby::{BatchOp, Db, DigbyError};
+use tempfile::NamedTempFile;
+
+#[test]
+fn test_db_snapshot_reads_old_values() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"key", b"old");
+    db.put(b"deleted", b"value");
+    db.put(b"large", vec![1u8; 20000]);
+    let snapshot = db.snapshot();
+
+    db.write_batch(vec![
+        BatchOp::Put(b"key".to_vec(), b"new".to_vec()),
+        BatchOp::Delete(b"deleted".to_vec()),
+        BatchOp::Put(b"added".to_vec(), b"value".to_vec()),
+    ])
+    .expect("Failed to write batch");
+    assert_eq!(db.get(b"key"), Some(b"new".to_vec()));
+
+    // One commit after the snapshot nothing it reads can have been
+    // reused.
+    assert_eq!(snapshot.get(&mut db, b"key"), Some(b"old".to_vec()));
+    assert_eq!(snapshot.get(&mut db, b"deleted"), Some(b"value".to_vec()));
+    assert_eq!(snapshot.get(&mut db, b"added"), None);
+    assert_eq!(snapshot.get(&mut db, b"large"), Some(vec![1u8; 20000]));
+    assert!(snapshot.get_version() < db.snapshot().get_version());
+}
+
+// Once the pages of the snapshot are reused it expires rather than
+// returning what the pages hold now.
+#[test]
+fn test_db_snapshot_expires() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"key", b"0");
+    let snapshot = db.snapshot();
+    let mut expired = false;
+    for i in 1..10u32 {
+        db.put(b"key", i.to_string().as_bytes());
+        match snapshot.try_get(&mut db, b"key") {
+            Ok(value) => {
+                assert!(!expired);
+                assert_eq!(value, Some(b"0".to_vec()));
+            }
+            Err(DigbyError::SnapshotExpired(_)) => expired = true,
+            Err(error) => panic!("{}", error),
+        }
+    }
+    assert!(expired);
+    assert_eq!(db.get(b"key"), Some(b"9".to_vec()));
+}
+
+#[test]
+#[should_panic(expected = "Snapshot has expired")]
+fn test_db_snapshot_expired_get() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"key", b"0");
+    let snapshot = db.snapshot();
+    for i in 1..10u32 {
+        db.put(b"key", i.to_string().as_bytes());
+    }
+    snapshot.get(&mut db, b"key");
+}
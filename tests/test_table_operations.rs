@@ -13,9 +13,9 @@ fn test_db_create_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
-        db.create_table(name.as_ref());
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
+        assert!(db.get_table_tree_root(&name).is_none());
+        db.create_table(&name);
+        assert!(db.get_table_tree_root(&name).is_some());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -31,7 +31,7 @@ fn test_db_create_table_name_too_big_get() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
+        assert!(db.get_table_tree_root(&name).is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -47,7 +47,7 @@ fn test_db_create_table_name_too_big_create() {
             None,
             CompressorType::None,
         );
-        db.create_table(name.as_ref());
+        db.create_table(&name);
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -65,7 +65,7 @@ fn test_db_create_table_name_too_big_put() {
             None,
             CompressorType::None,
         );
-        db.put_table_entry(name.as_ref(), key.as_ref(), value.as_ref());
+        db.put_table_entry(&name, &key, &value);
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -81,7 +81,7 @@ fn test_db_clear_table_name_too_big_put() {
             None,
             CompressorType::None,
         );
-        db.clear_table_with_delete(name.as_ref(), true);
+        db.clear_table_with_delete(&name, true);
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -96,8 +96,8 @@ fn test_db_clear_table_name_that_does_not_exist() {
             None,
             CompressorType::None,
         );
-        db.clear_table_with_delete(name.as_ref(), true);
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
+        db.clear_table_with_delete(&name, true);
+        assert!(db.get_table_tree_root(&name).is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -112,8 +112,8 @@ fn test_db_clear_table_name_that_does_not_exist_without_delete() {
             None,
             CompressorType::None,
         );
-        db.clear_table_with_delete(name.as_ref(), false);
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
+        db.clear_table_with_delete(&name, false);
+        assert!(db.get_table_tree_root(&name).is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -131,13 +131,13 @@ fn test_db_create_put_table_create_table() {
             CompressorType::None,
         );
         // Attmpt to delete from a table that does not exist - should return false but not panic
-        assert!(!db.delete_table_entry(name.as_ref(), key.as_ref()));
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
+        assert!(!db.delete_table_entry(&name, &key));
+        assert!(db.get_table_tree_root(&name).is_none());
 
         // Do not explicitly create the table, just put an entry in it - this should implicitly create the table
-        db.put_table_entry(name.as_ref(), key.as_ref(), value.as_ref());
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref()).unwrap();
+        db.put_table_entry(&name, &key, &value);
+        assert!(db.get_table_tree_root(&name).is_some());
+        let returned_value = db.get_table_entry(&name, &key).unwrap();
         assert!(returned_value == value);
     }
     {
@@ -146,14 +146,14 @@ fn test_db_create_put_table_create_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref()).unwrap();
+        assert!(db.get_table_tree_root(&name).is_some());
+        let returned_value = db.get_table_entry(&name, &key).unwrap();
         assert!(returned_value == value);
 
         // Attempt to delete a key that does not exist - should return false but not panic
-        assert!(!db.delete_table_entry(name.as_ref(), b"the_non_existent_key".as_ref()));
+        assert!(!db.delete_table_entry(&name, b"the_non_existent_key".as_ref()));
         let ver_large_key = vec![b'a'; 655];
-        assert!(!db.delete_table_entry(name.as_ref(), ver_large_key.as_ref()));
+        assert!(!db.delete_table_entry(&name, &ver_large_key));
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -170,10 +170,10 @@ fn test_db_create_put_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
-        db.create_table(name.as_ref());
-        db.put_table_entry(name.as_ref(), key.as_ref(), value.as_ref());
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
+        assert!(db.get_table_tree_root(&name).is_none());
+        db.create_table(&name);
+        db.put_table_entry(&name, &key, &value);
+        assert!(db.get_table_tree_root(&name).is_some());
     }
     {
         let mut db = Db::new(
@@ -181,8 +181,8 @@ fn test_db_create_put_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref()).unwrap();
+        assert!(db.get_table_tree_root(&name).is_some());
+        let returned_value = db.get_table_entry(&name, &key).unwrap();
         assert!(returned_value == value);
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
@@ -200,10 +200,10 @@ fn test_db_table_clear() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
-        db.create_table(name.as_ref());
-        db.put_table_entry(name.as_ref(), key.as_ref(), value.as_ref());
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
+        assert!(db.get_table_tree_root(&name).is_none());
+        db.create_table(&name);
+        db.put_table_entry(&name, &key, &value);
+        assert!(db.get_table_tree_root(&name).is_some());
     }
     {
         let mut db = Db::new(
@@ -211,14 +211,14 @@ fn test_db_table_clear() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref()).unwrap();
+        assert!(db.get_table_tree_root(&name).is_some());
+        let returned_value = db.get_table_entry(&name, &key).unwrap();
         assert!(returned_value == value);
-        db.clear_table(name.as_ref());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref());
+        db.clear_table(&name);
+        let returned_value = db.get_table_entry(&name, &key);
         assert!(returned_value.is_none());
-        db.delete_table(name.as_ref());
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
+        db.delete_table(&name);
+        assert!(db.get_table_tree_root(&name).is_none());
     }
     {
         let mut db = Db::new(
@@ -226,7 +226,7 @@ fn test_db_table_clear() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
+        assert!(db.get_table_tree_root(&name).is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
@@ -243,10 +243,10 @@ fn test_db_create_put_delete_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_none());
-        db.create_table(name.as_ref());
-        db.put_table_entry(name.as_ref(), key.as_ref(), value.as_ref());
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
+        assert!(db.get_table_tree_root(&name).is_none());
+        db.create_table(&name);
+        db.put_table_entry(&name, &key, &value);
+        assert!(db.get_table_tree_root(&name).is_some());
     }
     {
         let mut db = Db::new(
@@ -254,10 +254,10 @@ fn test_db_create_put_delete_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref()).unwrap();
+        assert!(db.get_table_tree_root(&name).is_some());
+        let returned_value = db.get_table_entry(&name, &key).unwrap();
         assert!(returned_value == value);
-        assert!(db.delete_table_entry(name.as_ref(), key.as_ref()))
+        assert!(db.delete_table_entry(&name, &key))
     }
     {
         let mut db = Db::new(
@@ -265,9 +265,401 @@ fn test_db_create_put_delete_table() {
             None,
             CompressorType::None,
         );
-        assert!(db.get_table_tree_root(name.as_ref()).is_some());
-        let returned_value = db.get_table_entry(name.as_ref(), key.as_ref());
+        assert!(db.get_table_tree_root(&name).is_some());
+        let returned_value = db.get_table_entry(&name, &key);
         assert!(returned_value.is_none());
     }
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
+
+#[test]
+fn test_db_delete_prefix() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let table = b"tenants".to_vec();
+    // Tenant keys either side of tenant 2, plus a large value and a long
+    // key so overflow pages and short keys are deleted too.
+    let tenants: [&[u8]; 4] = [b"tenant1/", b"tenant2/", b"tenant2", b"tenant3/"];
+    let long_key = [b"tenant2/".to_vec(), vec![b'k'; 300]].concat();
+    {
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        assert_eq!(db.delete_prefix(&table, b"tenant2/"), 0);
+        for tenant in tenants {
+            for i in 0..100u32 {
+                let key = [tenant, format!("{:03}", i).as_bytes()].concat();
+                db.put_table_entry(&table, &key, i.to_le_bytes());
+            }
+        }
+        db.put_table_entry(&table, b"tenant2/large", vec![1u8; 5000]);
+        db.put_table_entry(&table, &long_key, b"long");
+        db.put(b"tenant2/global", b"not in the table");
+
+        assert_eq!(db.delete_prefix(&table, b"tenant2/"), 102);
+        assert_eq!(db.delete_prefix(&table, b"tenant2/"), 0);
+        assert_eq!(db.delete_prefix(b"no_table", b"tenant2/"), 0);
+        db.verify().expect("DB should verify");
+    }
+    {
+        let mut db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            1024,
+        );
+        for tenant in tenants {
+            for i in 0..100u32 {
+                let key = [tenant, format!("{:03}", i).as_bytes()].concat();
+                let value = db.get_table_entry(&table, &key);
+                if tenant == b"tenant2/" {
+                    assert!(value.is_none());
+                } else {
+                    assert_eq!(value, Some(i.to_le_bytes().to_vec()));
+                }
+            }
+        }
+        assert!(db.get_table_entry(&table, b"tenant2/large").is_none());
+        assert!(db.get_table_entry(&table, &long_key).is_none());
+        assert_eq!(
+            db.get(b"tenant2/global"),
+            Some(b"not in the table".to_vec())
+        );
+        assert_eq!(db.table_infos()[0].entry_count, 300);
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+#[test]
+fn test_db_table_exists_lifecycle() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let name = b"the_table".to_vec();
+    let key = b"the_key".to_vec();
+    {
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        assert!(!db.table_exists(&name));
+        db.create_table(&name);
+        assert!(db.table_exists(&name));
+        assert_eq!(db.table_infos()[0].entry_count, 0);
+
+        db.put_table_entry(&name, &key, b"the_value");
+        db.clear_table(&name);
+        assert!(db.table_exists(&name));
+        assert!(db.get_table_entry(&name, &key).is_none());
+
+        // Deleting the last entry leaves the table in place too.
+        db.put_table_entry(&name, &key, b"the_value");
+        assert!(db.delete_table_entry(&name, &key));
+        assert!(db.table_exists(&name));
+    }
+    {
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        assert!(db.table_exists(&name));
+        db.delete_table(&name);
+        assert!(!db.table_exists(&name));
+        let tx_ctx = db.new_transaction();
+        assert!(!db.table_exists_txn(&name, &tx_ctx));
+    }
+    {
+        let mut db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        assert!(!db.table_exists(&name));
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+#[test]
+fn test_db_drop_table() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        assert!(!db.drop_table(b"table"));
+        for i in 0..500u32 {
+            db.put_table_entry(b"table", i.to_be_bytes(), [1u8; 100]);
+        }
+        // Values in overflow pages, 5 pages each.
+        for i in 0..10u32 {
+            let key = format!("large{}", i).into_bytes();
+            db.put_table_entry(b"table", &key, vec![2u8; 20000]);
+        }
+        db.put_table_entry(b"other", b"key", b"value");
+        db.put(b"key", b"value");
+        let table_page_count = db.table_infos()[1].page_count;
+        assert!(table_page_count > 1);
+
+        let free_before = db.free_page_count();
+        assert!(db.drop_table(b"table"));
+        assert!(db.get_table_tree_root(b"table").is_none());
+        assert!(!db.table_exists(b"table"));
+        assert_eq!(db.get_table_entry(b"table", 0u32.to_be_bytes()), None);
+        assert!(db.free_page_count() >= free_before + table_page_count + 50);
+        assert_eq!(db.list_tables(), vec![b"other".to_vec()]);
+        assert!(!db.drop_table(b"table"));
+        assert_eq!(db.verify(), Ok(()));
+    }
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert!(db.get_table_tree_root(b"table").is_none());
+    assert_eq!(
+        db.get_table_entry(b"other", b"key"),
+        Some(b"value".to_vec())
+    );
+    assert_eq!(db.get(b"key"), Some(b"value".to_vec()));
+    // The table can be created again.
+    db.put_table_entry(b"table", b"key", b"value");
+    assert_eq!(
+        db.get_table_entry(b"table", b"key"),
+        Some(b"value".to_vec())
+    );
+}
+
+#[test]
+fn test_db_delete_table_entry_keeps_other_keys() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        assert!(!db.delete_table_entry(b"missing", b"key"));
+        assert!(!db.table_exists(b"missing"));
+
+        db.put_table_entry(b"table", b"key1", b"value1");
+        db.put_table_entry(b"table", b"key2", b"value2");
+        db.put(b"key1", b"global");
+        assert!(db.delete_table_entry(b"table", b"key1"));
+        assert!(!db.delete_table_entry(b"table", b"key1"));
+        assert_eq!(db.get_table_entry(b"table", b"key1"), None);
+        assert_eq!(
+            db.get_table_entry(b"table", b"key2"),
+            Some(b"value2".to_vec())
+        );
+        // The global tree is not touched.
+        assert_eq!(db.get(b"key1"), Some(b"global".to_vec()));
+    }
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.get_table_entry(b"table", b"key1"), None);
+    assert_eq!(
+        db.get_table_entry(b"table", b"key2"),
+        Some(b"value2".to_vec())
+    );
+    assert!(!db.table_exists(b"missing"));
+}
+
+#[test]
+fn test_db_create_table_twice() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert!(db.create_table(b"table"));
+    db.put_table_entry(b"table", b"key", b"value");
+    let root_page_no = db.get_table_tree_root(b"table");
+    let free_page_count = db.free_page_count();
+
+    assert!(!db.create_table(b"table"));
+    assert_eq!(db.get_table_tree_root(b"table"), root_page_no);
+    assert_eq!(db.free_page_count(), free_page_count);
+    assert_eq!(
+        db.get_table_entry(b"table", b"key"),
+        Some(b"value".to_vec())
+    );
+
+    // Twice in the one transaction.
+    let mut tx_ctx = db.new_transaction();
+    assert!(db.create_table_txn(b"other", &mut tx_ctx));
+    assert!(!db.create_table_txn(b"other", &mut tx_ctx));
+    assert!(!db.create_table_txn(b"table", &mut tx_ctx));
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+    assert_eq!(db.list_tables(), vec![b"other".to_vec(), b"table".to_vec()]);
+    assert_eq!(db.verify(), Ok(()));
+}
+
+// Clearing a table frees its pages, overflow pages included, and leaves
+// the table and the other trees as they were.
+#[test]
+fn test_db_clear_table_frees_pages() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut db = Db::new_with_page_size(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+        1024,
+    );
+    db.put("global", "value");
+    db.create_table("other");
+    db.put_table_entry("other", "key", vec![2u8; 5000]);
+    db.create_table("table");
+    let mut tx_ctx = db.new_transaction();
+    for i in 0..2000u32 {
+        let mut key = i.to_be_bytes().to_vec();
+        if i % 100 == 0 {
+            key.resize(3000, 1);
+        }
+        let value = if i % 50 == 0 {
+            vec![1u8; 5000]
+        } else {
+            i.to_le_bytes().to_vec()
+        };
+        db.put_table_entry_txn(b"table", &key, &value, &mut tx_ctx);
+    }
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+    let free_before = db.free_page_count();
+    let root_before = db.get_table_tree_root(b"table").unwrap();
+
+    db.clear_table("table");
+    assert!(db.free_page_count() > free_before + 50);
+    let root_after = db.get_table_tree_root(b"table").unwrap();
+    assert_ne!(root_after, root_before);
+    assert!(db.table_exists(b"table"));
+    assert_eq!(db.get_table_entry("table", 1u32.to_be_bytes()), None);
+    assert_eq!(db.table_infos().len(), 2);
+    assert_eq!(
+        db.table_infos()
+            .iter()
+            .find(|info| info.name == b"table")
+            .map(|info| info.entry_count),
+        Some(0)
+    );
+    assert_eq!(db.get("global"), Some(b"value".to_vec()));
+    assert_eq!(db.get_table_entry("other", "key"), Some(vec![2u8; 5000]));
+    db.verify().expect("DB should verify");
+
+    // The cleared table takes entries again.
+    db.put_table_entry("table", "key", "value");
+    assert_eq!(db.get_table_entry("table", "key"), Some(b"value".to_vec()));
+}
+
+// A table of values that are already compressed skips the DB's
+// compressor, the other tables still use it.
+#[test]
+#[cfg(feature = "lz4")]
+fn test_db_table_compressor() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let value = vec![7u8; 40000];
+    {
+        let mut db = Db::new(path, None, CompressorType::LZ4);
+        assert!(db.create_table_with_compressor("blobs", CompressorType::None));
+        assert!(db.create_table_with_compressor("json", CompressorType::LZ4));
+        assert!(!db.create_table_with_compressor("json", CompressorType::None));
+        assert!(db.create_table("other"));
+        // A codec that is not built in cannot be used.
+        if !cfg!(feature = "snappy") {
+            assert!(matches!(
+                db.try_create_table_with_compressor("snappy", CompressorType::Snappy),
+                Err(digby::DigbyError::CompressorMismatch(_))
+            ));
+            assert!(!db.table_exists(b"snappy"));
+        }
+        assert_eq!(
+            db.table_compressor_type("blobs"),
+            Some(CompressorType::None)
+        );
+        assert_eq!(db.table_compressor_type("json"), Some(CompressorType::LZ4));
+        assert_eq!(db.table_compressor_type("other"), Some(CompressorType::LZ4));
+        assert_eq!(db.table_compressor_type("missing"), None);
+
+        // The value compresses to a page or so, stored raw it takes
+        // ten overflow pages.
+        let pages = |db: &mut Db| db.verify_report().pages_checked;
+        let before = pages(&mut db);
+        db.put_table_entry("json", "key", &value);
+        db.put_table_entry("other", "key", &value);
+        assert!(pages(&mut db) <= before + 4);
+        let before = pages(&mut db);
+        db.put_table_entry("blobs", "key", &value);
+        assert!(pages(&mut db) >= before + 9);
+    }
+    let check = |db: &mut Db| {
+        for table in ["blobs", "json", "other"] {
+            assert_eq!(db.get_table_entry(table, "key"), Some(value.clone()));
+        }
+        // Changes to the table keep its compressor.
+        assert_eq!(
+            db.table_compressor_type("blobs"),
+            Some(CompressorType::None)
+        );
+        db.verify().expect("DB should verify");
+    };
+    let mut db = Db::new(path, None, CompressorType::LZ4);
+    check(&mut db);
+    db.put_table_entry("blobs", "other", b"value");
+    drop(db);
+
+    Db::compact_file(path, None, CompressorType::LZ4).expect("Failed to compact");
+    let mut db = Db::new(path, None, CompressorType::LZ4);
+    check(&mut db);
+    assert_eq!(db.table_compressor_type("json"), Some(CompressorType::LZ4));
+}
+
+// Each table reads and writes its values with its own codec, whatever
+// the DB's codec is.
+#[test]
+#[cfg(all(feature = "lz4", feature = "zstd"))]
+fn test_db_table_compressor_mixed() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let json = b"{\"key\": \"value\"}, ".repeat(2000);
+    let logs = b"INFO started\n".repeat(3000);
+    let long_key = vec![5u8; 3000];
+    {
+        let mut db = Db::new(path, None, CompressorType::None);
+        assert!(db.create_table_with_compressor("json", CompressorType::Zstd));
+        assert!(db.create_table_with_compressor("logs", CompressorType::LZ4));
+        assert_eq!(db.table_compressor_type("json"), Some(CompressorType::Zstd));
+        assert_eq!(db.table_compressor_type("logs"), Some(CompressorType::LZ4));
+
+        // Both values compress to well under the ten or so overflow
+        // pages they take stored raw.
+        let pages = |db: &mut Db| db.verify_report().pages_checked;
+        let before = pages(&mut db);
+        db.put_table_entry("json", "key", &json);
+        db.put_table_entry("logs", "key", &logs);
+        assert!(pages(&mut db) <= before + 6);
+        db.put_table_entry("json", &long_key, &json);
+        db.put_table_entry("logs", &long_key, &logs);
+        db.put("global", &json);
+    }
+    let check = |db: &mut Db| {
+        for key in [b"key".to_vec(), long_key.clone()] {
+            assert_eq!(db.get_table_entry("json", &key), Some(json.clone()));
+            assert_eq!(db.get_table_entry("logs", &key), Some(logs.clone()));
+        }
+        assert_eq!(db.get("global"), Some(json.clone()));
+        assert_eq!(
+            db.table_iter(b"logs").collect::<Vec<_>>(),
+            vec![
+                (long_key.clone(), logs.clone()),
+                (b"key".to_vec(), logs.clone())
+            ]
+        );
+        let all: Vec<_> = db
+            .iter_all()
+            .map(|(table, _, value)| (table, value))
+            .collect();
+        assert_eq!(all[0], (Vec::new(), json.clone()));
+        assert_eq!(all[1], (b"json".to_vec(), json.clone()));
+        assert_eq!(all[4], (b"logs".to_vec(), logs.clone()));
+        assert_eq!(
+            db.read_transaction(|read_tx| read_tx.get_table_entry(b"json", b"key")),
+            Some(json.clone())
+        );
+        db.verify().expect("DB should verify");
+    };
+    let mut db = Db::new(path, None, CompressorType::None);
+    check(&mut db);
+    assert_eq!(db.delete_prefix("logs", &long_key), 1);
+    db.put_table_entry("logs", &long_key, &logs);
+    check(&mut db);
+}
@@ -18,13 +18,57 @@ fn test_basic_transaction() {
         let mut tx_ctx = db.new_transaction();
         db.put_txn(key.as_ref(), value.as_ref(), &mut tx_ctx);
         // Outside the scope of the txn the key is not in the DB.
-        assert!(db.get(key.as_ref()).is_none());
+        assert!(db.get(&key).is_none());
         // Inside the txn scope the key is in the DB.
         assert!(db.get_txn(key.as_ref(), &tx_ctx).is_some());
-        db.commit(&mut tx_ctx);
+        db.commit(&mut tx_ctx).expect("Failed to commit");
         // After the txn is committed the key is available.
-        assert!(db.get(key.as_ref()).is_some());
+        assert!(db.get(&key).is_some());
     }
 
     fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
 }
+
+#[test]
+fn test_read_transaction() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    // A transfer between accounts, the total is the same at every
+    // commit.
+    db.put(b"account_a", 100u32.to_le_bytes());
+    db.put(b"account_b", 50u32.to_le_bytes());
+    db.put_table_entry(b"audit", b"transfers", 0u32.to_le_bytes());
+
+    // Part way through a transfer that is not yet committed.
+    let mut tx_ctx = db.new_transaction();
+    db.put_txn(b"account_a", &70u32.to_le_bytes(), &mut tx_ctx);
+
+    let read = |db: &mut Db| {
+        db.read_transaction(|tx| {
+            let a = u32::from_le_bytes(tx.get(b"account_a").unwrap().try_into().unwrap());
+            let b = u32::from_le_bytes(tx.get(b"account_b").unwrap().try_into().unwrap());
+            let transfers = tx.get_table_entry(b"audit", b"transfers").unwrap();
+            assert!(tx.table_exists(b"audit"));
+            assert!(!tx.table_exists(b"missing"));
+            assert_eq!(tx.get(b"missing"), None);
+            assert_eq!(tx.range(b"", None).len(), 2);
+            (tx.get_version(), a, b, transfers)
+        })
+    };
+    let (version, a, b, transfers) = read(&mut db);
+    assert_eq!((a, b), (100, 50));
+    assert_eq!(transfers, 0u32.to_le_bytes());
+
+    db.put_txn(b"account_b", &80u32.to_le_bytes(), &mut tx_ctx);
+    db.put_table_entry_txn(b"audit", b"transfers", &1u32.to_le_bytes(), &mut tx_ctx);
+    db.commit(&mut tx_ctx).expect("Failed to commit");
+
+    let (new_version, a, b, transfers) = read(&mut db);
+    assert_eq!(new_version, version + 1);
+    assert_eq!((a, b), (70, 80));
+    assert_eq!(transfers, 1u32.to_le_bytes());
+}
@@ -0,0 +1,93 @@
+use digby::compressor::CompressorType;
+use digby::{Db, MemoryStorage};
+use tempfile::NamedTempFile;
+
+// Delete most of a DB, trim it and carry on using it. The trimmed pages
+// are reused by the commits that follow.
+fn trim_and_reuse(db: &mut Db) -> u64 {
+    db.put(b"blob", vec![1u8; 2 * 1024 * 1024]);
+    for i in 0..2000u32 {
+        db.put(i.to_be_bytes(), [2u8; 100]);
+    }
+    assert!(db.delete(b"blob"));
+    for i in 0..2000u32 {
+        if i % 10 != 0 {
+            assert!(db.delete(i.to_be_bytes()));
+        }
+    }
+    let free_page_count = db.free_page_count();
+    let trimmed = db.trim().expect("Failed to trim");
+    assert_eq!(trimmed, free_page_count);
+    assert_eq!(db.verify(), Ok(()));
+    for i in 0..2000u32 {
+        let expected = (i % 10 == 0).then_some(vec![2u8; 100]);
+        assert_eq!(db.get(i.to_be_bytes()), expected);
+    }
+
+    db.put(b"blob", vec![3u8; 1024 * 1024]);
+    assert_eq!(db.get(b"blob"), Some(vec![3u8; 1024 * 1024]));
+    assert_eq!(db.verify(), Ok(()));
+    trimmed
+}
+
+#[test]
+fn test_db_trim() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert!(trim_and_reuse(&mut db) > 500);
+    drop(db);
+
+    // The trimmed pages that were not reused are still zeros after the
+    // DB is reopened.
+    let mut db = Db::new(path, None, CompressorType::None);
+    assert_eq!(db.verify(), Ok(()));
+    assert_eq!(db.get(b"blob"), Some(vec![3u8; 1024 * 1024]));
+}
+
+// The disk space of the trimmed pages goes back to the file system, the
+// file keeps its length.
+#[test]
+#[cfg(target_os = "linux")]
+fn test_db_trim_frees_disk_space() {
+    use std::os::unix::fs::MetadataExt;
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let mut db = Db::new(path, None, CompressorType::None);
+    db.put(b"blob", vec![1u8; 4 * 1024 * 1024]);
+    assert!(db.delete(b"blob"));
+    let metadata = std::fs::metadata(path).unwrap();
+    db.trim().expect("Failed to trim");
+    let trimmed_metadata = std::fs::metadata(path).unwrap();
+    assert_eq!(trimmed_metadata.len(), metadata.len());
+    // st_blocks is in 512 byte units.
+    assert!(
+        trimmed_metadata.blocks() * 512 < metadata.blocks() * 512 - 3 * 1024 * 1024,
+        "{} blocks before, {} after",
+        metadata.blocks(),
+        trimmed_metadata.blocks()
+    );
+}
+
+// MemoryStorage zeros the trimmed pages.
+#[test]
+fn test_db_trim_memory_storage() {
+    let storage = MemoryStorage::new();
+    let mut db = Db::new_with_storage(Box::new(storage.clone()), None, CompressorType::None, 4096);
+    trim_and_reuse(&mut db);
+    let zero_blocks = storage
+        .to_vec()
+        .chunks(4096)
+        .filter(|block| block.iter().all(|b| *b == 0))
+        .count();
+    assert!(zero_blocks > 100);
+}
+
+#[test]
+fn test_db_trim_read_only() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    drop(Db::new(path, None, CompressorType::None));
+    let mut db = Db::open_read_only(path, None, CompressorType::None);
+    assert!(db.trim().is_err());
+}
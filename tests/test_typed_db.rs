@@ -0,0 +1,129 @@
+use digby::Db;
+use digby::TypedDb;
+use digby::codec::{BigEndianI64Codec, BigEndianU64Codec, StringCodec};
+use digby::compressor::CompressorType;
+use std::fs;
+use tempfile::NamedTempFile;
+
+#[test]
+fn test_typed_db_put_get_delete() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    {
+        let db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        let mut typed_db = TypedDb::new(db, BigEndianU64Codec, StringCodec);
+        typed_db.put(&1, &"one".to_string());
+        typed_db.put(&2, &"two".to_string());
+        assert_eq!(typed_db.get(&1), Some("one".to_string()));
+        assert_eq!(typed_db.get(&2), Some("two".to_string()));
+        assert_eq!(typed_db.get(&3), None);
+        assert!(typed_db.delete(&1));
+        assert!(!typed_db.delete(&1));
+        assert_eq!(typed_db.get(&1), None);
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+#[test]
+fn test_typed_db_range_numeric_order() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    {
+        let db = Db::new_with_page_size(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+            256,
+        );
+        let mut typed_db = TypedDb::new(db, BigEndianI64Codec, BigEndianU64Codec);
+        // Insert in an order that does not match the key order.
+        for i in (-300i64..300).rev() {
+            typed_db.put(&i, &(i.unsigned_abs()));
+        }
+        let entries = typed_db.range(&-10, Some(&10));
+        let keys: Vec<i64> = entries.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, (-10i64..10).collect::<Vec<i64>>());
+        assert_eq!(entries[0].1, 10);
+
+        let entries = typed_db.range(&290, None);
+        assert_eq!(entries.len(), 10);
+        assert_eq!(entries.last().unwrap().0, 299);
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_typed_db_bincode() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    {
+        let db = Db::new(
+            temp_file.path().to_str().unwrap(),
+            None,
+            CompressorType::None,
+        );
+        let mut typed_db: TypedDb<(u32, String), Vec<f64>, _, _> = TypedDb::with_bincode(db);
+        let key = (7u32, "seven".to_string());
+        typed_db.put(&key, &vec![7.0, 0.7]);
+        assert_eq!(typed_db.get(&key), Some(vec![7.0, 0.7]));
+    }
+    fs::remove_file(temp_file.path()).expect("Failed to remove temp file");
+}
+
+// A format of the caller's own, bincode behind a tag byte.
+#[cfg(feature = "serde")]
+struct Tagged;
+
+#[cfg(feature = "serde")]
+impl digby::SerdeFormat for Tagged {
+    fn serialize<T: serde::Serialize>(&self, value: &T) -> Result<Vec<u8>, digby::DigbyError> {
+        let mut bytes = vec![b'T'];
+        bytes.extend(digby::Bincode.serialize(value)?);
+        Ok(bytes)
+    }
+
+    fn deserialize<T: serde::de::DeserializeOwned>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<T, digby::DigbyError> {
+        match bytes.split_first() {
+            Some((b'T', rest)) => digby::Bincode.deserialize(rest),
+            _ => Err(digby::DigbyError::Serialization("missing tag".to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_db_put_get_typed() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let mut db = Db::new(
+        temp_file.path().to_str().unwrap(),
+        None,
+        CompressorType::None,
+    );
+    let user = (123u32, "name".to_string(), vec![1.5f64, 2.5]);
+    db.put_typed(b"user:123", &user);
+    assert_eq!(db.get_typed(b"user:123"), Some(user.clone()));
+    assert_eq!(db.get_typed::<u32>(b"user:124"), None);
+
+    // Bytes that are not the type asked for are an error.
+    db.put(b"short", [1]);
+    assert!(matches!(
+        db.try_get_typed::<u64>(b"short"),
+        Err(digby::DigbyError::Serialization(_))
+    ));
+
+    db.try_put_typed_with(b"tagged", &user, &Tagged)
+        .expect("Failed to put");
+    assert_eq!(
+        db.try_get_typed_with(b"tagged", &Tagged).unwrap(),
+        Some(user)
+    );
+    assert!(matches!(
+        db.try_get_typed_with::<u32>(b"user:123", &Tagged),
+        Err(digby::DigbyError::Serialization(_))
+    ));
+}
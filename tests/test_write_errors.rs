@@ -0,0 +1,265 @@
+use digby::BatchOp;
+use digby::Corruption;
+use digby::Db;
+use digby::DigbyError;
+use digby::Storage;
+use digby::compressor::CompressorType;
+use std::fs::File;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tempfile::NamedTempFile;
+
+// A file that starts failing writes once a number of writes have been
+// made, like a disk filling up. Every write after that fails too.
+struct FailingStorage {
+    file: File,
+    writes_left: Arc<AtomicU64>,
+}
+
+impl Storage for FailingStorage {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        self.file.read_exact_at(buf, offset)
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        let writes_left = self.writes_left.load(Ordering::SeqCst);
+        if writes_left == 0 {
+            return Err(std::io::Error::other("No space left"));
+        }
+        self.writes_left.store(writes_left - 1, Ordering::SeqCst);
+        self.file.write_all_at(buf, offset)
+    }
+
+    fn len(&self) -> std::io::Result<u64> {
+        Storage::len(&self.file)
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        Storage::set_len(&mut self.file, len)
+    }
+
+    fn sync_all(&mut self) -> std::io::Result<()> {
+        Storage::sync_all(&mut self.file)
+    }
+
+    fn sync_data(&mut self) -> std::io::Result<()> {
+        Storage::sync_data(&mut self.file)
+    }
+}
+
+fn open_db(path: &str, writes_left: &Arc<AtomicU64>) -> Db {
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .open(path)
+        .expect("Failed to open DB file");
+    let storage = FailingStorage {
+        file,
+        writes_left: writes_left.clone(),
+    };
+    Db::new_with_storage(Box::new(storage), None, CompressorType::None, 1024)
+}
+
+fn check_db(db: &mut Db, committed: u32) {
+    assert_eq!(db.verify(), Ok(()));
+    for i in 0..committed {
+        assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+    }
+    assert!(db.get(committed.to_be_bytes()).is_none());
+    assert!(db.get(b"large").is_none());
+}
+
+// Fail the Nth write of a transaction for each write it makes, the
+// commit must return the error and leave the Db at the last commit and
+// still usable.
+#[test]
+fn test_db_commit_write_error() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let writes_left = Arc::new(AtomicU64::new(u64::MAX));
+    let mut db = open_db(path, &writes_left);
+    for i in 0..100u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+
+    let mut committed = 100u32;
+    let mut fail_at = 0u64;
+    loop {
+        writes_left.store(fail_at, Ordering::SeqCst);
+        let mut tx_ctx = db.new_transaction();
+        for i in committed..committed + 50 {
+            db.put_txn(&i.to_be_bytes(), &i.to_le_bytes(), &mut tx_ctx);
+        }
+        db.put_txn(b"large", &vec![3u8; 5000], &mut tx_ctx);
+        let result = db.commit(&mut tx_ctx);
+        writes_left.store(u64::MAX, Ordering::SeqCst);
+        if result.is_ok() {
+            break;
+        }
+        assert_eq!(result.unwrap_err().to_string(), "No space left");
+        check_db(&mut db, committed);
+
+        // The Db can still be written to.
+        db.put(committed.to_be_bytes(), committed.to_le_bytes());
+        committed += 1;
+        check_db(&mut db, committed);
+        fail_at += 1;
+    }
+    assert!(fail_at > 10);
+    assert_eq!(db.get(b"large").unwrap(), vec![3u8; 5000]);
+    drop(db);
+
+    // The file holds everything that was committed.
+    let mut db = open_db(path, &writes_left);
+    assert_eq!(db.verify(), Ok(()));
+    for i in 0..committed + 50 {
+        assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+    }
+}
+
+#[test]
+#[should_panic(expected = "Failed to initialize DB file")]
+fn test_db_create_write_error() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let writes_left = Arc::new(AtomicU64::new(3));
+    open_db(temp_file.path().to_str().unwrap(), &writes_left);
+}
+
+// Fail each write made creating the DB, as a crash part way through
+// would leave it. The root page is written last, without it a reader is
+// told the DB is unfinished and a writer creates it again.
+#[test]
+fn test_db_create_write_error_reopen() {
+    let mut writes = 0;
+    loop {
+        let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+        let path = temp_file.path().to_str().unwrap();
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .expect("Failed to open DB file");
+        let storage = FailingStorage {
+            file,
+            writes_left: Arc::new(AtomicU64::new(writes)),
+        };
+        let created =
+            Db::try_new_with_storage(Box::new(storage), None, CompressorType::None, 1024).is_ok();
+        if !created && std::fs::metadata(path).unwrap().len() > 0 {
+            assert!(matches!(
+                Db::try_open_read_only_with_page_size(path, None, CompressorType::None, 1024),
+                Err(DigbyError::Corruption(Corruption::Unfinished(page_no))) if page_no.to_u64() == 0
+            ));
+        }
+        let mut db = open_db(path, &Arc::new(AtomicU64::new(u64::MAX)));
+        check_db(&mut db, 0);
+        db.put(0u32.to_be_bytes(), 0u32.to_le_bytes());
+        drop(db);
+        let mut db = open_db(path, &Arc::new(AtomicU64::new(u64::MAX)));
+        check_db(&mut db, 1);
+        if created {
+            break;
+        }
+        writes += 1;
+    }
+    assert!(writes > 5);
+}
+
+#[test]
+fn test_db_sync_masters_write_error() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let writes_left = Arc::new(AtomicU64::new(u64::MAX));
+    let mut db = open_db(path, &writes_left);
+    for i in 0..10u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    writes_left.store(0, Ordering::SeqCst);
+    assert_eq!(db.sync_masters().unwrap_err().to_string(), "No space left");
+    writes_left.store(u64::MAX, Ordering::SeqCst);
+    check_db(&mut db, 10);
+    db.sync_masters().expect("Failed to sync masters");
+    db.put(10u32.to_be_bytes(), 10u32.to_le_bytes());
+    drop(db);
+
+    let mut db = open_db(path, &writes_left);
+    check_db(&mut db, 11);
+}
+
+// Fail each write of a compaction in turn, the DB must be left as it
+// was or compacted and be usable either way.
+#[test]
+fn test_db_compact_in_place_write_error() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let writes_left = Arc::new(AtomicU64::new(u64::MAX));
+    let mut db = open_db(path, &writes_left);
+    for i in 0..1000u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    for i in 0..900u32 {
+        assert!(db.delete(i.to_be_bytes()));
+    }
+    let mut fail_at = 0u64;
+    loop {
+        writes_left.store(fail_at, Ordering::SeqCst);
+        let result = db.compact_in_place();
+        writes_left.store(u64::MAX, Ordering::SeqCst);
+        assert_eq!(db.verify(), Ok(()));
+        for i in 900..1000u32 {
+            assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+        }
+        if result.is_ok() {
+            break;
+        }
+        assert_eq!(result.unwrap_err().to_string(), "No space left");
+        fail_at += 1;
+    }
+    assert!(fail_at > 0);
+    drop(db);
+
+    let mut db = open_db(path, &writes_left);
+    assert_eq!(db.verify(), Ok(()));
+    for i in 900..1000u32 {
+        assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+    }
+}
+
+// Fail each write of a batch in turn, none of the batch is in the DB
+// until it all is.
+#[test]
+fn test_db_write_batch_write_error() {
+    let temp_file = NamedTempFile::new().expect("Failed to create temp file");
+    let path = temp_file.path().to_str().unwrap();
+    let writes_left = Arc::new(AtomicU64::new(u64::MAX));
+    let mut db = open_db(path, &writes_left);
+    for i in 0..100u32 {
+        db.put(i.to_be_bytes(), i.to_le_bytes());
+    }
+    let mut ops: Vec<BatchOp> = (100..200u32)
+        .map(|i| BatchOp::Put(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()))
+        .collect();
+    ops.push(BatchOp::Delete(0u32.to_be_bytes().to_vec()));
+
+    let mut fail_at = 0u64;
+    loop {
+        writes_left.store(fail_at, Ordering::SeqCst);
+        let result = db.write_batch(ops.clone());
+        writes_left.store(u64::MAX, Ordering::SeqCst);
+        if result.is_ok() {
+            break;
+        }
+        assert_eq!(result.unwrap_err().to_string(), "No space left");
+        check_db(&mut db, 100);
+        fail_at += 1;
+    }
+    assert!(fail_at > 0);
+    drop(db);
+
+    let mut db = open_db(path, &writes_left);
+    assert_eq!(db.verify(), Ok(()));
+    assert!(db.get(0u32.to_be_bytes()).is_none());
+    for i in 1..200u32 {
+        assert_eq!(db.get(i.to_be_bytes()).unwrap(), i.to_le_bytes());
+    }
+}